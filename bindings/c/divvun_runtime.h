@@ -34,6 +34,10 @@ bundle_handle_t _Nullable DRT_Bundle_fromBundle(rust_slice_t bundle_path, error_
 bundle_handle_t _Nullable DRT_Bundle_fromPath(rust_slice_t path, error_callback_t _Nonnull error_callback);
 void DRT_Bundle_drop(bundle_handle_t _Nonnull bundle);
 
+// Cancel running pipelines, empty caches and remove partial downloads before
+// unloading the library. Waits up to 5 seconds for blocking work.
+void DRT_shutdown(void);
+
 // Pipeline functions
 pipeline_handle_t _Nullable DRT_Bundle_create(bundle_handle_t _Nonnull bundle, rust_slice_t config, error_callback_t _Nonnull error_callback);
 void DRT_PipelineHandle_drop(pipeline_handle_t _Nonnull handle);
@@ -45,9 +49,28 @@ rust_slice_t DRT_PipelineHandle_forward(pipeline_handle_t _Nonnull handle, rust_
 // against the given list of preferred locales (JSON array of BCP-47 tags).
 rust_slice_t DRT_Bundle_errorPreferences(bundle_handle_t _Nonnull bundle, rust_slice_t locales, error_callback_t _Nonnull error_callback);
 
+// Returns a JSON blob describing what the bundle and this runtime support:
+// pipelines and their input/output types, message locales, voices, position
+// encodings and build features.
+rust_slice_t DRT_Bundle_capabilities(bundle_handle_t _Nonnull bundle, error_callback_t _Nonnull error_callback);
+
 // Memory management for Rust-allocated vectors
 void DRT_Vec_drop(rust_slice_t vec);
 
+// UTF-16 variants. Input and output are UTF-16 buffers whose len counts code
+// units, not bytes, and divvun::suggest error offsets are always UTF-16 code
+// units (the "encoding" config of suggest commands is set to "utf-16").
+// Handles from DRT_Bundle_create_utf16 only work with the _utf16 functions,
+// and buffers they return must be freed with DRT_Vec_drop_utf16.
+typedef void* utf16_pipeline_handle_t;
+
+utf16_pipeline_handle_t _Nullable DRT_Bundle_create_utf16(bundle_handle_t _Nonnull bundle, rust_slice_t config, error_callback_t _Nonnull error_callback);
+void DRT_PipelineHandle_drop_utf16(utf16_pipeline_handle_t _Nonnull handle);
+void DRT_PipelineHandle_cancel_utf16(utf16_pipeline_handle_t _Nonnull handle);
+rust_slice_t DRT_PipelineHandle_forward_utf16(utf16_pipeline_handle_t _Nonnull handle, rust_slice_t input, error_callback_t _Nonnull error_callback);
+rust_slice_t DRT_Bundle_runPipeline_utf16(bundle_handle_t _Nonnull bundle, rust_slice_t input, rust_slice_t config, error_callback_t _Nonnull error_callback);
+void DRT_Vec_drop_utf16(rust_slice_t vec);
+
 #ifdef __cplusplus
 }
 #endif
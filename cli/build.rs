@@ -1,4 +1,4 @@
-fn main() {
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     let target = std::env::var("TARGET").unwrap();
     let target_os = std::env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS not defined");
 
@@ -6,6 +6,9 @@ fn main() {
 
     // ICU linking is handled by cg3-rs and hfst-rs dependencies
 
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/divvun_runtime.proto")?;
+
     if target_os == "macos" {
         const EXPORT: &[&str] = &[
             "_DRT_Bundle_fromBundle",
@@ -22,4 +25,6 @@ fn main() {
             println!("cargo:rustc-link-arg=-Wl,-exported_symbol,{exp}");
         }
     }
+
+    Ok(())
 }
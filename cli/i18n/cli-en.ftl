@@ -0,0 +1,64 @@
+# Messages of the divvun-runtime CLI. Status labels (`repl-saved` etc.) are
+# right-aligned in a 12-column gutter, so keep them to one short word.
+
+shell-error = Error:
+shell-warning = Warning:
+shell-note = Note:
+
+no-command = No command specified
+
+## REPL
+
+repl-help-title = Available commands:
+repl-help-help = Display this help message
+repl-help-list = List all available modules
+repl-help-step = Enable/disable stepping through pipeline
+repl-help-ast = Display the parsed AST
+repl-help-config = Display the current configuration
+repl-help-set = Set a configuration variable
+repl-help-breakpoint = Set/clear breakpoint at command
+repl-help-breakpoint-example = e.g. { $example } (see docs for conditions)
+repl-help-save = Export last run as markdown
+repl-help-snippet-save = Save text (or the last input) as a snippet
+repl-help-snippet-run = Run a saved snippet
+repl-help-snippet-list = List or delete snippets
+repl-help-loadbin = Run the contents of a file, byte for byte
+repl-help-fold = Fold step output longer than this many lines
+repl-help-expand = Show a step's full output from the last run
+repl-help-exit = Exit the REPL
+
+repl-saved = Saved
+repl-deleted = Deleted
+repl-stepping = Stepping
+repl-setting = Setting
+repl-breakpoint = Breakpoint
+repl-folding = Folding
+
+repl-enabled = enabled
+repl-disabled = disabled
+repl-breakpoint-cleared = cleared
+repl-breakpoint-set = set at command '{ $command }'
+repl-breakpoint-set-when = set at command '{ $command }' when: { $condition }
+repl-command-not-found = Command '{ $command }' not found
+repl-unknown-command = Unknown command: { $command }
+repl-missing-id = Missing id name
+repl-invalid-value = Failed to parse value: { $error }
+repl-saved-log = Debug log to { $file }
+repl-save-failed = Failed to save: { $error }
+repl-read-failed = Failed to read { $file }: { $error }
+repl-loadbin-usage = Usage: :loadbin <file>
+repl-fold-set = step output over { $lines } lines
+repl-fold-usage = Usage: :fold <lines|off>
+repl-folded = … { $lines } more lines, :expand { $key } to show them
+repl-expand-usage = Usage: :expand <command_id>
+repl-expand-none = No output from '{ $key }' in the last run
+repl-reloaded = Reloaded
+repl-reload-changed = after changes to { $files }
+repl-reload-failed = Reloading failed, keeping the previous version: { $error }
+
+snippet-nothing-to-save = Nothing to save: give a sentence or run one first
+snippet-saved = snippet '{ $name }': { $text }
+snippet-deleted = snippet '{ $name }'
+snippet-not-found = Snippet '{ $name }' not found
+snippet-none = No snippets saved.
+snippet-usage = Usage: :snippet [list | save <name> [text] | run <name> | delete <name>]
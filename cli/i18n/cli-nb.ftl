@@ -0,0 +1,61 @@
+shell-error = Feil:
+shell-warning = Advarsel:
+shell-note = Merk:
+
+no-command = Ingen kommando oppgitt
+
+## REPL
+
+repl-help-title = Tilgjengelige kommandoer:
+repl-help-help = Vis denne hjelpeteksten
+repl-help-list = List opp alle tilgjengelige moduler
+repl-help-step = Slå steg-for-steg-kjøring av rørledningen av/på
+repl-help-ast = Vis det tolkede syntakstreet (AST)
+repl-help-config = Vis gjeldende konfigurasjon
+repl-help-set = Sett en konfigurasjonsverdi
+repl-help-breakpoint = Sett/fjern stoppunkt ved kommando
+repl-help-breakpoint-example = f.eks. { $example } (se dokumentasjonen for betingelser)
+repl-help-save = Eksporter siste kjøring som markdown
+repl-help-snippet-save = Lagre tekst (eller forrige inndata) som en snutt
+repl-help-snippet-run = Kjør en lagret snutt
+repl-help-snippet-list = List opp eller slett snutter
+repl-help-loadbin = Kjør innholdet i en fil, byte for byte
+repl-help-fold = Brett sammen utdata fra steg som er lengre enn så mange linjer
+repl-help-expand = Vis hele utdataen fra et steg i siste kjøring
+repl-help-exit = Avslutt REPL
+
+repl-saved = Lagret
+repl-deleted = Slettet
+repl-stepping = Stegvis
+repl-setting = Setter
+repl-breakpoint = Stoppunkt
+repl-folding = Bretter
+
+repl-enabled = på
+repl-disabled = av
+repl-breakpoint-cleared = fjernet
+repl-breakpoint-set = satt ved kommandoen '{ $command }'
+repl-breakpoint-set-when = satt ved kommandoen '{ $command }' når: { $condition }
+repl-command-not-found = Fant ikke kommandoen '{ $command }'
+repl-unknown-command = Ukjent kommando: { $command }
+repl-missing-id = Mangler id-navn
+repl-invalid-value = Kunne ikke tolke verdien: { $error }
+repl-saved-log = Feilsøkingslogg til { $file }
+repl-save-failed = Kunne ikke lagre: { $error }
+repl-read-failed = Kunne ikke lese { $file }: { $error }
+repl-loadbin-usage = Bruk: :loadbin <fil>
+repl-fold-set = utdata fra steg over { $lines } linjer
+repl-fold-usage = Bruk: :fold <linjer|off>
+repl-folded = … { $lines } linjer til, :expand { $key } for å vise dem
+repl-expand-usage = Bruk: :expand <kommando-id>
+repl-expand-none = Ingen utdata fra '{ $key }' i siste kjøring
+repl-reloaded = Oppdatert
+repl-reload-changed = etter endringer i { $files }
+repl-reload-failed = Kunne ikke laste inn på nytt, beholder forrige versjon: { $error }
+
+snippet-nothing-to-save = Ingenting å lagre: skriv en setning eller kjør en først
+snippet-saved = snutt '{ $name }': { $text }
+snippet-deleted = snutt '{ $name }'
+snippet-not-found = Fant ikke snutten '{ $name }'
+snippet-none = Ingen lagrede snutter.
+snippet-usage = Bruk: :snippet [list | save <navn> [tekst] | run <navn> | delete <navn>]
@@ -0,0 +1,61 @@
+shell-error = Meattáhus:
+shell-warning = Várrehus:
+shell-note = Fuomáš:
+
+no-command = Ii makkárge gohččun
+
+## REPL
+
+repl-help-title = Gohččumat:
+repl-help-help = Čájet dán veahkkediehtu
+repl-help-list = Čájet buot moduvllaid
+repl-help-step = Jođit bohcci lávki lávkki mielde dahje ii
+repl-help-ast = Čájet analyserejuvvon AST
+repl-help-config = Čájet dálá heivehusaid
+repl-help-set = Bija heivehusa árvvu
+repl-help-breakpoint = Bija/sihko bisánansaji gohččumii
+repl-help-breakpoint-example = omd. { $example } (eavttuid birra gč. dokumentašuvnna)
+repl-help-save = Vurke maŋimuš jođiheami markdownan
+repl-help-snippet-save = Vurke teavstta (dahje maŋimuš sisaččastaga) bihttán
+repl-help-snippet-run = Jođit vurkejuvvon bihtá
+repl-help-snippet-list = Čájet dahje sihko bihtáid
+repl-help-loadbin = Jođit fiilla sisdoalu, byte byte mielde
+repl-help-fold = Gárddit lávkki olggosbuktaga mii lea guhkit go nu máŋga linjá
+repl-help-expand = Čájet lávkki olles olggosbuktaga maŋimuš jođiheamis
+repl-help-exit = Heaitte REPL
+
+repl-saved = Vurkejuvvon
+repl-deleted = Sihkkojuvvon
+repl-stepping = Lávkkiid
+repl-setting = Bidjá
+repl-breakpoint = Bisánansadji
+repl-folding = Gárdda
+
+repl-enabled = alde
+repl-disabled = eret
+repl-breakpoint-cleared = sihkkojuvvon
+repl-breakpoint-set = bidjojuvvon gohččumii '{ $command }'
+repl-breakpoint-set-when = bidjojuvvon gohččumii '{ $command }' go: { $condition }
+repl-command-not-found = Gohččun '{ $command }' ii gávdno
+repl-unknown-command = Amas gohččun: { $command }
+repl-missing-id = Id-namma váilu
+repl-invalid-value = Ii sáhttán lohkat árvvu: { $error }
+repl-saved-log = Meattáhusohcanloggen fiilii { $file }
+repl-save-failed = Ii sáhttán vurket: { $error }
+repl-read-failed = Ii sáhttán lohkat { $file }: { $error }
+repl-loadbin-usage = Geavaheapmi: :loadbin <fiila>
+repl-fold-set = lávkki olggosbuvttus badjel { $lines } linjá
+repl-fold-usage = Geavaheapmi: :fold <linját|off>
+repl-folded = … { $lines } linjá vel, :expand { $key } čájeha daid
+repl-expand-usage = Geavaheapmi: :expand <gohččun-id>
+repl-expand-none = Ii olggosbuvttus '{ $key }' lávkkis maŋimuš jođiheamis
+repl-reloaded = Ođasmahtton
+repl-reload-changed = go { $files } rievdaduvvui
+repl-reload-failed = Ođasmahttin ii lihkostuvvan, ovddit veršuvdna bisuhuvvo: { $error }
+
+snippet-nothing-to-save = Ii mihkkege vurket: čále cealkaga dahje jođit ovtta ovdal
+snippet-saved = bihttá '{ $name }': { $text }
+snippet-deleted = bihttá '{ $name }'
+snippet-not-found = Bihttá '{ $name }' ii gávdno
+snippet-none = Ii leat vurkejuvvon bihtáid.
+snippet-usage = Geavaheapmi: :snippet [list | save <namma> [teaksta] | run <namma> | delete <namma>]
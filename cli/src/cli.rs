@@ -10,12 +10,49 @@ pub struct Args {
     /// Show version information. Use -VV for more details.
     #[clap(short = 'V', long, action = clap::ArgAction::Count)]
     pub version: u8,
+    /// With --version, print build info and the compiled-in modules as JSON.
+    #[clap(long)]
+    pub json: bool,
     /// Select syntax highlighting theme. Available themes:
     ///   Dark: base16-ocean.dark, base16-eighties.dark, base16-mocha.dark, Solarized (dark)
     ///   Light: base16-ocean.light, InspiredGitHub, Solarized (light)
     ///   Default: auto-detect based on terminal background
     #[clap(long, env = "DRT_THEME")]
     pub theme: Option<String>,
+    /// Make output reproducible byte-for-byte: sort hash-ordered output, run
+    /// on a single thread and replace temporary paths with $TMPDIR.
+    #[clap(
+        long,
+        global = true,
+        env = "DRT_DETERMINISTIC",
+        value_parser = clap::builder::FalseyValueParser::new()
+    )]
+    pub deterministic: bool,
+    /// Write input text to logs and crash dumps. By default release builds
+    /// log only its length and a hash.
+    #[clap(long, global = true, env = "DRT_LOG_INPUT")]
+    pub log_input: bool,
+    /// Language of the CLI's own messages, e.g. `se` or `nb,en`. Defaults to
+    /// the system locale.
+    #[clap(long, global = true, env = "DRT_UI_LANG")]
+    pub ui_lang: Option<String>,
+    /// Format of logs and of the final error on stderr: `json` prints one
+    /// JSON object per line, for CI scripts.
+    #[clap(
+        long,
+        global = true,
+        value_enum,
+        default_value_t,
+        env = "DRT_LOG_FORMAT"
+    )]
+    pub log_format: LogFormat,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 #[derive(Subcommand, Debug)]
@@ -30,11 +67,25 @@ pub enum Command {
     Run(RunArgs),
     /// List available pipelines and metadata from a bundle
     List(ListArgs),
+    /// Show how a .drb bundle was built: pipeline, assets and runtime version
+    Inspect(InspectArgs),
+    /// Run a single command with hand-written input, or list all commands
+    Exec(ExecArgs),
     /// Open a bundle in the graphical playground/debugger
     #[command(alias = "play")]
     Playground(PlaygroundArgs),
     /// Run TypeScript test files using Deno
     Test(TestArgs),
+    /// Walk through the grammar errors in a text file and apply suggestions
+    Fix(FixArgs),
+    /// Serve bundles over HTTP, picking one by language
+    Serve(ServeArgs),
+    /// Answer hunspell pipe queries (`hunspell -a`) on stdin with a spell
+    /// pipeline
+    Hunspell(HunspellArgs),
+    /// Keep a bundle loaded and answer JSON requests from editor plugins on a
+    /// local socket
+    Daemon(DaemonArgs),
     #[command(flatten)]
     Debug(DebugArgs),
 }
@@ -44,6 +95,139 @@ pub enum DebugArgs {
     /// Dump the parsed pipeline AST as JSON
     #[command(hide = true)]
     DumpAst(DebugDumpAstArgs),
+    /// Tools for tracking down regressions in bundles
+    #[command(subcommand)]
+    Debug(DebugCommand),
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DebugCommand {
+    /// Find the first bundle version whose output for an input changed
+    Bisect(BisectArgs),
+    /// Check a CG stream and pretty-print it
+    Cg3(DebugCg3Args),
+    /// Compare a bundle's grammar errors with libdivvun's for the same text
+    Compare(DebugCompareArgs),
+    /// List surface forms the bundle's speller accepts
+    Wordlist(DebugWordlistArgs),
+}
+
+#[derive(Parser, Debug)]
+pub struct DebugCompareArgs {
+    #[clap(index = 1)]
+    /// Bundle, pipeline file or project to check with. Defaults to current
+    /// directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(long, value_name = "FILE")]
+    /// `divvun-checker` JSON output to compare with. With `--batch`, a
+    /// directory of `<file>.json` mirroring the corpus.
+    pub libdivvun_json: PathBuf,
+
+    #[clap(long, conflicts_with = "batch")]
+    /// Text to check. Defaults to the `text` of the libdivvun output.
+    pub input: Option<String>,
+
+    #[clap(long, value_name = "DIR")]
+    /// Compare every file of a corpus.
+    pub batch: Option<PathBuf>,
+
+    #[clap(short, long)]
+    pub config: Vec<String>,
+
+    #[clap(short = 'P', long)]
+    /// Select a specific named pipeline from the bundle.
+    pub pipeline: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "ASSET=PATH",
+        env = "DRT_ASSET_OVERRIDE",
+        value_delimiter = ','
+    )]
+    /// Read a bundle asset from a local file instead. May be repeated.
+    pub asset_override: Vec<String>,
+
+    #[clap(long)]
+    /// Skip TypeScript type checking with Deno.
+    pub skip_check: bool,
+
+    #[clap(long)]
+    /// Print the differences as JSON.
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct DebugCg3Args {
+    #[clap(index = 1)]
+    /// CG stream to read. Reads stdin if omitted.
+    pub file: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Count cohorts, readings and error tags instead of printing the stream.
+    pub stats: bool,
+
+    #[clap(long, conflicts_with = "stats")]
+    /// Only check the stream, printing nothing if it is valid.
+    pub check: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct DebugWordlistArgs {
+    #[clap(short, long)]
+    /// Bundle or project directory with the speller. Defaults to current
+    /// directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(short = 'P', long)]
+    /// Take the speller of a specific named pipeline.
+    pub pipeline: Option<String>,
+
+    #[clap(long, value_name = "ASSET")]
+    /// Acceptor to list, e.g. `acceptor.default.hfst`. Defaults to the one
+    /// the pipeline's speller uses.
+    pub acceptor: Option<String>,
+
+    #[clap(long, default_value_t = 10_000)]
+    /// Forms to list at most.
+    pub max: usize,
+
+    #[clap(long, default_value_t = 32)]
+    /// Longest form to list, in symbols.
+    pub max_length: usize,
+
+    #[clap(long, value_name = "REGEX")]
+    /// Only list forms matching this.
+    pub pattern: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct BisectArgs {
+    #[clap(long, value_name = "DIR")]
+    /// Directory of .drb bundles, ordered by file name with numbers compared
+    /// numerically (`se-1.10.drb` comes after `se-1.9.drb`).
+    pub bundles: PathBuf,
+
+    #[clap(long)]
+    /// Text to run through each version.
+    pub input: String,
+
+    #[clap(long, value_name = "FILE")]
+    /// Expected output as JSON. Without it, the oldest version's output is
+    /// taken as correct.
+    pub expect_json: Option<PathBuf>,
+
+    #[clap(short, long)]
+    pub config: Vec<String>,
+
+    #[clap(short = 'P', long)]
+    /// Select a specific named pipeline from the bundles.
+    pub pipeline: Option<String>,
+
+    #[clap(long)]
+    /// Show what changed in the build manifests between the last good and
+    /// first bad version.
+    pub diff_manifests: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -58,6 +242,22 @@ pub struct SyncArgs {
     #[clap(index = 1)]
     /// Defaults to current directory.
     pub path: Option<PathBuf>,
+
+    #[clap(long, value_enum, default_value_t = ProgressFormat::Human)]
+    /// Progress output format. `json` prints one JSON object per line on
+    /// stdout for GUI wrappers.
+    pub progress: ProgressFormat,
+
+    #[clap(long)]
+    /// List what would be created, updated or removed without writing.
+    pub dry_run: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProgressFormat {
+    #[default]
+    Human,
+    Json,
 }
 
 #[derive(Parser, Debug)]
@@ -65,6 +265,21 @@ pub struct InitArgs {
     #[clap(index = 1)]
     /// Defaults to current directory.
     pub path: Option<PathBuf>,
+
+    #[clap(long, value_name = "NAME")]
+    /// Instead of a pipeline project, add a new module to the divvun-runtime
+    /// checkout at PATH: a command with tests, a toy asset, a cargo feature
+    /// and a documentation page to fill in.
+    pub module: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "NAME",
+        default_value = "process",
+        requires = "module"
+    )]
+    /// With `--module`, the name of the module's first command.
+    pub command: String,
 }
 
 #[derive(Parser, Debug)]
@@ -75,6 +290,11 @@ pub struct RunArgs {
 
     pub input: Option<String>,
 
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["input", "stream"])]
+    /// Read the input from a file, byte for byte. Use it for pipelines that
+    /// take bytes, such as audio or binary documents.
+    pub input_file: Option<PathBuf>,
+
     #[clap(short, long)]
     pub config: Vec<String>,
 
@@ -99,6 +319,106 @@ pub struct RunArgs {
     /// output, then stop. Useful for inspecting an intermediate stage
     /// non-interactively (replaces libdivvun's modes files).
     pub break_after: Option<String>,
+
+    #[clap(long, conflicts_with_all = ["input", "break_after"])]
+    /// Treat each line of stdin as a separate document, run them through one
+    /// pipeline and print one JSON result per line.
+    pub stream: bool,
+
+    #[clap(short = 'z', long)]
+    /// With `--stream` or `--batch -`, split stdin on NUL bytes instead of
+    /// newlines.
+    pub null_data: bool,
+
+    #[clap(
+        long = "asset-override",
+        value_name = "ASSET=PATH",
+        env = "DRT_ASSET_OVERRIDE",
+        value_delimiter = ','
+    )]
+    /// Read a bundle asset from a local file instead, e.g.
+    /// `--asset-override errors.json=./local/errors.json`. May be repeated.
+    pub asset_override: Vec<String>,
+
+    #[clap(long, value_enum, default_value_t = VerifyArg::Lazy, env = "DRT_VERIFY")]
+    /// When to check bundle assets against their checksums: `eager` checks
+    /// everything on load, `lazy` checks each asset on first use.
+    pub verify: VerifyArg,
+
+    #[clap(long, value_name = "N", env = "DRT_CHANNEL_CAPACITY")]
+    /// Number of events buffered between commands before a slow command
+    /// starts dropping them (default 16).
+    pub channel_capacity: Option<usize>,
+
+    #[clap(long)]
+    /// Grow the channel capacity whenever a command falls behind.
+    pub grow_channels: bool,
+
+    #[clap(long, value_name = "DIR", env = "DRT_CRASH_DUMPS")]
+    /// When the pipeline fails on a single input, write a zip with the input,
+    /// config, each step's output up to the failure and version info to DIR.
+    pub crash_dumps: Option<PathBuf>,
+
+    #[clap(long, value_enum, conflicts_with_all = ["stream", "break_after"])]
+    /// Print the grammar errors of a suggest pipeline as a report instead of
+    /// its output: `sarif` for code scanning, `github` for workflow
+    /// annotations, `gcc` for `file:line:col:` lines editors understand.
+    pub report: Option<ReportFormat>,
+
+    #[clap(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["input", "input_file", "stream", "break_after", "report"]
+    )]
+    /// Run every file under DIR through the pipeline and write each one's
+    /// output to a matching file under `--output-path` (default
+    /// `DIR.<step>`). With `-`, run each line of stdin as `--stream` does.
+    pub batch: Option<PathBuf>,
+
+    #[clap(long, value_name = "STEP", requires = "batch")]
+    /// With `--batch`, write the output of this step instead of the
+    /// pipeline's, e.g. the disambiguated CG stream.
+    pub emit_stage: Option<String>,
+
+    #[clap(
+        long,
+        value_name = "N",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1",
+        conflicts_with = "break_after"
+    )]
+    /// Exit with code 1 when the output has at least N grammar errors (1 if
+    /// N is left out), counted over every document with `--stream` or
+    /// `--batch`.
+    pub fail_on_errors: Option<usize>,
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    Sarif,
+    Github,
+    Gcc,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerifyArg {
+    Eager,
+    #[default]
+    Lazy,
+    Off,
+}
+
+impl From<VerifyArg> for divvun_runtime::util::integrity::VerifyMode {
+    fn from(value: VerifyArg) -> Self {
+        use divvun_runtime::util::integrity::VerifyMode;
+        match value {
+            VerifyArg::Eager => VerifyMode::Eager,
+            VerifyArg::Lazy => VerifyMode::Lazy,
+            VerifyArg::Off => VerifyMode::Off,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -126,6 +446,16 @@ pub struct BundleArgs {
     #[clap(long)]
     /// Bundle version metadata.
     pub vers: Option<String>,
+
+    #[clap(long = "only-pipeline", value_name = "NAME")]
+    /// Bundle only this pipeline, leaving out assets no bundled pipeline
+    /// uses. May be repeated.
+    pub only_pipeline: Vec<String>,
+
+    #[clap(long = "exclude-pipeline", value_name = "NAME")]
+    /// Leave this pipeline out, along with assets only it uses. May be
+    /// repeated.
+    pub exclude_pipeline: Vec<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -135,6 +465,158 @@ pub struct ListArgs {
     pub path: Option<PathBuf>,
 }
 
+#[derive(Parser, Debug)]
+pub struct ExecArgs {
+    #[clap(index = 1)]
+    /// Command to run, e.g. `divvun::suggest`. Lists all commands if omitted.
+    pub command: Option<String>,
+
+    #[clap(short = 'a', long = "arg", value_name = "NAME=VALUE")]
+    /// Command argument. Paths, strings and ints as written, anything else as
+    /// JSON. May be repeated.
+    pub args: Vec<String>,
+
+    #[clap(short, long)]
+    /// Input text. Read from stdin if omitted.
+    pub input: Option<String>,
+
+    #[clap(short, long)]
+    /// Bundle or project directory to read assets from. Defaults to current
+    /// directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(short, long)]
+    /// Runtime configuration for the command as JSON.
+    pub config: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub struct InspectArgs {
+    #[clap(index = 1)]
+    /// Path to the .drb bundle.
+    pub path: PathBuf,
+
+    #[clap(long)]
+    /// Print the raw build manifest as JSON.
+    pub json: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct ServeArgs {
+    #[clap(index = 1)]
+    /// JSON file mapping language tags to bundles, with pool settings.
+    /// Defaults to `serve.config` in divvun-runtime.toml.
+    pub config: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Address to listen on (default 127.0.0.1:4000).
+    pub addr: Option<String>,
+
+    #[cfg(feature = "grpc")]
+    #[clap(long)]
+    /// Also serve the gRPC API on this address, e.g. `127.0.0.1:4001`.
+    pub grpc_addr: Option<std::net::SocketAddr>,
+}
+
+#[derive(Parser, Debug)]
+pub struct HunspellArgs {
+    #[clap(short, long, short_alias = 'd', alias = "dict")]
+    /// Bundle, pipeline file or project to spell check with. Defaults to
+    /// current directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(short = 'P', long)]
+    /// Select a specific named pipeline from the bundle.
+    pub pipeline: Option<String>,
+
+    #[clap(short, long)]
+    pub config: Vec<String>,
+
+    #[clap(short = 'a', hide = true)]
+    /// Pipe mode, which is the only mode. Accepted so `hunspell -a` command
+    /// lines keep working.
+    pub pipe: bool,
+
+    #[clap(long)]
+    /// Skip TypeScript type checking with Deno.
+    pub skip_check: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct DaemonArgs {
+    #[clap(short, long)]
+    /// Bundle, pipeline file or project to load. Defaults to current
+    /// directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(short = 'P', long)]
+    /// Pipeline to run when a request doesn't name one. Defaults to the
+    /// bundle's default pipeline.
+    pub pipeline: Option<String>,
+
+    #[clap(long)]
+    /// Unix domain socket, or named pipe on Windows, to listen on. Defaults
+    /// to `divvun-runtime.sock` in `$XDG_RUNTIME_DIR` or a private
+    /// `divvun-runtime-<uid>` directory in the temporary directory, or
+    /// `\\.\pipe\divvun-runtime`.
+    pub socket: Option<PathBuf>,
+
+    #[clap(long)]
+    /// Skip TypeScript type checking with Deno.
+    pub skip_check: bool,
+}
+
+#[derive(Parser, Debug)]
+pub struct FixArgs {
+    #[clap(index = 1)]
+    /// The text file to check.
+    pub file: PathBuf,
+
+    #[clap(short, long)]
+    /// Bundle, pipeline file or project to check with. Defaults to current
+    /// directory.
+    pub path: Option<PathBuf>,
+
+    #[clap(short = 'P', long)]
+    /// Select a specific named pipeline from the bundle.
+    pub pipeline: Option<String>,
+
+    #[clap(short, long)]
+    pub config: Vec<String>,
+
+    #[clap(
+        long,
+        value_name = "ASSET=PATH",
+        env = "DRT_ASSET_OVERRIDE",
+        value_delimiter = ','
+    )]
+    /// Read a bundle asset from a local file instead. May be repeated.
+    pub asset_override: Vec<String>,
+
+    #[clap(long)]
+    /// Skip TypeScript type checking with Deno.
+    pub skip_check: bool,
+
+    #[clap(short, long)]
+    /// Write the corrected text back to FILE instead of printing it.
+    pub in_place: bool,
+
+    #[clap(long)]
+    /// Don't ask: apply only fixes marked safe (`autofix` in policies.json,
+    /// with a single suggestion) and print them as a unified diff.
+    pub autofix: bool,
+
+    #[clap(
+        long,
+        value_name = "ERROR_ID",
+        value_delimiter = ',',
+        requires = "autofix"
+    )]
+    /// Also treat errors of this type with a single suggestion as safe. May
+    /// be repeated.
+    pub autofix_allow: Vec<String>,
+}
+
 #[derive(Parser, Debug)]
 pub struct PlaygroundArgs {
     #[clap(index = 1)]
@@ -144,9 +626,49 @@ pub struct PlaygroundArgs {
 
 #[derive(Parser, Debug)]
 pub struct TestArgs {
-    /// Test files to run
+    /// Test files to run. With `--self`, the bundle or project to test.
     pub files: Vec<PathBuf>,
 
+    #[clap(long = "self")]
+    /// Run the bundle's own `selftest/` samples through each pipeline instead
+    /// of TypeScript tests
+    pub self_test: bool,
+
+    #[clap(long, conflicts_with = "self_test")]
+    /// Keep running, and rerun the tests a change to them, pipeline.ts or
+    /// the assets could affect
+    pub watch: bool,
+
+    #[clap(long, value_name = "CORPUS", conflicts_with_all = ["self_test", "watch"])]
+    /// Check a file or directory of known-good text, failing on error types
+    /// found more often than allowed. The bundle or project is the single
+    /// path given, or the current directory.
+    pub clean: Option<PathBuf>,
+
+    #[clap(long, value_name = "N", default_value_t = 0, requires = "clean")]
+    /// Errors of each type allowed in the clean corpus.
+    pub max_errors: usize,
+
+    #[clap(long, value_name = "ERROR_ID=N", requires = "clean")]
+    /// Errors of one type allowed in the clean corpus, overriding
+    /// `--max-errors`. May be repeated.
+    pub allow: Vec<String>,
+
+    #[clap(long, value_name = "N", default_value_t = 3, requires = "clean")]
+    /// Example sentences to show for each error type.
+    pub examples: usize,
+
+    #[clap(short = 'P', long, requires = "clean")]
+    /// Check the clean corpus with a specific named pipeline.
+    pub pipeline: Option<String>,
+
+    #[clap(short, long, requires = "clean")]
+    pub config: Vec<String>,
+
+    #[clap(long, requires = "clean")]
+    /// Print the clean corpus report as JSON.
+    pub json: bool,
+
     /// Arguments to pass to the test script (after --)
     #[clap(last = true)]
     pub script_args: Vec<String>,
@@ -0,0 +1,288 @@
+//! `run --batch DIR [--emit-stage STEP]`: run every file of a corpus through
+//! the pipeline and write one step's output for each to a mirror of the
+//! corpus, e.g. the disambiguated CG stream for grammarians to grep with the
+//! usual GiellaLT tools.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use divvun_runtime::{
+    ast::Command,
+    bundle::Bundle,
+    modules::{PipelineEvent, PipelineValue, TapOutput},
+};
+use futures_util::{FutureExt, StreamExt};
+use miette::IntoDiagnostic;
+use walkdir::WalkDir;
+
+use crate::{
+    exit::{self, FailAs, Failure},
+    shell::Shell,
+};
+
+pub struct Batch<'a> {
+    pub corpus: &'a Path,
+    /// Step to write, the pipeline's output step when `None`.
+    pub step: Option<&'a str>,
+    /// Defaults to `<corpus>.<step>` next to the corpus.
+    pub output_dir: Option<&'a Path>,
+}
+
+/// Returns how many grammar errors the written step's JSON output had, for
+/// `--fail-on-errors`.
+pub async fn run(
+    shell: &mut Shell,
+    bundle: &Bundle,
+    config: serde_json::Value,
+    batch: Batch<'_>,
+) -> miette::Result<usize> {
+    let defn = bundle.definition();
+    let step = batch.step.unwrap_or(&defn.output.r#ref).to_string();
+    let Some(command) = defn.commands.get(&step).cloned() else {
+        return Err(Failure::Usage.msg(format!("no pipeline step named '{}'", step)));
+    };
+    let output_dir = match batch.output_dir {
+        Some(dir) => dir.to_path_buf(),
+        None => {
+            let mut name = batch.corpus.file_name().unwrap_or_default().to_os_string();
+            name.push(format!(".{}", step));
+            batch.corpus.with_file_name(name)
+        }
+    };
+
+    if !batch.corpus.is_dir() {
+        return Err(Failure::Usage.msg(format!(
+            "--batch takes a directory, but {} isn't one",
+            batch.corpus.display()
+        )));
+    }
+    let files = corpus_files(batch.corpus, &output_dir);
+    if files.is_empty() {
+        return Err(Failure::Usage.msg(format!("no files in corpus {}", batch.corpus.display())));
+    }
+
+    let captured = Arc::new(Mutex::new(Vec::new()));
+    let tap = {
+        let captured = captured.clone();
+        let step = step.clone();
+        Arc::new(move |key: &str, _: &Command, event: &PipelineEvent| {
+            if key == step {
+                if let PipelineEvent::Value(value) = event {
+                    captured.lock().unwrap().push(value.clone());
+                }
+            }
+            async { TapOutput::Continue }.boxed()
+        })
+    };
+    let mut pipe = bundle
+        .create_with_tap(config.clone(), tap.clone())
+        .await
+        .into_diagnostic()
+        .fail_as(Failure::BundleLoad)?;
+
+    let mut failed = 0;
+    let mut errors_found = 0;
+    for file in &files {
+        let relative = file.strip_prefix(batch.corpus).unwrap_or(file);
+        let text = match std::fs::read(file) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) => {
+                failed += 1;
+                shell
+                    .warning(format!("{}: {}", relative.display(), e))
+                    .into_diagnostic()?;
+                continue;
+            }
+        };
+
+        captured.lock().unwrap().clear();
+        let mut error = None;
+        let mut stream = pipe.forward(PipelineValue::String(text)).await;
+        while let Some(result) = stream.next().await {
+            if let Err(e) = result {
+                error = Some(e);
+                break;
+            }
+        }
+        drop(stream);
+
+        if let Some(e) = error {
+            failed += 1;
+            shell
+                .warning(format!("{}: {}", relative.display(), e))
+                .into_diagnostic()?;
+            // A failed document takes its pipeline down
+            pipe = bundle
+                .create_with_tap(config.clone(), tap.clone())
+                .await
+                .into_diagnostic()
+                .fail_as(Failure::BundleLoad)?;
+            continue;
+        }
+
+        let values = std::mem::take(&mut *captured.lock().unwrap());
+        for value in &values {
+            if let PipelineValue::Json(json) = value {
+                errors_found += exit::count_errors(json);
+            }
+        }
+        let (ext, contents) = stage_file(&command, values)?;
+        let target = target_path(&output_dir, relative, ext);
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent).into_diagnostic()?;
+        }
+        std::fs::write(&target, contents).into_diagnostic()?;
+    }
+
+    shell
+        .status(
+            "Wrote",
+            format!(
+                "{} of {} files of step '{}' to {}",
+                files.len() - failed,
+                files.len(),
+                step,
+                output_dir.display()
+            ),
+        )
+        .into_diagnostic()?;
+    if failed > 0 {
+        return Err(Failure::Pipeline.msg(format!("{} files failed", failed)));
+    }
+    Ok(errors_found)
+}
+
+/// Files of the corpus in a stable order, skipping hidden files and the
+/// output directory if it is inside the corpus.
+fn corpus_files(corpus: &Path, output_dir: &Path) -> Vec<PathBuf> {
+    WalkDir::new(corpus)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            e.depth() == 0
+                || !(e.file_name().to_string_lossy().starts_with('.') || e.path() == output_dir)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Where a corpus file's result goes: its path under `output_dir` with `ext`
+/// appended, so `a.txt` and `a.md` don't both become `a.cg3`.
+fn target_path(output_dir: &Path, relative: &Path, ext: &str) -> PathBuf {
+    let mut path = output_dir.join(relative).into_os_string();
+    path.push(".");
+    path.push(ext);
+    PathBuf::from(path)
+}
+
+/// Extension and contents of the file for one document's values of a step:
+/// text values are concatenated, as the step would stream them, and JSON
+/// values are written one per line.
+fn stage_file(
+    command: &Command,
+    values: Vec<PipelineValue>,
+) -> miette::Result<(&'static str, Vec<u8>)> {
+    let ext = match command.kind.as_deref() {
+        Some("cg3") => "cg3",
+        _ if command.returns == "json" => "jsonl",
+        _ => "txt",
+    };
+
+    let mut contents = Vec::new();
+    for value in values {
+        match value {
+            PipelineValue::String(text) => contents.extend(text.into_bytes()),
+            PipelineValue::Json(json) => {
+                contents.extend(serde_json::to_vec(&json).into_diagnostic()?);
+                contents.push(b'\n');
+            }
+            value @ PipelineValue::Multiple(_) => {
+                contents.extend(value.to_string().into_bytes());
+                contents.push(b'\n');
+            }
+            PipelineValue::Bytes(_) | PipelineValue::Audio(_) => {
+                return Err(miette::miette!(
+                    "step produces binary output, which --batch can't write as a corpus"
+                ));
+            }
+        }
+    }
+    Ok((ext, contents))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toy() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/toy")
+    }
+
+    #[test]
+    fn appends_the_extension_to_the_file_name() {
+        let out = Path::new("out");
+        assert_eq!(
+            target_path(out, Path::new("sub/a.txt"), "cg3"),
+            Path::new("out/sub/a.txt.cg3")
+        );
+        assert_ne!(
+            target_path(out, Path::new("a.txt"), "cg3"),
+            target_path(out, Path::new("a.md"), "cg3")
+        );
+    }
+
+    #[test]
+    fn skips_hidden_files_and_the_output_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let corpus = dir.path();
+        std::fs::create_dir_all(corpus.join("sub")).unwrap();
+        std::fs::create_dir_all(corpus.join("out")).unwrap();
+        std::fs::create_dir_all(corpus.join(".git")).unwrap();
+        for file in [
+            "b.txt",
+            "sub/a.txt",
+            ".hidden",
+            ".git/HEAD",
+            "out/b.txt.txt",
+        ] {
+            std::fs::write(corpus.join(file), "x").unwrap();
+        }
+
+        let files = corpus_files(corpus, &corpus.join("out"));
+        let files = files
+            .iter()
+            .map(|x| x.strip_prefix(corpus).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(files, [Path::new("b.txt"), Path::new("sub/a.txt")]);
+    }
+
+    #[tokio::test]
+    async fn writes_each_file_next_to_its_namesakes() {
+        let dir = tempfile::tempdir().unwrap();
+        let corpus = dir.path().join("corpus");
+        std::fs::create_dir_all(&corpus).unwrap();
+        std::fs::write(corpus.join("a.txt"), "lean").unwrap();
+        std::fs::write(corpus.join("a.md"), "boahtan").unwrap();
+
+        let bundle = Bundle::from_path(toy()).await.unwrap();
+        let mut shell = Shell::from_write(Box::new(Vec::new()));
+        let batch = Batch {
+            corpus: &corpus,
+            step: None,
+            output_dir: None,
+        };
+        run(&mut shell, &bundle, serde_json::json!({}), batch)
+            .await
+            .unwrap();
+
+        let output_dir = dir.path().join("corpus.reverse");
+        let read = |name: &str| std::fs::read_to_string(output_dir.join(name)).unwrap();
+        assert_eq!(read("a.txt.txt"), "NAEL");
+        assert_eq!(read("a.md.txt"), "NATHAOB");
+    }
+}
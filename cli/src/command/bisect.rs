@@ -0,0 +1,250 @@
+//! `debug bisect`: find the first of a series of bundle versions whose output
+//! for an input stops matching what's expected, loading as few of them as a
+//! binary search allows.
+
+use std::{cmp::Ordering, path::PathBuf};
+
+use divvun_runtime::{
+    bundle::{Bundle, BundleOptions},
+    modules::PipelineValue,
+};
+use futures_util::StreamExt;
+use miette::IntoDiagnostic;
+
+use crate::{cli::BisectArgs, shell::Shell};
+
+use super::run::{parse_config, value_to_json};
+
+pub async fn bisect(shell: &mut Shell, args: BisectArgs) -> miette::Result<()> {
+    let mut versions = std::fs::read_dir(&args.bundles)
+        .map_err(|e| miette::miette!("Failed to read {}: {}", args.bundles.display(), e))?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|path| path.extension().is_some_and(|x| x == "drb"))
+        .collect::<Vec<_>>();
+    versions.sort_by(|a, b| natural_cmp(&file_name(a), &file_name(b)));
+    if versions.is_empty() {
+        miette::bail!("no .drb bundles in {}", args.bundles.display());
+    }
+
+    let config = parse_config(&args.config)?;
+    let mut bisect = Bisect {
+        shell,
+        versions: &versions,
+        args: &args,
+        config,
+    };
+
+    let (expected, mut good) = match &args.expect_json {
+        Some(path) => {
+            let text = std::fs::read_to_string(path)
+                .map_err(|e| miette::miette!("Failed to read {}: {}", path.display(), e))?;
+            let expected = serde_json::from_str(&text)
+                .map_err(|e| miette::miette!("{} is not JSON: {}", path.display(), e))?;
+            (expected, None)
+        }
+        None => {
+            // Without an expectation, whatever the oldest version says is right
+            let expected = bisect.output(0).await?;
+            bisect.report(0, true)?;
+            (expected, Some(0))
+        }
+    };
+
+    if good.is_none() {
+        if bisect.output(0).await? != expected {
+            bisect.report(0, false)?;
+            miette::bail!(
+                "the oldest version, {}, already differs from the expected output",
+                file_name(&versions[0])
+            );
+        }
+        bisect.report(0, true)?;
+        good = Some(0);
+    }
+
+    // Invariant: `good` matches, `bad` (if known) doesn't
+    let mut good = good.unwrap_or_default();
+    let last = versions.len() - 1;
+    if last == good || bisect.output(last).await? == expected {
+        if last != good {
+            bisect.report(last, true)?;
+        }
+        bisect
+            .shell
+            .status("Done", "every version gives the expected output")
+            .into_diagnostic()?;
+        return Ok(());
+    }
+    bisect.report(last, false)?;
+    let mut bad = last;
+
+    while bad - good > 1 {
+        let mid = good + (bad - good) / 2;
+        let matches = bisect.output(mid).await? == expected;
+        bisect.report(mid, matches)?;
+        if matches {
+            good = mid;
+        } else {
+            bad = mid;
+        }
+    }
+
+    let output = bisect.output(bad).await?;
+    let shell = bisect.shell;
+    shell
+        .status_with_color(
+            "First bad",
+            file_name(&versions[bad]),
+            termcolor::Color::Red,
+        )
+        .into_diagnostic()?;
+    shell
+        .status("Last good", file_name(&versions[good]))
+        .into_diagnostic()?;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).into_diagnostic()?
+    );
+
+    if args.diff_manifests {
+        let manifests = (
+            Bundle::manifest_from_bundle(&versions[good])
+                .await
+                .into_diagnostic()?,
+            Bundle::manifest_from_bundle(&versions[bad])
+                .await
+                .into_diagnostic()?,
+        );
+        match manifests {
+            (Some(good), Some(bad)) => {
+                let changes = good.diff(&bad);
+                if changes.is_empty() {
+                    shell
+                        .status("Manifest", "no differences")
+                        .into_diagnostic()?;
+                }
+                for change in changes {
+                    shell.status("Manifest", change).into_diagnostic()?;
+                }
+            }
+            _ => shell
+                .warning("one of the bundles has no build manifest to compare")
+                .into_diagnostic()?,
+        }
+    }
+
+    Ok(())
+}
+
+struct Bisect<'a> {
+    shell: &'a mut Shell,
+    versions: &'a [PathBuf],
+    args: &'a BisectArgs,
+    config: serde_json::Value,
+}
+
+impl Bisect<'_> {
+    /// Output of version `index` for the input, as JSON: the value, or an
+    /// array when the pipeline gives several.
+    async fn output(&mut self, index: usize) -> miette::Result<serde_json::Value> {
+        let path = &self.versions[index];
+        let options = BundleOptions {
+            pipeline: self.args.pipeline.clone(),
+            ..Default::default()
+        };
+        let bundle = Bundle::from_bundle_with_options(path, options)
+            .await
+            .map_err(|e| miette::miette!("Failed to load {}: {}", path.display(), e))?;
+        let mut pipe = bundle.create(self.config.clone()).await.into_diagnostic()?;
+
+        let mut outputs = Vec::new();
+        let mut stream = pipe
+            .forward(PipelineValue::String(self.args.input.clone()))
+            .await;
+        while let Some(result) = stream.next().await {
+            // A version that fails counts as bad, with the error as its output
+            match result.map_err(|e| e.to_string()).and_then(value_to_json) {
+                Ok(value) => outputs.push(value),
+                Err(e) => return Ok(serde_json::json!({ "error": e })),
+            }
+        }
+        Ok(match outputs.len() {
+            1 => outputs.remove(0),
+            _ => serde_json::Value::Array(outputs),
+        })
+    }
+
+    fn report(&mut self, index: usize, good: bool) -> miette::Result<()> {
+        let (status, color) = if good {
+            ("Good", termcolor::Color::Green)
+        } else {
+            ("Bad", termcolor::Color::Red)
+        };
+        self.shell
+            .status_with_color(status, file_name(&self.versions[index]), color)
+            .into_diagnostic()
+    }
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Compare names with runs of digits as numbers, so `se-1.10.drb` sorts
+/// after `se-1.9.drb`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+        let (Some(x), Some(y)) = (a.chars().next(), b.chars().next()) else {
+            return a.len().cmp(&b.len());
+        };
+        if x.is_ascii_digit() && y.is_ascii_digit() {
+            let end_a = a.find(|c: char| !c.is_ascii_digit()).unwrap_or(a.len());
+            let end_b = b.find(|c: char| !c.is_ascii_digit()).unwrap_or(b.len());
+            let (num_a, num_b) = (
+                a[..end_a].trim_start_matches('0'),
+                b[..end_b].trim_start_matches('0'),
+            );
+            let ordering = num_a.len().cmp(&num_b.len()).then_with(|| num_a.cmp(num_b));
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+            a = &a[end_a..];
+            b = &b[end_b..];
+        } else {
+            if x != y {
+                return x.cmp(&y);
+            }
+            a = &a[x.len_utf8()..];
+            b = &b[y.len_utf8()..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sorts_versions_numerically() {
+        let mut names = vec![
+            "se-1.10.0.drb",
+            "se-1.9.2.drb",
+            "se-1.9.10.drb",
+            "se-0.9.drb",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            [
+                "se-0.9.drb",
+                "se-1.9.2.drb",
+                "se-1.9.10.drb",
+                "se-1.10.0.drb"
+            ]
+        );
+    }
+}
@@ -1,7 +1,19 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use box_format::{BoxFileWriter, BoxPath, Compression, CompressionConfig};
-use divvun_runtime::ast::PipelineBundle;
+use divvun_runtime::{
+    ast::PipelineBundle,
+    bundle::SELFTEST_DIR,
+    modules::{self, AssetDep},
+    util::{
+        integrity::{CHECKSUMS_FILE, Checksums},
+        manifest::{BUILD_MANIFEST_FILE, BuildManifest},
+    },
+};
 use miette::IntoDiagnostic;
 use walkdir::WalkDir;
 
@@ -11,7 +23,16 @@ use super::utils;
 
 const BUNDLE_ALIGNMENT: u32 = 16;
 
-async fn insert_assets(box_file: &mut BoxFileWriter, assets_path: &Path) -> miette::Result<()> {
+/// Insert the files under `assets_path` that `keep` accepts, returning their
+/// checksums and sizes, and the sizes of those left out.
+async fn insert_assets(
+    box_file: &mut BoxFileWriter,
+    assets_path: &Path,
+    keep: &dyn Fn(&str) -> bool,
+) -> miette::Result<(Checksums, BTreeMap<String, u64>, BTreeMap<String, u64>)> {
+    let mut checksums = Checksums::default();
+    let mut sizes = BTreeMap::new();
+    let mut dropped = BTreeMap::new();
     let mut files = WalkDir::new(assets_path)
         .into_iter()
         .map(|entry| entry.into_diagnostic())
@@ -24,12 +45,25 @@ async fn insert_assets(box_file: &mut BoxFileWriter, assets_path: &Path) -> miet
     {
         let relative_path = entry.path().strip_prefix(assets_path).into_diagnostic()?;
         let box_path = BoxPath::new(relative_path).into_diagnostic()?;
+        let size = entry.metadata().into_diagnostic()?.len();
+        if !keep(&box_path.to_string()) {
+            dropped.insert(box_path.to_string(), size);
+            continue;
+        }
         if let Some(parent) = box_path.parent() {
             box_file
                 .mkdir_all(parent.into_owned(), Default::default())
                 .into_diagnostic()?;
         }
 
+        sizes.insert(box_path.to_string(), size);
+        checksums
+            .insert_reader(
+                box_path.to_string(),
+                std::fs::File::open(entry.path()).into_diagnostic()?,
+            )
+            .into_diagnostic()?;
+
         let file = tokio::fs::File::open(entry.path())
             .await
             .into_diagnostic()?;
@@ -45,7 +79,95 @@ async fn insert_assets(box_file: &mut BoxFileWriter, assets_path: &Path) -> miet
             .into_diagnostic()?;
     }
 
-    Ok(())
+    Ok((checksums, sizes, dropped))
+}
+
+/// Keep only the pipelines chosen with `--only-pipeline` and
+/// `--exclude-pipeline`, returning the names of those left out.
+fn select_pipelines(
+    bundle: &mut PipelineBundle,
+    only: &[String],
+    exclude: &[String],
+) -> miette::Result<Vec<String>> {
+    for name in only.iter().chain(exclude) {
+        if !bundle.pipelines.contains_key(name) {
+            miette::bail!(
+                "No pipeline named '{}'; the pipeline file defines: {}",
+                name,
+                bundle.list_pipelines().join(", ")
+            );
+        }
+    }
+
+    let left_out = bundle
+        .pipelines
+        .keys()
+        .filter(|name| (!only.is_empty() && !only.contains(name)) || exclude.contains(name))
+        .cloned()
+        .collect::<Vec<_>>();
+    bundle.pipelines.retain(|name, _| !left_out.contains(name));
+    Ok(left_out)
+}
+
+/// Assets the bundled pipelines can read: those named by their path
+/// arguments (or under a directory that is), those their commands declare,
+/// and self-test cases, except expected output of pipelines left out.
+struct Reachable {
+    paths: Vec<PathBuf>,
+    deps: Vec<&'static AssetDep>,
+    left_out: BTreeSet<String>,
+}
+
+impl Reachable {
+    /// Fails with the name of a command that isn't in this build, as what it
+    /// reads is then unknown.
+    fn new(bundle: &PipelineBundle, left_out: &[String]) -> Result<Self, String> {
+        let mut deps = Vec::new();
+        for pipeline in bundle.pipelines.values() {
+            for command in pipeline.commands.values() {
+                let Some(def) = modules::find_command(&command.module, &command.command) else {
+                    return Err(format!("{}::{}", command.module, command.command));
+                };
+                deps.extend(def.assets);
+            }
+        }
+
+        Ok(Reachable {
+            paths: bundle.assets(),
+            deps,
+            left_out: left_out.iter().cloned().collect(),
+        })
+    }
+
+    fn contains(&self, path: &str) -> bool {
+        if let Some(case) = path
+            .strip_prefix(SELFTEST_DIR)
+            .and_then(|x| x.strip_prefix('/'))
+        {
+            let pipeline = case
+                .strip_suffix(".expected")
+                .and_then(|x| x.rsplit_once('.'))
+                .map(|(_, pipeline)| pipeline);
+            return !pipeline.is_some_and(|x| self.left_out.contains(x));
+        }
+
+        self.paths.iter().any(|x| Path::new(path).starts_with(x))
+            || self.deps.iter().any(|x| x.matches(path))
+    }
+}
+
+/// Build time for the manifest, honouring `SOURCE_DATE_EPOCH` for
+/// reproducible builds.
+fn build_timestamp() -> u64 {
+    std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or_default()
+        })
 }
 
 pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
@@ -81,6 +203,16 @@ pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
 
     let mut bundle: PipelineBundle = PipelineBundle::from_json(value).into_diagnostic()?;
 
+    let left_out = select_pipelines(&mut bundle, &args.only_pipeline, &args.exclude_pipeline)?;
+    if !left_out.is_empty() {
+        shell
+            .status(
+                "Leaving out",
+                format!("{} pipeline(s): {}", left_out.len(), left_out.join(", ")),
+            )
+            .into_diagnostic()?;
+    }
+
     // Filter out dev pipelines
     let dev_pipelines: Vec<String> = bundle
         .pipelines
@@ -104,7 +236,7 @@ pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
     }
 
     if bundle.pipelines.is_empty() {
-        miette::bail!("Cannot create bundle: all pipelines are marked as dev-only");
+        miette::bail!("Cannot create bundle: no pipelines left that aren't dev-only");
     }
 
     // Update default if it was a dev pipeline
@@ -135,11 +267,12 @@ pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
     let mut box_file = BoxFileWriter::create_with_alignment("./bundle.drb", BUNDLE_ALIGNMENT)
         .await
         .into_diagnostic()?;
+    let pipeline_json = serde_json::to_vec(&bundle).into_diagnostic()?;
     box_file
         .insert(
             &CompressionConfig::new(Compression::Stored),
             BoxPath::new("pipeline.json").into_diagnostic()?,
-            &mut std::io::Cursor::new(serde_json::to_vec(&bundle).into_diagnostic()?),
+            &mut std::io::Cursor::new(&pipeline_json),
             Default::default(),
         )
         .await
@@ -153,10 +286,99 @@ pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
         }
     };
 
+    // Pruning only applies when pipelines were left out on purpose; a full
+    // bundle keeps everything in the assets directory, as before.
+    let reachable = if left_out.is_empty() {
+        None
+    } else {
+        match Reachable::new(&bundle, &left_out) {
+            Ok(reachable) => Some(reachable),
+            Err(command) => {
+                shell
+                    .warning(format!(
+                        "Not pruning assets: {} isn't in this build of divvun-runtime, so the assets it reads are unknown",
+                        command
+                    ))
+                    .into_diagnostic()?;
+                None
+            }
+        }
+    };
+    // Remote assets are downloaded on first use, so a local copy kept for
+    // development stays out of the bundle.
+    let keep = |path: &str| {
+        !bundle.remote_assets.contains_key(path)
+            && reachable.as_ref().is_none_or(|x| x.contains(path))
+    };
+
+    let (checksums, sizes, mut dropped) = if assets_exist {
+        insert_assets(&mut box_file, &assets_path, &keep).await?
+    } else {
+        Default::default()
+    };
+    for (path, asset) in &bundle.remote_assets {
+        if dropped.remove(path).is_some() {
+            shell
+                .status(
+                    "Remote",
+                    format!("{} is left out, it is downloaded from {}", path, asset.url),
+                )
+                .into_diagnostic()?;
+        }
+    }
+
+    if !dropped.is_empty() {
+        for (path, size) in &dropped {
+            shell
+                .status("Dropped", format!("{} ({} bytes)", path, size))
+                .into_diagnostic()?;
+        }
+        let saved: u64 = dropped.values().sum();
+        let kept: u64 = sizes.values().sum();
+        shell
+            .status(
+                "Pruned",
+                format!(
+                    "{} unused asset(s), {} bytes ({:.0}% of assets)",
+                    dropped.len(),
+                    saved,
+                    saved as f64 * 100.0 / (saved + kept).max(1) as f64
+                ),
+            )
+            .into_diagnostic()?;
+    }
+
     if assets_exist {
-        insert_assets(&mut box_file, &assets_path).await?;
+        box_file
+            .insert(
+                &CompressionConfig::new(Compression::Stored),
+                BoxPath::new(CHECKSUMS_FILE).into_diagnostic()?,
+                &mut std::io::Cursor::new(serde_json::to_vec(&checksums).into_diagnostic()?),
+                Default::default(),
+            )
+            .await
+            .into_diagnostic()?;
     }
 
+    let root = std::env::current_dir().into_diagnostic()?;
+    let mut manifest = BuildManifest::new(
+        manifest_pipeline_path(&pipeline_path, &root),
+        pipeline_file.as_bytes(),
+        &checksums,
+        &sizes,
+        build_timestamp(),
+    );
+    manifest.pipeline.json_hash = Some(Checksums::hash(&pipeline_json));
+    box_file
+        .insert(
+            &CompressionConfig::new(Compression::Stored),
+            BoxPath::new(BUILD_MANIFEST_FILE).into_diagnostic()?,
+            &mut std::io::Cursor::new(serde_json::to_vec_pretty(&manifest).into_diagnostic()?),
+            Default::default(),
+        )
+        .await
+        .into_diagnostic()?;
+
     // Set bundle metadata attributes
     if let Some(bundle_type) = &args.r#type {
         box_file
@@ -179,11 +401,84 @@ pub async fn bundle(shell: &mut Shell, args: BundleArgs) -> miette::Result<()> {
     Ok(())
 }
 
+/// `pipeline_path` relative to the bundle's `root`, with `/` separators, so
+/// the manifest doesn't record where the bundle happened to be built. A
+/// pipeline outside `root` is recorded by its file name.
+fn manifest_pipeline_path(pipeline_path: &Path, root: &Path) -> String {
+    let absolute = |path: &Path| path.canonicalize().unwrap_or_else(|_| root.join(path));
+    let path = absolute(pipeline_path);
+    let relative = match path.strip_prefix(absolute(root)) {
+        Ok(relative) => relative,
+        Err(_) => Path::new(path.file_name().unwrap_or_default()),
+    };
+    relative
+        .components()
+        .map(|x| x.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use box_format::BoxFileReader;
 
+    fn toy_bundle() -> PipelineBundle {
+        let json = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../tests/fixtures/toy/pipeline.json"
+        ))
+        .unwrap();
+        PipelineBundle::from_json(serde_json::from_slice(&json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn assets_of_left_out_pipelines_are_unreachable() {
+        let mut bundle = toy_bundle();
+        let left_out = select_pipelines(&mut bundle, &["shout".into()], &[]).unwrap();
+        assert_eq!(left_out, ["disambiguate"]);
+        assert_eq!(bundle.list_pipelines(), ["shout"]);
+
+        let reachable = Reachable::new(&bundle, &left_out).unwrap();
+        assert!(!reachable.contains("toy.cg3"));
+        assert!(reachable.contains("selftest/mun.input"));
+        assert!(reachable.contains("selftest/mun.expected"));
+        assert!(reachable.contains("selftest/mun.shout.expected"));
+        assert!(!reachable.contains("selftest/mun.disambiguate.expected"));
+
+        let mut bundle = toy_bundle();
+        let left_out = select_pipelines(&mut bundle, &[], &["shout".into()]).unwrap();
+        let reachable = Reachable::new(&bundle, &left_out).unwrap();
+        assert!(reachable.contains("toy.cg3"));
+    }
+
+    #[test]
+    fn manifest_records_the_pipeline_relative_to_the_root() {
+        let temp = tempfile::tempdir().unwrap();
+        let root = temp.path().join("lang-sme");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::write(root.join("src/pipeline.ts"), "").unwrap();
+
+        assert_eq!(
+            manifest_pipeline_path(&root.join("src/pipeline.ts"), &root),
+            "src/pipeline.ts"
+        );
+        assert_eq!(
+            manifest_pipeline_path(Path::new("./pipeline.ts"), &root),
+            "pipeline.ts"
+        );
+        assert_eq!(
+            manifest_pipeline_path(&temp.path().join("elsewhere/pipeline.ts"), &root),
+            "pipeline.ts"
+        );
+    }
+
+    #[test]
+    fn unknown_pipelines_are_refused() {
+        let err = select_pipelines(&mut toy_bundle(), &[], &["speller".into()]).unwrap_err();
+        assert!(err.to_string().contains("No pipeline named 'speller'"));
+    }
+
     #[tokio::test]
     async fn nested_assets_are_stored_at_sixteen_byte_alignment() {
         let temp = tempfile::tempdir().unwrap();
@@ -196,9 +491,17 @@ mod tests {
         let mut writer = BoxFileWriter::create_with_alignment(&bundle_path, BUNDLE_ALIGNMENT)
             .await
             .unwrap();
-        insert_assets(&mut writer, &assets).await.unwrap();
+        let (checksums, sizes, _) = insert_assets(&mut writer, &assets, &|_| true)
+            .await
+            .unwrap();
         writer.finish().await.unwrap();
 
+        assert_eq!(
+            checksums.assets.get("model/weights.bin").map(String::as_str),
+            Some(Checksums::hash(b"mapped model bytes").as_str())
+        );
+        assert_eq!(sizes.get("model/weights.bin"), Some(&18));
+
         let reader = BoxFileReader::open(&bundle_path).await.unwrap();
         assert_eq!(reader.alignment(), BUNDLE_ALIGNMENT);
         let record = reader
@@ -0,0 +1,121 @@
+//! `debug cg3`: check a CG stream, e.g. one edited by hand before feeding it
+//! back into a pipeline, and pretty-print it or summarize what's in it.
+
+use std::{collections::BTreeMap, io::Read};
+
+use divvun_runtime::modules::cg3::{Block, Output};
+use miette::IntoDiagnostic;
+
+use crate::{cli::DebugCg3Args, shell::Shell};
+
+pub fn cg3(shell: &mut Shell, args: DebugCg3Args) -> miette::Result<()> {
+    let (name, text) = match &args.file {
+        Some(path) => (
+            path.display().to_string(),
+            std::fs::read_to_string(path)
+                .map_err(|e| miette::miette!("Failed to read {}: {}", path.display(), e))?,
+        ),
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .into_diagnostic()?;
+            ("stdin".to_string(), text)
+        }
+    };
+
+    let output = Output::new(text.as_str());
+    let errors = output.errors();
+    if !errors.is_empty() {
+        for (line, error) in &errors {
+            shell
+                .error(format!("{}:{}: {}", name, line, error))
+                .into_diagnostic()?;
+        }
+        miette::bail!("{} lines of {} are not valid CG", errors.len(), name);
+    }
+
+    if args.stats {
+        print_stats(&Stats::new(&output));
+    } else if !args.check {
+        shell
+            .print_highlighted_stdout(&output.to_string(), "cg3")
+            .into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Stats {
+    cohorts: usize,
+    readings: usize,
+    /// Cohorts left with more than one reading.
+    ambiguous: usize,
+    /// `&`-prefixed tags, as added by grammar checker rules, by tag.
+    error_tags: BTreeMap<String, usize>,
+}
+
+impl Stats {
+    fn new<'a>(output: &'a Output<'a>) -> Stats {
+        let mut stats = Stats::default();
+        for block in output.iter().filter_map(Result::ok) {
+            let Block::Cohort(cohort) = block else {
+                continue;
+            };
+            stats.cohorts += 1;
+            stats.readings += cohort.readings.len();
+            if cohort.readings.len() > 1 {
+                stats.ambiguous += 1;
+            }
+            for tag in cohort.readings.iter().flat_map(|x| &x.tags) {
+                if tag.starts_with('&') {
+                    *stats.error_tags.entry(tag.to_string()).or_default() += 1;
+                }
+            }
+        }
+        stats
+    }
+}
+
+fn print_stats(stats: &Stats) {
+    let per_cohort = match stats.cohorts {
+        0 => 0.0,
+        n => stats.readings as f64 / n as f64,
+    };
+    println!("cohorts     {}", stats.cohorts);
+    println!(
+        "readings    {} ({:.2} per cohort)",
+        stats.readings, per_cohort
+    );
+    println!("ambiguous   {} cohorts", stats.ambiguous);
+    println!("error tags  {}", stats.error_tags.values().sum::<usize>());
+    let width = stats.error_tags.keys().map(|x| x.len()).max().unwrap_or(0);
+    for (tag, count) in &stats.error_tags {
+        println!("  {:width$}  {}", tag, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_cohorts_readings_and_error_tags() {
+        let output = Output::new(
+            "\"<Mun>\"\n\t\"mun\" Pron Sg1 Nom\n:\n\"<boahtán>\"\n\t\"boahtit\" V Ind Prs Sg1 &msyn-agr\n\t\"boahtit\" V PrfPrc &msyn-agr\n\"<.>\"\n\t\".\" CLB &punct\n",
+        );
+        let stats = Stats::new(&output);
+        assert_eq!(
+            stats,
+            Stats {
+                cohorts: 3,
+                readings: 4,
+                ambiguous: 1,
+                error_tags: [("&msyn-agr".to_string(), 2), ("&punct".to_string(), 1)]
+                    .into_iter()
+                    .collect(),
+            }
+        );
+    }
+}
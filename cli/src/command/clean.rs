@@ -0,0 +1,302 @@
+//! `test --clean`: check text known to be correct and count what the bundle
+//! finds in it anyway, per error type. Every error found is a false positive,
+//! so a checker fit to ship keeps them at zero, or under a limit set per
+//! type.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use divvun_runtime::{
+    ast::PipelineHandle, bundle::BundleOptions, modules::PipelineValue, session::split_sentences,
+};
+use futures_util::StreamExt;
+use miette::IntoDiagnostic;
+use walkdir::WalkDir;
+
+use crate::{cli::TestArgs, shell::Shell};
+
+use super::{
+    fix::{Candidate, candidates},
+    run::{load_bundle, parse_config},
+};
+
+/// A sentence of the corpus an error type was found in.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+struct Example {
+    file: String,
+    /// The sentence with the error's form in brackets.
+    sentence: String,
+    suggestions: Vec<String>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct TypeReport {
+    title: String,
+    count: usize,
+    allowed: usize,
+    examples: Vec<Example>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+struct Report {
+    files: usize,
+    sentences: usize,
+    words: usize,
+    /// Error types found, by ID.
+    errors: BTreeMap<String, TypeReport>,
+}
+
+impl Report {
+    /// Count the errors found in `paragraph`, keeping up to `examples`
+    /// sentences for each type.
+    fn add(&mut self, file: &str, paragraph: &str, found: Vec<Candidate>, examples: usize) {
+        let sentences = split_sentences(paragraph);
+        self.sentences += sentences.len();
+        self.words += paragraph.split_whitespace().count();
+
+        for error in found {
+            let entry = self.errors.entry(error.error_id.clone()).or_default();
+            entry.count += 1;
+            if entry.title.is_empty() {
+                entry.title = error.title.clone();
+            }
+            if entry.examples.len() >= examples {
+                continue;
+            }
+            let sentence = sentences
+                .iter()
+                .find(|x| x.start <= error.start && error.start < x.end)
+                .map(|x| x.start..x.end.max(error.end))
+                .unwrap_or(0..paragraph.len());
+            entry.examples.push(Example {
+                file: file.to_string(),
+                sentence: format!(
+                    "{}[{}]{}",
+                    &paragraph[sentence.start..error.start],
+                    &paragraph[error.start..error.end],
+                    &paragraph[error.end..sentence.end]
+                ),
+                suggestions: error.suggestions,
+            });
+        }
+    }
+
+    /// Error types found more often than allowed, most frequent first.
+    fn over_limit(&self) -> Vec<(&str, &TypeReport)> {
+        let mut over = self
+            .errors
+            .iter()
+            .filter(|(_, x)| x.count > x.allowed)
+            .map(|(id, x)| (id.as_str(), x))
+            .collect::<Vec<_>>();
+        over.sort_by(|a, b| b.1.count.cmp(&a.1.count));
+        over
+    }
+}
+
+pub async fn clean(shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
+    let corpus = args.clean.clone().unwrap();
+    let path = match args.files.as_slice() {
+        [] => std::env::current_dir().into_diagnostic()?,
+        [path] => path.clone(),
+        _ => miette::bail!("--clean takes a single bundle or project path"),
+    };
+    let allowed = parse_allow(&args.allow)?;
+
+    let options = BundleOptions {
+        pipeline: args.pipeline.clone(),
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options, false).await?;
+    let mut pipe = bundle
+        .create(parse_config(&args.config)?)
+        .await
+        .into_diagnostic()?;
+
+    let mut report = Report::default();
+    for (name, file) in corpus_files(&corpus)? {
+        let text = std::fs::read_to_string(&file)
+            .map_err(|e| miette::miette!("Failed to read {}: {}", file.display(), e))?;
+        for paragraph in text.split("\n\n").map(str::trim).filter(|x| !x.is_empty()) {
+            let outputs = check(&mut pipe, paragraph).await?;
+            let found =
+                candidates(paragraph, &outputs).map_err(|e| miette::miette!("{}: {}", name, e))?;
+            report.add(&name, paragraph, found, args.examples);
+        }
+        report.files += 1;
+    }
+    for (id, entry) in report.errors.iter_mut() {
+        entry.allowed = allowed.get(id).copied().unwrap_or(args.max_errors);
+    }
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&report).into_diagnostic()?
+        );
+    } else {
+        print_report(shell, &report)?;
+    }
+
+    let over = report.over_limit();
+    if !over.is_empty() {
+        miette::bail!(
+            "{} error type(s) over their limit in the clean corpus: {}",
+            over.len(),
+            over.iter()
+                .map(|(id, x)| format!("{} ({} > {})", id, x.count, x.allowed))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    shell
+        .status(
+            "Finished",
+            format!(
+                "{} sentences in {} files, no error type over its limit",
+                report.sentences, report.files
+            ),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Limits given with `--allow`, as `ERROR_ID=N`.
+fn parse_allow(allow: &[String]) -> miette::Result<BTreeMap<String, usize>> {
+    allow
+        .iter()
+        .map(|x| {
+            let (id, count) = x
+                .split_once('=')
+                .ok_or_else(|| miette::miette!("--allow takes ERROR_ID=N, not {:?}", x))?;
+            let count = count
+                .parse()
+                .map_err(|_| miette::miette!("--allow {}: {:?} is not a count", id, count))?;
+            Ok((id.to_string(), count))
+        })
+        .collect()
+}
+
+/// The corpus file, or the files of the corpus directory by name.
+fn corpus_files(corpus: &Path) -> miette::Result<Vec<(String, PathBuf)>> {
+    if corpus.is_file() {
+        return Ok(vec![(corpus.display().to_string(), corpus.to_path_buf())]);
+    }
+    let files = WalkDir::new(corpus)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| {
+            let name = e.path().strip_prefix(corpus).unwrap_or(e.path());
+            (name.display().to_string(), e.path().to_path_buf())
+        })
+        .collect::<Vec<_>>();
+    if files.is_empty() {
+        miette::bail!("no files in corpus {}", corpus.display());
+    }
+    Ok(files)
+}
+
+async fn check(pipe: &mut PipelineHandle, text: &str) -> miette::Result<Vec<serde_json::Value>> {
+    let mut outputs = Vec::new();
+    let mut stream = pipe.forward(PipelineValue::String(text.to_string())).await;
+    while let Some(result) = stream.next().await {
+        match result.into_diagnostic()? {
+            PipelineValue::Json(json) => outputs.push(json),
+            _ => miette::bail!("--clean needs a pipeline whose output is divvun::suggest's JSON"),
+        }
+    }
+    Ok(outputs)
+}
+
+fn print_report(shell: &mut Shell, report: &Report) -> miette::Result<()> {
+    let mut types = report.errors.iter().collect::<Vec<_>>();
+    types.sort_by(|a, b| b.1.count.cmp(&a.1.count).then(a.0.cmp(b.0)));
+
+    for (id, entry) in types {
+        let per_mille = entry.count as f64 * 1000.0 / report.words.max(1) as f64;
+        let message = format!(
+            "{} {}: {} ({:.2} per 1000 words, {} allowed)",
+            id, entry.title, entry.count, per_mille, entry.allowed
+        );
+        if entry.count > entry.allowed {
+            shell
+                .status_with_color("Over", message, termcolor::Color::Red)
+                .into_diagnostic()?;
+        } else {
+            shell.status("Within", message).into_diagnostic()?;
+        }
+        for example in &entry.examples {
+            let suggestions = match example.suggestions.as_slice() {
+                [] => String::new(),
+                x => format!(" -> {}", x.join(", ")),
+            };
+            println!("  {}: {}{}", example.file, example.sentence, suggestions);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(text: &str, form: &str, error_id: &str) -> Candidate {
+        let start = text.find(form).unwrap();
+        Candidate {
+            start,
+            end: start + form.len(),
+            form: form.to_string(),
+            error_id: error_id.to_string(),
+            title: format!("{} title", error_id),
+            description: String::new(),
+            suggestions: vec!["x".to_string()],
+            autofix: false,
+        }
+    }
+
+    #[test]
+    fn counts_errors_by_type_with_examples() {
+        let text = "Mun boahtán. Dat lea buorre dáhpáhus. Son lea dáppe.";
+        let mut report = Report::default();
+        report.add(
+            "a.txt",
+            text,
+            vec![
+                candidate(text, "dáhpáhus", "typo"),
+                candidate(text, "dáppe", "typo"),
+                candidate(text, "Mun", "msyn"),
+            ],
+            1,
+        );
+        report.errors.get_mut("msyn").unwrap().allowed = 1;
+
+        assert_eq!(report.sentences, 3);
+        assert_eq!(report.words, 9);
+        let typo = &report.errors["typo"];
+        assert_eq!(typo.count, 2);
+        assert_eq!(
+            typo.examples,
+            [Example {
+                file: "a.txt".to_string(),
+                sentence: "Dat lea buorre [dáhpáhus].".to_string(),
+                suggestions: vec!["x".to_string()],
+            }]
+        );
+        let over = report.over_limit();
+        assert_eq!(over.len(), 1);
+        assert_eq!(over[0].0, "typo");
+    }
+
+    #[test]
+    fn parses_per_type_limits() {
+        let allowed = parse_allow(&["typo=3".to_string()]).unwrap();
+        assert_eq!(allowed.get("typo"), Some(&3));
+        assert!(parse_allow(&["typo".to_string()]).is_err());
+        assert!(parse_allow(&["typo=many".to_string()]).is_err());
+    }
+}
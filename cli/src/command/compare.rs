@@ -0,0 +1,446 @@
+//! `debug compare`: run text through a bundle and compare its grammar errors
+//! with what libdivvun's `divvun-checker` found in the same text, field by
+//! field, so differences between the two implementations can be tracked down
+//! one category at a time.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use divvun_runtime::{ast::PipelineHandle, bundle::BundleOptions, modules::PipelineValue};
+use futures_util::StreamExt;
+use miette::IntoDiagnostic;
+use walkdir::WalkDir;
+
+use crate::{cli::DebugCompareArgs, shell::Shell};
+
+use super::{
+    fix::candidates,
+    run::{load_bundle, parse_asset_overrides, parse_config},
+};
+
+/// One grammar error, with offsets in UTF-16 code units as libdivvun gives
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+struct Found {
+    start: usize,
+    end: usize,
+    form: String,
+    error_id: String,
+    title: String,
+    description: String,
+    suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum Category {
+    /// Only libdivvun found the error.
+    Missing,
+    /// Only the runtime found the error.
+    Extra,
+    /// Same error type on overlapping but different ranges.
+    Range,
+    /// Same range, different error type.
+    ErrorId,
+    Title,
+    Description,
+    /// Different suggestions.
+    Suggestions,
+    /// The same suggestions in a different order.
+    SuggestionOrder,
+}
+
+impl Category {
+    fn name(self) -> &'static str {
+        match self {
+            Category::Missing => "missing",
+            Category::Extra => "extra",
+            Category::Range => "range",
+            Category::ErrorId => "error-id",
+            Category::Title => "title",
+            Category::Description => "description",
+            Category::Suggestions => "suggestions",
+            Category::SuggestionOrder => "suggestion-order",
+        }
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct Difference {
+    category: Category,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    libdivvun: Option<Found>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    runtime: Option<Found>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct Report {
+    name: String,
+    differences: Vec<Difference>,
+}
+
+pub async fn compare(shell: &mut Shell, args: DebugCompareArgs) -> miette::Result<()> {
+    let path = match args.path.clone() {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = BundleOptions {
+        pipeline: args.pipeline.clone(),
+        asset_overrides: parse_asset_overrides(&args.asset_override)?,
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options, args.skip_check).await?;
+    let config = parse_config(&args.config)?;
+
+    let cases = match &args.batch {
+        Some(corpus) => batch_cases(corpus, &args.libdivvun_json)?,
+        None => vec![(
+            args.libdivvun_json.display().to_string(),
+            args.input.clone(),
+            args.libdivvun_json.clone(),
+        )],
+    };
+
+    let mut pipe = bundle.create(config).await.into_diagnostic()?;
+    let mut reports = Vec::new();
+    for (name, input, expected) in cases {
+        let contents = std::fs::read_to_string(&expected)
+            .map_err(|e| miette::miette!("Failed to read {}: {}", expected.display(), e))?;
+        let json = serde_json::from_str(&contents)
+            .map_err(|e| miette::miette!("{} is not JSON: {}", expected.display(), e))?;
+        let (text, libdivvun) =
+            parse_libdivvun(&json).map_err(|e| miette::miette!("{}: {}", expected.display(), e))?;
+        let text = match input {
+            Some(input) => input,
+            None => text
+                .ok_or_else(|| miette::miette!("{} has no \"text\"; give it with --input", name))?,
+        };
+
+        let runtime = check(&mut pipe, &text).await?;
+        reports.push(Report {
+            name,
+            differences: diff(libdivvun, runtime),
+        });
+    }
+
+    let mut counts = BTreeMap::new();
+    for difference in reports.iter().flat_map(|x| &x.differences) {
+        *counts.entry(difference.category).or_insert(0) += 1;
+    }
+    let total = counts.values().sum::<usize>();
+
+    if args.json {
+        let json = serde_json::json!({
+            "reports": reports,
+            "counts": counts.iter().map(|(k, v)| (k.name(), v)).collect::<BTreeMap<_, _>>(),
+        });
+        println!("{}", serde_json::to_string_pretty(&json).into_diagnostic()?);
+    } else {
+        for report in &reports {
+            print_report(shell, report)?;
+        }
+        for (category, count) in &counts {
+            shell
+                .status("Total", format!("{} {}", count, category.name()))
+                .into_diagnostic()?;
+        }
+    }
+
+    if total > 0 {
+        miette::bail!(
+            "{} differences from libdivvun in {} of {} inputs",
+            total,
+            reports.iter().filter(|x| !x.differences.is_empty()).count(),
+            reports.len()
+        );
+    }
+    shell
+        .status(
+            "Finished",
+            format!("{} inputs match libdivvun", reports.len()),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// Files of the corpus with the libdivvun output for each, at the same path
+/// under `expected_dir` with `.json` appended.
+fn batch_cases(
+    corpus: &Path,
+    expected_dir: &Path,
+) -> miette::Result<Vec<(String, Option<String>, PathBuf)>> {
+    let mut cases = Vec::new();
+    for entry in WalkDir::new(corpus)
+        .follow_links(true)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let relative = entry.path().strip_prefix(corpus).unwrap_or(entry.path());
+        let text = std::fs::read_to_string(entry.path())
+            .map_err(|e| miette::miette!("Failed to read {}: {}", entry.path().display(), e))?;
+        let mut expected = expected_dir.join(relative).into_os_string();
+        expected.push(".json");
+        cases.push((relative.display().to_string(), Some(text), expected.into()));
+    }
+    if cases.is_empty() {
+        miette::bail!("no files in corpus {}", corpus.display());
+    }
+    Ok(cases)
+}
+
+/// Text and errors of `divvun-checker` JSON output:
+/// `{"errs": [[form, beg, end, err, msg, [reps], title]], "text": ...}`.
+fn parse_libdivvun(json: &serde_json::Value) -> Result<(Option<String>, Vec<Found>), String> {
+    let Some(errs) = json.get("errs").and_then(|x| x.as_array()) else {
+        return Err("expected divvun-checker output with an \"errs\" array".to_string());
+    };
+    let text = json
+        .get("text")
+        .and_then(|x| x.as_str())
+        .map(str::to_string);
+
+    let mut found = Vec::new();
+    for (i, err) in errs.iter().enumerate() {
+        let fields = err.as_array().map(|x| x.as_slice()).unwrap_or_default();
+        let str_at = |n: usize| fields.get(n).and_then(|x| x.as_str());
+        let offset_at = |n: usize| fields.get(n).and_then(|x| x.as_u64());
+        let (Some(form), Some(start), Some(end), Some(error_id)) =
+            (str_at(0), offset_at(1), offset_at(2), str_at(3))
+        else {
+            return Err(format!("errs[{}] is not [form, beg, end, err, ...]", i));
+        };
+        found.push(Found {
+            start: start as usize,
+            end: end as usize,
+            form: form.to_string(),
+            error_id: error_id.to_string(),
+            description: str_at(4).unwrap_or_default().to_string(),
+            suggestions: fields
+                .get(5)
+                .and_then(|x| x.as_array())
+                .into_iter()
+                .flatten()
+                .filter_map(|x| x.as_str().map(str::to_string))
+                .collect(),
+            // Older versions have no title
+            title: str_at(6).unwrap_or_default().to_string(),
+        });
+    }
+    Ok((text, found))
+}
+
+/// Run `text` through the pipeline and return its errors with UTF-16 offsets.
+async fn check(pipe: &mut PipelineHandle, text: &str) -> miette::Result<Vec<Found>> {
+    let mut outputs = Vec::new();
+    let mut stream = pipe.forward(PipelineValue::String(text.to_string())).await;
+    while let Some(result) = stream.next().await {
+        match result.into_diagnostic()? {
+            PipelineValue::Json(json) => outputs.push(json),
+            _ => miette::bail!("compare needs a pipeline whose output is divvun::suggest's JSON"),
+        }
+    }
+
+    let utf16 = |at: usize| text[..at].encode_utf16().count();
+    Ok(candidates(text, &outputs)?
+        .into_iter()
+        .map(|x| Found {
+            start: utf16(x.start),
+            end: utf16(x.end),
+            form: x.form,
+            error_id: x.error_id,
+            title: x.title,
+            description: x.description,
+            suggestions: x.suggestions,
+        })
+        .collect())
+}
+
+/// Pair up the errors of both sides and list how they differ. Errors pair
+/// first on range and type, then on range alone, then on type with an
+/// overlapping range; whatever is left only one side found.
+fn diff(libdivvun: Vec<Found>, runtime: Vec<Found>) -> Vec<Difference> {
+    let mut left = libdivvun.into_iter().map(Some).collect::<Vec<_>>();
+    let mut right = runtime.into_iter().map(Some).collect::<Vec<_>>();
+    let mut differences = Vec::new();
+
+    let passes: [fn(&Found, &Found) -> bool; 3] = [
+        |a, b| (a.start, a.end, &a.error_id) == (b.start, b.end, &b.error_id),
+        |a, b| (a.start, a.end) == (b.start, b.end),
+        |a, b| a.error_id == b.error_id && a.start < b.end && b.start < a.end,
+    ];
+    for same in passes {
+        for a in left.iter_mut() {
+            let Some(x) = a.as_ref() else {
+                continue;
+            };
+            let Some(b) = right
+                .iter_mut()
+                .find(|b| b.as_ref().is_some_and(|y| same(x, y)))
+            else {
+                continue;
+            };
+            let (x, y) = (a.take().unwrap(), b.take().unwrap());
+            differences.extend(compare_pair(x, y));
+        }
+    }
+
+    differences.extend(left.into_iter().flatten().map(|x| Difference {
+        category: Category::Missing,
+        libdivvun: Some(x),
+        runtime: None,
+    }));
+    differences.extend(right.into_iter().flatten().map(|x| Difference {
+        category: Category::Extra,
+        libdivvun: None,
+        runtime: Some(x),
+    }));
+    differences.sort_by_key(|x| {
+        let at = x.libdivvun.as_ref().or(x.runtime.as_ref()).unwrap();
+        (at.start, at.end, x.category)
+    });
+    differences
+}
+
+/// Differences between two errors paired up by [`diff`], one per field.
+fn compare_pair(libdivvun: Found, runtime: Found) -> Vec<Difference> {
+    let mut categories = Vec::new();
+    if (libdivvun.start, libdivvun.end) != (runtime.start, runtime.end) {
+        categories.push(Category::Range);
+    }
+    if libdivvun.error_id != runtime.error_id {
+        categories.push(Category::ErrorId);
+    }
+    if libdivvun.title != runtime.title {
+        categories.push(Category::Title);
+    }
+    if libdivvun.description != runtime.description {
+        categories.push(Category::Description);
+    }
+    if libdivvun.suggestions != runtime.suggestions {
+        let mut a = libdivvun.suggestions.clone();
+        let mut b = runtime.suggestions.clone();
+        a.sort();
+        b.sort();
+        categories.push(if a == b {
+            Category::SuggestionOrder
+        } else {
+            Category::Suggestions
+        });
+    }
+
+    categories
+        .into_iter()
+        .map(|category| Difference {
+            category,
+            libdivvun: Some(libdivvun.clone()),
+            runtime: Some(runtime.clone()),
+        })
+        .collect()
+}
+
+fn print_report(shell: &mut Shell, report: &Report) -> miette::Result<()> {
+    if report.differences.is_empty() {
+        return shell.status("Same", &report.name).into_diagnostic();
+    }
+    shell
+        .status_with_color("Differs", &report.name, termcolor::Color::Red)
+        .into_diagnostic()?;
+    for difference in &report.differences {
+        let (a, b) = (&difference.libdivvun, &difference.runtime);
+        let at = a.as_ref().or(b.as_ref()).unwrap();
+        let detail = match (difference.category, a, b) {
+            (Category::Missing | Category::Extra, _, _) => {
+                format!("{} [{}]", at.title, at.error_id)
+            }
+            (Category::Range, Some(a), Some(b)) => {
+                format!("{}..{} vs {}..{}", a.start, a.end, b.start, b.end)
+            }
+            (Category::ErrorId, Some(a), Some(b)) => format!("{} vs {}", a.error_id, b.error_id),
+            (Category::Title, Some(a), Some(b)) => format!("{:?} vs {:?}", a.title, b.title),
+            (Category::Description, Some(a), Some(b)) => {
+                format!("{:?} vs {:?}", a.description, b.description)
+            }
+            (Category::Suggestions | Category::SuggestionOrder, Some(a), Some(b)) => {
+                format!("{:?} vs {:?}", a.suggestions, b.suggestions)
+            }
+            _ => String::new(),
+        };
+        println!(
+            "  {}..{} {:?} {}: {}",
+            at.start,
+            at.end,
+            at.form,
+            difference.category.name(),
+            detail
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn found(start: usize, end: usize, error_id: &str, suggestions: &[&str]) -> Found {
+        Found {
+            start,
+            end,
+            form: String::new(),
+            error_id: error_id.to_string(),
+            title: format!("{} title", error_id),
+            description: String::new(),
+            suggestions: suggestions.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_divvun_checker_output() {
+        let json = serde_json::json!({
+            "errs": [
+                ["dáhpáhus", 4, 12, "typo", "Ii leat sátnelisttus", ["dáhpáhuvvá"], "Čállinmeattáhus"]
+            ],
+            "text": "Dat dáhpáhus."
+        });
+        let (text, errs) = parse_libdivvun(&json).unwrap();
+        assert_eq!(text.as_deref(), Some("Dat dáhpáhus."));
+        assert_eq!(errs[0].error_id, "typo");
+        assert_eq!(errs[0].title, "Čállinmeattáhus");
+        assert_eq!(errs[0].suggestions, ["dáhpáhuvvá"]);
+    }
+
+    #[test]
+    fn categorizes_differences() {
+        let libdivvun = vec![
+            found(0, 3, "typo", &["a", "b"]),
+            found(4, 8, "agr", &["x"]),
+            found(9, 12, "wo", &[]),
+            found(20, 22, "punct", &[]),
+        ];
+        let runtime = vec![
+            found(0, 3, "typo", &["b", "a"]),
+            found(4, 8, "msyn", &["x"]),
+            found(9, 14, "wo", &[]),
+            found(30, 31, "space", &[]),
+        ];
+        let categories = diff(libdivvun, runtime)
+            .into_iter()
+            .map(|x| x.category)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            categories,
+            [
+                Category::SuggestionOrder,
+                Category::ErrorId,
+                Category::Title,
+                Category::Range,
+                Category::Missing,
+                Category::Extra,
+            ]
+        );
+    }
+}
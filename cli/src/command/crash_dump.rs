@@ -0,0 +1,191 @@
+//! `run --crash-dumps DIR`: when a pipeline fails, write everything needed to
+//! reproduce it (input, config, each step's output up to the failure, runtime
+//! and bundle versions) to a zip that can be attached to a bug report.
+//!
+//! In privacy mode (see `divvun_runtime::util::privacy`) the input and step
+//! outputs are stored as their length and hash only; run with `--log-input`
+//! to get a dump that reproduces the failure.
+
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use divvun_runtime::{bundle::Bundle, modules::PipelineValue, util::privacy};
+use miette::IntoDiagnostic;
+use zip::{CompressionMethod, ZipWriter, write::SimpleFileOptions};
+
+/// Values produced by each step of the current run, in the order they came.
+#[derive(Clone, Default)]
+pub struct StepLog(Arc<Mutex<Vec<(String, PipelineValue)>>>);
+
+impl StepLog {
+    pub fn record(&self, key: &str, value: &PipelineValue) {
+        self.0
+            .lock()
+            .unwrap()
+            .push((key.to_string(), value.clone()));
+    }
+
+    fn take(&self) -> Vec<(String, PipelineValue)> {
+        std::mem::take(&mut *self.0.lock().unwrap())
+    }
+}
+
+pub struct CrashReport<'a> {
+    pub bundle_path: &'a Path,
+    pub pipeline: Option<&'a str>,
+    pub input: &'a PipelineValue,
+    pub config: &'a serde_json::Value,
+    pub steps: &'a StepLog,
+    pub error: &'a str,
+}
+
+/// Write `report` to a new zip in `dir` and return its path.
+pub async fn write(dir: &Path, report: CrashReport<'_>) -> miette::Result<PathBuf> {
+    let manifest = if report.bundle_path.is_file() {
+        Bundle::manifest_from_bundle(report.bundle_path)
+            .await
+            .ok()
+            .flatten()
+    } else {
+        None
+    };
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default();
+    std::fs::create_dir_all(dir).into_diagnostic()?;
+    let path = dir.join(format!("crash-{}-{}.zip", now, std::process::id()));
+
+    let crash = serde_json::json!({
+        "bundle": report.bundle_path.display().to_string(),
+        "pipeline": report.pipeline,
+        "pipeline_hash": manifest.as_ref().map(|x| &x.pipeline.hash),
+        "time": now,
+        "error": privacy::redact(report.error).to_string(),
+        "redacted": privacy::is_enabled(),
+    });
+
+    let file = std::fs::File::create(&path).into_diagnostic()?;
+    let mut zip = ZipWriter::new(file);
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Stored);
+    let mut add = |name: &str, bytes: &[u8]| -> miette::Result<()> {
+        zip.start_file(name, options).into_diagnostic()?;
+        zip.write_all(bytes).into_diagnostic()
+    };
+
+    add("crash.json", &to_json(&crash)?)?;
+    add(
+        "error.txt",
+        privacy::redact(report.error).to_string().as_bytes(),
+    )?;
+    let (ext, bytes) = value_file(report.input.clone())?;
+    add(&format!("input.{}", ext), &bytes)?;
+    add("config.json", &to_json(report.config)?)?;
+    add("version.json", &to_json(&divvun_runtime::version_json())?)?;
+    if let Some(manifest) = &manifest {
+        let manifest = serde_json::to_value(manifest).into_diagnostic()?;
+        add("build-manifest.json", &to_json(&manifest)?)?;
+    }
+    for (i, (key, value)) in report.steps.take().into_iter().enumerate() {
+        let (ext, bytes) = value_file(value)?;
+        add(&format!("steps/{:02}-{}.{}", i + 1, key, ext), &bytes)?;
+    }
+
+    zip.finish().into_diagnostic()?;
+    Ok(path)
+}
+
+/// File extension and contents to store `value` as.
+fn value_file(value: PipelineValue) -> miette::Result<(&'static str, Vec<u8>)> {
+    if privacy::is_enabled() {
+        let summary = match &value {
+            PipelineValue::String(s) => privacy::redact(s).to_string(),
+            PipelineValue::Json(j) => privacy::redact(&j.to_string()).to_string(),
+            PipelineValue::Bytes(b) => format!("<redacted: {} bytes>", b.len()),
+            PipelineValue::Audio(a) => format!("<redacted: {} audio samples>", a.samples.len()),
+            PipelineValue::Multiple(x) => format!("<redacted: {} values>", x.len()),
+        };
+        return Ok(("redacted.txt", summary.into_bytes()));
+    }
+
+    Ok(match value {
+        PipelineValue::String(s) => ("txt", s.into_bytes()),
+        PipelineValue::Json(j) => ("json", to_json(&j)?),
+        PipelineValue::Bytes(b) => ("bin", b),
+        PipelineValue::Audio(a) => ("wav", a.to_wav_bytes().into_diagnostic()?),
+        value @ PipelineValue::Multiple(_) => ("txt", value.to_string().into_bytes()),
+    })
+}
+
+fn to_json(value: &serde_json::Value) -> miette::Result<Vec<u8>> {
+    serde_json::to_vec_pretty(value).into_diagnostic()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn read(zip: &mut zip::ZipArchive<std::fs::File>, name: &str) -> String {
+        let mut text = String::new();
+        zip.by_name(name)
+            .unwrap()
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    // One test, as privacy mode is process-wide
+    #[tokio::test]
+    async fn dumps_the_run_and_redacts_it_in_privacy_mode() {
+        let dir = tempfile::tempdir().unwrap();
+        let steps = StepLog::default();
+        steps.record("upper", &PipelineValue::String("LEAN".into()));
+        steps.record("reverse", &PipelineValue::String("NAEL".into()));
+
+        privacy::set_enabled(false);
+        let report = CrashReport {
+            bundle_path: Path::new("pipeline.ts"),
+            pipeline: Some("shout"),
+            input: &PipelineValue::String("lean".into()),
+            config: &serde_json::json!({ "upper": {} }),
+            steps: &steps,
+            error: "reverse failed",
+        };
+        let path = write(dir.path(), report).await.unwrap();
+        assert!(steps.take().is_empty());
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names = zip.file_names().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "config.json",
+                "crash.json",
+                "error.txt",
+                "input.txt",
+                "steps/01-upper.txt",
+                "steps/02-reverse.txt",
+                "version.json",
+            ]
+        );
+        assert_eq!(read(&mut zip, "input.txt"), "lean");
+        assert_eq!(read(&mut zip, "steps/02-reverse.txt"), "NAEL");
+        assert_eq!(read(&mut zip, "error.txt"), "reverse failed");
+        let crash: serde_json::Value = serde_json::from_str(&read(&mut zip, "crash.json")).unwrap();
+        assert_eq!(crash["pipeline"], "shout");
+        assert_eq!(crash["redacted"], false);
+
+        privacy::set_enabled(true);
+        let (ext, bytes) = value_file(PipelineValue::String("lean".into())).unwrap();
+        privacy::set_enabled(false);
+        assert_eq!(ext, "redacted.txt");
+        assert!(!String::from_utf8(bytes).unwrap().contains("lean"));
+    }
+}
@@ -0,0 +1,37 @@
+//! `daemon`: keep a bundle loaded and answer newline-delimited JSON requests
+//! from editor plugins on a local socket. See [`divvun_runtime::daemon`].
+
+use divvun_runtime::{
+    bundle::BundleOptions,
+    daemon::{Daemon, default_address},
+    pipe_pool::PipePoolOptions,
+};
+use miette::IntoDiagnostic;
+
+use crate::{cli::DaemonArgs, shell::Shell};
+
+use super::run::load_bundle;
+
+pub async fn daemon(shell: &mut Shell, args: DaemonArgs) -> miette::Result<()> {
+    let path = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = BundleOptions {
+        pipeline: args.pipeline,
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options, args.skip_check).await?;
+    let socket = match args.socket {
+        Some(socket) => socket,
+        None => default_address().into_diagnostic()?,
+    };
+
+    shell
+        .status("Listening", socket.display())
+        .into_diagnostic()?;
+    Daemon::new(bundle, PipePoolOptions::default())
+        .listen(&socket)
+        .await
+        .into_diagnostic()
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::HashMap,
+    io::{IsTerminal, Read},
+    sync::Arc,
+};
+
+use divvun_runtime::{
+    ast,
+    modules::{self, CommandDef, Context, PipelineValue, Ty},
+};
+use miette::IntoDiagnostic;
+
+use crate::{cli::ExecArgs, shell::Shell};
+
+fn signature(def: &CommandDef) -> String {
+    let args = def
+        .args
+        .iter()
+        .map(|arg| {
+            format!(
+                "{}{}: {}",
+                arg.name,
+                if arg.optional { "?" } else { "" },
+                arg.ty.as_dr_type()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(
+        "{}::{}({}) -> {}",
+        def.module,
+        def.name,
+        args,
+        def.returns.as_dr_type()
+    )
+}
+
+/// Turn `NAME=VALUE` into an argument of the type the command declares.
+/// Paths, strings and ints are taken as written; anything else is JSON.
+fn parse_arg(def: &CommandDef, raw: &str) -> miette::Result<(String, ast::Arg)> {
+    let Some((name, value)) = raw.split_once('=') else {
+        miette::bail!("Invalid argument '{}', expected NAME=VALUE", raw);
+    };
+    let Some(spec) = def.args.iter().find(|x| x.name == name) else {
+        miette::bail!("{} has no argument '{}'", signature(def), name);
+    };
+
+    let arg = match &spec.ty {
+        Ty::Path => ast::Arg::path(value),
+        Ty::String => ast::Arg::string(value),
+        Ty::Int => ast::Arg::int(
+            value
+                .parse()
+                .map_err(|_| miette::miette!("Argument '{}' must be an integer", name))?,
+        ),
+        ty => {
+            let json: serde_json::Value = serde_json::from_str(value)
+                .map_err(|e| miette::miette!("Argument '{}' is not valid JSON: {}", name, e))?;
+            ast::Arg::typed(ty.clone(), ast::Value::from_json(json).into_diagnostic()?)
+        }
+    };
+    Ok((name.to_string(), arg))
+}
+
+pub async fn exec(shell: &mut Shell, args: ExecArgs) -> miette::Result<()> {
+    let Some(command) = args.command else {
+        for module in modules::get_modules() {
+            for def in module.commands.iter() {
+                println!("{}", signature(def));
+            }
+        }
+        return Ok(());
+    };
+
+    let Some((module, name)) = command.split_once("::") else {
+        miette::bail!("Expected MODULE::COMMAND, e.g. divvun::suggest");
+    };
+    let Some(def) = modules::find_command(module, name) else {
+        miette::bail!(
+            "Unknown command {}; run `exec` without arguments to list them",
+            command
+        );
+    };
+
+    let kwargs = args
+        .args
+        .iter()
+        .map(|raw| parse_arg(def, raw))
+        .collect::<miette::Result<HashMap<_, _>>>()?;
+
+    let input = match args.input {
+        Some(input) => input,
+        None if !std::io::stdin().is_terminal() => {
+            let mut s = String::new();
+            std::io::stdin().read_to_string(&mut s).into_diagnostic()?;
+            s
+        }
+        None => miette::bail!("No input; pass -i or pipe it on stdin"),
+    };
+    let input = if def.input.iter().all(|ty| matches!(ty, Ty::Json)) {
+        PipelineValue::Json(serde_json::from_str(&input).into_diagnostic()?)
+    } else {
+        PipelineValue::String(input)
+    };
+
+    let config = match args.config.as_deref() {
+        Some(config) => serde_json::from_str(config).into_diagnostic()?,
+        None => serde_json::Value::Null,
+    };
+
+    let path = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let context = Arc::new(Context::standalone(&path).await.into_diagnostic()?);
+
+    shell.status("Running", signature(def)).into_diagnostic()?;
+    let outputs = modules::run_single(context, module, name, kwargs, input, config)
+        .await
+        .into_diagnostic()?;
+    for output in outputs {
+        super::run::print_input_highlighted(shell, &output, None)?;
+    }
+
+    Ok(())
+}
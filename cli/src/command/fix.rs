@@ -0,0 +1,473 @@
+//! `fix FILE`: check a text file and walk through its grammar errors one by
+//! one, applying the chosen suggestions, or with `--autofix` apply the safe
+//! ones without asking.
+
+use std::io::Write as _;
+
+use divvun_runtime::{bundle::BundleOptions, modules::PipelineValue};
+use futures_util::StreamExt;
+use miette::IntoDiagnostic;
+use termcolor::{Color, ColorSpec};
+
+use crate::{cli::FixArgs, shell::Shell};
+
+use super::run::{load_bundle, parse_asset_overrides, parse_config};
+
+/// A grammar error found in the checked text, with its byte range in the
+/// whole file.
+pub(crate) struct Candidate {
+    pub start: usize,
+    pub end: usize,
+    pub form: String,
+    pub error_id: String,
+    pub title: String,
+    pub description: String,
+    pub suggestions: Vec<String>,
+    pub autofix: bool,
+}
+
+impl Candidate {
+    /// Whether the single suggestion can be applied without asking: the
+    /// error's policy says so, or its type is in `allow`.
+    fn is_safe(&self, allow: &[String]) -> bool {
+        self.suggestions.len() == 1 && (self.autofix || allow.contains(&self.error_id))
+    }
+}
+
+/// Replace the byte range `start..end` with `replacement`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Fix {
+    pub start: usize,
+    pub end: usize,
+    pub replacement: String,
+}
+
+enum Choice {
+    Apply(String),
+    Skip,
+    Quit,
+}
+
+pub async fn fix(shell: &mut Shell, args: FixArgs) -> miette::Result<()> {
+    let text = std::fs::read_to_string(&args.file)
+        .map_err(|e| miette::miette!("Failed to read {}: {}", args.file.display(), e))?;
+    let candidates = check(shell, &args, &text).await?;
+
+    if args.autofix {
+        return autofix(shell, &args, &text, &candidates);
+    }
+
+    let mut fixes = Vec::new();
+    for candidate in &candidates {
+        if fixes.iter().any(|x: &Fix| overlaps(x, candidate)) {
+            continue;
+        }
+        match ask(shell, &args.file.display().to_string(), &text, candidate)? {
+            Choice::Apply(replacement) => fixes.push(Fix {
+                start: candidate.start,
+                end: candidate.end,
+                replacement,
+            }),
+            Choice::Skip => {}
+            Choice::Quit => break,
+        }
+    }
+
+    write_fixed(shell, &args, &text, &fixes)
+}
+
+/// Run the pipeline over `text` and return its grammar errors in order.
+pub(crate) async fn check(
+    shell: &mut Shell,
+    args: &FixArgs,
+    text: &str,
+) -> miette::Result<Vec<Candidate>> {
+    let path = match &args.path {
+        Some(path) => path.clone(),
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = BundleOptions {
+        pipeline: args.pipeline.clone(),
+        asset_overrides: parse_asset_overrides(&args.asset_override)?,
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options, args.skip_check).await?;
+    let mut pipe = bundle
+        .create(parse_config(&args.config)?)
+        .await
+        .into_diagnostic()?;
+
+    let mut outputs = Vec::new();
+    let mut stream = pipe.forward(PipelineValue::String(text.to_string())).await;
+    while let Some(result) = stream.next().await {
+        match result.into_diagnostic()? {
+            PipelineValue::Json(json) => outputs.push(json),
+            _ => miette::bail!("fix needs a pipeline whose output is divvun::suggest's JSON"),
+        }
+    }
+    candidates(text, &outputs)
+}
+
+/// Errors of the suggest `outputs` for `text`. Each output covers a piece of
+/// the text, in order; its byte offsets are relative to that piece.
+pub(crate) fn candidates(
+    text: &str,
+    outputs: &[serde_json::Value],
+) -> miette::Result<Vec<Candidate>> {
+    let mut cursor = 0;
+    let mut candidates = Vec::new();
+    for output in outputs {
+        let (Some(piece), Some(errors)) = (
+            output.get("text").and_then(|x| x.as_str()),
+            output.get("errors").and_then(|x| x.as_array()),
+        ) else {
+            miette::bail!("the pipeline's output is not divvun::suggest's JSON");
+        };
+        if output.get("encoding").and_then(|x| x.as_str()) == Some("utf-16") {
+            miette::bail!("byte offsets are needed; remove `encoding: \"utf-16\"` from the config");
+        }
+        let Some(base) = text[cursor..].find(piece).map(|x| x + cursor) else {
+            miette::bail!("the pipeline's output text does not match the input file");
+        };
+        cursor = base + piece.len();
+
+        for error in errors {
+            let str_field = |name: &str| {
+                error
+                    .get(name)
+                    .and_then(|x| x.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let offset = |name: &str| error.get(name).and_then(|x| x.as_u64());
+            let (Some(start), Some(end)) = (offset("start"), offset("end")) else {
+                continue;
+            };
+            let (start, end) = (base + start as usize, base + end as usize);
+            if end > cursor || !text.is_char_boundary(start) || !text.is_char_boundary(end) {
+                continue;
+            }
+            candidates.push(Candidate {
+                start,
+                end,
+                form: text[start..end].to_string(),
+                error_id: str_field("error_id"),
+                title: str_field("title"),
+                description: str_field("description"),
+                suggestions: error["suggestions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|x| x.as_str().map(str::to_string))
+                    .collect(),
+                autofix: error["autofix"].as_bool().unwrap_or(false),
+            });
+        }
+    }
+    candidates.sort_by_key(|x| (x.start, x.end));
+    Ok(candidates)
+}
+
+fn overlaps(fix: &Fix, candidate: &Candidate) -> bool {
+    candidate.start < fix.end && fix.start < candidate.end
+}
+
+/// 1-based line and column (in characters) of byte offset `at`, and the
+/// byte range of its line.
+pub(crate) fn locate(text: &str, at: usize) -> (usize, usize, std::ops::Range<usize>) {
+    let line_start = text[..at].rfind('\n').map(|x| x + 1).unwrap_or(0);
+    let line_end = text[at..].find('\n').map(|x| x + at).unwrap_or(text.len());
+    let line = text[..at].matches('\n').count() + 1;
+    let column = text[line_start..at].chars().count() + 1;
+    (line, column, line_start..line_end)
+}
+
+fn ask(shell: &mut Shell, file: &str, text: &str, candidate: &Candidate) -> miette::Result<Choice> {
+    let (line, column, range) = locate(text, candidate.start);
+    let line_end = range.end.max(candidate.end);
+    let bold = ColorSpec::new().set_bold(true).clone();
+    let marked = ColorSpec::new()
+        .set_fg(Some(Color::Red))
+        .set_bold(true)
+        .set_underline(true)
+        .clone();
+
+    let out = (|| -> std::io::Result<()> {
+        shell.write_stderr(format!("\n{}:{}:{}: ", file, line, column), &bold)?;
+        shell.write_stderr(
+            format!("{} [{}]\n", candidate.title, candidate.error_id),
+            &ColorSpec::new(),
+        )?;
+        if !candidate.description.is_empty() {
+            shell.write_stderr(format!("{}\n", candidate.description), &ColorSpec::new())?;
+        }
+        shell.write_stderr(
+            format!("    {}", &text[range.start..candidate.start]),
+            &ColorSpec::new(),
+        )?;
+        shell.write_stderr(&candidate.form, &marked)?;
+        shell.write_stderr(
+            format!("{}\n", &text[candidate.end..line_end]),
+            &ColorSpec::new(),
+        )?;
+        for (i, suggestion) in candidate.suggestions.iter().enumerate() {
+            shell.write_stderr(format!("  {}) {}\n", i + 1, suggestion), &ColorSpec::new())?;
+        }
+        Ok(())
+    })();
+    out.into_diagnostic()?;
+
+    let numbers = match candidate.suggestions.len() {
+        0 => String::new(),
+        1 => "1 apply, ".to_string(),
+        n => format!("1-{} apply, ", n),
+    };
+    loop {
+        eprint!("[{}e edit, s skip, q quit] ", numbers);
+        std::io::stderr().flush().into_diagnostic()?;
+        let mut answer = String::new();
+        if std::io::stdin().read_line(&mut answer).into_diagnostic()? == 0 {
+            return Ok(Choice::Quit);
+        }
+        match answer.trim() {
+            "s" | "" => return Ok(Choice::Skip),
+            "q" => return Ok(Choice::Quit),
+            "e" => {
+                eprint!("Replace '{}' with: ", candidate.form);
+                std::io::stderr().flush().into_diagnostic()?;
+                let mut replacement = String::new();
+                std::io::stdin()
+                    .read_line(&mut replacement)
+                    .into_diagnostic()?;
+                return Ok(Choice::Apply(
+                    replacement.trim_end_matches(['\r', '\n']).to_string(),
+                ));
+            }
+            n => match n.parse::<usize>() {
+                Ok(n) if (1..=candidate.suggestions.len()).contains(&n) => {
+                    return Ok(Choice::Apply(candidate.suggestions[n - 1].clone()));
+                }
+                _ => eprintln!("Unknown answer '{}'", n),
+            },
+        }
+    }
+}
+
+fn autofix(
+    shell: &mut Shell,
+    args: &FixArgs,
+    text: &str,
+    candidates: &[Candidate],
+) -> miette::Result<()> {
+    let mut fixes = Vec::new();
+    for candidate in candidates {
+        if !candidate.is_safe(&args.autofix_allow)
+            || fixes.iter().any(|x: &Fix| overlaps(x, candidate))
+        {
+            continue;
+        }
+        fixes.push(Fix {
+            start: candidate.start,
+            end: candidate.end,
+            replacement: candidate.suggestions[0].clone(),
+        });
+    }
+
+    print!(
+        "{}",
+        unified_diff(&args.file.display().to_string(), text, &fixes)
+    );
+    if args.in_place && !fixes.is_empty() {
+        std::fs::write(&args.file, apply(text, &fixes)).into_diagnostic()?;
+    }
+    shell
+        .status(
+            "Fixed",
+            format!(
+                "{} of {} error(s) in {}",
+                fixes.len(),
+                candidates.len(),
+                args.file.display()
+            ),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+/// `text` with every fix applied. Fixes must not overlap.
+pub(crate) fn apply(text: &str, fixes: &[Fix]) -> String {
+    let mut fixes = fixes.iter().collect::<Vec<_>>();
+    fixes.sort_by_key(|x| x.start);
+    let mut out = String::with_capacity(text.len());
+    let mut cursor = 0;
+    for fix in fixes {
+        out.push_str(&text[cursor..fix.start]);
+        out.push_str(&fix.replacement);
+        cursor = fix.end;
+    }
+    out.push_str(&text[cursor..]);
+    out
+}
+
+/// The changes `fixes` make to `text` as a unified diff with three lines of
+/// context, for `patch -p1` or `git apply`.
+pub(crate) fn unified_diff(file: &str, text: &str, fixes: &[Fix]) -> String {
+    let mut fixes = fixes.iter().collect::<Vec<_>>();
+    fixes.sort_by_key(|x| x.start);
+    let line_starts = std::iter::once(0)
+        .chain(text.match_indices('\n').map(|(i, _)| i + 1))
+        .filter(|&i| i < text.len())
+        .collect::<Vec<_>>();
+    let line_of = |at: usize| line_starts.partition_point(|&x| x <= at).saturating_sub(1);
+    let last_line = line_starts.len().saturating_sub(1);
+
+    // Line ranges touched by each fix, widened by the context and merged
+    // when they meet.
+    let mut hunks: Vec<(usize, usize, Vec<&Fix>)> = Vec::new();
+    for fix in fixes {
+        let first = line_of(fix.start).saturating_sub(3);
+        let last = (line_of(fix.end.max(fix.start + 1) - 1) + 3).min(last_line);
+        match hunks.last_mut() {
+            Some(hunk) if first <= hunk.1 + 1 => {
+                hunk.1 = hunk.1.max(last);
+                hunk.2.push(fix);
+            }
+            _ => hunks.push((first, last, vec![fix])),
+        }
+    }
+    if hunks.is_empty() {
+        return String::new();
+    }
+
+    let mut out = format!("--- a/{}\n+++ b/{}\n", file, file);
+    let mut shift = 0isize;
+    for (first, last, fixes) in hunks {
+        let start = line_starts[first];
+        let end = line_starts.get(last + 1).copied().unwrap_or(text.len());
+        let fixes = fixes
+            .into_iter()
+            .map(|x| Fix {
+                start: x.start - start,
+                end: x.end - start,
+                replacement: x.replacement.clone(),
+            })
+            .collect::<Vec<_>>();
+        let old_text = &text[start..end];
+        let new_text = apply(old_text, &fixes);
+        let old = old_text.split_inclusive('\n').collect::<Vec<_>>();
+        let new = new_text.split_inclusive('\n').collect::<Vec<_>>();
+
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let new_first = (first as isize + shift) as usize;
+        out.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            first + 1,
+            old.len(),
+            new_first + 1,
+            new.len()
+        ));
+        let mut push = |marker: char, line: &str| {
+            out.push(marker);
+            out.push_str(line);
+            if !line.ends_with('\n') {
+                out.push_str("\n\\ No newline at end of file\n");
+            }
+        };
+        old[..prefix].iter().for_each(|x| push(' ', x));
+        old[prefix..old.len() - suffix]
+            .iter()
+            .for_each(|x| push('-', x));
+        new[prefix..new.len() - suffix]
+            .iter()
+            .for_each(|x| push('+', x));
+        old[old.len() - suffix..].iter().for_each(|x| push(' ', x));
+        shift += new.len() as isize - old.len() as isize;
+    }
+    out
+}
+
+/// Write the fixed text back to the file with `--in-place`, or to stdout.
+pub(crate) fn write_fixed(
+    shell: &mut Shell,
+    args: &FixArgs,
+    text: &str,
+    fixes: &[Fix],
+) -> miette::Result<()> {
+    let fixed = apply(text, fixes);
+    if args.in_place {
+        if !fixes.is_empty() {
+            std::fs::write(&args.file, fixed).into_diagnostic()?;
+        }
+        shell
+            .status(
+                "Fixed",
+                format!("{} error(s) in {}", fixes.len(), args.file.display()),
+            )
+            .into_diagnostic()?;
+    } else {
+        print!("{}", fixed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_errors_across_output_pieces() {
+        let text = "Mun leat.\nDát lea boahtán.\n";
+        let outputs = [
+            serde_json::json!({ "text": "Mun leat.", "errors": [], "encoding": "utf-8" }),
+            serde_json::json!({
+                "text": "Dát lea boahtán.",
+                "errors": [{ "start": 9, "end": 17, "error_id": "typo", "suggestions": ["boahtan"] }],
+                "encoding": "utf-8",
+            }),
+        ];
+        let candidates = candidates(text, &outputs).unwrap();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].form, "boahtán");
+        assert_eq!(locate(text, candidates[0].start).0, 2);
+
+        let fixed = apply(
+            text,
+            &[Fix {
+                start: candidates[0].start,
+                end: candidates[0].end,
+                replacement: "boahtan".to_string(),
+            }],
+        );
+        assert_eq!(fixed, "Mun leat.\nDát lea boahtan.\n");
+    }
+
+    #[test]
+    fn autofix_diff_shows_changed_lines_with_context() {
+        let text = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\nk\nl\nm teh\n";
+        let fixes = [
+            Fix {
+                start: 2,
+                end: 3,
+                replacement: "B".to_string(),
+            },
+            Fix {
+                start: text.find("teh").unwrap(),
+                end: text.len() - 1,
+                replacement: "the".to_string(),
+            },
+        ];
+        assert_eq!(
+            unified_diff("x.txt", text, &fixes),
+            "--- a/x.txt\n+++ b/x.txt\n\
+             @@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n d\n e\n\
+             @@ -10,4 +10,4 @@\n j\n k\n l\n-m teh\n+m the\n"
+        );
+        assert_eq!(unified_diff("x.txt", text, &[]), "");
+    }
+}
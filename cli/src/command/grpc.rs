@@ -0,0 +1,253 @@
+//! gRPC front end of `serve`, for integrators that need a proto-defined
+//! contract. The service is defined in `proto/divvun_runtime.proto` and
+//! shares the bundles and pipeline pools of the HTTP server.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use divvun_runtime::modules::PipelineValue;
+use tonic::{Request, Response, Status};
+
+use super::serve::{Languages, Server, Service, binary_output};
+
+pub(crate) mod proto {
+    tonic::include_proto!("divvun_runtime.v1");
+}
+
+use proto::{
+    CheckRequest, CheckResponse, GrammarError, PreferencesRequest, PreferencesResponse,
+    SpellRequest, SpellResponse, SpellSuggestion, SpelledWord, SynthesizeRequest,
+    SynthesizeResponse,
+    divvun_runtime_server::{DivvunRuntime, DivvunRuntimeServer},
+};
+
+struct GrpcService {
+    server: Arc<Server>,
+}
+
+pub(crate) async fn serve_grpc(
+    server: Arc<Server>,
+    addr: SocketAddr,
+) -> Result<(), tonic::transport::Error> {
+    tonic::transport::Server::builder()
+        .add_service(DivvunRuntimeServer::new(GrpcService { server }))
+        .serve(addr)
+        .await
+}
+
+impl GrpcService {
+    fn languages(&self, service: Service) -> Result<&Languages, Status> {
+        self.server
+            .languages(service)
+            .ok_or_else(|| Status::unimplemented("No bundles configured for this service"))
+    }
+
+    /// Run `text` through the bundle for `tag`, returning the language it
+    /// resolved to and the outputs.
+    async fn run(
+        &self,
+        service: Service,
+        tag: &str,
+        text: String,
+    ) -> Result<(String, Vec<PipelineValue>), Status> {
+        let languages = self.languages(service)?;
+        let lang = languages
+            .resolve(tag)
+            .ok_or_else(|| Status::not_found(format!("No bundle for language {:?}", tag)))?;
+        let outputs = languages
+            .pool(lang)
+            .forward(PipelineValue::String(text))
+            .await
+            .map_err(|e| {
+                tracing::error!("Pipeline for {} failed: {}", lang, e);
+                Status::internal(e.to_string())
+            })?;
+        Ok((lang.clone(), outputs))
+    }
+}
+
+/// JSON output of a pipeline, which some commands return as text.
+fn json_output(value: PipelineValue) -> Result<serde_json::Value, Status> {
+    match value {
+        PipelineValue::Json(json) => Ok(json),
+        PipelineValue::String(text) => serde_json::from_str(&text)
+            .map_err(|e| Status::internal(format!("Pipeline output is not JSON: {}", e))),
+        _ => Err(Status::internal("Pipeline output is not JSON")),
+    }
+}
+
+fn str_field(value: &serde_json::Value, key: &str) -> String {
+    value
+        .get(key)
+        .and_then(|x| x.as_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn grammar_error(value: &serde_json::Value) -> GrammarError {
+    GrammarError {
+        form: str_field(value, "form"),
+        start: value.get("start").and_then(|x| x.as_u64()).unwrap_or(0),
+        end: value.get("end").and_then(|x| x.as_u64()).unwrap_or(0),
+        error_id: str_field(value, "error_id"),
+        title: str_field(value, "title"),
+        description: str_field(value, "description"),
+        suggestions: value
+            .get("suggestions")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .filter_map(|x| x.as_str().map(str::to_string))
+            .collect(),
+        severity: str_field(value, "severity"),
+        url: str_field(value, "url"),
+    }
+}
+
+fn spelled_word(value: &serde_json::Value) -> SpelledWord {
+    SpelledWord {
+        index: value.get("index").and_then(|x| x.as_u64()).unwrap_or(0),
+        word: str_field(value, "word"),
+        suggestions: value
+            .get("suggestions")
+            .and_then(|x| x.as_array())
+            .into_iter()
+            .flatten()
+            .map(|x| match x.as_str() {
+                Some(value) => SpellSuggestion {
+                    value: value.to_string(),
+                    weight: 0.0,
+                },
+                None => SpellSuggestion {
+                    value: str_field(x, "value"),
+                    weight: x.get("weight").and_then(|w| w.as_f64()).unwrap_or(0.0) as f32,
+                },
+            })
+            .collect(),
+    }
+}
+
+#[tonic::async_trait]
+impl DivvunRuntime for GrpcService {
+    async fn check(
+        &self,
+        request: Request<CheckRequest>,
+    ) -> Result<Response<CheckResponse>, Status> {
+        let request = request.into_inner();
+        let (language, outputs) = self
+            .run(Service::Check, &request.language, request.text)
+            .await?;
+
+        let mut response = CheckResponse {
+            language,
+            errors: Vec::new(),
+            timed_out: false,
+        };
+        for output in outputs {
+            let json = json_output(output)?;
+            response.timed_out |= json.get("timed_out").and_then(|x| x.as_bool()) == Some(true);
+            response.errors.extend(
+                json.get("errors")
+                    .and_then(|x| x.as_array())
+                    .into_iter()
+                    .flatten()
+                    .map(grammar_error),
+            );
+        }
+        Ok(Response::new(response))
+    }
+
+    async fn spell(
+        &self,
+        request: Request<SpellRequest>,
+    ) -> Result<Response<SpellResponse>, Status> {
+        let request = request.into_inner();
+        let (language, outputs) = self
+            .run(Service::Spell, &request.language, request.text)
+            .await?;
+
+        let mut words = Vec::new();
+        for output in outputs {
+            let json = json_output(output)?;
+            words.extend(json.as_array().into_iter().flatten().map(spelled_word));
+        }
+        Ok(Response::new(SpellResponse { language, words }))
+    }
+
+    async fn synthesize(
+        &self,
+        request: Request<SynthesizeRequest>,
+    ) -> Result<Response<SynthesizeResponse>, Status> {
+        let request = request.into_inner();
+        let (language, outputs) = self
+            .run(Service::Synthesize, &request.language, request.text)
+            .await?;
+
+        let audio = outputs
+            .iter()
+            .map(|output| {
+                binary_output(output)
+                    .ok_or_else(|| Status::internal("Pipeline output is not audio"))?
+                    .map_err(|e| Status::internal(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Response::new(SynthesizeResponse { language, audio }))
+    }
+
+    async fn preferences(
+        &self,
+        request: Request<PreferencesRequest>,
+    ) -> Result<Response<PreferencesResponse>, Status> {
+        let request = request.into_inner();
+        let languages = self.languages(Service::Check)?;
+        let language = languages.resolve(&request.language).ok_or_else(|| {
+            Status::not_found(format!("No bundle for language {:?}", request.language))
+        })?;
+
+        let locales = request
+            .locales
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+        let titles = languages
+            .pool(language)
+            .bundle()
+            .error_preferences(&locales)
+            .ok_or_else(|| Status::failed_precondition("Bundle has no divvun::suggest command"))?;
+
+        Ok(Response::new(PreferencesResponse {
+            language: language.clone(),
+            error_titles: titles.into_iter().collect(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_suggest_and_speller_json() {
+        let err = grammar_error(&serde_json::json!({
+            "form": "boahtan",
+            "start": 8,
+            "end": 15,
+            "error_id": "typo",
+            "title": "Spelling",
+            "description": "",
+            "suggestions": ["boahtán"],
+        }));
+        assert_eq!((err.start, err.end), (8, 15));
+        assert_eq!(err.suggestions, ["boahtán"]);
+        assert_eq!(err.severity, "");
+        assert_eq!(err.url, "");
+
+        let word = spelled_word(&serde_json::json!({
+            "index": 4,
+            "word": "boahtan",
+            "suggestions": [{ "value": "boahtán", "weight": 12.5 }],
+        }));
+        assert_eq!(word.index, 4);
+        assert_eq!(word.suggestions[0].value, "boahtán");
+        assert_eq!(word.suggestions[0].weight, 12.5);
+    }
+}
@@ -0,0 +1,29 @@
+//! `hunspell`: speak hunspell's pipe protocol on stdin and stdout, so
+//! applications that can only run `hunspell -a` can use a Divvun speller.
+
+use divvun_runtime::{bundle::BundleOptions, hunspell::HunspellPipe};
+use miette::IntoDiagnostic;
+
+use crate::{cli::HunspellArgs, shell::Shell};
+
+use super::run::{load_bundle, parse_config};
+
+pub async fn hunspell(shell: &mut Shell, args: HunspellArgs) -> miette::Result<()> {
+    let path = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = BundleOptions {
+        pipeline: args.pipeline,
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options, args.skip_check).await?;
+    let pipe = HunspellPipe::new(&bundle, parse_config(&args.config)?)
+        .await
+        .into_diagnostic()?;
+
+    let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+    pipe.serve(stdin, tokio::io::stdout())
+        .await
+        .into_diagnostic()
+}
@@ -1,24 +1,37 @@
+use std::path::Path;
+
 use miette::IntoDiagnostic;
 
 use crate::{
-    cli::{InitArgs, SyncArgs},
+    cli::{InitArgs, ProgressFormat, SyncArgs},
     shell::Shell,
 };
 
 use super::sync::sync;
 
 pub async fn init(shell: &mut Shell, args: InitArgs) -> miette::Result<()> {
+    if let Some(module) = &args.module {
+        let root = match &args.path {
+            Some(path) => path.clone(),
+            None => std::env::current_dir().into_diagnostic()?,
+        };
+        return init_module(shell, &root, module, &args.command);
+    }
+
     sync(
         shell,
         SyncArgs {
             path: args.path.clone(),
+            progress: ProgressFormat::Human,
+            dry_run: false,
         },
     )
     .await?;
 
-    let cur_dir = args
-        .path
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    let cur_dir = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
 
     shell.status("Creating", "pipeline.ts").into_diagnostic()?;
 
@@ -39,3 +52,178 @@ export default function examplePipeline(entry: StringEntry): Command {
     return x;
 }
 "#;
+
+const MODULE_RS: &str = include_str!("../../templates/module/module.rs");
+const MODULE_ASSET: &str = include_str!("../../templates/module/asset.tsv");
+const MODULE_README: &str = include_str!("../../templates/module/README.md");
+
+/// Add module `name` with a command `command` to the divvun-runtime checkout
+/// at `root`, behind a new `mod-<name>` feature.
+fn init_module(shell: &mut Shell, root: &Path, name: &str, command: &str) -> miette::Result<()> {
+    for (what, value) in [("module", name), ("command", command)] {
+        if !is_identifier(value) {
+            miette::bail!(
+                "Invalid {} name '{}': use lowercase letters, digits and underscores",
+                what,
+                value
+            );
+        }
+    }
+
+    let modules_rs = root.join("src/modules/mod.rs");
+    let cargo_toml = root.join("Cargo.toml");
+    if !modules_rs.is_file() || !cargo_toml.is_file() {
+        miette::bail!(
+            "{} is not a divvun-runtime checkout (no src/modules/mod.rs)",
+            root.display()
+        );
+    }
+
+    let files = [
+        (format!("src/modules/{}.rs", name), MODULE_RS),
+        (
+            format!("tests/fixtures/{}/assets/{}.tsv", name, name),
+            MODULE_ASSET,
+        ),
+        (format!("docs/docs/modules/{}.md", name), MODULE_README),
+    ];
+    for (path, _) in &files {
+        if root.join(path).exists() {
+            miette::bail!("{} already exists", path);
+        }
+    }
+
+    let modules = register_module(
+        &std::fs::read_to_string(&modules_rs).into_diagnostic()?,
+        name,
+    )?;
+    let manifest = add_feature(
+        &std::fs::read_to_string(&cargo_toml).into_diagnostic()?,
+        name,
+    )?;
+
+    for (path, template) in &files {
+        shell.status("Creating", path).into_diagnostic()?;
+        let path = root.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).into_diagnostic()?;
+        std::fs::write(&path, render(template, name, command)).into_diagnostic()?;
+    }
+    shell
+        .status("Updating", "src/modules/mod.rs")
+        .into_diagnostic()?;
+    std::fs::write(&modules_rs, modules).into_diagnostic()?;
+    shell.status("Updating", "Cargo.toml").into_diagnostic()?;
+    std::fs::write(&cargo_toml, manifest).into_diagnostic()?;
+
+    shell
+        .note(format!(
+            "run `cargo test --features mod-{} {}` to try it",
+            name, name
+        ))
+        .into_diagnostic()?;
+    Ok(())
+}
+
+fn is_identifier(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn render(template: &str, name: &str, command: &str) -> String {
+    let type_name = command
+        .split('_')
+        .map(|part| {
+            let mut chars = part.chars();
+            match chars.next() {
+                Some(first) => first.to_ascii_uppercase().to_string() + chars.as_str(),
+                None => String::new(),
+            }
+        })
+        .collect::<String>();
+    template
+        .replace("__module__", name)
+        .replace("__command__", command)
+        .replace("__Command__", &type_name)
+}
+
+/// Declare the module after the last feature-gated module of `mod.rs`.
+fn register_module(source: &str, name: &str) -> miette::Result<String> {
+    let lines = source.lines().collect::<Vec<_>>();
+    let Some(last) = lines
+        .windows(2)
+        .rposition(|x| x[0].starts_with("#[cfg(feature = \"mod-") && x[1].starts_with("pub mod "))
+    else {
+        miette::bail!("Found no feature-gated modules in src/modules/mod.rs");
+    };
+
+    let declaration = [
+        "",
+        &format!("#[cfg(feature = \"mod-{}\")]", name),
+        &format!("pub mod {};", name),
+    ];
+    let mut source = lines[..last + 2]
+        .iter()
+        .chain(&declaration)
+        .chain(&lines[last + 2..])
+        .copied()
+        .collect::<Vec<_>>()
+        .join("\n");
+    source.push('\n');
+    Ok(source)
+}
+
+/// Add a `mod-<name>` feature after the other modules' and to `all-mods`.
+fn add_feature(manifest: &str, name: &str) -> miette::Result<String> {
+    let mut lines = manifest.lines().map(str::to_string).collect::<Vec<_>>();
+    let Some(all_mods) = lines.iter().position(|x| x.starts_with("all-mods = [")) else {
+        miette::bail!("Found no all-mods feature in Cargo.toml");
+    };
+    let Some(close) = lines[all_mods].rfind(']') else {
+        miette::bail!("all-mods in Cargo.toml isn't a one-line list");
+    };
+    lines[all_mods].insert_str(close, &format!(", \"mod-{}\"", name));
+
+    let last = lines
+        .iter()
+        .rposition(|x| x.starts_with("mod-"))
+        .unwrap_or(all_mods);
+    lines.insert(last + 1, format!("mod-{} = []", name));
+
+    let mut manifest = lines.join("\n");
+    manifest.push('\n');
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_the_module_and_its_feature() {
+        let modules = "pub mod example;\n\n#[cfg(feature = \"mod-cg3\")]\npub mod cg3;\n\n#[cfg(feature = \"mod-jq\")]\npub mod jq;\n\nmod tests {\n    #[cfg(feature = \"mod-jq\")]\n    commands.push(());\n}\n";
+        assert_eq!(
+            register_module(modules, "sandhi").unwrap(),
+            "pub mod example;\n\n#[cfg(feature = \"mod-cg3\")]\npub mod cg3;\n\n#[cfg(feature = \"mod-jq\")]\npub mod jq;\n\n#[cfg(feature = \"mod-sandhi\")]\npub mod sandhi;\n\nmod tests {\n    #[cfg(feature = \"mod-jq\")]\n    commands.push(());\n}\n"
+        );
+
+        let manifest = "[features]\nall-mods = [\"mod-cg3\", \"mod-jq\"]\nmod-cg3 = [\"cg3\"]\nmod-jq = []\nffi = []\n";
+        assert_eq!(
+            add_feature(manifest, "sandhi").unwrap(),
+            "[features]\nall-mods = [\"mod-cg3\", \"mod-jq\", \"mod-sandhi\"]\nmod-cg3 = [\"cg3\"]\nmod-jq = []\nmod-sandhi = []\nffi = []\n"
+        );
+    }
+
+    #[test]
+    fn renders_names_into_the_templates() {
+        let module = render(MODULE_RS, "sandhi", "join_words");
+        assert!(module.contains("pub struct JoinWords {"));
+        assert!(module.contains("module = \"sandhi\""));
+        assert!(module.contains("name = \"join_words\""));
+        assert!(!module.contains("__"));
+        assert!(is_identifier("mod2_x"));
+        assert!(!is_identifier("Mod"));
+        assert!(!is_identifier("my-mod"));
+    }
+}
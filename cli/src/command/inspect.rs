@@ -0,0 +1,63 @@
+use divvun_runtime::bundle::Bundle;
+use miette::IntoDiagnostic;
+
+use crate::{cli::InspectArgs, shell::Shell};
+
+pub async fn inspect(shell: &mut Shell, args: InspectArgs) -> miette::Result<()> {
+    let Some(manifest) = Bundle::manifest_from_bundle(&args.path)
+        .await
+        .into_diagnostic()?
+    else {
+        miette::bail!(
+            "{} has no build manifest; it was built by an older divvun-runtime",
+            args.path.display()
+        );
+    };
+
+    if args.json {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&manifest).into_diagnostic()?
+        );
+        return Ok(());
+    }
+
+    shell
+        .status("Bundle", args.path.display())
+        .into_diagnostic()?;
+    shell
+        .status(
+            "Runtime",
+            format!(
+                "{} ({}, {})",
+                manifest.runtime.version, manifest.runtime.git_describe, manifest.runtime.target
+            ),
+        )
+        .into_diagnostic()?;
+    shell
+        .status("Built", format!("{} (Unix time)", manifest.built_at))
+        .into_diagnostic()?;
+    shell
+        .status(
+            "Pipeline",
+            format!("{} {}", manifest.pipeline.path, manifest.pipeline.hash),
+        )
+        .into_diagnostic()?;
+    shell
+        .status(
+            "Assets",
+            format!(
+                "{} file(s), {} bytes",
+                manifest.assets.len(),
+                manifest.total_asset_size()
+            ),
+        )
+        .into_diagnostic()?;
+    for (path, asset) in &manifest.assets {
+        shell
+            .status("•", format!("{} {} bytes {}", path, asset.size, asset.hash))
+            .into_diagnostic()?;
+    }
+
+    Ok(())
+}
@@ -7,9 +7,10 @@ use crate::{cli::ListArgs, shell::Shell};
 use super::utils;
 
 pub async fn list(shell: &mut Shell, args: ListArgs) -> miette::Result<()> {
-    let path = args
-        .path
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
+    let path = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
 
     let is_drb = path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb");
 
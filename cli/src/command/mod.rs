@@ -1,8 +1,25 @@
+pub mod batch;
+pub mod bisect;
 pub mod bundle;
+pub mod cg3;
+pub mod clean;
+pub mod compare;
+pub mod crash_dump;
+pub mod daemon;
+pub mod exec;
+pub mod fix;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hunspell;
 pub mod init;
+pub mod inspect;
 pub mod list;
+pub mod pager;
 pub mod playground;
+pub mod report;
 pub mod run;
+pub mod serve;
 pub mod sync;
 pub mod test;
 pub mod utils;
+pub mod wordlist;
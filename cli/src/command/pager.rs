@@ -0,0 +1,73 @@
+//! Long step output in the REPL. Each step's output is folded to its first
+//! lines so a long document doesn't flood the terminal, and `:expand <step>`
+//! shows it in full from the last run, through a pager when it doesn't fit.
+
+use std::io::{IsTerminal, Write};
+
+/// Lines of step output shown before folding, until changed with `:fold`.
+pub(crate) const DEFAULT_FOLD_LINES: usize = 40;
+
+/// The first `max_lines` lines of `text` and how many lines were left out,
+/// or `None` if it fits. A limit of 0 never folds.
+pub(crate) fn fold(text: &str, max_lines: usize) -> Option<(&str, usize)> {
+    if max_lines == 0 {
+        return None;
+    }
+    let total = text.lines().count();
+    if total <= max_lines {
+        return None;
+    }
+    let (end, _) = text.match_indices('\n').nth(max_lines - 1)?;
+    Some((&text[..end], total - max_lines))
+}
+
+/// Show `text` through `$PAGER` (`less -R` by default) if it's taller than
+/// the terminal, else print it. Falls back to printing when the pager can't
+/// be run.
+pub(crate) fn page(text: &str) {
+    let rows = crossterm::terminal::size()
+        .map(|(_, rows)| rows as usize)
+        .unwrap_or(usize::MAX);
+    if !std::io::stdout().is_terminal() || text.lines().count() < rows {
+        println!("{}", text);
+        return;
+    }
+
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| "less -R".to_string());
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next() else {
+        println!("{}", text);
+        return;
+    };
+    let child = std::process::Command::new(program)
+        .args(words)
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+    let mut child = match child {
+        Ok(child) => child,
+        Err(e) => {
+            tracing::debug!("Failed to run pager {}: {}", pager, e);
+            println!("{}", text);
+            return;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // The pager closes its input when quit before the end
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn folds_after_the_line_limit() {
+        let text = "\"<Mun>\"\n\t\"mun\" Pron\n\"<lean>\"\n\t\"leat\" V\n";
+        assert_eq!(fold(text, 2), Some(("\"<Mun>\"\n\t\"mun\" Pron", 2)));
+        assert_eq!(fold(text, 4), None);
+        assert_eq!(fold(text, 0), None);
+        assert_eq!(fold("a\nb\nc", 1), Some(("a", 2)));
+    }
+}
@@ -8,7 +8,10 @@ use crate::cli::PlaygroundArgs;
 use crate::shell::Shell;
 
 pub fn playground(_shell: &mut Shell, args: PlaygroundArgs) -> miette::Result<()> {
-    let path = args.path.unwrap_or_else(|| env::current_dir().unwrap());
+    let path = match args.path {
+        Some(path) => path,
+        None => env::current_dir().into_diagnostic()?,
+    };
 
     let playground_path = find_playground_binary()?;
 
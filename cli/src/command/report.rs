@@ -0,0 +1,267 @@
+//! `run --report FORMAT`: grammar errors from `divvun::suggest` as reports
+//! CI systems understand, pointing at lines of the checked file.
+
+use divvun_runtime::ast::PipelineDefinition;
+use miette::IntoDiagnostic;
+
+use crate::cli::ReportFormat;
+
+/// One grammar error, with the position `positions: "linecol"` adds.
+struct Finding<'a> {
+    error_id: &'a str,
+    title: &'a str,
+    description: &'a str,
+    severity: Option<&'a str>,
+    url: Option<&'a str>,
+    suggestions: Vec<&'a str>,
+    start: (u64, u64),
+    end: (u64, u64),
+}
+
+/// Ask every suggest command in the pipeline for line/column positions,
+/// unless the user configured positions themselves.
+pub fn request_positions(defn: &PipelineDefinition, config: &mut serde_json::Value) {
+    let Some(config) = config.as_object_mut() else {
+        return;
+    };
+    for (key, command) in &defn.commands {
+        if command.kind.as_deref() != Some("suggest") {
+            continue;
+        }
+        let entry = config
+            .entry(key.clone())
+            .or_insert_with(|| serde_json::json!({}));
+        if let Some(entry) = entry.as_object_mut() {
+            entry.entry("positions").or_insert_with(|| "linecol".into());
+        }
+    }
+}
+
+fn findings(outputs: &[serde_json::Value]) -> miette::Result<Vec<Finding<'_>>> {
+    let mut findings = Vec::new();
+    for output in outputs {
+        let Some(errors) = output.get("errors").and_then(|x| x.as_array()) else {
+            miette::bail!("--report needs a pipeline whose output is divvun::suggest's JSON");
+        };
+        for error in errors {
+            let str_field = move |name: &str| error.get(name).and_then(|x| x.as_str());
+            let position = &error["position"];
+            let at = |line: &str, column: &str| {
+                (
+                    position[line].as_u64().unwrap_or(1),
+                    position[column].as_u64().unwrap_or(1),
+                )
+            };
+            findings.push(Finding {
+                error_id: str_field("error_id").unwrap_or_default(),
+                title: str_field("title").unwrap_or_default(),
+                description: str_field("description").unwrap_or_default(),
+                severity: str_field("severity"),
+                url: str_field("url"),
+                suggestions: error["suggestions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|x| x.as_str())
+                    .collect(),
+                start: at("start_line", "start_column"),
+                end: at("end_line", "end_column"),
+            });
+        }
+    }
+    Ok(findings)
+}
+
+impl Finding<'_> {
+    /// The description, or the title if there is none, with the suggestions.
+    fn message(&self) -> String {
+        let mut message = if self.description.is_empty() {
+            self.title.to_string()
+        } else {
+            self.description.to_string()
+        };
+        if !self.suggestions.is_empty() {
+            message.push_str(&format!(" (suggestions: {})", self.suggestions.join(", ")));
+        }
+        message
+    }
+
+    /// Severity as "error", "warning" or "note".
+    fn level(&self) -> &'static str {
+        match self.severity {
+            Some("error") => "error",
+            Some("hint" | "info" | "note" | "notice") => "note",
+            _ => "warning",
+        }
+    }
+}
+
+/// Render the suggest `outputs` for the checked `file` in `format`.
+pub fn render(
+    format: ReportFormat,
+    file: &str,
+    outputs: &[serde_json::Value],
+) -> miette::Result<String> {
+    let findings = findings(outputs)?;
+    Ok(match format {
+        ReportFormat::Gcc => findings
+            .iter()
+            .map(|f| {
+                format!(
+                    "{}:{}:{}: {}: {} [{}]\n",
+                    file,
+                    f.start.0,
+                    f.start.1,
+                    f.level(),
+                    f.message(),
+                    f.error_id
+                )
+            })
+            .collect(),
+        ReportFormat::Github => findings
+            .iter()
+            .map(|f| {
+                let command = match f.level() {
+                    "note" => "notice",
+                    level => level,
+                };
+                format!(
+                    "::{} file={},line={},col={},endLine={},endColumn={},title={}::{}\n",
+                    command,
+                    escape_property(file),
+                    f.start.0,
+                    f.start.1,
+                    f.end.0,
+                    f.end.1,
+                    escape_property(&format!("{} ({})", f.title, f.error_id)),
+                    escape_data(&f.message())
+                )
+            })
+            .collect(),
+        ReportFormat::Sarif => {
+            let mut rules = Vec::new();
+            for f in &findings {
+                if !rules.iter().any(|x: &&Finding| x.error_id == f.error_id) {
+                    rules.push(f);
+                }
+            }
+            let rules = rules
+                .iter()
+                .map(|f| {
+                    let mut rule = serde_json::json!({
+                        "id": f.error_id,
+                        "shortDescription": { "text": f.title },
+                    });
+                    if let Some(url) = f.url {
+                        rule["helpUri"] = url.into();
+                    }
+                    rule
+                })
+                .collect::<Vec<_>>();
+            let results = findings
+                .iter()
+                .map(|f| {
+                    serde_json::json!({
+                        "ruleId": f.error_id,
+                        "level": f.level(),
+                        "message": { "text": f.message() },
+                        "locations": [{
+                            "physicalLocation": {
+                                "artifactLocation": { "uri": file },
+                                "region": {
+                                    "startLine": f.start.0,
+                                    "startColumn": f.start.1,
+                                    "endLine": f.end.0,
+                                    "endColumn": f.end.1,
+                                }
+                            }
+                        }],
+                    })
+                })
+                .collect::<Vec<_>>();
+            let sarif = serde_json::json!({
+                "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+                "version": "2.1.0",
+                "runs": [{
+                    "tool": {
+                        "driver": {
+                            "name": "divvun-runtime",
+                            "version": env!("CARGO_PKG_VERSION"),
+                            "informationUri": "https://github.com/divvun/divvun-runtime",
+                            "rules": rules,
+                        }
+                    },
+                    "results": results,
+                }]
+            });
+            let mut out = serde_json::to_string_pretty(&sarif).into_diagnostic()?;
+            out.push('\n');
+            out
+        }
+    })
+}
+
+/// Escape a workflow command's message.
+fn escape_data(s: &str) -> String {
+    s.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escape a workflow command's property value.
+fn escape_property(s: &str) -> String {
+    escape_data(s).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output() -> serde_json::Value {
+        serde_json::json!({
+            "text": "Mun leat.\nDát lea boahtán, ja.",
+            "errors": [{
+                "form": "boahtán",
+                "start": 18,
+                "end": 26,
+                "error_id": "typo",
+                "title": "Spelling error",
+                "description": "",
+                "suggestions": ["boahtan"],
+                "position": { "start_line": 2, "start_column": 9, "end_line": 2, "end_column": 16 },
+            }],
+            "encoding": "utf-8",
+        })
+    }
+
+    #[test]
+    fn renders_gcc_and_github_lines() {
+        let outputs = [output()];
+        assert_eq!(
+            render(ReportFormat::Gcc, "doc.md", &outputs).unwrap(),
+            "doc.md:2:9: warning: Spelling error (suggestions: boahtan) [typo]\n"
+        );
+        assert_eq!(
+            render(ReportFormat::Github, "docs/a,b.md", &outputs).unwrap(),
+            "::warning file=docs/a%2Cb.md,line=2,col=9,endLine=2,endColumn=16,\
+             title=Spelling error (typo)::Spelling error (suggestions: boahtan)\n"
+        );
+    }
+
+    #[test]
+    fn sarif_rules_link_error_documentation() {
+        let mut output = output();
+        output["errors"][0]["url"] = "https://giellalt.github.io/errors/typo".into();
+        let sarif: serde_json::Value =
+            serde_json::from_str(&render(ReportFormat::Sarif, "doc.md", &[output]).unwrap())
+                .unwrap();
+        let rule = &sarif["runs"][0]["tool"]["driver"]["rules"][0];
+        assert_eq!(rule["id"], "typo");
+        assert_eq!(rule["helpUri"], "https://giellalt.github.io/errors/typo");
+    }
+
+    #[test]
+    fn rejects_output_that_is_not_suggest_json() {
+        assert!(render(ReportFormat::Sarif, "x", &[serde_json::json!("text")]).is_err());
+    }
+}
@@ -1,18 +1,25 @@
 use std::{
     borrow::Cow,
+    collections::{BTreeMap, HashMap},
     io::{self, IsTerminal, Read, Write},
+    path::{Path, PathBuf},
     sync::{
         Arc, Mutex,
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
     },
+    time::Duration,
 };
 
 use miette::IntoDiagnostic;
 
 use divvun_runtime::{
-    ast::Command,
-    bundle::Bundle,
-    modules::{PipelineEvent, PipelineValue, TapOutput},
+    ast::{Command, PipelineDefinition},
+    bundle::{Bundle, BundleOptions},
+    modules::{PipelineEvent, PipelineValue, TapFn, TapOutput},
+    util::{
+        breakpoint::Breakpoint, deterministic, download::DownloadCallback, recorder::RunRecorder,
+    },
+    watch::{BundleWatch, Reload, Snapshot},
 };
 use futures_util::{FutureExt, StreamExt};
 use pathos::AppDirs;
@@ -26,14 +33,23 @@ use rustyline::{
 };
 use serde_json::Map;
 use termcolor::Color;
-use tokio::{io::AsyncReadExt as _, sync::RwLock};
+use tokio::{
+    io::AsyncReadExt as _,
+    sync::{RwLock, broadcast},
+};
 
 use crate::{
     cli::{DebugDumpAstArgs, RunArgs},
+    exit::{self, FailAs, Failure},
+    i18n::t,
     shell::Shell,
 };
 
-use super::utils;
+use super::{
+    batch,
+    crash_dump::{self, StepLog},
+    pager, report, utils,
+};
 
 // Themed helper for rustyline that applies background/foreground colors
 struct ThemedHelper {
@@ -130,7 +146,7 @@ fn format_input_highlighted(
     }
 }
 
-fn print_input_highlighted(
+pub(crate) fn print_input_highlighted(
     shell: &mut Shell,
     input: &PipelineValue,
     command: Option<&Command>,
@@ -140,6 +156,14 @@ fn print_input_highlighted(
             .map(|theme| syntax_highlight::extract_command_colors(theme).1)
     });
 
+    let normalized;
+    let input = if deterministic::is_enabled() {
+        normalized = deterministic_value(input);
+        &normalized
+    } else {
+        input
+    };
+
     let formatted = format_input_highlighted(input, command, shell.theme(), theme_bg);
     io::Write::write_all(shell.out(), formatted.as_bytes()).into_diagnostic()?;
     writeln!(shell.out()).into_diagnostic()?;
@@ -147,6 +171,19 @@ fn print_input_highlighted(
     Ok(())
 }
 
+/// `input` with JSON keys sorted and temporary paths normalized.
+fn deterministic_value(input: &PipelineValue) -> PipelineValue {
+    match input {
+        PipelineValue::Json(j) => {
+            let mut j = j.clone();
+            deterministic::canonicalize_json(&mut j);
+            PipelineValue::Json(j)
+        }
+        PipelineValue::String(s) => PipelineValue::String(deterministic::normalize_paths(s)),
+        other => other.clone(),
+    }
+}
+
 pub fn dump_ast(shell: &mut Shell, args: DebugDumpAstArgs) -> miette::Result<()> {
     let value = crate::deno_rt::dump_ast(&std::fs::read_to_string(args.path).into_diagnostic()?)?;
     let json = serde_json::to_string_pretty(&value).unwrap();
@@ -191,24 +228,248 @@ pub fn dump_ast(shell: &mut Shell, args: DebugDumpAstArgs) -> miette::Result<()>
 //     }
 // }
 
-#[derive(Clone)]
-struct TapEvent {
-    key: String,
-    command: Command,
-    event: PipelineEvent,
+/// Named test sentences for the REPL, stored in the user data dir and shared
+/// between bundles.
+struct Snippets {
+    path: PathBuf,
+    entries: BTreeMap<String, String>,
 }
 
-#[derive(Clone)]
-struct PipelineRun {
-    input: String,
-    events: Vec<TapEvent>,
+impl Snippets {
+    fn load(path: PathBuf) -> Self {
+        let entries = std::fs::read(&path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+            .unwrap_or_default();
+        Snippets { path, entries }
+    }
+
+    fn save(&self) -> miette::Result<()> {
+        let json = serde_json::to_vec_pretty(&self.entries).into_diagnostic()?;
+        std::fs::write(&self.path, json).into_diagnostic()
+    }
+
+    /// Handle `:snippet <args>`. Returns the text to run for `:snippet run`.
+    fn command(
+        &mut self,
+        shell: &mut Shell,
+        args: &str,
+        last_input: Option<&str>,
+    ) -> miette::Result<Option<String>> {
+        let (action, rest) = split_word(args);
+        let (name, text) = split_word(rest);
+        match (action, name) {
+            (Some("save"), Some(name)) => {
+                // As typed, so spacing in the test sentence survives
+                let text = text.to_string();
+                let text = match (text.is_empty(), last_input) {
+                    (false, _) => text,
+                    (true, Some(last)) => last.to_string(),
+                    (true, None) => {
+                        shell
+                            .error(t!("snippet-nothing-to-save"))
+                            .into_diagnostic()?;
+                        return Ok(None);
+                    }
+                };
+                shell
+                    .status(
+                        t!("repl-saved"),
+                        t!("snippet-saved", name = name, text = text),
+                    )
+                    .into_diagnostic()?;
+                self.entries.insert(name.to_string(), text);
+                self.save()?;
+            }
+            (Some("run"), Some(name)) => match self.entries.get(name) {
+                Some(text) => return Ok(Some(text.clone())),
+                None => shell
+                    .error(t!("snippet-not-found", name = name))
+                    .into_diagnostic()?,
+            },
+            (Some("delete"), Some(name)) => {
+                if self.entries.remove(name).is_some() {
+                    self.save()?;
+                    shell
+                        .status(t!("repl-deleted"), t!("snippet-deleted", name = name))
+                        .into_diagnostic()?;
+                } else {
+                    shell
+                        .error(t!("snippet-not-found", name = name))
+                        .into_diagnostic()?;
+                }
+            }
+            (Some("list") | None, _) => {
+                if self.entries.is_empty() {
+                    println!("{}", t!("snippet-none"));
+                }
+                for (name, text) in self.entries.iter() {
+                    println!("{}: {}", name, text);
+                }
+                println!();
+            }
+            _ => shell.error(t!("snippet-usage")).into_diagnostic()?,
+        }
+        Ok(None)
+    }
+}
+
+/// The first word of `text` and what follows it, without the whitespace
+/// between them.
+fn split_word(text: &str) -> (Option<&str>, &str) {
+    let text = text.trim_start();
+    match text.split_once(char::is_whitespace) {
+        Some((word, rest)) => (Some(word), rest.trim_start()),
+        None if text.is_empty() => (None, ""),
+        None => (Some(text), ""),
+    }
+}
+
+/// History file for the bundle at `path`, so each language keeps its own
+/// REPL history.
+fn repl_history_path(data_dir: &Path, path: &Path) -> PathBuf {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let name = path
+        .to_string_lossy()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>();
+    data_dir.join("history").join(name.trim_start_matches('_'))
+}
+
+/// How long the REPL waits for the bundle to reload after regenerating
+/// `pipeline.json` from `pipeline.ts`.
+const RELOAD_WAIT: Duration = Duration::from_secs(2);
+
+/// Reloads the REPL's bundle when the project's files change.
+struct Reloader {
+    watch: BundleWatch,
+    reloads: broadcast::Receiver<Reload>,
+    last: Arc<Bundle>,
+    /// `pipeline.ts` and where `pipeline.json` is generated from it.
+    typescript: Option<(PathBuf, PathBuf)>,
+    snapshot: Snapshot,
+}
+
+impl Reloader {
+    async fn new(bundle: Bundle, path: &Path, options: BundleOptions) -> Reloader {
+        let watch = BundleWatch::from_bundle(bundle, path, options);
+        let pipeline_ts = if path.is_dir() {
+            path.join("pipeline.ts")
+        } else {
+            path.to_path_buf()
+        };
+        let typescript = (pipeline_ts.extension().is_some_and(|x| x == "ts")
+            && pipeline_ts.exists())
+        .then(|| {
+            let base = pipeline_ts.parent().unwrap_or(Path::new(""));
+            let pipeline_json = base.join("pipeline.json");
+            (pipeline_ts, pipeline_json)
+        });
+        let snapshot = Snapshot::new(typescript.iter().map(|(x, _)| x.clone()).collect()).await;
+        Reloader {
+            reloads: watch.subscribe(),
+            last: watch.current(),
+            watch,
+            typescript,
+            snapshot,
+        }
+    }
+
+    fn current(&self) -> Arc<Bundle> {
+        self.last.clone()
+    }
+
+    /// The bundle, if it was loaded again since the last call. Regenerates
+    /// `pipeline.json` first if `pipeline.ts` changed.
+    async fn check(&mut self, shell: &mut Shell) -> miette::Result<Option<Arc<Bundle>>> {
+        let mut events = Vec::new();
+        if let Some((pipeline_ts, pipeline_json)) = &self.typescript {
+            let next = Snapshot::new(vec![pipeline_ts.clone()]).await;
+            let changed = !self.snapshot.changed(&next).is_empty();
+            self.snapshot = next;
+            if changed {
+                match crate::deno_rt::save_ast(pipeline_ts, pipeline_json) {
+                    Ok(()) => {
+                        if let Ok(Ok(event)) =
+                            tokio::time::timeout(RELOAD_WAIT, self.reloads.recv()).await
+                        {
+                            events.push(event);
+                        }
+                    }
+                    Err(e) => shell
+                        .error(format!("{}: {}", pipeline_ts.display(), e))
+                        .into_diagnostic()?,
+                }
+            }
+        }
+        loop {
+            match self.reloads.try_recv() {
+                Ok(event) => events.push(event),
+                Err(broadcast::error::TryRecvError::Lagged(_)) => continue,
+                Err(_) => break,
+            }
+        }
+
+        let mut changed = Vec::new();
+        for event in events {
+            match event {
+                Reload::Reloaded { changed: files } => changed.extend(files),
+                Reload::Failed { error, .. } => shell
+                    .error(t!("repl-reload-failed", error = error))
+                    .into_diagnostic()?,
+            }
+        }
+        let current = self.watch.current();
+        if Arc::ptr_eq(&current, &self.last) {
+            return Ok(None);
+        }
+        self.last = current.clone();
+        let files = changed
+            .iter()
+            .map(|x| x.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        shell
+            .status(
+                t!("repl-reloaded"),
+                t!("repl-reload-changed", files = files),
+            )
+            .into_diagnostic()?;
+        Ok(Some(current))
+    }
 }
 
-async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette::Result<()> {
+async fn run_repl(
+    shell: &mut Shell,
+    bundle: Bundle,
+    path: &Path,
+    options: BundleOptions,
+    args: &RunArgs,
+) -> miette::Result<()> {
     let dirs = pathos::user::AppDirs::new("Divvun Runtime").into_diagnostic()?;
     std::fs::create_dir_all(dirs.data_dir()).into_diagnostic()?;
 
-    let history_path = dirs.data_dir().join("repl_history");
+    // Edits to a project show up in the next run; a .drb stays as it was
+    let (mut bundle, mut reloader) = if path.extension().is_some_and(|x| x == "drb") {
+        (Arc::new(bundle), None)
+    } else {
+        let reloader = Reloader::new(bundle, path, options).await;
+        (reloader.current(), Some(reloader))
+    };
+
+    let history_path = repl_history_path(dirs.data_dir(), path);
+    if let Some(parent) = history_path.parent() {
+        std::fs::create_dir_all(parent).into_diagnostic()?;
+    }
+    let mut snippets = Snippets::load(dirs.data_dir().join("snippets.json"));
+    let mut last_input: Option<String> = None;
 
     // Extract command colors from theme BEFORE creating editor
     let (cmd_colors, theme_bg) = shell
@@ -229,6 +490,7 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
     }
 
     let is_stepping = Arc::new(AtomicBool::new(false));
+    let fold_lines = Arc::new(AtomicUsize::new(pager::DEFAULT_FOLD_LINES));
 
     // Print welcome message with theme background if available
     if let Some(ref colors) = cmd_colors {
@@ -245,15 +507,14 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
     }
 
     let mut config = parse_config(&args.config)?;
-    let breakpoint: Arc<RwLock<Option<String>>> = Arc::new(RwLock::new(None));
+    let breakpoint: Arc<RwLock<Option<Breakpoint>>> = Arc::new(RwLock::new(None));
 
-    // Buffer to store the last pipeline run
-    let last_run: Arc<Mutex<Option<PipelineRun>>> = Arc::new(Mutex::new(None));
-    let current_events: Arc<Mutex<Vec<TapEvent>>> = Arc::new(Mutex::new(Vec::new()));
-    let current_events_clone = current_events.clone();
+    // The last runs, for `:save`
+    let recorder = Arc::new(RunRecorder::default());
 
     let tap_stepping = is_stepping.clone();
     let tap_breakpoint = breakpoint.clone();
+    let tap_fold_lines = fold_lines.clone();
     let theme = shell.theme().map(|s| s.to_string());
 
     // Prompt is simple - ThemedHelper::highlight_prompt applies theming
@@ -263,7 +524,6 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
     let output_cmd_colors = cmd_colors.clone();
 
     let tap = Arc::new(move |key: &str, cmd: &Command, event: &PipelineEvent| {
-        let current_events_clone = current_events_clone.clone();
         let tap_breakpoint = tap_breakpoint.clone();
         let tap_stepping = tap_stepping.clone();
         let cmd_colors_clone = cmd_colors.clone();
@@ -286,7 +546,16 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
                 let formatted =
                     format_input_highlighted(input, Some(cmd), theme.as_deref(), theme_bg_clone);
                 // format_input_highlighted returns content with \x1b[K per line and final \x1b[0m
-                println!("{}", formatted);
+                match pager::fold(&formatted, tap_fold_lines.load(Ordering::Relaxed)) {
+                    Some((head, hidden)) => {
+                        println!("{}\x1b[0m", head);
+                        println!(
+                            "\x1b[2m{}\x1b[0m",
+                            t!("repl-folded", lines = hidden, key = key)
+                        );
+                    }
+                    None => println!("{}", formatted),
+                }
             }
             _ => {
                 if let Some(ref colors) = cmd_colors_clone {
@@ -298,19 +567,16 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
             }
         }
 
-        // Store the event for the current run
-        if let Ok(mut events) = current_events_clone.lock() {
-            events.push(TapEvent {
-                key: key.to_string(),
-                command: cmd.clone(),
-                event: event.clone(),
-            });
-        }
-
         let key = key.to_string();
+        let event = event.clone();
 
         async move {
-            if tap_breakpoint.read().await.as_deref() == Some(&key) {
+            let hit = tap_breakpoint
+                .read()
+                .await
+                .as_ref()
+                .is_some_and(|x| x.hits(&key, &event));
+            if hit {
                 if let Some(ref colors) = cmd_colors_clone {
                     println!(
                         "{}[{}] <-> [Breakpoint hit]\x1b[K\x1b[0m",
@@ -346,6 +612,7 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
         }
         .boxed()
     });
+    let tap = recorder.tap(Some(tap as Arc<TapFn>));
 
     let mut pipe = bundle
         .create_with_tap(config.clone(), tap.clone())
@@ -378,22 +645,61 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
             }
         };
 
-        if line.starts_with(":") {
+        if let Some(reloaded) = match reloader.as_mut() {
+            Some(reloader) => reloader.check(shell).await?,
+            None => None,
+        } {
+            match reloaded.create_with_tap(config.clone(), tap.clone()).await {
+                Ok(created) => {
+                    pipe = created;
+                    bundle = reloaded;
+                }
+                Err(e) => shell
+                    .error(t!("repl-reload-failed", error = e.to_string()))
+                    .into_diagnostic()?,
+            }
+        }
+
+        let mut loaded = None;
+        let line = if line.starts_with(":") {
             let mut chunks = line.split_ascii_whitespace();
             let command = chunks.next().unwrap();
+            let mut snippet_input = None;
 
             match command {
                 ":help" => {
-                    println!("Available commands:");
-                    println!(":help - Display this help message");
-                    println!(":list - List all available modules");
-                    println!(":step - Enable/disable stepping through pipeline");
-                    println!(":ast - Display the parsed AST");
-                    println!(":config - Display the current configuration");
-                    println!(":set [id] [value] - Set a configuration variable");
-                    println!(":breakpoint [command_id|clear] - Set/clear breakpoint at command");
-                    println!(":save [filename] - Export last run as markdown");
-                    println!(":exit - Exit the REPL");
+                    println!("{}", t!("repl-help-title"));
+                    println!(":help - {}", t!("repl-help-help"));
+                    println!(":list - {}", t!("repl-help-list"));
+                    println!(":step - {}", t!("repl-help-step"));
+                    println!(":ast - {}", t!("repl-help-ast"));
+                    println!(":config - {}", t!("repl-help-config"));
+                    println!(":set [id] [value] - {}", t!("repl-help-set"));
+                    println!(
+                        ":breakpoint [command_id [condition]|clear] - {}",
+                        t!("repl-help-breakpoint")
+                    );
+                    println!(
+                        "    {}",
+                        t!(
+                            "repl-help-breakpoint-example",
+                            example = ":breakpoint suggest contains \"girjii\""
+                        )
+                    );
+                    println!(":save [filename] - {}", t!("repl-help-save"));
+                    println!(
+                        ":snippet save <name> [text] - {}",
+                        t!("repl-help-snippet-save")
+                    );
+                    println!(":snippet run <name> - {}", t!("repl-help-snippet-run"));
+                    println!(
+                        ":snippet list|delete <name> - {}",
+                        t!("repl-help-snippet-list")
+                    );
+                    println!(":loadbin <file> - {}", t!("repl-help-loadbin"));
+                    println!(":fold <lines|off> - {}", t!("repl-help-fold"));
+                    println!(":expand <command_id> - {}", t!("repl-help-expand"));
+                    println!(":exit - {}", t!("repl-help-exit"));
                     println!();
                 }
                 ":exit" => {
@@ -420,9 +726,13 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
                         .unwrap();
 
                     if !cur {
-                        shell.status("Stepping", "enabled").into_diagnostic()?;
+                        shell
+                            .status(t!("repl-stepping"), t!("repl-enabled"))
+                            .into_diagnostic()?;
                     } else {
-                        shell.status("Stepping", "disabled").into_diagnostic()?;
+                        shell
+                            .status(t!("repl-stepping"), t!("repl-disabled"))
+                            .into_diagnostic()?;
                     }
                 }
                 ":config" => {
@@ -434,18 +744,18 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
                 }
                 ":save" => {
                     let filename = chunks.next().unwrap_or("pipeline_debug.md");
-                    match save_markdown(&last_run, filename) {
+                    match save_markdown(&recorder, filename) {
                         Ok(()) => shell
-                            .status("Saved", format!("Debug log to {}", filename))
+                            .status(t!("repl-saved"), t!("repl-saved-log", file = filename))
                             .into_diagnostic()?,
                         Err(e) => shell
-                            .error(format!("Failed to save: {}", e))
+                            .error(t!("repl-save-failed", error = e))
                             .into_diagnostic()?,
                     }
                 }
                 ":set" => {
                     let Some(var) = chunks.next() else {
-                        shell.error("Missing id name").into_diagnostic()?;
+                        shell.error(t!("repl-missing-id")).into_diagnostic()?;
                         continue;
                     };
                     let value = chunks.collect::<Vec<_>>().join(" ");
@@ -453,60 +763,158 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
                         Ok(v) => v,
                         Err(e) => {
                             shell
-                                .error(format!("Failed to parse value: {}", e))
+                                .error(t!("repl-invalid-value", error = e))
                                 .into_diagnostic()?;
                             continue;
                         }
                     };
 
                     shell
-                        .status("Setting", format!("{var} = {value:?}"))
+                        .status(t!("repl-setting"), format!("{var} = {value:?}"))
                         .into_diagnostic()?;
                     config
                         .as_object_mut()
                         .unwrap()
                         .insert(var.to_string(), value);
-                    pipe = bundle
-                        .create_with_tap(config.clone(), tap.clone())
-                        .await
-                        .into_diagnostic()?;
+                    pipe.update_config(config.clone());
                 }
                 ":breakpoint" => {
-                    let arg = chunks.next();
+                    // The condition may contain quoted spaces, so take the whole rest of the line
+                    let arg = line[command.len()..].trim();
                     let mut breakpoint_guard = breakpoint.write().await;
-                    match arg {
-                        Some("clear") | None => {
-                            *breakpoint_guard = None;
-                            shell.status("Breakpoint", "cleared").into_diagnostic()?;
+                    if arg.is_empty() || arg == "clear" {
+                        *breakpoint_guard = None;
+                        shell
+                            .status(t!("repl-breakpoint"), t!("repl-breakpoint-cleared"))
+                            .into_diagnostic()?;
+                        continue;
+                    }
+                    let bp = match Breakpoint::parse(arg) {
+                        Ok(bp) => bp,
+                        Err(e) => {
+                            shell.error(e).into_diagnostic()?;
+                            continue;
                         }
-                        Some(id) => {
-                            if bundle.definition().commands.contains_key(id) {
-                                *breakpoint_guard = Some(id.to_string());
-                                shell
-                                    .status("Breakpoint", format!("set at command '{}'", id))
-                                    .into_diagnostic()?;
-                            } else {
-                                shell
-                                    .error(format!("Command '{}' not found", id))
-                                    .into_diagnostic()?;
-                                continue;
-                            }
+                    };
+                    if !bundle.definition().commands.contains_key(bp.step()) {
+                        shell
+                            .error(t!("repl-command-not-found", command = bp.step()))
+                            .into_diagnostic()?;
+                        continue;
+                    }
+                    let message = if bp.has_condition() {
+                        t!(
+                            "repl-breakpoint-set-when",
+                            command = bp.step(),
+                            condition = bp
+                        )
+                    } else {
+                        t!("repl-breakpoint-set", command = bp.step())
+                    };
+                    shell
+                        .status(t!("repl-breakpoint"), message)
+                        .into_diagnostic()?;
+                    *breakpoint_guard = Some(bp);
+                }
+                ":snippet" => {
+                    snippet_input =
+                        snippets.command(shell, &line[command.len()..], last_input.as_deref())?;
+                }
+                ":loadbin" => {
+                    let Some(file) = chunks.next() else {
+                        shell.error(t!("repl-loadbin-usage")).into_diagnostic()?;
+                        continue;
+                    };
+                    match std::fs::read(file) {
+                        Ok(bytes) => loaded = Some((file.to_string(), bytes)),
+                        Err(e) => {
+                            shell
+                                .error(t!("repl-read-failed", file = file, error = e))
+                                .into_diagnostic()?;
+                            continue;
                         }
                     }
                 }
+                ":fold" => {
+                    let lines = match chunks.next() {
+                        Some("off") => Some(0),
+                        Some(n) => n.parse::<usize>().ok().filter(|&n| n > 0),
+                        None => None,
+                    };
+                    let Some(lines) = lines else {
+                        shell.error(t!("repl-fold-usage")).into_diagnostic()?;
+                        continue;
+                    };
+                    fold_lines.store(lines, Ordering::Relaxed);
+                    let message = if lines == 0 {
+                        t!("repl-disabled")
+                    } else {
+                        t!("repl-fold-set", lines = lines)
+                    };
+                    shell
+                        .status(t!("repl-folding"), message)
+                        .into_diagnostic()?;
+                }
+                ":expand" => {
+                    let Some(key) = chunks.next() else {
+                        shell.error(t!("repl-expand-usage")).into_diagnostic()?;
+                        continue;
+                    };
+                    let theme = shell.theme().map(str::to_string);
+                    let run = recorder.last_run();
+                    let outputs = run
+                        .iter()
+                        .flat_map(|run| run.events_for(key))
+                        .filter_map(|x| match &x.event {
+                            PipelineEvent::Value(value) => Some(format_input_highlighted(
+                                value,
+                                Some(&x.command),
+                                theme.as_deref(),
+                                theme_bg,
+                            )),
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>();
+                    if outputs.is_empty() {
+                        shell
+                            .error(t!("repl-expand-none", key = key))
+                            .into_diagnostic()?;
+                        continue;
+                    }
+                    pager::page(&outputs.join("\n"));
+                }
                 unknown => {
                     shell
-                        .error(format!("Unknown command: {}", unknown))
+                        .error(t!("repl-unknown-command", command = unknown))
                         .into_diagnostic()?;
                 }
             }
-            continue;
-        }
+            match (snippet_input, &loaded) {
+                (Some(text), _) => text,
+                // Recorded as the input of the run, for `:save`.
+                (None, Some(_)) => line.clone(),
+                (None, None) => continue,
+            }
+        } else {
+            line
+        };
 
-        // Clear the events for the new run
-        if let Ok(mut events) = current_events.lock() {
-            events.clear();
-        }
+        let input = match loaded {
+            Some((file, bytes)) => entry_value(bundle.definition(), bytes, &file),
+            None => {
+                last_input = Some(line.clone());
+                entry_value(bundle.definition(), line.clone().into_bytes(), "the input")
+            }
+        };
+        let input = match input {
+            Ok(input) => input,
+            Err(e) => {
+                shell.error(e).into_diagnostic()?;
+                continue;
+            }
+        };
+
+        recorder.start(line.clone());
 
         // let result = if is_stepping {
         //     bundle
@@ -523,7 +931,7 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
         //         .await
         //         .map_err(|e| Arc::new(e.into()))?
         // };
-        let mut stream = pipe.forward(PipelineValue::String(line.to_string())).await;
+        let mut stream = pipe.forward(input).await;
 
         let output_cmd = bundle.definition().output.resolve(bundle.definition());
 
@@ -561,6 +969,9 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
                                 std::fs::write(path, audio.to_wav_bytes().into_diagnostic()?)
                                     .into_diagnostic()?
                             }
+                            other @ PipelineValue::Multiple(_) => {
+                                std::fs::write(path, other.to_string()).into_diagnostic()?
+                            }
                         }
 
                         if let Some(app) = args.command.as_deref() {
@@ -598,17 +1009,8 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
             }
         }
 
-        // Save the completed run for potential export
-        if let Ok(events) = current_events.lock() {
-            if !events.is_empty() {
-                if let Ok(mut run) = last_run.lock() {
-                    *run = Some(PipelineRun {
-                        input: line.to_string(),
-                        events: events.clone(),
-                    });
-                }
-            }
-        }
+        // Keep the run for `:save`
+        recorder.finish();
 
         tracing::debug!("DONE");
     }
@@ -622,7 +1024,34 @@ async fn run_repl(shell: &mut Shell, bundle: &Bundle, args: &RunArgs) -> miette:
     Ok(())
 }
 
-fn parse_config(config: &[String]) -> miette::Result<serde_json::Value> {
+/// Turn raw input into the value the pipeline's entry takes, or explain why
+/// it can't be. `source` names where the input came from.
+fn entry_value(
+    defn: &PipelineDefinition,
+    input: Vec<u8>,
+    source: &str,
+) -> miette::Result<PipelineValue> {
+    let value = match defn.entry.value_type.as_str() {
+        "bytes" => Ok(PipelineValue::Bytes(input)),
+        "json" => serde_json::from_slice(&input)
+            .map(PipelineValue::Json)
+            .map_err(|e| miette::miette!("The pipeline takes JSON, but {source} isn't: {e}")),
+        "string" | "path" => String::from_utf8(input)
+            .map(PipelineValue::String)
+            .map_err(|_| {
+                miette::miette!(
+                    "The pipeline takes text, but {source} is not valid UTF-8 \
+                     (binary input needs a pipeline with a bytes entry)"
+                )
+            }),
+        other => Err(miette::miette!(
+            "The pipeline takes {other}, which can't be given on the command line"
+        )),
+    };
+    value.fail_as(Failure::Usage)
+}
+
+pub(crate) fn parse_config(config: &[String]) -> miette::Result<serde_json::Value> {
     tracing::debug!("Parsing config: {:?}", config);
     let map = config
         .iter()
@@ -638,115 +1067,287 @@ fn parse_config(config: &[String]) -> miette::Result<serde_json::Value> {
                 .into_diagnostic()
                 .map(|b| (a.to_string(), b))
         })
-        .collect::<Result<Map<_, _>, _>>()?;
+        .collect::<Result<Map<_, _>, _>>()
+        .fail_as(Failure::Usage)?;
 
     Ok(serde_json::Value::Object(map))
 }
 
-fn strip_ansi_codes(s: &str) -> String {
-    // Simple ANSI escape sequence removal
-    use regex::Regex;
-    let re = Regex::new(r"\x1b\[[0-9;]*m").unwrap();
-    re.replace_all(s, "").to_string()
+pub(crate) fn parse_asset_overrides(
+    overrides: &[String],
+) -> miette::Result<HashMap<String, PathBuf>> {
+    overrides
+        .iter()
+        .map(|x| {
+            let Some((asset, path)) = x.split_once('=') else {
+                miette::bail!("Invalid asset override (expected ASSET=PATH): {}", x);
+            };
+            let path = PathBuf::from(path);
+            if !path.is_file() {
+                miette::bail!(
+                    "Asset override for {} does not exist: {}",
+                    asset,
+                    path.display()
+                );
+            }
+            Ok((asset.to_string(), path))
+        })
+        .collect::<miette::Result<_>>()
+        .fail_as(Failure::Usage)
 }
 
-fn save_markdown(last_run: &Arc<Mutex<Option<PipelineRun>>>, filename: &str) -> miette::Result<()> {
-    use std::fmt::Write;
-
-    let run = match last_run.lock() {
-        Ok(run) => run.clone(),
-        Err(_) => miette::bail!("Failed to acquire lock on pipeline run"),
+fn save_markdown(recorder: &RunRecorder, filename: &str) -> miette::Result<()> {
+    let Some(run) = recorder.last_run() else {
+        miette::bail!("No pipeline run to export");
     };
+    std::fs::write(filename, run.to_markdown()).into_diagnostic()
+}
 
-    let run = match run {
-        Some(run) => run,
-        None => miette::bail!("No pipeline run to export"),
+pub(crate) fn value_to_json(value: PipelineValue) -> Result<serde_json::Value, String> {
+    let value = if deterministic::is_enabled() {
+        deterministic_value(&value)
+    } else {
+        value
     };
-
-    let mut markdown = String::new();
-
-    writeln!(markdown, "# Pipeline Debug Report").into_diagnostic()?;
-    writeln!(markdown).into_diagnostic()?;
-    writeln!(markdown, "## PipelineValue").into_diagnostic()?;
-    writeln!(markdown, "```").into_diagnostic()?;
-    writeln!(markdown, "{}", run.input).into_diagnostic()?;
-    writeln!(markdown, "```").into_diagnostic()?;
-    writeln!(markdown).into_diagnostic()?;
-    writeln!(markdown, "## Pipeline Execution").into_diagnostic()?;
-    writeln!(markdown).into_diagnostic()?;
-
-    for event in &run.events {
-        let command_str = strip_ansi_codes(&format!("{}", event.command));
-        let event_str = strip_ansi_codes(&format!("{:#}", event.event));
-
-        writeln!(markdown, "<details>").into_diagnostic()?;
-        writeln!(
-            markdown,
-            "<summary><code>[{}]</code> <code>{}</code></summary>",
-            event.key, command_str
-        )
-        .into_diagnostic()?;
-        writeln!(markdown).into_diagnostic()?;
-        writeln!(markdown, "```").into_diagnostic()?;
-        writeln!(markdown, "{}", event_str).into_diagnostic()?;
-        writeln!(markdown, "```").into_diagnostic()?;
-        writeln!(markdown, "</details>").into_diagnostic()?;
-        writeln!(markdown).into_diagnostic()?;
+    match value {
+        PipelineValue::String(s) => Ok(serde_json::Value::String(s)),
+        PipelineValue::Json(j) => Ok(j),
+        PipelineValue::Multiple(x) => x
+            .into_iter()
+            .map(value_to_json)
+            .collect::<Result<Vec<_>, _>>()
+            .map(serde_json::Value::Array),
+        PipelineValue::Bytes(_) | PipelineValue::Audio(_) => {
+            Err("binary output can't be streamed as JSON".to_string())
+        }
     }
+}
 
-    std::fs::write(filename, markdown).into_diagnostic()?;
+/// `run --stream`: every line (or NUL-delimited chunk) of `reader`, stdin,
+/// is a document, run through one pipeline. Each gets exactly one line of
+/// `out`, so results line up with the input: the output value, an array if
+/// there were several, or `{"error": ...}`. A failed document takes its
+/// pipeline down, so a fresh one is created.
+async fn run_stream(
+    bundle: &Bundle,
+    config: serde_json::Value,
+    null_data: bool,
+    fail_on_errors: Option<usize>,
+    mut reader: impl tokio::io::AsyncBufRead + Unpin,
+    out: &mut dyn Write,
+) -> miette::Result<()> {
+    use tokio::io::AsyncBufReadExt as _;
 
-    Ok(())
-}
+    let mut pipe = bundle
+        .create(config.clone())
+        .await
+        .into_diagnostic()
+        .fail_as(Failure::BundleLoad)?;
+    let delimiter = if null_data { b'\0' } else { b'\n' };
+    let mut buf = Vec::new();
+    let mut errors_found = 0;
+    let mut documents_failed = 0;
 
-pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
-    let path = args
-        .path
-        .as_ref()
-        .cloned()
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
-    let bundle = if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
-        if let Some(ref pipeline_name) = args.pipeline {
-            Bundle::from_bundle_named(&path, pipeline_name)
-                .await
-                .into_diagnostic()?
-        } else {
-            Bundle::from_bundle(&path).await.into_diagnostic()?
+    loop {
+        buf.clear();
+        if reader
+            .read_until(delimiter, &mut buf)
+            .await
+            .into_diagnostic()?
+            == 0
+        {
+            break;
         }
-    } else {
-        // For TypeScript files, prepare the environment (sync + type check)
-        let pipeline_path = if path.is_dir() {
-            path.join("pipeline.ts")
-        } else {
-            path.clone()
-        };
-
-        if pipeline_path.exists() {
-            utils::prepare_typescript_pipeline(shell, &pipeline_path, args.skip_check)?;
+        if buf.last() == Some(&delimiter) {
+            buf.pop();
+        }
+        if !null_data && buf.last() == Some(&b'\r') {
+            buf.pop();
+        }
+        let document = String::from_utf8_lossy(&buf).into_owned();
+
+        let mut outputs = Vec::new();
+        let mut error = None;
+        let mut stream = pipe.forward(PipelineValue::String(document)).await;
+        while let Some(result) = stream.next().await {
+            match result.map_err(|e| e.to_string()).and_then(value_to_json) {
+                Ok(value) => outputs.push(value),
+                Err(e) => {
+                    error = Some(e);
+                    break;
+                }
+            }
+        }
+        drop(stream);
+        if deterministic::is_enabled() {
+            error = error.map(|e| deterministic::normalize_paths(&e));
         }
 
-        crate::deno_rt::save_ast(&path, "pipeline.json")?;
-        if let Some(ref pipeline_name) = args.pipeline {
-            Bundle::from_path_named(&path, pipeline_name)
+        let failed = error.is_some();
+        if failed {
+            documents_failed += 1;
+        }
+        errors_found += outputs.iter().map(exit::count_errors).sum::<usize>();
+        let line = match error {
+            Some(e) => serde_json::json!({ "error": e }),
+            None if outputs.len() == 1 => outputs.remove(0),
+            None if outputs.is_empty() => serde_json::Value::Null,
+            None => serde_json::Value::Array(outputs),
+        };
+        if failed {
+            pipe = bundle
+                .create(config.clone())
                 .await
-                .into_diagnostic()?
-        } else {
-            Bundle::from_path(&path).await.into_diagnostic()?
+                .into_diagnostic()
+                .fail_as(Failure::BundleLoad)?;
         }
+        writeln!(out, "{}", line).into_diagnostic()?;
+        out.flush().into_diagnostic()?;
+    }
+
+    if documents_failed > 0 {
+        return Err(Failure::Pipeline.msg(format!("{} documents failed", documents_failed)));
+    }
+    exit::check_errors(errors_found, fail_on_errors)
+}
+
+/// Shows downloads of remote assets on stderr, which happen the first time
+/// a bundle using them loads.
+fn download_progress() -> DownloadCallback {
+    let tty = std::io::stderr().is_terminal();
+    DownloadCallback::new(move |progress| {
+        let mb = |bytes: u64| bytes >> 20;
+        if progress.done {
+            if tty {
+                eprint!("\r\x1b[2K");
+            }
+            eprintln!(
+                "{:>12} {} ({} MB)",
+                "Downloaded",
+                progress.path,
+                mb(progress.downloaded)
+            );
+        } else if tty {
+            let total = progress
+                .total
+                .map(|x| format!(" of {}", mb(x)))
+                .unwrap_or_default();
+            eprint!(
+                "\r{:>12} {}: {}{} MB",
+                "Downloading",
+                progress.path,
+                mb(progress.downloaded),
+                total
+            );
+        } else if progress.downloaded == 0 {
+            eprintln!(
+                "{:>12} {} from {}",
+                "Downloading", progress.path, progress.url
+            );
+        }
+    })
+}
+
+/// Load a `.drb` bundle, or a TypeScript pipeline (a file or a project
+/// directory) after syncing and type checking it.
+pub(crate) async fn load_bundle(
+    shell: &mut Shell,
+    path: &Path,
+    options: BundleOptions,
+    skip_check: bool,
+) -> miette::Result<Bundle> {
+    open_bundle(shell, path, options, skip_check)
+        .await
+        .fail_as(Failure::BundleLoad)
+}
+
+async fn open_bundle(
+    shell: &mut Shell,
+    path: &Path,
+    mut options: BundleOptions,
+    skip_check: bool,
+) -> miette::Result<Bundle> {
+    if options.on_download.is_none() {
+        options.on_download = Some(download_progress());
+    }
+    if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
+        return Bundle::from_bundle_with_options(path, options)
+            .await
+            .into_diagnostic();
+    }
+
+    // For TypeScript files, prepare the environment (sync + type check)
+    let pipeline_path = if path.is_dir() {
+        path.join("pipeline.ts")
+    } else {
+        path.to_path_buf()
     };
 
-    let config = parse_config(&args.config)?;
+    if pipeline_path.exists() {
+        utils::prepare_typescript_pipeline(shell, &pipeline_path, skip_check)?;
+    }
+
+    crate::deno_rt::save_ast(path, "pipeline.json")?;
+    Bundle::from_path_with_options(path, options)
+        .await
+        .into_diagnostic()
+}
 
-    if !std::io::stdin().is_terminal() {
-        let mut s = String::new();
-        std::io::stdin().read_to_string(&mut s).into_diagnostic()?;
-        args.input = Some(s);
+pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
+    // `--batch -` is the corpus on stdin, a document per line: --stream
+    if args.batch.as_deref() == Some(Path::new("-")) {
+        if args.emit_stage.is_some() {
+            return Err(Failure::Usage.msg("--emit-stage needs a corpus directory, not --batch -"));
+        }
+        args.batch = None;
+        args.stream = true;
     }
 
+    let path = match &args.path {
+        Some(path) => path.clone(),
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = BundleOptions {
+        pipeline: args.pipeline.clone(),
+        asset_overrides: parse_asset_overrides(&args.asset_override)?,
+        verify: args.verify.into(),
+        channel_capacity: args.channel_capacity,
+        grow_channels: args.grow_channels,
+        ..Default::default()
+    };
+    let bundle = load_bundle(shell, &path, options.clone(), args.skip_check).await?;
+
+    let mut config = parse_config(&args.config)?;
+    if args.report.is_some() {
+        report::request_positions(bundle.definition(), &mut config);
+    }
+
+    let input = if let Some(file) = args.input_file.as_deref() {
+        let bytes = std::fs::read(file)
+            .map_err(|e| Failure::Usage.msg(format!("Failed to read {}: {}", file.display(), e)))?;
+        Some(entry_value(
+            bundle.definition(),
+            bytes,
+            &file.display().to_string(),
+        )?)
+    } else if !args.stream && args.batch.is_none() && !std::io::stdin().is_terminal() {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes).into_diagnostic()?;
+        Some(entry_value(bundle.definition(), bytes, "stdin")?)
+    } else if let Some(input) = args.input.take() {
+        Some(entry_value(
+            bundle.definition(),
+            input.into_bytes(),
+            "the input",
+        )?)
+    } else {
+        None
+    };
+
     // --break-after <step>: run the pipeline up to the named step, print that
     // step's raw output, and stop — for inspecting an intermediate stage
     // non-interactively, like libdivvun's modes files (#40).
-    if let Some(step) = args.break_after.as_deref() {
+    if let Some(step) = args.break_after.as_deref().or(args.emit_stage.as_deref()) {
         if !bundle.definition().commands.contains_key(step) {
             let mut steps = bundle
                 .definition()
@@ -755,19 +1356,68 @@ pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
                 .cloned()
                 .collect::<Vec<_>>();
             steps.sort();
-            return Err(miette::miette!(
+            return Err(Failure::Usage.msg(format!(
                 "no pipeline step named '{step}'; available steps: {}",
                 steps.join(", ")
+            )));
+        }
+    }
+
+    if args.null_data && !args.stream {
+        return Err(Failure::Usage.msg("-z only applies to --stream and --batch -"));
+    }
+    if args.stream {
+        if args.output_path.is_some() {
+            return Err(Failure::Usage.msg(
+                "--stream and --batch - print their results; --output-path needs a corpus directory",
             ));
         }
+        let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+        return run_stream(
+            &bundle,
+            config,
+            args.null_data,
+            args.fail_on_errors,
+            stdin,
+            &mut io::stdout(),
+        )
+        .await;
+    }
+
+    if let Some(corpus) = args.batch.as_deref() {
+        let batch = batch::Batch {
+            corpus,
+            step: args.emit_stage.as_deref(),
+            output_dir: args.output_path.as_deref(),
+        };
+        let errors_found = batch::run(shell, &bundle, config, batch).await?;
+        return exit::check_errors(errors_found, args.fail_on_errors);
+    }
+
+    if args.report.is_some() && input.is_none() {
+        return Err(Failure::Usage.msg(
+            "--report needs an input: pass --input-file, pipe to stdin or give it as an argument",
+        ));
+    }
+    if args.fail_on_errors.is_some() && input.is_none() {
+        return Err(Failure::Usage.msg(
+            "--fail-on-errors needs an input: pass --input-file, pipe to stdin or give it as an argument",
+        ));
     }
 
     let captured: Arc<Mutex<Option<(PipelineValue, Command)>>> = Arc::new(Mutex::new(None));
+    let steps = StepLog::default();
+    let record_steps = args.crash_dumps.is_some() && input.is_some();
 
-    let mut pipe = if let Some(step) = args.break_after.clone() {
+    let mut pipe = if args.break_after.is_some() || record_steps {
         let captured = captured.clone();
+        let step = args.break_after.clone();
+        let steps = record_steps.then(|| steps.clone());
         let tap = Arc::new(move |key: &str, cmd: &Command, event: &PipelineEvent| {
-            let stop = key == step.as_str();
+            if let (Some(steps), PipelineEvent::Value(v)) = (&steps, event) {
+                steps.record(key, v);
+            }
+            let stop = step.as_deref() == Some(key);
             if stop {
                 if let PipelineEvent::Value(v) = event {
                     *captured.lock().unwrap() = Some((v.clone(), cmd.clone()));
@@ -782,13 +1432,21 @@ pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
             }
             .boxed()
         });
-        bundle.create_with_tap(config, tap).await.into_diagnostic()?
+        bundle
+            .create_with_tap(config.clone(), tap)
+            .await
+            .into_diagnostic()
+            .fail_as(Failure::BundleLoad)?
     } else {
-        bundle.create(config).await.into_diagnostic()?
+        bundle
+            .create(config.clone())
+            .await
+            .into_diagnostic()
+            .fail_as(Failure::BundleLoad)?
     };
 
-    if let Some(input) = args.input {
-        let mut stream = pipe.forward(PipelineValue::String(input)).await;
+    if let Some(input) = input {
+        let mut stream = pipe.forward(input.clone()).await;
 
         if let Some(step) = args.break_after.as_deref() {
             // Drain so the pipeline runs up to the breakpoint; the tap captured
@@ -804,10 +1462,54 @@ pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
             }
         } else {
             let output_cmd = bundle.definition().output.resolve(bundle.definition());
+            let mut outputs = Vec::new();
+            let mut errors_found = 0;
+
+            while let Some(result) = stream.next().await {
+                match result {
+                    Ok(value) if args.report.is_some() => {
+                        let value = value_to_json(value).map_err(|e| miette::miette!(e))?;
+                        errors_found += exit::count_errors(&value);
+                        outputs.push(value);
+                    }
+                    Ok(value) => {
+                        if let PipelineValue::Json(json) = &value {
+                            errors_found += exit::count_errors(json);
+                        }
+                        print_input_highlighted(shell, &value, output_cmd)?
+                    }
+                    Err(e) => {
+                        let Some(dir) = args.crash_dumps.as_deref() else {
+                            return Err(Failure::Pipeline.msg(e));
+                        };
+                        let error = e.to_string();
+                        let report = crash_dump::CrashReport {
+                            bundle_path: &path,
+                            pipeline: args.pipeline.as_deref(),
+                            input: &input,
+                            config: &config,
+                            steps: &steps,
+                            error: &error,
+                        };
+                        let dump = crash_dump::write(dir, report).await?;
+                        return Err(Failure::Pipeline.msg(format!(
+                            "{}\ncrash dump written to {}",
+                            error,
+                            dump.display()
+                        )));
+                    }
+                }
+            }
 
-            while let Some(Ok(input)) = stream.next().await {
-                print_input_highlighted(shell, &input, output_cmd)?;
+            if let Some(format) = args.report {
+                let file = args
+                    .input_file
+                    .as_ref()
+                    .map(|x| x.display().to_string())
+                    .unwrap_or_else(|| "stdin".to_string());
+                print!("{}", report::render(format, &file, &outputs)?);
             }
+            exit::check_errors(errors_found, args.fail_on_errors)?;
         }
 
         // if let Some(path) = args.output_path.as_deref() {
@@ -845,8 +1547,50 @@ pub async fn run(shell: &mut Shell, mut args: RunArgs) -> miette::Result<()> {
         //     }
         // }
     } else {
-        run_repl(shell, &bundle, &args).await?;
+        run_repl(shell, bundle, &path, options, &args).await?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets_keep_their_spacing() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut snippets = Snippets::load(dir.path().join("snippets.json"));
+        let mut shell = Shell::from_write(Box::new(Vec::new()));
+        snippets
+            .command(&mut shell, " save  spaced  Mun  leat\tboahtán ", None)
+            .unwrap();
+        assert_eq!(snippets.entries["spaced"], "Mun  leat\tboahtán ");
+
+        let snippets = Snippets::load(dir.path().join("snippets.json"));
+        assert_eq!(snippets.entries["spaced"], "Mun  leat\tboahtán ");
+    }
+
+    #[tokio::test]
+    async fn streams_each_line_as_a_document() {
+        let toy = Path::new(env!("CARGO_MANIFEST_DIR")).join("../tests/fixtures/toy");
+        let bundle = Bundle::from_path(toy).await.unwrap();
+        let run = |input: &'static [u8], null_data: bool| {
+            let bundle = &bundle;
+            async move {
+                let mut out = Vec::new();
+                let config = serde_json::json!({});
+                run_stream(bundle, config, null_data, None, input, &mut out)
+                    .await
+                    .unwrap();
+                String::from_utf8(out).unwrap()
+            }
+        };
+
+        assert_eq!(
+            run(b"lean\nboahtan\r\n", false).await,
+            "\"NAEL\"\n\"NATHAOB\"\n"
+        );
+        assert_eq!(run(b"le\nan\0mun", true).await, "\"NA\\nEL\"\n\"NUM\"\n");
+    }
+}
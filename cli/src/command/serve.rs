@@ -0,0 +1,1020 @@
+//! `serve CONFIG`: a small HTTP server running text through one bundle per
+//! language, for each configured [`Service`].
+//!
+//! `POST /check`, `/spell` and `/synthesize` run the request body through the
+//! default pipeline of the bundle picked by the `lang` query parameter, the
+//! `Accept-Language` header or the configured default, in that order.
+//! `GET /status` reports the pipeline pool of each bundle, and `GET /metrics`
+//! the same with request counts and latencies for Prometheus. Each bundle keeps a
+//! pool of warm pipelines, so requests don't pay for setting up the pipeline
+//! every time. With the `grpc` feature the same services are also offered
+//! over gRPC, see [`super::grpc`].
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use divvun_runtime::{
+    bundle::{Bundle, BundleOptions},
+    bundle_set::resolve_language,
+    modules::PipelineValue,
+    pipe_pool::{PipePool, PipePoolOptions},
+    util::metrics::HistogramSnapshot,
+};
+use miette::IntoDiagnostic;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+use walkdir::WalkDir;
+
+use crate::{cli::ServeArgs, shell::Shell};
+
+const DEFAULT_ADDR: &str = "127.0.0.1:4000";
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+/// How long a client gets to send its request, and to take the response.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a set of bundles is for. Each service has its own section of the
+/// config and its own endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Service {
+    /// Grammar checking, from `"bundles"`.
+    Check,
+    /// Spell checking, from `"spellers"`.
+    Spell,
+    /// Text-to-speech, from `"voices"`.
+    Synthesize,
+}
+
+impl Service {
+    const ALL: [Service; 3] = [Service::Check, Service::Spell, Service::Synthesize];
+
+    fn config_key(self) -> &'static str {
+        match self {
+            Service::Check => "bundles",
+            Service::Spell => "spellers",
+            Service::Synthesize => "voices",
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Service::Check => "check",
+            Service::Spell => "spell",
+            Service::Synthesize => "synthesize",
+        }
+    }
+}
+
+struct ServeConfig {
+    services: BTreeMap<Service, BTreeMap<String, PathBuf>>,
+    default: Option<String>,
+    pool: PipePoolOptions,
+}
+
+struct Language {
+    path: PathBuf,
+    pool: Arc<PipePool>,
+    /// [`Bundle::capabilities`], as `/status` reports it.
+    capabilities: serde_json::Value,
+    /// Bytes of the bundle on disk, as an estimate of the memory its models
+    /// take.
+    size: u64,
+}
+
+/// The bundles of one service, by language tag.
+pub(crate) struct Languages {
+    bundles: BTreeMap<String, Language>,
+    default: Option<String>,
+}
+
+impl Languages {
+    /// The registered language `tag` resolves to, or the default for an
+    /// empty tag.
+    pub(crate) fn resolve(&self, tag: &str) -> Option<&String> {
+        if tag.is_empty() {
+            return self.default.as_ref();
+        }
+        resolve_language(self.bundles.keys(), tag)
+    }
+
+    pub(crate) fn pool(&self, lang: &str) -> &Arc<PipePool> {
+        &self.bundles[lang].pool
+    }
+}
+
+pub(crate) struct Server {
+    services: BTreeMap<Service, Languages>,
+}
+
+impl Server {
+    pub(crate) fn languages(&self, service: Service) -> Option<&Languages> {
+        self.services.get(&service)
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn json(status: u16, value: &serde_json::Value) -> Response {
+        Response {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: serde_json::to_vec(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Response {
+        Response::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+}
+
+pub async fn serve(shell: &mut Shell, args: ServeArgs) -> miette::Result<()> {
+    let Some(config) = &args.config else {
+        miette::bail!(
+            "No bundles file given: pass one, or set `config` under [serve] in {}",
+            crate::config::FILE_NAME
+        );
+    };
+    let config = read_config(config)?;
+    let addr = args.addr.as_deref().unwrap_or(DEFAULT_ADDR);
+
+    let mut services = BTreeMap::new();
+    for (service, paths) in config.services {
+        let mut bundles = BTreeMap::new();
+        for (lang, path) in paths {
+            shell
+                .status(
+                    "Loading",
+                    format!("{} {} from {}", service.name(), lang, path.display()),
+                )
+                .into_diagnostic()?;
+            let bundle = load_bundle(&path)
+                .await
+                .map_err(|e| miette::miette!("Failed to load bundle for {}: {}", lang, e))?;
+            let capabilities = bundle
+                .capabilities()
+                .await
+                .map_err(|e| miette::miette!("Failed to read capabilities of {}: {}", lang, e))?;
+            let capabilities = serde_json::to_value(capabilities).into_diagnostic()?;
+            let pool = PipePool::new(Arc::new(bundle), config.pool.clone());
+            let size = bundle_size(&path);
+            bundles.insert(
+                lang,
+                Language {
+                    path,
+                    pool,
+                    capabilities,
+                    size,
+                },
+            );
+        }
+        let default = config
+            .default
+            .as_deref()
+            .and_then(|tag| resolve_language(bundles.keys(), tag))
+            .cloned();
+        services.insert(service, Languages { bundles, default });
+    }
+    let server = Arc::new(Server { services });
+
+    // Drop pipelines nobody has used for a while
+    let evict_every = (config.pool.idle_timeout / 4).max(Duration::from_secs(1));
+    let evicting = server.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(evict_every);
+        loop {
+            interval.tick().await;
+            for (service, languages) in &evicting.services {
+                for (lang, language) in &languages.bundles {
+                    let evicted = language.pool.evict_idle();
+                    if evicted > 0 {
+                        tracing::debug!(
+                            "Evicted {} idle {} pipelines of {}",
+                            evicted,
+                            service.name(),
+                            lang
+                        );
+                    }
+                }
+            }
+        }
+    });
+
+    #[cfg(feature = "grpc")]
+    if let Some(addr) = args.grpc_addr {
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = super::grpc::serve_grpc(server, addr).await {
+                tracing::error!("gRPC server failed: {}", e);
+            }
+        });
+        shell
+            .status("Listening", format!("for gRPC on {}", addr))
+            .into_diagnostic()?;
+    }
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| miette::miette!("Failed to listen on {}: {}", addr, e))?;
+    shell
+        .status(
+            "Listening",
+            format!("on http://{}", listener.local_addr().into_diagnostic()?),
+        )
+        .into_diagnostic()?;
+
+    loop {
+        let (stream, peer) = listener.accept().await.into_diagnostic()?;
+        let server = server.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, &server).await {
+                tracing::debug!("Connection from {} failed: {}", peer, e);
+            }
+        });
+    }
+}
+
+fn read_config(path: &Path) -> miette::Result<ServeConfig> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| miette::miette!("Failed to read {}: {}", path.display(), e))?;
+    let json: serde_json::Value = serde_json::from_str(&text)
+        .map_err(|e| miette::miette!("Invalid server config {}: {}", path.display(), e))?;
+    parse_config(&json, path.parent().unwrap_or(Path::new(".")))
+}
+
+/// Read the server config. Bundle paths are relative to `base`, the
+/// directory of the config file.
+fn parse_config(json: &serde_json::Value, base: &Path) -> miette::Result<ServeConfig> {
+    let mut services = BTreeMap::new();
+    for service in Service::ALL {
+        let key = service.config_key();
+        let Some(section) = json.get(key) else {
+            continue;
+        };
+        let bundles = section
+            .as_object()
+            .ok_or_else(|| miette::miette!("\"{}\" must map language tags to bundles", key))?
+            .iter()
+            .map(|(lang, path)| {
+                let path = path
+                    .as_str()
+                    .ok_or_else(|| miette::miette!("Bundle path for {} must be a string", lang))?;
+                Ok((lang.clone(), base.join(path)))
+            })
+            .collect::<miette::Result<BTreeMap<_, _>>>()?;
+        if !bundles.is_empty() {
+            services.insert(service, bundles);
+        }
+    }
+    if services.is_empty() {
+        miette::bail!("Server config has no bundles, spellers or voices");
+    }
+
+    let default = match json.get("default") {
+        None | Some(serde_json::Value::Null) => None,
+        Some(serde_json::Value::String(lang)) => {
+            if !services
+                .values()
+                .any(|bundles| resolve_language(bundles.keys(), lang).is_some())
+            {
+                miette::bail!("Default language {} has no bundle", lang);
+            }
+            Some(lang.clone())
+        }
+        Some(_) => miette::bail!("\"default\" must be a language tag"),
+    };
+
+    let number = |key: &str| -> miette::Result<Option<u64>> {
+        match json.get(key) {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(value) => value
+                .as_u64()
+                .map(Some)
+                .ok_or_else(|| miette::miette!("\"{}\" must be a whole number", key)),
+        }
+    };
+    let mut pool = PipePoolOptions::default();
+    if let Some(n) = number("max_concurrent")? {
+        pool.max_concurrent = n as usize;
+    }
+    pool.max_idle = number("max_idle")?.map_or(pool.max_concurrent, |n| n as usize);
+    if let Some(secs) = number("idle_timeout_secs")? {
+        pool.idle_timeout = Duration::from_secs(secs);
+    }
+
+    Ok(ServeConfig {
+        services,
+        default,
+        pool,
+    })
+}
+
+async fn load_bundle(path: &Path) -> Result<Bundle, divvun_runtime::bundle::Error> {
+    if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
+        Bundle::from_bundle_with_options(path, BundleOptions::default()).await
+    } else {
+        Bundle::from_path_with_options(path, BundleOptions::default()).await
+    }
+}
+
+/// Size of a `.drb` file, or of all files in a bundle directory.
+fn bundle_size(path: &Path) -> u64 {
+    WalkDir::new(path)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| e.metadata().ok())
+        .map(|m| m.len())
+        .sum()
+}
+
+async fn handle_connection(mut stream: TcpStream, server: &Server) -> std::io::Result<()> {
+    let response = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream)).await {
+        Ok(request) => match request? {
+            Ok(request) => route(server, &request).await,
+            Err(response) => response,
+        },
+        Err(_) => Response::error(408, "Timed out reading the request"),
+    };
+    tokio::time::timeout(WRITE_TIMEOUT, write_response(&mut stream, response))
+        .await
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))?
+}
+
+async fn route(server: &Server, request: &Request) -> Response {
+    let service = Service::ALL
+        .into_iter()
+        .find(|service| request.path.strip_prefix('/') == Some(service.name()));
+    match (request.method.as_str(), request.path.as_str(), service) {
+        ("GET", "/status", _) => status(server),
+        ("GET", "/metrics", _) => metrics(server),
+        ("POST", _, Some(service)) => run(server, service, request).await,
+        (_, "/status" | "/metrics", _) | (_, _, Some(_)) => {
+            Response::error(405, "Method not allowed")
+        }
+        _ => Response::error(404, "Not found"),
+    }
+}
+
+fn status(server: &Server) -> Response {
+    let services = server
+        .services
+        .iter()
+        .map(|(service, languages)| {
+            let bundles = languages
+                .bundles
+                .iter()
+                .map(|(lang, language)| {
+                    let stats = serde_json::to_value(language.pool.stats()).unwrap_or_default();
+                    (
+                        lang.clone(),
+                        serde_json::json!({
+                            "path": language.path.display().to_string(),
+                            "pool": stats,
+                            "capabilities": language.capabilities,
+                        }),
+                    )
+                })
+                .collect::<serde_json::Map<_, _>>();
+            (
+                service.name().to_string(),
+                serde_json::json!({
+                    "default": languages.default,
+                    "languages": bundles,
+                }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>();
+    Response::json(200, &serde_json::Value::Object(services))
+}
+
+fn metrics(server: &Server) -> Response {
+    let mut out = Exposition::default();
+    for (service, languages) in &server.services {
+        for (lang, language) in &languages.bundles {
+            let labels = [("service", service.name()), ("lang", lang.as_str())];
+            let pool = &language.pool;
+            let stats = pool.stats();
+
+            out.counter(
+                "drt_requests_total",
+                "Requests run through the bundle.",
+                &labels,
+                stats.requests,
+            );
+            out.counter(
+                "drt_request_errors_total",
+                "Requests that failed.",
+                &labels,
+                stats.errors,
+            );
+            out.histogram(
+                "drt_request_duration_seconds",
+                "Time to run a request, waiting for a free pipeline included.",
+                &labels,
+                &pool.latency(),
+            );
+            for (stage, histogram) in pool.stage_latency() {
+                let labels = [labels[0], labels[1], ("stage", stage.as_str())];
+                out.histogram(
+                    "drt_stage_duration_seconds",
+                    "Time from a step's input to each of its outputs.",
+                    &labels,
+                    &histogram,
+                );
+            }
+
+            for (state, count) in [("idle", stats.idle), ("in_use", stats.in_use)] {
+                let labels = [labels[0], labels[1], ("state", state)];
+                out.gauge(
+                    "drt_pool_pipelines",
+                    "Pipelines in the pool.",
+                    &labels,
+                    count,
+                );
+            }
+            out.gauge(
+                "drt_pool_max_concurrent",
+                "Pipelines the pool runs at most at once.",
+                &labels,
+                stats.max_concurrent,
+            );
+            out.gauge(
+                "drt_pool_waiting",
+                "Requests waiting for a free pipeline.",
+                &labels,
+                stats.waiting,
+            );
+            for (event, count) in [
+                ("created", stats.created),
+                ("reused", stats.reused),
+                ("evicted", stats.evicted),
+            ] {
+                let labels = [labels[0], labels[1], ("event", event)];
+                out.counter(
+                    "drt_pool_pipelines_total",
+                    "Pipelines created, reused and evicted by the pool.",
+                    &labels,
+                    count,
+                );
+            }
+            out.gauge(
+                "drt_bundle_size_bytes",
+                "Size of the bundle on disk, an estimate of the memory its models take.",
+                &labels,
+                language.size,
+            );
+        }
+    }
+
+    Response {
+        status: 200,
+        content_type: "text/plain; version=0.0.4",
+        headers: Vec::new(),
+        body: out.render().into_bytes(),
+    }
+}
+
+/// Metrics in the Prometheus text format. Samples are kept by metric, as
+/// each metric's samples have to be together under its `HELP` and `TYPE`.
+#[derive(Default)]
+struct Exposition {
+    /// Name, type, help and samples of each metric, in the order first seen.
+    metrics: Vec<(&'static str, &'static str, &'static str, String)>,
+}
+
+impl Exposition {
+    fn counter(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: impl std::fmt::Display,
+    ) {
+        self.sample(name, "counter", help, name, labels, value);
+    }
+
+    fn gauge(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        value: impl std::fmt::Display,
+    ) {
+        self.sample(name, "gauge", help, name, labels, value);
+    }
+
+    fn histogram(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        histogram: &HistogramSnapshot,
+    ) {
+        let bucket = format!("{}_bucket", name);
+        let bounds = histogram
+            .buckets
+            .iter()
+            .map(|(le, count)| (le.to_string(), *count))
+            .chain([("+Inf".to_string(), histogram.count)]);
+        for (le, count) in bounds {
+            let mut labels = labels.to_vec();
+            labels.push(("le", &le));
+            self.sample(name, "histogram", help, &bucket, &labels, count);
+        }
+        let sum = format!("{}_sum", name);
+        self.sample(name, "histogram", help, &sum, labels, histogram.sum);
+        let count = format!("{}_count", name);
+        self.sample(name, "histogram", help, &count, labels, histogram.count);
+    }
+
+    fn sample(
+        &mut self,
+        name: &'static str,
+        kind: &'static str,
+        help: &'static str,
+        sample: &str,
+        labels: &[(&str, &str)],
+        value: impl std::fmt::Display,
+    ) {
+        let index = match self.metrics.iter().position(|x| x.0 == name) {
+            Some(index) => index,
+            None => {
+                self.metrics.push((name, kind, help, String::new()));
+                self.metrics.len() - 1
+            }
+        };
+        let labels = labels
+            .iter()
+            .map(|(k, v)| {
+                let v = v
+                    .replace('\\', "\\\\")
+                    .replace('"', "\\\"")
+                    .replace('\n', "\\n");
+                format!("{}=\"{}\"", k, v)
+            })
+            .collect::<Vec<_>>()
+            .join(",");
+        let samples = &mut self.metrics[index].3;
+        samples.push_str(&format!("{}{{{}}} {}\n", sample, labels, value));
+    }
+
+    fn render(self) -> String {
+        let mut out = String::new();
+        for (name, kind, help, samples) in self.metrics {
+            out.push_str(&format!(
+                "# HELP {} {}\n# TYPE {} {}\n",
+                name, help, name, kind
+            ));
+            out.push_str(&samples);
+        }
+        out
+    }
+}
+
+/// The bundle language for a request: the `lang` query parameter, else the
+/// best `Accept-Language` match, else the default.
+fn pick_language<'a>(languages: &'a Languages, request: &Request) -> Result<&'a String, Response> {
+    if let Some(lang) = request.query("lang") {
+        return languages
+            .resolve(lang)
+            .ok_or_else(|| Response::error(404, format!("No bundle for language {}", lang)));
+    }
+
+    if let Some(header) = request.header("accept-language") {
+        if let Some(lang) = accept_languages(header)
+            .iter()
+            .find_map(|tag| resolve_language(languages.bundles.keys(), tag))
+        {
+            return Ok(lang);
+        }
+    }
+
+    languages.default.as_ref().ok_or_else(|| {
+        Response::error(
+            400,
+            "No language given: use ?lang= or an Accept-Language header",
+        )
+    })
+}
+
+fn is_binary(value: &PipelineValue) -> bool {
+    matches!(value, PipelineValue::Bytes(_) | PipelineValue::Audio(_))
+}
+
+/// The bytes of a binary output: raw bytes, or audio as WAV.
+pub(crate) fn binary_output(value: &PipelineValue) -> Option<std::io::Result<Vec<u8>>> {
+    match value {
+        PipelineValue::Bytes(bytes) => Some(Ok(bytes.clone())),
+        PipelineValue::Audio(audio) => Some(audio.to_wav_bytes()),
+        _ => None,
+    }
+}
+
+async fn run(server: &Server, service: Service, request: &Request) -> Response {
+    let Some(languages) = server.languages(service) else {
+        return Response::error(404, format!("No {} bundles configured", service.name()));
+    };
+    let lang = match pick_language(languages, request) {
+        Ok(lang) => lang,
+        Err(response) => return response,
+    };
+    let Ok(text) = std::str::from_utf8(&request.body) else {
+        return Response::error(400, "Request body is not UTF-8");
+    };
+
+    let pool = languages.pool(lang);
+    let outputs = match pool.forward(PipelineValue::String(text.to_string())).await {
+        Ok(outputs) => outputs,
+        Err(e) => {
+            tracing::error!("Pipeline for {} failed: {}", lang, e);
+            return Response::error(500, e);
+        }
+    };
+
+    let binary = match outputs.as_slice() {
+        [value] => binary_output(value),
+        _ => None,
+    };
+    let mut response = match binary {
+        Some(Ok(bytes)) => Response {
+            status: 200,
+            content_type: if bytes.starts_with(b"RIFF") {
+                "audio/wav"
+            } else {
+                "application/octet-stream"
+            },
+            headers: Vec::new(),
+            body: bytes,
+        },
+        Some(Err(e)) => Response::error(500, e),
+        None if outputs.iter().any(is_binary) => {
+            Response::error(500, "Pipeline returned several binary values")
+        }
+        None => Response::json(
+            200,
+            &serde_json::Value::Array(
+                outputs
+                    .into_iter()
+                    .map(|x| match x {
+                        PipelineValue::Json(value) => value,
+                        other => serde_json::Value::String(other.to_string()),
+                    })
+                    .collect(),
+            ),
+        ),
+    };
+    response.headers.push(("Content-Language", lang.clone()));
+    response
+}
+
+/// Language tags of an `Accept-Language` header, most preferred first.
+/// Wildcards and tags with `q=0` are left out.
+fn accept_languages(header: &str) -> Vec<String> {
+    let mut tags = header
+        .split(',')
+        .filter_map(|part| {
+            let mut params = part.split(';');
+            let tag = params.next()?.trim();
+            let q = params
+                .filter_map(|p| p.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*" && q > 0.0).then(|| (tag.to_string(), q))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so equal weights keep the client's order
+    tags.sort_by(|a, b| b.1.total_cmp(&a.1));
+    tags.into_iter().map(|(tag, _)| tag).collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (escaped, bytes[i]) {
+            (Some(b), _) => {
+                out.push(b);
+                i += 2;
+            }
+            (None, b'+') => out.push(b' '),
+            (None, b) => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Read more of the request into `buf`, failing if the client hung up.
+async fn read_more<S: AsyncRead + Unpin>(stream: &mut S, buf: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut chunk = [0u8; 4096];
+    let n = stream.read(&mut chunk).await?;
+    if n == 0 {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    buf.extend_from_slice(&chunk[..n]);
+    Ok(())
+}
+
+/// Read one HTTP/1.1 request. Protocol errors become the response to send.
+async fn read_request<S: AsyncRead + Unpin>(
+    stream: &mut S,
+) -> std::io::Result<Result<Request, Response>> {
+    let mut buf = Vec::new();
+    let header_end = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos;
+        }
+        if buf.len() > MAX_HEADER_SIZE {
+            return Ok(Err(Response::error(431, "Request headers too large")));
+        }
+        read_more(stream, &mut buf).await?;
+    };
+
+    let Ok(head) = std::str::from_utf8(&buf[..header_end]) else {
+        return Ok(Err(Response::error(400, "Malformed request")));
+    };
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let (Some(method), Some(target)) = (request_line.next(), request_line.next()) else {
+        return Ok(Err(Response::error(400, "Malformed request")));
+    };
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(k, v)| (k.trim().to_string(), v.trim().to_string()))
+        .collect::<Vec<_>>();
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|pair| {
+            let (k, v) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(k), percent_decode(v))
+        })
+        .collect();
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        headers,
+        body: Vec::new(),
+    };
+
+    let mut body = buf.split_off(header_end + 4);
+    let chunked = request
+        .header("transfer-encoding")
+        .is_some_and(|x| x.to_ascii_lowercase().contains("chunked"));
+    if chunked {
+        request.body = match read_chunked(stream, body).await? {
+            Ok(body) => body,
+            Err(response) => return Ok(Err(response)),
+        };
+        return Ok(Ok(request));
+    }
+
+    let length = match request.header("content-length").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(length)) => length,
+        Some(Err(_)) => return Ok(Err(Response::error(400, "Invalid Content-Length"))),
+    };
+    if length > MAX_BODY_SIZE {
+        return Ok(Err(Response::error(413, "Request body too large")));
+    }
+
+    while body.len() < length {
+        read_more(stream, &mut body).await?;
+    }
+    body.truncate(length);
+    request.body = body;
+
+    Ok(Ok(request))
+}
+
+/// Read a `Transfer-Encoding: chunked` body, of which `buf` has been read
+/// already. Chunk extensions and trailers are skipped.
+async fn read_chunked<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    mut buf: Vec<u8>,
+) -> std::io::Result<Result<Vec<u8>, Response>> {
+    let mut body = Vec::new();
+    loop {
+        let line_end = loop {
+            if let Some(pos) = buf.windows(2).position(|w| w == b"\r\n") {
+                break pos;
+            }
+            if buf.len() > MAX_HEADER_SIZE {
+                return Ok(Err(Response::error(400, "Malformed chunked body")));
+            }
+            read_more(stream, &mut buf).await?;
+        };
+        let size = std::str::from_utf8(&buf[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok());
+        let Some(size) = size else {
+            return Ok(Err(Response::error(400, "Malformed chunked body")));
+        };
+        buf.drain(..line_end + 2);
+
+        if size == 0 {
+            // Trailers, if any, end with an empty line
+            while !buf.starts_with(b"\r\n") && !buf.windows(4).any(|w| w == b"\r\n\r\n") {
+                if buf.len() > MAX_HEADER_SIZE {
+                    return Ok(Err(Response::error(431, "Request headers too large")));
+                }
+                read_more(stream, &mut buf).await?;
+            }
+            return Ok(Ok(body));
+        }
+        if body
+            .len()
+            .checked_add(size)
+            .is_none_or(|len| len > MAX_BODY_SIZE)
+        {
+            return Ok(Err(Response::error(413, "Request body too large")));
+        }
+        let Some(end) = size.checked_add(2) else {
+            return Ok(Err(Response::error(400, "Malformed chunked body")));
+        };
+        while buf.len() < end {
+            read_more(stream, &mut buf).await?;
+        }
+        if &buf[size..end] != b"\r\n" {
+            return Ok(Err(Response::error(400, "Malformed chunked body")));
+        }
+        body.extend(buf.drain(..size));
+        buf.drain(..2);
+    }
+}
+
+async fn write_response(stream: &mut TcpStream, response: Response) -> std::io::Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        _ => "Internal Server Error",
+    };
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason,
+        response.content_type,
+        response.body.len()
+    );
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&response.body).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_language_is_ordered_by_weight() {
+        assert_eq!(
+            accept_languages("nb;q=0.5, se-NO, sma;q=0.8, *;q=0.1, en;q=0"),
+            ["se-NO", "sma", "nb"]
+        );
+        assert!(accept_languages("").is_empty());
+    }
+
+    #[test]
+    fn percent_decode_handles_escapes_and_plus() {
+        assert_eq!(percent_decode("se%2DNO+x"), "se-NO x");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%C3%A1"), "á");
+    }
+
+    #[tokio::test]
+    async fn reads_chunked_bodies() {
+        let raw = b"POST /check?lang=se HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\ngiel\r\n3;ext=1\r\nla \r\n5\r\ns\xC3\xA1tn\r\n1\r\ni\r\n0\r\nX-Trailer: 1\r\n\r\n";
+        let request = read_request(&mut &raw[..]).await.unwrap().ok().unwrap();
+        assert_eq!(request.query("lang"), Some("se"));
+        assert_eq!(std::str::from_utf8(&request.body).unwrap(), "giella sátni");
+
+        let raw = b"POST /check HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n";
+        let response = read_request(&mut &raw[..]).await.unwrap().err().unwrap();
+        assert_eq!(response.status, 400);
+
+        // A second chunk whose size would overflow the body length
+        let raw = b"POST /check HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n\
+            4\r\ngiel\r\nffffffffffffffff\r\nla\r\n0\r\n\r\n";
+        let response = read_request(&mut &raw[..]).await.unwrap().err().unwrap();
+        assert_eq!(response.status, 413);
+    }
+
+    #[test]
+    fn config_resolves_paths_and_default() {
+        let json = serde_json::json!({
+            "bundles": { "se": "se.drb", "sma": "/srv/sma.drb" },
+            "voices": { "se": "voices/se.drb" },
+            "default": "se-NO",
+            "max_concurrent": 2,
+            "idle_timeout_secs": 60,
+        });
+        let config = parse_config(&json, Path::new("/etc/drt")).unwrap();
+        let bundles = &config.services[&Service::Check];
+        assert_eq!(bundles["se"], Path::new("/etc/drt/se.drb"));
+        assert_eq!(bundles["sma"], Path::new("/srv/sma.drb"));
+        assert_eq!(
+            config.services[&Service::Synthesize]["se"],
+            Path::new("/etc/drt/voices/se.drb")
+        );
+        assert!(!config.services.contains_key(&Service::Spell));
+        assert_eq!(config.default.as_deref(), Some("se-NO"));
+        assert_eq!(config.pool.max_concurrent, 2);
+        assert_eq!(config.pool.max_idle, 2);
+        assert_eq!(config.pool.idle_timeout, Duration::from_secs(60));
+
+        let json = serde_json::json!({ "bundles": { "se": "se.drb" }, "default": "fi" });
+        assert!(parse_config(&json, Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn metrics_are_grouped_under_one_header() {
+        let mut out = Exposition::default();
+        out.counter("drt_requests_total", "Requests.", &[("lang", "se")], 3);
+        out.histogram(
+            "drt_request_duration_seconds",
+            "Time.",
+            &[("lang", "se")],
+            &HistogramSnapshot {
+                buckets: vec![(0.1, 1), (1.0, 2)],
+                count: 3,
+                sum: 12.5,
+            },
+        );
+        out.counter("drt_requests_total", "Requests.", &[("lang", "s\"e")], 0);
+
+        assert_eq!(
+            out.render(),
+            "# HELP drt_requests_total Requests.\n\
+             # TYPE drt_requests_total counter\n\
+             drt_requests_total{lang=\"se\"} 3\n\
+             drt_requests_total{lang=\"s\\\"e\"} 0\n\
+             # HELP drt_request_duration_seconds Time.\n\
+             # TYPE drt_request_duration_seconds histogram\n\
+             drt_request_duration_seconds_bucket{lang=\"se\",le=\"0.1\"} 1\n\
+             drt_request_duration_seconds_bucket{lang=\"se\",le=\"1\"} 2\n\
+             drt_request_duration_seconds_bucket{lang=\"se\",le=\"+Inf\"} 3\n\
+             drt_request_duration_seconds_sum{lang=\"se\"} 12.5\n\
+             drt_request_duration_seconds_count{lang=\"se\"} 3\n"
+        );
+    }
+}
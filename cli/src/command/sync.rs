@@ -1,17 +1,194 @@
+use std::{
+    io::Write as _,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
 use miette::IntoDiagnostic;
+use serde_json::json;
 
-use crate::{cli::SyncArgs, shell::Shell};
+use crate::{
+    cli::{ProgressFormat, SyncArgs},
+    shell::Shell,
+};
 
-pub async fn sync(shell: &mut Shell, args: SyncArgs) -> miette::Result<()> {
-    let cur_dir = args
-        .path
-        .unwrap_or_else(|| std::env::current_dir().unwrap());
+/// Reports sync progress either as the usual human status lines or as JSON
+/// lines on stdout, so GUI wrappers can render progress bars.
+struct Progress {
+    format: ProgressFormat,
+    started: Instant,
+    bytes_total: usize,
+    bytes_done: usize,
+    files_total: usize,
+    files_done: usize,
+}
+
+impl Progress {
+    fn new(format: ProgressFormat, files: &[(String, String)]) -> Self {
+        Progress {
+            format,
+            started: Instant::now(),
+            bytes_total: files.iter().map(|(_, contents)| contents.len()).sum(),
+            bytes_done: 0,
+            files_total: files.len(),
+            files_done: 0,
+        }
+    }
+
+    fn emit(&self, value: serde_json::Value) -> miette::Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", value).into_diagnostic()?;
+        stdout.flush().into_diagnostic()
+    }
+
+    fn status(
+        &self,
+        shell: &mut Shell,
+        status: &str,
+        message: impl std::fmt::Display,
+    ) -> miette::Result<()> {
+        match self.format {
+            ProgressFormat::Human => shell.status(status, message).into_diagnostic(),
+            ProgressFormat::Json => self.emit(json!({
+                "event": "status",
+                "status": status,
+                "message": message.to_string(),
+            })),
+        }
+    }
+
+    fn file_written(&mut self, file: &str, len: usize) -> miette::Result<()> {
+        self.bytes_done += len;
+        self.files_done += 1;
+
+        if self.format != ProgressFormat::Json {
+            return Ok(());
+        }
+
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        let eta_ms = if self.bytes_done == 0 {
+            None
+        } else {
+            let remaining = (self.bytes_total - self.bytes_done) as u64;
+            Some(elapsed * remaining / self.bytes_done as u64)
+        };
+
+        self.emit(json!({
+            "event": "progress",
+            "file": file,
+            "files_done": self.files_done,
+            "files_total": self.files_total,
+            "bytes_done": self.bytes_done,
+            "bytes_total": self.bytes_total,
+            "elapsed_ms": elapsed,
+            "eta_ms": eta_ms,
+        }))
+    }
+
+    fn done(&self) -> miette::Result<()> {
+        match self.format {
+            ProgressFormat::Human => Ok(()),
+            ProgressFormat::Json => self.emit(json!({
+                "event": "done",
+                "files_total": self.files_total,
+                "bytes_total": self.bytes_total,
+                "elapsed_ms": self.started.elapsed().as_millis() as u64,
+            })),
+        }
+    }
+}
 
-    shell
-        .status("Initializing", "TypeScript runtime environment")
-        .into_diagnostic()?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlannedAction {
+    Create,
+    Update,
+    Unchanged,
+    Remove,
+}
+
+impl PlannedAction {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PlannedAction::Create => "create",
+            PlannedAction::Update => "update",
+            PlannedAction::Unchanged => "unchanged",
+            PlannedAction::Remove => "remove",
+        }
+    }
+}
+
+/// Compare the generated bindings against what's currently in `.divvun-rt`.
+fn plan(divvun_rt_path: &Path, files: &[(String, String)]) -> Vec<(PathBuf, PlannedAction)> {
+    let mut actions = files
+        .iter()
+        .map(|(file_name, contents)| {
+            let path = divvun_rt_path.join(file_name);
+            let action = match std::fs::read(&path) {
+                Ok(existing) if existing == contents.as_bytes() => PlannedAction::Unchanged,
+                Ok(_) => PlannedAction::Update,
+                Err(_) => PlannedAction::Create,
+            };
+            (path, action)
+        })
+        .collect::<Vec<_>>();
+
+    // Everything else in .divvun-rt is removed by a real sync.
+    if let Ok(entries) = std::fs::read_dir(divvun_rt_path) {
+        let mut stale = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| !actions.iter().any(|(p, _)| p == path))
+            .collect::<Vec<_>>();
+        stale.sort();
+        actions.extend(stale.into_iter().map(|p| (p, PlannedAction::Remove)));
+    }
+
+    actions
+}
+
+fn dry_run(
+    shell: &mut Shell,
+    divvun_rt_path: &Path,
+    files: &[(String, String)],
+    progress: &Progress,
+) -> miette::Result<()> {
+    for (path, action) in plan(divvun_rt_path, files) {
+        match progress.format {
+            ProgressFormat::Human => {
+                let status = match action {
+                    PlannedAction::Create => "Would create",
+                    PlannedAction::Update => "Would update",
+                    PlannedAction::Unchanged => "Unchanged",
+                    PlannedAction::Remove => "Would remove",
+                };
+                shell.status(status, path.display()).into_diagnostic()?;
+            }
+            ProgressFormat::Json => progress.emit(json!({
+                "event": "plan",
+                "file": path.display().to_string(),
+                "action": action.as_str(),
+            }))?,
+        }
+    }
+
+    progress.done()
+}
+
+pub async fn sync(shell: &mut Shell, args: SyncArgs) -> miette::Result<()> {
+    let cur_dir = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
 
     let divvun_rt_path = cur_dir.join(".divvun-rt");
+    let files = divvun_runtime::ts::generate_files().into_diagnostic()?;
+    let mut progress = Progress::new(args.progress, &files);
+
+    if args.dry_run {
+        return dry_run(shell, &divvun_rt_path, &files, &progress);
+    }
+
+    progress.status(shell, "Initializing", "TypeScript runtime environment")?;
 
     // Remove existing .divvun-rt directory
     match std::fs::remove_dir_all(&divvun_rt_path) {
@@ -25,14 +202,14 @@ pub async fn sync(shell: &mut Shell, args: SyncArgs) -> miette::Result<()> {
         }
     }
 
-    shell
-        .status("Generating", "Divvun Runtime TypeScript bindings")
-        .into_diagnostic()?;
-    divvun_runtime::ts::generate(&divvun_rt_path).into_diagnostic()?;
+    progress.status(shell, "Generating", "Divvun Runtime TypeScript bindings")?;
+    std::fs::create_dir_all(&divvun_rt_path).into_diagnostic()?;
+    for (file_name, contents) in &files {
+        std::fs::write(divvun_rt_path.join(file_name), contents).into_diagnostic()?;
+        progress.file_written(file_name, contents.len())?;
+    }
 
-    shell
-        .status("Checking", "Deno installation")
-        .into_diagnostic()?;
+    progress.status(shell, "Checking", "Deno installation")?;
     let result = std::process::Command::new("deno")
         .args(&["--version"])
         .output();
@@ -40,12 +217,11 @@ pub async fn sync(shell: &mut Shell, args: SyncArgs) -> miette::Result<()> {
     match result {
         Ok(output) if output.status.success() => {
             let version = String::from_utf8_lossy(&output.stdout);
-            shell
-                .status(
-                    "Found",
-                    format!("Deno {}", version.lines().next().unwrap_or("")),
-                )
-                .into_diagnostic()?;
+            progress.status(
+                shell,
+                "Found",
+                format!("Deno {}", version.lines().next().unwrap_or("")),
+            )?;
         }
         _ => {
             return Err(miette::miette!(
@@ -54,5 +230,5 @@ pub async fn sync(shell: &mut Shell, args: SyncArgs) -> miette::Result<()> {
         }
     }
 
-    Ok(())
+    progress.done()
 }
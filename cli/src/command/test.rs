@@ -1,10 +1,21 @@
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::time::Duration;
 
+use divvun_runtime::{
+    bundle::{Bundle, SelfTestOutcome},
+    watch::Snapshot,
+};
 use miette::IntoDiagnostic;
 use walkdir::WalkDir;
 
-use crate::{cli::TestArgs, shell::Shell};
+use crate::{
+    cli::TestArgs,
+    exit::{FailAs, Failure},
+    shell::Shell,
+};
 
 fn collect_ts_files(path: &PathBuf) -> miette::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
@@ -30,12 +41,102 @@ fn collect_ts_files(path: &PathBuf) -> miette::Result<Vec<PathBuf>> {
     Ok(files)
 }
 
-pub async fn test(_shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
+async fn self_test(shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
+    let path = match args.files.as_slice() {
+        [] => std::env::current_dir().into_diagnostic()?,
+        [path] => path.clone(),
+        _ => miette::bail!("--self takes a single bundle or project path"),
+    };
+
+    shell.status("Loading", path.display()).into_diagnostic()?;
+    let bundle = if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
+        Bundle::from_bundle(&path)
+            .await
+            .into_diagnostic()
+            .fail_as(Failure::BundleLoad)?
+    } else {
+        crate::deno_rt::save_ast(&path, "pipeline.json").fail_as(Failure::BundleLoad)?;
+        Bundle::from_path(&path)
+            .await
+            .into_diagnostic()
+            .fail_as(Failure::BundleLoad)?
+    };
+
+    let report = bundle.self_test().await.into_diagnostic()?;
+    if report.cases.is_empty() {
+        miette::bail!(
+            "No self-test cases found. Add `{}/<case>.input` files to the bundle assets.",
+            divvun_runtime::bundle::SELFTEST_DIR
+        );
+    }
+
+    for case in &report.cases {
+        let name = format!("{} ({})", case.name, case.pipeline);
+        match &case.outcome {
+            SelfTestOutcome::Passed => shell.status("Passed", name),
+            SelfTestOutcome::Ran => shell.status("Ran", format!("{} (no expected output)", name)),
+            SelfTestOutcome::Mismatch { expected, actual } => shell.error(format!(
+                "{}: output differs\n--- expected\n{}\n--- actual\n{}",
+                name,
+                expected.trim_end(),
+                actual.trim_end()
+            )),
+            SelfTestOutcome::Failed { error } => shell.error(format!("{}: {}", name, error)),
+            SelfTestOutcome::Nondeterministic { first, second } => shell.error(format!(
+                "{}: output differs between runs\n--- first\n{}\n--- second\n{}",
+                name, first, second
+            )),
+        }
+        .into_diagnostic()?;
+    }
+
+    let failed = report.failures().count();
+    if failed > 0 {
+        miette::bail!(
+            "{} of {} self-test cases failed",
+            failed,
+            report.cases.len()
+        );
+    }
+
+    shell
+        .status(
+            "Finished",
+            format!("{} self-test cases", report.cases.len()),
+        )
+        .into_diagnostic()?;
+    Ok(())
+}
+
+pub async fn test(shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
+    if args.self_test {
+        return self_test(shell, args).await;
+    }
+    if args.clean.is_some() {
+        return super::clean::clean(shell, args).await;
+    }
+
     let exe_path = std::env::current_exe().into_diagnostic()?;
+    if args.watch {
+        return watch(shell, &exe_path, &args).await;
+    }
 
+    let test_files = collect_test_files(&args.files)?;
+    let status = deno_test(&exe_path, &test_files, &args.script_args)
+        .status()
+        .into_diagnostic()?;
+
+    if !status.success() {
+        std::process::exit(status.code().unwrap_or(1));
+    }
+
+    Ok(())
+}
+
+fn collect_test_files(paths: &[PathBuf]) -> miette::Result<Vec<PathBuf>> {
     let mut test_files = Vec::new();
 
-    if args.files.is_empty() {
+    if paths.is_empty() {
         let default_tests_dir = PathBuf::from("tests");
         if default_tests_dir.exists() && default_tests_dir.is_dir() {
             test_files = collect_ts_files(&default_tests_dir)?;
@@ -47,7 +148,7 @@ pub async fn test(_shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
             );
         }
     } else {
-        for path in &args.files {
+        for path in paths {
             let mut collected = collect_ts_files(path)?;
             test_files.append(&mut collected);
         }
@@ -58,7 +159,10 @@ pub async fn test(_shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
     }
 
     test_files.sort();
+    Ok(test_files)
+}
 
+fn deno_test(exe_path: &Path, test_files: &[PathBuf], script_args: &[String]) -> Command {
     let mut cmd = Command::new("deno");
     cmd.arg("test")
         .arg("--hide-stacktraces")
@@ -68,22 +172,254 @@ pub async fn test(_shell: &mut Shell, args: TestArgs) -> miette::Result<()> {
         .arg("--no-check")
         .env("LIB_PATH", exe_path);
 
-    for file in &test_files {
+    for file in test_files {
         cmd.arg(file);
     }
 
-    if !args.script_args.is_empty() {
+    if !script_args.is_empty() {
         cmd.arg("--");
-        for arg in &args.script_args {
+        for arg in script_args {
             cmd.arg(arg);
         }
     }
 
-    let status = cmd.status().into_diagnostic()?;
+    cmd
+}
 
-    if !status.success() {
-        std::process::exit(status.code().unwrap_or(1));
+/// How often `--watch` looks for changed files.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+async fn watch(shell: &mut Shell, exe_path: &Path, args: &TestArgs) -> miette::Result<()> {
+    let project = std::env::current_dir().into_diagnostic()?;
+    let mut roots = match args.files.as_slice() {
+        [] => vec![project.join("tests")],
+        files => files.to_vec(),
+    };
+    roots.push(project.join("pipeline.ts"));
+    roots.push(project.join("assets"));
+
+    let mut results = BTreeMap::new();
+    let test_files = collect_test_files(&args.files)?;
+    run_watched(
+        shell,
+        exe_path,
+        &test_files,
+        &args.script_args,
+        &mut results,
+    )?;
+
+    let mut snapshot = Snapshot::new(roots.clone()).await;
+    loop {
+        shell
+            .status("Watching", "tests, pipeline.ts and assets (Ctrl-C to stop)")
+            .into_diagnostic()?;
+        let (changed, selected) = loop {
+            tokio::time::sleep(POLL_INTERVAL).await;
+            let next = Snapshot::new(roots.clone()).await;
+            let changed = snapshot.changed(&next);
+            snapshot = next;
+            if changed.is_empty() {
+                continue;
+            }
+            match collect_test_files(&args.files) {
+                Ok(test_files) => {
+                    let selected = affected_tests(&changed, &test_files);
+                    if !selected.is_empty() {
+                        break (changed, selected);
+                    }
+                }
+                Err(e) => shell.error(e).into_diagnostic()?,
+            }
+        };
+
+        let pipeline_ts = project.join("pipeline.ts");
+        if changed.contains(&pipeline_ts) {
+            let saved = crate::deno_rt::save_ast(&pipeline_ts, project.join("pipeline.json"));
+            if let Err(e) = saved {
+                shell
+                    .error(format!("pipeline.ts: {}", e))
+                    .into_diagnostic()?;
+                continue;
+            }
+        }
+
+        run_watched(shell, exe_path, &selected, &args.script_args, &mut results)?;
+    }
+}
+
+/// Test files to rerun after `changed`. A changed test reruns itself, and
+/// anything else reruns every test, except for `.ftl` files: messages only
+/// show up in errors' `title` and `description`, so those rerun only the
+/// tests that mention either.
+fn affected_tests(changed: &[PathBuf], test_files: &[PathBuf]) -> Vec<PathBuf> {
+    let is_test = |path: &Path| test_files.iter().any(|x| same_file(x, path));
+    let is_ftl = |path: &Path| path.extension().is_some_and(|x| x == "ftl");
+
+    if changed.iter().any(|x| !is_test(x) && !is_ftl(x)) {
+        return test_files.to_vec();
     }
+    let messages_changed = changed.iter().any(|x| is_ftl(x));
 
+    test_files
+        .iter()
+        .filter(|file| {
+            changed.iter().any(|x| same_file(file, x))
+                || (messages_changed
+                    && std::fs::read_to_string(file)
+                        .map(|source| asserts_messages(&source))
+                        .unwrap_or(true))
+        })
+        .cloned()
+        .collect()
+}
+
+fn asserts_messages(source: &str) -> bool {
+    source.contains("title") || source.contains("description")
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (std::fs::canonicalize(a), std::fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Run some tests and print how their results changed since the last run.
+fn run_watched(
+    shell: &mut Shell,
+    exe_path: &Path,
+    test_files: &[PathBuf],
+    script_args: &[String],
+    results: &mut BTreeMap<String, bool>,
+) -> miette::Result<()> {
+    shell
+        .status("Running", format!("{} test files", test_files.len()))
+        .into_diagnostic()?;
+    let output = deno_test(exe_path, test_files, script_args)
+        .env("NO_COLOR", "1")
+        .output()
+        .into_diagnostic()?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let ran = parse_results(&stdout);
+
+    if ran.is_empty() {
+        // Deno couldn't load the tests, e.g. a syntax error
+        std::io::stdout()
+            .write_all(&output.stdout)
+            .into_diagnostic()?;
+        std::io::stderr()
+            .write_all(&output.stderr)
+            .into_diagnostic()?;
+        shell
+            .status_with_color("Failed", "no test results", termcolor::Color::Red)
+            .into_diagnostic()?;
+        return Ok(());
+    }
+
+    let failed = ran.values().filter(|x| !**x).count();
+    if failed > 0 {
+        let details = stdout.find(" ERRORS ").map_or(&*stdout, |i| &stdout[i..]);
+        print!("{}", details);
+    }
+
+    for (name, passed) in &ran {
+        match (results.insert(name.clone(), *passed), passed) {
+            (Some(false), true) => shell.status_with_color("Fixed", name, termcolor::Color::Green),
+            (Some(true) | None, false) => {
+                shell.status_with_color("Broke", name, termcolor::Color::Red)
+            }
+            _ => Ok(()),
+        }
+        .into_diagnostic()?;
+    }
+
+    let message = format!("{} passed, {} failed", ran.len() - failed, failed);
+    if failed > 0 {
+        shell
+            .status_with_color("Failed", message, termcolor::Color::Red)
+            .into_diagnostic()?;
+    } else {
+        shell.status("Passed", message).into_diagnostic()?;
+    }
     Ok(())
 }
+
+/// Results in `deno test` output, by `<file> > <test>`.
+fn parse_results(stdout: &str) -> BTreeMap<String, bool> {
+    let mut results = BTreeMap::new();
+    let mut file = "";
+    for line in stdout.lines() {
+        if let Some(rest) = line.strip_prefix("running ") {
+            if let Some((_, path)) = rest.split_once(" from ") {
+                file = path;
+            }
+            continue;
+        }
+        // Steps are indented under their test
+        if line.starts_with(char::is_whitespace) {
+            continue;
+        }
+        let Some((name, outcome)) = line.rsplit_once(" ... ") else {
+            continue;
+        };
+        let passed = match outcome.split_whitespace().next() {
+            Some("ok") => true,
+            Some("FAILED") => false,
+            _ => continue,
+        };
+        results.insert(format!("{} > {}", file, name), passed);
+    }
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_deno_test_output() {
+        let stdout = "running 2 tests from ./tests/agr.ts
+subject agreement ... ok (12ms)
+object ... agreement ... FAILED (3ms)
+running 1 test from ./tests/typo.ts
+typos ...
+  in compounds ... ok (1ms)
+typos ... ok (2ms)
+skipped ... ignored (0ms)
+
+ok | 2 passed | 1 failed (40ms)
+";
+        let results = parse_results(stdout);
+        assert_eq!(
+            results.into_iter().collect::<Vec<_>>(),
+            [
+                ("./tests/agr.ts > object ... agreement".to_string(), false),
+                ("./tests/agr.ts > subject agreement".to_string(), true),
+                ("./tests/typo.ts > typos".to_string(), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn message_changes_rerun_message_tests() {
+        let temp = tempfile::tempdir().unwrap();
+        let tests = ["agr.ts", "msg.ts"].map(|x| temp.path().join(x));
+        std::fs::write(&tests[0], "assertEquals(errs.length, 1);").unwrap();
+        std::fs::write(&tests[1], "assertEquals(errs[0].title, \"Agreement\");").unwrap();
+        let asset = |x: &str| temp.path().join("assets").join(x);
+
+        assert_eq!(
+            affected_tests(&[asset("errors-en.ftl")], &tests),
+            [tests[1].clone()]
+        );
+        assert_eq!(
+            affected_tests(&[tests[0].clone()], &tests),
+            [tests[0].clone()]
+        );
+        assert_eq!(affected_tests(&[asset("grammar.bin")], &tests), tests);
+        assert_eq!(
+            affected_tests(&[asset("errors-en.ftl"), tests[0].clone()], &tests),
+            tests
+        );
+    }
+}
@@ -0,0 +1,115 @@
+//! `debug wordlist`: list surface forms a bundle's speller accepts, for
+//! checking its coverage or building a fallback dictionary from the same
+//! bundle.
+
+use std::{io::Write, sync::Arc};
+
+use divvun_runtime::{
+    ast::PipelineBundle,
+    modules::{
+        Context,
+        spell::{WordlistOptions, acceptor_paths, wordlist as list_forms},
+    },
+};
+use miette::IntoDiagnostic;
+
+use crate::{cli::DebugWordlistArgs, shell::Shell};
+
+pub async fn wordlist(shell: &mut Shell, args: DebugWordlistArgs) -> miette::Result<()> {
+    let path = match args.path {
+        Some(path) => path,
+        None => std::env::current_dir().into_diagnostic()?,
+    };
+    let options = WordlistOptions {
+        max_forms: args.max,
+        max_length: args.max_length,
+        pattern: args
+            .pattern
+            .as_deref()
+            .map(regex::Regex::new)
+            .transpose()
+            .map_err(|e| miette::miette!("Invalid --pattern: {}", e))?,
+        ..Default::default()
+    };
+
+    let context = Arc::new(Context::standalone(&path).await.into_diagnostic()?);
+    let acceptor = match args.acceptor {
+        Some(acceptor) => acceptor,
+        None => {
+            if path.is_dir() && path.join("pipeline.ts").exists() {
+                crate::deno_rt::save_ast(&path, "pipeline.json")?;
+            }
+            let bundle = context.load_pipeline_bundle().await.into_diagnostic()?;
+            find_acceptor(shell, &bundle, args.pipeline.as_deref())?
+        }
+    };
+
+    shell.status("Listing", &acceptor).into_diagnostic()?;
+    let list = {
+        let acceptor = acceptor.clone();
+        tokio::task::spawn_blocking(move || list_forms(&context, &acceptor, &options))
+            .await
+            .into_diagnostic()?
+            .into_diagnostic()?
+    };
+
+    let out = shell.out();
+    for form in &list.forms {
+        writeln!(out, "{}", form).into_diagnostic()?;
+    }
+    if !list.complete {
+        shell
+            .note(format!(
+                "{} accepts more; stopped after {} forms",
+                acceptor,
+                list.forms.len()
+            ))
+            .into_diagnostic()?;
+    }
+    Ok(())
+}
+
+/// The acceptor of the named pipeline's speller, or of the first pipeline
+/// having one.
+fn find_acceptor(
+    shell: &mut Shell,
+    bundle: &PipelineBundle,
+    pipeline: Option<&str>,
+) -> miette::Result<String> {
+    let mut paths = match pipeline {
+        Some(name) => {
+            let definition = bundle
+                .get_pipeline(Some(name))
+                .ok_or_else(|| miette::miette!("No pipeline named {}", name))?;
+            acceptor_paths(definition)
+        }
+        None => {
+            let mut paths = Vec::new();
+            let default = bundle.get_pipeline(None).into_iter();
+            for definition in default.chain(bundle.pipelines.values()) {
+                for path in acceptor_paths(definition) {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            }
+            paths
+        }
+    };
+
+    if paths.is_empty() {
+        miette::bail!(
+            "No speller in {}; name an acceptor with --acceptor",
+            pipeline.unwrap_or("the bundle's pipelines")
+        );
+    }
+    if paths.len() > 1 {
+        shell
+            .note(format!(
+                "Several acceptors ({}); listing the first. Pick one with --acceptor",
+                paths.join(", ")
+            ))
+            .into_diagnostic()?;
+    }
+    Ok(paths.remove(0))
+}
@@ -0,0 +1,234 @@
+//! Defaults for CLI flags from `divvun-runtime.toml` files: the user's, in
+//! the platform's config directory, and the project's, in the current
+//! directory or the nearest parent with one. The project's settings win over
+//! the user's, and flags and `DRT_*` variables over both.
+
+use std::path::{Path, PathBuf};
+
+use miette::IntoDiagnostic;
+use serde::Deserialize;
+
+use crate::cli::{Args, Command, DebugArgs, DebugCommand, ReportFormat};
+
+pub const FILE_NAME: &str = "divvun-runtime.toml";
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// `--theme`
+    pub theme: Option<String>,
+    /// `--ui-lang`
+    pub ui_lang: Option<String>,
+    /// `--path` of `run`, `fix`, `hunspell`, `daemon`, `debug compare` and
+    /// `debug wordlist`.
+    pub path: Option<PathBuf>,
+    /// `--pipeline` of `run`, `fix`, `hunspell`, `daemon`, `debug compare` and
+    /// `debug wordlist`.
+    pub pipeline: Option<String>,
+    /// Pipeline config by command key, as given with `-c key=value`. A `-c`
+    /// for the same key replaces it.
+    pub config: serde_json::Map<String, serde_json::Value>,
+    pub run: RunConfig,
+    pub serve: ServeConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RunConfig {
+    /// `run --report`
+    pub report: Option<ReportFormat>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServeConfig {
+    /// `serve --addr`
+    pub addr: Option<String>,
+    /// The bundles file `serve` takes.
+    pub config: Option<PathBuf>,
+}
+
+impl Config {
+    /// The user's and the project's config, whichever exist.
+    pub fn load() -> miette::Result<Config> {
+        let mut config = Config::default();
+        if let Some(path) = user_path().filter(|x| x.is_file()) {
+            config = config.merge(Config::read(&path)?);
+        }
+        let cwd = std::env::current_dir().into_diagnostic()?;
+        if let Some(path) = project_path(&cwd) {
+            config = config.merge(Config::read(&path)?);
+        }
+        Ok(config)
+    }
+
+    /// Read a config file. Paths in it are relative to the file.
+    pub fn read(path: &Path) -> miette::Result<Config> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| miette::miette!("Failed to read {}: {}", path.display(), e))?;
+        let mut config: Config = toml::from_str(&text)
+            .map_err(|e| miette::miette!("Invalid {}: {}", path.display(), e))?;
+
+        let base = path.parent().unwrap_or(Path::new("."));
+        config.path = config.path.map(|x| base.join(x));
+        config.serve.config = config.serve.config.map(|x| base.join(x));
+        Ok(config)
+    }
+
+    /// `other` over `self`.
+    fn merge(mut self, other: Config) -> Config {
+        self.config.extend(other.config);
+        Config {
+            theme: other.theme.or(self.theme),
+            ui_lang: other.ui_lang.or(self.ui_lang),
+            path: other.path.or(self.path),
+            pipeline: other.pipeline.or(self.pipeline),
+            config: self.config,
+            run: RunConfig {
+                report: other.run.report.or(self.run.report),
+            },
+            serve: ServeConfig {
+                addr: other.serve.addr.or(self.serve.addr),
+                config: other.serve.config.or(self.serve.config),
+            },
+        }
+    }
+
+    /// Fill in the flags `args` doesn't have.
+    pub fn apply(&self, args: &mut Args) {
+        fill(&mut args.theme, &self.theme);
+        fill(&mut args.ui_lang, &self.ui_lang);
+
+        match &mut args.command {
+            Some(Command::Run(args)) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+                fill(&mut args.report, &self.run.report);
+                self.prepend_config(&mut args.config);
+            }
+            Some(Command::Fix(args)) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+                self.prepend_config(&mut args.config);
+            }
+            Some(Command::Hunspell(args)) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+                self.prepend_config(&mut args.config);
+            }
+            Some(Command::Daemon(args)) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+            }
+            Some(Command::Debug(DebugArgs::Debug(DebugCommand::Compare(args)))) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+                self.prepend_config(&mut args.config);
+            }
+            Some(Command::Debug(DebugArgs::Debug(DebugCommand::Wordlist(args)))) => {
+                fill(&mut args.path, &self.path);
+                fill(&mut args.pipeline, &self.pipeline);
+            }
+            Some(Command::Serve(args)) => {
+                fill(&mut args.addr, &self.serve.addr);
+                fill(&mut args.config, &self.serve.config);
+            }
+            _ => {}
+        }
+    }
+
+    /// Put the file's config before the `-c` flags, which then replace it
+    /// key by key.
+    fn prepend_config(&self, flags: &mut Vec<String>) {
+        let mut config = self
+            .config
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>();
+        config.append(flags);
+        *flags = config;
+    }
+}
+
+fn fill<T: Clone>(flag: &mut Option<T>, default: &Option<T>) {
+    if flag.is_none() {
+        *flag = default.clone();
+    }
+}
+
+fn user_path() -> Option<PathBuf> {
+    let dirs = pathos::user::AppDirs::new("Divvun Runtime").ok()?;
+    Some(dirs.config_dir().join(FILE_NAME))
+}
+
+/// `divvun-runtime.toml` in `dir` or the nearest parent having one.
+fn project_path(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|x| x.join(FILE_NAME))
+        .find(|x| x.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+
+    #[test]
+    fn flags_win_over_the_project_and_the_project_over_the_user() {
+        let temp = tempfile::tempdir().unwrap();
+        let user = temp.path().join("user.toml");
+        std::fs::write(
+            &user,
+            "theme = \"InspiredGitHub\"\nui_lang = \"nb\"\n[config]\nsuggest = { locales = [\"nb\"] }\n",
+        )
+        .unwrap();
+        let project = temp.path().join("lang-sme").join(FILE_NAME);
+        std::fs::create_dir_all(project.parent().unwrap()).unwrap();
+        std::fs::write(
+            &project,
+            "ui_lang = \"se\"\npath = \"tools/grammarcheckers\"\npipeline = \"grammar\"\n\n[config]\nsuggest = { locales = [\"se\"] }\ntokenize = { x = 1 }\n\n[run]\nreport = \"gcc\"\n",
+        )
+        .unwrap();
+        let sub = project.parent().unwrap().join("docs");
+        std::fs::create_dir_all(&sub).unwrap();
+        assert_eq!(project_path(&sub), Some(project.clone()));
+
+        let config = Config::read(&user)
+            .unwrap()
+            .merge(Config::read(&project).unwrap());
+        let mut args = Args::parse_from([
+            "divvun-runtime",
+            "run",
+            "-P",
+            "speller",
+            "-c",
+            "tokenize={\"x\":2}",
+        ]);
+        config.apply(&mut args);
+
+        assert_eq!(args.theme.as_deref(), Some("InspiredGitHub"));
+        assert_eq!(args.ui_lang.as_deref(), Some("se"));
+        let Some(Command::Run(run)) = args.command else {
+            panic!("expected run");
+        };
+        assert_eq!(
+            run.path,
+            Some(project.parent().unwrap().join("tools/grammarcheckers"))
+        );
+        assert_eq!(run.pipeline.as_deref(), Some("speller"));
+        assert_eq!(run.report, Some(ReportFormat::Gcc));
+        assert_eq!(
+            crate::command::run::parse_config(&run.config).unwrap(),
+            serde_json::json!({ "suggest": { "locales": ["se"] }, "tokenize": { "x": 2 } })
+        );
+    }
+
+    #[test]
+    fn unknown_settings_are_errors() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join(FILE_NAME);
+        std::fs::write(&path, "them = \"x\"\n").unwrap();
+        assert!(Config::read(&path).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 use tempfile::tempdir;
@@ -29,9 +29,35 @@ pub fn dump_ast(input: &str) -> Result<serde_json::Value, Error> {
     }
 
     // Create a wrapper TypeScript file that imports the pipeline and exports the AST
-    let wrapper_content = r#"
+    let wrapper_path = tmp.path().join("wrapper.ts");
+    std::fs::write(&wrapper_path, WRAPPER)?;
+
+    // Execute with Deno
+    let output = Command::new("deno")
+        .args(&["run", "--allow-read"])
+        .arg(&wrapper_path)
+        .current_dir(tmp.path())
+        .output()?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::DenoExecution(format!(
+            "Exit code: {}, stderr: {}",
+            output.status.code().unwrap_or(-1),
+            stderr
+        )));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let json_value: serde_json::Value = serde_json::from_str(&stdout)?;
+
+    Ok(json_value)
+}
+
+/// Imports the pipeline and prints its AST as pipeline.json.
+const WRAPPER: &str = r#"
 import { toKebabCase } from "jsr:@std/text/to-kebab-case";
-import { StringEntry, Ref, _current } from './.divvun-rt/mod.ts';
+import { BytesEntry, StringEntry, Ref, _current } from './.divvun-rt/mod.ts';
 import * as pipelineModule from './pipeline.ts';
 
 const pipelines: { [key: string]: any } = {};
@@ -50,7 +76,8 @@ for (const [exportName, fn] of Object.entries(pipelineModule)) {
     const name = toKebabCase(cleanName);
 
     _current.clear();
-    const entry = new StringEntry();
+    // Pipelines taking binary input say so with `fn.entryType = "bytes"`.
+    const entry = (fn as any).entryType === 'bytes' ? new BytesEntry() : new StringEntry();
     const output = fn(entry);
     const commands: { [key: string]: any } = {};
 
@@ -85,38 +112,128 @@ if (!defaultPipelineName) {
     defaultPipelineName = Object.keys(pipelines)[0];
 }
 
-const result = {
+const result: { [key: string]: any } = {
     version: 1,
     default: defaultPipelineName,
     pipelines
 };
 
+// `export const minRuntimeVersion = "0.5.0"` marks the oldest runtime able to
+// run the bundle.
+const minRuntimeVersion = (pipelineModule as any).minRuntimeVersion;
+if (typeof minRuntimeVersion === 'string') {
+    result.min_runtime_version = minRuntimeVersion;
+}
+
+// `export const remoteAssets = { "voice.onnx": { url, hash, size } }` lists
+// assets downloaded on first use instead of being bundled.
+const remoteAssets = (pipelineModule as any).remoteAssets;
+if (remoteAssets && typeof remoteAssets === 'object') {
+    result.remote_assets = remoteAssets;
+}
+
 console.log(JSON.stringify(result));
 "#;
 
-    let wrapper_path = tmp.path().join("wrapper.ts");
-    std::fs::write(&wrapper_path, wrapper_content)?;
+/// Where generated ASTs are cached, relative to the pipeline's directory.
+pub const AST_CACHE_DIR: &str = ".divvun-runtime/cache";
 
-    // Execute with Deno
-    let output = Command::new("deno")
-        .args(&["run", "--allow-read"])
-        .arg(&wrapper_path)
-        .current_dir(tmp.path())
-        .output()?;
+/// Like [`dump_ast`], but reuses the AST from the last run when neither the
+/// pipeline, the local modules it imports nor the runtime have changed since.
+/// Anything that gets in the way of caching falls back to running Deno.
+pub fn dump_ast_cached(pipeline_path: &Path) -> Result<serde_json::Value, Error> {
+    let input = std::fs::read_to_string(pipeline_path)?;
+    let key = match module_graph_hash(pipeline_path) {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::debug!("Not caching pipeline AST: {}", e);
+            return dump_ast(&input);
+        }
+    };
 
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(Error::DenoExecution(format!(
-            "Exit code: {}, stderr: {}",
-            output.status.code().unwrap_or(-1),
-            stderr
-        )));
+    let cache_dir = pipeline_path
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(AST_CACHE_DIR);
+    let cache_path = cache_dir.join(format!("ast-{}.json", key));
+    if let Ok(cached) = std::fs::read(&cache_path) {
+        match serde_json::from_slice(&cached) {
+            Ok(value) => {
+                tracing::debug!("Using cached pipeline AST {}", cache_path.display());
+                return Ok(value);
+            }
+            Err(e) => tracing::warn!("Ignoring corrupt {}: {}", cache_path.display(), e),
+        }
     }
 
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let json_value: serde_json::Value = serde_json::from_str(&stdout)?;
+    let value = dump_ast(&input)?;
+    // Only the newest AST is worth keeping; the old ones can't match again
+    // until the files they were made from are restored.
+    if let Ok(entries) = std::fs::read_dir(&cache_dir) {
+        for entry in entries.flatten() {
+            if entry.file_name().to_string_lossy().starts_with("ast-") {
+                let _ = std::fs::remove_file(entry.path());
+            }
+        }
+    }
+    let written = std::fs::create_dir_all(&cache_dir)
+        .and_then(|_| std::fs::write(&cache_path, serde_json::to_vec(&value)?));
+    if let Err(e) = written {
+        tracing::warn!("Failed to cache pipeline AST: {}", e);
+    }
+    Ok(value)
+}
 
-    Ok(json_value)
+/// Hash of everything the AST of `pipeline_path` is made from: the runtime
+/// build and wrapper script, then every module in its import graph as
+/// reported by `deno info`. Local modules are hashed by contents, remote ones
+/// by their (versioned) URL.
+fn module_graph_hash(pipeline_path: &Path) -> Result<String, Error> {
+    let output = Command::new("deno")
+        .args(&["info", "--json"])
+        .arg(pipeline_path)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::DenoExecution(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+    let info: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+
+    let mut modules = info
+        .get("modules")
+        .and_then(|x| x.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|module| {
+            let specifier = module.get("specifier")?.as_str()?.to_string();
+            let local = module
+                .get("local")
+                .and_then(|x| x.as_str())
+                .filter(|_| specifier.starts_with("file:"))
+                .map(PathBuf::from);
+            Some((specifier, local))
+        })
+        .collect::<Vec<_>>();
+    if modules.is_empty() {
+        return Err(Error::DenoExecution(
+            "deno info reported no modules".to_string(),
+        ));
+    }
+    modules.sort();
+
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&serde_json::to_vec(&divvun_runtime::VERSION_INFO)?);
+    hasher.update(WRAPPER.as_bytes());
+    for (specifier, local) in modules {
+        hasher.update(specifier.as_bytes());
+        hasher.update(b"\0");
+        if let Some(local) = local {
+            hasher.update(&std::fs::read(local)?);
+            hasher.update(b"\0");
+        }
+    }
+    Ok(hasher.finalize().to_hex().to_string())
 }
 
 pub fn save_ast(path: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(), Error> {
@@ -124,8 +241,7 @@ pub fn save_ast(path: impl AsRef<Path>, output: impl AsRef<Path>) -> Result<(),
     if path.is_dir() {
         path = path.join("pipeline.ts");
     }
-    let input = std::fs::read_to_string(path)?;
-    let res = dump_ast(&input)?;
+    let res = dump_ast_cached(&path)?;
     std::fs::write(output, serde_json::to_string(&res)?)?;
     Ok(())
 }
@@ -0,0 +1,245 @@
+//! Exit codes, so scripts and CI jobs can tell a text with grammar errors
+//! from a broken bundle without parsing messages:
+//!
+//! | Code | Meaning |
+//! |------|---------|
+//! | 0 | Success |
+//! | 1 | `--fail-on-errors` found too many grammar errors |
+//! | 2 | Usage error: bad flags, config or input |
+//! | 3 | The bundle or pipeline couldn't be loaded |
+//! | 4 | The pipeline failed while running |
+//! | 5 | Anything else |
+//!
+//! With `--log-format json`, the failure is also printed to stderr as a
+//! single JSON line instead of a rendered report.
+
+use std::{fmt, process::ExitCode};
+
+use miette::{Diagnostic, LabeledSpan, Report, Severity, SourceCode};
+
+use crate::cli::LogFormat;
+
+/// What kind of failure an error is, which decides the exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Failure {
+    /// `--fail-on-errors` found at least as many grammar errors as allowed.
+    ErrorsFound,
+    /// Bad flags, config or input, or flags that don't fit the pipeline.
+    Usage,
+    /// The bundle or its pipeline couldn't be loaded.
+    BundleLoad,
+    /// The pipeline failed on the input.
+    Pipeline,
+    /// Anything else, such as a file that can't be written.
+    Other,
+}
+
+impl Failure {
+    pub fn code(self) -> u8 {
+        match self {
+            Failure::ErrorsFound => 1,
+            Failure::Usage => 2,
+            Failure::BundleLoad => 3,
+            Failure::Pipeline => 4,
+            Failure::Other => 5,
+        }
+    }
+
+    /// `report` as this kind of failure, unless it already has a kind.
+    pub fn wrap(self, report: Report) -> Report {
+        if report.downcast_ref::<Failed>().is_some() {
+            return report;
+        }
+        Report::new(Failed {
+            failure: self,
+            report,
+        })
+    }
+
+    /// A failure of this kind with `message`.
+    pub fn msg(self, message: impl fmt::Display) -> Report {
+        self.wrap(miette::miette!("{}", message))
+    }
+
+    /// The kind of failure `report` is.
+    pub fn of(report: &Report) -> Failure {
+        report
+            .downcast_ref::<Failed>()
+            .map(|x| x.failure)
+            .unwrap_or(Failure::Other)
+    }
+}
+
+pub trait FailAs<T> {
+    /// Count an error as `failure` for the exit code.
+    fn fail_as(self, failure: Failure) -> miette::Result<T>;
+}
+
+impl<T> FailAs<T> for miette::Result<T> {
+    fn fail_as(self, failure: Failure) -> miette::Result<T> {
+        self.map_err(|report| failure.wrap(report))
+    }
+}
+
+/// An error with the [`Failure`] it counts as. It shows as the error it
+/// wraps.
+#[derive(Debug)]
+struct Failed {
+    failure: Failure,
+    report: Report,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.report, f)
+    }
+}
+
+impl std::error::Error for Failed {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.report.source()
+    }
+}
+
+impl Diagnostic for Failed {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report.code()
+    }
+
+    fn severity(&self) -> Option<Severity> {
+        self.report.severity()
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report.help()
+    }
+
+    fn url<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        self.report.url()
+    }
+
+    fn source_code(&self) -> Option<&dyn SourceCode> {
+        self.report.source_code()
+    }
+
+    fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+        self.report.labels()
+    }
+
+    fn related<'a>(&'a self) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+        self.report.related()
+    }
+
+    fn diagnostic_source(&self) -> Option<&dyn Diagnostic> {
+        self.report.diagnostic_source()
+    }
+}
+
+/// Grammar errors in one output value of a pipeline: the `errors` of a
+/// `divvun::suggest` result, or of each one in an array.
+pub fn count_errors(value: &serde_json::Value) -> usize {
+    match value {
+        serde_json::Value::Object(output) => output
+            .get("errors")
+            .and_then(|x| x.as_array())
+            .map_or(0, |x| x.len()),
+        serde_json::Value::Array(values) => values.iter().map(count_errors).sum(),
+        _ => 0,
+    }
+}
+
+/// The error for `--fail-on-errors=threshold` after `found` errors, if it
+/// fails.
+pub fn check_errors(found: usize, threshold: Option<usize>) -> miette::Result<()> {
+    match threshold {
+        Some(threshold) if found >= threshold => Err(Failure::ErrorsFound.msg(format!(
+            "found {} grammar errors (--fail-on-errors={})",
+            found, threshold
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Print how the CLI failed, if it did, and the exit code for it.
+pub fn finish(result: miette::Result<()>, log_format: LogFormat) -> ExitCode {
+    let Err(report) = result else {
+        return ExitCode::SUCCESS;
+    };
+    let failure = Failure::of(&report);
+    match log_format {
+        LogFormat::Text => eprintln!("Error: {:?}", report),
+        LogFormat::Json => eprintln!("{}", failure_json(&report, failure)),
+    }
+    ExitCode::from(failure.code())
+}
+
+/// A usage error clap found before the flags were parsed.
+pub fn usage_json(message: &str) -> serde_json::Value {
+    serde_json::json!({
+        "level": "ERROR",
+        "kind": Failure::Usage,
+        "exit_code": Failure::Usage.code(),
+        "message": message.trim(),
+    })
+}
+
+fn failure_json(report: &Report, failure: Failure) -> serde_json::Value {
+    let mut json = serde_json::json!({
+        "level": "ERROR",
+        "kind": failure,
+        "exit_code": failure.code(),
+        "message": report.to_string(),
+    });
+    let causes = report
+        .chain()
+        .skip(1)
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
+    if !causes.is_empty() {
+        json["causes"] = causes.into();
+    }
+    if let Some(code) = report.code() {
+        json["code"] = code.to_string().into();
+    }
+    json
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failures_keep_their_first_kind() {
+        let report = Failure::BundleLoad.msg("no pipeline.json");
+        assert_eq!(Failure::of(&report), Failure::BundleLoad);
+        assert_eq!(report.to_string(), "no pipeline.json");
+
+        let report = Failure::Pipeline.wrap(report);
+        assert_eq!(Failure::of(&report), Failure::BundleLoad);
+        assert_eq!(Failure::of(&miette::miette!("disk full")), Failure::Other);
+
+        let json = failure_json(&report, Failure::of(&report));
+        assert_eq!(json["kind"], "bundle_load");
+        assert_eq!(json["exit_code"], 3);
+        assert_eq!(json["message"], "no pipeline.json");
+        assert!(!json.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn errors_are_counted_in_suggest_output() {
+        let output = serde_json::json!({
+            "text": "Mun lean boahtan.",
+            "errors": [{ "error_id": "typo" }, { "error_id": "typo" }],
+            "encoding": "utf-8"
+        });
+        assert_eq!(count_errors(&output), 2);
+        assert_eq!(count_errors(&serde_json::json!([output, output])), 4);
+        assert_eq!(count_errors(&"NAEL".into()), 0);
+
+        assert!(check_errors(2, None).is_ok());
+        assert!(check_errors(1, Some(2)).is_ok());
+        let report = check_errors(2, Some(2)).unwrap_err();
+        assert_eq!(Failure::of(&report), Failure::ErrorsFound);
+    }
+}
@@ -0,0 +1,147 @@
+//! Localized CLI and REPL messages. The FTL files in `cli/i18n/` are compiled
+//! in; the language is `--ui-lang`, or else the system locale, falling back to
+//! English for anything a translation lacks.
+
+use divvun_runtime::util::fluent_loader::FluentLoader;
+use fluent_bundle::FluentArgs;
+use once_cell::sync::{Lazy, OnceCell};
+
+const SOURCES: &[(&str, &str)] = &[
+    ("cli-en.ftl", include_str!("../i18n/cli-en.ftl")),
+    ("cli-nb.ftl", include_str!("../i18n/cli-nb.ftl")),
+    ("cli-se.ftl", include_str!("../i18n/cli-se.ftl")),
+];
+
+/// The compiled-in messages, or why they don't parse. Until [`init`] reports
+/// that, messages show as their IDs.
+static LOADER: Lazy<Result<FluentLoader, String>> = Lazy::new(|| {
+    FluentLoader::from_sources(SOURCES.iter().copied(), "en").map_err(|e| e.to_string())
+});
+
+static LOCALES: OnceCell<Vec<String>> = OnceCell::new();
+
+/// Pick the UI language: `ui_lang` (comma-separated, in order of preference)
+/// or the system locale. Messages shown before this use the system locale.
+/// Fails if the compiled-in messages don't parse.
+pub fn init(ui_lang: Option<&str>) -> miette::Result<()> {
+    let locales = match ui_lang {
+        Some(x) => locales(x.split(',')),
+        None => system_locales(),
+    };
+    let _ = LOCALES.set(locales);
+    match &*LOADER {
+        Ok(_) => Ok(()),
+        Err(e) => Err(miette::miette!("invalid CLI messages: {}", e)),
+    }
+}
+
+/// The message `id`, formatted with `args`. See `t!`.
+pub fn message(id: &str, args: Option<&FluentArgs>) -> String {
+    let locales = LOCALES.get_or_init(system_locales);
+    let locales = locales.iter().map(String::as_str).collect::<Vec<_>>();
+    LOADER
+        .as_ref()
+        .ok()
+        .and_then(|loader| loader.get_message_localized(&locales, id, args))
+        .map(|(message, _)| message)
+        .unwrap_or_else(|| id.to_string())
+}
+
+/// `LC_ALL`, `LC_MESSAGES` and `LANG` as the C library reads them, and the
+/// GNU `LANGUAGE` list.
+fn system_locales() -> Vec<String> {
+    let var = |name| std::env::var(name).ok().filter(|x| !x.is_empty());
+    let mut tags = Vec::new();
+    if let Some(language) = var("LANGUAGE") {
+        tags.extend(language.split(':').map(str::to_string));
+    }
+    if let Some(locale) = var("LC_ALL")
+        .or_else(|| var("LC_MESSAGES"))
+        .or_else(|| var("LANG"))
+    {
+        tags.push(locale);
+    }
+    locales(tags.iter().map(String::as_str))
+}
+
+/// `se_NO.UTF-8` style POSIX locales and BCP 47 tags as the languages to try,
+/// each followed by its base language (`se-NO`, `se`).
+fn locales<'a>(tags: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut locales = Vec::new();
+    for tag in tags {
+        let tag = tag.split(['.', '@']).next().unwrap_or_default().trim();
+        if tag.is_empty() || tag == "C" || tag == "POSIX" {
+            continue;
+        }
+        let tag = tag.replace('_', "-");
+        let base = tag.split('-').next().unwrap_or_default().to_string();
+        for locale in [tag, base] {
+            if !locales.contains(&locale) {
+                locales.push(locale);
+            }
+        }
+    }
+    locales
+}
+
+/// A localized message: `t!("repl-saved")`, or with arguments,
+/// `t!("snippet-not-found", name = name)`.
+macro_rules! t {
+    ($id:literal) => {
+        $crate::i18n::message($id, None)
+    };
+    ($id:literal, $($name:ident = $value:expr),+ $(,)?) => {{
+        let mut args = fluent_bundle::FluentArgs::new();
+        $(args.set(stringify!($name), $value.to_string());)+
+        $crate::i18n::message($id, Some(&args))
+    }};
+}
+pub(crate) use t;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_ids(source: &str) -> Vec<&str> {
+        source
+            .lines()
+            .filter(|x| x.starts_with(|c: char| c.is_ascii_lowercase()))
+            .filter_map(|x| x.split_once(" =").map(|(id, _)| id))
+            .collect()
+    }
+
+    #[test]
+    fn translations_have_every_message() {
+        let english = message_ids(SOURCES[0].1);
+        assert!(english.contains(&"shell-error"));
+        for (name, source) in &SOURCES[1..] {
+            assert_eq!(message_ids(source), english, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reads_posix_locales_and_language_tags() {
+        assert_eq!(
+            locales(["se_NO.UTF-8", "nb-NO", "C", "se"].into_iter()),
+            ["se-NO", "se", "nb-NO", "nb"]
+        );
+    }
+
+    #[test]
+    fn formats_messages_in_the_chosen_language() {
+        let mut args = FluentArgs::new();
+        args.set("name", "giella");
+        let message = |locale| {
+            LOADER
+                .as_ref()
+                .unwrap()
+                .get_message_localized(&[locale], "snippet-not-found", Some(&args))
+                .unwrap()
+                .0
+        };
+        assert_eq!(message("en"), "Snippet 'giella' not found");
+        assert_eq!(message("se"), "Bihttá 'giella' ii gávdno");
+        // Unknown languages fall back to English
+        assert_eq!(message("fi"), "Snippet 'giella' not found");
+    }
+}
@@ -1,27 +1,84 @@
-use std::io::IsTerminal;
+use std::{io::IsTerminal, process::ExitCode};
 
 use clap::Parser;
-use cli::{Args, Command, DebugArgs};
+use cli::{Command, DebugArgs, DebugCommand};
 use command::{
+    bisect::bisect,
     bundle::bundle,
+    cg3::cg3,
+    compare::compare,
+    daemon::daemon,
+    exec::exec,
+    fix::fix,
+    hunspell::hunspell,
     init::init,
+    inspect::inspect,
     list::list,
     playground::playground,
     run::{dump_ast, run},
+    serve::serve,
     sync::sync,
     test::test,
+    wordlist::wordlist,
 };
+use exit::{FailAs, Failure};
+use miette::IntoDiagnostic;
 use shell::Shell;
 
 mod cli;
 mod command;
+mod config;
 mod deno_rt;
+mod exit;
+mod i18n;
+mod logging;
 mod shell;
 
-pub async fn run_cli() -> miette::Result<()> {
+pub use cli::Args;
+
+/// Parse the command line, or report why it doesn't parse and return the
+/// exit code for that.
+pub fn parse_args() -> Result<Args, ExitCode> {
+    match Args::try_parse() {
+        Ok(args) => Ok(args),
+        Err(e) if e.use_stderr() && logging::json_requested() => {
+            eprintln!("{}", exit::usage_json(&e.to_string()));
+            Err(ExitCode::from(Failure::Usage.code()))
+        }
+        Err(e) => e.exit(),
+    }
+}
+
+/// Run the command line, returning the exit code. See [`exit`] for what
+/// each code means.
+pub async fn run_cli(args: Args) -> ExitCode {
+    let log_format = args.log_format;
+    logging::init(log_format);
+    exit::finish(run_command(args).await, log_format)
+}
+
+async fn run_command(mut args: Args) -> miette::Result<()> {
     let mut shell = Shell::new();
 
-    let args = Args::parse();
+    config::Config::load()
+        .fail_as(Failure::Usage)?
+        .apply(&mut args);
+    i18n::init(args.ui_lang.as_deref())?;
+
+    if args.deterministic {
+        divvun_runtime::util::deterministic::enable();
+    }
+
+    if args.log_input {
+        divvun_runtime::util::privacy::set_enabled(false);
+    }
+
+    if args.version > 0 && args.json {
+        let json =
+            serde_json::to_string_pretty(&divvun_runtime::version_json()).into_diagnostic()?;
+        println!("{}", json);
+        std::process::exit(0);
+    }
 
     if args.version > 0 {
         divvun_runtime::print_version(args.version > 1);
@@ -52,7 +109,7 @@ pub async fn run_cli() -> miette::Result<()> {
     shell.set_theme(theme);
 
     let Some(command) = args.command else {
-        return Err(miette::miette!("No command specified"));
+        return Err(Failure::Usage.msg(i18n::t!("no-command")));
     };
 
     match command {
@@ -61,12 +118,22 @@ pub async fn run_cli() -> miette::Result<()> {
         Command::Sync(args) => sync(&mut shell, args).await?,
         Command::Bundle(args) => bundle(&mut shell, args).await?,
         Command::List(args) => list(&mut shell, args).await?,
+        Command::Inspect(args) => inspect(&mut shell, args).await?,
+        Command::Exec(args) => exec(&mut shell, args).await?,
         Command::Playground(args) => playground(&mut shell, args)?,
         Command::Test(args) => test(&mut shell, args).await?,
+        Command::Fix(args) => fix(&mut shell, args).await?,
+        Command::Serve(args) => serve(&mut shell, args).await?,
+        Command::Hunspell(args) => hunspell(&mut shell, args).await?,
+        Command::Daemon(args) => daemon(&mut shell, args).await?,
         Command::Debug(args) => match args {
             DebugArgs::DumpAst(args) => {
                 dump_ast(&mut shell, args)?;
             }
+            DebugArgs::Debug(DebugCommand::Bisect(args)) => bisect(&mut shell, args).await?,
+            DebugArgs::Debug(DebugCommand::Cg3(args)) => cg3(&mut shell, args)?,
+            DebugArgs::Debug(DebugCommand::Compare(args)) => compare(&mut shell, args).await?,
+            DebugArgs::Debug(DebugCommand::Wordlist(args)) => wordlist(&mut shell, args).await?,
         },
     }
 
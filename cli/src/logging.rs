@@ -0,0 +1,99 @@
+//! Logs on stderr, as text or with `--log-format json` as one JSON object
+//! per line.
+
+use std::io::IsTerminal;
+
+use tracing::{
+    Event, Subscriber,
+    field::{Field, Visit},
+};
+use tracing_subscriber::{
+    Layer,
+    fmt::{FmtContext, FormatEvent, FormatFields, format::Writer},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
+};
+
+use crate::cli::LogFormat;
+
+pub fn init(format: LogFormat) {
+    // Diagnostic logs go to stderr (not stdout) so they never pollute piped
+    // output, and only use ANSI colour when stderr is a terminal (#39).
+    // RUST_LOG keeps overriding the default `info` level as before.
+    let filter = std::env::var("RUST_LOG")
+        .map(tracing_subscriber::EnvFilter::new)
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
+    let logs = tracing_subscriber::fmt::layer().with_writer(std::io::stderr);
+    let logs = match format {
+        LogFormat::Text => logs.with_ansi(std::io::stderr().is_terminal()).boxed(),
+        LogFormat::Json => logs.event_format(JsonLines).boxed(),
+    };
+    // Privacy mode is only known once the flags are applied, so the filter
+    // asks for each event.
+    let privacy = tracing_subscriber::filter::filter_fn(divvun_runtime::util::privacy::allows);
+    tracing_subscriber::registry()
+        .with(logs.with_filter(filter).with_filter(privacy))
+        .init();
+}
+
+/// `--log-format json` was given, for failures before the flags are parsed.
+pub fn json_requested() -> bool {
+    let mut args = std::env::args_os();
+    while let Some(arg) = args.next() {
+        if arg == "--log-format=json" {
+            return true;
+        }
+        if arg == "--log-format" {
+            return args.next().is_some_and(|x| x == "json");
+        }
+    }
+    std::env::var("DRT_LOG_FORMAT").is_ok_and(|x| x == "json")
+}
+
+/// Each event as a line of `{"level", "target", "message", ...fields}`.
+struct JsonLines;
+
+impl<S, N> FormatEvent<S, N> for JsonLines
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        _ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let mut line = serde_json::Map::new();
+        line.insert("level".into(), event.metadata().level().as_str().into());
+        line.insert("target".into(), event.metadata().target().into());
+        event.record(&mut Fields(&mut line));
+        writeln!(writer, "{}", serde_json::Value::Object(line))
+    }
+}
+
+struct Fields<'a>(&'a mut serde_json::Map<String, serde_json::Value>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.0
+            .insert(field.name().into(), format!("{:?}", value).into());
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().into(), value.into());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().into(), value.into());
+    }
+}
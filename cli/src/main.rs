@@ -1,6 +1,6 @@
-use std::io::IsTerminal;
+use std::process::ExitCode;
 
-fn main() -> miette::Result<()> {
+fn main() -> miette::Result<ExitCode> {
     miette::set_hook(Box::new(|_| {
         Box::new(
             miette::MietteHandlerOpts::new()
@@ -10,19 +10,22 @@ fn main() -> miette::Result<()> {
         )
     }))?;
 
-    // Diagnostic logs go to stderr (not stdout) so they never pollute piped
-    // output, and only use ANSI colour when stderr is a terminal (#39).
-    // RUST_LOG keeps overriding the default `info` level as before.
-    let filter = std::env::var("RUST_LOG")
-        .map(tracing_subscriber::EnvFilter::new)
-        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"));
-    tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(std::io::stderr)
-        .with_ansi(std::io::stderr().is_terminal())
-        .init();
+    let args = match divvun_runtime_cli::parse_args() {
+        Ok(args) => args,
+        Err(code) => return Ok(code),
+    };
 
-    tokio::runtime::Runtime::new()
-        .map_err(|e| miette::miette!("Failed to create tokio runtime: {}", e))?
-        .block_on(divvun_runtime_cli::run_cli())
+    // --deterministic runs every stage on one thread, which has to be decided
+    // before the runtime is built.
+    let runtime = if args.deterministic {
+        tokio::runtime::Builder::new_current_thread()
+            .max_blocking_threads(1)
+            .enable_all()
+            .build()
+    } else {
+        tokio::runtime::Runtime::new()
+    };
+
+    let runtime = runtime.map_err(|e| miette::miette!("Failed to create tokio runtime: {}", e))?;
+    Ok(runtime.block_on(divvun_runtime_cli::run_cli(args)))
 }
@@ -6,6 +6,8 @@ use std::io::{IsTerminal as _, prelude::*};
 use termcolor::Color::{Cyan, Green, Red, Yellow};
 use termcolor::{self, Color, ColorSpec, StandardStream, WriteColor};
 
+use crate::i18n::t;
+
 pub enum TtyWidth {
     NoTty,
     Known(usize),
@@ -275,7 +277,7 @@ impl Shell {
             self.err_erase_line();
         }
         self.output
-            .message_stderr(&"Error:", Some(&message), Red, false)
+            .message_stderr(&t!("shell-error"), Some(&message), Red, false)
     }
 
     /// Prints a yellow 'warning' message.
@@ -284,7 +286,7 @@ impl Shell {
             self.err_erase_line();
         }
         self.output
-            .message_stderr(&"Warning:", Some(&message), Yellow, false)
+            .message_stderr(&t!("shell-warning"), Some(&message), Yellow, false)
     }
 
     /// Prints a cyan 'note' message.
@@ -293,7 +295,7 @@ impl Shell {
             self.err_erase_line();
         }
         self.output
-            .message_stderr(&"Note:", Some(&message), Cyan, false)
+            .message_stderr(&t!("shell-note"), Some(&message), Cyan, false)
     }
 
     /// Updates the verbosity of the shell.
@@ -0,0 +1,129 @@
+//! `__module__` commands. Generated by `divvun-runtime init --module`; see
+//! docs/docs/modules/__module__.md for what's left to do.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use divvun_runtime_macros::rt_command;
+
+use crate::ast;
+
+use super::{CommandRunner, Context, Error, PipelineValue, PipelineValues};
+
+/// Replaces each word found in a tab-separated word list (`from<TAB>to` per
+/// line) with its replacement. Stands in for the real work of the command.
+#[derive(facet::Facet)]
+pub struct __Command__ {
+    #[facet(opaque)]
+    replacements: HashMap<String, String>,
+}
+
+#[rt_command(
+    module = "__module__",
+    name = "__command__",
+    input = [String],
+    output = "String",
+    args = [model_path = "Path"]
+)]
+impl __Command__ {
+    pub async fn new(
+        context: Arc<Context>,
+        mut kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        let model_path = kwargs
+            .remove("model_path")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_string())
+            .ok_or_else(|| {
+                Error::msg("model_path missing").at("pipeline.json", "/args/model_path")
+            })?;
+        let model = context.load_file(&model_path).await?;
+        let model = String::from_utf8(model)
+            .map_err(|e| Error::wrap(e).at_file(model_path.clone()))?;
+
+        Ok(Arc::new(Self {
+            replacements: parse_replacements(&model),
+        }))
+    }
+}
+
+fn parse_replacements(text: &str) -> HashMap<String, String> {
+    text.lines()
+        .filter(|x| !x.trim().is_empty() && !x.starts_with('#'))
+        .filter_map(|x| x.split_once('\t'))
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect()
+}
+
+#[async_trait]
+impl CommandRunner for __Command__ {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        _config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let input = input.try_into_string()?;
+        let output = input
+            .split(' ')
+            .map(|word| {
+                self.replacements
+                    .get(word)
+                    .map(String::as_str)
+                    .unwrap_or(word)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        Ok(output.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "__module__::__command__"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn run(input: &str) -> String {
+        let fixture = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/__module__");
+        let context = Arc::new(
+            Context::standalone(std::path::Path::new(fixture))
+                .await
+                .unwrap(),
+        );
+        let args = HashMap::from([(
+            "model_path".to_string(),
+            ast::Arg::path("__module__.tsv"),
+        )]);
+        let output = crate::modules::run_single(
+            context,
+            "__module__",
+            "__command__",
+            args,
+            PipelineValue::String(input.to_string()),
+            serde_json::Value::Null,
+        )
+        .await
+        .unwrap();
+        output.into_iter().next().unwrap().try_into_string().unwrap()
+    }
+
+    #[tokio::test]
+    async fn replaces_listed_words() {
+        assert_eq!(run("mun lean dáppe").await, "mun lean deike");
+    }
+
+    #[tokio::test]
+    async fn passes_blank_input_through() {
+        assert_eq!(run("").await, "");
+        assert_eq!(run(" \n").await, " \n");
+    }
+
+    #[test]
+    fn skips_comments_and_malformed_lines() {
+        let replacements = parse_replacements("# from\tto\ndáppe\tdeike\nbroken\n\n");
+        assert_eq!(replacements.len(), 1);
+        assert_eq!(replacements["dáppe"], "deike");
+    }
+}
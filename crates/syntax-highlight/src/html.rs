@@ -5,7 +5,15 @@ use syntect::util::LinesWithEndings;
 use crate::{get_syntax_and_theme, get_syntax_set};
 
 pub fn highlight_to_html(content: &str, syntax_name: &str) -> String {
-    let Some((syntax, theme)) = get_syntax_and_theme(syntax_name, None) else {
+    highlight_to_html_with_theme(content, syntax_name, None)
+}
+
+pub fn highlight_to_html_with_theme(
+    content: &str,
+    syntax_name: &str,
+    theme_name: Option<&str>,
+) -> String {
+    let Some((syntax, theme)) = get_syntax_and_theme(syntax_name, theme_name) else {
         return html_escape::encode_text(content).to_string();
     };
 
@@ -9,7 +9,7 @@ mod html;
 mod terminal;
 
 #[cfg(feature = "html")]
-pub use html::highlight_to_html;
+pub use html::{highlight_to_html, highlight_to_html_with_theme};
 #[cfg(feature = "terminal")]
 pub use terminal::{highlight_to_terminal, highlight_to_terminal_with_theme, supports_color};
 
@@ -13,7 +13,8 @@ use unsynn::*;
 ///     name = "blanktag",
 ///     input = [String],
 ///     output = "String",
-///     args(model_path = "Path")
+///     args(model_path = "Path"),
+///     resources = [memory = "64M", gpu = "optional"]
 /// )]
 /// impl Blanktag {
 ///     // implementation...
@@ -163,6 +164,29 @@ fn expand_divvun_command(
         quote! { None }
     };
 
+    // Environment variables the command passes to its library, see `declared_env`
+    let env_tokens: Vec<TokenStream2> = attrs
+        .env
+        .iter()
+        .map(|(name, value)| quote! { (#name, #value) })
+        .collect();
+
+    // Approximate resources the command needs, for preflight checks
+    let memory = attrs.resources.memory;
+    let disk = attrs.resources.disk;
+    let gpu_token = match attrs.resources.gpu.as_deref() {
+        None => quote! { crate::modules::Gpu::None },
+        Some("optional") => quote! { crate::modules::Gpu::Optional },
+        Some("required") => quote! { crate::modules::Gpu::Required },
+        Some(other) => {
+            return Err(format!(
+                "Unknown gpu requirement: {} (expected \"optional\" or \"required\")",
+                other
+            )
+            .into());
+        }
+    };
+
     // Generate config_shape token
     let config_shape_token = if let Some(ref config_str) = attrs.config {
         let config_ident = syn::Ident::new(config_str, proc_macro2::Span::call_site());
@@ -182,6 +206,12 @@ fn expand_divvun_command(
             input: &[#(#input_ty_tokens),*],
             args: &[#(#args_tokens),*],
             assets: &[#(#assets_tokens),*],
+            env: &[#(#env_tokens),*],
+            resources: crate::modules::Resources {
+                memory: #memory,
+                disk: #disk,
+                gpu: #gpu_token,
+            },
             init: |ctx, kwargs| ::std::boxed::Box::pin(#impl_type::new(ctx, kwargs)),
             returns: #output_ty_token,
             kind: #kind_token,
@@ -212,6 +242,15 @@ struct CommandAttrs {
     kind: Option<String>,
     schema: Option<String>,
     config: Option<String>,
+    env: Vec<(String, String)>, // name, value
+    resources: ResourceAttrs,
+}
+
+#[derive(Debug, Default)]
+struct ResourceAttrs {
+    memory: u64,
+    disk: u64,
+    gpu: Option<String>,
 }
 
 #[derive(Debug)]
@@ -232,6 +271,8 @@ fn parse_command_attributes(token_iter: &mut TokenIter) -> unsynn::Result<Comman
     let mut kind = None;
     let mut schema = None;
     let mut config = None;
+    let mut env = Vec::new();
+    let mut resources = ResourceAttrs::default();
 
     // Parse comma-separated attribute items
     loop {
@@ -277,6 +318,59 @@ fn parse_command_attributes(token_iter: &mut TokenIter) -> unsynn::Result<Comman
                 let lit: LiteralString = token_iter.parse()?;
                 config = Some(lit.as_str().to_string());
             }
+            "env" => {
+                // Environment variables as "NAME=value" strings
+                let group: BracketGroupContaining<CommaDelimitedVec<LiteralString>> =
+                    token_iter.parse()?;
+                for delimited_item in group.content.iter() {
+                    let var = delimited_item.value.as_str();
+                    match var.split_once('=') {
+                        Some((name, value)) if !name.is_empty() => {
+                            env.push((name.to_string(), value.to_string()));
+                        }
+                        _ => {
+                            return Error::other(
+                                None,
+                                token_iter,
+                                format!("env entry must be \"NAME=value\", got {:?}", var),
+                            );
+                        }
+                    }
+                }
+            }
+            "resources" => {
+                // Sizes as "64M", "1.5G" or plain bytes; gpu as "optional" or "required"
+                let group: BracketGroupContaining<CommaDelimitedVec<ResourcePair>> =
+                    token_iter.parse()?;
+                for delimited_item in group.content.iter() {
+                    let pair = &delimited_item.value;
+                    let value = pair.value.as_str();
+                    match pair.name.to_string().as_str() {
+                        "memory" | "disk" => {
+                            let Some(bytes) = parse_size(value) else {
+                                return Error::other(
+                                    None,
+                                    token_iter,
+                                    format!("Invalid size {:?}, expected e.g. \"64M\"", value),
+                                );
+                            };
+                            if pair.name.to_string() == "memory" {
+                                resources.memory = bytes;
+                            } else {
+                                resources.disk = bytes;
+                            }
+                        }
+                        "gpu" => resources.gpu = Some(value.to_string()),
+                        other => {
+                            return Error::other(
+                                None,
+                                token_iter,
+                                format!("Unknown resource: {}", other),
+                            );
+                        }
+                    }
+                }
+            }
             "args" => {
                 // For args, we expect brackets containing comma-delimited arg definitions
                 let group: BracketGroupContaining<CommaDelimitedVec<ArgDefPair>> =
@@ -369,9 +463,29 @@ fn parse_command_attributes(token_iter: &mut TokenIter) -> unsynn::Result<Comman
         kind,
         schema,
         config,
+        env,
+        resources,
     })
 }
 
+/// Bytes in a size like `"512"`, `"64K"`, `"300M"` or `"1.5G"`.
+fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, unit) = match size.find(|c: char| c.is_ascii_alphabetic()) {
+        Some(i) => size.split_at(i),
+        None => (size, ""),
+    };
+    let scale = match unit.to_ascii_uppercase().trim_end_matches('B') {
+        "" => 1u64,
+        "K" => 1 << 10,
+        "M" => 1 << 20,
+        "G" => 1 << 30,
+        _ => return None,
+    };
+    let number: f64 = number.trim().parse().ok()?;
+    (number >= 0.0).then(|| (number * scale as f64) as u64)
+}
+
 // Define custom parser for arg definitions
 unsynn! {
     struct ArgDefPair {
@@ -382,6 +496,15 @@ unsynn! {
     }
 }
 
+// Define custom parser for resource requirements like memory = "64M"
+unsynn! {
+    struct ResourcePair {
+        name: Ident,
+        eq: Operator<'='>,
+        value: LiteralString,
+    }
+}
+
 // Define custom parser for asset function calls like required("file") or optional(r"pattern")
 unsynn! {
     struct AssetFuncCall {
@@ -555,7 +678,10 @@ fn map_rust_type_to_ts(rust_type: &str) -> String {
                 "any[]".to_string()
             }
         }
-        ty if ty.starts_with("HashMap <") || ty.starts_with("HashMap<") => {
+        ty if ["HashMap", "IndexMap", "BTreeMap"].iter().any(|map| {
+            ty.starts_with(&format!("{} <", map)) || ty.starts_with(&format!("{}<", map))
+        }) =>
+        {
             // Extract key and value types from HashMap<K, V>
             if let Some(start) = ty.find('<') {
                 if let Some(end) = ty.rfind('>') {
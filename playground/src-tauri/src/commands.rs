@@ -1,14 +1,23 @@
+use crate::recording::{RecordedBundle, RecordedStep, RecordingInfo, RunRecording};
+use crate::settings::{RecentBundle, Settings};
 use crate::state::PlaygroundState;
 use crate::syntax;
 use divvun_runtime::{
-    ast::Command,
-    bundle::Bundle,
+    ast::{Command, StepHeader},
+    bundle::{Bundle, BundleOptions},
     modules::{
         PipelineEvent, PipelineValue,
         divvun::{GrammarErr, GrammarOutput},
     },
     ts::MODULES,
-    util::fluent_loader::FluentLoader,
+    util::{
+        breakpoint::Breakpoint,
+        download::{DownloadCallback, DownloadProgress},
+        fluent_loader::FluentLoader,
+        integrity::Checksums,
+        manifest::BUILD_MANIFEST_FILE,
+        recorder::PipelineRun,
+    },
 };
 use fluent_bundle::FluentArgs;
 use fluent_syntax::ast::{Expression, InlineExpression, PatternElement};
@@ -18,6 +27,7 @@ use std::collections::{HashMap, HashSet};
 use std::io::Read as _;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 use tauri::{AppHandle, Emitter, State};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,6 +42,15 @@ pub struct BundleInfo {
     pub output: RefInfo,
 }
 
+/// Progress of a remote asset downloading while a tab loads its bundle.
+#[derive(Debug, Clone, Serialize)]
+pub struct AssetDownloadEvent {
+    pub window_id: String,
+    pub tab_id: String,
+    #[serde(flatten)]
+    pub progress: DownloadProgress,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EntryInfo {
     pub value_type: String,
@@ -164,6 +183,9 @@ pub struct TabData {
     pub fluent_file: Option<String>,
     pub fluent_message: Option<String>,
     pub fluent_args: HashMap<String, String>,
+    pub config: serde_json::Value,
+    /// Set when the tab shows a run recording rather than a bundle.
+    pub recording: Option<RecordingInfo>,
 }
 
 #[tauri::command]
@@ -324,6 +346,8 @@ pub async fn duplicate_tab(
     let mut new_tab = source_tab.clone();
     new_tab.tab_id = uuid::Uuid::new_v4().to_string();
     new_tab.pipeline_steps.clear();
+    new_tab.recorder = crate::state::new_recorder();
+    new_tab.last_execution_id = None;
 
     let tab_info = TabInfo {
         tab_id: new_tab.tab_id.clone(),
@@ -366,6 +390,11 @@ pub async fn get_tab_data(
         fluent_file: tab.fluent_file.clone(),
         fluent_message: tab.fluent_message.clone(),
         fluent_args: tab.fluent_args.clone(),
+        config: tab.config.clone(),
+        recording: tab
+            .recording
+            .as_ref()
+            .map(|x| x.info(tab.bundle_path.as_deref().unwrap_or_default())),
     })
 }
 
@@ -426,6 +455,7 @@ pub async fn load_bundle(
     tab_id: String,
     path: String,
     pipeline_name: Option<String>,
+    app_handle: AppHandle,
     state: State<'_, PlaygroundState>,
 ) -> Result<BundleInfo, String> {
     tracing::info!(
@@ -438,13 +468,33 @@ pub async fn load_bundle(
 
     let is_dev_path = path.ends_with(".ts");
 
+    // Remote assets download the first time a bundle loads; the tab shows
+    // their progress.
+    let on_download = {
+        let app_handle = app_handle.clone();
+        let window_id = window_id.clone();
+        let tab_id = tab_id.clone();
+        DownloadCallback::new(move |progress| {
+            let payload = AssetDownloadEvent {
+                window_id: window_id.clone(),
+                tab_id: tab_id.clone(),
+                progress: progress.clone(),
+            };
+            if let Err(e) = app_handle.emit("asset-download", payload) {
+                tracing::error!("Failed to emit asset-download event: {}", e);
+            }
+        })
+    };
+    let options = BundleOptions {
+        pipeline: pipeline_name.clone(),
+        on_download: Some(on_download),
+        ..Default::default()
+    };
+
     let bundle = if path.ends_with(".drb") {
-        if let Some(ref name) = pipeline_name {
-            Bundle::from_bundle_named(&path, name)
-                .map_err(|e| format!("Failed to load bundle: {}", e))?
-        } else {
-            Bundle::from_bundle(&path).map_err(|e| format!("Failed to load bundle: {}", e))?
-        }
+        Bundle::from_bundle_with_options(&path, options)
+            .await
+            .map_err(|e| format!("Failed to load bundle: {}", e))?
     } else {
         // For .ts files or directories, load from path (which loads from directory containing pipeline.json)
         let load_path = if path.ends_with(".ts") {
@@ -453,12 +503,9 @@ pub async fn load_bundle(
             PathBuf::from(&path)
         };
 
-        if let Some(ref name) = pipeline_name {
-            Bundle::from_path_named(load_path, name)
-                .map_err(|e| format!("Failed to load bundle: {}", e))?
-        } else {
-            Bundle::from_path(load_path).map_err(|e| format!("Failed to load bundle: {}", e))?
-        }
+        Bundle::from_path_with_options(load_path, options)
+            .await
+            .map_err(|e| format!("Failed to load bundle: {}", e))?
     };
 
     let bundle_id = uuid::Uuid::new_v4().to_string();
@@ -481,11 +528,29 @@ pub async fn load_bundle(
         .get_tab_by_id_mut(&tab_id)
         .ok_or_else(|| "Tab not found".to_string())?;
 
+    if tab.bundle_path.as_deref() != Some(path.as_str()) {
+        tab.config = Settings::load(&app_handle)
+            .bundle_configs
+            .remove(&path)
+            .unwrap_or_else(|| serde_json::json!({}));
+    }
+    let recent = RecentBundle {
+        path: path.clone(),
+        name: bundle_info.name.clone(),
+        pipeline_name: Some(pipeline_name.clone()),
+    };
+    if let Err(e) = Settings::update(&app_handle, |settings| settings.add_recent(recent)) {
+        tracing::warn!("Failed to remember recent bundle: {}", e);
+    }
+
     tab.bundle = Some(Arc::new(bundle));
+    tab.recording = None;
     tab.bundle_info = Some(bundle_info.clone());
     tab.bundle_path = Some(path);
     tab.selected_pipeline = Some(pipeline_name);
     tab.pipeline_steps.clear();
+    tab.recorder = crate::state::new_recorder();
+    tab.last_execution_id = None;
 
     Ok(bundle_info)
 }
@@ -549,13 +614,6 @@ fn generate_rich_html(kind: &Option<String>, event: &PipelineEvent) -> Option<St
                 None
             }
         }
-        Some("audio") => {
-            if let PipelineEvent::Value(PipelineValue::Bytes(bytes)) = event {
-                generate_audio_html(bytes).ok()
-            } else {
-                None
-            }
-        }
         _ => None,
     }
 }
@@ -644,26 +702,20 @@ fn generate_suggest_html(json: &GrammarOutput) -> Result<String, String> {
     Ok(html)
 }
 
-fn generate_audio_html(bytes: &[u8]) -> Result<String, String> {
-    let base64_data = base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes);
-    let byte_count = bytes.len();
-
-    let html = format!(
-        r#"<div class="audio-view">
-  <div class="audio-info">
-    <p>Audio file generated ({} bytes)</p>
-  </div>
-  <div class="audio-player-container">
-    <audio controls src="data:audio/wav;base64,{}" preload="metadata">
-      Your browser does not support the audio element.
-    </audio>
-  </div>
-</div>"#,
-        byte_count.to_string().as_str(),
-        base64_data
-    );
+/// Whether `bytes` is a RIFF/WAVE file, which is what the speech modules
+/// emit.
+fn is_wav(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+}
 
-    Ok(html)
+/// Base64 of audio output, for the playground's audio player.
+fn audio_data(kind: &Option<String>, event: &PipelineEvent) -> Option<String> {
+    match (kind.as_deref(), event) {
+        (Some("audio"), PipelineEvent::Value(PipelineValue::Bytes(bytes))) => Some(
+            base64::Engine::encode(&base64::engine::general_purpose::STANDARD, bytes),
+        ),
+        _ => None,
+    }
 }
 
 fn determine_kind(cmd: &Command, event: &PipelineEvent) -> Option<String> {
@@ -672,20 +724,46 @@ fn determine_kind(cmd: &Command, event: &PipelineEvent) -> Option<String> {
         return Some(kind.clone());
     }
 
-    // Fall back to content-based detection for JSON
-    if let PipelineEvent::Value(PipelineValue::Json(_)) = event {
-        return Some("json".to_string());
+    // Fall back to content-based detection for JSON and audio
+    match event {
+        PipelineEvent::Value(PipelineValue::Json(_)) => return Some("json".to_string()),
+        PipelineEvent::Value(PipelineValue::Bytes(bytes)) if is_wav(bytes) => {
+            return Some("audio".to_string());
+        }
+        _ => {}
     }
 
     // Default to plain text
     None
 }
 
+/// A step of a run as the frontend shows it, emitted as `pipeline-step`
+/// while a pipeline runs and returned for recordings.
+#[derive(Serialize, Clone)]
+pub struct PipelineStepEvent {
+    window_id: String,
+    tab_id: String,
+    execution_id: String,
+    step_index: usize,
+    command_key: String,
+    header: StepHeader,
+    command: serde_json::Value,
+    command_display: String,
+    event_html: String,
+    kind: Option<String>,
+    value_type: Option<String>,
+    event_rich_html: Option<String>,
+    audio: Option<String>,
+    /// The run stopped here on the breakpoint.
+    breakpoint: bool,
+}
+
 #[tauri::command]
 pub async fn run_pipeline(
     window_id: String,
     tab_id: String,
     input: String,
+    breakpoint: Option<String>,
     app_handle: AppHandle,
     state: State<'_, PlaygroundState>,
 ) -> Result<String, String> {
@@ -695,120 +773,466 @@ pub async fn run_pipeline(
         window_id
     );
 
-    let windows = state.windows.lock().await;
+    let execution_id = uuid::Uuid::new_v4().to_string();
+    let mut windows = state.windows.lock().await;
     let window_state = windows
-        .get(&window_id)
+        .get_mut(&window_id)
         .ok_or_else(|| "Window not found".to_string())?;
+    let theme = window_state.theme_name();
 
     let tab = window_state
-        .get_tab_by_id(&tab_id)
+        .get_tab_by_id_mut(&tab_id)
         .ok_or_else(|| "Tab not found".to_string())?;
+    tab.last_execution_id = Some(execution_id.clone());
+    let recorder = tab.recorder.clone();
 
     let bundle = tab
         .bundle
         .as_ref()
         .ok_or_else(|| "No bundle loaded in tab".to_string())?;
+    let config = tab.config.clone();
+    let highlights = state.highlights.clone();
+
+    // Same syntax as the REPL's `:breakpoint`
+    let breakpoint = match breakpoint.as_deref().map(str::trim) {
+        Some(x) if !x.is_empty() => {
+            let bp = Breakpoint::parse(x).map_err(|e| format!("Invalid breakpoint: {}", e))?;
+            if !bundle.definition().commands.contains_key(bp.step()) {
+                return Err(format!("Breakpoint step '{}' not found", bp.step()));
+            }
+            Some(Arc::new(bp))
+        }
+        _ => None,
+    };
 
-    let execution_id = uuid::Uuid::new_v4().to_string();
     let execution_id_clone = execution_id.clone();
     let app_handle_clone = app_handle.clone();
     let window_id_clone = window_id.clone();
     let tab_id_clone = tab_id.clone();
+    let tap_recorder = recorder.clone();
 
     // Create tap function to emit events
     let tap = Arc::new(move |key: &str, cmd: &Command, event: &PipelineEvent| {
+        let step_index = tap_recorder.record(key, cmd, event);
         let execution_id = execution_id_clone.clone();
         let app_handle = app_handle_clone.clone();
         let window_id = window_id_clone.clone();
         let tab_id = tab_id_clone.clone();
+        let breakpoint = breakpoint.as_ref().is_some_and(|x| x.hits(key, event));
         let command_key = key.to_string();
         let command_json = serde_json::to_value(cmd).unwrap_or_default();
+        let header = cmd.header(key);
         let kind = determine_kind(cmd, event);
         let value_type = cmd.returns.clone();
 
-        // Format the event output - pretty-print JSON based on data type
-        let event_str = match event {
-            PipelineEvent::Value(PipelineValue::Json(val)) => {
-                // Always pretty-print JSON data, regardless of kind
-                serde_json::to_string_pretty(val).unwrap_or_else(|_| format!("{:#}", event))
-            }
-            _ => format!("{:#}", event),
-        };
-
         // Convert to HTML with syntax highlighting
-        let event_html = if let Some(ref syntax_kind) = kind {
-            syntax::highlight_to_html(&event_str, syntax_kind)
-        } else {
-            html_escape::encode_text(&event_str).to_string()
-        };
+        let event_html = highlights
+            .render(&event_text(event), kind.as_deref(), theme)
+            .to_string();
 
         // Generate rich HTML for interactive views
         let event_rich_html = generate_rich_html(&kind, event);
+        let audio = audio_data(&kind, event);
 
         let command_display = cmd.as_str(None);
 
         async move {
             // Emit event to frontend with window and tab context
-            #[derive(Serialize, Clone)]
-            struct PipelineStepEvent {
-                window_id: String,
-                tab_id: String,
-                execution_id: String,
-                step_index: usize,
-                command_key: String,
-                command: serde_json::Value,
-                command_display: String,
-                event_html: String,
-                kind: Option<String>,
-                value_type: Option<String>,
-                event_rich_html: Option<String>,
-            }
-
             let payload = PipelineStepEvent {
                 window_id,
                 tab_id,
                 execution_id: execution_id.clone(),
-                step_index: 0, // We'll increment this in the frontend
+                step_index,
                 command_key,
+                header,
                 command: command_json,
                 command_display,
                 event_html,
                 kind,
                 value_type: Some(value_type),
                 event_rich_html,
+                audio,
+                breakpoint,
             };
 
             if let Err(e) = app_handle.emit("pipeline-step", payload) {
                 tracing::error!("Failed to emit pipeline-step event: {}", e);
             }
 
-            divvun_runtime::modules::TapOutput::Continue
+            if breakpoint {
+                divvun_runtime::modules::TapOutput::Stop
+            } else {
+                divvun_runtime::modules::TapOutput::Continue
+            }
         }
         .boxed()
     });
 
     // Create pipeline with tap
     let mut pipe = bundle
-        .create_with_tap(serde_json::json!({}), tap)
+        .create_with_tap(config, tap)
         .await
         .map_err(|e| format!("Failed to create pipeline: {}", e))?;
 
     // Run pipeline
+    recorder.start(input.clone());
     let mut stream = pipe.forward(PipelineValue::String(input)).await;
-    let mut final_output = String::new();
+    let mut final_output = Ok(String::new());
 
     while let Some(result) = stream.next().await {
         match result {
             Ok(output) => {
-                final_output = format!("{:#}", output);
+                final_output = Ok(format!("{:#}", output));
             }
             Err(e) => {
-                return Err(format!("Pipeline error: {}", e));
+                final_output = Err(format!("Pipeline error: {}", e));
+                break;
             }
         }
     }
+    recorder.finish();
+
+    final_output
+}
+
+/// An event as shown in a step: JSON pretty-printed, whatever the kind.
+fn event_text(event: &PipelineEvent) -> String {
+    match event {
+        PipelineEvent::Value(PipelineValue::Json(val)) => {
+            serde_json::to_string_pretty(val).unwrap_or_else(|_| format!("{:#}", event))
+        }
+        _ => format!("{:#}", event),
+    }
+}
+
+/// The HTML of a step shown again in another theme.
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedStep {
+    pub execution_id: String,
+    pub step_index: usize,
+    pub event_html: String,
+}
+
+/// Highlighting themes to choose from.
+#[tauri::command]
+pub fn list_themes() -> Vec<String> {
+    syntax::themes().into_iter().map(str::to_string).collect()
+}
+
+/// Set a window's highlighting theme, `None` to follow the OS, and whether
+/// the OS is in dark mode. Returns the theme the window now uses.
+#[tauri::command]
+pub async fn set_window_theme(
+    window_id: String,
+    theme: Option<String>,
+    dark: bool,
+    state: State<'_, PlaygroundState>,
+) -> Result<String, String> {
+    let mut windows = state.windows.lock().await;
+    let window_state = windows
+        .get_mut(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+
+    window_state.theme = theme;
+    window_state.dark = dark;
+    Ok(window_state.theme_name().to_string())
+}
+
+/// The steps of a tab's last run in the window's current theme, from the
+/// highlight cache where possible, without running the pipeline again.
+#[tauri::command]
+pub async fn rerender_steps(
+    window_id: String,
+    tab_id: String,
+    state: State<'_, PlaygroundState>,
+) -> Result<Vec<RenderedStep>, String> {
+    let windows = state.windows.lock().await;
+    let window_state = windows
+        .get(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+    let theme = window_state.theme_name();
+    let tab = window_state
+        .get_tab_by_id(&tab_id)
+        .ok_or_else(|| "Tab not found".to_string())?;
+
+    let Some(execution_id) = &tab.last_execution_id else {
+        return Ok(Vec::new());
+    };
+    if let Some(recording) = &tab.recording {
+        return Ok(recording
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(step_index, step)| RenderedStep {
+                execution_id: execution_id.clone(),
+                step_index,
+                event_html: state
+                    .highlights
+                    .render(&step.text, step.kind.as_deref(), theme)
+                    .to_string(),
+            })
+            .collect());
+    }
+    let Some(run) = tab.recorder.last_run() else {
+        return Ok(Vec::new());
+    };
+    Ok(run
+        .events
+        .iter()
+        .enumerate()
+        .map(|(step_index, step)| RenderedStep {
+            execution_id: execution_id.clone(),
+            step_index,
+            event_html: state
+                .highlights
+                .render(
+                    &event_text(&step.event),
+                    determine_kind(&step.command, &step.event).as_deref(),
+                    theme,
+                )
+                .to_string(),
+        })
+        .collect())
+}
+
+/// A run as saved in a recording, with each step's output as the playground
+/// shows it.
+fn record_run(
+    run: &PipelineRun,
+    bundle: RecordedBundle,
+    config: serde_json::Value,
+) -> RunRecording {
+    let steps = run
+        .events
+        .iter()
+        .map(|x| {
+            let kind = determine_kind(&x.command, &x.event);
+            RecordedStep {
+                command_key: x.key.clone(),
+                header: x.command.header(&x.key),
+                command: serde_json::to_value(&x.command).unwrap_or_default(),
+                command_display: x.command.as_str(None),
+                at_ms: x.at.as_secs_f64() * 1000.0,
+                value_type: x.command.returns.clone(),
+                text: event_text(&x.event),
+                audio: audio_data(&kind, &x.event),
+                kind,
+            }
+        })
+        .collect();
 
-    Ok(final_output)
+    RunRecording {
+        version: crate::recording::RECORDING_VERSION,
+        runtime_version: divvun_runtime::VERSION.to_string(),
+        started_ms: run
+            .started
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64,
+        bundle,
+        input: run.input.clone(),
+        config,
+        steps,
+        dropped: run.dropped,
+    }
+}
+
+/// Ask the user where to save a file. The commands writing files ask
+/// themselves rather than taking a path from the webview, so the webview
+/// can't write anywhere else. None if the user cancelled.
+async fn pick_save_path(
+    app: &AppHandle,
+    file_name: &str,
+    filter: &str,
+    extension: &str,
+) -> Result<Option<PathBuf>, String> {
+    use tauri_plugin_dialog::DialogExt;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    app.dialog()
+        .file()
+        .set_file_name(file_name)
+        .add_filter(filter, &[extension])
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+    match rx.await.map_err(|e| e.to_string())? {
+        Some(path) => path.into_path().map(Some).map_err(|e| e.to_string()),
+        None => Ok(None),
+    }
+}
+
+/// Save a tab's last run as a `.drrun` recording, to be opened again without
+/// the bundle, where the user picks. Returns whether it was saved.
+#[tauri::command]
+pub async fn save_recording(
+    app: AppHandle,
+    window_id: String,
+    tab_id: String,
+    state: State<'_, PlaygroundState>,
+) -> Result<bool, String> {
+    tracing::info!("Saving run of tab {} in window {}", tab_id, window_id);
+
+    let windows = state.windows.lock().await;
+    let window_state = windows
+        .get(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+    let tab = window_state
+        .get_tab_by_id(&tab_id)
+        .ok_or_else(|| "Tab not found".to_string())?;
+
+    // A tab showing a recording saves it as it is
+    if let Some(recording) = tab.recording.clone() {
+        drop(windows);
+        return save_recording_as(&app, &recording).await;
+    }
+
+    let (Some(bundle), Some(info)) = (tab.bundle.clone(), tab.bundle_info.clone()) else {
+        return Err("No bundle loaded in tab".to_string());
+    };
+    let run = tab
+        .recorder
+        .last_run()
+        .ok_or_else(|| "Nothing to save; run the pipeline first".to_string())?;
+    let config = tab.config.clone();
+    drop(windows);
+
+    let manifest_hash = if info.is_dev_path {
+        None
+    } else {
+        bundle
+            .context()
+            .load_file_optional(BUILD_MANIFEST_FILE)
+            .await
+            .map_err(|e| format!("Failed to read the bundle's build manifest: {}", e))?
+            .map(|x| Checksums::hash(&x))
+    };
+    let bundle = RecordedBundle {
+        info,
+        manifest_hash,
+    };
+    save_recording_as(&app, &record_run(&run, bundle, config)).await
+}
+
+async fn save_recording_as(app: &AppHandle, recording: &RunRecording) -> Result<bool, String> {
+    let info = &recording.bundle.info;
+    let file_name = format!("{}-{}.drrun", info.name, info.pipeline_name);
+    let Some(path) = pick_save_path(app, &file_name, "Run Recording", "drrun").await? else {
+        return Ok(false);
+    };
+    recording.write(&path)?;
+    Ok(true)
+}
+
+/// Open a `.drrun` recording in a tab, which then shows its run read-only
+/// in place of a bundle.
+#[tauri::command]
+pub async fn open_recording(
+    window_id: String,
+    tab_id: String,
+    path: String,
+    state: State<'_, PlaygroundState>,
+) -> Result<RecordingInfo, String> {
+    tracing::info!(
+        "Opening recording {} in tab {} in window {}",
+        path,
+        tab_id,
+        window_id
+    );
+
+    let recording = RunRecording::read(Path::new(&path))?;
+    let info = recording.info(&path);
+
+    let mut windows = state.windows.lock().await;
+    let window_state = windows
+        .get_mut(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+    let tab = window_state
+        .get_tab_by_id_mut(&tab_id)
+        .ok_or_else(|| "Tab not found".to_string())?;
+
+    tab.bundle = None;
+    tab.bundle_info = Some(recording.bundle.info.clone());
+    tab.bundle_path = Some(path);
+    tab.selected_pipeline = Some(recording.bundle.info.pipeline_name.clone());
+    tab.pipeline_input = recording.input.clone();
+    tab.config = recording.config.clone();
+    tab.pipeline_steps.clear();
+    tab.recorder = crate::state::new_recorder();
+    tab.last_execution_id = None;
+    tab.recording = Some(Arc::new(recording));
+
+    Ok(info)
+}
+
+/// The steps of the recording a tab shows, as they were emitted when it was
+/// recorded, highlighted in the window's theme.
+#[tauri::command]
+pub async fn get_recording_steps(
+    window_id: String,
+    tab_id: String,
+    state: State<'_, PlaygroundState>,
+) -> Result<Vec<PipelineStepEvent>, String> {
+    let mut windows = state.windows.lock().await;
+    let window_state = windows
+        .get_mut(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+    let theme = window_state.theme_name();
+    let tab = window_state
+        .get_tab_by_id_mut(&tab_id)
+        .ok_or_else(|| "Tab not found".to_string())?;
+    let recording = tab
+        .recording
+        .clone()
+        .ok_or_else(|| "Tab has no recording open".to_string())?;
+
+    let execution_id = uuid::Uuid::new_v4().to_string();
+    tab.last_execution_id = Some(execution_id.clone());
+
+    Ok(recording
+        .steps
+        .iter()
+        .enumerate()
+        .map(|(step_index, step)| {
+            // Suggest output is shown as JSON text, so it reads back as JSON
+            let event_rich_html = serde_json::from_str(&step.text).ok().and_then(|x| {
+                generate_rich_html(&step.kind, &PipelineEvent::Value(PipelineValue::Json(x)))
+            });
+            PipelineStepEvent {
+                window_id: window_id.clone(),
+                tab_id: tab_id.clone(),
+                execution_id: execution_id.clone(),
+                step_index,
+                command_key: step.command_key.clone(),
+                header: step.header.clone(),
+                command: step.command.clone(),
+                command_display: step.command_display.clone(),
+                event_html: state
+                    .highlights
+                    .render(&step.text, step.kind.as_deref(), theme)
+                    .to_string(),
+                kind: step.kind.clone(),
+                value_type: Some(step.value_type.clone()),
+                event_rich_html,
+                audio: step.audio.clone(),
+                breakpoint: false,
+            }
+        })
+        .collect())
+}
+
+/// Save audio from a pipeline step, as given in its `audio` field, where the
+/// user picks. Returns whether it was saved.
+#[tauri::command]
+pub async fn save_audio(app: AppHandle, file_name: String, data: String) -> Result<bool, String> {
+    let bytes = base64::Engine::decode(&base64::engine::general_purpose::STANDARD, data)
+        .map_err(|e| format!("Invalid audio data: {}", e))?;
+    let Some(path) = pick_save_path(&app, &file_name, "WAV audio", "wav").await? else {
+        return Ok(false);
+    };
+    std::fs::write(&path, bytes)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+    Ok(true)
 }
 
 #[tauri::command]
@@ -1094,53 +1518,133 @@ pub async fn test_ftl_message(
     Ok(FluentMessageResult { title, description })
 }
 
+/// The bundle or recording the app was started with (from the command line
+/// or by opening a `.drb` or `.drrun` file), handed out once so only the first window opens it.
 #[tauri::command]
 pub async fn get_cli_args(cli_args: State<'_, crate::CliArgs>) -> Result<Option<String>, String> {
     Ok(cli_args
         .initial_path
-        .as_ref()
+        .lock()
+        .map_err(|e| e.to_string())?
+        .take()
         .map(|p| p.to_string_lossy().to_string()))
 }
 
+/// Recently opened bundles that still exist, most recent first.
+#[tauri::command]
+pub fn get_recent_bundles(app_handle: AppHandle) -> Vec<RecentBundle> {
+    Settings::load(&app_handle)
+        .recent_bundles
+        .into_iter()
+        .filter(|x| Path::new(&x.path).exists())
+        .collect()
+}
+
+#[tauri::command]
+pub fn clear_recent(app_handle: AppHandle) -> Result<(), String> {
+    Settings::update(&app_handle, |settings| settings.recent_bundles.clear())
+}
+
+/// A field of a command's runtime config, for rendering a form.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ConfigFieldInfo {
+pub struct ConfigFieldSchema {
     pub name: String,
     pub doc: Vec<String>,
-    pub type_name: String,
+    /// TypeScript type, as in the generated bindings: "string", "number",
+    /// "boolean", "string[]", or the name of another struct.
+    pub ty: String,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConfigSchema {
+    pub name: String,
+    pub fields: Vec<ConfigFieldSchema>,
 }
 
 #[tauri::command]
-pub fn get_command_config_fields(
+pub fn get_command_config_schema(
     module: String,
     command: String,
-) -> Result<Option<Vec<ConfigFieldInfo>>, String> {
-    tracing::info!("Getting config fields for command {}::{}", module, command);
-
-    if let Some(commands) = MODULES.get(&module) {
-        if let Some(cmd_def) = commands.get(&command) {
-            if let Some(config_shape) = cmd_def.config_shape {
-                let fields = if let facet::Type::User(facet::UserType::Struct(struct_type)) =
-                    config_shape.ty
-                {
-                    struct_type
-                        .fields
-                        .iter()
-                        .map(|field| ConfigFieldInfo {
-                            name: field.name.to_string(),
-                            doc: field.doc.iter().map(|s| s.to_string()).collect(),
-                            type_name: format!("{:?}", field.shape().ty),
-                        })
-                        .collect()
-                } else {
-                    Vec::new()
-                };
-
-                return Ok(Some(fields));
-            } else {
-                return Ok(None);
-            }
-        }
+) -> Result<Option<ConfigSchema>, String> {
+    tracing::info!("Getting config schema for command {}::{}", module, command);
+
+    let cmd_def = MODULES
+        .get(&module)
+        .and_then(|commands| commands.get(&command))
+        .ok_or_else(|| format!("Command {}::{} not found", module, command))?;
+    let Some(config_name) = cmd_def.config else {
+        return Ok(None);
+    };
+    let Some(struct_def) = divvun_runtime::modules::get_structs()
+        .find(|x| x.name == config_name && x.module == cmd_def.module)
+    else {
+        return Ok(None);
+    };
+
+    // Doc comments are only known to facet.
+    let docs: HashMap<&str, Vec<String>> = match cmd_def.config_shape.map(|x| x.ty) {
+        Some(facet::Type::User(facet::UserType::Struct(struct_type))) => struct_type
+            .fields
+            .iter()
+            .map(|field| {
+                (
+                    field.name,
+                    field.doc.iter().map(|s| s.trim().to_string()).collect(),
+                )
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
+
+    Ok(Some(ConfigSchema {
+        name: config_name.to_string(),
+        fields: struct_def
+            .fields
+            .iter()
+            .map(|field| ConfigFieldSchema {
+                name: field.name.to_string(),
+                doc: docs.get(field.name).cloned().unwrap_or_default(),
+                ty: field.ty.to_string(),
+                optional: field.optional,
+            })
+            .collect(),
+    }))
+}
+
+/// Set the tab's runtime config and remember it for the tab's bundle.
+#[tauri::command]
+pub async fn update_tab_config(
+    window_id: String,
+    tab_id: String,
+    config: serde_json::Value,
+    app_handle: AppHandle,
+    state: State<'_, PlaygroundState>,
+) -> Result<(), String> {
+    tracing::debug!("Updating config for tab {} in window {}", tab_id, window_id);
+
+    let mut windows = state.windows.lock().await;
+    let window_state = windows
+        .get_mut(&window_id)
+        .ok_or_else(|| "Window not found".to_string())?;
+
+    let tab = window_state
+        .get_tab_by_id_mut(&tab_id)
+        .ok_or_else(|| "Tab not found".to_string())?;
+
+    if tab.recording.is_some() {
+        return Err("Recordings are read-only".to_string());
+    }
+    if !config.is_object() {
+        return Err("Config must be an object keyed by command".to_string());
     }
+    tab.config = config.clone();
 
-    Err(format!("Command {}::{} not found", module, command))
+    if let Some(path) = tab.bundle_path.clone() {
+        Settings::update(&app_handle, |settings| {
+            settings.bundle_configs.insert(path, config);
+        })?;
+    }
+
+    Ok(())
 }
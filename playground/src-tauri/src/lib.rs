@@ -1,4 +1,6 @@
 mod commands;
+mod recording;
+mod settings;
 mod state;
 mod syntax;
 
@@ -8,9 +10,12 @@ use tauri::Manager;
 #[cfg(desktop)]
 use tauri::WebviewWindowBuilder;
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct CliArgs {
-    pub initial_path: Option<PathBuf>,
+    /// Bundle or run recording given on the command line, which is also how
+    /// the OS passes a double-clicked `.drb` or `.drrun` file. Taken by the
+    /// first window that loads.
+    pub initial_path: std::sync::Mutex<Option<PathBuf>>,
 }
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
@@ -38,11 +43,21 @@ pub fn run() {
             commands::load_bundle,
             commands::list_pipelines,
             commands::run_pipeline,
+            commands::save_audio,
             commands::list_ftl_files,
             commands::get_ftl_messages,
             commands::test_ftl_message,
             commands::get_cli_args,
-            commands::get_command_config_fields,
+            commands::get_recent_bundles,
+            commands::clear_recent,
+            commands::get_command_config_schema,
+            commands::update_tab_config,
+            commands::list_themes,
+            commands::set_window_theme,
+            commands::rerender_steps,
+            commands::save_recording,
+            commands::open_recording,
+            commands::get_recording_steps,
         ])
         .setup(|app| {
             #[cfg(desktop)]
@@ -77,11 +92,14 @@ fn configure_menus(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error
     // On macOS, the first submenu becomes the app menu with standard items
     // Create app menu (first submenu on macOS)
     let app_name = app.package_info().name.clone();
+    let about = tauri::menu::AboutMetadataBuilder::new()
+        .comments(Some(about_comments(&divvun_runtime::version_json())))
+        .build();
     let app_menu = SubmenuBuilder::new(app, &app_name)
         .item(&PredefinedMenuItem::about(
             app,
             Some("About Divvun Runtime Playground".into()),
-            None,
+            Some(about),
         )?)
         .separator()
         .item(&PredefinedMenuItem::hide(
@@ -167,6 +185,29 @@ fn configure_menus(app: &mut tauri::App) -> Result<(), Box<dyn std::error::Error
     Ok(())
 }
 
+/// Runtime version and compiled-in modules, from `version_json()`, for the
+/// About dialog.
+#[cfg(desktop)]
+fn about_comments(info: &serde_json::Value) -> String {
+    let mut comments = format!(
+        "Divvun Runtime {}",
+        info["version"].as_str().unwrap_or("unknown")
+    );
+    if let Some(describe) = info["build"]["git_describe"].as_str() {
+        comments.push_str(&format!(" ({})", describe));
+    }
+    let modules = info["modules"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|x| x["name"].as_str())
+        .collect::<Vec<_>>();
+    if !modules.is_empty() {
+        comments.push_str(&format!("\nModules: {}", modules.join(", ")));
+    }
+    comments
+}
+
 fn parse_args() -> CliArgs {
     let args: Vec<String> = std::env::args().collect();
 
@@ -176,5 +217,7 @@ fn parse_args() -> CliArgs {
         None
     };
 
-    CliArgs { initial_path }
+    CliArgs {
+        initial_path: std::sync::Mutex::new(initial_path),
+    }
 }
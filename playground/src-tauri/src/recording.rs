@@ -0,0 +1,100 @@
+//! Run recordings, saved as `.drrun` files: a tab's last run with its input,
+//! config and the output of every step, so it can be looked at again without
+//! the bundle. A linguist who hits a wrong analysis can send the recording
+//! instead of describing it.
+
+use std::path::Path;
+
+use divvun_runtime::ast::StepHeader;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::BundleInfo;
+
+/// Version of the `.drrun` format this playground writes.
+pub const RECORDING_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecording {
+    pub version: u32,
+    /// divvun-runtime version of the playground that recorded the run.
+    pub runtime_version: String,
+    /// When the run started, in milliseconds since the Unix epoch.
+    pub started_ms: u64,
+    pub bundle: RecordedBundle,
+    pub input: String,
+    /// Runtime config the run had, keyed by command key.
+    pub config: serde_json::Value,
+    pub steps: Vec<RecordedStep>,
+    /// Steps the run emitted beyond what was recorded.
+    #[serde(default)]
+    pub dropped: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedBundle {
+    /// The bundle and pipeline as the tab showed them.
+    pub info: BundleInfo,
+    /// BLAKE3 digest of the bundle's `build-manifest.json`, telling which
+    /// build ran. Bundles loaded from a project have none.
+    pub manifest_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedStep {
+    pub command_key: String,
+    pub header: StepHeader,
+    pub command: serde_json::Value,
+    pub command_display: String,
+    /// Time since the run started.
+    pub at_ms: f64,
+    pub kind: Option<String>,
+    pub value_type: String,
+    /// The step's output as shown, before highlighting.
+    pub text: String,
+    /// Base64 of the audio the step emitted, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub audio: Option<String>,
+}
+
+/// What a tab showing a recording says about it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    pub path: String,
+    pub runtime_version: String,
+    pub started_ms: u64,
+    pub manifest_hash: Option<String>,
+    pub dropped: usize,
+}
+
+impl RunRecording {
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        let recording: RunRecording = serde_json::from_slice(&bytes)
+            .map_err(|e| format!("{} is not a run recording: {}", path.display(), e))?;
+        if recording.version > RECORDING_VERSION {
+            return Err(format!(
+                "{} was saved by a newer playground (recording version {}); update the playground to open it",
+                path.display(),
+                recording.version
+            ));
+        }
+        Ok(recording)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_vec_pretty(self)
+            .map_err(|e| format!("Failed to serialize recording: {}", e))?;
+        std::fs::write(path, json).map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    pub fn info(&self, path: &str) -> RecordingInfo {
+        RecordingInfo {
+            path: path.to_string(),
+            runtime_version: self.runtime_version.clone(),
+            started_ms: self.started_ms,
+            manifest_hash: self.bundle.manifest_hash.clone(),
+            dropped: self.dropped,
+        }
+    }
+}
@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+/// Playground settings kept between sessions, in `settings.json` in the
+/// app's config directory.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Settings {
+    /// Runtime config last used with each bundle, keyed by bundle path.
+    #[serde(default)]
+    pub bundle_configs: HashMap<String, serde_json::Value>,
+    /// Bundles opened lately, most recent first.
+    #[serde(default)]
+    pub recent_bundles: Vec<RecentBundle>,
+}
+
+/// How many bundles the recent list keeps.
+const MAX_RECENT_BUNDLES: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentBundle {
+    pub path: String,
+    pub name: String,
+    pub pipeline_name: Option<String>,
+}
+
+fn settings_path(app: &AppHandle) -> Result<PathBuf, String> {
+    app.path()
+        .app_config_dir()
+        .map(|dir| dir.join("settings.json"))
+        .map_err(|e| format!("No config directory: {}", e))
+}
+
+impl Settings {
+    /// Read the settings, or the defaults if there are none or they can't
+    /// be parsed.
+    pub fn load(app: &AppHandle) -> Settings {
+        let Ok(path) = settings_path(app) else {
+            return Settings::default();
+        };
+        match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                tracing::warn!("Ignoring unreadable {}: {}", path.display(), e);
+                Settings::default()
+            }),
+            Err(_) => Settings::default(),
+        }
+    }
+
+    pub fn save(&self, app: &AppHandle) -> Result<(), String> {
+        let path = settings_path(app)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create {}: {}", parent.display(), e))?;
+        }
+        let json = serde_json::to_vec_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(&path, json)
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))
+    }
+
+    /// Move `bundle` to the top of the recent list.
+    pub fn add_recent(&mut self, bundle: RecentBundle) {
+        self.recent_bundles.retain(|x| x.path != bundle.path);
+        self.recent_bundles.insert(0, bundle);
+        self.recent_bundles.truncate(MAX_RECENT_BUNDLES);
+    }
+
+    /// Load the settings, change them and write them back.
+    pub fn update(app: &AppHandle, f: impl FnOnce(&mut Settings)) -> Result<(), String> {
+        let mut settings = Settings::load(app);
+        f(&mut settings);
+        settings.save(app)
+    }
+}
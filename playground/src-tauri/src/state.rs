@@ -1,4 +1,5 @@
 use divvun_runtime::bundle::Bundle;
+use divvun_runtime::util::recorder::RunRecorder;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
@@ -16,9 +17,21 @@ pub struct TabState {
     pub pipeline_input: String,
     #[serde(skip)]
     pub pipeline_steps: Vec<crate::commands::PipelineStepPayload>,
+    /// The last run, to show it in another theme without running the
+    /// pipeline again.
+    #[serde(skip)]
+    pub recorder: Arc<RunRecorder>,
+    /// Execution ID of the last run.
+    #[serde(skip)]
+    pub last_execution_id: Option<String>,
     pub fluent_file: Option<String>,
     pub fluent_message: Option<String>,
     pub fluent_args: HashMap<String, String>,
+    /// Runtime config for the pipeline, keyed by command key like `run -c`.
+    pub config: serde_json::Value,
+    /// The run recording the tab shows instead of a bundle, read-only.
+    #[serde(skip)]
+    pub recording: Option<Arc<crate::recording::RunRecording>>,
 }
 
 impl TabState {
@@ -32,17 +45,30 @@ impl TabState {
             current_view: "pipeline".to_string(),
             pipeline_input: String::new(),
             pipeline_steps: Vec::new(),
+            recorder: new_recorder(),
+            last_execution_id: None,
             fluent_file: None,
             fluent_message: None,
             fluent_args: HashMap::new(),
+            config: serde_json::json!({}),
+            recording: None,
         }
     }
 }
 
+/// A recorder for a tab, keeping only its last run.
+pub fn new_recorder() -> Arc<RunRecorder> {
+    Arc::new(RunRecorder::new(1, 10_000))
+}
+
 pub struct WindowState {
     pub window_id: String,
     pub tabs: Vec<TabState>,
     pub active_tab_index: usize,
+    /// Highlighting theme chosen for the window, or `None` to follow the OS.
+    pub theme: Option<String>,
+    /// The OS is in dark mode, as the window last reported.
+    pub dark: bool,
 }
 
 impl WindowState {
@@ -52,9 +78,15 @@ impl WindowState {
             window_id,
             tabs: vec![initial_tab],
             active_tab_index: 0,
+            theme: None,
+            dark: true,
         }
     }
 
+    pub fn theme_name(&self) -> &'static str {
+        crate::syntax::theme_name(self.theme.as_deref(), self.dark)
+    }
+
     pub fn get_active_tab(&self) -> Option<&TabState> {
         self.tabs.get(self.active_tab_index)
     }
@@ -74,12 +106,14 @@ impl WindowState {
 
 pub struct PlaygroundState {
     pub windows: Arc<Mutex<HashMap<String, WindowState>>>,
+    pub highlights: Arc<crate::syntax::HighlightCache>,
 }
 
 impl PlaygroundState {
     pub fn new() -> Self {
         Self {
             windows: Arc::new(Mutex::new(HashMap::new())),
+            highlights: Arc::new(crate::syntax::HighlightCache::default()),
         }
     }
 }
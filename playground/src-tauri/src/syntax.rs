@@ -1 +1,71 @@
-pub use syntax_highlight::highlight_to_html;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use syntax_highlight::{
+    get_default_theme_for_background, highlight_to_html_with_theme, list_available_themes,
+};
+
+/// Highlighted events kept at most; the oldest go first.
+const MAX_CACHED: usize = 2048;
+
+/// Highlighted HTML of pipeline events, by content hash, syntax kind and
+/// theme, so re-running a pipeline on the same input or switching back to a
+/// theme doesn't highlight everything again.
+#[derive(Default)]
+pub struct HighlightCache {
+    inner: Mutex<CacheEntries>,
+}
+
+type CacheKey = (u64, String, String);
+
+#[derive(Default)]
+struct CacheEntries {
+    html: HashMap<CacheKey, Arc<str>>,
+    order: VecDeque<CacheKey>,
+}
+
+impl HighlightCache {
+    /// `content` as HTML, highlighted as `kind` in `theme`, or only escaped
+    /// when it has no kind.
+    pub fn render(&self, content: &str, kind: Option<&str>, theme: &str) -> Arc<str> {
+        let Some(kind) = kind else {
+            return html_escape::encode_text(content).into();
+        };
+
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        let key = (hasher.finish(), kind.to_string(), theme.to_string());
+        if let Some(html) = self.inner.lock().unwrap().html.get(&key) {
+            return html.clone();
+        }
+
+        let html: Arc<str> = highlight_to_html_with_theme(content, kind, Some(theme)).into();
+        let mut entries = self.inner.lock().unwrap();
+        if entries.html.insert(key.clone(), html.clone()).is_none() {
+            entries.order.push_back(key);
+        }
+        while entries.order.len() > MAX_CACHED {
+            if let Some(oldest) = entries.order.pop_front() {
+                entries.html.remove(&oldest);
+            }
+        }
+        html
+    }
+}
+
+/// Highlighting themes, by name.
+pub fn themes() -> Vec<&'static str> {
+    let mut themes = list_available_themes();
+    themes.sort_unstable();
+    themes
+}
+
+/// The theme a window highlights with: the one chosen for it, or else the
+/// default for the OS's dark or light mode.
+pub fn theme_name(choice: Option<&str>, dark: bool) -> &'static str {
+    choice
+        .and_then(|choice| themes().into_iter().find(|x| *x == choice))
+        .unwrap_or_else(|| get_default_theme_for_background(dark))
+}
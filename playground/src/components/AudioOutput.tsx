@@ -0,0 +1,147 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useEffect, useRef, useState } from "preact/hooks";
+
+interface AudioOutputProps {
+  /** Base64 WAV, one per chunk the command emitted. */
+  chunks: string[];
+  name: string;
+}
+
+/** Audio player with waveform and download, one per emitted chunk. */
+export function AudioOutput({ chunks, name }: AudioOutputProps) {
+  if (chunks.length === 1) {
+    return (
+      <div class="audio-view">
+        <AudioChunk data={chunks[0]} fileName={`${name}.wav`} />
+      </div>
+    );
+  }
+
+  return (
+    <div class="audio-view">
+      <div class="audio-info">{chunks.length} chunks</div>
+      {chunks.map((data, i) => (
+        <div key={i} class="audio-chunk">
+          <div class="audio-chunk-label">Sentence {i + 1}</div>
+          <AudioChunk data={data} fileName={`${name}-${i + 1}.wav`} />
+        </div>
+      ))}
+    </div>
+  );
+}
+
+function decodeBase64(data: string): ArrayBuffer {
+  const binary = atob(data);
+  const bytes = new Uint8Array(binary.length);
+  for (let i = 0; i < binary.length; i++) {
+    bytes[i] = binary.charCodeAt(i);
+  }
+  return bytes.buffer;
+}
+
+/** Min and max sample of each of `width` columns, mixed down to mono. */
+function peaks(buffer: AudioBuffer, width: number): [number, number][] {
+  const channels = Array.from(
+    { length: buffer.numberOfChannels },
+    (_, i) => buffer.getChannelData(i),
+  );
+  const step = Math.max(1, Math.floor(buffer.length / width));
+  const result: [number, number][] = [];
+  for (let x = 0; x < width; x++) {
+    let min = 0;
+    let max = 0;
+    const start = x * step;
+    const end = Math.min(start + step, buffer.length);
+    for (let i = start; i < end; i++) {
+      let sample = 0;
+      for (const channel of channels) sample += channel[i];
+      sample /= channels.length;
+      if (sample < min) min = sample;
+      if (sample > max) max = sample;
+    }
+    result.push([min, max]);
+  }
+  return result;
+}
+
+function AudioChunk({ data, fileName }: { data: string; fileName: string }) {
+  const canvasRef = useRef<HTMLCanvasElement>(null);
+  const audioRef = useRef<HTMLAudioElement>(null);
+  const [waveform, setWaveform] = useState<[number, number][] | null>(null);
+  const [duration, setDuration] = useState(0);
+  const [progress, setProgress] = useState(0);
+  const [error, setError] = useState<string | null>(null);
+
+  useEffect(() => {
+    const ctx = new AudioContext();
+    ctx.decodeAudioData(decodeBase64(data))
+      .then((buffer) => {
+        const width = canvasRef.current?.clientWidth || 600;
+        setWaveform(peaks(buffer, width));
+        setDuration(buffer.duration);
+      })
+      .catch((err) => setError(`Could not decode audio: ${err}`))
+      .finally(() => ctx.close());
+  }, [data]);
+
+  useEffect(() => {
+    const canvas = canvasRef.current;
+    if (!canvas || !waveform) return;
+
+    const dpr = window.devicePixelRatio || 1;
+    canvas.width = waveform.length * dpr;
+    canvas.height = canvas.clientHeight * dpr;
+    const g = canvas.getContext("2d");
+    if (!g) return;
+
+    g.scale(dpr, dpr);
+    const mid = canvas.clientHeight / 2;
+    const played = progress * waveform.length;
+    g.clearRect(0, 0, waveform.length, canvas.clientHeight);
+    waveform.forEach(([min, max], x) => {
+      g.fillStyle = x < played ? "#4fc1ff" : "#6a6a6a";
+      const top = mid - max * mid;
+      g.fillRect(x, top, 1, Math.max(1, (max - min) * mid));
+    });
+  }, [waveform, progress]);
+
+  const seek = (e: MouseEvent) => {
+    const canvas = canvasRef.current;
+    const audio = audioRef.current;
+    if (!canvas || !audio || !duration) return;
+    const rect = canvas.getBoundingClientRect();
+    audio.currentTime = ((e.clientX - rect.left) / rect.width) * duration;
+  };
+
+  const download = async () => {
+    try {
+      await invoke("save_audio", { fileName, data });
+    } catch (err) {
+      alert(`Failed to save audio: ${err}`);
+    }
+  };
+
+  return (
+    <div class="audio-player-container">
+      {error
+        ? <div class="audio-error">{error}</div>
+        : <canvas ref={canvasRef} class="audio-waveform" onClick={seek} />}
+      <div class="audio-controls">
+        <audio
+          ref={audioRef}
+          controls
+          preload="metadata"
+          src={`data:audio/wav;base64,${data}`}
+          onTimeUpdate={(e) => {
+            const audio = e.currentTarget as HTMLAudioElement;
+            setProgress(audio.duration ? audio.currentTime / audio.duration : 0);
+          }}
+          onEnded={() => setProgress(0)}
+        />
+        <button type="button" class="toggle-btn" onClick={download}>
+          Download
+        </button>
+      </div>
+    </div>
+  );
+}
@@ -1,47 +1,45 @@
-import { useEffect, useState } from "preact/hooks";
-import { ConfigFieldInfo } from "../types";
+import { ConfigFieldSchema } from "../types";
 
 interface ConfigEditorProps {
-  fields: ConfigFieldInfo[];
+  fields: ConfigFieldSchema[];
   value: Record<string, unknown>;
   onChange: (value: Record<string, unknown>) => void;
 }
 
 export function ConfigEditor({ fields, value, onChange }: ConfigEditorProps) {
-  const [config, setConfig] = useState<Record<string, unknown>>(value);
-
-  useEffect(() => {
-    setConfig(value);
-  }, [value]);
-
   const handleFieldChange = (fieldName: string, fieldValue: unknown) => {
-    const newConfig = { ...config, [fieldName]: fieldValue };
-    setConfig(newConfig);
+    const newConfig = { ...value };
+    // Leave unset fields out so the command uses its default.
+    if (fieldValue === null || fieldValue === undefined) {
+      delete newConfig[fieldName];
+    } else {
+      newConfig[fieldName] = fieldValue;
+    }
     onChange(newConfig);
   };
 
-  const renderField = (field: ConfigFieldInfo) => {
-    const currentValue = config[field.name];
+  const label = (field: ConfigFieldSchema, showType = false) => (
+    <>
+      <span class="field-name">{field.name}</span>
+      {showType && <span class="field-type">({field.ty})</span>}
+      {field.doc.length > 0 && (
+        <span class="field-doc" title={field.doc.join("\n")}>
+          {field.doc.join(" ")}
+        </span>
+      )}
+    </>
+  );
 
-    const typeName = field.type_name.toLowerCase();
+  const renderField = (field: ConfigFieldSchema) => {
+    const currentValue = value[field.name];
 
-    if (
-      typeName.includes("option<vec<string>>") ||
-      typeName.includes("vec<string>")
-    ) {
+    if (field.ty === "string[]") {
       const stringValue = Array.isArray(currentValue)
         ? currentValue.join(", ")
         : "";
       return (
         <div key={field.name} class="config-field">
-          <label>
-            <span class="field-name">{field.name}</span>
-            {field.doc.length > 0 && (
-              <span class="field-doc" title={field.doc.join("\n")}>
-                {field.doc.join(" ")}
-              </span>
-            )}
-          </label>
+          <label>{label(field)}</label>
           <input
             type="text"
             value={stringValue}
@@ -56,17 +54,10 @@ export function ConfigEditor({ fields, value, onChange }: ConfigEditorProps) {
       );
     }
 
-    if (typeName.includes("option<string>") || typeName.includes("string")) {
+    if (field.ty === "string") {
       return (
         <div key={field.name} class="config-field">
-          <label>
-            <span class="field-name">{field.name}</span>
-            {field.doc.length > 0 && (
-              <span class="field-doc" title={field.doc.join("\n")}>
-                {field.doc.join(" ")}
-              </span>
-            )}
-          </label>
+          <label>{label(field)}</label>
           <input
             type="text"
             value={(currentValue as string) || ""}
@@ -80,7 +71,7 @@ export function ConfigEditor({ fields, value, onChange }: ConfigEditorProps) {
       );
     }
 
-    if (typeName.includes("bool")) {
+    if (field.ty === "boolean") {
       return (
         <div key={field.name} class="config-field">
           <label>
@@ -90,37 +81,30 @@ export function ConfigEditor({ fields, value, onChange }: ConfigEditorProps) {
               onChange={(e) =>
                 handleFieldChange(
                   field.name,
-                  (e.target as HTMLInputElement).checked,
+                  (e.target as HTMLInputElement).checked || null,
                 )}
             />
-            <span class="field-name">{field.name}</span>
-            {field.doc.length > 0 && (
-              <span class="field-doc" title={field.doc.join("\n")}>
-                {field.doc.join(" ")}
-              </span>
-            )}
+            {label(field)}
           </label>
         </div>
       );
     }
 
-    if (typeName.includes("i32") || typeName.includes("i64")) {
+    if (field.ty === "number") {
       return (
         <div key={field.name} class="config-field">
-          <label>
-            <span class="field-name">{field.name}</span>
-            {field.doc.length > 0 && (
-              <span class="field-doc" title={field.doc.join("\n")}>
-                {field.doc.join(" ")}
-              </span>
-            )}
-          </label>
+          <label>{label(field)}</label>
           <input
             type="number"
-            value={(currentValue as number) || 0}
+            value={currentValue === undefined ? "" : (currentValue as number)}
+            placeholder={field.optional ? "Default" : ""}
             onInput={(e) => {
-              const val = parseInt((e.target as HTMLInputElement).value);
-              handleFieldChange(field.name, isNaN(val) ? null : val);
+              const raw = (e.target as HTMLInputElement).value;
+              const val = Number(raw);
+              handleFieldChange(
+                field.name,
+                raw === "" || isNaN(val) ? null : val,
+              );
             }}
           />
         </div>
@@ -129,23 +113,21 @@ export function ConfigEditor({ fields, value, onChange }: ConfigEditorProps) {
 
     return (
       <div key={field.name} class="config-field">
-        <label>
-          <span class="field-name">{field.name}</span>
-          <span class="field-type">({field.type_name})</span>
-          {field.doc.length > 0 && (
-            <span class="field-doc" title={field.doc.join("\n")}>
-              {field.doc.join(" ")}
-            </span>
-          )}
-        </label>
+        <label>{label(field, true)}</label>
         <input
           type="text"
-          value={JSON.stringify(currentValue || null)}
+          value={currentValue === undefined ? "" : JSON.stringify(currentValue)}
+          placeholder="JSON"
           onInput={(e) => {
+            const raw = (e.target as HTMLInputElement).value;
+            if (raw.trim() === "") {
+              handleFieldChange(field.name, null);
+              return;
+            }
             try {
-              const val = JSON.parse((e.target as HTMLInputElement).value);
-              handleFieldChange(field.name, val);
+              handleFieldChange(field.name, JSON.parse(raw));
             } catch {
+              // Keep the last valid value until the JSON parses.
             }
           }}
         />
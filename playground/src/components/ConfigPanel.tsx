@@ -0,0 +1,111 @@
+import { invoke } from "@tauri-apps/api/core";
+import { useEffect, useState } from "preact/hooks";
+import { BundleInfo, ConfigSchema, RuntimeConfig } from "../types";
+import { ConfigEditor } from "./ConfigEditor";
+
+const schemaCache = new Map<string, Promise<ConfigSchema | null>>();
+
+/** Config schema of `module::command`, fetched once per command. */
+export function loadConfigSchema(
+  module: string,
+  command: string,
+): Promise<ConfigSchema | null> {
+  const key = `${module}::${command}`;
+  let schema = schemaCache.get(key);
+  if (!schema) {
+    schema = invoke<ConfigSchema | null>("get_command_config_schema", {
+      module,
+      command,
+    });
+    schema.catch(() => schemaCache.delete(key));
+    schemaCache.set(key, schema);
+  }
+  return schema;
+}
+
+interface ConfigPanelProps {
+  bundle: BundleInfo;
+  config: RuntimeConfig;
+  onChange: (config: RuntimeConfig) => void;
+}
+
+/** Runtime config of every configurable command in the pipeline. */
+export function ConfigPanel({ bundle, config, onChange }: ConfigPanelProps) {
+  const [schemas, setSchemas] = useState<Record<string, ConfigSchema | null>>(
+    {},
+  );
+
+  const configurable = Object.entries(bundle.commands)
+    .filter(([, cmd]) => cmd.config_name)
+    .sort(([a], [b]) => a.localeCompare(b));
+
+  useEffect(() => {
+    for (const [key, cmd] of configurable) {
+      loadConfigSchema(cmd.module, cmd.command)
+        .then((schema) => setSchemas((prev) => ({ ...prev, [key]: schema })))
+        .catch((err) => console.error("Failed to fetch config schema:", err));
+    }
+  }, [bundle.id]);
+
+  if (configurable.length === 0) {
+    return (
+      <div class="config-panel">
+        <div class="config-none">
+          No command in this pipeline takes runtime configuration
+        </div>
+      </div>
+    );
+  }
+
+  const handleChange = (key: string, value: Record<string, unknown>) => {
+    const next = { ...config };
+    if (Object.keys(value).length === 0) {
+      delete next[key];
+    } else {
+      next[key] = value;
+    }
+    onChange(next);
+  };
+
+  return (
+    <div class="config-panel">
+      <div class="config-panel-header">
+        <span>
+          Runtime configuration, saved for {bundle.name}. The same as{" "}
+          <code>run -c</code>.
+        </span>
+        <button
+          type="button"
+          onClick={() => onChange({})}
+          disabled={Object.keys(config).length === 0}
+        >
+          Reset
+        </button>
+      </div>
+      {configurable.map(([key, cmd]) => {
+        const schema = schemas[key];
+        return (
+          <div key={key} class="config-section">
+            <div class="config-header">
+              <span class="config-label">{key}</span>
+              <span class="field-type">
+                {cmd.module}::{cmd.command}
+              </span>
+            </div>
+            {schema === undefined
+              ? <div class="config-loading">Loading...</div>
+              : schema === null
+              ? <div class="config-none">No configuration available</div>
+              : (
+                <ConfigEditor
+                  fields={schema.fields}
+                  value={config[key] || {}}
+                  onChange={(value) => handleChange(key, value)}
+                />
+              )}
+          </div>
+        );
+      })}
+    </div>
+  );
+}
@@ -1,16 +1,34 @@
 import { PipelineStep } from "../types";
+import { AudioOutput } from "./AudioOutput";
 
 export type ViewMode = "interactive" | "raw";
 
 interface InteractiveOutputProps {
   step: PipelineStep;
+  /** Audio chunks of the step, if it emitted audio. */
+  audio: string[];
   rawHtml: string;
   viewMode: ViewMode;
 }
 
 export function InteractiveOutput(
-  { step, rawHtml, viewMode }: InteractiveOutputProps,
+  { step, audio, rawHtml, viewMode }: InteractiveOutputProps,
 ) {
+  if (audio.length > 0) {
+    return (
+      <div class="view-content">
+        {viewMode === "interactive"
+          ? <AudioOutput chunks={audio} name={step.command_key} />
+          : (
+            <div
+              class="step-content"
+              dangerouslySetInnerHTML={{ __html: rawHtml }}
+            />
+          )}
+      </div>
+    );
+  }
+
   if (!step.event_rich_html) {
     return (
       <div
@@ -1,18 +1,57 @@
 import { useEffect, useRef, useState } from "preact/hooks";
-import { invoke } from "@tauri-apps/api/core";
-import { BundleInfo, ConfigFieldInfo, PipelineStep } from "../types";
+import {
+  BundleInfo,
+  ConfigSchema,
+  PipelineStep,
+  RuntimeConfig,
+} from "../types";
 import { InteractiveOutput, ViewMode } from "./InteractiveOutput";
 import { ConfigEditor } from "./ConfigEditor";
+import { loadConfigSchema } from "./ConfigPanel";
+
+/** A step as shown: consecutive audio from one command is shown as one. */
+interface StepEntry {
+  step: PipelineStep;
+  index: number;
+  audio: string[];
+  rawHtml: string;
+}
+
+function groupSteps(steps: PipelineStep[]): StepEntry[] {
+  const entries: StepEntry[] = [];
+  steps.forEach((step, index) => {
+    const last = entries[entries.length - 1];
+    if (
+      step.audio && last && last.audio.length > 0 &&
+      last.step.execution_id === step.execution_id &&
+      last.step.command_key === step.command_key
+    ) {
+      last.audio.push(step.audio);
+      last.rawHtml += "\n" + step.event_html;
+    } else {
+      entries.push({
+        step,
+        index,
+        audio: step.audio ? [step.audio] : [],
+        rawHtml: step.event_html,
+      });
+    }
+  });
+  return entries;
+}
 
 interface PipelineOutputProps {
   steps: PipelineStep[];
   bundle: BundleInfo | null;
   isRunning: boolean;
   isBundleLoading: boolean;
+  config: RuntimeConfig;
+  onConfigChange: (config: RuntimeConfig) => void;
 }
 
 export function PipelineOutput(
-  { steps, bundle, isRunning, isBundleLoading }: PipelineOutputProps,
+  { steps, bundle, isRunning, isBundleLoading, config, onConfigChange }:
+    PipelineOutputProps,
 ) {
   const [expanded, setExpanded] = useState<Record<number, boolean>>({});
   const [allExpanded, setAllExpanded] = useState(true);
@@ -21,21 +60,20 @@ export function PipelineOutput(
   const [configExpanded, setConfigExpanded] = useState<Record<number, boolean>>(
     {},
   );
-  const [configFields, setConfigFields] = useState<
-    Record<string, ConfigFieldInfo[] | null>
-  >({});
-  const [configValues, setConfigValues] = useState<
-    Record<number, Record<string, unknown>>
+  const [configSchemas, setConfigSchemas] = useState<
+    Record<string, ConfigSchema | null>
   >({});
   const lastStepRef = useRef<HTMLDivElement>(null);
 
   // Auto-collapse previous steps when new step arrives and scroll to it
   useEffect(() => {
     if (steps.length > 0) {
+      const entries = groupSteps(steps);
+      const lastIndex = entries[entries.length - 1].index;
       const newExpanded: Record<number, boolean> = {};
-      steps.forEach((_, i) => {
+      entries.forEach(({ index }) => {
         // Only expand the last step
-        newExpanded[i] = i === steps.length - 1;
+        newExpanded[index] = index === lastIndex;
       });
       setExpanded(newExpanded);
       setAllExpanded(false);
@@ -97,8 +135,8 @@ export function PipelineOutput(
     const newState = !allExpanded;
     setAllExpanded(newState);
     const newExpanded: Record<number, boolean> = {};
-    steps.forEach((_, i) => {
-      newExpanded[i] = newState;
+    groupSteps(steps).forEach(({ index }) => {
+      newExpanded[index] = newState;
     });
     setExpanded(newExpanded);
   };
@@ -131,30 +169,30 @@ export function PipelineOutput(
       [index]: isExpanding,
     }));
 
-    if (isExpanding && !configFields[key]) {
+    if (isExpanding && configSchemas[key] === undefined) {
       try {
-        const fields = await invoke<ConfigFieldInfo[] | null>(
-          "get_command_config_fields",
-          {
-            module: step.command.module,
-            command: step.command.command,
-          },
+        const schema = await loadConfigSchema(
+          step.command.module,
+          step.command.command,
         );
-        setConfigFields((prev) => ({ ...prev, [key]: fields }));
+        setConfigSchemas((prev) => ({ ...prev, [key]: schema }));
       } catch (err) {
-        console.error("Failed to fetch config fields:", err);
+        console.error("Failed to fetch config schema:", err);
       }
     }
   };
 
   const handleConfigChange = (
-    index: number,
+    commandKey: string,
     value: Record<string, unknown>,
   ) => {
-    setConfigValues((prev) => ({
-      ...prev,
-      [index]: value,
-    }));
+    const next = { ...config };
+    if (Object.keys(value).length === 0) {
+      delete next[commandKey];
+    } else {
+      next[commandKey] = value;
+    }
+    onConfigChange(next);
   };
 
   const getCommandConfigName = (step: PipelineStep): string | undefined => {
@@ -167,6 +205,8 @@ export function PipelineOutput(
     return cmdInfo?.config_name;
   };
 
+  const entries = groupSteps(steps);
+
   return (
     <div class="pipeline-output">
       <div class="output-controls">
@@ -174,9 +214,9 @@ export function PipelineOutput(
           {allExpanded ? "Collapse All" : "Expand All"}
         </button>
       </div>
-      {steps.map((step, i) => {
+      {entries.map(({ step, index: i, audio, rawHtml }, n) => {
         const isExpanded = expanded[i] !== undefined ? expanded[i] : true;
-        const isLastStep = i === steps.length - 1;
+        const isLastStep = n === entries.length - 1;
         return (
           <div
             key={i}
@@ -190,7 +230,11 @@ export function PipelineOutput(
               <span class="step-display">
                 {step.command.module}::{step.command.command}
                 {step.command.id && ` (${step.command.id})`}
+                {audio.length > 1 && ` (${audio.length} chunks)`}
               </span>
+              {step.breakpoint && (
+                <span class="step-breakpoint">Breakpoint hit</span>
+              )}
               <button
                 type="button"
                 class="toggle-btn"
@@ -204,7 +248,7 @@ export function PipelineOutput(
               <>
                 <div class="step-params">
                   <span>{step.command_display}</span>
-                  {step.event_rich_html && (
+                  {(step.event_rich_html || audio.length > 0) && (
                     <div class="header-view-toggle">
                       <button
                         type="button"
@@ -231,7 +275,8 @@ export function PipelineOutput(
                 </div>
                 <InteractiveOutput
                   step={step}
-                  rawHtml={step.event_html}
+                  audio={audio}
+                  rawHtml={rawHtml}
                   viewMode={viewModes[i] || "interactive"}
                 />
                 {getCommandConfigName(step) && (
@@ -248,11 +293,11 @@ export function PipelineOutput(
                     {configExpanded[i] && (() => {
                       const key =
                         `${step.command.module}::${step.command.command}`;
-                      const fields = configFields[key];
-                      if (fields === undefined) {
+                      const schema = configSchemas[key];
+                      if (schema === undefined) {
                         return <div class="config-loading">Loading...</div>;
                       }
-                      if (fields === null) {
+                      if (schema === null) {
                         return (
                           <div class="config-none">
                             No configuration available
@@ -261,9 +306,10 @@ export function PipelineOutput(
                       }
                       return (
                         <ConfigEditor
-                          fields={fields}
-                          value={configValues[i] || {}}
-                          onChange={(value) => handleConfigChange(i, value)}
+                          fields={schema.fields}
+                          value={config[step.command_key] || {}}
+                          onChange={(value) =>
+                            handleConfigChange(step.command_key, value)}
                         />
                       );
                     })()}
@@ -5,19 +5,40 @@ import { type as os } from "@tauri-apps/plugin-os";
 import { useEffect, useState } from "preact/hooks";
 import { useTab } from "../contexts/TabContext";
 import { useWindow } from "../contexts/WindowContext";
-import { BundleInfo, PipelineMetadata, PipelineStep, TabData } from "../types";
+import {
+  AssetDownload,
+  BundleInfo,
+  PipelineMetadata,
+  PipelineStep,
+  RecentBundle,
+  RenderedStep,
+  RuntimeConfig,
+  TabData,
+} from "../types";
+import { ConfigPanel } from "./ConfigPanel";
 import { FluentTester } from "./FluentTester";
 import { InputEditor } from "./InputEditor";
 import { PipelineOutput } from "./PipelineOutput";
 
-type InternalView = "pipeline" | "fluent";
+type InternalView = "pipeline" | "config" | "fluent";
 
 interface TabContentProps {
   isActive: boolean;
 }
 
+function formatMegabytes(bytes: number): string {
+  return (bytes / (1 << 20)).toFixed(0);
+}
+
 export function TabContent({ isActive }: TabContentProps) {
-  const { windowId, refreshTabs } = useWindow();
+  const {
+    windowId,
+    refreshTabs,
+    themes,
+    themeChoice,
+    highlightTheme,
+    setThemeChoice,
+  } = useWindow();
   const { tabId } = useTab();
   const [tabData, setTabData] = useState<TabData | null>(null);
   const [steps, setSteps] = useState<PipelineStep[]>([]);
@@ -25,6 +46,9 @@ export function TabContent({ isActive }: TabContentProps) {
   const [isLoading, setIsLoading] = useState(true);
   const [isBundleLoading, setIsBundleLoading] = useState(false);
   const [pipelines, setPipelines] = useState<PipelineMetadata[]>([]);
+  const [recentBundles, setRecentBundles] = useState<RecentBundle[]>([]);
+  const [breakpoint, setBreakpoint] = useState("");
+  const [download, setDownload] = useState<AssetDownload | null>(null);
 
   // Load tab state from backend ONLY on first mount (not when switching tabs)
   useEffect(() => {
@@ -32,6 +56,9 @@ export function TabContent({ isActive }: TabContentProps) {
       try {
         const data = await invoke<TabData>("get_tab_data", { windowId, tabId });
         setTabData(data);
+        if (data.recording) {
+          setSteps(await recordingSteps());
+        }
       } catch (error) {
         console.error("Failed to load tab data:", error);
       } finally {
@@ -40,6 +67,7 @@ export function TabContent({ isActive }: TabContentProps) {
     }
 
     loadTabState();
+    loadRecentBundles();
   }, []); // Empty dependency array - only run once on mount
 
   useEffect(() => {
@@ -57,10 +85,44 @@ export function TabContent({ isActive }: TabContentProps) {
     };
   }, [windowId, tabId]);
 
+  useEffect(() => {
+    const unlisten = listen<AssetDownload>("asset-download", (event) => {
+      if (
+        event.payload.window_id === windowId && event.payload.tab_id === tabId
+      ) {
+        setDownload(event.payload.done ? null : event.payload);
+      }
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [windowId, tabId]);
+
+  // Show the last run in the window's new theme, from the backend's cache
+  useEffect(() => {
+    if (!highlightTheme || isRunning) return;
+
+    invoke<RenderedStep[]>("rerender_steps", { windowId, tabId })
+      .then((rendered) => {
+        if (rendered.length === 0) return;
+        const html = new Map(
+          rendered.map((x) => [`${x.execution_id}/${x.step_index}`, x]),
+        );
+        setSteps((prev) =>
+          prev.map((step) => {
+            const x = html.get(`${step.execution_id}/${step.step_index}`);
+            return x ? { ...step, event_html: x.event_html } : step;
+          })
+        );
+      })
+      .catch(console.error);
+  }, [highlightTheme]);
+
   // Load available pipelines when bundle is loaded
   useEffect(() => {
     async function loadPipelines() {
-      if (tabData?.bundle_info) {
+      if (tabData?.bundle_info && !tabData.recording) {
         try {
           const pipelineList = await invoke<PipelineMetadata[]>(
             "list_pipelines",
@@ -81,39 +143,94 @@ export function TabContent({ isActive }: TabContentProps) {
     loadPipelines();
   }, [tabData?.bundle_info?.id]);
 
+  async function loadRecentBundles() {
+    try {
+      setRecentBundles(await invoke<RecentBundle[]>("get_recent_bundles"));
+    } catch (error) {
+      console.error("Failed to load recent bundles:", error);
+    }
+  }
+
+  /** The steps of the recording the tab shows, as they were recorded. */
+  function recordingSteps(): Promise<PipelineStep[]> {
+    return invoke<PipelineStep[]>("get_recording_steps", { windowId, tabId });
+  }
+
+  async function loadBundle(path: string, pipelineName: string | null) {
+    setIsBundleLoading(true);
+    try {
+      if (path.endsWith(".drrun")) {
+        await invoke("open_recording", { windowId, tabId, path });
+      } else {
+        await invoke<BundleInfo>("load_bundle", {
+          windowId,
+          tabId,
+          path,
+          pipelineName,
+        });
+      }
+      // Loading a bundle restores the config saved for it
+      const data = await invoke<TabData>("get_tab_data", {
+        windowId,
+        tabId,
+      });
+      setTabData(data);
+      setSteps(data.recording ? await recordingSteps() : []);
+      await refreshTabs();
+    } catch (error) {
+      console.error("Failed to load bundle:", error);
+      alert(`Failed to load bundle: ${error}`);
+    } finally {
+      setIsBundleLoading(false);
+      setDownload(null);
+      loadRecentBundles();
+    }
+  }
+
   async function openBundle() {
     try {
       const selected = await open({
         multiple: false,
         filters: os() === "ios" ? [] : [
           {
-            name: "Divvun Runtime Bundle or TypeScript Pipeline",
-            extensions: ["drb", "ts"],
+            name: "Divvun Runtime Bundle, TypeScript Pipeline or Run Recording",
+            extensions: ["drb", "ts", "drrun"],
           },
         ],
       });
 
       if (selected) {
-        setIsBundleLoading(true);
-        try {
-          const bundleInfo = await invoke<BundleInfo>("load_bundle", {
-            windowId,
-            tabId,
-            path: selected.replace(/^file:\/\//, ""),
-            pipelineName: null,
-          });
-          // Update local state optimistically
-          setTabData({ ...tabData!, bundle_info: bundleInfo });
-          setSteps([]);
-          await refreshTabs();
-        } finally {
-          setIsBundleLoading(false);
-        }
+        await loadBundle(selected.replace(/^file:\/\//, ""), null);
       }
     } catch (error) {
-      console.error("Failed to load bundle:", error);
-      alert(`Failed to load bundle: ${error}`);
-      setIsBundleLoading(false);
+      console.error("Failed to open bundle:", error);
+    }
+  }
+
+  async function saveRun() {
+    if (!tabData?.bundle_info) return;
+
+    try {
+      await invoke("save_recording", { windowId, tabId });
+    } catch (error) {
+      console.error("Failed to save run:", error);
+      alert(`Failed to save run: ${error}`);
+    }
+  }
+
+  async function handleRecentChange(e: Event) {
+    const select = e.currentTarget as HTMLSelectElement;
+    const value = select.value;
+    select.value = "";
+
+    if (value === "__clear") {
+      await invoke("clear_recent").catch(console.error);
+      setRecentBundles([]);
+      return;
+    }
+    const recent = recentBundles.find((b) => b.path === value);
+    if (recent) {
+      await loadBundle(recent.path, recent.pipeline_name);
     }
   }
 
@@ -133,6 +250,17 @@ export function TabContent({ isActive }: TabContentProps) {
     invoke("update_tab_view", { windowId, tabId, view }).catch(console.error);
   }
 
+  async function handleConfigChange(config: RuntimeConfig) {
+    // Recordings keep the config they were recorded with
+    if (tabData?.recording) return;
+    // Optimistic update
+    setTabData({ ...tabData!, config });
+    // Sync to backend, which also saves it for the bundle
+    invoke("update_tab_config", { windowId, tabId, config }).catch(
+      console.error,
+    );
+  }
+
   async function handlePipelineChange(e: Event) {
     const select = e.currentTarget as HTMLSelectElement;
     const newPipeline = select.value;
@@ -155,11 +283,16 @@ export function TabContent({ isActive }: TabContentProps) {
       alert(`Failed to switch pipeline: ${error}`);
     } finally {
       setIsBundleLoading(false);
+      setDownload(null);
     }
   }
 
   async function runPipeline() {
-    if (!tabData?.bundle_info || !tabData.pipeline_input) return;
+    if (
+      !tabData?.bundle_info || tabData.recording || !tabData.pipeline_input
+    ) {
+      return;
+    }
 
     setIsRunning(true);
     setSteps([]);
@@ -169,6 +302,7 @@ export function TabContent({ isActive }: TabContentProps) {
         windowId,
         tabId,
         input: tabData.pipeline_input,
+        breakpoint: breakpoint.trim() || null,
       });
     } catch (error) {
       console.error("Pipeline error:", error);
@@ -192,13 +326,34 @@ export function TabContent({ isActive }: TabContentProps) {
   }
 
   const bundle = tabData.bundle_info;
+  const recording = tabData.recording;
   const activeView = tabData.current_view as InternalView;
 
   return (
     <div class="tab-content" style={{ display: isActive ? "flex" : "none" }}>
       <header class="app-header">
         <div class="header-left">
-          {bundle
+          {bundle && recording
+            ? (
+              <>
+                <span class="bundle-name" title={recording.path}>
+                  Recording: {recording.path}
+                </span>
+                <span
+                  class="recording-info"
+                  title={recording.manifest_hash
+                    ? `Build manifest ${recording.manifest_hash}`
+                    : undefined}
+                >
+                  {bundle.name} ({bundle.pipeline_name}), recorded{" "}
+                  {new Date(recording.started_ms).toLocaleString()} with
+                  runtime {recording.runtime_version}
+                  {recording.dropped > 0 &&
+                    `, ${recording.dropped} steps not recorded`}
+                </span>
+              </>
+            )
+            : bundle
             ? (
               <>
                 <span class="bundle-name">
@@ -223,8 +378,57 @@ export function TabContent({ isActive }: TabContentProps) {
               </>
             )
             : <span class="bundle-name">No bundle loaded</span>}
+          {download && (
+            <span class="download-progress" title={download.url}>
+              Downloading {download.path}: {formatMegabytes(download.downloaded)}
+              {download.total !== null &&
+                ` of ${formatMegabytes(download.total)}`} MB
+            </span>
+          )}
         </div>
         <div class="header-right">
+          {recentBundles.length > 0 && (
+            <select
+              class="recent-selector"
+              value=""
+              onChange={handleRecentChange}
+              onFocus={loadRecentBundles}
+              disabled={isBundleLoading}
+            >
+              <option value="" disabled>Open Recent</option>
+              {recentBundles.map((b) => (
+                <option key={b.path} value={b.path} title={b.path}>
+                  {b.name}
+                  {b.pipeline_name && ` (${b.pipeline_name})`}
+                </option>
+              ))}
+              <option value="__clear">Clear Recent</option>
+            </select>
+          )}
+          {themes.length > 0 && (
+            <select
+              class="theme-selector"
+              value={themeChoice ?? ""}
+              onChange={(e) =>
+                setThemeChoice(e.currentTarget.value || null)}
+              title={`Highlighting: ${highlightTheme}`}
+            >
+              <option value="">Theme: follow system</option>
+              {themes.map((theme) => (
+                <option key={theme} value={theme}>{theme}</option>
+              ))}
+            </select>
+          )}
+          {bundle && (
+            <button
+              type="button"
+              onClick={saveRun}
+              disabled={isRunning || steps.length === 0}
+              title="Save the last run to open again without the bundle"
+            >
+              Save Run
+            </button>
+          )}
           <button type="button" onClick={openBundle}>Open Bundle</button>
         </div>
       </header>
@@ -238,6 +442,15 @@ export function TabContent({ isActive }: TabContentProps) {
         >
           Pipeline
         </button>
+        <button
+          type="button"
+          class={activeView === "config" ? "tab active" : "tab"}
+          onClick={() =>
+            handleViewChange("config")}
+          disabled={!bundle || !!recording}
+        >
+          Config
+        </button>
         <button
           type="button"
           class={activeView === "fluent" ? "tab active" : "tab"}
@@ -258,23 +471,48 @@ export function TabContent({ isActive }: TabContentProps) {
                   bundle={bundle}
                   isRunning={isRunning}
                   isBundleLoading={isBundleLoading}
+                  config={tabData.config}
+                  onConfigChange={handleConfigChange}
                 />
               </div>
 
               <div class="input-container">
+                <input
+                  type="text"
+                  class="breakpoint-input"
+                  value={breakpoint}
+                  onInput={(e) => setBreakpoint(e.currentTarget.value)}
+                  placeholder='Breakpoint, e.g. suggest contains "girjii"'
+                  spellcheck={false}
+                  disabled={!bundle || !!recording}
+                />
                 <InputEditor
                   value={tabData.pipeline_input}
                   onChange={handleInputChange}
                   onRun={runPipeline}
-                  disabled={isRunning || !bundle}
+                  disabled={isRunning || !bundle || !!recording}
                   running={isRunning}
                 />
               </div>
             </>
           )
+          : activeView === "config" && bundle && !recording
+          ? (
+            <div class="config-container">
+              <ConfigPanel
+                bundle={bundle}
+                config={tabData.config}
+                onChange={handleConfigChange}
+              />
+            </div>
+          )
           : (
             <div class="fluent-container">
-              <FluentTester windowId={windowId} tabId={tabId} bundle={bundle} />
+              <FluentTester
+                windowId={windowId}
+                tabId={tabId}
+                bundle={recording ? null : bundle}
+              />
             </div>
           )}
       </main>
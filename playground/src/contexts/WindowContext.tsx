@@ -2,8 +2,36 @@ import { createContext } from "preact";
 import { useContext, useEffect, useState } from "preact/hooks";
 import { invoke } from "@tauri-apps/api/core";
 import { getCurrentWindow } from "@tauri-apps/api/window";
+import { getCurrentWebview } from "@tauri-apps/api/webview";
 import type { TabInfo, WindowStateInfo } from "../types";
 
+/**
+ * Files the playground can open: bundles, TypeScript pipelines and run
+ * recordings.
+ */
+export function isOpenablePath(path: string): boolean {
+  return path.endsWith(".drb") || path.endsWith(".ts") ||
+    path.endsWith(".drrun");
+}
+
+/** Open a bundle, or a `.drrun` recording read-only, in a tab. */
+export async function openPathInTab(
+  windowId: string,
+  tabId: string,
+  path: string,
+): Promise<void> {
+  if (path.endsWith(".drrun")) {
+    await invoke("open_recording", { windowId, tabId, path });
+  } else {
+    await invoke("load_bundle", {
+      windowId,
+      tabId,
+      path,
+      pipelineName: null,
+    });
+  }
+}
+
 interface WindowContextValue {
   windowId: string;
   tabs: TabInfo[];
@@ -13,6 +41,14 @@ interface WindowContextValue {
   switchTab: (index: number) => Promise<void>;
   duplicateTab: (tabId: string) => Promise<void>;
   refreshTabs: () => Promise<void>;
+  openBundleInNewTab: (path: string) => Promise<void>;
+  /** Highlighting themes to choose from. */
+  themes: string[];
+  /** The theme chosen for the window, or `null` to follow the OS. */
+  themeChoice: string | null;
+  /** The theme pipeline output is highlighted with. */
+  highlightTheme: string;
+  setThemeChoice: (theme: string | null) => void;
 }
 
 const WindowContext = createContext<WindowContextValue | null>(null);
@@ -23,6 +59,10 @@ export function WindowProvider(
   const [windowId, setWindowId] = useState<string>("");
   const [tabs, setTabs] = useState<TabInfo[]>([]);
   const [activeTabIndex, setActiveTabIndex] = useState(0);
+  const [themes, setThemes] = useState<string[]>([]);
+  const [themeChoice, setThemeChoice] = useState<string | null>(null);
+  const [dark, setDark] = useState(true);
+  const [highlightTheme, setHighlightTheme] = useState("");
 
   useEffect(() => {
     async function init() {
@@ -34,6 +74,25 @@ export function WindowProvider(
         const state = await invoke<WindowStateInfo>("init_window", {
           windowId: label,
         });
+
+        // A bundle or recording given on the command line or double-clicked
+        // in the file manager opens in the first tab, before the tab reads
+        // its state.
+        const initialPath = await invoke<string | null>("get_cli_args");
+        if (initialPath) {
+          const tab = state.tabs[state.active_tab_index];
+          try {
+            await openPathInTab(label, tab.tab_id, initialPath);
+            const loaded = await invoke<WindowStateInfo>("get_window_state", {
+              windowId: label,
+            });
+            state.tabs = loaded.tabs;
+          } catch (error) {
+            console.error("Failed to open initial bundle:", error);
+            alert(`Failed to open ${initialPath}: ${error}`);
+          }
+        }
+
         setTabs(state.tabs);
         setActiveTabIndex(state.active_tab_index);
       } catch (error) {
@@ -69,6 +128,51 @@ export function WindowProvider(
     };
   }, [tabs, activeTabIndex]);
 
+  useEffect(() => {
+    if (!windowId) return;
+
+    // Bundles dropped onto the window each open in a new tab
+    const unlisten = getCurrentWebview().onDragDropEvent(async (event) => {
+      if (event.payload.type !== "drop") return;
+      for (const path of event.payload.paths.filter(isOpenablePath)) {
+        await openBundleInNewTab(path);
+      }
+    });
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [windowId]);
+
+  useEffect(() => {
+    if (!windowId) return;
+
+    // Highlighting follows the OS's dark or light mode unless a theme is
+    // chosen for the window
+    const window = getCurrentWindow();
+    invoke<string[]>("list_themes").then(setThemes).catch(console.error);
+    window.theme().then((theme) => setDark(theme !== "light")).catch(
+      console.error,
+    );
+    const unlisten = window.onThemeChanged(({ payload }) =>
+      setDark(payload !== "light")
+    );
+
+    return () => {
+      unlisten.then((f) => f());
+    };
+  }, [windowId]);
+
+  useEffect(() => {
+    if (!windowId) return;
+
+    invoke<string>("set_window_theme", {
+      windowId,
+      theme: themeChoice,
+      dark,
+    }).then(setHighlightTheme).catch(console.error);
+  }, [windowId, themeChoice, dark]);
+
   const refreshTabs = async () => {
     if (!windowId) return;
 
@@ -130,6 +234,22 @@ export function WindowProvider(
     }
   };
 
+  const openBundleInNewTab = async (path: string) => {
+    if (!windowId) return;
+
+    try {
+      const tab = await invoke<TabInfo>("create_tab", { windowId });
+      try {
+        await openPathInTab(windowId, tab.tab_id, path);
+      } finally {
+        await refreshTabs();
+      }
+    } catch (error) {
+      console.error("Failed to open bundle:", error);
+      alert(`Failed to open ${path}: ${error}`);
+    }
+  };
+
   const value: WindowContextValue = {
     windowId,
     tabs,
@@ -139,6 +259,11 @@ export function WindowProvider(
     switchTab,
     duplicateTab,
     refreshTabs,
+    openBundleInNewTab,
+    themes,
+    themeChoice,
+    highlightTheme,
+    setThemeChoice,
   };
 
   return (
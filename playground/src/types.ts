@@ -30,10 +30,27 @@ export interface CommandInfo {
   config_name?: string;
 }
 
-export interface ConfigFieldInfo {
+export interface ConfigFieldSchema {
   name: string;
   doc: string[];
-  type_name: string;
+  ty: string;
+  optional: boolean;
+}
+
+export interface ConfigSchema {
+  name: string;
+  fields: ConfigFieldSchema[];
+}
+
+/** Runtime config keyed by command key, as passed to `run -c`. */
+export type RuntimeConfig = Record<string, Record<string, unknown>>;
+
+export interface StepHeader {
+  key: string;
+  module: string;
+  command: string;
+  kind?: string;
+  returns: string;
 }
 
 export interface PipelineStep {
@@ -42,6 +59,7 @@ export interface PipelineStep {
   execution_id: string;
   step_index: number;
   command_key: string;
+  header: StepHeader;
   command: {
     module: string;
     command: string;
@@ -53,6 +71,34 @@ export interface PipelineStep {
   kind?: string;
   value_type?: string;
   event_rich_html?: string;
+  /** Base64 WAV when the step emitted audio. */
+  audio?: string;
+  /** The run stopped here on the breakpoint. */
+  breakpoint?: boolean;
+}
+
+/** Progress of a remote asset downloading while a bundle loads. */
+export interface AssetDownload {
+  window_id: string;
+  tab_id: string;
+  path: string;
+  url: string;
+  downloaded: number;
+  total: number | null;
+  done: boolean;
+}
+
+/** A step of the last run, highlighted again in another theme. */
+export interface RenderedStep {
+  execution_id: string;
+  step_index: number;
+  event_html: string;
+}
+
+export interface RecentBundle {
+  path: string;
+  name: string;
+  pipeline_name: string | null;
 }
 
 export interface TabInfo {
@@ -75,4 +121,18 @@ export interface TabData {
   fluent_file: string | null;
   fluent_message: string | null;
   fluent_args: Record<string, string>;
+  config: RuntimeConfig;
+  /** Set when the tab shows a run recording rather than a bundle. */
+  recording: RecordingInfo | null;
+}
+
+/** A `.drrun` recording open in a tab. */
+export interface RecordingInfo {
+  path: string;
+  runtime_version: string;
+  started_ms: number;
+  /** BLAKE3 of the bundle's build manifest, absent for dev paths. */
+  manifest_hash: string | null;
+  /** Steps the run emitted beyond what was recorded. */
+  dropped: number;
 }
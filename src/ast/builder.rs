@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+
+use indexmap::IndexMap;
+
+use super::{Arg, Command, Entry, Error, InputValue, PipelineDefinition, Ref};
+use crate::modules::{self, CommandDef, Ty};
+
+/// Reference to the pipeline's input, usable as the source of [`PipelineBuilder::pipe`].
+pub const ENTRY: &str = "#/entry";
+
+/// Build a [`PipelineDefinition`] in code instead of from a TypeScript
+/// pipeline, checked against the registered commands when built.
+///
+/// Each command reads the output of the command added before it (the first
+/// one reads the entry) unless [`pipe`](Self::pipe) says otherwise. Without
+/// [`output`](Self::output), the last command is the output.
+///
+/// ```no_run
+/// # use divvun_runtime::{ast::{Arg, PipelineBuilder}, modules::Ty};
+/// let defn = PipelineBuilder::new()
+///     .entry(Ty::String)
+///     .cmd("tok", "hfst", "tokenize", [("model_path", Arg::path("tokenizer.pmhfst"))])
+///     .cmd("cg", "cg3", "vislcg3", [("model_path", Arg::path("grammar.bin"))])
+///     .pipe("tok", "cg")
+///     .output("cg")
+///     .build()?;
+/// # Ok::<_, divvun_runtime::ast::Error>(())
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PipelineBuilder {
+    entry: Option<Ty>,
+    commands: IndexMap<String, PendingCommand>,
+    pipes: HashMap<String, String>,
+    defaults: HashMap<String, serde_json::Value>,
+    output: Option<String>,
+    dev: bool,
+}
+
+#[derive(Debug, Clone)]
+struct PendingCommand {
+    module: String,
+    command: String,
+    args: HashMap<String, Arg>,
+}
+
+impl PipelineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Type of the value fed into the pipeline. Defaults to a string.
+    pub fn entry(mut self, ty: Ty) -> Self {
+        self.entry = Some(ty);
+        self
+    }
+
+    /// Add `module::command` under `key`.
+    pub fn cmd<K: Into<String>>(
+        mut self,
+        key: impl Into<String>,
+        module: impl Into<String>,
+        command: impl Into<String>,
+        args: impl IntoIterator<Item = (K, Arg)>,
+    ) -> Self {
+        self.commands.insert(
+            key.into(),
+            PendingCommand {
+                module: module.into(),
+                command: command.into(),
+                args: args.into_iter().map(|(k, v)| (k.into(), v)).collect(),
+            },
+        );
+        self
+    }
+
+    /// Runtime config for the command under `key` when the caller gives it
+    /// none, merged under whatever the caller does give it.
+    pub fn defaults(mut self, key: impl Into<String>, defaults: serde_json::Value) -> Self {
+        self.defaults.insert(key.into(), defaults);
+        self
+    }
+
+    /// Feed the output of `from` (a command key or [`ENTRY`]) into `to`.
+    pub fn pipe(mut self, from: impl Into<String>, to: impl Into<String>) -> Self {
+        self.pipes.insert(to.into(), from.into());
+        self
+    }
+
+    pub fn output(mut self, key: impl Into<String>) -> Self {
+        self.output = Some(key.into());
+        self
+    }
+
+    /// Allow `@` asset paths relative to the base path of the context.
+    pub fn dev(mut self, dev: bool) -> Self {
+        self.dev = dev;
+        self
+    }
+
+    /// Check the pipeline and produce its definition. Fails on unknown
+    /// commands or arguments, missing required arguments, argument or input
+    /// types that don't match what a command declares, and commands that
+    /// can't be reached from the entry.
+    pub fn build(self) -> Result<PipelineDefinition, Error> {
+        let entry = self.entry.unwrap_or(Ty::String);
+        let Some(output) = self.output.or_else(|| self.commands.keys().last().cloned()) else {
+            return Err(invalid("pipeline has no commands"));
+        };
+        if !self.commands.contains_key(&output) {
+            return Err(invalid(format!("output '{}' is not a command", output)));
+        }
+        for (to, from) in &self.pipes {
+            if !self.commands.contains_key(to) {
+                return Err(invalid(format!("pipe into unknown command '{}'", to)));
+            }
+            if from != ENTRY && !self.commands.contains_key(from) {
+                return Err(invalid(format!("pipe from unknown command '{}'", from)));
+            }
+        }
+        if let Some(key) = self
+            .defaults
+            .keys()
+            .find(|k| !self.commands.contains_key(*k))
+        {
+            return Err(invalid(format!("defaults for unknown command '{}'", key)));
+        }
+
+        let mut defs = HashMap::new();
+        let mut commands = IndexMap::new();
+        let mut previous = ENTRY.to_string();
+        for (key, pending) in &self.commands {
+            let def =
+                modules::find_command(&pending.module, &pending.command).ok_or_else(|| {
+                    invalid(format!(
+                        "{}: unknown command {}::{}",
+                        key, pending.module, pending.command
+                    ))
+                })?;
+            check_args(key, def, &pending.args)?;
+            defs.insert(key.as_str(), def);
+
+            let input = self.pipes.get(key).cloned().unwrap_or(previous);
+            if &input == key {
+                return Err(invalid(format!("{}: command reads its own output", key)));
+            }
+            previous = key.clone();
+
+            commands.insert(
+                key.clone(),
+                Command {
+                    module: pending.module.clone(),
+                    command: pending.command.clone(),
+                    args: pending.args.clone(),
+                    input: InputValue::Single(Ref { r#ref: input }),
+                    returns: def.returns.as_dr_type().into_owned(),
+                    kind: def.kind.map(str::to_string),
+                    defaults: self.defaults.get(key).cloned(),
+                },
+            );
+        }
+
+        // Walk from the entry so every command's input type is known before it
+        // is checked, and anything left over is part of a cycle.
+        let mut types: HashMap<&str, Ty> = HashMap::from([(ENTRY, entry.clone())]);
+        while types.len() <= commands.len() {
+            let ready = commands.iter().find_map(|(key, command)| {
+                let InputValue::Single(input) = &command.input else {
+                    return None;
+                };
+                if types.contains_key(key.as_str()) {
+                    return None;
+                }
+                types.get(input.r#ref.as_str()).map(|ty| (key, ty.clone()))
+            });
+            let Some((key, input_ty)) = ready else {
+                let stuck = commands
+                    .keys()
+                    .filter(|key| !types.contains_key(key.as_str()))
+                    .cloned()
+                    .collect::<Vec<_>>();
+                return Err(invalid(format!(
+                    "commands not reachable from the entry: {}",
+                    stuck.join(", ")
+                )));
+            };
+            let def = defs[key.as_str()];
+            if !accepts(def.input, &input_ty) {
+                return Err(invalid(format!(
+                    "{}: {}::{} takes {} but is given {}",
+                    key,
+                    def.module,
+                    def.name,
+                    Ty::Union(def.input.to_vec()).as_dr_type(),
+                    input_ty.as_dr_type()
+                )));
+            }
+            types.insert(key.as_str(), def.returns.clone());
+        }
+
+        Ok(PipelineDefinition {
+            entry: Entry {
+                value_type: entry.as_dr_type().into_owned(),
+            },
+            output: Ref { r#ref: output },
+            commands,
+            dev: self.dev,
+        })
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::Command(modules::Error::msg(msg))
+}
+
+fn check_args(key: &str, def: &CommandDef, args: &HashMap<String, Arg>) -> Result<(), Error> {
+    for name in args.keys() {
+        if !def.args.iter().any(|x| x.name == name) {
+            return Err(invalid(format!(
+                "{}: {}::{} has no argument '{}'",
+                key, def.module, def.name, name
+            )));
+        }
+    }
+
+    for spec in def.args {
+        let Some(arg) = args.get(spec.name).filter(|x| x.value.is_some()) else {
+            if spec.optional {
+                continue;
+            }
+            return Err(invalid(format!(
+                "{}: missing required argument '{}'",
+                key, spec.name
+            )));
+        };
+        let expected = spec.ty.as_dr_type();
+        let json_like = matches!(spec.ty, Ty::Json | Ty::Struct(_)) && arg.r#type == "json";
+        if arg.r#type != expected && !json_like {
+            return Err(invalid(format!(
+                "{}: argument '{}' should be {}, not {}",
+                key, spec.name, expected, arg.r#type
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Whether a command declaring `inputs` can read a value of type `ty`.
+/// Commands that declare no input types take anything. Arrays are sent on
+/// one element at a time, so a command taking strings can read `[string]`.
+fn accepts(inputs: &[Ty], ty: &Ty) -> bool {
+    fn names(ty: &Ty, out: &mut HashSet<String>) {
+        match ty {
+            Ty::Union(tys) => tys.iter().for_each(|x| names(x, out)),
+            Ty::ArrayString => {
+                out.insert(Ty::String.as_dr_type().into_owned());
+                out.insert(ty.as_dr_type().into_owned());
+            }
+            Ty::ArrayBytes => {
+                out.insert(Ty::Bytes.as_dr_type().into_owned());
+                out.insert(ty.as_dr_type().into_owned());
+            }
+            ty => {
+                out.insert(ty.as_dr_type().into_owned());
+            }
+        }
+    }
+
+    if inputs.is_empty() {
+        return true;
+    }
+    let mut accepted = HashSet::new();
+    inputs.iter().for_each(|x| names(x, &mut accepted));
+    let mut given = HashSet::new();
+    names(ty, &mut given);
+    !accepted.is_disjoint(&given)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chains_commands_in_order() {
+        let defn = PipelineBuilder::new()
+            .cmd("rev", "example", "reverse", Vec::<(String, Arg)>::new())
+            .cmd("up", "example", "upper", Vec::<(String, Arg)>::new())
+            .build()
+            .unwrap();
+
+        assert_eq!(defn.entry.value_type, "string");
+        assert_eq!(defn.output.r#ref, "up");
+        let InputValue::Single(input) = &defn.commands["up"].input else {
+            panic!("expected a single input");
+        };
+        assert_eq!(input.r#ref, "rev");
+        assert_eq!(defn.commands["rev"].returns, "string");
+    }
+
+    #[test]
+    fn explicit_pipes_override_order() {
+        let defn = PipelineBuilder::new()
+            .cmd("up", "example", "upper", Vec::<(String, Arg)>::new())
+            .cmd("rev", "example", "reverse", Vec::<(String, Arg)>::new())
+            .pipe(ENTRY, "rev")
+            .pipe("rev", "up")
+            .output("up")
+            .build()
+            .unwrap();
+        let InputValue::Single(input) = &defn.commands["up"].input else {
+            panic!("expected a single input");
+        };
+        assert_eq!(input.r#ref, "rev");
+    }
+
+    #[test]
+    fn rejects_invalid_pipelines() {
+        let none = Vec::<(String, Arg)>::new;
+
+        let err = PipelineBuilder::new()
+            .cmd("x", "example", "nope", none())
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("unknown command example::nope"));
+
+        let err = PipelineBuilder::new()
+            .cmd("rev", "example", "reverse", [("model", Arg::path("x"))])
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("has no argument 'model'"));
+
+        let err = PipelineBuilder::new()
+            .entry(Ty::Bytes)
+            .cmd("rev", "example", "reverse", none())
+            .build()
+            .unwrap_err();
+        assert!(err.to_string().contains("takes string but is given bytes"));
+
+        let err = PipelineBuilder::new()
+            .cmd("rev", "example", "reverse", none())
+            .defaults("up", serde_json::json!({}))
+            .build()
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("defaults for unknown command 'up'")
+        );
+
+        let err = PipelineBuilder::new()
+            .cmd("a", "example", "reverse", none())
+            .cmd("b", "example", "upper", none())
+            .pipe("b", "a")
+            .build()
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("not reachable from the entry: a, b")
+        );
+    }
+}
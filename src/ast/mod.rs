@@ -3,7 +3,9 @@ use std::path::PathBuf;
 use std::pin::Pin;
 use std::{collections::HashMap, fmt::Display, sync::Arc};
 
-use crate::modules::{CommandRunner, PipelineEvent, PipelineValueRx, PipelineValueTx, Tap, TapFn};
+use crate::modules::{
+    CommandRunner, LiveConfig, PipelineEvent, PipelineValueRx, PipelineValueTx, Tap, TapFn,
+};
 use futures_util::Stream;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
@@ -11,10 +13,15 @@ use tokio::sync::{Mutex, broadcast};
 use tokio::task::JoinHandle;
 
 use crate::{
-    modules::{Context, PipelineValue},
+    modules::{Context, PipelineValue, Ty},
     ts::MODULES,
+    util::channel::Channels,
 };
 
+mod builder;
+
+pub use builder::{ENTRY, PipelineBuilder};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Ref {
     pub r#ref: String,
@@ -43,24 +50,58 @@ pub struct PipelineDefinition {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PipelineBundle {
     pub version: u32,
+    /// Oldest divvun-runtime version able to run the bundle, e.g. `"0.5.0"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_runtime_version: Option<String>,
     pub default: String,
     pub pipelines: IndexMap<String, PipelineDefinition>,
+    /// Assets downloaded the first time they are loaded instead of being
+    /// bundled, keyed by the path pipeline args name them by.
+    #[serde(default, skip_serializing_if = "IndexMap::is_empty")]
+    pub remote_assets: IndexMap<String, RemoteAsset>,
+}
+
+/// An asset of a bundle served from `url`. See
+/// [`util::download`](crate::util::download).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteAsset {
+    pub url: String,
+    /// BLAKE3 digest of the file, as hex.
+    pub hash: String,
+    /// Size in bytes, for progress when the server doesn't send a length.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
 }
 
 impl PipelineBundle {
     pub fn from_json(json: serde_json::Value) -> Result<Self, serde_json::Error> {
-        if json.get("version").is_some() {
-            serde_json::from_value(json)
+        if let Some(version) = json.get("version") {
+            let version = version.as_u64();
+            serde_json::from_value(json).map_err(|e| match version {
+                // A newer schema may not parse at all; say so rather than
+                // reporting whatever field it trips over.
+                Some(v) if v > u64::from(crate::compat::SCHEMA_VERSION) => {
+                    <serde_json::Error as serde::de::Error>::custom(format!(
+                        "pipeline.json uses schema version {}, but this runtime reads up to {}; upgrade divvun-runtime ({})",
+                        v,
+                        crate::compat::SCHEMA_VERSION,
+                        e
+                    ))
+                }
+                _ => e,
+            })
         } else {
             let pipeline: PipelineDefinition = serde_json::from_value(json)?;
             Ok(PipelineBundle {
                 version: 1,
+                min_runtime_version: None,
                 default: "default".to_string(),
                 pipelines: {
                     let mut map = IndexMap::new();
                     map.insert("default".to_string(), pipeline);
                     map
                 },
+                remote_assets: IndexMap::new(),
             })
         }
     }
@@ -74,16 +115,29 @@ impl PipelineBundle {
         self.pipelines.keys().map(|s| s.as_str()).collect()
     }
 
+    /// Paths of the assets the bundle's release pipelines read from the
+    /// bundle, leaving out remote assets.
     pub fn assets(&self) -> Vec<PathBuf> {
         self.pipelines
             .values()
             .filter(|p| !p.dev)
             .flat_map(|p| p.assets())
+            .filter(|x| {
+                !x.to_str()
+                    .is_some_and(|x| self.remote_assets.contains_key(x))
+            })
             .collect()
     }
 }
 
 impl PipelineDefinition {
+    /// Header of the step whose values the pipeline outputs.
+    pub fn output_header(&self) -> Option<StepHeader> {
+        self.output
+            .resolve(self)
+            .map(|cmd| cmd.header(&self.output.r#ref))
+    }
+
     pub fn assets(&self) -> Vec<PathBuf> {
         self.commands
             .values()
@@ -132,9 +186,44 @@ pub struct Command {
     pub returns: String,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub kind: Option<String>,
+    /// Runtime config the bundle author set for this command, which the
+    /// caller's config for it is merged over (see [`merge_config`]).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub defaults: Option<serde_json::Value>,
+}
+
+/// Deep-merge `overlay` over `base`: objects are merged key by key, anything
+/// else in `overlay` replaces what `base` has, and a null `overlay` (or null
+/// member) leaves `base` as it is.
+pub fn merge_config(base: &serde_json::Value, overlay: &serde_json::Value) -> serde_json::Value {
+    match (base, overlay) {
+        (base, serde_json::Value::Null) => base.clone(),
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            let mut merged = base.clone();
+            for (key, value) in overlay {
+                let value = match base.get(key) {
+                    Some(base) => merge_config(base, value),
+                    None => value.clone(),
+                };
+                merged.insert(key.clone(), value);
+            }
+            serde_json::Value::Object(merged)
+        }
+        (_, overlay) => overlay.clone(),
+    }
 }
 
 impl Command {
+    /// Config to run this command with: the caller's `config` merged over
+    /// the command's bundle defaults.
+    pub fn runtime_config(&self, config: Option<&serde_json::Value>) -> serde_json::Value {
+        let config = config.unwrap_or(&serde_json::Value::Null);
+        match &self.defaults {
+            Some(defaults) => merge_config(defaults, config),
+            None => config.clone(),
+        }
+    }
+
     pub fn as_str(&self, colors: Option<&syntax_highlight::CommandColors>) -> String {
         let mut result = String::new();
 
@@ -204,6 +293,30 @@ impl Command {
 
         result
     }
+
+    /// Describe this command as pipeline step `key`, for tap consumers.
+    pub fn header(&self, key: &str) -> StepHeader {
+        StepHeader {
+            key: key.to_string(),
+            module: self.module.clone(),
+            command: self.command.clone(),
+            kind: self.kind.clone(),
+            returns: self.returns.clone(),
+        }
+    }
+}
+
+/// Which step produced an event, so frontends can pick a renderer (e.g. a
+/// CG3 view when `kind` is `"cg3"`) without hardcoding module names.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepHeader {
+    pub key: String,
+    pub module: String,
+    pub command: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    /// Pipeline type of the step's values, e.g. `"string"` or `"json"`.
+    pub returns: String,
 }
 
 impl Display for Command {
@@ -212,7 +325,7 @@ impl Display for Command {
     }
 }
 
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Value {
     Int(isize),
@@ -289,7 +402,61 @@ impl Display for Value {
     }
 }
 
+impl From<isize> for Value {
+    fn from(value: isize) -> Self {
+        Value::Int(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Value::Bool(value)
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Self {
+        Value::String(value)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::String(value.to_string())
+    }
+}
+
+impl From<PathBuf> for Value {
+    fn from(value: PathBuf) -> Self {
+        Value::String(value.to_string_lossy().into_owned())
+    }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(value: Vec<T>) -> Self {
+        Value::Array(value.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<Value>> From<IndexMap<String, T>> for Value {
+    fn from(value: IndexMap<String, T>) -> Self {
+        Value::Map(value.into_iter().map(|(k, v)| (k, v.into())).collect())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(Value::Null)
+    }
+}
+
 impl Value {
+    /// Convert arbitrary JSON into a `Value`. Fails for non-integer numbers,
+    /// which pipeline arguments can't represent.
+    pub fn from_json(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        serde_json::from_value(value)
+    }
+
     pub fn try_as_int(&self) -> Option<isize> {
         match self {
             Value::Int(x) => Some(*x),
@@ -382,15 +549,82 @@ impl Value {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Arg {
     pub r#type: String,
     pub value_type: Option<String>,
     pub value: Option<Value>,
 }
 
+impl Arg {
+    /// An argument of the given pipeline type (e.g. `"path"`, `"[string]"`).
+    pub fn new(r#type: impl Into<String>, value: impl Into<Value>) -> Self {
+        Arg {
+            r#type: r#type.into(),
+            value_type: None,
+            value: Some(value.into()),
+        }
+    }
+
+    pub fn typed(ty: Ty, value: impl Into<Value>) -> Self {
+        Self::new(ty.as_dr_type(), value)
+    }
+
+    pub fn path(value: impl Into<PathBuf>) -> Self {
+        Self::typed(Ty::Path, value.into())
+    }
+
+    pub fn string(value: impl Into<String>) -> Self {
+        Self::typed(Ty::String, value.into())
+    }
+
+    pub fn int(value: isize) -> Self {
+        Self::typed(Ty::Int, value)
+    }
+
+    pub fn array_string<S: Into<String>>(values: impl IntoIterator<Item = S>) -> Self {
+        Self::typed(
+            Ty::ArrayString,
+            values.into_iter().map(Into::into).collect::<Vec<String>>(),
+        )
+    }
+
+    pub fn map_path<K: Into<String>, P: Into<PathBuf>>(
+        values: impl IntoIterator<Item = (K, P)>,
+    ) -> Self {
+        Self::typed(
+            Ty::MapPath,
+            values
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect::<IndexMap<String, PathBuf>>(),
+        )
+    }
+
+    pub fn map_string<K: Into<String>, V: Into<String>>(
+        values: impl IntoIterator<Item = (K, V)>,
+    ) -> Self {
+        Self::typed(
+            Ty::MapString,
+            values
+                .into_iter()
+                .map(|(k, v)| (k.into(), v.into()))
+                .collect::<IndexMap<String, String>>(),
+        )
+    }
+
+    pub fn json(value: serde_json::Value) -> Result<Self, serde_json::Error> {
+        Ok(Self::typed(Ty::Json, Value::from_json(value)?))
+    }
+
+    pub fn with_value_type(mut self, value_type: impl Into<String>) -> Self {
+        self.value_type = Some(value_type.into());
+        self
+    }
+}
+
 pub struct Pipe {
-    _context: Arc<Context>,
+    context: Arc<Context>,
     modules: IndexMap<String, Arc<dyn CommandRunner + Send + Sync>>,
     pub(crate) defn: Arc<PipelineDefinition>,
 }
@@ -400,12 +634,179 @@ pub enum Error {
     #[error("{0}")]
     #[diagnostic(transparent)]
     Command(#[from] crate::modules::Error),
+    #[error("{0}")]
+    #[diagnostic(transparent)]
+    Construction(#[from] ConstructionErrors),
+}
+
+/// A command of a pipeline that could not be created.
+#[derive(Debug, Clone)]
+pub struct CommandError {
+    pub key: String,
+    pub module: String,
+    pub command: String,
+    pub error: crate::modules::Error,
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}::{}): {}",
+            self.key, self.module, self.command, self.error
+        )
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Every command of a pipeline that could not be created, so they can all be
+/// fixed in one go.
+#[derive(Debug, Clone)]
+pub struct ConstructionErrors {
+    pub errors: Vec<CommandError>,
 }
 
+impl Display for ConstructionErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} of the pipeline's commands could not be created:",
+            self.errors.len()
+        )?;
+        for error in &self.errors {
+            write!(f, "\n  - {}{}", error, error.error.location())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ConstructionErrors {}
+
+impl miette::Diagnostic for ConstructionErrors {}
+
+/// A running pipeline. Any number of documents can be sent through one
+/// handle: each [`forward`](Self::forward) sends one, and its stream yields
+/// that document's output only, however the calls and the reading of their
+/// streams interleave.
 pub struct PipelineHandle {
     handles: Vec<JoinHandle<Result<(), crate::modules::Error>>>,
     input: Arc<Mutex<PipelineValueTx>>,
-    output: PipelineValueRx,
+    documents: Arc<std::sync::Mutex<Documents>>,
+    entry: Entry,
+    /// Commands reading the pipeline's input, for errors about it.
+    entry_commands: Vec<String>,
+    /// Each command's runtime config, for [`update_config`](Self::update_config).
+    configs: Vec<(String, Arc<Command>, LiveConfig)>,
+    /// Output each document's stream buffers.
+    capacity: usize,
+}
+
+type DocumentTx = tokio::sync::mpsc::Sender<Result<PipelineValue, crate::modules::Error>>;
+
+/// An item for a document's stream, sent once the [`Documents`] lock is
+/// released, as a reader that is behind makes the send wait.
+type Delivery = (DocumentTx, Result<PipelineValue, crate::modules::Error>);
+
+/// The documents sent through a handle, by request id, for routing the
+/// pipeline's output back to them. Commands keep output in input order and end
+/// each input's output with Finish, so the output belongs to the oldest
+/// document whose Finish hasn't come yet.
+#[derive(Default)]
+struct Documents {
+    /// Id of the next document sent.
+    next: u64,
+    /// Id of the document the output belongs to.
+    current: u64,
+    /// Streams of the documents whose output hasn't ended. A document whose
+    /// stream was dropped stays out; its output is discarded.
+    open: std::collections::BTreeMap<u64, DocumentTx>,
+    /// For each Cancel not yet through the pipeline, the id of the first
+    /// document sent after it.
+    cancels: std::collections::VecDeque<u64>,
+    /// Why the pipeline stopped, once it has.
+    stopped: Option<crate::modules::Error>,
+}
+
+impl Documents {
+    /// Route `event`, returning what it delivers to the documents' streams.
+    fn route(&mut self, event: PipelineEvent) -> Vec<Delivery> {
+        match event {
+            PipelineEvent::Value(value) => {
+                return self
+                    .open
+                    .get(&self.current)
+                    .map(|tx| (tx.clone(), Ok(value)))
+                    .into_iter()
+                    .collect();
+            }
+            PipelineEvent::Finish => {
+                tracing::debug!("pipeline: document {} finished", self.current);
+                self.open.remove(&self.current);
+                self.current += 1;
+            }
+            // Cancel ends every document sent before it. A command may pass it
+            // on in place of the Finish of the input it abandoned, or after
+            // that Finish.
+            PipelineEvent::Cancel => {
+                let Some(until) = self.cancels.pop_front() else {
+                    return Vec::new();
+                };
+                tracing::debug!("pipeline: documents before {until} cancelled");
+                self.open.retain(|id, _| *id >= until);
+                self.current = self.current.max(until);
+            }
+            PipelineEvent::Error(e) => return self.stop(e),
+            PipelineEvent::Close => {
+                return self.stop(crate::modules::Error::msg("pipeline closed"));
+            }
+        }
+        Vec::new()
+    }
+
+    /// The pipeline can't run anything more; tell every open document why.
+    fn stop(&mut self, error: crate::modules::Error) -> Vec<Delivery> {
+        let open = std::mem::take(&mut self.open);
+        self.stopped = Some(error.clone());
+        open.into_values()
+            .map(|tx| (tx, Err(error.clone())))
+            .collect()
+    }
+}
+
+/// Route the pipeline's output to the documents it belongs to, until the
+/// pipeline stops.
+fn route_documents(
+    mut output: PipelineValueRx,
+    documents: Arc<std::sync::Mutex<Documents>>,
+    channels: Arc<Channels>,
+) -> JoinHandle<Result<(), crate::modules::Error>> {
+    tokio::spawn(async move {
+        loop {
+            let event = match output.recv().await {
+                Ok(PipelineEvent::Error(e)) => PipelineEvent::Error(channels.explain(e)),
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => PipelineEvent::Error(
+                    channels.explain(crate::util::channel::report_lag("pipeline output", skipped)),
+                ),
+                Err(broadcast::error::RecvError::Closed) => PipelineEvent::Error(
+                    crate::modules::Error::msg("pipeline output channel closed"),
+                ),
+            };
+            let (deliveries, stopped) = {
+                let mut documents = documents.lock().unwrap();
+                let deliveries = documents.route(event);
+                (deliveries, documents.stopped.is_some())
+            };
+            // A dropped stream's output is discarded
+            for (tx, item) in deliveries {
+                let _ = tx.send(item).await;
+            }
+            if stopped {
+                return Ok(());
+            }
+        }
+    })
 }
 
 impl Drop for PipelineHandle {
@@ -425,116 +826,256 @@ impl Drop for PipelineHandle {
 type PipelineStream =
     Pin<Box<dyn Stream<Item = Result<PipelineValue, crate::modules::Error>> + Send + 'static>>;
 
+/// The output of one document sent through a [`PipelineHandle`].
+pub struct DocumentStream {
+    id: Option<u64>,
+    inner: PipelineStream,
+}
+
+impl DocumentStream {
+    /// The id the handle gave the document, counting up from 0, for telling
+    /// apart the documents of one handle in logs and responses. None if the
+    /// document was refused before it was sent.
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+}
+
+impl Stream for DocumentStream {
+    type Item = Result<PipelineValue, crate::modules::Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        self.inner.as_mut().poll_next(cx)
+    }
+}
+
+fn error_stream(error: crate::modules::Error) -> DocumentStream {
+    DocumentStream {
+        id: None,
+        inner: Box::pin(futures_util::stream::once(async move { Err(error) })),
+    }
+}
+
 impl PipelineHandle {
-    pub async fn forward(&mut self, input: PipelineValue) -> PipelineStream {
-        let input_lock = Arc::clone(&self.input);
-        let mut rx = self.output.resubscribe();
-
-        let output = Box::pin(async_stream::stream! {
-            tracing::debug!("pipeline: acquiring input lock");
-            let guard = input_lock.lock().await;
-            tracing::debug!("pipeline: sending input");
-            match guard.send(PipelineEvent::Value(input)) {
-                Ok(_) => (),
-                Err(e) => {
-                    tracing::error!("pipeline: failed to send input: {e}");
-                    yield Err(crate::modules::Error::msg(e.to_string()));
-                    return;
-                }
-            }
-            if let Err(e) = guard.send(PipelineEvent::Finish) {
-                tracing::error!("pipeline: failed to finish input: {e}");
-                yield Err(crate::modules::Error::msg(e.to_string()));
-                return;
+    /// Send `input` through the pipeline as a document of its own. The input
+    /// is sent before this returns, so several documents can be queued before
+    /// reading any of their streams. A stream ends after its document's last
+    /// value, with an error if the pipeline failed, or early on
+    /// [`cancel`](Self::cancel).
+    ///
+    /// Each stream buffers up to the channel capacity of output; while a
+    /// document's buffer is full the pipeline's output waits for it to be
+    /// read, so read the streams in the order the documents were sent, or
+    /// concurrently.
+    pub async fn forward(&mut self, input: PipelineValue) -> DocumentStream {
+        self.forward_values(vec![input]).await
+    }
+
+    /// Send `inputs` through the pipeline as one document, as the output of
+    /// a step is passed to the next: the values, then one Finish.
+    pub(crate) async fn forward_values(&mut self, inputs: Vec<PipelineValue>) -> DocumentStream {
+        if let Some(e) = inputs.iter().find_map(|x| self.check_input(x).err()) {
+            return error_stream(e);
+        }
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(self.capacity);
+        tracing::debug!("pipeline: acquiring input lock");
+        let guard = self.input.lock().await;
+        let id = {
+            let mut documents = self.documents.lock().unwrap();
+            if let Some(e) = &documents.stopped {
+                return error_stream(e.clone());
             }
+            let id = documents.next;
+            documents.next += 1;
+            documents.open.insert(id, tx);
+            id
+        };
+
+        tracing::debug!("pipeline: sending document {id}");
+        let sent = inputs
+            .into_iter()
+            .try_for_each(|x| guard.send(PipelineEvent::Value(x)).map(|_| ()))
+            .and_then(|_| guard.send(PipelineEvent::Finish).map(|_| ()));
+        if let Err(e) = sent {
+            tracing::error!("pipeline: failed to send document {id}: {e}");
+            self.documents.lock().unwrap().open.remove(&id);
+            return error_stream(crate::modules::Error::msg(e.to_string()));
+        }
 
-            tracing::debug!("pipeline: waiting for output");
-            loop {
-                match rx.recv().await {
-                    Ok(PipelineEvent::Value(input)) => {
-                        tracing::debug!("pipeline: received output");
-                        yield Ok(input)
-                    },
-                    Ok(PipelineEvent::Error(e)) => {
-                        tracing::error!("pipeline: received error: {e:?}");
-                        yield Err(e);
-                        break;
-                    },
-                    Ok(PipelineEvent::Cancel) => {
-                        // Ends *this* forward() call's stream. The pipeline is
-                        // still alive; the next forward() will get a fresh stream.
-                        tracing::debug!("pipeline: received Cancel");
-                        break;
-                    }
-                    Ok(PipelineEvent::Close) => {
-                        tracing::debug!("pipeline: received Close");
-                        break;
-                    }
-                    Ok(PipelineEvent::Finish) => {
-                        tracing::debug!("pipeline: received Finish");
-                        break;
-                    }
-                    Err(e) => {
-                        tracing::error!("pipeline: recv error: {e}");
-                        yield Err(crate::modules::Error::msg(e.to_string()));
-                    }
+        DocumentStream {
+            id: Some(id),
+            inner: Box::pin(async_stream::stream! {
+                while let Some(item) = rx.recv().await {
+                    yield item;
                 }
-            }
-        });
+            }),
+        }
+    }
+
+    /// Fail early on input of a type the entry doesn't take, rather than in
+    /// whichever command first tries to convert it.
+    fn check_input(&self, input: &PipelineValue) -> Result<(), crate::modules::Error> {
+        let expected = &*self.entry.value_type;
+        let accepted = match expected {
+            // Paths are passed as strings
+            "string" | "path" => matches!(input, PipelineValue::String(_)),
+            "bytes" | "json" | "audio" => input.type_name() == expected,
+            _ => true,
+        };
+        if accepted {
+            return Ok(());
+        }
+
+        Err(crate::modules::Error::msg(format!(
+            "Pipeline input is {}, but the pipeline's entry takes {} (read by {})",
+            input.type_name(),
+            expected,
+            self.entry_commands.join(", ")
+        ))
+        .at("pipeline.json", "/entry/value_type"))
+    }
 
-        output
+    /// Replace the runtime config the handle was created with, e.g. to change
+    /// the locales or ignored errors of `divvun::suggest`, without creating
+    /// the pipeline again. Commands read it for each input they receive from
+    /// now on, so a document already in the pipeline may see the old config
+    /// in some commands and the new one in others. A command's `priority`
+    /// keeps its old value.
+    pub fn update_config(&self, config: serde_json::Value) {
+        for (key, command, live) in &self.configs {
+            live.set(command.runtime_config(config.as_object().and_then(|obj| obj.get(key))));
+        }
     }
 
     /// Send a Cancel signal through the pipeline. Each command discards any
-    /// in-flight emission for the current input but stays alive; the next
-    /// `forward()` call works normally. Does NOT drop the handle or abort
-    /// task handles — use `drop(handle)` for that. Infallible: a send error
-    /// means the pipeline is already dead, which is fine.
+    /// in-flight emission but stays alive, and the streams of every document
+    /// sent so far end; documents sent afterwards run normally. Does NOT drop
+    /// the handle or abort task handles — use `drop(handle)` for that.
+    /// Infallible: a send error means the pipeline is already dead, which is
+    /// fine.
     pub async fn cancel(&self) {
         let guard = self.input.lock().await;
+        {
+            let mut documents = self.documents.lock().unwrap();
+            let next = documents.next;
+            documents.cancels.push_back(next);
+        }
         let _ = guard.send(PipelineEvent::Cancel);
     }
 }
 
+/// A command already created for a pipe of the same [`Context`], kept in its
+/// cache so pipes with identical command definitions share one runner (e.g.
+/// the analyser of a bundle's speller and grammar checker pipelines). Weak, as
+/// runners usually hold the context themselves.
+///
+/// A shared runner is called by pipelines running at the same time, so a
+/// runner feeding a worker thread holds its output lock from sending an input
+/// until it has the output for it.
+struct SharedCommand(std::sync::Weak<dyn CommandRunner + Send + Sync>);
+
+/// Commands are shared when their module, command and args all match.
+fn shared_command_key(command: &Command) -> Option<String> {
+    let args = command
+        .args
+        .iter()
+        .collect::<std::collections::BTreeMap<_, _>>();
+    serde_json::to_string(&(&command.module, &command.command, args)).ok()
+}
+
+fn shared_command(context: &Context, key: &str) -> Option<Arc<dyn CommandRunner + Send + Sync>> {
+    context.cache.get::<SharedCommand>(key)?.0.upgrade()
+}
+
 impl Pipe {
     #[inline]
     pub async fn new(context: Arc<Context>, defn: Arc<PipelineDefinition>) -> Result<Self, Error> {
         let mut cache: IndexMap<String, Arc<dyn CommandRunner + Send + Sync>> = IndexMap::new();
+        // Keep going after a command fails, so every broken command is
+        // reported at once.
+        let mut errors = Vec::new();
 
         for (key, command) in defn.commands.iter() {
             if cache.contains_key(&**key) {
                 continue;
             }
 
-            let module =
-                MODULES
-                    .get(&command.module)
-                    .ok_or(Error::Command(crate::modules::Error::msg(format!(
+            let Some(module) = MODULES.get(&command.module) else {
+                errors.push(CommandError {
+                    key: key.clone(),
+                    module: command.module.clone(),
+                    command: command.command.clone(),
+                    error: crate::modules::Error::msg(format!(
                         "Module {} not found",
                         command.module
-                    ))))?;
-            let subcommand =
-                module
-                    .get(&command.command)
-                    .ok_or(Error::Command(crate::modules::Error::msg(format!(
+                    ))
+                    .in_command(key),
+                });
+                continue;
+            };
+            let Some(subcommand) = module.get(&command.command) else {
+                errors.push(CommandError {
+                    key: key.clone(),
+                    module: command.module.clone(),
+                    command: command.command.clone(),
+                    error: crate::modules::Error::msg(format!(
                         "Module {}, command {} not found",
                         command.module, command.command
-                    ))))?;
+                    ))
+                    .in_command(key),
+                });
+                continue;
+            };
+            let shared_key = shared_command_key(command);
+            if let Some(cmd) = shared_key
+                .as_deref()
+                .and_then(|k| shared_command(&context, k))
+            {
+                tracing::info!("Sharing command: {key}");
+                cache.insert(key.clone(), cmd);
+                continue;
+            }
             tracing::info!(
                 "Initializing command: {key} ({}.{})",
                 command.module,
                 command.command
             );
-            let cmd = (subcommand.init)(context.clone(), command.args.clone())
+            match subcommand
+                .create(context.clone(), command.args.clone())
                 .await
-                .map_err(Error::Command)?;
-            tracing::info!("Initialized command: {key}");
+            {
+                Ok(cmd) => {
+                    tracing::info!("Initialized command: {key}");
+                    if let Some(shared_key) = shared_key {
+                        context
+                            .cache
+                            .insert(&shared_key, SharedCommand(Arc::downgrade(&cmd)));
+                    }
+                    cache.insert(key.clone(), cmd);
+                }
+                Err(e) => {
+                    tracing::info!("Failed to initialize command: {key}");
+                    errors.push(CommandError {
+                        key: key.clone(),
+                        module: command.module.clone(),
+                        command: command.command.clone(),
+                        error: e.in_command(key),
+                    });
+                }
+            }
+        }
 
-            cache.insert(key.clone(), cmd);
+        if !errors.is_empty() {
+            return Err(Error::Construction(ConstructionErrors { errors }));
         }
 
+        crate::util::shutdown::register(&context);
         Ok(Self {
-            _context: context,
+            context,
             defn,
             modules: cache,
         })
@@ -563,11 +1104,14 @@ impl Pipe {
         config: Arc<serde_json::Value>,
         tap: Option<Arc<TapFn>>,
     ) -> Result<PipelineHandle, Error> {
-        let (main_input_tx, _main_input_rx) = broadcast::channel(16);
+        let capacity = self.context.channels.capacity();
+        let (main_input_tx, _main_input_rx) = broadcast::channel(capacity);
         let mut cache: IndexMap<&str, PipelineValueTx> = IndexMap::new();
         let mut outputs: HashMap<&str, PipelineValueRx> = HashMap::new();
         let mut handles: HashMap<&str, JoinHandle<Result<(), crate::modules::Error>>> =
             HashMap::new();
+        let mut configs = Vec::new();
+        let mut relays: Vec<JoinHandle<Result<(), crate::modules::Error>>> = Vec::new();
 
         cache.insert("#/entry", main_input_tx.clone());
         let output_ref = &*self.defn.output.r#ref;
@@ -602,55 +1146,60 @@ impl Pipe {
                 tracing::debug!("create_stream: wiring command {key}");
                 let cmd = Arc::clone(self.modules.get(&**key).unwrap());
 
-                match &command.input {
-                    InputValue::Single(x) => {
-                        let parent_input = cache.get(&*x.r#ref).unwrap().clone();
-                        let parent_output = parent_input.subscribe();
-                        let (child_input, child_output) = broadcast::channel::<PipelineEvent>(16);
-
-                        let tap = tap.clone().map(|x| Tap {
-                            key: key.to_string().into(),
-                            command: Arc::new(command.clone()),
-                            tap: x,
-                        });
-                        // Extract command-specific config or use empty object
-                        let cmd_config = config
-                            .as_object()
-                            .and_then(|obj| obj.get(key))
-                            .map(|v| Arc::new(v.clone()))
-                            .unwrap_or_else(|| Arc::new(serde_json::Value::Null));
-
-                        let handle =
-                            cmd.forward_stream(parent_output, child_input.clone(), tap, cmd_config);
-                        handles.insert(key, handle);
-                        cache.insert(key, child_input);
-                        outputs.insert(key, child_output);
-
-                        if output_ref == *key {
-                            break;
-                        }
-                    }
+                let parent_input = match &command.input {
+                    InputValue::Single(x) => cache.get(&*x.r#ref).unwrap().clone(),
                     InputValue::Multiple(x) => {
-                        todo!()
-                        // let inputs = x
-                        //     .iter()
-                        //     .map(|x| cache.get(&*x.r#ref).unwrap().clone())
-                        //     .collect::<Vec<_>>();
-                        // let fut = join_all(inputs.into_iter());
-                        // let input: PipelineValueFut = Box::pin(async move {
-                        //     Ok(PipelineValue::Multiple(
-                        //         fut.await
-                        //             .into_iter()
-                        //             .collect::<Result<Vec<_>, _>>()?
-                        //             .into_boxed_slice(),
-                        //     ))
-                        // });
-                        // cache.insert(
-                        //     key,
-                        //     cmd.forward(input.boxed_shared(), config.clone())
-                        //         .boxed_shared(),
-                        // );
+                        // Each document's values from every input, as one
+                        // PipelineValue::Multiple
+                        let inputs = x
+                            .iter()
+                            .map(|x| cache.get(&*x.r#ref).unwrap().subscribe())
+                            .collect();
+                        let (joined, _) = broadcast::channel::<PipelineEvent>(capacity);
+                        relays.push(crate::modules::join::join(key, inputs, joined.clone()));
+                        joined
                     }
+                };
+                let parent_output = parent_input.subscribe();
+                let (child_input, child_output) = broadcast::channel::<PipelineEvent>(capacity);
+
+                let tap = tap.clone().map(|x| Tap {
+                    key: key.to_string().into(),
+                    command: Arc::new(command.clone()),
+                    tap: x,
+                });
+                // The caller's config for this command over the
+                // bundle's defaults, or null when neither has any
+                let cmd_config = LiveConfig::new(
+                    command.runtime_config(config.as_object().and_then(|obj| obj.get(key))),
+                );
+                configs.push((
+                    key.to_string(),
+                    Arc::new(command.clone()),
+                    cmd_config.clone(),
+                ));
+
+                #[cfg(debug_assertions)]
+                let cmd_output = {
+                    let (tx, relay) = crate::modules::protocol::validate(
+                        key,
+                        parent_input.subscribe(),
+                        child_input.clone(),
+                        capacity,
+                    );
+                    relays.push(relay);
+                    tx
+                };
+                #[cfg(not(debug_assertions))]
+                let cmd_output = child_input.clone();
+
+                let handle = cmd.forward_stream(parent_output, cmd_output, tap, cmd_config);
+                handles.insert(key, handle);
+                cache.insert(key, child_input);
+                outputs.insert(key, child_output);
+
+                if output_ref == *key {
+                    break;
                 }
             }
 
@@ -677,10 +1226,411 @@ impl Pipe {
 
         let main_output_rx = outputs.remove(output_ref).unwrap();
 
+        let entry_commands = self
+            .defn
+            .commands
+            .iter()
+            .filter(|(_, command)| match &command.input {
+                InputValue::Single(x) => x.r#ref == "#/entry",
+                InputValue::Multiple(x) => x.iter().any(|x| x.r#ref == "#/entry"),
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let documents = Arc::new(std::sync::Mutex::new(Documents::default()));
+        let router = route_documents(
+            main_output_rx,
+            documents.clone(),
+            self.context.channels.clone(),
+        );
+        let handles = handles
+            .into_values()
+            .chain(relays)
+            .chain(std::iter::once(router))
+            .collect::<Vec<_>>();
+
+        let stopped = Arc::downgrade(&documents);
+        self.context.tasks.track(
+            handles.iter().map(JoinHandle::abort_handle).collect(),
+            move || {
+                if let Some(documents) = stopped.upgrade() {
+                    let deliveries = documents
+                        .lock()
+                        .unwrap()
+                        .stop(crate::modules::Error::msg("the runtime was shut down"));
+                    // Nothing waits on a shutting down runtime; a reader
+                    // whose stream is full sees it end without the error.
+                    for (tx, item) in deliveries {
+                        let _ = tx.try_send(item);
+                    }
+                }
+            },
+        );
+
         Ok(PipelineHandle {
-            handles: handles.into_values().collect(),
+            handles,
             input: Arc::new(Mutex::new(main_input_tx)),
-            output: main_output_rx,
+            documents,
+            entry: self.defn.entry.clone(),
+            entry_commands,
+            configs,
+            capacity,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: Serialize + serde::de::DeserializeOwned,
+    {
+        serde_json::from_str(&serde_json::to_string(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn value_round_trips_through_serde() {
+        let values = [
+            Value::Int(-3),
+            Value::Bool(true),
+            Value::String("sme.hfstol".to_string()),
+            Value::from(vec!["a", "b"]),
+            Value::from(IndexMap::from([
+                ("se".to_string(), Value::from("errors-se.ftl")),
+                ("nested".to_string(), Value::from(vec![1isize, 2])),
+            ])),
+            Value::Null,
+        ];
+        for value in values {
+            assert_eq!(round_trip(&value), value);
+            assert_eq!(
+                Value::from_json(value.try_as_json().unwrap()).unwrap(),
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn output_header_names_the_output_step() {
+        let defn = PipelineBuilder::new()
+            .cmd("rev", "example", "reverse", Vec::<(String, Arg)>::new())
+            .build()
+            .unwrap();
+        let header = defn.output_header().unwrap();
+        assert_eq!(header.key, "rev");
+        assert_eq!(
+            (header.module.as_str(), header.command.as_str()),
+            ("example", "reverse")
+        );
+        assert_eq!(header.returns, "string");
+        assert!(serde_json::to_value(&header).unwrap().get("kind").is_none());
+    }
+
+    #[test]
+    fn value_from_json_rejects_floats() {
+        assert!(Value::from_json(serde_json::json!(1.5)).is_err());
+        assert!(Value::from_json(serde_json::json!({"x": [1.5]})).is_err());
+    }
+
+    #[test]
+    fn arg_builders_use_pipeline_types() {
+        let arg = Arg::path("tokenizer.pmhfst");
+        assert_eq!(arg.r#type, "path");
+        assert_eq!(
+            arg.value.as_ref().and_then(|v| v.try_as_path()),
+            Some(PathBuf::from("tokenizer.pmhfst"))
+        );
+
+        assert_eq!(Arg::array_string(["a", "b"]).r#type, "[string]");
+        assert_eq!(Arg::map_path([("se", "se.hfst")]).r#type, "{path}");
+        assert_eq!(Arg::map_string([("k", "v")]).r#type, "{string}");
+        assert_eq!(Arg::int(4).value, Some(Value::Int(4)));
+    }
+
+    #[test]
+    fn arg_round_trips_through_serde() {
+        let args = [
+            Arg::path("model.hfstol"),
+            Arg::string("sme").with_value_type("string"),
+            Arg::int(10),
+            Arg::array_string(["x"]),
+            Arg::map_path([("se", "se.drb")]),
+            Arg::json(serde_json::json!({"ignore": ["typo"]})).unwrap(),
+            Arg {
+                r#type: "string".to_string(),
+                value_type: None,
+                value: None,
+            },
+        ];
+        for arg in args {
+            assert_eq!(round_trip(&arg), arg);
+        }
+
+        // The shape the TypeScript frontend writes, without `value_type`.
+        let arg: Arg =
+            serde_json::from_value(serde_json::json!({"type": "path", "value": "a.hfst"})).unwrap();
+        assert_eq!(arg, Arg::path("a.hfst"));
+    }
+
+    #[test]
+    fn command_args_built_in_code_match_json() {
+        let command = Command {
+            module: "hfst".to_string(),
+            command: "tokenize".to_string(),
+            args: HashMap::from([(
+                "model_path".to_string(),
+                Arg::path("tokeniser-disamb-gt-desc.pmhfst"),
+            )]),
+            input: InputValue::Single(Ref {
+                r#ref: "#/entry".to_string(),
+            }),
+            returns: "string".to_string(),
+            kind: None,
+            defaults: None,
+        };
+        let json = serde_json::to_value(&command).unwrap();
+        assert_eq!(json["args"]["model_path"]["type"], "path");
+        let parsed: Command = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.args, command.args);
+    }
+
+    #[test]
+    fn caller_config_is_merged_over_defaults() {
+        let command: Command = serde_json::from_value(serde_json::json!({
+            "module": "divvun",
+            "command": "suggest",
+            "input": { "ref": "#/entry" },
+            "returns": "json",
+            "defaults": { "locales": ["se"], "format": "json", "lookup": { "max_results": 5 } },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            command.runtime_config(None),
+            command.defaults.clone().unwrap()
+        );
+        assert_eq!(
+            command.runtime_config(Some(&serde_json::json!({
+                "locales": ["nb", "en"],
+                "lookup": { "cache_size": 0 },
+                "format": null,
+            }))),
+            serde_json::json!({
+                "locales": ["nb", "en"],
+                "format": "json",
+                "lookup": { "max_results": 5, "cache_size": 0 },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn pipes_of_one_context_share_identical_commands() {
+        let trickle = |count: isize| {
+            serde_json::json!({
+                "module": "debug",
+                "command": "trickle",
+                "args": {
+                    "count": { "type": "int", "value": count },
+                    "delay_ms": { "type": "int", "value": 0 }
+                },
+                "input": { "ref": "#/entry" },
+                "returns": "string"
+            })
+        };
+        let defn = |a: isize, b: isize| -> Arc<PipelineDefinition> {
+            Arc::new(
+                serde_json::from_value(serde_json::json!({
+                    "entry": { "value_type": "string" },
+                    "output": { "ref": "b" },
+                    "commands": { "a": trickle(a), "b": trickle(b) }
+                }))
+                .unwrap(),
+            )
+        };
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+        let first = Pipe::new(context.clone(), defn(1, 2)).await.unwrap();
+        let second = Pipe::new(context.clone(), defn(2, 3)).await.unwrap();
+        assert!(Arc::ptr_eq(&first.modules["b"], &second.modules["a"]));
+        assert!(!Arc::ptr_eq(&first.modules["a"], &first.modules["b"]));
+
+        // Another context builds its own
+        let other = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let third = Pipe::new(other, defn(1, 2)).await.unwrap();
+        assert!(!Arc::ptr_eq(&first.modules["a"], &third.modules["a"]));
+
+        // Nothing keeps a command alive once its pipes are gone
+        let weak = Arc::downgrade(&first.modules["a"]);
+        drop((first, second, third));
+        assert!(weak.upgrade().is_none());
+        assert!(Pipe::new(context, defn(1, 2)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn forward_rejects_input_the_entry_does_not_take() {
+        use futures_util::StreamExt;
+
+        let defn: PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "bytes" },
+            "output": { "ref": "upper" },
+            "commands": {
+                "upper": {
+                    "module": "example",
+                    "command": "upper",
+                    "input": { "ref": "#/entry" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let pipe = Pipe::new(context, Arc::new(defn)).await.unwrap();
+        let mut handle = pipe
+            .create_stream(Arc::new(serde_json::json!({})), None)
+            .await
+            .unwrap();
+
+        let mut stream = handle.forward(PipelineValue::String("a".into())).await;
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Pipeline input is string, but the pipeline's entry takes bytes (read by upper)"
+        );
+        assert_eq!(err.location().path, "/entry/value_type");
+        assert!(stream.next().await.is_none());
+    }
+
+    async fn trickle_handle(count: isize, delay_ms: isize) -> PipelineHandle {
+        let defn: PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "string" },
+            "output": { "ref": "trickle" },
+            "commands": {
+                "trickle": {
+                    "module": "debug",
+                    "command": "trickle",
+                    "args": {
+                        "count": { "type": "int", "value": count },
+                        "delay_ms": { "type": "int", "value": delay_ms }
+                    },
+                    "input": { "ref": "#/entry" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let pipe = Pipe::new(context, Arc::new(defn)).await.unwrap();
+        pipe.create_stream(Arc::new(serde_json::json!({})), None)
+            .await
+            .unwrap()
+    }
+
+    async fn collect(stream: DocumentStream) -> Vec<String> {
+        use futures_util::StreamExt;
+
+        stream
+            .map(|x| x.unwrap().try_into_string().unwrap())
+            .collect()
+            .await
+    }
+
+    #[tokio::test]
+    async fn one_handle_runs_many_documents() {
+        let mut handle = trickle_handle(2, 1).await;
+
+        // One after another
+        for i in 0..20 {
+            let stream = handle.forward(format!("doc{i}").into()).await;
+            assert_eq!(stream.id(), Some(i));
+            assert_eq!(
+                collect(stream).await,
+                [format!("doc{i}#0"), format!("doc{i}#1")]
+            );
+        }
+
+        // All sent before any is read, and read back to front
+        let mut streams = Vec::new();
+        for i in 0..10 {
+            streams.push(handle.forward(format!("queued{i}").into()).await);
+        }
+        for (i, stream) in streams.into_iter().enumerate().rev() {
+            assert_eq!(
+                collect(stream).await,
+                [format!("queued{i}#0"), format!("queued{i}#1")]
+            );
+        }
+
+        // A dropped stream's output doesn't reach the next document
+        drop(handle.forward("dropped".to_string().into()).await);
+        let stream = handle.forward("kept".to_string().into()).await;
+        assert_eq!(collect(stream).await, ["kept#0", "kept#1"]);
+    }
+
+    #[tokio::test]
+    async fn cancel_ends_the_document_being_run() {
+        use futures_util::StreamExt;
+
+        let mut handle = trickle_handle(3, 20).await;
+
+        let mut first = handle.forward("a".to_string().into()).await;
+        let value = first.next().await.unwrap().unwrap();
+        assert_eq!(value.try_into_string().unwrap(), "a#0");
+        handle.cancel().await;
+        assert!(!collect(first).await.contains(&"a#2".to_string()));
+
+        let stream = handle.forward("b".to_string().into()).await;
+        assert_eq!(collect(stream).await, ["b#0", "b#1", "b#2"]);
+    }
+
+    #[tokio::test]
+    async fn pipe_reports_every_command_that_fails() {
+        let defn: PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "string" },
+            "output": { "ref": "b" },
+            "commands": {
+                "a": {
+                    "module": "debug",
+                    "command": "trickle",
+                    "input": { "ref": "#/entry" },
+                    "returns": "string"
+                },
+                "b": {
+                    "module": "debug",
+                    "command": "trickle",
+                    "args": { "count": { "type": "int", "value": 2 } },
+                    "input": { "ref": "a" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+        let Err(Error::Construction(errors)) = Pipe::new(context, Arc::new(defn)).await else {
+            panic!("expected construction errors");
+        };
+        let found = errors
+            .errors
+            .iter()
+            .map(|x| (x.key.as_str(), x.error.location().path.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("a", "/commands/a/args/count"),
+                ("b", "/commands/b/args/delay_ms")
+            ]
+        );
+        assert!(
+            errors
+                .to_string()
+                .starts_with("2 of the pipeline's commands")
+        );
+    }
+}
@@ -1,10 +1,27 @@
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use box_format::OpenError;
+use futures_util::StreamExt;
+use serde::Serialize;
 
 use crate::{
     ast::{self, Pipe, PipelineBundle, PipelineDefinition, PipelineHandle},
-    modules::{self, Context, TapFn},
+    compat,
+    fanout::{Fanout, Outputs},
+    modules::{self, Context, PipelineValue, TapFn},
+    preflight::{self, Host, Preflight},
+    util::{
+        channel::{Channels, DEFAULT_CAPACITY},
+        download::{DownloadCallback, Downloads},
+        integrity::VerifyMode,
+        manifest::{BUILD_MANIFEST_FILE, BuildManifest},
+        shutdown::{self, Shutdown},
+    },
+    watch::{self, BundleWatch},
 };
 
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
@@ -19,12 +36,356 @@ pub enum Error {
     Command(#[from] modules::Error),
     #[error("{0}")]
     Bundle(#[from] OpenError),
+    #[error("No bundle registered for language '{0}'")]
+    UnknownLanguage(String),
+    #[error("{0}")]
+    #[diagnostic(transparent)]
+    Incompatible(#[from] compat::Incompatible),
+    #[error("{0}")]
+    #[diagnostic(transparent)]
+    Unavailable(#[from] preflight::Unavailable),
+}
+
+/// Options for loading a bundle beyond the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct BundleOptions {
+    /// Named pipeline to load instead of the bundle's default.
+    pub pipeline: Option<String>,
+    /// Assets to read from disk instead of the bundle, keyed by their path
+    /// inside the bundle (e.g. `errors.json` -> `./local/errors.json`).
+    pub asset_overrides: HashMap<String, PathBuf>,
+    /// When to check bundled assets against the bundle's checksum manifest.
+    pub verify: VerifyMode,
+    /// What the host offers, for the [preflight check](crate::preflight);
+    /// detected when not given.
+    pub host: Option<Host>,
+    /// Fail to load when the host lacks memory, disk or a GPU the pipeline
+    /// needs, instead of only logging a warning.
+    pub require_resources: bool,
+    /// Where the bundle's remote assets are downloaded to;
+    /// [`default_download_dir`](crate::util::download::default_download_dir)
+    /// when not given.
+    pub download_dir: Option<PathBuf>,
+    /// Called as remote assets download, which happens while the bundle
+    /// loads the first time, and as the assets of a bundle opened with
+    /// [`Bundle::from_url_with_options`] download.
+    pub on_download: Option<DownloadCallback>,
+    /// Events buffered between commands,
+    /// [`DEFAULT_CAPACITY`](crate::util::channel::DEFAULT_CAPACITY) when not
+    /// given. A command that falls further behind fails the pipeline.
+    pub channel_capacity: Option<usize>,
+    /// Double the channel capacity for pipelines created afterwards whenever
+    /// a command falls behind.
+    pub grow_channels: bool,
+}
+
+/// The preflight settings of the [`BundleOptions`] a bundle was loaded with,
+/// for checking the other pipelines it loads.
+#[derive(Clone, Default)]
+struct ResourceCheck {
+    host: Option<Host>,
+    require: bool,
+}
+
+impl ResourceCheck {
+    fn new(options: &BundleOptions) -> Self {
+        Self {
+            host: options.host.clone(),
+            require: options.require_resources,
+        }
+    }
+
+    fn check(&self, defn: &PipelineDefinition) -> Result<(), Error> {
+        preflight_check(defn, self.host.as_ref(), self.require)
+    }
+}
+
+/// Check `defn` against the host before its commands are created.
+fn preflight_check(
+    defn: &PipelineDefinition,
+    host: Option<&Host>,
+    require: bool,
+) -> Result<(), Error> {
+    let host = host.cloned().unwrap_or_else(Host::detect);
+    let preflight = preflight::check(defn, &host);
+    if preflight.is_ok() {
+        return Ok(());
+    }
+    if require {
+        return Err(preflight::Unavailable {
+            shortfalls: preflight.shortfalls,
+        }
+        .into());
+    }
+    for shortfall in &preflight.shortfalls {
+        tracing::warn!(
+            "This device may lack what the pipeline needs: {}",
+            shortfall
+        );
+    }
+    Ok(())
+}
+
+/// Asset directory holding self-test cases.
+///
+/// `<case>.input` is run through every pipeline in the bundle. The output of the
+/// default pipeline is compared against `<case>.expected`, and the output of any
+/// other pipeline against `<case>.<pipeline>.expected`. Case names may have
+/// dots. Without an expected file the case only has to run without error.
+pub const SELFTEST_DIR: &str = "selftest";
+
+/// Result of [`Bundle::self_test`].
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SelfTestReport {
+    pub cases: Vec<SelfTestCase>,
+}
+
+impl SelfTestReport {
+    pub fn passed(&self) -> bool {
+        self.cases.iter().all(|case| case.outcome.is_ok())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &SelfTestCase> {
+        self.cases.iter().filter(|case| !case.outcome.is_ok())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestCase {
+    pub pipeline: String,
+    pub name: String,
+    pub outcome: SelfTestOutcome,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SelfTestOutcome {
+    /// Output matched the expected file.
+    Passed,
+    /// No expected file; the pipeline ran without error.
+    Ran,
+    Mismatch {
+        expected: String,
+        actual: String,
+    },
+    Failed {
+        error: String,
+    },
+    /// In [deterministic mode](crate::util::deterministic), a second run of
+    /// the same input gave different output. Outputs are given as digests,
+    /// as audio can't be shown.
+    Nondeterministic {
+        first: String,
+        second: String,
+    },
+}
+
+impl SelfTestOutcome {
+    pub fn is_ok(&self) -> bool {
+        matches!(self, SelfTestOutcome::Passed | SelfTestOutcome::Ran)
+    }
+}
+
+/// What a bundle and this build of the runtime support, from
+/// [`Bundle::capabilities`]. Clients check this instead of assuming that a
+/// feature is there because the release they were written against had it.
+#[derive(Debug, Clone, Serialize)]
+pub struct Capabilities {
+    pub runtime_version: &'static str,
+    /// Newest `pipeline.json` schema version the runtime reads.
+    pub schema_version: u32,
+    /// Cargo features the runtime was built with, e.g. `"mod-speech"`.
+    pub features: Vec<&'static str>,
+    /// Values of `divvun::suggest`'s `encoding` config: the units error
+    /// offsets are counted in.
+    pub encodings: Vec<&'static str>,
+    /// Values of `divvun::suggest`'s `positions` config.
+    pub positions: Vec<&'static str>,
+    pub default_pipeline: String,
+    pub pipelines: Vec<PipelineCapabilities>,
+    /// Locales the bundle has error messages for, from its `errors-*.ftl`
+    /// assets.
+    pub locales: Vec<String>,
+    /// Voices of the bundle's `speech::tts` commands.
+    pub voices: Vec<Voice>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PipelineCapabilities {
+    pub name: String,
+    /// Pipeline type of the input, e.g. `"string"`.
+    pub input: String,
+    /// The step whose values the pipeline outputs, with their type and kind.
+    pub output: Option<ast::StepHeader>,
+    pub dev: bool,
+    /// What this runtime lacks to run the pipeline; empty if it can.
+    pub missing: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Voice {
+    /// Key of the voice in the command's `config` arg.
+    pub id: String,
+    pub name: String,
+    pub pipeline: String,
+    /// Speaker names, by the number the command's `speaker` arg takes.
+    pub speakers: BTreeMap<u32, String>,
+}
+
+/// Voices listed in the `config` arg of a `speech::tts` command.
+fn tts_voices(pipeline: &str, command: &ast::Command) -> Vec<Voice> {
+    let Some(config) = command
+        .args
+        .get("config")
+        .and_then(|x| x.value.as_ref())
+        .and_then(|x| x.try_as_json().ok())
+    else {
+        return Vec::new();
+    };
+    let Some(voices) = config.get("voices").and_then(|x| x.as_object()) else {
+        return Vec::new();
+    };
+    voices
+        .iter()
+        .map(|(id, voice)| Voice {
+            id: id.clone(),
+            name: voice
+                .get("name")
+                .and_then(|x| x.as_str())
+                .unwrap_or(id)
+                .to_string(),
+            pipeline: pipeline.to_string(),
+            speakers: voice
+                .get("speakers")
+                .and_then(|x| x.as_object())
+                .map(|speakers| {
+                    speakers
+                        .iter()
+                        .filter_map(|(id, name)| {
+                            Some((id.parse().ok()?, name.as_str()?.to_string()))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// The case and pipeline of an expected file named `<rest>.expected`. Case
+/// names may have dots, so only a last part naming a pipeline is taken as one.
+fn expected_case<'a>(rest: &'a str, bundle: &'a PipelineBundle) -> (&'a str, &'a str) {
+    match rest.rsplit_once('.') {
+        Some((case, pipeline)) if bundle.pipelines.contains_key(pipeline) => (case, pipeline),
+        _ => (rest, bundle.default.as_str()),
+    }
+}
+
+/// Compare pipeline output against an expected file. JSON output is compared
+/// structurally when the expected file parses as JSON; everything else is
+/// compared as text, ignoring surrounding whitespace.
+fn compare_output(expected: &str, actual: &[PipelineValue]) -> SelfTestOutcome {
+    if let [PipelineValue::Json(actual)] = actual {
+        if let Ok(expected) = serde_json::from_str::<serde_json::Value>(expected) {
+            if &expected == actual {
+                return SelfTestOutcome::Passed;
+            }
+        }
+    }
+
+    let actual = actual
+        .iter()
+        .map(|value| format!("{:#}", value))
+        .collect::<Vec<_>>()
+        .join("\n");
+    if expected.trim() == actual.trim() {
+        SelfTestOutcome::Passed
+    } else {
+        SelfTestOutcome::Mismatch {
+            expected: expected.to_string(),
+            actual,
+        }
+    }
+}
+
+/// A short, exact summary of `values`: their kinds and a hash of their
+/// contents, samples and word timings included.
+fn output_digest(values: &[PipelineValue]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    let mut kinds = Vec::new();
+    for value in values {
+        match value {
+            PipelineValue::String(x) => {
+                kinds.push("string");
+                hasher.update(b"s").update(x.as_bytes());
+            }
+            PipelineValue::Bytes(x) => {
+                kinds.push("bytes");
+                hasher.update(b"b").update(x);
+            }
+            PipelineValue::Json(x) => {
+                kinds.push("json");
+                hasher.update(b"j").update(x.to_string().as_bytes());
+            }
+            PipelineValue::Audio(x) => {
+                kinds.push("audio");
+                hasher.update(b"a");
+                hasher.update(&x.sample_rate.to_le_bytes());
+                hasher.update(&x.channels.to_le_bytes());
+                for sample in &x.samples {
+                    hasher.update(&sample.to_le_bytes());
+                }
+                for timing in &x.word_timings {
+                    hasher.update(timing.word.as_bytes());
+                    hasher.update(&timing.start_sample.to_le_bytes());
+                    hasher.update(&timing.end_sample.to_le_bytes());
+                }
+                for chunk in &x.chunks {
+                    hasher.update(chunk.text.as_bytes());
+                    hasher.update(&chunk.start_sample.to_le_bytes());
+                    hasher.update(&chunk.end_sample.to_le_bytes());
+                }
+            }
+            PipelineValue::Multiple(x) => {
+                kinds.push("multiple");
+                hasher.update(b"m").update(output_digest(x).as_bytes());
+            }
+        }
+        hasher.update(b"\0");
+    }
+    format!(
+        "[{}] blake3:{}",
+        kinds.join(", "),
+        &hasher.finalize().to_hex()[..16]
+    )
 }
 
+/// A loaded pipeline with its assets, from a `.drb` file or an unpacked
+/// bundle directory. [`create`](Self::create) gives a handle to feed it
+/// input.
+///
+/// ```
+/// # use divvun_runtime::{bundle::Bundle, modules::PipelineValue};
+/// # use futures_util::StreamExt;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+/// let bundle = Bundle::from_path(path).await?;
+/// let mut handle = bundle.create(serde_json::json!({})).await?;
+///
+/// let mut stream = handle.forward(PipelineValue::String("Mun borran.".into())).await;
+/// while let Some(value) = stream.next().await {
+///     assert_eq!(value?.try_into_string()?, ".NARROB NUM");
+/// }
+/// # Ok(())
+/// # }
+/// ```
 pub struct Bundle {
     context: Arc<Context>,
     bundle: Arc<PipelineBundle>,
     pipe: Pipe,
+    /// Plans of [`run_all`](Self::run_all), by the pipelines they run.
+    fanouts: tokio::sync::Mutex<HashMap<Vec<String>, Arc<Fanout>>>,
+    resources: ResourceCheck,
 }
 
 impl Drop for Bundle {
@@ -39,13 +400,43 @@ impl Bundle {
     ) -> Result<Arc<PipelineBundle>, Error> {
         let box_file = box_format::BoxFileReader::open(bundle_path).await?;
         let context = Context {
-            data: modules::DataRef::BoxFile(Box::new(box_file)),
+            data: Box::new(modules::DataRef::BoxFile(Box::new(box_file))),
             dev: false,
             base_path: None,
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
         };
         Ok(Arc::new(context.load_pipeline_bundle().await?))
     }
 
+    /// Read the build manifest of a .drb bundle, if it has one.
+    pub async fn manifest_from_bundle<P: AsRef<Path>>(
+        bundle_path: P,
+    ) -> Result<Option<BuildManifest>, Error> {
+        let box_file = box_format::BoxFileReader::open(bundle_path).await?;
+        let context = Context {
+            data: Box::new(modules::DataRef::BoxFile(Box::new(box_file))),
+            dev: false,
+            base_path: None,
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
+        };
+        let Some(buf) = context.load_file_optional(BUILD_MANIFEST_FILE).await? else {
+            return Ok(None);
+        };
+        let manifest = serde_json::from_slice(&buf)
+            .map_err(|e| modules::Error::wrap(e).at_file(BUILD_MANIFEST_FILE))?;
+        Ok(Some(manifest))
+    }
+
     pub async fn metadata_from_path<P: AsRef<Path>>(
         contents_path: P,
     ) -> Result<Arc<PipelineBundle>, Error> {
@@ -56,35 +447,112 @@ impl Bundle {
         };
 
         let context = Context {
-            data: modules::DataRef::Path(base.to_path_buf()),
+            data: Box::new(modules::DataRef::Path(base.to_path_buf())),
             dev: false,
             base_path: None,
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
         };
         Ok(Arc::new(context.load_pipeline_bundle().await?))
     }
 
+    /// Check what a pipeline of the bundle at `path` (a `.drb` or a bundle
+    /// directory) needs against the host, without creating any command.
+    /// `options.pipeline` and `options.host` are used as when loading it.
+    ///
+    /// ```
+    /// # use divvun_runtime::bundle::{Bundle, BundleOptions};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+    /// let preflight = Bundle::preflight(path, &BundleOptions::default()).await?;
+    /// for shortfall in &preflight.shortfalls {
+    ///     eprintln!("This device lacks {}", shortfall);
+    /// }
+    /// if preflight.is_ok() {
+    ///     let bundle = Bundle::from_path(path).await?;
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn preflight<P: AsRef<Path>>(
+        path: P,
+        options: &BundleOptions,
+    ) -> Result<Preflight, Error> {
+        let path = path.as_ref();
+        let data = if path.is_file() && path.extension().is_some_and(|x| x == "drb") {
+            let box_file = box_format::BoxFileReader::open(path).await?;
+            modules::DataRef::BoxFile(Box::new(box_file))
+        } else {
+            let base = if path.is_dir() {
+                path
+            } else {
+                path.parent().unwrap_or(Path::new("."))
+            };
+            modules::DataRef::Path(base.to_path_buf())
+        };
+        let context = Context {
+            data: Box::new(data),
+            dev: false,
+            base_path: None,
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
+        };
+        let defn = match options.pipeline.as_deref() {
+            Some(name) => context.load_pipeline_definition_named(name).await?,
+            None => context.load_pipeline_definition().await?,
+        };
+        let host = options.host.clone().unwrap_or_else(Host::detect);
+        Ok(preflight::check(&defn, &host))
+    }
+
     pub async fn from_bundle<P: AsRef<Path>>(bundle_path: P) -> Result<Bundle, Error> {
         Self::_from_bundle(bundle_path).await
     }
 
     async fn _from_bundle<P: AsRef<Path>>(bundle_path: P) -> Result<Bundle, Error> {
-        Self::_from_bundle_named(bundle_path, None).await
+        Self::_from_bundle_with_options(bundle_path, BundleOptions::default()).await
     }
 
-    async fn _from_bundle_named<P: AsRef<Path>>(
+    async fn _from_bundle_with_options<P: AsRef<Path>>(
         bundle_path: P,
-        pipeline_name: Option<&str>,
+        options: BundleOptions,
     ) -> Result<Bundle, Error> {
         tracing::debug!("Loading bundle");
         let box_file = box_format::BoxFileReader::open(bundle_path).await?;
+        let resources = ResourceCheck::new(&options);
         let mut context = Context {
-            data: modules::DataRef::BoxFile(Box::new(box_file)),
+            data: Box::new(modules::DataRef::BoxFile(Box::new(box_file))),
             dev: false,
             base_path: None,
+            asset_overrides: options.asset_overrides,
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Arc::new(Channels::new(
+                options.channel_capacity.unwrap_or(DEFAULT_CAPACITY),
+                options.grow_channels,
+            )),
         };
+        context.init_integrity(options.verify).await?;
+        let pipeline_name = options.pipeline.as_deref();
 
         tracing::debug!("Loading pipeline bundle from context");
         let bundle = Arc::new(context.load_pipeline_bundle().await?);
+        context.downloads = Downloads::new(
+            bundle.remote_assets.clone(),
+            options.download_dir.clone(),
+            options.on_download.clone(),
+        );
 
         tracing::debug!("Loading pipeline definition");
         let defn = if let Some(name) = pipeline_name {
@@ -93,6 +561,9 @@ impl Bundle {
             context.load_pipeline_definition().await?
         };
 
+        compat::check(&bundle, &defn)?;
+        resources.check(&defn)?;
+
         // Update context with pipeline's dev flag
         context.dev = defn.dev;
         let context = Arc::new(context);
@@ -104,6 +575,8 @@ impl Bundle {
             context,
             bundle,
             pipe,
+            fanouts: Default::default(),
+            resources,
         })
     }
 
@@ -111,7 +584,89 @@ impl Bundle {
         bundle_path: P,
         pipeline_name: &str,
     ) -> Result<Bundle, Error> {
-        Self::_from_bundle_named(bundle_path, Some(pipeline_name)).await
+        Self::_from_bundle_with_options(
+            bundle_path,
+            BundleOptions {
+                pipeline: Some(pipeline_name.to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    pub async fn from_bundle_with_options<P: AsRef<Path>>(
+        bundle_path: P,
+        options: BundleOptions,
+    ) -> Result<Bundle, Error> {
+        Self::_from_bundle_with_options(bundle_path, options).await
+    }
+
+    /// Open the bundle served at `url`, caching downloaded assets under
+    /// `cache_dir`. Assets are fetched the first time a command loads them,
+    /// reporting progress to [`BundleOptions::on_download`] when opened with
+    /// [`from_url_with_options`](Self::from_url_with_options).
+    #[cfg(feature = "remote")]
+    pub async fn from_url<P: AsRef<Path>>(url: &str, cache_dir: P) -> Result<Bundle, Error> {
+        Self::from_url_with_options(url, cache_dir, BundleOptions::default()).await
+    }
+
+    #[cfg(feature = "remote")]
+    pub async fn from_url_with_options<P: AsRef<Path>>(
+        url: &str,
+        cache_dir: P,
+        options: BundleOptions,
+    ) -> Result<Bundle, Error> {
+        tracing::debug!("Loading bundle from {}", url);
+        let remote = crate::util::remote::RemoteAssets::open(
+            url,
+            cache_dir.as_ref(),
+            options.on_download.clone(),
+        )
+        .await?;
+        // Downloads are checked against the build manifest as they arrive, so
+        // there is no separate integrity pass.
+        let resources = ResourceCheck::new(&options);
+        let mut context = Context {
+            data: Box::new(modules::DataRef::Remote(Box::new(remote))),
+            dev: false,
+            base_path: None,
+            asset_overrides: options.asset_overrides,
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Arc::new(Channels::new(
+                options.channel_capacity.unwrap_or(DEFAULT_CAPACITY),
+                options.grow_channels,
+            )),
+        };
+
+        let bundle = Arc::new(context.load_pipeline_bundle().await?);
+        context.downloads = Downloads::new(
+            bundle.remote_assets.clone(),
+            options.download_dir.clone(),
+            options.on_download.clone(),
+        );
+        let defn = if let Some(name) = options.pipeline.as_deref() {
+            context.load_pipeline_definition_named(name).await?
+        } else {
+            context.load_pipeline_definition().await?
+        };
+
+        compat::check(&bundle, &defn)?;
+        resources.check(&defn)?;
+
+        context.dev = defn.dev;
+        let context = Arc::new(context);
+        let pipe = Pipe::new(context.clone(), Arc::new(defn)).await?;
+
+        Ok(Bundle {
+            context,
+            bundle,
+            pipe,
+            fanouts: Default::default(),
+            resources,
+        })
     }
 
     pub async fn from_path<P: AsRef<Path>>(contents_path: P) -> Result<Bundle, Error> {
@@ -119,12 +674,12 @@ impl Bundle {
     }
 
     async fn _from_path<P: AsRef<Path>>(contents_path: P) -> Result<Bundle, Error> {
-        Self::_from_path_named(contents_path, None).await
+        Self::_from_path_with_options(contents_path, BundleOptions::default()).await
     }
 
-    async fn _from_path_named<P: AsRef<Path>>(
+    async fn _from_path_with_options<P: AsRef<Path>>(
         contents_path: P,
-        pipeline_name: Option<&str>,
+        options: BundleOptions,
     ) -> Result<Bundle, Error> {
         tracing::debug!(
             "Loading bundle from path: {}",
@@ -137,14 +692,30 @@ impl Bundle {
             contents_path.as_ref().parent().unwrap()
         };
 
+        let resources = ResourceCheck::new(&options);
         let mut context = Context {
-            data: modules::DataRef::Path(base.to_path_buf()),
+            data: Box::new(modules::DataRef::Path(base.to_path_buf())),
             dev: false,
             base_path: Some(base.to_path_buf()),
+            asset_overrides: options.asset_overrides,
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Arc::new(Channels::new(
+                options.channel_capacity.unwrap_or(DEFAULT_CAPACITY),
+                options.grow_channels,
+            )),
         };
+        let pipeline_name = options.pipeline.as_deref();
 
         tracing::trace!("Loading pipeline bundle");
         let bundle = Arc::new(context.load_pipeline_bundle().await?);
+        context.downloads = Downloads::new(
+            bundle.remote_assets.clone(),
+            options.download_dir.clone(),
+            options.on_download.clone(),
+        );
 
         tracing::trace!("Loading pipeline definition");
         let defn = if let Some(name) = pipeline_name {
@@ -153,6 +724,9 @@ impl Bundle {
             context.load_pipeline_definition().await?
         };
 
+        compat::check(&bundle, &defn)?;
+        resources.check(&defn)?;
+
         // Update context with pipeline's dev flag
         context.dev = defn.dev;
         let context = Arc::new(context);
@@ -164,6 +738,8 @@ impl Bundle {
             context,
             bundle,
             pipe,
+            fanouts: Default::default(),
+            resources,
         })
     }
 
@@ -171,7 +747,161 @@ impl Bundle {
         contents_path: P,
         pipeline_name: &str,
     ) -> Result<Bundle, Error> {
-        Self::_from_path_named(contents_path, Some(pipeline_name)).await
+        Self::_from_path_with_options(
+            contents_path,
+            BundleOptions {
+                pipeline: Some(pipeline_name.to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    pub async fn from_path_with_options<P: AsRef<Path>>(
+        contents_path: P,
+        options: BundleOptions,
+    ) -> Result<Bundle, Error> {
+        Self::_from_path_with_options(contents_path, options).await
+    }
+
+    /// Load the bundle directory at `contents_path` like
+    /// [`from_path_with_options`](Self::from_path_with_options), and load it
+    /// again whenever its `pipeline.json`, assets or asset overrides change.
+    /// Take the bundle from [`BundleWatch::current`] for each run to pick up
+    /// edits. See [`crate::watch`].
+    ///
+    /// ```no_run
+    /// # use divvun_runtime::{bundle::{Bundle, BundleOptions}, watch::Reload};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let watch = Bundle::watch("tools/grammarcheckers", BundleOptions::default()).await?;
+    /// let mut reloads = watch.subscribe();
+    /// while let Ok(reload) = reloads.recv().await {
+    ///     if let Reload::Failed { error, .. } = reload {
+    ///         eprintln!("Keeping the previous pipeline: {}", error);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn watch<P: AsRef<Path>>(
+        contents_path: P,
+        options: BundleOptions,
+    ) -> Result<BundleWatch, Error> {
+        BundleWatch::start(
+            contents_path.as_ref().to_path_buf(),
+            options,
+            watch::POLL_INTERVAL,
+        )
+        .await
+    }
+
+    /// Run a pipeline built in code (see [`ast::PipelineBuilder`]), reading
+    /// assets through `context`, e.g. [`Context::standalone`].
+    pub async fn from_definition(
+        context: Arc<Context>,
+        defn: PipelineDefinition,
+    ) -> Result<Bundle, Error> {
+        let bundle = Arc::new(PipelineBundle {
+            version: 1,
+            min_runtime_version: None,
+            default: "default".to_string(),
+            pipelines: [("default".to_string(), defn.clone())].into_iter().collect(),
+            remote_assets: indexmap::IndexMap::new(),
+        });
+        let pipe = Pipe::new(context.clone(), Arc::new(defn)).await?;
+
+        Ok(Bundle {
+            context,
+            bundle,
+            pipe,
+            fanouts: Default::default(),
+            resources: ResourceCheck::default(),
+        })
+    }
+
+    /// Load another of the bundle's pipelines, e.g. the speller next to the
+    /// grammar checker. The two share the bundle's assets, and commands the
+    /// pipelines define identically (same module, command and args) are only
+    /// created once.
+    ///
+    /// ```
+    /// # use divvun_runtime::bundle::Bundle;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+    /// let shout = Bundle::from_path(path).await?;
+    /// let disambiguate = shout.pipeline("disambiguate").await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn pipeline(&self, name: &str) -> Result<Bundle, Error> {
+        let defn = self.context.load_pipeline_definition_named(name).await?;
+        if defn.dev && !self.context.dev {
+            return Err(modules::Error::msg(format!(
+                "Pipeline '{}' is a dev pipeline and can't share a release pipeline's assets",
+                name
+            ))
+            .at_file("pipeline.json")
+            .into());
+        }
+        compat::check(&self.bundle, &defn)?;
+        self.resources.check(&defn)?;
+        let pipe = Pipe::new(self.context.clone(), Arc::new(defn)).await?;
+
+        Ok(Bundle {
+            context: self.context.clone(),
+            bundle: self.bundle.clone(),
+            pipe,
+            fanouts: Default::default(),
+            resources: self.resources.clone(),
+        })
+    }
+
+    /// Run several of the bundle's pipelines over one input at once, e.g. a
+    /// grammar checker and a readability scorer over the same paragraph.
+    /// Steps the pipelines have in common from the entry, such as
+    /// tokenization and disambiguation, run once and each pipeline goes on
+    /// from their output. Returns each pipeline's output by name, in the order
+    /// asked for; one pipeline failing doesn't stop the others.
+    ///
+    /// `config` is runtime config keyed by step key as for
+    /// [`create`](Self::create). A pipeline given different config than the
+    /// others for a common step runs on its own.
+    ///
+    /// ```
+    /// # use divvun_runtime::{bundle::Bundle, modules::PipelineValue};
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+    /// let bundle = Bundle::from_path(path).await?;
+    /// let input = PipelineValue::String("Mun lean.".into());
+    /// let outputs = bundle
+    ///     .run_all(&["shout", "disambiguate"], input, serde_json::json!({}))
+    ///     .await?;
+    /// assert_eq!(outputs.keys().collect::<Vec<_>>(), ["shout", "disambiguate"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_all(
+        &self,
+        pipelines: &[&str],
+        input: PipelineValue,
+        config: serde_json::Value,
+    ) -> Result<Outputs, Error> {
+        let names = pipelines.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        let fanout = {
+            let mut fanouts = self.fanouts.lock().await;
+            match fanouts.get(&names) {
+                Some(fanout) => fanout.clone(),
+                None => {
+                    let fanout = Arc::new(Fanout::new(self, &names).await?);
+                    fanouts.insert(names, fanout.clone());
+                    fanout
+                }
+            }
+        };
+        Ok(fanout.run(input, &config).await)
     }
 
     pub async fn create(&self, config: serde_json::Value) -> Result<PipelineHandle, Error> {
@@ -192,6 +922,17 @@ impl Bundle {
             .map_err(|e| Error::Ast(e))
     }
 
+    /// Unload the bundle: cancel its running pipelines and those of bundles
+    /// loaded from it with [`pipeline`](Self::pipeline), empty their caches
+    /// and remove downloads cut short, then wait up to `timeout` for the
+    /// blocking work those pipelines already started to finish. See
+    /// [`crate::shutdown`] to do this for every bundle at once.
+    pub async fn close(self, timeout: std::time::Duration) -> Shutdown {
+        let closed = shutdown::close_context(&self.context);
+        drop(self);
+        shutdown::finish(closed, timeout).await
+    }
+
     pub fn definition(&self) -> &Arc<PipelineDefinition> {
         &self.pipe.defn
     }
@@ -207,6 +948,25 @@ impl Bundle {
         &self.context
     }
 
+    /// Localized titles of the error types the pipeline's `divvun::suggest`
+    /// can report, keyed by error id, or `None` without a suggest command.
+    /// `locales` are tried in order. See [`modules::divvun::Suggest::error_preferences`].
+    pub fn error_preferences(
+        &self,
+        locales: &[&str],
+    ) -> Option<indexmap::IndexMap<String, String>> {
+        #[cfg(feature = "mod-divvun")]
+        {
+            self.command::<modules::divvun::Suggest>(None)
+                .map(|(_, suggest)| suggest.error_preferences(locales))
+        }
+        #[cfg(not(feature = "mod-divvun"))]
+        {
+            let _ = locales;
+            None
+        }
+    }
+
     pub fn list_pipelines(&self) -> Vec<&str> {
         self.bundle.list_pipelines()
     }
@@ -214,4 +974,294 @@ impl Bundle {
     pub fn bundle(&self) -> &Arc<PipelineBundle> {
         &self.bundle
     }
+
+    /// What the bundle offers and this runtime supports: its pipelines and
+    /// their types, message locales and voices, and the runtime's features and
+    /// position encodings. Dev pipelines are only listed for dev bundles.
+    ///
+    /// ```
+    /// # use divvun_runtime::bundle::Bundle;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+    /// let bundle = Bundle::from_path(path).await?;
+    /// let capabilities = bundle.capabilities().await?;
+    /// assert!(capabilities.encodings.contains(&"utf-16"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn capabilities(&self) -> Result<Capabilities, Error> {
+        let mut pipelines = Vec::new();
+        let mut voices = Vec::new();
+        for (name, defn) in self.bundle.pipelines.iter() {
+            if defn.dev && !self.context.dev {
+                continue;
+            }
+            let missing = match compat::check(&self.bundle, defn) {
+                Ok(()) => Vec::new(),
+                Err(e) => e.missing.iter().map(|x| x.to_string()).collect(),
+            };
+            pipelines.push(PipelineCapabilities {
+                name: name.clone(),
+                input: defn.entry.value_type.clone(),
+                output: defn.output_header(),
+                dev: defn.dev,
+                missing,
+            });
+            for command in defn.commands.values() {
+                if command.module == "speech" && command.command == "tts" {
+                    voices.extend(tts_voices(name, command));
+                }
+            }
+        }
+
+        let mut locales = self
+            .context
+            .load_files_glob("errors-*.ftl")
+            .await?
+            .into_iter()
+            .filter_map(|(path, _)| {
+                let stem = path.file_stem()?.to_str()?;
+                Some(stem.strip_prefix("errors-")?.to_string())
+            })
+            .collect::<Vec<_>>();
+        locales.sort();
+        locales.dedup();
+
+        Ok(Capabilities {
+            runtime_version: env!("CARGO_PKG_VERSION"),
+            schema_version: compat::SCHEMA_VERSION,
+            features: crate::cargo_features(),
+            encodings: vec!["utf-8", "utf-16"],
+            positions: vec!["offset", "linecol"],
+            default_pipeline: self.bundle.default.clone(),
+            pipelines,
+            locales,
+            voices,
+        })
+    }
+
+    /// Run the sample inputs in the bundle's `selftest/` assets through each
+    /// pipeline. See [`SELFTEST_DIR`] for the file layout.
+    ///
+    /// ```
+    /// # use divvun_runtime::bundle::Bundle;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+    /// let bundle = Bundle::from_path(path).await?;
+    /// let report = bundle.self_test().await?;
+    /// for case in report.failures() {
+    ///     eprintln!("{} ({}): {:?}", case.name, case.pipeline, case.outcome);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn self_test(&self) -> Result<SelfTestReport, Error> {
+        let mut inputs = BTreeMap::new();
+        let mut expected = HashMap::new();
+        for (path, contents) in self
+            .context
+            .load_files_glob(&format!("{}/*", SELFTEST_DIR))
+            .await?
+        {
+            let Some(file_name) = path.file_name().and_then(|x| x.to_str()) else {
+                continue;
+            };
+            let contents = String::from_utf8_lossy(&contents).into_owned();
+            if let Some(case) = file_name.strip_suffix(".input") {
+                inputs.insert(case.to_string(), contents);
+            } else if let Some(rest) = file_name.strip_suffix(".expected") {
+                let (case, pipeline) = expected_case(rest, &self.bundle);
+                expected.insert((case.to_string(), pipeline.to_string()), contents);
+            }
+        }
+
+        let mut report = SelfTestReport::default();
+        if inputs.is_empty() {
+            return Ok(report);
+        }
+
+        for (name, defn) in self.bundle.pipelines.iter() {
+            if defn.dev && !self.context.dev {
+                continue;
+            }
+
+            tracing::debug!("Self-testing pipeline {}", name);
+            let pipe = match self.context.load_pipeline_definition_named(name).await {
+                Ok(defn) => Pipe::new(self.context.clone(), Arc::new(defn))
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(e) => Err(e.to_string()),
+            };
+
+            for (case, input) in inputs.iter() {
+                let outcome = match &pipe {
+                    Ok(pipe) => {
+                        let expected = expected.get(&(case.clone(), name.clone()));
+                        Self::run_self_test_case(pipe, input, expected.map(|x| x.as_str())).await
+                    }
+                    Err(error) => SelfTestOutcome::Failed {
+                        error: error.clone(),
+                    },
+                };
+                report.cases.push(SelfTestCase {
+                    pipeline: name.clone(),
+                    name: case.clone(),
+                    outcome,
+                });
+            }
+        }
+
+        Ok(report)
+    }
+
+    async fn run_self_test_case(
+        pipe: &Pipe,
+        input: &str,
+        expected: Option<&str>,
+    ) -> SelfTestOutcome {
+        let mut handle = match pipe
+            .create_stream(Arc::new(serde_json::json!({})), None)
+            .await
+        {
+            Ok(handle) => handle,
+            Err(e) => {
+                return SelfTestOutcome::Failed {
+                    error: e.to_string(),
+                };
+            }
+        };
+
+        let output = match Self::run_self_test_input(&mut handle, input).await {
+            Ok(output) => output,
+            Err(error) => return SelfTestOutcome::Failed { error },
+        };
+
+        // Audio has no expected file to catch drift, so check that the same
+        // input at least gives the same bytes twice.
+        if crate::util::deterministic::is_enabled() {
+            let again = match Self::run_self_test_input(&mut handle, input).await {
+                Ok(output) => output,
+                Err(error) => return SelfTestOutcome::Failed { error },
+            };
+            let (first, second) = (output_digest(&output), output_digest(&again));
+            if first != second {
+                return SelfTestOutcome::Nondeterministic { first, second };
+            }
+        }
+
+        match expected {
+            Some(expected) => compare_output(expected, &output),
+            None => SelfTestOutcome::Ran,
+        }
+    }
+
+    async fn run_self_test_input(
+        handle: &mut PipelineHandle,
+        input: &str,
+    ) -> Result<Vec<PipelineValue>, String> {
+        let mut stream = handle
+            .forward(PipelineValue::String(input.trim_end().to_string()))
+            .await;
+        let mut output = Vec::new();
+        while let Some(value) = stream.next().await {
+            output.push(value.map_err(|e| e.to_string())?);
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expected_files_are_split_on_their_last_dot() {
+        let bundle: PipelineBundle =
+            serde_json::from_str(include_str!("../tests/fixtures/toy/pipeline.json")).unwrap();
+        assert_eq!(
+            expected_case("v1.2.disambiguate", &bundle),
+            ("v1.2", "disambiguate")
+        );
+        assert_eq!(expected_case("v1.2", &bundle), ("v1.2", "shout"));
+        assert_eq!(expected_case("mun", &bundle), ("mun", "shout"));
+    }
+
+    #[test]
+    fn compare_output_matches_json_structurally_and_text_loosely() {
+        let json = [PipelineValue::Json(serde_json::json!({"a": 1, "b": [2]}))];
+        assert!(matches!(
+            compare_output("{ \"b\": [2], \"a\": 1 }\n", &json),
+            SelfTestOutcome::Passed
+        ));
+
+        let text = [PipelineValue::String("sámi\n".to_string())];
+        assert!(matches!(
+            compare_output("sámi", &text),
+            SelfTestOutcome::Passed
+        ));
+        assert!(matches!(
+            compare_output("sami", &text),
+            SelfTestOutcome::Mismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn tts_voices_come_from_the_config_arg() {
+        let command: ast::Command = serde_json::from_value(serde_json::json!({
+            "module": "speech",
+            "command": "tts",
+            "args": {
+                "config": {
+                    "type": "TtsConfig",
+                    "value": {
+                        "voices": {
+                            "sme": { "name": "Davvisámegiella", "language": 0, "speakers": { "0": "Biret", "1": "Máhtte" } }
+                        }
+                    }
+                }
+            },
+            "input": { "ref": "#/entry" },
+            "returns": "bytes"
+        }))
+        .unwrap();
+
+        let voices = tts_voices("tts", &command);
+        assert_eq!(voices.len(), 1);
+        assert_eq!(voices[0].id, "sme");
+        assert_eq!(voices[0].name, "Davvisámegiella");
+        assert_eq!(voices[0].speakers[&1], "Máhtte");
+        assert!(
+            tts_voices(
+                "tts",
+                &ast::Command {
+                    args: HashMap::new(),
+                    ..command
+                }
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn output_digest_covers_every_sample() {
+        let audio = |samples: Vec<f32>| {
+            [PipelineValue::Audio(modules::AudioBuffer {
+                samples,
+                sample_rate: 22050,
+                channels: 1,
+                word_timings: Vec::new(),
+                chunks: Vec::new(),
+            })]
+        };
+        let digest = output_digest(&audio(vec![0.0, 0.25, -0.5]));
+        assert!(digest.starts_with("[audio] blake3:"));
+        assert_eq!(digest, output_digest(&audio(vec![0.0, 0.25, -0.5])));
+        assert_ne!(digest, output_digest(&audio(vec![0.0, 0.25, -0.5001])));
+        assert_ne!(
+            output_digest(&[PipelineValue::Bytes(vec![1, 2])]),
+            output_digest(&[PipelineValue::Bytes(vec![1, 3])])
+        );
+    }
 }
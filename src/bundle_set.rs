@@ -0,0 +1,365 @@
+//! Several bundles, one per language, behind a single `check` entry point.
+//!
+//! Bundles are loaded on first use and evicted least-recently-used first when
+//! the configured bundle count or memory cap would be exceeded. Each loaded
+//! bundle keeps a [`PipePool`] of warm pipelines, and all checks share one
+//! concurrency limit, so a multilingual server can't oversubscribe the
+//! blocking pool however many languages are loaded.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use lru::LruCache;
+use tokio::sync::{OnceCell, Semaphore};
+use unic_langid::LanguageIdentifier;
+
+use crate::{
+    bundle::{Bundle, BundleOptions, Error},
+    modules::PipelineValue,
+    pipe_pool::{PipePool, PipePoolOptions},
+};
+
+#[derive(Debug, Clone)]
+pub struct BundleSetOptions {
+    /// Maximum number of bundles kept loaded at once.
+    pub max_loaded: Option<usize>,
+    /// Approximate memory budget in bytes, estimated from bundle file sizes.
+    pub memory_cap: Option<u64>,
+    /// Maximum number of checks running at the same time across all bundles.
+    pub max_concurrent: usize,
+    /// Options used when loading each bundle.
+    pub bundle_options: BundleOptions,
+}
+
+impl Default for BundleSetOptions {
+    fn default() -> Self {
+        BundleSetOptions {
+            max_loaded: None,
+            memory_cap: None,
+            max_concurrent: std::thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(4),
+            bundle_options: BundleOptions::default(),
+        }
+    }
+}
+
+/// Least-recently-used set of loaded values with a size budget.
+struct Lru<T> {
+    entries: LruCache<String, (T, u64)>,
+    size: u64,
+    max_entries: Option<usize>,
+    max_size: Option<u64>,
+}
+
+impl<T: Clone> Lru<T> {
+    fn new(max_entries: Option<usize>, max_size: Option<u64>) -> Self {
+        Lru {
+            entries: LruCache::unbounded(),
+            size: 0,
+            max_entries,
+            max_size,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<T> {
+        self.entries.get(key).map(|(value, _)| value.clone())
+    }
+
+    /// Insert `value`, evicting the least recently used entries to make room.
+    /// Returns the evicted keys.
+    fn insert(&mut self, key: String, value: T, size: u64) -> Vec<String> {
+        self.remove(&key);
+        let mut evicted = Vec::new();
+        loop {
+            let over_count = self
+                .max_entries
+                .is_some_and(|max| self.entries.len() + 1 > max);
+            let over_size = self.max_size.is_some_and(|max| self.size + size > max);
+            if !(over_count || over_size) {
+                break;
+            }
+            // Nothing left to evict; a single oversized entry is still allowed.
+            let Some((oldest, (_, oldest_size))) = self.entries.pop_lru() else {
+                break;
+            };
+            self.size -= oldest_size;
+            evicted.push(oldest);
+        }
+
+        self.size += size;
+        self.entries.put(key, (value, size));
+        evicted
+    }
+
+    fn remove(&mut self, key: &str) -> Option<T> {
+        let (value, size) = self.entries.pop(key)?;
+        self.size -= size;
+        Some(value)
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &String> {
+        self.entries.iter().map(|(key, _)| key)
+    }
+
+    fn values(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter().map(|(_, (value, _))| value)
+    }
+}
+
+/// Pick the registered language for `tag`: an exact match first, then the
+/// same language without region or script (`se-NO` falls back to `se`).
+pub fn resolve_language<'a>(
+    available: impl Iterator<Item = &'a String> + Clone,
+    tag: &str,
+) -> Option<&'a String> {
+    if let Some(exact) = available.clone().find(|x| x.eq_ignore_ascii_case(tag)) {
+        return Some(exact);
+    }
+
+    let wanted = tag.parse::<LanguageIdentifier>().ok()?;
+    available.into_iter().find(|x| {
+        x.parse::<LanguageIdentifier>()
+            .map(|id| id.language == wanted.language && id.region.is_none())
+            .unwrap_or(false)
+    })
+}
+
+/// ```
+/// # use divvun_runtime::bundle_set::{BundleSet, BundleSetOptions};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+/// let mut set = BundleSet::new(BundleSetOptions::default());
+/// set.insert("se", path);
+///
+/// // Loads the bundle; `se-NO` falls back to `se`
+/// let output = set.check("se-NO", "giella").await?;
+/// assert_eq!(output.len(), 1);
+/// assert_eq!(set.loaded_languages().await, ["se"]);
+/// # Ok(())
+/// # }
+/// ```
+pub struct BundleSet {
+    sources: HashMap<String, PathBuf>,
+    loaded: Mutex<Lru<Arc<PipePool>>>,
+    /// Bundles being loaded, so each is loaded by whichever check asks first
+    /// while checks of other languages go on.
+    loading: Mutex<HashMap<String, Arc<OnceCell<Arc<PipePool>>>>>,
+    permits: Arc<Semaphore>,
+    options: BundleSetOptions,
+}
+
+impl BundleSet {
+    pub fn new(options: BundleSetOptions) -> Self {
+        BundleSet {
+            sources: HashMap::new(),
+            loaded: Mutex::new(Lru::new(options.max_loaded, options.memory_cap)),
+            loading: Mutex::new(HashMap::new()),
+            permits: Arc::new(Semaphore::new(options.max_concurrent.max(1))),
+            options,
+        }
+    }
+
+    /// Register the bundle (a `.drb` file or bundle directory) for a language
+    /// tag. Nothing is loaded until the language is first checked.
+    pub fn insert(&mut self, lang_tag: impl Into<String>, path: impl Into<PathBuf>) {
+        self.sources.insert(lang_tag.into(), path.into());
+    }
+
+    pub fn languages(&self) -> impl Iterator<Item = &str> {
+        self.sources.keys().map(|x| x.as_str())
+    }
+
+    /// Languages whose bundles are currently loaded.
+    pub async fn loaded_languages(&self) -> Vec<String> {
+        self.loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    /// The registered language `lang_tag` resolves to, if any.
+    pub fn resolve(&self, lang_tag: &str) -> Option<&str> {
+        resolve_language(self.sources.keys(), lang_tag).map(|x| x.as_str())
+    }
+
+    /// Get the bundle for `lang_tag`, loading it if necessary.
+    pub async fn get(&self, lang_tag: &str) -> Result<Arc<Bundle>, Error> {
+        Ok(self.pool(lang_tag).await?.bundle().clone())
+    }
+
+    async fn pool(&self, lang_tag: &str) -> Result<Arc<PipePool>, Error> {
+        let lang = self
+            .resolve(lang_tag)
+            .ok_or_else(|| Error::UnknownLanguage(lang_tag.to_string()))?
+            .to_string();
+        if let Some(pool) = self
+            .loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&lang)
+        {
+            return Ok(pool);
+        }
+
+        let cell = self
+            .loading
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(lang.clone())
+            .or_default()
+            .clone();
+        let pool = cell.get_or_try_init(|| self.load(&lang)).await.cloned();
+        let mut loading = self.loading.lock().unwrap_or_else(PoisonError::into_inner);
+        if loading.get(&lang).is_some_and(|x| Arc::ptr_eq(x, &cell)) {
+            loading.remove(&lang);
+        }
+        pool
+    }
+
+    async fn load(&self, lang: &str) -> Result<Arc<PipePool>, Error> {
+        // Loaded by a check that finished loading it after ours looked
+        if let Some(pool) = self
+            .loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(lang)
+        {
+            return Ok(pool);
+        }
+
+        let path = &self.sources[lang];
+        tracing::debug!("Loading bundle for {} from {}", lang, path.display());
+        let bundle = Arc::new(load_bundle(path, self.options.bundle_options.clone()).await?);
+        // The set's permits limit how many run, across all languages
+        let pool = PipePool::new(
+            bundle,
+            PipePoolOptions {
+                max_concurrent: self.options.max_concurrent.max(1),
+                max_idle: self.options.max_concurrent.max(1),
+                ..Default::default()
+            },
+        );
+        let size = bundle_size(path);
+        let evicted = self
+            .loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(lang.to_string(), pool.clone(), size);
+        for evicted in evicted {
+            tracing::debug!("Evicted bundle for {}", evicted);
+        }
+        Ok(pool)
+    }
+
+    /// Drop the loaded bundle for `lang_tag`, if any. Checks already running
+    /// keep their bundle alive until they finish.
+    pub async fn unload(&self, lang_tag: &str) -> bool {
+        let Some(lang) = self.resolve(lang_tag) else {
+            return false;
+        };
+        self.loaded
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(lang)
+            .is_some()
+    }
+
+    /// Drop the warm pipelines of loaded bundles that went unused for five
+    /// minutes, e.g. from a timer. Returns how many were dropped.
+    pub fn evict_idle(&self) -> usize {
+        let loaded = self.loaded.lock().unwrap_or_else(PoisonError::into_inner);
+        loaded.values().map(|x| x.evict_idle()).sum()
+    }
+
+    pub async fn check(&self, lang_tag: &str, text: &str) -> Result<Vec<PipelineValue>, Error> {
+        self.check_with_config(lang_tag, text, serde_json::json!({}))
+            .await
+    }
+
+    /// Run `text` through the default pipeline of the bundle for `lang_tag`,
+    /// on a warm pipeline if one is free.
+    pub async fn check_with_config(
+        &self,
+        lang_tag: &str,
+        text: &str,
+        config: serde_json::Value,
+    ) -> Result<Vec<PipelineValue>, Error> {
+        let pool = self.pool(lang_tag).await?;
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| Error::Command(crate::modules::Error::wrap(e)))?;
+
+        pool.forward_with_config(PipelineValue::String(text.to_string()), config)
+            .await
+    }
+}
+
+async fn load_bundle(path: &Path, options: BundleOptions) -> Result<Bundle, Error> {
+    if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
+        Bundle::from_bundle_with_options(path, options).await
+    } else {
+        Bundle::from_path_with_options(path, options).await
+    }
+}
+
+/// Rough memory cost of a loaded bundle: models are memory mapped, so the
+/// bundle file size is a reasonable upper bound. Directories count as zero.
+fn bundle_size(path: &Path) -> u64 {
+    std::fs::metadata(path)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_language_prefers_exact_then_language_only() {
+        let available = vec!["se".to_string(), "se-FI".to_string(), "sma".to_string()];
+        assert_eq!(
+            resolve_language(available.iter(), "se-FI").map(String::as_str),
+            Some("se-FI")
+        );
+        assert_eq!(
+            resolve_language(available.iter(), "se-NO").map(String::as_str),
+            Some("se")
+        );
+        assert_eq!(
+            resolve_language(available.iter(), "SMA").map(String::as_str),
+            Some("sma")
+        );
+        assert_eq!(resolve_language(available.iter(), "fi"), None);
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used_within_budget() {
+        let mut lru = Lru::new(Some(2), Some(100));
+        assert!(lru.insert("se".into(), 1, 40).is_empty());
+        assert!(lru.insert("sma".into(), 2, 40).is_empty());
+        assert_eq!(lru.get("se"), Some(1));
+
+        // Count limit: sma is the least recently used.
+        assert_eq!(lru.insert("smj".into(), 3, 10), vec!["sma".to_string()]);
+        // Size limit: 40 + 10 + 60 > 100, so se goes.
+        assert_eq!(lru.insert("fo".into(), 4, 60), vec!["se".to_string()]);
+        assert_eq!(lru.get("smj"), Some(3));
+        assert_eq!(lru.get("fo"), Some(4));
+
+        // An entry larger than the budget still loads, alone.
+        let evicted = lru.insert("kl".into(), 5, 500);
+        assert_eq!(evicted.len(), 2);
+        assert_eq!(lru.get("kl"), Some(5));
+    }
+}
@@ -0,0 +1,283 @@
+//! Whether this runtime can run a bundle: the pipeline schema it is written
+//! in, the minimum runtime version it asks for and the commands it uses.
+//!
+//! Bundles are checked when they are loaded, before any command starts, so an
+//! outdated or cut-down runtime fails with one error listing everything it
+//! lacks instead of the first lookup that happens to go wrong.
+
+use std::fmt;
+
+use crate::ast::{PipelineBundle, PipelineDefinition};
+
+/// Newest `pipeline.json` schema version this runtime reads.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Something a bundle needs that this runtime doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Requirement {
+    /// A newer `pipeline.json` schema.
+    Schema(u32),
+    /// A newer runtime, from the bundle's `min_runtime_version`.
+    RuntimeVersion(String),
+    /// A module this runtime was built without, and the feature enabling it.
+    Module {
+        module: String,
+        feature: Option<&'static str>,
+    },
+    /// A command this runtime's version of the module doesn't have.
+    Command { module: String, command: String },
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Requirement::Schema(version) => write!(
+                f,
+                "pipeline schema version {} (this runtime reads up to {})",
+                version, SCHEMA_VERSION
+            ),
+            Requirement::RuntimeVersion(version) => write!(
+                f,
+                "divvun-runtime {} or newer (this is {})",
+                version,
+                env!("CARGO_PKG_VERSION")
+            ),
+            Requirement::Module {
+                module,
+                feature: Some(feature),
+            } => write!(
+                f,
+                "module `{}` (this runtime was built without the `{}` feature)",
+                module, feature
+            ),
+            Requirement::Module {
+                module,
+                feature: None,
+            } => write!(f, "module `{}` (unknown to this runtime)", module),
+            Requirement::Command { module, command } => write!(
+                f,
+                "command `{}::{}` (not in this runtime's `{}` module)",
+                module, command, module
+            ),
+        }
+    }
+}
+
+/// A bundle that needs things this runtime doesn't have.
+#[derive(Debug, Clone)]
+pub struct Incompatible {
+    pub missing: Vec<Requirement>,
+}
+
+impl fmt::Display for Incompatible {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "This bundle can't run on this divvun-runtime. It needs:")?;
+        for requirement in &self.missing {
+            write!(f, "\n  - {}", requirement)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Incompatible {}
+
+impl miette::Diagnostic for Incompatible {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("divvun_runtime::incompatible_bundle"))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let mut features = self
+            .missing
+            .iter()
+            .filter_map(|x| match x {
+                Requirement::Module {
+                    feature: Some(feature),
+                    ..
+                } => Some(*feature),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        features.dedup();
+        let upgrade = self.missing.iter().any(|x| {
+            !matches!(
+                x,
+                Requirement::Module {
+                    feature: Some(_),
+                    ..
+                }
+            )
+        });
+
+        let mut help = Vec::new();
+        if upgrade {
+            help.push("upgrade divvun-runtime".to_string());
+        }
+        if !features.is_empty() {
+            help.push(format!("build it with `--features {}`", features.join(",")));
+        }
+        let help = help.join(", or ");
+        let mut chars = help.chars();
+        let first = chars.next()?;
+        Some(Box::new(format!(
+            "{}{}",
+            first.to_uppercase(),
+            chars.as_str()
+        )))
+    }
+}
+
+/// Check that this runtime can run `defn` from `bundle`. Only the commands
+/// of the pipeline being loaded count, so a bundle whose other pipelines need
+/// a module this runtime lacks still loads.
+pub fn check(bundle: &PipelineBundle, defn: &PipelineDefinition) -> Result<(), Incompatible> {
+    let mut missing = Vec::new();
+
+    if bundle.version > SCHEMA_VERSION {
+        missing.push(Requirement::Schema(bundle.version));
+    }
+
+    if let Some(min) = &bundle.min_runtime_version {
+        match (parse_version(min), parse_version(env!("CARGO_PKG_VERSION"))) {
+            (Some(min_version), Some(version)) if min_version > version => {
+                missing.push(Requirement::RuntimeVersion(min.clone()));
+            }
+            (None, _) => {
+                tracing::warn!("Ignoring unreadable min_runtime_version {:?}", min);
+            }
+            _ => {}
+        }
+    }
+
+    for command in defn.commands.values() {
+        let requirement = if !crate::ts::MODULES.contains_key(&command.module) {
+            Requirement::Module {
+                module: command.module.clone(),
+                feature: crate::module_feature(&command.module),
+            }
+        } else if crate::modules::find_command(&command.module, &command.command).is_none() {
+            Requirement::Command {
+                module: command.module.clone(),
+                command: command.command.clone(),
+            }
+        } else {
+            continue;
+        };
+        if !missing.contains(&requirement) {
+            missing.push(requirement);
+        }
+    }
+
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(Incompatible { missing })
+    }
+}
+
+/// `major.minor.patch` of a version like `0.4.0`, `v1.2` or `1.0.0-beta.1`.
+/// Pre-release and build suffixes are ignored.
+fn parse_version(version: &str) -> Option<(u64, u64, u64)> {
+    let core = version.trim().trim_start_matches('v');
+    let core = core.split(['-', '+']).next()?;
+    let mut parts = core.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next().map_or(Some(0), |x| x.parse().ok())?;
+    let patch = parts.next().map_or(Some(0), |x| x.parse().ok())?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use miette::Diagnostic;
+
+    fn bundle(json: serde_json::Value) -> PipelineBundle {
+        PipelineBundle::from_json(json).unwrap()
+    }
+
+    #[test]
+    fn parses_versions() {
+        assert_eq!(parse_version("0.4.0"), Some((0, 4, 0)));
+        assert_eq!(parse_version("v1.2"), Some((1, 2, 0)));
+        assert_eq!(parse_version("1.0.0-beta.1"), Some((1, 0, 0)));
+        assert_eq!(parse_version("latest"), None);
+        assert_eq!(parse_version("1.2.3.4"), None);
+    }
+
+    #[test]
+    fn lists_everything_missing() {
+        let bundle = bundle(serde_json::json!({
+            "version": SCHEMA_VERSION + 1,
+            "min_runtime_version": "999.0.0",
+            "default": "default",
+            "pipelines": {
+                "default": {
+                    "entry": { "value_type": "string" },
+                    "output": { "ref": "b" },
+                    "commands": {
+                        "a": {
+                            "module": "nosuchmodule",
+                            "command": "run",
+                            "input": { "ref": "#/entry" },
+                            "returns": "string"
+                        },
+                        "b": {
+                            "module": "example",
+                            "command": "nosuchcommand",
+                            "input": { "ref": "a" },
+                            "returns": "string"
+                        }
+                    }
+                }
+            }
+        }));
+        let defn = bundle.get_pipeline(None).unwrap();
+
+        let err = check(&bundle, defn).unwrap_err();
+        assert_eq!(
+            err.missing,
+            vec![
+                Requirement::Schema(SCHEMA_VERSION + 1),
+                Requirement::RuntimeVersion("999.0.0".to_string()),
+                Requirement::Module {
+                    module: "nosuchmodule".to_string(),
+                    feature: None,
+                },
+                Requirement::Command {
+                    module: "example".to_string(),
+                    command: "nosuchcommand".to_string(),
+                },
+            ]
+        );
+        assert!(err.to_string().contains("\n  - pipeline schema version"));
+        assert_eq!(err.help().unwrap().to_string(), "Upgrade divvun-runtime");
+    }
+
+    #[test]
+    fn accepts_bundles_this_runtime_can_run() {
+        let bundle = bundle(serde_json::json!({
+            "version": SCHEMA_VERSION,
+            "min_runtime_version": "0.1.0",
+            "default": "default",
+            "pipelines": {
+                "default": {
+                    "entry": { "value_type": "string" },
+                    "output": { "ref": "a" },
+                    "commands": {
+                        "a": {
+                            "module": "example",
+                            "command": "upper",
+                            "input": { "ref": "#/entry" },
+                            "returns": "string"
+                        }
+                    }
+                }
+            }
+        }));
+        assert!(check(&bundle, bundle.get_pipeline(None).unwrap()).is_ok());
+    }
+}
@@ -0,0 +1,413 @@
+//! A long-running process answering word processor plugins (LibreOffice, MS
+//! Word) over a Unix domain socket, or a named pipe on Windows.
+//!
+//! Loading a bundle's transducers takes seconds, too long to pay for every
+//! paragraph by running the CLI once per check. A [`Daemon`] loads the bundle
+//! once and keeps a [`PipePool`] of warm pipelines for each pipeline asked
+//! for.
+//!
+//! Each line a client sends is a JSON request, answered with one line of JSON
+//! in the order the requests came:
+//!
+//! ```json
+//! {"id": 1, "pipeline": "grammar", "text": "Mun lean boahtan.", "config": {}}
+//! {"id": 1, "output": [[{"form": "boahtan", "beg": 9, ...}]]}
+//! ```
+//!
+//! `pipeline` defaults to the bundle's default pipeline, and `config` is
+//! runtime config as for [`Bundle::create`], for that request only. `id` is
+//! optional and copied to the answer as it is. `output` has a value per
+//! document the pipeline returned: JSON as it is, anything else as its text.
+//! A request that fails is answered with `{"id": 1, "error": "..."}` and the
+//! connection stays open. A line longer than [`MAX_LINE`] is answered with an
+//! error and ends the connection.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError, Weak},
+};
+
+use tokio::{
+    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    sync::OnceCell,
+};
+
+use crate::{
+    bundle::{Bundle, Error},
+    modules::PipelineValue,
+    pipe_pool::{PipePool, PipePoolOptions},
+};
+
+#[derive(Debug, serde::Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Option<serde_json::Value>,
+    #[serde(default)]
+    pipeline: Option<String>,
+    text: String,
+    #[serde(default)]
+    config: Option<serde_json::Value>,
+}
+
+/// Longest request line a daemon reads, in bytes.
+pub const MAX_LINE: usize = 16 * 1024 * 1024;
+
+/// Where a daemon listens unless told otherwise: `divvun-runtime.sock` in
+/// `$XDG_RUNTIME_DIR`, or else in a `divvun-runtime-<uid>` directory of the
+/// temporary directory that only the user can access, created if missing.
+/// On Windows the pipe `\\.\pipe\divvun-runtime`.
+pub fn default_address() -> std::io::Result<PathBuf> {
+    #[cfg(windows)]
+    {
+        Ok(PathBuf::from(r"\\.\pipe\divvun-runtime"))
+    }
+    #[cfg(not(windows))]
+    {
+        let dir = match std::env::var_os("XDG_RUNTIME_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => {
+                // SAFETY: getuid has no preconditions and can't fail.
+                let uid = unsafe { libc::getuid() };
+                let dir = std::env::temp_dir().join(format!("divvun-runtime-{}", uid));
+                private_dir(&dir, uid)?;
+                dir
+            }
+        };
+        Ok(dir.join("divvun-runtime.sock"))
+    }
+}
+
+/// Create `dir` accessible only to `uid`, or check that it already is: the
+/// temporary directory is shared, and anyone who can reach the socket can
+/// have text checked and read the answers.
+#[cfg(not(windows))]
+fn private_dir(dir: &Path, uid: u32) -> std::io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.permissions().mode() & 0o077 != 0 {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            format!(
+                "{} is not a directory only its owner can access; remove it or give a socket path",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// A bundle kept loaded between requests. See the [module docs](self).
+pub struct Daemon {
+    /// Pool of the bundle's default pipeline.
+    default: Arc<PipePool>,
+    /// Pools of the named pipelines asked for so far, each loaded once by
+    /// whichever request asks first while the others wait for it.
+    pools: Mutex<HashMap<String, Arc<OnceCell<Arc<PipePool>>>>>,
+    options: PipePoolOptions,
+}
+
+impl Daemon {
+    pub fn new(bundle: Bundle, options: PipePoolOptions) -> Arc<Daemon> {
+        Arc::new(Daemon {
+            default: PipePool::new(Arc::new(bundle), options.clone()),
+            pools: Mutex::new(HashMap::new()),
+            options,
+        })
+    }
+
+    /// The pool of pipeline `name`, loading it the first time it is asked
+    /// for.
+    async fn pool(&self, name: Option<&str>) -> Result<Arc<PipePool>, Error> {
+        let Some(name) = name else {
+            return Ok(self.default.clone());
+        };
+        let cell = self
+            .pools
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(name.to_string())
+            .or_default()
+            .clone();
+        let loaded = cell
+            .get_or_try_init(|| async {
+                tracing::debug!("Loading pipeline {}", name);
+                let bundle = self.default.bundle().pipeline(name).await?;
+                Ok::<_, Error>(PipePool::new(Arc::new(bundle), self.options.clone()))
+            })
+            .await;
+        match loaded {
+            Ok(pool) => Ok(pool.clone()),
+            Err(e) => {
+                // Keep no entry for names that don't load
+                let mut pools = self.pools.lock().unwrap_or_else(PoisonError::into_inner);
+                if pools
+                    .get(name)
+                    .is_some_and(|x| Arc::ptr_eq(x, &cell) && !x.initialized())
+                {
+                    pools.remove(name);
+                }
+                Err(e)
+            }
+        }
+    }
+
+    async fn run(&self, request: Request) -> Result<Vec<PipelineValue>, Error> {
+        let pool = self.pool(request.pipeline.as_deref()).await?;
+        let input = PipelineValue::String(request.text);
+        match request.config {
+            Some(config) => pool.forward_with_config(input, config).await,
+            None => pool.forward(input).await,
+        }
+    }
+
+    /// The answer to one request line, without its newline.
+    pub async fn respond(&self, line: &str) -> String {
+        let (id, result) = match serde_json::from_str::<Request>(line) {
+            Ok(mut request) => {
+                let id = request.id.take();
+                let result = self.run(request).await.map_err(|e| e.to_string());
+                (id, result)
+            }
+            Err(e) => (None, Err(format!("invalid request: {}", e))),
+        };
+
+        let mut answer = serde_json::Map::new();
+        if let Some(id) = id {
+            answer.insert("id".into(), id);
+        }
+        match result {
+            Ok(output) => {
+                let output = output
+                    .into_iter()
+                    .map(|x| match x {
+                        PipelineValue::Json(value) => value,
+                        other => serde_json::Value::String(other.to_string()),
+                    })
+                    .collect();
+                answer.insert("output".into(), serde_json::Value::Array(output));
+            }
+            Err(e) => {
+                answer.insert("error".into(), serde_json::Value::String(e));
+            }
+        }
+        serde_json::Value::Object(answer).to_string()
+    }
+
+    /// Answer every line of `input` on `output` until `input` ends, or a
+    /// line is longer than [`MAX_LINE`]. Empty lines are skipped.
+    pub async fn serve<R, W>(&self, mut input: R, mut output: W) -> Result<(), Error>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            let read = (&mut input)
+                .take(MAX_LINE as u64 + 1)
+                .read_until(b'\n', &mut line)
+                .await?;
+            if read == 0 {
+                return Ok(());
+            }
+            let too_long = line.len() > MAX_LINE && !line.ends_with(b"\n");
+            let answer = if too_long {
+                Some(error_answer(format!(
+                    "request longer than {} bytes",
+                    MAX_LINE
+                )))
+            } else {
+                match std::str::from_utf8(&line).map(str::trim) {
+                    Ok("") => None,
+                    Ok(line) => Some(self.respond(line).await),
+                    Err(e) => Some(error_answer(format!("invalid request: {}", e))),
+                }
+            };
+            if let Some(answer) = answer {
+                output.write_all(answer.as_bytes()).await?;
+                output.write_all(b"\n").await?;
+                output.flush().await?;
+            }
+            if too_long {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Drop pipelines idle for longer than the pool options' idle timeout.
+    /// Returns how many were dropped.
+    pub fn evict_idle(&self) -> usize {
+        let pools = self.pools.lock().unwrap_or_else(PoisonError::into_inner);
+        self.default.evict_idle()
+            + pools
+                .values()
+                .filter_map(|x| x.get())
+                .map(|x| x.evict_idle())
+                .sum::<usize>()
+    }
+
+    /// Evict idle pipelines every idle timeout for as long as the daemon is
+    /// alive.
+    fn spawn_eviction(self: &Arc<Self>) {
+        let daemon = Arc::downgrade(self);
+        let every = self.options.idle_timeout;
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(every);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let Some(daemon) = Weak::upgrade(&daemon) else {
+                    return;
+                };
+                let evicted = daemon.evict_idle();
+                if evicted > 0 {
+                    tracing::debug!("Evicted {} idle pipelines", evicted);
+                }
+            }
+        });
+    }
+
+    /// Accept connections on the Unix domain socket `path` and serve each
+    /// one, until accepting fails. A socket file left behind by a daemon that
+    /// didn't exit cleanly is replaced, but not one a daemon still listens on.
+    #[cfg(unix)]
+    pub async fn listen(self: Arc<Self>, path: &Path) -> Result<(), Error> {
+        use tokio::net::{UnixListener, UnixStream};
+
+        if path.exists() {
+            if UnixStream::connect(path).await.is_ok() {
+                return Err(Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("a daemon is already listening on {}", path.display()),
+                )));
+            }
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        tracing::info!("Listening on {}", path.display());
+        self.spawn_eviction();
+
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let daemon = self.clone();
+            tokio::spawn(async move {
+                let (read, write) = stream.into_split();
+                if let Err(e) = daemon.serve(tokio::io::BufReader::new(read), write).await {
+                    tracing::warn!("Connection failed: {}", e);
+                }
+            });
+        }
+    }
+
+    /// Accept connections on the named pipe `path`, such as
+    /// `\\.\pipe\divvun-runtime`, and serve each one, until accepting fails.
+    #[cfg(windows)]
+    pub async fn listen(self: Arc<Self>, path: &Path) -> Result<(), Error> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .create(path)?;
+        tracing::info!("Listening on {}", path.display());
+        self.spawn_eviction();
+
+        loop {
+            server.connect().await?;
+            // Create the next instance before serving this one, so clients
+            // never find the pipe missing
+            let connected = std::mem::replace(&mut server, ServerOptions::new().create(path)?);
+            let daemon = self.clone();
+            tokio::spawn(async move {
+                let (read, write) = tokio::io::split(connected);
+                if let Err(e) = daemon.serve(tokio::io::BufReader::new(read), write).await {
+                    tracing::warn!("Connection failed: {}", e);
+                }
+            });
+        }
+    }
+}
+
+/// The answer to a line that isn't a request at all.
+fn error_answer(message: String) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::io::BufReader;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn answers_each_request_on_its_own_line() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+        let bundle = Bundle::from_path(path).await.unwrap();
+        let daemon = Daemon::new(bundle, PipePoolOptions::default());
+
+        let (client, server) = tokio::io::duplex(4096);
+        let serving = tokio::spawn({
+            let daemon = daemon.clone();
+            async move {
+                let (read, write) = tokio::io::split(server);
+                daemon.serve(BufReader::new(read), write).await
+            }
+        });
+
+        let (read, mut write) = tokio::io::split(client);
+        let requests = [
+            r#"{"id": 1, "text": "lean"}"#,
+            "",
+            r#"{"id": "b", "pipeline": "nope", "text": "lean"}"#,
+            "not json",
+            r#"{"pipeline": "shout", "text": "giella"}"#,
+            r#"{"id": 2, "pipeline": "shout", "text": "sátni", "config": {}}"#,
+        ];
+        for request in requests {
+            write.write_all(request.as_bytes()).await.unwrap();
+            write.write_all(b"\n").await.unwrap();
+        }
+        write.shutdown().await.unwrap();
+
+        let mut answers = Vec::new();
+        let mut lines = BufReader::new(read).lines();
+        while let Some(line) = lines.next_line().await.unwrap() {
+            answers.push(serde_json::from_str::<serde_json::Value>(&line).unwrap());
+        }
+        serving.await.unwrap().unwrap();
+
+        assert_eq!(answers.len(), 5);
+        assert_eq!(
+            answers[0],
+            serde_json::json!({ "id": 1, "output": ["NAEL"] })
+        );
+        assert_eq!(answers[1]["id"], "b");
+        assert!(
+            answers[1]["error"]
+                .as_str()
+                .unwrap()
+                .contains("Pipeline 'nope' not found")
+        );
+        assert!(answers[2].get("id").is_none());
+        assert!(
+            answers[2]["error"]
+                .as_str()
+                .unwrap()
+                .starts_with("invalid request")
+        );
+        assert_eq!(answers[3], serde_json::json!({ "output": ["ALLEIG"] }));
+        assert_eq!(
+            answers[4],
+            serde_json::json!({ "id": 2, "output": ["INTÁS"] })
+        );
+
+        // The pipeline stays loaded for the next request
+        assert_eq!(daemon.pools.lock().unwrap().len(), 1);
+    }
+}
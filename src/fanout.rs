@@ -0,0 +1,546 @@
+//! Several pipelines of a bundle over one input, for
+//! [`Bundle::run_all`](crate::bundle::Bundle::run_all).
+//!
+//! Pipelines of a bundle often start the same way: a grammar checker and a
+//! readability scorer both tokenize and disambiguate the text first. Steps
+//! that compute the same thing (same command, args and input, all the way
+//! back to the entry) run once for all the pipelines having them, and each
+//! pipeline goes on from their output. Pipelines that don't share the steps,
+//! or are given different config for them, run on their own at the same
+//! time.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use futures_util::{FutureExt, StreamExt, future::join_all};
+use indexmap::IndexMap;
+
+use crate::{
+    ast::{self, Command, ENTRY, InputValue, Pipe, PipelineDefinition, PipelineHandle, Ref},
+    bundle::{Bundle, Error},
+    modules::{self, PipelineValue},
+};
+
+/// What each pipeline gave, or why it failed.
+pub type Outputs = IndexMap<String, Result<Vec<PipelineValue>, modules::Error>>;
+
+/// Pipelines prepared to run over one input, sharing what steps they can.
+pub(crate) struct Fanout {
+    members: Vec<Member>,
+    /// The shared steps, keyed as in the first pipeline having them.
+    shared: Option<Pipe>,
+}
+
+struct Member {
+    name: String,
+    /// The whole pipeline, for when it can't use the shared steps.
+    bundle: Bundle,
+    shared: Option<SharedPart>,
+}
+
+struct SharedPart {
+    /// This pipeline's keys of the shared steps, in the order of the
+    /// shared pipe's.
+    keys: Vec<String>,
+    /// The rest of the pipeline, reading the last shared step's output as
+    /// its input. `None` if that output is the pipeline's.
+    rest: Option<Pipe>,
+}
+
+impl Fanout {
+    pub(crate) async fn new(bundle: &Bundle, names: &[String]) -> Result<Fanout, Error> {
+        let mut pipelines = Vec::with_capacity(names.len());
+        for name in names {
+            pipelines.push(bundle.pipeline(name).await?);
+        }
+        let defns = pipelines
+            .iter()
+            .map(|x| &**x.definition())
+            .collect::<Vec<_>>();
+        let splits = split(&defns);
+
+        let mut shared = None;
+        let mut members = Vec::with_capacity(names.len());
+        for ((name, pipeline), split) in names.iter().zip(pipelines).zip(splits) {
+            let shared_part = match split {
+                Some(split) => {
+                    let defn = pipeline.definition();
+                    if shared.is_none() {
+                        tracing::debug!(
+                            "run_all: sharing {} of {}'s steps",
+                            split.keys.len(),
+                            name
+                        );
+                        let prefix = prefix_definition(defn, &split);
+                        shared = Some(Pipe::new(bundle.context().clone(), Arc::new(prefix)).await?);
+                    }
+                    let rest = match rest_definition(defn, &split) {
+                        Some(rest) => {
+                            Some(Pipe::new(bundle.context().clone(), Arc::new(rest)).await?)
+                        }
+                        None => None,
+                    };
+                    Some(SharedPart {
+                        keys: split.keys,
+                        rest,
+                    })
+                }
+                None => None,
+            };
+            members.push(Member {
+                name: name.clone(),
+                bundle: pipeline,
+                shared: shared_part,
+            });
+        }
+
+        Ok(Fanout { members, shared })
+    }
+
+    pub(crate) async fn run(&self, input: PipelineValue, config: &serde_json::Value) -> Outputs {
+        let sharing = self
+            .members
+            .iter()
+            .map(|member| {
+                let (Some(shared), Some(part)) = (&self.shared, &member.shared) else {
+                    return false;
+                };
+                same_config(&shared.defn, member.bundle.definition(), &part.keys, config)
+            })
+            .collect::<Vec<_>>();
+
+        let prefix = async {
+            let Some(shared) = &self.shared else {
+                return Err(modules::Error::msg("no shared steps"));
+            };
+            let handle = shared
+                .create_stream(Arc::new(config.clone()), None)
+                .await
+                .map_err(construction_error);
+            run_handle(handle, vec![input.clone()]).await
+        }
+        .shared();
+
+        let runs = self.members.iter().zip(sharing).map(|(member, sharing)| {
+            let prefix = prefix.clone();
+            let input = input.clone();
+            async move {
+                let output = if !sharing {
+                    let handle = member
+                        .bundle
+                        .create(config.clone())
+                        .await
+                        .map_err(|e| match e {
+                            Error::Ast(e) => construction_error(e),
+                            e => modules::Error::wrap(e),
+                        });
+                    run_handle(handle, vec![input]).await
+                } else {
+                    match (prefix.await, &member.shared) {
+                        (
+                            Ok(values),
+                            Some(SharedPart {
+                                rest: Some(rest), ..
+                            }),
+                        ) => {
+                            let handle = rest
+                                .create_stream(Arc::new(config.clone()), None)
+                                .await
+                                .map_err(construction_error);
+                            run_handle(handle, values).await
+                        }
+                        (output, _) => output,
+                    }
+                };
+                (member.name.clone(), output)
+            }
+        });
+
+        join_all(runs).await.into_iter().collect()
+    }
+}
+
+fn construction_error(error: ast::Error) -> modules::Error {
+    match error {
+        ast::Error::Command(e) => e,
+        e => modules::Error::wrap(e),
+    }
+}
+
+async fn run_handle(
+    handle: Result<PipelineHandle, modules::Error>,
+    inputs: Vec<PipelineValue>,
+) -> Result<Vec<PipelineValue>, modules::Error> {
+    let mut stream = handle?.forward_values(inputs).await;
+    let mut output = Vec::new();
+    while let Some(value) = stream.next().await {
+        output.push(value?);
+    }
+    Ok(output)
+}
+
+/// Whether the caller's config gives the steps the pipeline shares the same
+/// config as the shared pipe's, which are keyed as in another pipeline.
+fn same_config(
+    shared: &PipelineDefinition,
+    defn: &PipelineDefinition,
+    keys: &[String],
+    config: &serde_json::Value,
+) -> bool {
+    shared
+        .commands
+        .iter()
+        .zip(keys)
+        .all(|((shared_key, a), key)| {
+            let b = &defn.commands[key];
+            a.runtime_config(config.get(shared_key)) == b.runtime_config(config.get(key))
+        })
+}
+
+/// Where a pipeline is cut to use the shared steps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Split {
+    /// The pipeline's keys of the shared steps, in the order of the first
+    /// pipeline having them.
+    keys: Vec<String>,
+    /// The last shared step, which the rest of the pipeline reads from.
+    cut: String,
+}
+
+fn input_refs(command: &Command) -> Vec<&str> {
+    match &command.input {
+        InputValue::Single(x) => vec![x.r#ref.as_str()],
+        InputValue::Multiple(x) => x.iter().map(|x| x.r#ref.as_str()).collect(),
+    }
+}
+
+/// A digest of what each step computes: its command, args and the digests
+/// of its inputs. Steps of two pipelines with the same digest give the same
+/// output for the same input.
+fn signatures(defn: &PipelineDefinition) -> HashMap<&str, String> {
+    let mut signatures = HashMap::new();
+    signatures.insert(ENTRY, format!("entry:{}", defn.entry.value_type));
+    loop {
+        let before = signatures.len();
+        for (key, command) in &defn.commands {
+            if signatures.contains_key(key.as_str()) {
+                continue;
+            }
+            let Some(inputs) = input_refs(command)
+                .into_iter()
+                .map(|x| signatures.get(x).cloned())
+                .collect::<Option<Vec<_>>>()
+            else {
+                continue;
+            };
+            let args = command.args.iter().collect::<BTreeMap<_, _>>();
+            let json = serde_json::to_string(&(
+                &command.module,
+                &command.command,
+                args,
+                &command.defaults,
+                &command.returns,
+                inputs,
+            ))
+            .unwrap_or_default();
+            signatures.insert(
+                key.as_str(),
+                blake3::hash(json.as_bytes()).to_hex().to_string(),
+            );
+        }
+        if signatures.len() == before {
+            break;
+        }
+    }
+    signatures.remove(ENTRY);
+    signatures
+}
+
+/// `key` and the steps it reads from, directly or not.
+fn ancestors<'a>(defn: &'a PipelineDefinition, key: &'a str) -> HashSet<&'a str> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![key];
+    while let Some(key) = stack.pop() {
+        let Some(command) = defn.commands.get(key) else {
+            continue;
+        };
+        if seen.insert(key) {
+            stack.extend(input_refs(command));
+        }
+    }
+    seen
+}
+
+/// Whether the pipeline can run on from `cut` alone: nothing after it reads
+/// the entry or a step before it.
+fn can_cut(defn: &PipelineDefinition, cut: &str, before: &HashSet<&str>) -> bool {
+    let rest_reads_cut_only = defn
+        .commands
+        .iter()
+        .filter(|(key, _)| !before.contains(key.as_str()))
+        .flat_map(|(_, command)| input_refs(command))
+        .all(|x| x == cut || (x != ENTRY && !before.contains(x)));
+    let output = defn.output.r#ref.as_str();
+    rest_reads_cut_only && (output == cut || !before.contains(output))
+}
+
+/// The steps shared by the most pipelines, weighed by how many steps they
+/// are, and where each pipeline having them is cut. `None` for pipelines
+/// that run on their own.
+fn split(defns: &[&PipelineDefinition]) -> Vec<Option<Split>> {
+    let signatures = defns.iter().map(|x| signatures(x)).collect::<Vec<_>>();
+    let find = |i: usize, signature: &str| {
+        defns[i]
+            .commands
+            .keys()
+            .find(|key| signatures[i].get(key.as_str()).map(String::as_str) == Some(signature))
+    };
+    // Where pipeline `i` is cut after the step with `signature`, if it can be
+    let cut_at = |i: usize, signature: &str| {
+        find(i, signature).filter(|key| can_cut(defns[i], key, &ancestors(defns[i], key)))
+    };
+
+    // Score, signature of the cut
+    let mut best: Option<(usize, &str)> = None;
+    for (i, defn) in defns.iter().enumerate() {
+        for key in defn.commands.keys() {
+            let Some(signature) = signatures[i].get(key.as_str()).map(String::as_str) else {
+                continue;
+            };
+            let sharing = (0..defns.len())
+                .filter(|&j| cut_at(j, signature).is_some())
+                .count();
+            if sharing < 2 {
+                continue;
+            }
+            let score = (sharing - 1) * ancestors(defn, key).len();
+            if best.is_none_or(|(best, _)| score > best) {
+                best = Some((score, signature));
+            }
+        }
+    }
+    let Some((_, cut)) = best else {
+        return vec![None; defns.len()];
+    };
+
+    // The shared steps in the order of the first pipeline having them
+    let Some((first, first_cut)) = (0..defns.len()).find_map(|i| Some((i, cut_at(i, cut)?))) else {
+        return vec![None; defns.len()];
+    };
+    let before = ancestors(defns[first], first_cut);
+    let shared = defns[first]
+        .commands
+        .keys()
+        .filter(|key| before.contains(key.as_str()))
+        .map(|key| signatures[first][key.as_str()].as_str())
+        .collect::<Vec<_>>();
+
+    (0..defns.len())
+        .map(|i| {
+            let cut = cut_at(i, cut)?;
+            let keys = shared
+                .iter()
+                .map(|&x| find(i, x).cloned())
+                .collect::<Option<Vec<_>>>()?;
+            Some(Split {
+                keys,
+                cut: cut.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The shared steps of `defn`, ending in the cut.
+fn prefix_definition(defn: &PipelineDefinition, split: &Split) -> PipelineDefinition {
+    PipelineDefinition {
+        entry: defn.entry.clone(),
+        output: Ref {
+            r#ref: split.cut.clone(),
+        },
+        commands: defn
+            .commands
+            .iter()
+            .filter(|(key, _)| split.keys.contains(key))
+            .map(|(key, command)| (key.clone(), command.clone()))
+            .collect(),
+        dev: defn.dev,
+    }
+}
+
+/// The steps of `defn` after the cut, reading its output as their entry, or
+/// `None` if there are none.
+fn rest_definition(defn: &PipelineDefinition, split: &Split) -> Option<PipelineDefinition> {
+    if defn.output.r#ref == split.cut {
+        return None;
+    }
+    let from_cut = |x: &Ref| Ref {
+        r#ref: if x.r#ref == split.cut {
+            ENTRY.to_string()
+        } else {
+            x.r#ref.clone()
+        },
+    };
+    let commands = defn
+        .commands
+        .iter()
+        .filter(|(key, _)| !split.keys.contains(key))
+        .map(|(key, command)| {
+            let input = match &command.input {
+                InputValue::Single(x) => InputValue::Single(from_cut(x)),
+                InputValue::Multiple(x) => InputValue::Multiple(x.iter().map(from_cut).collect()),
+            };
+            let command = Command {
+                input,
+                ..command.clone()
+            };
+            (key.clone(), command)
+        })
+        .collect();
+
+    Some(PipelineDefinition {
+        entry: ast::Entry {
+            value_type: defn.commands[&split.cut].returns.clone(),
+        },
+        output: defn.output.clone(),
+        commands,
+        dev: defn.dev,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(module: &str, command: &str, input: &str) -> serde_json::Value {
+        serde_json::json!({
+            "module": module,
+            "command": command,
+            "args": if command == "trickle" {
+                serde_json::json!({
+                    "count": { "type": "int", "value": 2 },
+                    "delay_ms": { "type": "int", "value": 0 }
+                })
+            } else {
+                serde_json::json!({})
+            },
+            "input": { "ref": input },
+            "returns": "string"
+        })
+    }
+
+    /// A grammar checker and a readability scorer tokenizing the same way,
+    /// and a language guesser reading the raw text.
+    fn pipelines() -> serde_json::Value {
+        serde_json::json!({
+            "grammar": {
+                "entry": { "value_type": "string" },
+                "output": { "ref": "suggest" },
+                "commands": {
+                    "tokenize": step("example", "upper", "#/entry"),
+                    "disambiguate": step("example", "reverse", "tokenize"),
+                    "suggest": step("debug", "trickle", "disambiguate")
+                }
+            },
+            "readability": {
+                "entry": { "value_type": "string" },
+                "output": { "ref": "score" },
+                "commands": {
+                    "upper": step("example", "upper", "#/entry"),
+                    "reverse": step("example", "reverse", "upper"),
+                    "score": step("example", "upper", "reverse")
+                }
+            },
+            "langid": {
+                "entry": { "value_type": "string" },
+                "output": { "ref": "guess" },
+                "commands": {
+                    "guess": step("debug", "trickle", "#/entry")
+                }
+            }
+        })
+    }
+
+    fn definitions() -> Vec<PipelineDefinition> {
+        serde_json::from_value::<IndexMap<String, PipelineDefinition>>(pipelines())
+            .unwrap()
+            .into_values()
+            .collect()
+    }
+
+    #[test]
+    fn pipelines_share_their_common_steps() {
+        let defns = definitions();
+        let splits = split(&defns.iter().collect::<Vec<_>>());
+        assert_eq!(
+            splits,
+            [
+                Some(Split {
+                    keys: vec!["tokenize".to_string(), "disambiguate".to_string()],
+                    cut: "disambiguate".to_string(),
+                }),
+                Some(Split {
+                    keys: vec!["upper".to_string(), "reverse".to_string()],
+                    cut: "reverse".to_string(),
+                }),
+                None,
+            ]
+        );
+
+        let rest = rest_definition(&defns[1], splits[1].as_ref().unwrap()).unwrap();
+        assert_eq!(rest.commands.keys().collect::<Vec<_>>(), ["score"]);
+        assert_eq!(rest.entry.value_type, "string");
+        assert!(matches!(&rest.commands["score"].input, InputValue::Single(x) if x.r#ref == ENTRY));
+    }
+
+    #[test]
+    fn steps_still_reading_the_entry_prevent_sharing() {
+        let mut defns = definitions();
+        // Reads the input again after the common steps
+        defns[1].commands["score"].input = InputValue::Single(Ref {
+            r#ref: ENTRY.to_string(),
+        });
+        let splits = split(&defns.iter().collect::<Vec<_>>());
+        assert!(splits.iter().all(Option::is_none));
+    }
+
+    #[tokio::test]
+    async fn run_all_gives_what_each_pipeline_gives_alone() {
+        let temp = tempfile::tempdir().unwrap();
+        let bundle = serde_json::json!({
+            "version": 1,
+            "default": "grammar",
+            "pipelines": pipelines()
+        });
+        std::fs::write(temp.path().join("pipeline.json"), bundle.to_string()).unwrap();
+        let bundle = Bundle::from_path(temp.path()).await.unwrap();
+
+        let names = ["grammar", "readability", "langid"];
+        let outputs = bundle
+            .run_all(
+                &names,
+                "Mun borran".to_string().into(),
+                serde_json::json!({}),
+            )
+            .await
+            .unwrap();
+        assert_eq!(outputs.keys().collect::<Vec<_>>(), names);
+        for name in names {
+            let alone = bundle.pipeline(name).await.unwrap();
+            let mut handle = alone.create(serde_json::json!({})).await.unwrap();
+            let expected = handle
+                .forward("Mun borran".to_string().into())
+                .await
+                .collect::<Vec<_>>()
+                .await;
+            let expected = expected.into_iter().collect::<Result<Vec<_>, _>>().unwrap();
+            let output = outputs[name].as_ref().unwrap();
+            assert_eq!(
+                output.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                expected.iter().map(|x| x.to_string()).collect::<Vec<_>>(),
+                "{name}"
+            );
+        }
+    }
+}
@@ -5,13 +5,21 @@ use std::sync::Arc;
 use cffi::{FromForeign, ToForeign, marshal};
 use futures_util::StreamExt;
 
-use crate::{ast::PipelineHandle, bundle::Bundle, modules::PipelineValue};
+use crate::{
+    ast::{PipelineDefinition, PipelineHandle},
+    bundle::Bundle,
+    modules::PipelineValue,
+};
 
 type U8VecMarshaler = cffi::VecMarshaler<u8>;
+type U16VecMarshaler = cffi::VecMarshaler<u16>;
+type U16SliceMarshaler<'a> = cffi::SliceMarshaler<'a, u16>;
 type BundleArcMarshaler = cffi::ArcMarshaler<Bundle>;
 type BundleArcRefMarshaler = cffi::ArcRefMarshaler<Bundle>;
 type PipelineHandleBoxMarshaler = cffi::BoxMarshaler<PipelineHandle>;
 type PipelineHandleBoxMutRefMarshaler = cffi::BoxMutRefMarshaler<PipelineHandle>;
+type Utf16HandleBoxMarshaler = cffi::BoxMarshaler<Utf16PipelineHandle>;
+type Utf16HandleBoxMutRefMarshaler = cffi::BoxMutRefMarshaler<Utf16PipelineHandle>;
 
 #[derive(Debug, thiserror::Error)]
 #[error("{0}")]
@@ -43,6 +51,25 @@ const _: () = {
     std::hint::black_box(DRT_Bundle_fromBundle);
 };
 
+/// Turn privacy mode off to have input text logged, or back on. See
+/// [`crate::util::privacy`].
+#[marshal]
+pub fn DRT_setPrivacyMode(#[marshal(cffi::BoolMarshaler)] enabled: bool) {
+    crate::util::privacy::set_enabled(enabled);
+}
+
+/// How long [`DRT_shutdown`] waits for blocking work to finish.
+const SHUTDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Stop everything the runtime has running before the host unloads it. See
+/// [`crate::shutdown`].
+#[marshal]
+pub fn DRT_shutdown() {
+    RT.with(|rt| {
+        rt.block_on(crate::shutdown(SHUTDOWN_TIMEOUT));
+    });
+}
+
 #[marshal]
 pub fn DRT_Bundle_drop(#[marshal(cffi::ArcMarshaler::<Bundle>)] bundle: Arc<Bundle>) {
     drop(bundle);
@@ -118,6 +145,9 @@ pub fn DRT_PipelineHandle_forward(
                     PipelineValue::Audio(audio) => {
                         return Ok(audio.to_wav_bytes().map_err(crate::bundle::Error::Io)?);
                     }
+                    other @ PipelineValue::Multiple(_) => {
+                        return Ok(other.to_string().into_bytes());
+                    }
                 }
             }
 
@@ -158,6 +188,9 @@ pub fn DRT_Bundle_runPipeline(
                     PipelineValue::Audio(audio) => {
                         return Ok(audio.to_wav_bytes().map_err(crate::bundle::Error::Io)?);
                     }
+                    other @ PipelineValue::Multiple(_) => {
+                        return Ok(other.to_string().into_bytes());
+                    }
                 }
             }
 
@@ -176,9 +209,168 @@ pub fn DRT_Bundle_errorPreferences(
 ) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
     let locales: Vec<String> = serde_json::from_str(locales_json)?;
     let locale_refs: Vec<&str> = locales.iter().map(|s| s.as_str()).collect();
-    let Some((_, suggest)) = bundle.command::<crate::modules::divvun::Suggest>(None) else {
+    let Some(prefs) = bundle.error_preferences(&locale_refs) else {
         return Err("Suggest command not found in bundle".into());
     };
-    let prefs = suggest.error_preferences(&locale_refs);
     Ok(serde_json::to_vec(&prefs)?)
 }
+
+/// [`Bundle::capabilities`] as JSON, for hosts to check what the bundle and
+/// this runtime support before relying on it.
+#[marshal(return_marshaler = U8VecMarshaler)]
+pub fn DRT_Bundle_capabilities(
+    #[marshal(BundleArcRefMarshaler)] bundle: Arc<Bundle>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let capabilities = RT.with(|rt| rt.block_on(async move { bundle.capabilities().await }))?;
+    Ok(serde_json::to_vec(&capabilities)?)
+}
+
+// UTF-16 variants, for hosts whose strings are UTF-16 (Windows, Office). Text
+// goes in and out as UTF-16 code units, and `divvun::suggest` always reports
+// error offsets in UTF-16 code units, whatever the config says.
+
+/// A pipeline created by [`DRT_Bundle_create_utf16`], so it can only be run
+/// with suggest set to UTF-16 offsets.
+pub struct Utf16PipelineHandle(PipelineHandle);
+
+/// `config` with `encoding: "utf-16"` set for every `divvun::suggest` command
+/// of `defn`.
+fn utf16_config(
+    defn: &PipelineDefinition,
+    config: Option<&str>,
+) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+    let mut config = match serde_json::from_str(config.unwrap_or("{}"))? {
+        serde_json::Value::Null => serde_json::Map::new(),
+        serde_json::Value::Object(config) => config,
+        _ => return Err("config must be a JSON object".into()),
+    };
+    for (key, command) in defn.commands.iter() {
+        if command.module != "divvun" || command.command != "suggest" {
+            continue;
+        }
+        let current = config.remove(&**key).unwrap_or_default();
+        let merged =
+            crate::ast::merge_config(&current, &serde_json::json!({ "encoding": "utf-16" }));
+        config.insert(key.to_string(), merged);
+    }
+    Ok(serde_json::Value::Object(config))
+}
+
+/// Run `input` through `pipe` and return its first output as UTF-16.
+async fn forward_utf16(
+    pipe: &mut PipelineHandle,
+    input: &[u16],
+) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    let input = String::from_utf16(input).map_err(|_| "input is not valid UTF-16")?;
+    let mut stream = pipe.forward(PipelineValue::String(input)).await;
+    let text = match stream.next().await {
+        Some(Ok(PipelineValue::String(s))) => s,
+        Some(Ok(PipelineValue::Json(v))) => serde_json::to_string(&v)?,
+        Some(Ok(_)) => return Err("pipeline output is not text".into()),
+        Some(Err(e)) => return Err(e.into()),
+        None => return Err("Pipeline produced no output".into()),
+    };
+    Ok(text.encode_utf16().collect())
+}
+
+#[marshal(return_marshaler = Utf16HandleBoxMarshaler)]
+pub fn DRT_Bundle_create_utf16(
+    #[marshal(BundleArcRefMarshaler)] bundle: Arc<Bundle>,
+    #[marshal(cffi::StrMarshaler)] config: Option<&str>,
+) -> Result<Box<Utf16PipelineHandle>, Box<dyn std::error::Error>> {
+    let config = utf16_config(bundle.definition(), config)?;
+
+    Ok(RT
+        .with(|rt| rt.block_on(async move { bundle.create(config).await }))
+        .map(|x| Box::new(Utf16PipelineHandle(x)))?)
+}
+
+#[marshal]
+pub fn DRT_PipelineHandle_drop_utf16(
+    #[marshal(Utf16HandleBoxMarshaler)] handle: Box<Utf16PipelineHandle>,
+) {
+    drop(handle);
+}
+
+#[marshal]
+pub fn DRT_PipelineHandle_cancel_utf16(
+    #[marshal(Utf16HandleBoxMutRefMarshaler)] pipe: &mut Utf16PipelineHandle,
+) {
+    RT.with(|rt| rt.block_on(pipe.0.cancel()));
+}
+
+#[marshal(return_marshaler = U16VecMarshaler)]
+pub fn DRT_PipelineHandle_forward_utf16(
+    #[marshal(Utf16HandleBoxMutRefMarshaler)] pipe: &mut Utf16PipelineHandle,
+    #[marshal(U16SliceMarshaler)] input: &[u16],
+) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    RT.with(|rt| rt.block_on(forward_utf16(&mut pipe.0, input)))
+}
+
+#[marshal(return_marshaler = U16VecMarshaler)]
+pub fn DRT_Bundle_runPipeline_utf16(
+    #[marshal(BundleArcRefMarshaler)] bundle: Arc<Bundle>,
+    #[marshal(U16SliceMarshaler)] input: &[u16],
+    #[marshal(cffi::StrMarshaler)] config: Option<&str>,
+) -> Result<Vec<u16>, Box<dyn std::error::Error>> {
+    let config = utf16_config(bundle.definition(), config)?;
+
+    RT.with(|rt| {
+        rt.block_on(async move {
+            let mut pipe = bundle.create(config).await?;
+            forward_utf16(&mut pipe, input).await
+        })
+    })
+}
+
+/// Free a buffer returned by one of the `_utf16` functions.
+#[marshal]
+pub fn DRT_Vec_drop_utf16(#[marshal(U16VecMarshaler)] vec: Vec<u16>) {
+    drop(vec);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn utf16_config_sets_encoding_for_every_suggest_command() {
+        let defn: PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "string" },
+            "output": { "ref": "b" },
+            "commands": {
+                "a": {
+                    "module": "divvun",
+                    "command": "suggest",
+                    "input": { "ref": "#/entry" },
+                    "returns": "json"
+                },
+                "b": {
+                    "module": "example",
+                    "command": "upper",
+                    "input": { "ref": "a" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+
+        let config = utf16_config(
+            &defn,
+            Some(r#"{ "a": { "encoding": "utf-8", "locales": ["se"] }, "x": 1 }"#),
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            serde_json::json!({
+                "a": { "encoding": "utf-16", "locales": ["se"] },
+                "x": 1,
+            })
+        );
+        assert_eq!(
+            utf16_config(&defn, None).unwrap(),
+            serde_json::json!({ "a": { "encoding": "utf-16" } })
+        );
+        assert!(utf16_config(&defn, Some("[]")).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+//! Hunspell's pipe protocol (`hunspell -a`, inherited from ispell) on top of
+//! a spell pipeline, for applications that can only talk to a spell checker
+//! through a hunspell process.
+//!
+//! A [`HunspellPipe`] answers each line of text with one line per word: `*`
+//! for a correct word, `& word count offset: suggestion, ...` for a misspelt
+//! one, or `# word offset` when there are no suggestions, then an empty line.
+//! Offsets count characters from the start of the line as received. Lines
+//! starting with `*`, `&` or `@` accept a word for the rest of the session,
+//! `!` and `%` turn terse mode (no `*` lines) on and off, and `^` escapes a
+//! line of text that would otherwise be read as one of these.
+//!
+//! The pipeline has to end in `spell::suggest`, or anything with the same
+//! output: a JSON array of `{ "index", "word", "suggestions", "correct" }`,
+//! `index` being the word's byte offset in the line.
+
+use std::collections::HashSet;
+
+use futures_util::StreamExt;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    ast::PipelineHandle,
+    bundle::{Bundle, Error},
+    modules::{self, PipelineValue},
+};
+
+/// A word of a checked line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckedWord {
+    pub word: String,
+    /// Byte offset in the line.
+    pub index: usize,
+    pub correct: bool,
+    pub suggestions: Vec<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+struct Token {
+    index: usize,
+    word: String,
+    #[serde(default)]
+    suggestions: Vec<Suggestion>,
+    correct: Option<bool>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(untagged)]
+enum Suggestion {
+    Text(String),
+    Weighted { value: String },
+}
+
+impl Suggestion {
+    fn into_value(self) -> String {
+        match self {
+            Suggestion::Text(x) | Suggestion::Weighted { value: x } => x,
+        }
+    }
+}
+
+/// The words of a spell pipeline's output, leaving out spaces and
+/// punctuation. Without a `correct` field, a word is correct when the
+/// speller suggests it as it is.
+pub fn checked_words(output: serde_json::Value) -> Result<Vec<CheckedWord>, serde_json::Error> {
+    let tokens: Vec<Token> = serde_json::from_value(output)?;
+    Ok(tokens
+        .into_iter()
+        .filter(|x| x.word.chars().any(char::is_alphanumeric))
+        .map(|x| {
+            let suggestions = x
+                .suggestions
+                .into_iter()
+                .map(Suggestion::into_value)
+                .collect::<Vec<_>>();
+            CheckedWord {
+                correct: x.correct.unwrap_or_else(|| suggestions.contains(&x.word)),
+                suggestions: suggestions.into_iter().filter(|s| *s != x.word).collect(),
+                word: x.word,
+                index: x.index,
+            }
+        })
+        .collect())
+}
+
+/// A hunspell pipe session. See the [module docs](self).
+pub struct HunspellPipe {
+    handle: PipelineHandle,
+    terse: bool,
+    /// Words accepted as they are, and lowercased words accepted in any case.
+    accepted: HashSet<String>,
+    accepted_any_case: HashSet<String>,
+}
+
+impl HunspellPipe {
+    /// Start a session on `bundle`'s pipeline, run with `config` like
+    /// [`Bundle::create`].
+    pub async fn new(bundle: &Bundle, config: serde_json::Value) -> Result<HunspellPipe, Error> {
+        Ok(HunspellPipe {
+            handle: bundle.create(config).await?,
+            terse: false,
+            accepted: HashSet::new(),
+            accepted_any_case: HashSet::new(),
+        })
+    }
+
+    /// The line a session starts with. Clients check that it starts with
+    /// `@(#)`.
+    pub fn banner() -> String {
+        format!(
+            "@(#) International Ispell Version 3.2.06 (but really Divvun Runtime {})",
+            env!("CARGO_PKG_VERSION")
+        )
+    }
+
+    /// The answer to one line of input, without its newline. It's empty for
+    /// lines that only change the session, and otherwise ends with the empty
+    /// line that ends an answer.
+    pub async fn respond(&mut self, line: &str) -> Result<String, Error> {
+        let (text, shift) = match line.chars().next() {
+            Some('*' | '@') => {
+                self.accepted.insert(line[1..].trim().to_string());
+                return Ok(String::new());
+            }
+            Some('&') => {
+                self.accepted_any_case
+                    .insert(line[1..].trim().to_lowercase());
+                return Ok(String::new());
+            }
+            Some('!') => {
+                self.terse = true;
+                return Ok(String::new());
+            }
+            Some('%') => {
+                self.terse = false;
+                return Ok(String::new());
+            }
+            // Saving the dictionary, TeX mode and SGML mode
+            Some('#' | '+' | '-' | '~') => return Ok(String::new()),
+            Some('^') => (&line[1..], 1),
+            _ => (line, 0),
+        };
+
+        let mut answer = String::new();
+        for word in self.check(text).await? {
+            if word.correct || self.is_accepted(&word.word) {
+                if !self.terse {
+                    answer.push_str("*\n");
+                }
+                continue;
+            }
+            let Some(before) = text.get(..word.index) else {
+                return Err(not_spell(format!(
+                    "word {:?} at byte {} is not in the line",
+                    word.word, word.index
+                )));
+            };
+            let offset = before.chars().count() + shift;
+            if word.suggestions.is_empty() {
+                answer.push_str(&format!("# {} {}\n", word.word, offset));
+            } else {
+                answer.push_str(&format!(
+                    "& {} {} {}: {}\n",
+                    word.word,
+                    word.suggestions.len(),
+                    offset,
+                    word.suggestions.join(", ")
+                ));
+            }
+        }
+        answer.push('\n');
+        Ok(answer)
+    }
+
+    /// Answer every line of `input` on `output`, after the banner, until
+    /// `input` ends.
+    pub async fn serve<R, W>(mut self, input: R, mut output: W) -> Result<(), Error>
+    where
+        R: AsyncBufRead + Unpin,
+        W: AsyncWrite + Unpin,
+    {
+        output
+            .write_all(format!("{}\n", Self::banner()).as_bytes())
+            .await?;
+        output.flush().await?;
+
+        let mut lines = input.lines();
+        while let Some(line) = lines.next_line().await? {
+            let answer = self.respond(line.trim_end_matches('\r')).await?;
+            output.write_all(answer.as_bytes()).await?;
+            output.flush().await?;
+        }
+        Ok(())
+    }
+
+    fn is_accepted(&self, word: &str) -> bool {
+        self.accepted.contains(word) || self.accepted_any_case.contains(&word.to_lowercase())
+    }
+
+    async fn check(&mut self, text: &str) -> Result<Vec<CheckedWord>, Error> {
+        if text.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut words = Vec::new();
+        let mut stream = self
+            .handle
+            .forward(PipelineValue::String(text.to_string()))
+            .await;
+        while let Some(value) = stream.next().await {
+            let json = match value? {
+                PipelineValue::Json(json) => json,
+                PipelineValue::String(text) => serde_json::from_str(&text).map_err(not_spell)?,
+                _ => return Err(not_spell("not JSON")),
+            };
+            words.extend(checked_words(json).map_err(not_spell)?);
+        }
+        Ok(words)
+    }
+}
+
+fn not_spell(e: impl std::fmt::Display) -> Error {
+    Error::Command(modules::Error::msg(format!(
+        "hunspell pipeline output is not spell::suggest JSON: {}",
+        e
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_read_from_spell_output() {
+        let words = checked_words(serde_json::json!([
+            { "index": 0, "word": "Mun", "suggestions": [{ "value": "Mun", "weight": 0.0 }] },
+            { "index": 3, "word": " ", "suggestions": [] },
+            { "index": 4, "word": "boahtan", "suggestions": [
+                { "value": "boahtán", "weight": 3.5 },
+                { "value": "boahtin", "weight": 5.0 }
+            ] },
+            { "index": 11, "word": "!", "suggestions": [] },
+            { "index": 13, "word": "dáppe", "suggestions": [], "correct": true },
+            { "index": 20, "word": "xyz", "suggestions": ["xyz"], "correct": false }
+        ]))
+        .unwrap();
+
+        assert_eq!(
+            words,
+            [
+                CheckedWord {
+                    word: "Mun".into(),
+                    index: 0,
+                    correct: true,
+                    suggestions: vec![],
+                },
+                CheckedWord {
+                    word: "boahtan".into(),
+                    index: 4,
+                    correct: false,
+                    suggestions: vec!["boahtán".into(), "boahtin".into()],
+                },
+                CheckedWord {
+                    word: "dáppe".into(),
+                    index: 13,
+                    correct: true,
+                    suggestions: vec![],
+                },
+                CheckedWord {
+                    word: "xyz".into(),
+                    index: 20,
+                    correct: false,
+                    suggestions: vec![],
+                },
+            ]
+        );
+    }
+}
@@ -1,4 +1,4 @@
-export type ValueType = "string" | "path";
+export type ValueType = "string" | "path" | "bytes";
 
 export const _current = new Map<string, Command>();
 
@@ -23,6 +23,12 @@ export class PathEntry extends Entry {
   }
 }
 
+export class BytesEntry extends Entry {
+  constructor() {
+    super("bytes");
+  }
+}
+
 export class Arg {
   type: string;
   value: any;
@@ -45,6 +51,7 @@ export class Command {
   config?: string;
   args?: { [key: string]: Arg };
   kind?: string;
+  defaults?: { [key: string]: any };
 
   constructor(config: {
     id?: string;
@@ -56,6 +63,7 @@ export class Command {
     args?: { [key: string]: Arg };
     schema?: string;
     kind?: string;
+    defaults?: { [key: string]: any };
   }) {
     this.module = config.module;
     this.command = config.command;
@@ -70,6 +78,9 @@ export class Command {
     if (config.kind) {
       this.kind = config.kind;
     }
+    if (config.defaults) {
+      this.defaults = config.defaults;
+    }
 
     // Store reference for pipeline processing - use provided ID or generate random one
     let id = config.id || Math.random().toString(16).substring(2);
@@ -0,0 +1,238 @@
+//! The CG-3 stream parser and grammar runner of `cg3::vislcg3` and the
+//! `divvun` commands.
+//!
+//! ```
+//! use divvun_runtime::interop::cg3::{Block, Output};
+//!
+//! let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n\"<borran>\"\n\t\"borrat\" V Ind Prs Sg1\n\t\"borran\" N Sg Nom\n";
+//! let mut readings = Vec::new();
+//! for block in Output::new(stream).iter() {
+//!     if let Block::Cohort(cohort) = block? {
+//!         readings.push((cohort.word_form(), cohort.readings().len()));
+//!     }
+//! }
+//! assert_eq!(readings, [("Mun", 1), ("borran", 2)]);
+//! # Ok::<(), divvun_runtime::interop::cg3::ParseError>(())
+//! ```
+
+use std::{borrow::Cow, fmt, panic::AssertUnwindSafe};
+
+use crate::modules::{Context, Error, cg3 as inner};
+
+/// A textual or binary grammar, such as a disambiguator.
+///
+/// [`run`](Self::run) blocks while the grammar runs. Call it from a thread
+/// of the command's own, as `cg3::vislcg3` does, or in
+/// `tokio::task::spawn_blocking`.
+///
+/// ```
+/// # use std::path::Path;
+/// # use divvun_runtime::{interop::cg3::Grammar, modules::Context};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy"));
+/// let context = Context::standalone(path).await?;
+/// let grammar = Grammar::load(&context, "toy.cg3").await?;
+///
+/// let stream = "\"<borran>\"\n\t\"borrat\" V Ind Prs Sg1\n\t\"borran\" N Sg Nom\n";
+/// let output = grammar.run(stream).unwrap_or_default();
+/// assert!(!output.contains("\"borrat\""));
+/// # Ok(())
+/// # }
+/// ```
+pub struct Grammar(inner::Applicator);
+
+impl Grammar {
+    /// Load the grammar at `path` in the assets of `context`.
+    pub async fn load(context: &Context, path: &str) -> Result<Grammar, Error> {
+        let mapped = context.memory_map_file(path).await?;
+        let bytes = mapped
+            .as_slice()
+            .map_err(|e| Error::msg(format!("failed to map grammar {path}: {e}")))?;
+        // The engine panics on grammars it can't parse
+        std::panic::catch_unwind(AssertUnwindSafe(|| {
+            inner::Applicator::from_bytes(&bytes, path)
+        }))
+        .map(Grammar)
+        .map_err(|_| Error::msg(format!("grammar {path} could not be parsed")).at_file(path))
+    }
+
+    /// Run the grammar on a CG stream, returning the stream it leaves, or
+    /// `None` if the engine failed.
+    pub fn run(&self, input: &str) -> Option<String> {
+        self.0.run(input)
+    }
+
+    /// Have [`run`](Self::run) mark each reading with the rules that
+    /// touched it.
+    pub fn set_trace(&self, trace: bool) {
+        self.0.set_trace(trace)
+    }
+}
+
+/// The multi-word expression splitter of `cg3::mwesplit`.
+#[derive(Default)]
+pub struct MweSplit(inner::MweSplit);
+
+impl MweSplit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Split the multi-word cohorts of a CG stream, or `None` if the engine
+    /// failed.
+    pub fn run(&self, input: &str) -> Option<String> {
+        self.0.run(input)
+    }
+}
+
+/// A CG stream to read.
+#[derive(Debug, Clone)]
+pub struct Output<'a>(inner::Output<'a>);
+
+impl<'a> Output<'a> {
+    pub fn new<S: Into<Cow<'a, str>>>(buf: S) -> Self {
+        Output(inner::Output::new(buf))
+    }
+
+    /// The stream's cohorts and the lines between them, ending at the first
+    /// line that doesn't parse.
+    pub fn iter(&'a self) -> impl Iterator<Item = Result<Block<'a>, ParseError>> {
+        self.0
+            .iter()
+            .map(|x| x.map(Block::from).map_err(ParseError))
+    }
+
+    /// The text of each sentence, from the base form of each cohort's first
+    /// reading.
+    pub fn sentences(&'a self) -> impl Iterator<Item = Result<String, ParseError>> {
+        self.0.sentences().map(|x| x.map_err(ParseError))
+    }
+
+    /// Every line that doesn't parse, numbered from 1. Unlike
+    /// [`iter`](Self::iter), this carries on past the first problem.
+    pub fn errors(&'a self) -> Vec<(usize, ParseError)> {
+        self.0
+            .errors()
+            .into_iter()
+            .map(|(line, e)| (line, ParseError(e)))
+            .collect()
+    }
+}
+
+impl fmt::Display for Output<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// A part of a CG stream.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum Block<'a> {
+    Cohort(Cohort<'a>),
+    /// A `:` line, without the `:`: text between cohorts, such as spaces.
+    Escaped(&'a str),
+    /// Any other line.
+    Text(&'a str),
+}
+
+impl<'a> From<inner::Block<'a>> for Block<'a> {
+    fn from(block: inner::Block<'a>) -> Self {
+        match block {
+            inner::Block::Cohort(cohort) => Block::Cohort(Cohort {
+                word_form: cohort.word_form,
+                readings: cohort.readings.into_iter().map(Reading).collect(),
+            }),
+            inner::Block::Escaped(text) => Block::Escaped(text),
+            inner::Block::Text(text) => Block::Text(text),
+        }
+    }
+}
+
+impl fmt::Display for Block<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Block::Cohort(cohort) => write!(f, "{}", cohort),
+            Block::Escaped(text) => writeln!(f, ":{}", text),
+            Block::Text(text) => writeln!(f, "{}", text),
+        }
+    }
+}
+
+/// A word form and its readings.
+#[derive(Debug, Clone)]
+pub struct Cohort<'a> {
+    word_form: &'a str,
+    readings: Vec<Reading<'a>>,
+}
+
+impl<'a> Cohort<'a> {
+    /// The word form, without the `"<` and `>"`.
+    pub fn word_form(&self) -> &'a str {
+        self.word_form
+    }
+
+    pub fn readings(&self) -> &[Reading<'a>] {
+        &self.readings
+    }
+}
+
+impl fmt::Display for Cohort<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\"<{}>\"", self.word_form)?;
+        for reading in &self.readings {
+            writeln!(f, "{}", reading)?;
+        }
+        Ok(())
+    }
+}
+
+/// A base form and its tags.
+#[derive(Clone)]
+pub struct Reading<'a>(inner::Reading<'a>);
+
+impl<'a> Reading<'a> {
+    /// The base form, without quotes.
+    pub fn base_form(&self) -> &'a str {
+        self.0.base_form
+    }
+
+    pub fn tags(&self) -> &[&'a str] {
+        &self.0.tags
+    }
+
+    /// 1 for a reading, more for the subreadings of a multi-word expression.
+    pub fn depth(&self) -> usize {
+        self.0.depth
+    }
+
+    /// The line as it was in the stream, without the leading tabs.
+    pub fn raw_line(&self) -> &'a str {
+        self.0.raw_line
+    }
+}
+
+impl fmt::Debug for Reading<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.0, f)
+    }
+}
+
+impl fmt::Display for Reading<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+/// A line of a CG stream that doesn't parse.
+#[derive(Debug)]
+pub struct ParseError(inner::ParseError);
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for ParseError {}
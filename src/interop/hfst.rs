@@ -0,0 +1,57 @@
+//! Lookup in HFST optimized-lookup transducers (`.hfstol`), with the limits
+//! and result cache the `divvun` and `speech` commands have.
+
+use std::path::Path;
+
+use crate::modules::{
+    Context, Error,
+    hfst::{Lookup, load_lookup, lookup_tags},
+};
+
+pub use crate::modules::hfst::LookupConfig;
+
+/// An analyser, generator or normaliser. Lookups take `&self` and may run
+/// from several tasks at once; they wait for each other on the transducer.
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use divvun_runtime::{interop::hfst::{LookupConfig, Transducer}, modules::Context};
+/// # async fn example() -> Result<(), Box<dyn std::error::Error>> {
+/// let context = Context::standalone(Path::new("se.drb")).await?;
+/// let config = LookupConfig {
+///     max_results: Some(10),
+///     ..Default::default()
+/// };
+/// let generator = Transducer::load(&context, "generator-gt-norm.hfstol", &config).await?;
+/// for form in generator.lookup("girji+N+Sg+Gen") {
+///     println!("{}", form);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct Transducer(Lookup);
+
+impl Transducer {
+    /// Load the transducer at `path` in the assets of `context`. Fails if it
+    /// isn't an optimized-lookup transducer.
+    pub async fn load(
+        context: &Context,
+        path: impl AsRef<Path>,
+        config: &LookupConfig,
+    ) -> Result<Transducer, Error> {
+        load_lookup(context, path, config).await.map(Transducer)
+    }
+
+    /// The output of each path `input` leads to, without flag diacritics:
+    /// analyses such as `girji+N+Sg+Nom` for an analyser, word forms for a
+    /// generator.
+    pub fn lookup(&self, input: &str) -> Vec<String> {
+        lookup_tags(&self.0, input, false)
+    }
+
+    /// Only the flag diacritics of each path, in the order of
+    /// [`lookup`](Self::lookup).
+    pub fn flag_diacritics(&self, input: &str) -> Vec<String> {
+        lookup_tags(&self.0, input, true)
+    }
+}
@@ -0,0 +1,14 @@
+//! Building blocks of the built-in commands for modules written outside this
+//! crate: parsing and running CG-3 in [`cg3`], transducer lookup in [`hfst`].
+//!
+//! Everything here follows semver: it only changes incompatibly with a new
+//! major version of the runtime. The modules it comes from
+//! (`divvun_runtime::modules::cg3` and `::hfst`) are the commands'
+//! implementation and may change in any release, so use these paths instead.
+//! Items are added here once a built-in command has used them for a release
+//! or two.
+
+#[cfg(feature = "mod-cg3")]
+pub mod cg3;
+#[cfg(feature = "mod-hfst")]
+pub mod hfst;
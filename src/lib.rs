@@ -1,13 +1,38 @@
+// Library code reports failures as errors instead of unwrapping; tests may
+// unwrap (see clippy.toml). Modules still to be converted are allowed below.
+#![deny(clippy::unwrap_used)]
+
+#[allow(clippy::unwrap_used)]
 pub mod ast;
+#[allow(clippy::unwrap_used)]
 pub mod bundle;
+pub mod bundle_set;
+pub mod compat;
+pub mod daemon;
+pub mod fanout;
+pub mod hunspell;
+pub mod interop;
 pub mod modules;
+pub mod pipe_pool;
+pub mod preflight;
+pub mod presets;
+#[cfg(feature = "mod-divvun")]
+pub mod session;
+#[allow(clippy::unwrap_used)]
 pub mod ts;
 pub mod util;
+pub mod watch;
 
 #[cfg(feature = "ffi")]
 pub mod ffi;
 
-#[derive(Debug)]
+pub use util::shutdown::{Shutdown, shutdown};
+
+/// Version of this runtime, e.g. for recording which one produced some
+/// output.
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+#[derive(Debug, serde::Serialize)]
 #[allow(dead_code)] // used in cli
 pub struct VersionInfo {
     build_date: &'static str,
@@ -68,6 +93,81 @@ pub fn print_version(verbose: bool) {
     println!("{:#?}", VERSION_INFO);
 }
 
+/// Build information and the compiled-in modules and commands, for bug
+/// reports and about dialogs. `feature` is the cargo feature a module needs,
+/// or null for modules that are always built.
+pub fn version_json() -> serde_json::Value {
+    let features = cargo_features();
+
+    let modules = modules::get_modules()
+        .iter()
+        .map(|module| {
+            let mut commands = module.commands.iter().collect::<Vec<_>>();
+            commands.sort_by_key(|def| def.name);
+            let commands = commands
+                .into_iter()
+                .map(|def| {
+                    let input = def.input.iter().map(|x| x.as_dr_type()).collect::<Vec<_>>();
+                    let args = def
+                        .args
+                        .iter()
+                        .map(|arg| {
+                            serde_json::json!({
+                                "name": arg.name,
+                                "type": arg.ty.as_dr_type(),
+                                "optional": arg.optional,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    serde_json::json!({
+                        "name": def.name,
+                        "input": input,
+                        "returns": def.returns.as_dr_type(),
+                        "kind": def.kind,
+                        "args": args,
+                    })
+                })
+                .collect::<Vec<_>>();
+            serde_json::json!({
+                "name": module.name,
+                "feature": module_feature(module.name),
+                "commands": commands,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION"),
+        "schema_version": compat::SCHEMA_VERSION,
+        "build": VERSION_INFO,
+        "features": features,
+        "modules": modules,
+    })
+}
+
+/// Cargo features this runtime was built with.
+pub(crate) fn cargo_features() -> Vec<&'static str> {
+    VERSION_INFO
+        .cargo_features
+        .split(',')
+        .map(str::trim)
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+pub(crate) fn module_feature(module: &str) -> Option<&'static str> {
+    Some(match module {
+        "cg3" => "mod-cg3",
+        "divvun" => "mod-divvun",
+        "hfst" => "mod-hfst",
+        "jq" => "mod-jq",
+        "speech" => "mod-speech",
+        "ssml" => "mod-ssml",
+        _ => return None,
+    })
+}
+
 pub fn print_modules() {
     for module in modules::get_modules().iter() {
         println!("{}", module);
@@ -1,4 +1,9 @@
-use std::{collections::HashMap, str::FromStr, sync::Arc, thread::JoinHandle};
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, PoisonError},
+    thread::JoinHandle,
+};
 
 use async_trait::async_trait;
 use divvun_runtime_macros::{rt_command, rt_struct};
@@ -10,7 +15,7 @@ use tokio::sync::{
     mpsc::{self, Receiver, Sender},
 };
 
-use crate::ast;
+use crate::{ast, util::privacy::redact};
 
 use super::{CommandRunner, Context, Error, PipelineValue, PipelineValues};
 
@@ -44,7 +49,7 @@ impl Applicator {
         Self::from_bytes(&buffer, &path.display().to_string())
     }
 
-    fn from_bytes(buffer: &[u8], source: &str) -> Self {
+    pub(crate) fn from_bytes(buffer: &[u8], source: &str) -> Self {
         use ::cg3::binary_grammar::BinaryGrammar;
         use ::cg3::grammar::Grammar;
         use ::cg3::inlines::is_cg3b;
@@ -85,7 +90,7 @@ impl Applicator {
         use ::cg3::grammar_applicator::{GrammarApplicator, cg3_sformat};
         use ::cg3::options::{OPTIONS, options};
 
-        let mut guard = self.grammar.lock().unwrap();
+        let mut guard = self.grammar.lock().unwrap_or_else(PoisonError::into_inner);
         // Move the grammar into a fresh applicator; `set_grammar`'s tag seeding
         // is idempotent (`add_tag` interns), so reuse across runs is safe.
         let grammar = std::mem::replace(&mut *guard, Grammar::default());
@@ -184,13 +189,14 @@ impl std::fmt::Debug for Reading<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let alt = f.alternate();
 
+        let tags = format!("{:?}", self.tags);
         let mut x = f.debug_struct("Reading");
-        x.field("base_form", &self.base_form)
-            .field("tags", &self.tags)
+        x.field("base_form", &redact(self.base_form))
+            .field("tags", &format_args!("{}", redact(&tags)))
             .field("depth", &self.depth);
 
         if alt {
-            x.field("raw_line", &self.raw_line).finish()
+            x.field("raw_line", &redact(self.raw_line)).finish()
         } else {
             x.finish_non_exhaustive()
         }
@@ -352,12 +358,13 @@ impl<'a> Output<'a> {
                                 return Some(Ok(Block::Cohort(cohort)));
                             }
 
-                            let (Some(start), Some(end)) = (x.find("\"<"), x.find(">\"")) else {
-                                return Some(Err(ParseError::InvalidLine(x.to_string())));
+                            // Skip the line, or callers that drop errors would
+                            // see it again forever
+                            let word_form = match parse_word_form(x) {
+                                Ok(v) => v,
+                                Err(e) => break Some(Err(e)),
                             };
 
-                            let word_form = &x[start + 2..end];
-
                             cohort = Some(Cohort {
                                 word_form,
                                 readings: Vec::new(),
@@ -370,32 +377,10 @@ impl<'a> Output<'a> {
                                 break Some(Err(ParseError::InvalidReading(x.to_string())));
                             };
 
-                            let Some(depth) = x.rfind('\t') else {
-                                break Some(Err(ParseError::InvalidReading(x.to_string())));
-                            };
-
-                            let x = &x[depth + 1..];
-                            let mut chunks = tokenize_tags(x).into_iter();
-
-                            let base_form = match chunks
-                                .next()
-                                .ok_or_else(|| ParseError::InvalidReading(x.to_string()))
-                            {
-                                Ok(v) => v,
+                            match parse_reading(x) {
+                                Ok(reading) => cohort.readings.push(reading),
                                 Err(e) => break Some(Err(e)),
-                            };
-
-                            if !(base_form.starts_with('"') && base_form.ends_with('"')) {
-                                break Some(Err(ParseError::InvalidReading(x.to_string())));
                             }
-                            let base_form = &base_form[1..base_form.len() - 1];
-
-                            cohort.readings.push(Reading {
-                                raw_line: x,
-                                base_form,
-                                tags: chunks.collect(),
-                                depth: depth + 1,
-                            });
 
                             break None;
                         }
@@ -419,6 +404,63 @@ impl<'a> Output<'a> {
             }
         })
     }
+
+    /// Every line that doesn't parse, numbered from 1. Unlike [`Output::iter`],
+    /// this carries on past the first problem, for checking hand-edited
+    /// streams.
+    pub fn errors(&'a self) -> Vec<(usize, ParseError)> {
+        let mut errors = Vec::new();
+        let mut in_cohort = false;
+
+        for (i, line) in self.lines().enumerate() {
+            let result = match line {
+                Line::WordForm(x) => {
+                    in_cohort = true;
+                    parse_word_form(x).map(|_| ())
+                }
+                Line::Reading(x) if !in_cohort => Err(ParseError::InvalidReading(x.to_string())),
+                Line::Reading(x) => parse_reading(x).map(|_| ()),
+                Line::Text(_) => Ok(()),
+            };
+            if let Err(e) = result {
+                errors.push((i + 1, e));
+            }
+        }
+
+        errors
+    }
+}
+
+fn parse_word_form(line: &str) -> Result<&str, ParseError> {
+    match (line.find("\"<"), line.find(">\"")) {
+        (Some(start), Some(end)) if start + 2 <= end => Ok(&line[start + 2..end]),
+        _ => Err(ParseError::InvalidLine(line.to_string())),
+    }
+}
+
+fn parse_reading(line: &str) -> Result<Reading<'_>, ParseError> {
+    let Some(depth) = line.rfind('\t') else {
+        return Err(ParseError::InvalidReading(line.to_string()));
+    };
+
+    let x = &line[depth + 1..];
+    let mut chunks = tokenize_tags(x).into_iter();
+
+    let base_form = chunks
+        .next()
+        .ok_or_else(|| ParseError::InvalidReading(x.to_string()))?;
+
+    if !(base_form.len() >= 2 && base_form.starts_with('"') && base_form.ends_with('"')) {
+        return Err(ParseError::InvalidReading(x.to_string()));
+    }
+    let base_form = &base_form[1..base_form.len() - 1];
+
+    Ok(Reading {
+        raw_line: x,
+        base_form,
+        tags: chunks.collect(),
+        depth: depth + 1,
+    })
 }
 
 impl std::fmt::Display for Block<'_> {
@@ -630,7 +672,9 @@ impl Mwesplit {
                     break;
                 };
 
-                output_tx.blocking_send(mwesplit.run(&input)).unwrap();
+                if output_tx.blocking_send(mwesplit.run(&input)).is_err() {
+                    break;
+                }
             }
         });
 
@@ -938,11 +982,16 @@ impl CommandRunner for Mwesplit {
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
 
+        // An empty stream has no cohorts to work on
+        if input.trim().is_empty() {
+            return Ok(input.into());
+        }
+
+        let mut output_rx = self.output_rx.lock().await;
         self.input_tx
             .send(Some(input))
             .await
             .expect("input tx send");
-        let mut output_rx = self.output_rx.lock().await;
         let output = output_rx.recv().await.expect("output rx recv");
 
         Ok(output.unwrap_or_else(|| "".to_string()).into())
@@ -981,7 +1030,8 @@ struct Vislcg3Config {
     args = [
         model_path = "Path",
         config? = "Vislcg3Config",
-    ]
+    ],
+    resources = [memory = "64M"]
 )]
 impl Vislcg3 {
     pub async fn new(
@@ -1032,7 +1082,9 @@ impl Vislcg3 {
                     break;
                 };
 
-                output_tx.blocking_send(applicator.run(&input)).unwrap();
+                if output_tx.blocking_send(applicator.run(&input)).is_err() {
+                    break;
+                }
             }
         });
 
@@ -1054,11 +1106,18 @@ impl CommandRunner for Vislcg3 {
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
 
+        // An empty stream has no cohorts to work on
+        if input.trim().is_empty() {
+            return Ok(input.into());
+        }
+
+        // Locked before sending, so callers sharing the runner each get the
+        // output of their own input.
+        let mut output_rx = self.output_rx.lock().await;
         self.input_tx
             .send(Some(input))
             .await
             .expect("input tx send");
-        let mut output_rx = self.output_rx.lock().await;
         let output = output_rx.recv().await.expect("output rx recv");
 
         Ok(output.unwrap_or_else(|| "".to_string()).into())
@@ -1113,6 +1172,7 @@ impl CommandRunner for ToJson {
     }
 }
 
+#[allow(clippy::unwrap_used)] // a constant pattern
 pub static CG_LINE: Lazy<Regex> = Lazy::<Regex>::new(|| {
     Regex::new(
         "^
@@ -1320,3 +1380,28 @@ mod sentences_tests {
         );
     }
 }
+
+#[cfg(test)]
+mod stream_tests {
+    use super::*;
+
+    #[test]
+    fn reports_every_bad_line() {
+        let stream = "\t\"orpan\" N\n\"<Mun>\"\n\t\"mun\" Pron\n\tPron Sg1\n:\\n\n\"<boahtán\n\t\"boahtit\" V\n";
+        let output = Output::new(stream);
+        let lines = output
+            .errors()
+            .into_iter()
+            .map(|(line, _)| line)
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [1, 4, 6]);
+        let valid = Output::new("\"<Mun>\"\n\t\"mun\" Pron\n");
+        assert!(valid.errors().is_empty());
+
+        // Iterating skips a bad line rather than returning it forever
+        let output = Output::new("\"<boahtán\n\"<Mun>\"\n");
+        let blocks = output.iter().collect::<Vec<_>>();
+        assert_eq!(blocks.len(), 2);
+        assert!(matches!(&blocks[1], Ok(Block::Cohort(x)) if x.word_form == "Mun"));
+    }
+}
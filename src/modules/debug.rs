@@ -1,14 +1,18 @@
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Arc,
+    time::Duration,
+};
 
 use async_trait::async_trait;
 use divvun_runtime_macros::rt_command;
 use tokio::task::JoinHandle;
 
-use crate::ast;
+use crate::{ast, util::channel::recv_error};
 
 use super::{
-    CommandRunner, Context, Error, PipelineEvent, PipelineValue, PipelineValueRx, PipelineValueTx,
-    PipelineValues, Tap,
+    CommandRunner, Context, Error, LiveConfig, PipelineEvent, PipelineValue, PipelineValueRx,
+    PipelineValueTx, PipelineValues, Tap,
 };
 
 /// Streaming test command: trickles `count` values out one at a time, with
@@ -72,7 +76,7 @@ impl CommandRunner for Trickle {
         mut input_rx: PipelineValueRx,
         output: PipelineValueTx,
         _tap: Option<Tap>,
-        _config: Arc<serde_json::Value>,
+        _config: LiveConfig,
     ) -> JoinHandle<Result<(), Error>> {
         let name = self.name().to_string();
         let count = self.count;
@@ -80,20 +84,31 @@ impl CommandRunner for Trickle {
 
         tokio::spawn(async move {
             tracing::debug!("{name}: forward_stream task started");
+            // Input received mid-emission: the input's Finish and any
+            // documents after it wait until this one's output is out.
+            let mut queued = VecDeque::new();
             loop {
-                let event = input_rx.recv().await.map_err(Error::wrap)?;
+                let event = match queued.pop_front() {
+                    Some(event) => event,
+                    None => input_rx
+                        .recv()
+                        .await
+                        .map_err(|e| recv_error(&name, &output, e))?,
+                };
                 match event {
                     PipelineEvent::Value(value) => {
                         let s = value.try_into_string()?;
-                        let mut cancelled = false;
-                        for i in 0..count {
+                        let mut i = 0;
+                        while i < count {
                             tokio::select! {
                                 biased;
-                                ev = input_rx.recv() => match ev.map_err(Error::wrap)? {
+                                ev = input_rx.recv() => match ev.map_err(|e| recv_error(&name, &output, e))? {
+                                    // Everything queued was sent before the Cancel, so it
+                                    // is cancelled too.
                                     PipelineEvent::Cancel => {
                                         tracing::debug!("{name}: Cancel mid-emission at i={i}");
+                                        queued.clear();
                                         output.send(PipelineEvent::Cancel).map_err(Error::wrap)?;
-                                        cancelled = true;
                                         break;
                                     }
                                     PipelineEvent::Close => {
@@ -105,20 +120,15 @@ impl CommandRunner for Trickle {
                                         output.send(PipelineEvent::Error(e.clone())).map_err(Error::wrap)?;
                                         return Err(e);
                                     }
-                                    other => {
-                                        // Finish / Value arriving mid-stream is unusual; just pass through.
-                                        output.send(other).map_err(Error::wrap)?;
-                                    }
+                                    other => queued.push_back(other),
                                 },
                                 _ = tokio::time::sleep(delay) => {
                                     let v: PipelineValue = format!("{s}#{i}").into();
                                     output.send(PipelineEvent::Value(v)).map_err(Error::wrap)?;
+                                    i += 1;
                                 }
                             }
                         }
-                        if !cancelled {
-                            output.send(PipelineEvent::Finish).map_err(Error::wrap)?;
-                        }
                     }
                     PipelineEvent::Cancel => {
                         output.send(PipelineEvent::Cancel).map_err(Error::wrap)?;
@@ -150,7 +160,6 @@ impl CommandRunner for Trickle {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use serde_json::json;
     use tokio::sync::broadcast;
 
     #[tokio::test(flavor = "multi_thread")]
@@ -163,7 +172,7 @@ mod tests {
         let (out_tx, mut out_rx) = broadcast::channel(64);
         let handle = trickle
             .clone()
-            .forward_stream(in_rx, out_tx, None, Arc::new(json!({})));
+            .forward_stream(in_rx, out_tx, None, LiveConfig::default());
 
         // 1. Send Input "a"; collect a few Value events.
         in_tx
@@ -0,0 +1,398 @@
+//! Sentence alignment of a text and its translation, for checking parallel
+//! corpora. Sentences are paired by their lengths with the Gale-Church
+//! algorithm: a translated sentence tends to be about as long as its source,
+//! so a pairing whose lengths differ a lot is unlikely.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use divvun_runtime_macros::{rt_command, rt_struct};
+use serde::{Deserialize, Serialize};
+
+use crate::{ast, modules::Error, session::split_sentences};
+
+use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
+
+/// Sentences per side in each kind of pairing Gale-Church allows, with its
+/// prior probability.
+const BEADS: [((usize, usize), f64); 6] = [
+    ((1, 1), 0.89),
+    ((1, 0), 0.0099),
+    ((0, 1), 0.0099),
+    ((2, 1), 0.089),
+    ((1, 2), 0.089),
+    ((2, 2), 0.011),
+];
+
+/// Variance of the target length per source length unit, from Gale and
+/// Church's measurements.
+const DEFAULT_VARIANCE: f64 = 6.8;
+
+/// Configuration for `divvun::align`.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AlignConfig {
+    /// What sentence lengths are counted in: "chars" (default) or "words".
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Expected target length per unit of source length; 1 by default.
+    #[serde(default)]
+    pub ratio: Option<f64>,
+    /// Variance of that ratio; 6.8 by default.
+    #[serde(default)]
+    pub variance: Option<f64>,
+}
+
+/// A sentence of one of the aligned texts, by byte offsets.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlignSentence {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+/// Source sentences paired with target sentences, by index. One side is
+/// empty for a sentence without a counterpart.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Alignment {
+    pub source: Vec<usize>,
+    pub target: Vec<usize>,
+    /// Negative log probability of the pairing; high costs are worth a look.
+    pub cost: f64,
+}
+
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlignOutput {
+    pub source: Vec<AlignSentence>,
+    pub target: Vec<AlignSentence>,
+    pub alignments: Vec<Alignment>,
+}
+
+/// Sentence alignment of a source text and its translation
+#[derive(facet::Facet)]
+pub struct Align;
+
+#[rt_command(
+    module = "divvun",
+    name = "align",
+    input = [String, Json],
+    output = "Json",
+    args = [],
+    schema = "AlignOutput",
+    config = "AlignConfig"
+)]
+impl Align {
+    pub async fn new(
+        _context: Arc<Context>,
+        _kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        Ok(Arc::new(Self) as _)
+    }
+}
+
+#[async_trait]
+impl CommandRunner for Align {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let config: AlignConfig = serde_json::from_value((*config).clone()).unwrap_or_default();
+        let words = match config.unit.as_deref() {
+            None | Some("chars") => false,
+            Some("words") => true,
+            Some(other) => {
+                return Err(Error::msg(format!(
+                    "unknown unit '{}', expected \"chars\" or \"words\"",
+                    other
+                ))
+                .at_path("/config/unit"));
+            }
+        };
+        let ratio = config.ratio.unwrap_or(1.0);
+        let variance = config.variance.unwrap_or(DEFAULT_VARIANCE);
+        if ratio <= 0.0 || variance <= 0.0 {
+            return Err(Error::msg("ratio and variance must be positive").at_path("/config"));
+        }
+        let (source, target) = texts(input)?;
+
+        let source = sentences(&source);
+        let target = sentences(&target);
+        let length = |x: &AlignSentence| {
+            if words {
+                x.text.split_whitespace().count()
+            } else {
+                x.text.chars().count()
+            }
+        };
+        let alignments = align(
+            &source.iter().map(length).collect::<Vec<_>>(),
+            &target.iter().map(length).collect::<Vec<_>>(),
+            ratio,
+            variance,
+        );
+
+        let output = AlignOutput {
+            source,
+            target,
+            alignments,
+        };
+        let json = serde_json::to_value(output).map_err(|e| {
+            Error::msg(format!("Failed to serialize divvun::align output: {}", e))
+                .at_path("/output")
+        })?;
+        Ok(PipelineValue::Json(json).into())
+    }
+
+    fn name(&self) -> &'static str {
+        "divvun::align"
+    }
+}
+
+/// The source and target text of `input`: the values of its two inputs, as
+/// in `divvun.align([source, target])`, or JSON `[source, target]` or
+/// `{"source": .., "target": ..}`. Text input is read as JSON, so either can
+/// be given on the command line.
+fn texts(input: PipelineValue) -> Result<(String, String), Error> {
+    let json = match input {
+        PipelineValue::Multiple(_) => {
+            let values = input.try_into_multiple()?;
+            let texts = values
+                .into_iter()
+                .map(|x| match x {
+                    PipelineValue::String(text) => Some(serde_json::Value::String(text)),
+                    PipelineValue::Json(json) if json.is_string() => Some(json),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>();
+            texts.map_or(serde_json::Value::Null, serde_json::Value::Array)
+        }
+        PipelineValue::Json(json) => json,
+        PipelineValue::String(text) => {
+            serde_json::from_str(&text).unwrap_or(serde_json::Value::String(text))
+        }
+        _ => serde_json::Value::Null,
+    };
+    let pair = match &json {
+        serde_json::Value::Array(x) if x.len() == 2 => (x[0].as_str(), x[1].as_str()),
+        serde_json::Value::Object(x) => (
+            x.get("source").and_then(|x| x.as_str()),
+            x.get("target").and_then(|x| x.as_str()),
+        ),
+        _ => (None, None),
+    };
+    match pair {
+        (Some(source), Some(target)) => Ok((source.to_string(), target.to_string())),
+        _ => Err(Error::msg(
+            "divvun::align expects two texts: [source, target] or {\"source\": .., \"target\": ..}",
+        )),
+    }
+}
+
+fn sentences(text: &str) -> Vec<AlignSentence> {
+    split_sentences(text)
+        .into_iter()
+        .map(|x| AlignSentence {
+            text: text[x.clone()].to_string(),
+            start: x.start,
+            end: x.end,
+        })
+        .collect()
+}
+
+/// Pair sentences of the given lengths at the lowest total cost, in order.
+fn align(source: &[usize], target: &[usize], ratio: f64, variance: f64) -> Vec<Alignment> {
+    let (n, m) = (source.len(), target.len());
+    // Lowest cost of aligning the first i source and j target sentences, and
+    // the bead ending that alignment.
+    let mut best = vec![vec![(f64::INFINITY, (0, 0)); m + 1]; n + 1];
+    best[0][0].0 = 0.0;
+
+    for i in 0..=n {
+        for j in 0..=m {
+            for &((di, dj), prior) in BEADS.iter() {
+                if di > i || dj > j {
+                    continue;
+                }
+                let (before, _) = best[i - di][j - dj];
+                if before.is_infinite() {
+                    continue;
+                }
+                let source_len = source[i - di..i].iter().sum::<usize>();
+                let target_len = target[j - dj..j].iter().sum::<usize>();
+                let cost = before + bead_cost(source_len, target_len, prior, ratio, variance);
+                if cost < best[i][j].0 {
+                    best[i][j] = (cost, (di, dj));
+                }
+            }
+        }
+    }
+
+    let mut alignments = Vec::new();
+    let (mut i, mut j) = (n, m);
+    while i > 0 || j > 0 {
+        let (cost, (di, dj)) = best[i][j];
+        let (before, _) = best[i - di][j - dj];
+        alignments.push(Alignment {
+            source: (i - di..i).collect(),
+            target: (j - dj..j).collect(),
+            cost: cost - before,
+        });
+        i -= di;
+        j -= dj;
+    }
+    alignments.reverse();
+    alignments
+}
+
+/// Negative log probability of pairing text of `source_len` with text of
+/// `target_len`: how far their lengths are from the expected ratio, in
+/// standard deviations, weighed by the prior of the kind of pairing.
+fn bead_cost(source_len: usize, target_len: usize, prior: f64, ratio: f64, variance: f64) -> f64 {
+    let (source_len, target_len) = (source_len as f64, target_len as f64);
+    let mean = (source_len + target_len / ratio) / 2.0;
+    if mean == 0.0 {
+        return f64::INFINITY;
+    }
+    let delta = (source_len * ratio - target_len) / (mean * variance).sqrt();
+    // Two-tailed: 2 * P(Z > |delta|) = erfc(|delta| / sqrt(2))
+    -(ln_erfc(delta.abs() / std::f64::consts::SQRT_2) + prior.ln())
+}
+
+/// Natural log of the complementary error function for `x >= 0`, from the
+/// Chebyshev fit in Numerical Recipes (relative error below 1.2e-7). Taking
+/// the log of the fit directly keeps large `x` from underflowing to ln(0).
+fn ln_erfc(x: f64) -> f64 {
+    let t = 1.0 / (1.0 + 0.5 * x);
+    let poly = -1.26551223
+        + t * (1.00002368
+            + t * (0.37409196
+                + t * (0.09678418
+                    + t * (-0.18628806
+                        + t * (0.27886807
+                            + t * (-1.13520398
+                                + t * (1.48851587 + t * (-0.82215223 + t * 0.17087277))))))));
+    t.ln() - x * x + poly
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(alignments: &[Alignment]) -> Vec<(Vec<usize>, Vec<usize>)> {
+        alignments
+            .iter()
+            .map(|x| (x.source.clone(), x.target.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn aligns_merged_sentences_by_length() {
+        let source =
+            sentences("A short one. Another short one. This is a much longer third sentence here.");
+        let target =
+            sentences("A short one, another short one. This is a much longer third sentence here.");
+        let length = |x: &AlignSentence| x.text.chars().count();
+        let alignments = align(
+            &source.iter().map(length).collect::<Vec<_>>(),
+            &target.iter().map(length).collect::<Vec<_>>(),
+            1.0,
+            DEFAULT_VARIANCE,
+        );
+        assert_eq!(
+            pairs(&alignments),
+            [(vec![0, 1], vec![0]), (vec![2], vec![1])]
+        );
+        assert!(alignments.iter().all(|x| x.cost.is_finite()));
+    }
+
+    #[test]
+    fn leaves_sentences_without_counterpart_unpaired() {
+        assert_eq!(
+            pairs(&align(&[20, 40], &[], 1.0, DEFAULT_VARIANCE)),
+            [(vec![0], vec![]), (vec![1], vec![])]
+        );
+        assert!(align(&[], &[], 1.0, DEFAULT_VARIANCE).is_empty());
+    }
+
+    #[test]
+    fn reads_two_texts_as_array_or_object() {
+        let array = PipelineValue::Json(serde_json::json!(["Mun.", "I."]));
+        assert_eq!(texts(array).unwrap(), ("Mun.".into(), "I.".into()));
+        let object = PipelineValue::Json(serde_json::json!({"source": "Mun.", "target": "I."}));
+        assert_eq!(texts(object).unwrap(), ("Mun.".into(), "I.".into()));
+        assert!(texts(PipelineValue::Json(serde_json::json!(["Mun."]))).is_err());
+
+        let inputs = PipelineValue::Multiple(vec![
+            PipelineValue::String("Mun.".into()),
+            PipelineValue::String("I.".into()),
+        ]);
+        assert_eq!(texts(inputs).unwrap(), ("Mun.".into(), "I.".into()));
+    }
+
+    #[tokio::test]
+    async fn aligns_the_values_of_two_inputs() {
+        use futures_util::StreamExt;
+
+        let temp = tempfile::tempdir().unwrap();
+        let pipeline = serde_json::json!({
+            "version": 1,
+            "default": "main",
+            "pipelines": {
+                "main": {
+                    "entry": { "value_type": "string" },
+                    "output": { "ref": "align" },
+                    "commands": {
+                        "upper": {
+                            "module": "example",
+                            "command": "upper",
+                            "input": { "ref": "#/entry" },
+                            "returns": "string"
+                        },
+                        "align": {
+                            "module": "divvun",
+                            "command": "align",
+                            "input": [{ "ref": "#/entry" }, { "ref": "upper" }],
+                            "returns": "json"
+                        }
+                    }
+                }
+            }
+        });
+        std::fs::write(temp.path().join("pipeline.json"), pipeline.to_string()).unwrap();
+        let bundle = crate::bundle::Bundle::from_path(temp.path()).await.unwrap();
+        let mut handle = bundle.create(serde_json::json!({})).await.unwrap();
+
+        for text in ["Mun boađán. Don manat.", "Son lea dás."] {
+            let mut stream = handle.forward(PipelineValue::String(text.into())).await;
+            let output: AlignOutput = serde_json::from_value(
+                stream
+                    .next()
+                    .await
+                    .unwrap()
+                    .unwrap()
+                    .try_into_json()
+                    .unwrap(),
+            )
+            .unwrap();
+            assert!(stream.next().await.is_none());
+            assert_eq!(output.target[0].text, output.source[0].text.to_uppercase());
+            assert_eq!(
+                pairs(&output.alignments),
+                (0..output.source.len())
+                    .map(|i| (vec![i], vec![i]))
+                    .collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn ln_erfc_matches_known_values() {
+        assert!(ln_erfc(0.0).abs() < 1e-6);
+        assert!((ln_erfc(1.0) - 0.157_299_207_f64.ln()).abs() < 1e-6);
+        assert!(ln_erfc(40.0).is_finite());
+    }
+}
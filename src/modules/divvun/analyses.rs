@@ -0,0 +1,234 @@
+//! Morphological analyses of a CG3 stream as JSON, for corpus tools and
+//! research that want a bundle's analyses without parsing CG text.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use divvun_runtime_macros::{rt_command, rt_struct};
+use serde::{Deserialize, Serialize};
+
+use super::suggest::{clean_blank, group_readings};
+use crate::{
+    ast,
+    modules::{Error, cg3},
+};
+
+use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
+
+/// A compound part of an analysis other than its head, in stream order.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisPart {
+    pub lemma: String,
+    pub tags: Vec<String>,
+}
+
+/// One reading of a token. Compounds have their other parts in `parts`.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Analysis {
+    pub lemma: String,
+    /// Tags of the reading, without the weight.
+    pub tags: Vec<String>,
+    /// From the `<W:…>` tag, if the reading has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub parts: Vec<AnalysisPart>,
+}
+
+/// A cohort of the stream, with its byte range in `text`.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysisToken {
+    pub form: String,
+    pub start: usize,
+    pub end: usize,
+    /// Readings left after disambiguation, in stream order.
+    pub analyses: Vec<Analysis>,
+    /// More than one reading is left.
+    pub ambiguous: bool,
+    /// Added by a grammar rule (`&ADDED`), so not part of `text` and its
+    /// range is empty.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub added: bool,
+}
+
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AnalysesOutput {
+    /// The text the stream was made from, rebuilt from its forms and blanks.
+    pub text: String,
+    pub tokens: Vec<AnalysisToken>,
+}
+
+/// Tokens with their lemmas, tags and ambiguity as JSON, from a CG3 stream
+#[derive(facet::Facet)]
+pub struct Analyses;
+
+#[rt_command(
+    module = "divvun",
+    name = "analyses",
+    input = [String],
+    output = "Json",
+    args = [],
+    schema = "AnalysesOutput"
+)]
+impl Analyses {
+    pub async fn new(
+        _context: Arc<Context>,
+        _kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        Ok(Arc::new(Self) as _)
+    }
+}
+
+#[async_trait]
+impl CommandRunner for Analyses {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        _config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let input = input.try_into_string()?;
+        let output = analyses(&input)?;
+        let json = serde_json::to_value(output).map_err(|e| {
+            Error::msg(format!(
+                "Failed to serialize divvun::analyses output: {}",
+                e
+            ))
+            .at_path("/output")
+        })?;
+        Ok(PipelineValue::Json(json).into())
+    }
+
+    fn name(&self) -> &'static str {
+        "divvun::analyses"
+    }
+}
+
+fn analyses(stream: &str) -> Result<AnalysesOutput, Error> {
+    let output = cg3::Output::new(stream);
+    let mut text = String::new();
+    let mut tokens = Vec::new();
+
+    for block in output.iter() {
+        match block.map_err(|e| Error::msg(format!("Invalid CG3 stream: {}", e)))? {
+            cg3::Block::Cohort(cohort) => {
+                let added = cohort.readings.iter().any(|x| {
+                    x.tags.iter().any(|tag| {
+                        matches!(
+                            tag.trim_start_matches('&'),
+                            "ADDED" | "ADDED-AFTER-BLANK" | "ADDED-BEFORE-BLANK"
+                        )
+                    })
+                });
+                let start = text.len();
+                if !added {
+                    text.push_str(cohort.word_form);
+                }
+                let analyses = group_readings(&cohort)
+                    .into_iter()
+                    .map(|group| analysis(&cohort, &group))
+                    .collect::<Vec<_>>();
+                tokens.push(AnalysisToken {
+                    form: cohort.word_form.to_string(),
+                    start,
+                    end: text.len(),
+                    ambiguous: analyses.len() > 1,
+                    analyses,
+                    added,
+                });
+            }
+            cg3::Block::Escaped(blank) => text.push_str(&clean_blank(blank)),
+            cg3::Block::Text(_) => {}
+        }
+    }
+
+    Ok(AnalysesOutput { text, tokens })
+}
+
+/// The analysis of a group of readings: the head and its compound parts.
+fn analysis(cohort: &cg3::Cohort, group: &[usize]) -> Analysis {
+    let head = &cohort.readings[group[0]];
+    let mut weight = None;
+    let tags = head
+        .tags
+        .iter()
+        .filter(|tag| match parse_weight(tag) {
+            Some(w) => {
+                weight = Some(w);
+                false
+            }
+            None => true,
+        })
+        .map(|x| x.to_string())
+        .collect();
+    let parts = group[1..]
+        .iter()
+        .map(|&i| {
+            let reading = &cohort.readings[i];
+            AnalysisPart {
+                lemma: reading.base_form.to_string(),
+                tags: reading
+                    .tags
+                    .iter()
+                    .filter(|tag| parse_weight(tag).is_none())
+                    .map(|x| x.to_string())
+                    .collect(),
+            }
+        })
+        .collect();
+
+    Analysis {
+        lemma: head.base_form.to_string(),
+        tags,
+        weight,
+        parts,
+    }
+}
+
+fn parse_weight(tag: &str) -> Option<f64> {
+    tag.strip_prefix("<W:")?.strip_suffix('>')?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_have_ranges_in_the_rebuilt_text() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom <W:0.0>\n: \n\"<borran>\"\n\t\"borrat\" V Ind Prs Sg1 <W:1.5>\n\t\"borran\" N Sg Nom <W:2.0>\n\"<.>\"\n\t\".\" CLB\n:\\n\n";
+        let output = analyses(stream).unwrap();
+
+        assert_eq!(output.text, "Mun borran.\n");
+        let ranges = output
+            .tokens
+            .iter()
+            .map(|x| (x.form.as_str(), x.start, x.end))
+            .collect::<Vec<_>>();
+        assert_eq!(ranges, [("Mun", 0, 3), ("borran", 4, 10), (".", 10, 11)]);
+
+        let borran = &output.tokens[1];
+        assert!(borran.ambiguous);
+        assert_eq!(borran.analyses[0].lemma, "borrat");
+        assert_eq!(borran.analyses[0].tags, ["V", "Ind", "Prs", "Sg1"]);
+        assert_eq!(borran.analyses[1].weight, Some(2.0));
+        assert!(!output.tokens[0].ambiguous);
+    }
+
+    #[test]
+    fn compound_parts_belong_to_their_head() {
+        let stream = "\"<gielladoaibmi>\"\n\t\"doaibmi\" N Sg Nom\n\t\t\"giella\" N Cmp/SgNom Cmp\n\"<ja>\"\n\t\"ja\" CC &ADDED\n";
+        let output = analyses(stream).unwrap();
+
+        let analysis = &output.tokens[0].analyses[0];
+        assert_eq!(analysis.lemma, "doaibmi");
+        assert_eq!(analysis.parts[0].lemma, "giella");
+        assert_eq!(output.tokens[0].analyses.len(), 1);
+
+        assert!(output.tokens[1].added);
+        assert_eq!((output.tokens[1].start, output.tokens[1].end), (13, 13));
+        assert_eq!(output.text, "gielladoaibmi");
+    }
+}
@@ -2,8 +2,6 @@ use std::{collections::HashMap, sync::Arc, thread::JoinHandle};
 
 use async_trait::async_trait;
 use divvun_runtime_macros::rt_command;
-use hfst::hfst_transducer::AnyTransducer;
-
 use tokio::sync::{
     Mutex,
     mpsc::{self, Receiver, Sender},
@@ -11,7 +9,11 @@ use tokio::sync::{
 
 use crate::{
     ast,
-    modules::{Error, SharedPipelineValueFut},
+    modules::{
+        Error, SharedPipelineValueFut,
+        hfst::{Lookup, LookupConfig},
+    },
+    util::privacy::redact,
 };
 
 use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
@@ -36,7 +38,7 @@ pub struct Blanktag {
     input = [String],
     output = "String",
     kind = "cg3",
-    args = [model_path = "Path"]
+    args = [model_path = "Path", lookup? = "LookupConfig"]
 )]
 impl Blanktag {
     pub async fn new(
@@ -54,7 +56,8 @@ impl Blanktag {
         let (input_tx, mut input_rx) = mpsc::channel(1);
         let (output_tx, output_rx) = mpsc::channel(1);
 
-        let analyzer = crate::modules::hfst::load_lookup(&context, &model_path).await?;
+        let lookup = LookupConfig::from_kwargs(&kwargs)?;
+        let analyzer = crate::modules::hfst::load_lookup(&context, &model_path, &lookup).await?;
 
         let thread = std::thread::spawn(move || {
             loop {
@@ -62,9 +65,12 @@ impl Blanktag {
                     break;
                 };
 
-                output_tx
+                if output_tx
                     .blocking_send(Some(blanktag(&analyzer, &input)))
-                    .unwrap();
+                    .is_err()
+                {
+                    break;
+                }
             }
         });
 
@@ -80,7 +86,7 @@ impl Blanktag {
 const BOSMARK: cg3::Block<'static> = cg3::Block::Text("__DIVVUN_BOS__");
 const EOSMARK: cg3::Block<'static> = cg3::Block::Text("__DIVVUN_EOS__");
 
-fn blanktag(analyzer: &std::sync::Mutex<AnyTransducer>, input: &str) -> String {
+fn blanktag(analyzer: &Lookup, input: &str) -> String {
     let cg_output = Output::new(input);
     let mut output = String::new();
     let mut preblank: Vec<cg3::Block> = vec![BOSMARK];
@@ -126,17 +132,21 @@ fn blanktag(analyzer: &std::sync::Mutex<AnyTransducer>, input: &str) -> String {
                     std::mem::swap(&mut preblank, &mut postblank);
                     postblank.clear();
 
-                    tracing::debug!("after cohort: pre:{:?} post:{:?}", preblank, postblank);
+                    tracing::debug!(
+                        "after cohort: pre:{} post:{} blocks",
+                        preblank.len(),
+                        postblank.len()
+                    );
                 }
 
                 cur_cohort = Some(cohort);
             }
             cg3::Block::Text(x) | cg3::Block::Escaped(x) => {
                 if cur_cohort.is_none() {
-                    tracing::debug!("preblank: {:?}", x);
+                    tracing::debug!("preblank: {:?}", redact(x));
                     preblank.push(block);
                 } else {
-                    tracing::debug!("postblank: {:?}", x);
+                    tracing::debug!("postblank: {:?}", redact(x));
                     postblank.push(block);
                 }
             }
@@ -209,7 +219,7 @@ fn blanktag(analyzer: &std::sync::Mutex<AnyTransducer>, input: &str) -> String {
 }
 
 fn process_cohort(
-    analyzer: &std::sync::Mutex<AnyTransducer>,
+    analyzer: &Lookup,
     preblank: &[cg3::Block],
     postblank: &[cg3::Block],
     cohort: &cg3::Cohort,
@@ -249,7 +259,7 @@ fn process_cohort(
     let tags = crate::modules::hfst::lookup_tags(analyzer, &lookup_string, false);
     let other_tags = crate::modules::hfst::lookup_tags(analyzer, &lookup_string, true);
 
-    tracing::debug!("lookup_string: {:?}", lookup_string);
+    tracing::debug!("lookup_string: {:?}", redact(&lookup_string));
     tracing::debug!("tags: {:?}", tags);
     tracing::debug!("other_tags: {:?}", other_tags);
 
@@ -290,11 +300,11 @@ impl CommandRunner for Blanktag {
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
 
+        let mut output_rx = self.output_rx.lock().await;
         self.input_tx
             .send(Some(input))
             .await
             .expect("input tx send");
-        let mut output_rx = self.output_rx.lock().await;
         let output = output_rx.recv().await.expect("output rx recv");
 
         Ok(output.unwrap_or_else(|| "".to_string()).into())
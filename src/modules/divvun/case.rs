@@ -0,0 +1,223 @@
+use std::{borrow::Cow, collections::HashMap, fmt::Write as _, str::FromStr, sync::Arc};
+
+use async_trait::async_trait;
+use divvun_runtime_macros::rt_command;
+
+use super::casing::{Casing, get_casing, with_casing};
+use crate::{
+    ast,
+    modules::{Error, cg3},
+};
+
+use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
+
+const CASING_TAG_PREFIX: &str = "<DRT-CASING:";
+
+#[derive(facet::Facet, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum CaseOperation {
+    /// Tag each cohort with the casing of its word form.
+    #[default]
+    Detect,
+    /// Lowercase word forms for analysis, tagging the original casing.
+    Lower,
+    /// Reapply the tagged casing to word forms and drop the tag.
+    Restore,
+}
+
+impl FromStr for CaseOperation {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "detect" => Ok(Self::Detect),
+            "lower" => Ok(Self::Lower),
+            "restore" => Ok(Self::Restore),
+            _ => Err(()),
+        }
+    }
+}
+
+/// Detect, lower and restore word form casing in a CG3 stream
+#[derive(facet::Facet)]
+pub struct Case {
+    operation: CaseOperation,
+}
+
+#[rt_command(
+    module = "divvun",
+    name = "case",
+    input = [String],
+    output = "String",
+    kind = "cg3",
+    args = [operation? = "String"]
+)]
+impl Case {
+    pub async fn new(
+        _context: Arc<Context>,
+        mut kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        let operation = match kwargs
+            .remove("operation")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_string())
+        {
+            Some(op) => op.parse::<CaseOperation>().map_err(|_| {
+                Error::msg(format!(
+                    "Unknown case operation '{}' (expected detect, lower or restore)",
+                    op
+                ))
+                .at("pipeline.json", "/args/operation")
+            })?,
+            None => CaseOperation::default(),
+        };
+
+        Ok(Arc::new(Self { operation }) as _)
+    }
+}
+
+#[async_trait]
+impl CommandRunner for Case {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        _config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let input = input.try_into_string()?;
+        Ok(recase(&input, self.operation)?.into())
+    }
+
+    fn name(&self) -> &'static str {
+        "divvun::case"
+    }
+}
+
+fn casing_tag(casing: Casing) -> String {
+    format!("{}{}>", CASING_TAG_PREFIX, casing.as_str())
+}
+
+fn tagged_casing(cohort: &cg3::Cohort<'_>) -> Option<Casing> {
+    cohort
+        .readings
+        .iter()
+        .flat_map(|r| r.tags.iter())
+        .find_map(|tag| {
+            tag.strip_prefix(CASING_TAG_PREFIX)
+                .and_then(|x| x.strip_suffix('>'))
+                .and_then(|x| x.parse().ok())
+        })
+}
+
+/// Word form tags (`"<...>"`) inside readings, e.g. from SUGGESTWF.
+fn is_wordform_tag(tag: &str) -> bool {
+    tag.len() >= 4 && tag.starts_with("\"<") && tag.ends_with(">\"")
+}
+
+fn map_wordform_tag(tag: &str, f: impl Fn(&str) -> String) -> String {
+    format!("\"<{}>\"", f(&tag[2..tag.len() - 2]))
+}
+
+fn recase(input: &str, operation: CaseOperation) -> Result<String, Error> {
+    let write_err = |e: std::fmt::Error| Error::msg(format!("Failed to write CG output: {}", e));
+    let stream = cg3::Output::new(input);
+    let mut out = String::with_capacity(input.len());
+
+    for block in stream.iter() {
+        let block = block.map_err(Error::wrap)?;
+        let cg3::Block::Cohort(cohort) = block else {
+            write!(out, "{}", block).map_err(write_err)?;
+            continue;
+        };
+
+        let tagged = tagged_casing(&cohort);
+        let (word_form, casing, keep_tag): (Cow<str>, Option<Casing>, bool) = match operation {
+            CaseOperation::Detect => (
+                cohort.word_form.into(),
+                tagged.or_else(|| Some(get_casing(cohort.word_form))),
+                true,
+            ),
+            CaseOperation::Lower => (
+                cohort.word_form.to_lowercase().into(),
+                tagged.or_else(|| Some(get_casing(cohort.word_form))),
+                true,
+            ),
+            CaseOperation::Restore => match tagged {
+                Some(casing) => (
+                    with_casing(false, casing, cohort.word_form).into(),
+                    None,
+                    false,
+                ),
+                None => (cohort.word_form.into(), None, true),
+            },
+        };
+
+        writeln!(out, "\"<{}>\"", word_form).map_err(write_err)?;
+        for reading in &cohort.readings {
+            write!(
+                out,
+                "{}\"{}\"",
+                "\t".repeat(reading.depth),
+                reading.base_form
+            )
+            .map_err(write_err)?;
+            for tag in &reading.tags {
+                if tag.starts_with(CASING_TAG_PREFIX) {
+                    if keep_tag {
+                        write!(out, " {}", tag).map_err(write_err)?;
+                    }
+                    continue;
+                }
+                let tag: Cow<str> = match (operation, tagged) {
+                    (CaseOperation::Lower, _) if is_wordform_tag(tag) => {
+                        map_wordform_tag(tag, str::to_lowercase).into()
+                    }
+                    (CaseOperation::Restore, Some(casing)) if is_wordform_tag(tag) => {
+                        map_wordform_tag(tag, |x| with_casing(false, casing, x)).into()
+                    }
+                    _ => (*tag).into(),
+                };
+                write!(out, " {}", tag).map_err(write_err)?;
+            }
+            if let (Some(casing), None) = (casing, tagged) {
+                write!(out, " {}", casing_tag(casing)).map_err(write_err)?;
+            }
+            writeln!(out).map_err(write_err)?;
+        }
+    }
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: &str = "\"<Sámi>\"\n\t\"sápmi\" N Sg Gen\n: \n\"<GIELLA>\"\n\t\"giella\" N Sg Nom \"<GIELLA>\"\n\"<.>\"\n\t\".\" CLB\n";
+
+    #[test]
+    fn detect_tags_casing_without_changing_forms() {
+        let out = recase(STREAM, CaseOperation::Detect).unwrap();
+        assert!(out.contains("\"<Sámi>\"\n\t\"sápmi\" N Sg Gen <DRT-CASING:title>\n"));
+        assert!(out.contains("\"giella\" N Sg Nom \"<GIELLA>\" <DRT-CASING:upper>\n"));
+        assert!(out.contains("\t\".\" CLB <DRT-CASING:mixed>\n"));
+        // Detecting twice doesn't add a second tag.
+        assert_eq!(recase(&out, CaseOperation::Detect).unwrap(), out);
+    }
+
+    #[test]
+    fn lower_then_restore_round_trips() {
+        let lowered = recase(STREAM, CaseOperation::Lower).unwrap();
+        assert!(lowered.contains("\"<sámi>\""));
+        assert!(
+            lowered.contains("\"<giella>\"\n\t\"giella\" N Sg Nom \"<giella>\" <DRT-CASING:upper>")
+        );
+
+        let restored = recase(&lowered, CaseOperation::Restore).unwrap();
+        assert_eq!(restored, STREAM);
+    }
+
+    #[test]
+    fn restore_without_tag_is_a_no_op() {
+        assert_eq!(recase(STREAM, CaseOperation::Restore).unwrap(), STREAM);
+    }
+}
@@ -0,0 +1,94 @@
+//! Casing helpers shared by `divvun::suggest` and `divvun::case`.
+
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum Casing {
+    Lower,
+    Title,
+    Upper,
+    Mixed,
+}
+
+impl Casing {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Casing::Lower => "lower",
+            Casing::Title => "title",
+            Casing::Upper => "upper",
+            Casing::Mixed => "mixed",
+        }
+    }
+}
+
+impl FromStr for Casing {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lower" => Ok(Casing::Lower),
+            "title" => Ok(Casing::Title),
+            "upper" => Ok(Casing::Upper),
+            "mixed" => Ok(Casing::Mixed),
+            _ => Err(()),
+        }
+    }
+}
+
+pub(crate) fn get_casing(input: &str) -> Casing {
+    if input.is_empty() {
+        return Casing::Mixed;
+    }
+
+    let mut seen_upper = false;
+    let mut seen_lower = false;
+    let mut fst_upper = false;
+    let mut non_fst_upper = false;
+
+    for c in input.chars() {
+        if c.is_uppercase() {
+            if seen_lower || seen_upper {
+                non_fst_upper = true;
+            } else {
+                fst_upper = true;
+            }
+            seen_upper = true;
+        }
+        if c.is_lowercase() {
+            seen_lower = true;
+        }
+    }
+
+    if !seen_upper && !seen_lower {
+        Casing::Mixed // No letters found, preserve original casing
+    } else if !seen_upper {
+        Casing::Lower
+    } else if !seen_lower {
+        Casing::Upper
+    } else if fst_upper && !non_fst_upper {
+        Casing::Title
+    } else {
+        Casing::Mixed
+    }
+}
+
+pub(crate) fn with_casing(fixedcase: bool, input_casing: Casing, input: &str) -> String {
+    if fixedcase {
+        return input.to_string();
+    }
+    match input_casing {
+        Casing::Title => {
+            let mut chars: Vec<char> = input.chars().collect();
+            if let Some(first_alpha_pos) = chars.iter().position(|c| c.is_alphabetic()) {
+                chars[first_alpha_pos] = chars[first_alpha_pos]
+                    .to_uppercase()
+                    .next()
+                    .unwrap_or(chars[first_alpha_pos]);
+            }
+            chars.into_iter().collect()
+        }
+        Casing::Upper => input.to_uppercase(),
+        Casing::Lower => input.to_lowercase(),
+        Casing::Mixed => input.to_string(),
+    }
+}
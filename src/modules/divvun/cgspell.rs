@@ -10,7 +10,7 @@ use divvun_runtime_macros::{rt_command, rt_struct};
 use rayon::iter::{IntoParallelRefIterator as _, ParallelIterator as _};
 use serde::{Deserialize, Serialize};
 
-use crate::{ast, modules::Error};
+use crate::{ast, modules::Error, util::privacy::redact};
 
 use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
 
@@ -63,6 +63,37 @@ pub struct SpellerConfig {
     pub recase: bool,
 }
 
+/// Configuration for the cgspell command's forward() function
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CgspellConfig {
+    /// Drop suggestions whose analyses disagree with the morphology the
+    /// neighbouring cohorts settle on. Off by default.
+    #[serde(default)]
+    pub context_filter: Option<bool>,
+    /// Groups of mutually exclusive tags words agree in, e.g.
+    /// `[["Sg", "Pl"], ["Nom", "Gen"]]`. Defaults to number.
+    #[serde(default)]
+    pub agreement: Option<Vec<Vec<String>>>,
+    /// Also compare case with the default groups. Off by default, as a
+    /// word's case often differs from its neighbours' in correct text.
+    #[serde(default)]
+    pub case_agreement: Option<bool>,
+}
+
+const NUMBER: &[&str] = &["Sg", "Du", "Pl"];
+const CASE: &[&str] = &[
+    "Nom", "Acc", "Gen", "Ill", "Loc", "Com", "Ess", "Abe", "Par",
+];
+
+fn default_agreement(case: bool) -> Vec<Vec<String>> {
+    let groups: &[&[&str]] = if case { &[NUMBER, CASE] } else { &[NUMBER] };
+    groups
+        .iter()
+        .map(|group| group.iter().map(|x| x.to_string()).collect())
+        .collect()
+}
+
 impl TryFrom<divvun_fst::speller::SpellerConfig> for SpellerConfig {
     type Error = serde_json::Error;
 
@@ -79,7 +110,9 @@ impl TryFrom<divvun_fst::speller::SpellerConfig> for SpellerConfig {
     input = [String],
     output = "String",
     kind = "cg3",
-    args = [err_model_path = "Path", acc_model_path = "Path", config? = "SpellerConfig"]
+    args = [err_model_path = "Path", acc_model_path = "Path", config? = "SpellerConfig"],
+    config = "CgspellConfig",
+    resources = [memory = "128M"]
 )]
 impl Cgspell {
     pub async fn new(
@@ -129,6 +162,8 @@ impl Cgspell {
             config
         });
 
+        context.prefetch(&acc_model_path).await?;
+        context.prefetch(&err_model_path).await?;
         let lexicon = context.load_fst::<HfstTransducer>(&acc_model_path)?;
         let mutator = context.load_fst::<HfstTransducer>(&err_model_path)?;
         let speller = HfstSpeller::new(mutator, lexicon);
@@ -142,13 +177,70 @@ impl Cgspell {
     }
 }
 
+/// Tags from one agreement group that a misspelling's neighbours settle on.
+struct Agreement<'a> {
+    group: &'a [String],
+    tags: Vec<&'a str>,
+}
+
+/// The tag from `group` that every main reading of `cohort` carries, if
+/// they all carry the same one.
+fn agreed_tag<'a>(cohort: &cg3::Cohort<'a>, group: &[String]) -> Option<&'a str> {
+    let mut agreed = None;
+    for reading in cohort.readings.iter().filter(|x| x.depth == 1) {
+        let mut tags = reading
+            .tags
+            .iter()
+            .filter(|tag| group.iter().any(|x| x == *tag));
+        let tag = *tags.next()?;
+        if tags.next().is_some() || agreed.is_some_and(|x| x != tag) {
+            return None;
+        }
+        agreed = Some(tag);
+    }
+    agreed
+}
+
+fn agreements<'a>(
+    groups: &'a [Vec<String>],
+    neighbours: &[&cg3::Cohort<'a>],
+) -> Vec<Agreement<'a>> {
+    groups
+        .iter()
+        .filter_map(|group| {
+            let mut tags = neighbours
+                .iter()
+                .filter_map(|c| agreed_tag(c, group))
+                .collect::<Vec<_>>();
+            tags.dedup();
+            (!tags.is_empty()).then_some(Agreement { group, tags })
+        })
+        .collect()
+}
+
+/// Whether an analysis (`lemma Tag Tag#lemma Tag`) carries a tag from an
+/// agreement group but none of the tags the neighbours agree on. Only the
+/// last compound part is looked at, as that one inflects.
+fn conflicts(analysis: &str, context: &[Agreement<'_>]) -> bool {
+    let head = analysis.rsplit('#').next().unwrap_or(analysis);
+    let tags = head.split_ascii_whitespace().skip(1).collect::<Vec<_>>();
+    context.iter().any(|agreement| {
+        let mut in_group = tags
+            .iter()
+            .filter(|tag| agreement.group.iter().any(|x| x == **tag))
+            .peekable();
+        in_group.peek().is_some() && !in_group.any(|tag| agreement.tags.contains(tag))
+    })
+}
+
 fn do_cgspell(
     speller: Arc<dyn Speller + Sync + Send>,
     analyzer: Arc<dyn Speller + Sync + Send>,
     word: &str,
     config: Option<&divvun_fst::speller::SpellerConfig>,
+    context: &[Agreement<'_>],
 ) -> String {
-    tracing::debug!("cgspell processing word: {}", word);
+    tracing::debug!("cgspell processing word: {}", redact(word));
     let suggestions = match config {
         Some(cfg) => speller.clone().suggest_with_config(word, cfg),
         None => speller.clone().suggest(word),
@@ -156,23 +248,46 @@ fn do_cgspell(
 
     tracing::debug!(
         "speller.suggest('{}') returned {} suggestions",
-        word,
+        redact(word),
         suggestions.len()
     );
 
-    suggestions
+    let mut analysed = suggestions
         .par_iter()
         .map(|sugg| {
             let analyses = analyzer.clone().analyze_output(&sugg.value);
             tracing::debug!(
                 "  suggestion '{}' (weight: {}, details: {:?}) -> {} analyses",
-                sugg.value,
+                redact(&sugg.value),
                 sugg.weight,
                 sugg.weight_details,
                 analyses.len()
             );
-            print_readings(&analyses, sugg)
+            (sugg, analyses)
         })
+        .collect::<Vec<_>>();
+
+    // Only filter when some suggestion survives: better an implausible
+    // suggestion than none at all.
+    let plausible =
+        |analyses: &[Suggestion]| analyses.iter().any(|x| !conflicts(&x.value, context));
+    if !context.is_empty() && analysed.iter().any(|(_, analyses)| plausible(analyses)) {
+        let before = analysed.len();
+        for (_, analyses) in analysed.iter_mut() {
+            analyses.retain(|x| !conflicts(&x.value, context));
+        }
+        analysed.retain(|(_, analyses)| !analyses.is_empty());
+        tracing::debug!(
+            "context filter dropped {} of {} suggestions for '{}'",
+            before - analysed.len(),
+            before,
+            redact(word)
+        );
+    }
+
+    analysed
+        .iter()
+        .map(|(sugg, analyses)| print_readings(analyses, sugg))
         .collect::<Vec<String>>()
         .join("")
 }
@@ -210,14 +325,14 @@ fn print_readings(analyses: &[Suggestion], sugg: &Suggestion) -> String {
                 ret.push_str(chunk);
             }
             if depth == 1 {
-                write!(
+                // Writing to a `String` can't fail
+                let _ = write!(
                     &mut ret,
                     " <W:{}> <WA:{}> <spelled> \"{}\"S",
                     weight,
                     analysis_weight(analysis.weight.0),
                     form
-                )
-                .unwrap();
+                );
             }
             ret.push('\n');
         }
@@ -231,17 +346,41 @@ impl CommandRunner for Cgspell {
     async fn forward(
         self: Arc<Self>,
         input: PipelineValue,
-        _config: Arc<serde_json::Value>,
+        config: Arc<serde_json::Value>,
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
+        let config: CgspellConfig = serde_json::from_value((*config).clone()).unwrap_or_default();
+        let groups = match config.context_filter {
+            Some(true) => config
+                .agreement
+                .clone()
+                .unwrap_or_else(|| default_agreement(config.case_agreement == Some(true))),
+            _ => Vec::new(),
+        };
+
         let output = cg3::Output::new(&input);
         let mut out = String::new();
 
-        for thing in output.clone().iter() {
-            let thing = thing.map_err(Error::wrap)?;
+        // Collected up front so the filter can look at the cohort after an
+        // unknown word.
+        let blocks = output
+            .iter()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(Error::wrap)?;
+        let cohorts = blocks
+            .iter()
+            .filter_map(|x| match x {
+                Block::Cohort(c) => Some(c),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let mut cohort_index = 0;
 
+        for thing in &blocks {
             match thing {
                 Block::Cohort(c) => {
+                    let index = cohort_index;
+                    cohort_index += 1;
                     writeln!(&mut out, "\"<{}>\"", c.word_form).map_err(Error::wrap)?;
 
                     let is_unknown = c
@@ -250,11 +389,17 @@ impl CommandRunner for Cgspell {
                         .any(|x| x.tags.contains(&"+?") || x.tags.contains(&"?"));
 
                     let spelled = if is_unknown {
+                        let neighbours = [
+                            index.checked_sub(1).map(|i| cohorts[i]),
+                            cohorts.get(index + 1).copied(),
+                        ];
+                        let neighbours = neighbours.into_iter().flatten().collect::<Vec<_>>();
                         do_cgspell(
                             self.speller.clone(),
                             self.analyzer.clone(),
                             c.word_form,
                             self.config.as_ref(),
+                            &agreements(&groups, &neighbours),
                         )
                     } else {
                         String::new()
@@ -281,10 +426,10 @@ impl CommandRunner for Cgspell {
                 }
                 Block::Escaped(x) => {
                     out.push(':');
-                    out.push_str(&x);
+                    out.push_str(x);
                 }
                 Block::Text(x) => {
-                    out.push_str(&x);
+                    out.push_str(x);
                 }
             }
             out.push('\n');
@@ -297,3 +442,45 @@ impl CommandRunner for Cgspell {
         "divvun::cgspell"
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_filter_drops_disagreeing_analyses() {
+        let input = "\"<guokte>\"\n\t\"guokte\" Num Pl Nom\n\t\"guokte\" Num Pl Gen\n";
+        let output = cg3::Output::new(input);
+        let Some(Ok(Block::Cohort(prev))) = output.iter().next() else {
+            panic!("expected a cohort");
+        };
+        let groups = default_agreement(true);
+        let context = agreements(&groups, &[&prev]);
+
+        // Number is agreed on, case isn't.
+        assert_eq!(context.len(), 1);
+        assert_eq!(context[0].tags, ["Pl"]);
+
+        assert!(conflicts("girji N Sg Nom", &context));
+        assert!(!conflicts("girji N Pl Gen", &context));
+        assert!(!conflicts("ja CC", &context));
+        assert!(!conflicts("girji N Sg Nom#lávka N Pl Nom", &context));
+    }
+
+    #[test]
+    fn case_agreement_is_opt_in() {
+        let input = "\"<girjji>\"\n\t\"girji\" N Sg Gen\n";
+        let output = cg3::Output::new(input);
+        let Some(Ok(Block::Cohort(prev))) = output.iter().next() else {
+            panic!("expected a cohort");
+        };
+
+        let groups = default_agreement(false);
+        let context = agreements(&groups, &[&prev]);
+        assert!(!conflicts("girji N Sg Nom", &context));
+
+        let groups = default_agreement(true);
+        let context = agreements(&groups, &[&prev]);
+        assert!(conflicts("girji N Sg Nom", &context));
+    }
+}
@@ -0,0 +1,192 @@
+//! Characters in the input that look like nothing or like something else:
+//! zero-width characters, bidi controls, and words mixing Latin letters with
+//! Cyrillic or Greek lookalikes. Text pasted from the web or PDFs carries
+//! them often enough, and a word with one inside doesn't match the speller or
+//! the grammar, so the checker seems to miss an obvious typo.
+
+use divvun_runtime_macros::rt_struct;
+use serde::{Deserialize, Serialize};
+
+/// Characters in the input that may keep it from being checked as it reads.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TextWarning {
+    /// "invisible" (zero-width characters, soft hyphens, byte order marks),
+    /// "bidi" (direction controls) or "mixed_script" (a word with letters
+    /// from more than one of Latin, Cyrillic and Greek).
+    pub kind: String,
+    pub form: String,
+    pub start: usize,
+    pub end: usize,
+    /// The characters at fault as `U+XXXX`: the invisible ones, or the
+    /// letters of a mixed word that aren't in its main script.
+    pub chars: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Script {
+    Latin,
+    Cyrillic,
+    Greek,
+}
+
+/// Warnings for `text` by position, with byte offsets.
+pub(super) fn text_warnings(text: &str) -> Vec<TextWarning> {
+    let mut warnings: Vec<TextWarning> = Vec::new();
+    let mut prev = None;
+    for (i, c) in text.char_indices() {
+        let end = i + c.len_utf8();
+        if let Some(kind) = control_kind(c, prev) {
+            // A run of the same kind is one warning
+            match warnings.last_mut() {
+                Some(last) if last.kind == kind && last.end == i => {
+                    last.form.push(c);
+                    last.end = end;
+                    let code = code_point(c);
+                    if !last.chars.contains(&code) {
+                        last.chars.push(code);
+                    }
+                }
+                _ => warnings.push(TextWarning {
+                    kind: kind.to_string(),
+                    form: c.to_string(),
+                    start: i,
+                    end,
+                    chars: vec![code_point(c)],
+                }),
+            }
+        }
+        prev = Some(c);
+    }
+
+    warnings.extend(mixed_script_words(text));
+    warnings.sort_by_key(|x| x.start);
+    warnings
+}
+
+fn control_kind(c: char, prev: Option<char>) -> Option<&'static str> {
+    match c {
+        // A joiner also holds emoji sequences together; only one in a word
+        // is suspicious
+        '\u{200D}' => prev.is_some_and(char::is_alphabetic).then_some("invisible"),
+        '\u{00AD}'
+        | '\u{034F}'
+        | '\u{180E}'
+        | '\u{200B}'
+        | '\u{200C}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{FEFF}' => Some("invisible"),
+        '\u{061C}'
+        | '\u{200E}'
+        | '\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2066}'..='\u{2069}' => Some("bidi"),
+        _ => None,
+    }
+}
+
+fn script(c: char) -> Option<Script> {
+    match c {
+        'A'..='Z' | 'a'..='z' | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+        '\u{00C0}'..='\u{024F}' if c != '×' && c != '÷' => Some(Script::Latin),
+        '\u{0370}'..='\u{03FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+        '\u{0400}'..='\u{052F}' => Some(Script::Cyrillic),
+        _ => None,
+    }
+}
+
+fn mixed_script_words(text: &str) -> Vec<TextWarning> {
+    let mut warnings = Vec::new();
+    let mut start = None;
+    let end_of_text = std::iter::once((text.len(), ' '));
+    for (i, c) in text.char_indices().chain(end_of_text) {
+        // Combining accents belong to the word they're in
+        if c.is_alphanumeric() || ('\u{0300}'..='\u{036F}').contains(&c) {
+            start.get_or_insert(i);
+            continue;
+        }
+        if let Some(start) = start.take() {
+            warnings.extend(mixed_script(&text[start..i], start));
+        }
+    }
+    warnings
+}
+
+/// A warning for `word` at `offset` if its letters aren't all of one script.
+fn mixed_script(word: &str, offset: usize) -> Option<TextWarning> {
+    let scripts = [Script::Latin, Script::Cyrillic, Script::Greek];
+    let counts = scripts.map(|s| word.chars().filter(|&c| script(c) == Some(s)).count());
+    if counts.iter().filter(|&&n| n > 0).count() < 2 {
+        return None;
+    }
+    // The main script has the most letters; Latin wins a tie
+    let main = scripts
+        .iter()
+        .zip(counts)
+        .rev()
+        .max_by_key(|(_, n)| *n)
+        .map(|(s, _)| *s);
+
+    let mut chars = Vec::new();
+    for c in word.chars() {
+        let code = code_point(c);
+        if script(c).is_some() && script(c) != main && !chars.contains(&code) {
+            chars.push(code);
+        }
+    }
+    Some(TextWarning {
+        kind: "mixed_script".to_string(),
+        form: word.to_string(),
+        start: offset,
+        end: offset + word.len(),
+        chars,
+    })
+}
+
+fn code_point(c: char) -> String {
+    format!("U+{:04X}", c as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(text: &str) -> Vec<(String, String, Vec<String>)> {
+        text_warnings(text)
+            .into_iter()
+            .map(|x| (x.kind, x.form, x.chars))
+            .collect()
+    }
+
+    #[test]
+    fn finds_runs_of_invisible_and_bidi_characters() {
+        let text = "Mun\u{200B}\u{200B} lea \u{202E}dás\u{202C}, bu\u{00AD}orre";
+        let warnings = text_warnings(text);
+        assert_eq!(warnings.len(), 4);
+        assert_eq!(warnings[0].kind, "invisible");
+        assert_eq!((warnings[0].start, warnings[0].end), (3, 9));
+        assert_eq!(warnings[0].chars, ["U+200B"]);
+        assert_eq!(warnings[1].kind, "bidi");
+        assert_eq!(warnings[3].chars, ["U+00AD"]);
+
+        // A joiner in an emoji sequence is fine, one in a word isn't
+        assert!(text_warnings("👩\u{200D}💻").is_empty());
+        assert_eq!(text_warnings("gi\u{200D}ella").len(), 1);
+    }
+
+    #[test]
+    fn finds_lookalike_letters_in_words() {
+        // Cyrillic а and о in a Sámi word
+        assert_eq!(
+            kinds("Mun lea d\u{0430}hk\u{043E}n dan."),
+            [(
+                "mixed_script".to_string(),
+                "d\u{0430}hk\u{043E}n".to_string(),
+                vec!["U+0430".to_string(), "U+043E".to_string()]
+            )]
+        );
+        // Latin o in a Russian word
+        assert_eq!(kinds("хорoшо")[0].2, ["U+006F"]);
+        assert!(text_warnings("Čáhppes ja ruoná. Москва").is_empty());
+    }
+}
@@ -1,7 +1,19 @@
+mod align;
+mod analyses;
 mod blanktag;
+mod case;
+mod casing;
 mod cgspell;
+mod invisible;
+mod postprocess;
+mod punct;
 mod suggest;
 
+pub use align::Align;
+pub use analyses::Analyses;
 pub use blanktag::Blanktag;
+pub use case::Case;
 pub use cgspell::Cgspell;
-pub use suggest::{GrammarErr, GrammarOutput, Suggest};
+pub use invisible::TextWarning;
+pub use punct::Punct;
+pub use suggest::{GrammarErr, GrammarOutput, NocheckRange, Suggest, SuppressedErr};
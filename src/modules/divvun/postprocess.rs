@@ -0,0 +1,131 @@
+//! Final orthographic fixes to the suggestions of `divvun::suggest`, such as
+//! normalizing apostrophes or hyphenating compounds, which are easier to make
+//! on the finished string than in the generator.
+//!
+//! The `postprocess` argument names the asset. An optimized-lookup
+//! transducer (`.hfstol`) maps each suggestion to its first result, leaving
+//! it as it is without one. Anything else is read as JSON: a list of regex
+//! rules applied in order, with `$1`-style references to groups.
+//!
+//! ```json
+//! [
+//!   { "pattern": "'", "replacement": "ʼ" },
+//!   { "pattern": "(\\w)-\\s+(\\w)", "replacement": "$1-$2" }
+//! ]
+//! ```
+
+use std::path::Path;
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::modules::{
+    Context, Error,
+    hfst::{Lookup, LookupConfig, load_lookup, lookup_tags},
+};
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleDef {
+    pattern: String,
+    replacement: String,
+}
+
+pub(super) struct Rule {
+    pattern: Regex,
+    replacement: String,
+}
+
+pub(super) enum Postprocess {
+    Transducer(Lookup),
+    Rules(Vec<Rule>),
+}
+
+impl Postprocess {
+    pub(super) async fn load(context: &Context, path: &str) -> Result<Postprocess, Error> {
+        let is_transducer = Path::new(path)
+            .extension()
+            .is_some_and(|x| x == "hfstol" || x == "hfst");
+        if is_transducer {
+            let lookup = load_lookup(context, path, &LookupConfig::default())
+                .await
+                .map_err(|e| e.at("pipeline.json", "/args/postprocess"))?;
+            return Ok(Postprocess::Transducer(lookup));
+        }
+
+        let content = context.load_file(path).await?;
+        Postprocess::from_json(&content).map_err(|e| e.at_file(path))
+    }
+
+    /// Rules from a JSON list of `pattern` and `replacement`.
+    pub(super) fn from_json(json: &[u8]) -> Result<Postprocess, Error> {
+        let defs: Vec<RuleDef> = serde_json::from_slice(json)
+            .map_err(|e| Error::msg(format!("Failed to parse postprocess rules: {}", e)))?;
+        let rules = defs
+            .into_iter()
+            .map(|def| {
+                let pattern = Regex::new(&def.pattern).map_err(|e| {
+                    Error::msg(format!(
+                        "invalid postprocess pattern {:?}: {}",
+                        def.pattern, e
+                    ))
+                })?;
+                Ok(Rule {
+                    pattern,
+                    replacement: def.replacement,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(Postprocess::Rules(rules))
+    }
+
+    /// `suggestion` as it should be shown.
+    pub(super) fn apply(&self, suggestion: &str) -> String {
+        match self {
+            Postprocess::Transducer(lookup) => lookup_tags(lookup, suggestion, false)
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| suggestion.to_string()),
+            Postprocess::Rules(rules) => {
+                let mut text = suggestion.to_string();
+                for rule in rules {
+                    text = rule
+                        .pattern
+                        .replace_all(&text, rule.replacement.as_str())
+                        .into_owned();
+                }
+                text
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rules_apply_in_order() {
+        let postprocess = Postprocess::from_json(
+            r#"[
+                { "pattern": "'", "replacement": "ʼ" },
+                { "pattern": "(\\w)-\\s+(\\w)", "replacement": "$1-$2" }
+            ]"#
+            .as_bytes(),
+        )
+        .unwrap();
+        assert_eq!(postprocess.apply("Sámi'giella"), "Sámiʼgiella");
+        assert_eq!(postprocess.apply("davvi- sámegiella"), "davvi-sámegiella");
+        assert_eq!(postprocess.apply("lean"), "lean");
+
+        let transducer = Postprocess::Transducer(Lookup::canned([(
+            "ja'".to_string(),
+            vec!["jaʼ".to_string()],
+        )]));
+        assert_eq!(transducer.apply("ja'"), "jaʼ");
+        assert_eq!(transducer.apply("lean"), "lean");
+
+        assert!(Postprocess::from_json(br#"[{ "pattern": "(", "replacement": "" }]"#).is_err());
+        assert!(Postprocess::from_json(r#"{ "'": "ʼ" }"#.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,586 @@
+//! Mechanical whitespace and punctuation checks that don't need a grammar:
+//! doubled spaces, spaces before punctuation, quotation marks not used in the
+//! language, and paragraphs without sentence-final punctuation.
+
+use std::{collections::HashMap, sync::Arc};
+
+use async_trait::async_trait;
+use divvun_runtime_macros::{rt_command, rt_struct};
+use fluent_bundle::FluentArgs;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use super::suggest::{ErrorPolicy, GrammarErr, GrammarOutput, LineCol, load_error_policies};
+use crate::{ast, modules::Error, util::fluent_loader::FluentLoader};
+
+use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
+
+/// Error IDs of the checks, in the order they run.
+const CHECKS: [&str; 4] = [
+    "double-space",
+    "space-before-punct",
+    "quotation-marks",
+    "final-punct",
+];
+
+/// Double quotation marks of any language; the ones checked against the
+/// language's pairs.
+const DOUBLE_QUOTES: &[char] = &['"', '“', '”', '„', '‟', '«', '»'];
+
+/// Characters after which a quotation mark opens a quote.
+const QUOTE_OPENERS: &[char] = &['(', '[', '{', '—', '–', '-', '/'];
+
+/// Characters a paragraph may end in after its final punctuation.
+const TRAILING_CLOSERS: &[char] = &[
+    '"', '\'', '“', '”', '„', '«', '»', '‘', '’', '‹', '›', ')', ']',
+];
+
+/// English titles and descriptions, for bundles without a Fluent message for
+/// a check.
+fn default_message(error_id: &str) -> (&'static str, &'static str) {
+    match error_id {
+        "double-space" => ("Double space", "There is more than one space here."),
+        "space-before-punct" => (
+            "Space before punctuation",
+            "Punctuation follows the word before it without a space.",
+        ),
+        "quotation-marks" => (
+            "Quotation marks",
+            "These quotation marks are not the ones used in this language.",
+        ),
+        "final-punct" => (
+            "Missing final punctuation",
+            "The sentence does not end in punctuation.",
+        ),
+        _ => ("", ""),
+    }
+}
+
+/// Punctuation conventions of a language.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Conventions {
+    /// Accepted (opening, closing) double quotation marks, the first pair
+    /// being suggested. Quotes aren't checked without any.
+    quotes: Vec<(char, char)>,
+    /// Punctuation written right after the word before it.
+    no_space_before: Vec<char>,
+    /// Punctuation ending a sentence, the first one being suggested.
+    sentence_final: Vec<char>,
+    /// Paragraphs with fewer words, e.g. headings, need no final punctuation.
+    min_words: usize,
+}
+
+impl Conventions {
+    /// Conventions of the language with the given BCP 47 tag, or neutral
+    /// ones not checking quotation marks for other languages.
+    fn for_language(language: &str) -> Self {
+        let primary = language
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let (quotes, no_space_before) = match primary.as_str() {
+            "en" => (vec![('“', '”')], ",.;:!?"),
+            "se" | "sma" | "smj" | "smn" | "sms" => (vec![('”', '”'), ('«', '»')], ",.;:!?"),
+            "fi" | "sv" => (vec![('”', '”'), ('»', '»')], ",.;:!?"),
+            "nb" | "nn" | "no" => (vec![('«', '»')], ",.;:!?"),
+            "da" | "de" => (vec![('„', '“'), ('»', '«')], ",.;:!?"),
+            "ru" => (vec![('«', '»'), ('„', '“')], ",.;:!?"),
+            // A narrow space goes before ; : ! and ?
+            "fr" => (vec![('«', '»')], ",."),
+            _ => (vec![], ",.;:!?"),
+        };
+        Conventions {
+            quotes,
+            no_space_before: no_space_before.chars().collect(),
+            sentence_final: vec!['.', '!', '?', '…'],
+            min_words: 4,
+        }
+    }
+}
+
+/// Parse quotation mark pairs written as two-character strings, e.g. `«»`.
+fn parse_quotes(pairs: &[String]) -> Option<Vec<(char, char)>> {
+    pairs
+        .iter()
+        .map(|pair| {
+            let mut chars = pair.chars();
+            match (chars.next(), chars.next(), chars.next()) {
+                (Some(open), Some(close), None) => Some((open, close)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn parse_checks(checks: Vec<String>) -> Result<Vec<String>, String> {
+    match checks.iter().find(|x| !CHECKS.contains(&x.as_str())) {
+        Some(unknown) => Err(format!(
+            "Unknown punctuation check '{}' (expected one of {})",
+            unknown,
+            CHECKS.join(", ")
+        )),
+        None => Ok(checks),
+    }
+}
+
+/// Configuration for `divvun::punct`.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PunctConfig {
+    /// Locales for titles and descriptions, in priority order.
+    #[serde(default)]
+    pub locales: Option<Vec<String>>,
+    /// Offsets of text input: "utf-8" (default) or "utf-16". Output of
+    /// `divvun::suggest` keeps its own encoding.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// "offset" (default) or "linecol", as for `divvun::suggest`. Errors get
+    /// positions anyway when the errors of the input have them.
+    #[serde(default)]
+    pub positions: Option<String>,
+    /// Error IDs of the checks to run. Overrides the `checks` argument.
+    #[serde(default)]
+    pub checks: Option<Vec<String>>,
+}
+
+/// Whitespace and punctuation checks, adding errors to the output of
+/// `divvun::suggest` or checking plain text
+#[derive(facet::Facet)]
+pub struct Punct {
+    #[facet(opaque)]
+    conventions: Conventions,
+    checks: Vec<String>,
+    #[facet(opaque)]
+    fluent_loader: FluentLoader,
+    #[facet(opaque)]
+    policies: Arc<IndexMap<String, ErrorPolicy>>,
+}
+
+#[rt_command(
+    module = "divvun",
+    name = "punct",
+    input = [String, Json],
+    output = "Json",
+    args = [language? = "String", quotes? = "ArrayString", no_space_before? = "String", sentence_final? = "String", min_words? = "Int", checks? = "ArrayString"],
+    kind = "suggest",
+    schema = "GrammarOutput",
+    config = "PunctConfig",
+    assets = [optional("policies.json"), optional("errors-*.ftl")]
+)]
+impl Punct {
+    pub async fn new(
+        context: Arc<Context>,
+        mut kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        let mut string_arg = |name: &str| {
+            kwargs
+                .remove(name)
+                .and_then(|x| x.value)
+                .and_then(|x| x.try_as_string())
+        };
+
+        let mut conventions =
+            Conventions::for_language(&string_arg("language").unwrap_or_default());
+        if let Some(chars) = string_arg("no_space_before") {
+            conventions.no_space_before = chars.chars().collect();
+        }
+        if let Some(chars) = string_arg("sentence_final") {
+            if chars.is_empty() {
+                return Err(Error::msg("sentence_final must not be empty")
+                    .at("pipeline.json", "/args/sentence_final"));
+            }
+            conventions.sentence_final = chars.chars().collect();
+        }
+        if let Some(pairs) = kwargs
+            .remove("quotes")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_array_string())
+        {
+            conventions.quotes = parse_quotes(&pairs).ok_or_else(|| {
+                Error::msg("quotes must be pairs of an opening and a closing mark, e.g. \"«»\"")
+                    .at("pipeline.json", "/args/quotes")
+            })?;
+        }
+        if let Some(min_words) = kwargs
+            .remove("min_words")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_int())
+        {
+            conventions.min_words = usize::try_from(min_words).map_err(|_| {
+                Error::msg("min_words must not be negative").at("pipeline.json", "/args/min_words")
+            })?;
+        }
+        let checks = match kwargs
+            .remove("checks")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_array_string())
+        {
+            Some(checks) => parse_checks(checks)
+                .map_err(|e| Error::msg(e).at("pipeline.json", "/args/checks"))?,
+            None => CHECKS.iter().map(|x| x.to_string()).collect(),
+        };
+
+        let fluent_loader = FluentLoader::new(context.clone(), "errors-*.ftl", "en").await?;
+        let policies = Arc::new(load_error_policies(&context).await?);
+
+        Ok(Arc::new(Self {
+            conventions,
+            checks,
+            fluent_loader,
+            policies,
+        }) as _)
+    }
+}
+
+#[async_trait]
+impl CommandRunner for Punct {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let config: PunctConfig = serde_json::from_value((*config).clone()).unwrap_or_default();
+        let checks = match config.checks.clone() {
+            Some(checks) => parse_checks(checks).map_err(Error::msg)?,
+            None => self.checks.clone(),
+        };
+
+        let mut output = match input {
+            PipelineValue::Json(json) => {
+                serde_json::from_value::<GrammarOutput>(json).map_err(|e| {
+                    Error::msg(format!(
+                        "divvun::punct expects text or divvun::suggest output: {}",
+                        e
+                    ))
+                })?
+            }
+            input => GrammarOutput {
+                text: input.try_into_string()?,
+                errors: vec![],
+                encoding: config.encoding.clone().unwrap_or_else(|| "utf-8".into()),
+                timed_out: false,
+                suppressed: None,
+                warnings: None,
+            },
+        };
+
+        let line_col = config.positions.as_deref() == Some("linecol")
+            || output.errors.iter().any(|x| x.position.is_some());
+        let locales = config.locales.clone().unwrap_or_default();
+        let locales = locales.iter().map(String::as_str).collect::<Vec<_>>();
+
+        let text = output.text.clone();
+        for mistake in find_mistakes(&text, &self.conventions, &checks) {
+            let mut err = self.describe(&text, mistake, &locales);
+            if line_col {
+                err.position = Some(LineCol::of(&text, err.start, err.end));
+            }
+            if output.encoding == "utf-16" {
+                err = err.into_utf16(&text);
+            }
+            let seen = output
+                .errors
+                .iter()
+                .any(|x| x.start == err.start && x.end == err.end && x.error_id == err.error_id);
+            if !seen {
+                output.errors.push(err);
+            }
+        }
+        output.errors.sort_by_key(|x| (x.start, x.end));
+
+        let json = serde_json::to_value(output).map_err(|e| {
+            Error::msg(format!("Failed to serialize divvun::punct output: {}", e))
+                .at_path("/output")
+        })?;
+        Ok(PipelineValue::Json(json).into())
+    }
+
+    fn name(&self) -> &'static str {
+        "divvun::punct"
+    }
+}
+
+impl Punct {
+    fn describe(&self, text: &str, mistake: Mistake, locales: &[&str]) -> GrammarErr {
+        let form = &text[mistake.start..mistake.end];
+        let mut args = FluentArgs::new();
+        args.set("1", form.to_string());
+
+        let (title, description) = self
+            .fluent_loader
+            .get_message_localized(locales, mistake.error_id, Some(&args))
+            .unwrap_or_else(|| {
+                let (title, description) = default_message(mistake.error_id);
+                (title.to_string(), description.to_string())
+            });
+
+        let mut suggestions = vec![mistake.suggestion];
+        let policy = self.policies.get(mistake.error_id);
+        if policy.is_some_and(|x| !x.generate_suggestions || x.max_rep == Some(0)) {
+            suggestions.clear();
+        }
+        GrammarErr {
+            form: form.to_string(),
+            start: mistake.start,
+            end: mistake.end,
+            error_id: mistake.error_id.to_string(),
+            title,
+            description,
+            autofix: policy.is_some_and(|x| x.autofix) && suggestions.len() == 1,
+            suggestions,
+            severity: policy.and_then(|x| x.severity.clone()),
+            url: self.fluent_loader.get_attribute_localized(
+                locales,
+                mistake.error_id,
+                "url",
+                Some(&args),
+            ),
+            relations: None,
+            position: None,
+            truncated: false,
+        }
+    }
+}
+
+/// A byte range of the text and what should replace it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mistake {
+    start: usize,
+    end: usize,
+    error_id: &'static str,
+    suggestion: String,
+}
+
+fn find_mistakes(text: &str, conventions: &Conventions, checks: &[String]) -> Vec<Mistake> {
+    let enabled = |id: &str| checks.iter().any(|x| x == id);
+    let mut mistakes = Vec::new();
+    if enabled("double-space") {
+        let skip_punct = enabled("space-before-punct");
+        double_spaces(text, conventions, skip_punct, &mut mistakes);
+    }
+    if enabled("space-before-punct") {
+        spaces_before_punct(text, conventions, &mut mistakes);
+    }
+    if enabled("quotation-marks") {
+        quotation_marks(text, conventions, &mut mistakes);
+    }
+    if enabled("final-punct") {
+        final_punct(text, conventions, &mut mistakes);
+    }
+    mistakes.sort_by_key(|x| (x.start, x.end));
+    mistakes
+}
+
+/// Runs of spaces between words. Spaces before punctuation are left to the
+/// `space-before-punct` check when `skip_punct` is set.
+fn double_spaces(text: &str, conventions: &Conventions, skip_punct: bool, out: &mut Vec<Mistake>) {
+    let bytes = text.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b' ' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && bytes[i] == b' ' {
+            i += 1;
+        }
+        let before = text[..start].chars().next_back();
+        let after = text[i..].chars().next();
+        let (Some(before), Some(after)) = (before, after) else {
+            continue;
+        };
+        if i - start < 2 || before.is_whitespace() || after.is_whitespace() {
+            continue;
+        }
+        if skip_punct && conventions.no_space_before.contains(&after) {
+            continue;
+        }
+        out.push(Mistake {
+            start,
+            end: i,
+            error_id: "double-space",
+            suggestion: " ".to_string(),
+        });
+    }
+}
+
+/// Spaces between a word and punctuation that follows it directly, such as
+/// `word ,`. Punctuation followed by a letter or digit, as in `.5` or `:)`,
+/// is left alone.
+fn spaces_before_punct(text: &str, conventions: &Conventions, out: &mut Vec<Mistake>) {
+    for (i, c) in text.char_indices() {
+        if !conventions.no_space_before.contains(&c) {
+            continue;
+        }
+        let end = i + c.len_utf8();
+        if text[end..]
+            .chars()
+            .next()
+            .is_some_and(|x| x.is_alphanumeric() || x == c)
+        {
+            continue;
+        }
+        let word = text[..i].trim_end_matches([' ', '\t', '\u{a0}', '\u{202f}']);
+        if word.len() == i || !word.ends_with(|x: char| !x.is_whitespace()) {
+            continue;
+        }
+        out.push(Mistake {
+            start: word.len(),
+            end,
+            error_id: "space-before-punct",
+            suggestion: c.to_string(),
+        });
+    }
+}
+
+/// Double quotation marks that aren't an opening or closing mark of the
+/// language where they stand. A mark opens a quote at the start of the text,
+/// after whitespace or after an opening bracket or dash.
+fn quotation_marks(text: &str, conventions: &Conventions, out: &mut Vec<Mistake>) {
+    let Some(&(open, close)) = conventions.quotes.first() else {
+        return;
+    };
+    for (i, c) in text.char_indices() {
+        if !DOUBLE_QUOTES.contains(&c) {
+            continue;
+        }
+        let opening = text[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|x| x.is_whitespace() || QUOTE_OPENERS.contains(&x));
+        let accepted = conventions
+            .quotes
+            .iter()
+            .any(|&(o, cl)| if opening { c == o } else { c == cl });
+        if !accepted {
+            out.push(Mistake {
+                start: i,
+                end: i + c.len_utf8(),
+                error_id: "quotation-marks",
+                suggestion: if opening { open } else { close }.to_string(),
+            });
+        }
+    }
+}
+
+/// Paragraphs of at least `min_words` words whose last word isn't followed
+/// by sentence-final punctuation. Paragraphs ending in `:` introduce a list
+/// and are left alone.
+fn final_punct(text: &str, conventions: &Conventions, out: &mut Vec<Mistake>) {
+    let Some(&mark) = conventions.sentence_final.first() else {
+        return;
+    };
+    for (start, end) in paragraphs(text) {
+        let paragraph = &text[start..end];
+        if paragraph.split_whitespace().count() < conventions.min_words.max(1) {
+            continue;
+        }
+        let last = paragraph
+            .trim_end_matches(TRAILING_CLOSERS)
+            .chars()
+            .next_back();
+        if last.is_some_and(|x| x == ':' || conventions.sentence_final.contains(&x)) {
+            continue;
+        }
+        let word_start = paragraph
+            .char_indices()
+            .rev()
+            .find(|(_, x)| x.is_whitespace())
+            .map_or(0, |(i, x)| i + x.len_utf8());
+        let word = &paragraph[word_start..];
+        out.push(Mistake {
+            start: start + word_start,
+            end,
+            error_id: "final-punct",
+            suggestion: format!("{}{}", word, mark),
+        });
+    }
+}
+
+/// Byte ranges of the paragraphs of `text`, without surrounding whitespace.
+/// Paragraphs are separated by blank lines.
+fn paragraphs(text: &str) -> Vec<(usize, usize)> {
+    let mut paragraphs = Vec::new();
+    let mut current: Option<(usize, usize)> = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            paragraphs.extend(current.take());
+        } else {
+            let start = offset + (line.len() - line.trim_start().len());
+            let end = offset + line.trim_end().len();
+            current = Some((current.map_or(start, |(s, _)| s), end));
+        }
+        offset += line.len();
+    }
+    paragraphs.extend(current);
+    paragraphs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_checks() -> Vec<String> {
+        CHECKS.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn mistakes(text: &str, language: &str) -> Vec<(&'static str, String, String)> {
+        find_mistakes(text, &Conventions::for_language(language), &all_checks())
+            .into_iter()
+            .map(|x| (x.error_id, text[x.start..x.end].to_string(), x.suggestion))
+            .collect()
+    }
+
+    #[test]
+    fn finds_spacing_mistakes() {
+        assert_eq!(
+            mistakes("Mun  boađán ihttin , jus sáhtán.", "se"),
+            [
+                ("double-space", "  ".into(), " ".into()),
+                ("space-before-punct", " ,".into(), ",".into()),
+            ]
+        );
+        // Indentation, line breaks, decimals and ellipses are fine
+        assert!(mistakes("  Mun boađán .5 ... ihttin.\n\n  Dat lea buorre.", "se").is_empty());
+        // French puts a space before ; : ! and ?
+        assert!(mistakes("Il arrive demain !", "fr").is_empty());
+        assert_eq!(mistakes("Il arrive demain !", "nb").len(), 1);
+    }
+
+    #[test]
+    fn checks_quotation_marks_of_the_language() {
+        assert_eq!(
+            mistakes("Son celkkii \"boađe\" munnje.", "se"),
+            [
+                ("quotation-marks", "\"".into(), "”".into()),
+                ("quotation-marks", "\"".into(), "”".into()),
+            ]
+        );
+        assert!(mistakes("Son celkkii «boađe» munnje.", "se").is_empty());
+        assert_eq!(
+            mistakes("Han sa “kom” til meg.", "nb"),
+            [
+                ("quotation-marks", "“".into(), "«".into()),
+                ("quotation-marks", "”".into(), "»".into()),
+            ]
+        );
+        // Without conventions for the language, quotes aren't checked
+        assert!(mistakes("He said \"come\" to me.", "xx").is_empty());
+    }
+
+    #[test]
+    fn finds_paragraphs_without_final_punctuation() {
+        let text = "Sámegiela giellaoahppa\n\nMun boađán ihttin jus sáhtán\n\nDat lea buorre, celkkii son.\n";
+        assert_eq!(
+            mistakes(text, "se"),
+            [("final-punct", "sáhtán".into(), "sáhtán.".into())]
+        );
+        assert!(mistakes("Dát leat mu ustibat:\n", "se").is_empty());
+        assert!(mistakes("Son celkkii ”boađe deike!”", "se").is_empty());
+        assert_eq!(paragraphs(" a\nb \n \n\nc"), [(1, 5), (9, 10)]);
+    }
+}
@@ -1,11 +1,15 @@
 use super::super::{CommandRunner, Context, PipelineValue, PipelineValues};
+use super::casing::{get_casing, with_casing};
+use super::invisible::{TextWarning, text_warnings};
+use super::postprocess::Postprocess;
 use crate::modules::cg3;
+use crate::modules::hfst::{Lookup, LookupConfig};
+use crate::util::privacy::redact;
 use crate::{ast, modules::Error, util::fluent_loader::FluentLoader};
 use async_trait::async_trait;
 use divvun_runtime_macros::{rt_command, rt_struct};
 use fluent_bundle::FluentArgs;
-use hfst::hfst_transducer::AnyTransducer;
-use indexmap::IndexMap;
+use indexmap::{IndexMap, IndexSet};
 use once_cell::sync::Lazy;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -15,7 +19,8 @@ use std::io::Write;
 use std::ops::Deref;
 use std::{
     collections::{BTreeMap, HashMap},
-    sync::{Arc, Mutex},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 fn encode_unicode_identifier(s: &str) -> String {
@@ -47,12 +52,16 @@ fn encode_unicode_identifier(s: &str) -> String {
 struct ErrorJsonEntry {
     id: Option<String>,
     re: Option<String>,
+    url: Option<String>,
 }
 
-async fn load_error_mappings(context: &Arc<Context>) -> Result<IndexMap<String, Vec<Id>>, Error> {
+/// Error IDs by errors.json key, and the documentation URLs some keys have.
+type ErrorMappings = (IndexMap<String, Vec<Id>>, IndexMap<String, String>);
+
+async fn load_error_mappings(context: &Arc<Context>) -> Result<ErrorMappings, Error> {
     let Some(content) = context.load_file_optional("errors.json").await? else {
         tracing::debug!("No errors.json found, using empty error mappings");
-        return Ok(IndexMap::new());
+        return Ok(Default::default());
     };
 
     let raw_mappings: IndexMap<String, Vec<ErrorJsonEntry>> = serde_json::from_slice(&content)
@@ -61,10 +70,14 @@ async fn load_error_mappings(context: &Arc<Context>) -> Result<IndexMap<String,
         })?;
 
     let mut mappings = IndexMap::new();
+    let mut urls = IndexMap::new();
 
     for (key, entries) in raw_mappings {
         let mut ids = Vec::new();
         for entry in entries {
+            if let Some(url) = entry.url {
+                urls.entry(key.clone()).or_insert(url);
+            }
             if let Some(explicit_id) = entry.id {
                 ids.push(Id::Explicit(explicit_id));
             } else if let Some(regex_pattern) = entry.re {
@@ -86,7 +99,49 @@ async fn load_error_mappings(context: &Arc<Context>) -> Result<IndexMap<String,
     }
 
     tracing::debug!("Loaded {} error mappings from errors.json", mappings.len());
-    Ok(mappings)
+    Ok((mappings, urls))
+}
+
+/// How errors of one type are reported, from the bundle's `policies.json`,
+/// keyed by error ID (the errors.json key, or the bare CG3 tag if unmapped).
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ErrorPolicy {
+    /// Offer replacements at all; off for style hints.
+    #[serde(default = "default_true")]
+    pub(super) generate_suggestions: bool,
+    /// Upper limit on the number of suggestions.
+    #[serde(default)]
+    pub(super) max_rep: Option<usize>,
+    /// Passed through to the error's `severity`, e.g. "error" or "hint".
+    #[serde(default)]
+    pub(super) severity: Option<String>,
+    /// Errors of this type with a single suggestion are safe to correct
+    /// without asking.
+    #[serde(default)]
+    pub(super) autofix: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+pub(super) async fn load_error_policies(
+    context: &Arc<Context>,
+) -> Result<IndexMap<String, ErrorPolicy>, Error> {
+    let Some(content) = context.load_file_optional("policies.json").await? else {
+        return Ok(IndexMap::new());
+    };
+
+    let policies: IndexMap<String, ErrorPolicy> =
+        serde_json::from_slice(&content).map_err(|e| {
+            Error::msg(format!("Failed to parse policies.json: {}", e)).at_file("policies.json")
+        })?;
+    tracing::debug!(
+        "Loaded {} error policies from policies.json",
+        policies.len()
+    );
+    Ok(policies)
 }
 
 #[derive(Debug, Clone)]
@@ -139,6 +194,74 @@ pub struct SuggestConfig {
     /// (the VISL CG3 stream with generated suggestions appended, #29).
     #[serde(default)]
     pub format: Option<String>,
+    /// Time budget in milliseconds for generating suggestions for each
+    /// sentence. Once it runs out, the sentence's later cohorts get no
+    /// suggestions or errors and the output is marked `timed_out`, instead of
+    /// stalling the stream. The next sentence starts with the full budget.
+    #[serde(default)]
+    pub time_budget_ms: Option<u64>,
+    /// "offset" (default) or "linecol", which adds 1-based line and column
+    /// numbers to every error for tools that point at source lines.
+    #[serde(default)]
+    pub positions: Option<String>,
+    /// Add a `relations` list to each error describing the cohorts linked to
+    /// it by relations (LEFT/RIGHT/DELETE, message template `$N`), and which
+    /// of them carry the error as a COERROR.
+    #[serde(default)]
+    pub relations: Option<bool>,
+    /// How to split the input into units that errors are reported for:
+    /// "input" (each input as a whole), "sentence" (after a CLB cohort whose
+    /// form is one of `delimiters`) or "paragraph" (at blank lines).
+    /// Overrides the `flush_mode` argument.
+    #[serde(default)]
+    pub flush_mode: Option<String>,
+    /// Sentence-ending forms for `flush_mode: "sentence"`. Overrides the
+    /// `delimiters` argument; the default is `.`, `?` and `!`.
+    #[serde(default)]
+    pub delimiters: Option<Vec<String>>,
+    /// Split after at most this many cohorts when segmenting, however long the
+    /// sentence. Overrides the `hard_limit` argument; the default is 1000.
+    #[serde(default)]
+    pub hard_limit: Option<usize>,
+    /// Add a `suppressed` list to the output, counting the errors left out by
+    /// `ignore` and by what reason.
+    #[serde(default)]
+    pub report_suppressed: Option<bool>,
+    /// Add a `warnings` list to the output for characters that may keep the
+    /// text from being checked as it reads: zero-width characters, bidi
+    /// controls and words mixing Latin with Cyrillic or Greek letters.
+    #[serde(default)]
+    pub report_warnings: Option<bool>,
+    /// Keep at most this many partial replacements while building the
+    /// suggestions of one error, cohort by cohort; the default is 256.
+    #[serde(default)]
+    pub max_replacements: Option<usize>,
+    /// Underline at most this many cohorts around the error cohort, however
+    /// far its relations reach; the default is 64.
+    #[serde(default)]
+    pub max_underline_cohorts: Option<usize>,
+    /// Regions of the input not to report errors in, such as code blocks,
+    /// quotations or a selection the user turned checking off for, with
+    /// offsets in the units of `encoding`. Errors overlapping one are left
+    /// out. JSON output only.
+    #[serde(default)]
+    pub nocheck: Option<Vec<NocheckRange>>,
+}
+
+/// A region of the input `suggest` reports no errors in.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NocheckRange {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl NocheckRange {
+    /// Whether the error at `start..end` touches this region. An error
+    /// without a width counts if it is inside.
+    pub fn covers(&self, start: usize, end: usize) -> bool {
+        start < self.end && self.start < end.max(start + 1)
+    }
 }
 
 /// Grammar and spelling suggestion for text
@@ -147,11 +270,19 @@ pub struct Suggest {
     #[facet(opaque)]
     _context: Arc<Context>,
     #[facet(opaque)]
-    generator: Arc<Mutex<AnyTransducer>>,
+    generator: Arc<Lookup>,
     #[facet(opaque)]
     fluent_loader: FluentLoader,
     #[facet(opaque)]
     error_mappings: Arc<IndexMap<String, Vec<Id>>>,
+    #[facet(opaque)]
+    error_urls: Arc<IndexMap<String, String>>,
+    #[facet(opaque)]
+    policies: Arc<IndexMap<String, ErrorPolicy>>,
+    #[facet(opaque)]
+    segmentation: Segmentation,
+    #[facet(opaque)]
+    postprocess: Option<Arc<Postprocess>>,
 }
 
 #[rt_command(
@@ -159,14 +290,12 @@ pub struct Suggest {
     name = "suggest",
     input = [String],
     output = "Json",
-    args = [model_path = "Path"],
+    args = [model_path = "Path", flush_mode? = "String", delimiters? = "ArrayString", hard_limit? = "Int", lookup? = "LookupConfig", postprocess? = "Path"],
     kind = "suggest",
     schema = "GrammarOutput",
     config = "SuggestConfig",
-    // assets = [
-    //     required("errors.json"),
-    //     required(r"errors-.*\.ftl")
-    // ]
+    assets = [optional("errors.json"), optional("policies.json"), optional("errors-*.ftl")],
+    resources = [memory = "64M"]
 )]
 impl Suggest {
     pub async fn new(
@@ -182,19 +311,85 @@ impl Suggest {
                 Error::msg("model_path missing").at("pipeline.json", "/args/model_path")
             })?;
 
-        let generator = Arc::new(crate::modules::hfst::load_lookup(&context, &model_path).await?);
+        let mut segmentation = Segmentation::default();
+        if let Some(mode) = kwargs
+            .remove("flush_mode")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_string())
+        {
+            segmentation.flush_on =
+                FlushOn::parse(&mode).map_err(|e| e.at("pipeline.json", "/args/flush_mode"))?;
+        }
+        if let Some(delimiters) = kwargs
+            .remove("delimiters")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_array_string())
+        {
+            segmentation.delimiters = delimiters.into_iter().collect();
+        }
+        if let Some(limit) = kwargs
+            .remove("hard_limit")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_int())
+        {
+            segmentation.hard_limit =
+                usize::try_from(limit)
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or_else(|| {
+                        Error::msg("hard_limit must be at least 1")
+                            .at("pipeline.json", "/args/hard_limit")
+                    })?;
+        }
+
+        let postprocess = match kwargs
+            .remove("postprocess")
+            .and_then(|x| x.value)
+            .and_then(|x| x.try_as_string())
+        {
+            Some(path) => Some(Arc::new(Postprocess::load(&context, &path).await?)),
+            None => None,
+        };
+
+        let lookup = LookupConfig::from_kwargs(&kwargs)?;
+        let generator =
+            Arc::new(crate::modules::hfst::load_lookup(&context, &model_path, &lookup).await?);
 
         // Always use errors-*.ftl pattern for loading Fluent files
         let fluent_loader = FluentLoader::new(context.clone(), "errors-*.ftl", "en").await?;
 
         // Load error mappings from errors.json
-        let error_mappings = Arc::new(load_error_mappings(&context).await?);
+        let (error_mappings, error_urls) = load_error_mappings(&context).await?;
+        let policies = Arc::new(load_error_policies(&context).await?);
 
         Ok(Arc::new(Self {
             _context: context,
             generator,
             fluent_loader,
-            error_mappings,
+            error_mappings: Arc::new(error_mappings),
+            error_urls: Arc::new(error_urls),
+            policies,
+            segmentation,
+            postprocess,
+        }) as _)
+    }
+
+    /// A suggester generating from `results` only, with no error messages,
+    /// for running the command in tests without a model.
+    #[cfg(test)]
+    pub(crate) fn canned(
+        context: Arc<Context>,
+        results: impl IntoIterator<Item = (String, Vec<String>)>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        Ok(Arc::new(Self {
+            _context: context,
+            generator: Arc::new(Lookup::canned(results)),
+            fluent_loader: FluentLoader::from_sources(std::iter::empty(), "en")?,
+            error_mappings: Default::default(),
+            error_urls: Default::default(),
+            policies: Default::default(),
+            segmentation: Segmentation::default(),
+            postprocess: None,
         }) as _)
     }
 
@@ -241,11 +436,32 @@ impl CommandRunner for Suggest {
         let fluent_loader = self.fluent_loader.clone();
         let generator = self.generator.clone();
         let error_mappings = self.error_mappings.clone();
+        let error_urls = self.error_urls.clone();
+        let policies = self.policies.clone();
+        let postprocess = self.postprocess.clone();
+        let segmentation = self.segmentation.with_config(&config)?;
         let encoding = config.encoding.clone();
         let ignore_tags = config.ignore.clone();
         let cg_output = config.format.as_deref() == Some("cg");
+        let time_budget = config.time_budget_ms.map(Duration::from_millis);
+        let relations = config.relations.unwrap_or(false);
+        let report_suppressed = config.report_suppressed.unwrap_or(false);
+        let report_warnings = config.report_warnings.unwrap_or(false);
+        let limits = ReplacementLimits::default().with_config(&config)?;
+        let nocheck = config.nocheck.clone().unwrap_or_default();
+        let line_col = match config.positions.as_deref() {
+            None | Some("offset") => false,
+            Some("linecol") => true,
+            Some(other) => {
+                return Err(Error::msg(format!(
+                    "unknown positions '{}', expected \"offset\" or \"linecol\"",
+                    other
+                ))
+                .at_path("/config/positions"));
+            }
+        };
 
-        let output = tokio::task::spawn_blocking(move || {
+        let output = crate::util::priority::spawn_blocking(move || {
             let ignores = if let Some(ignore_list) = ignore_tags {
                 let ignore_tags = ignore_list
                     .iter()
@@ -265,23 +481,41 @@ impl CommandRunner for Suggest {
                 error_mappings,
                 ignores.map(IdSet),
                 None,
-            );
+            )
+            .with_time_budget(time_budget)
+            .with_relations(relations)
+            .with_report_suppressed(report_suppressed)
+            .with_report_warnings(report_warnings)
+            .with_replacement_limits(limits)
+            .with_nocheck(nocheck)
+            .with_line_col(line_col)
+            .with_policies(policies)
+            .with_urls(error_urls)
+            .with_postprocess(postprocess)
+            .with_segmentation(segmentation);
 
             if cg_output {
-                SuggestOutput::Cg(suggester.run_cg(&input))
+                suggester.run_cg(&input).map(SuggestOutput::Cg)
             } else {
-                SuggestOutput::Json(suggester.run(&input, encoding.as_deref()))
+                Ok(SuggestOutput::Json(
+                    suggester.run(&input, encoding.as_deref()),
+                ))
             }
         })
         .await
-        .unwrap();
+        .map_err(|e| Error::msg(format!("divvun::suggest worker failed: {}", e)))??;
+
+        let stats = self.generator.stats();
+        tracing::debug!(
+            "generator lookups: {} cached, {} looked up, {} cache entries",
+            stats.hits,
+            stats.misses,
+            stats.entries
+        );
 
         match output {
             SuggestOutput::Cg(s) => Ok(s.into()),
-            SuggestOutput::Json(go) => {
-                let value: serde_json::Value = serde_json::to_value(go).unwrap();
-                Ok(value.into())
-            }
+            SuggestOutput::Json(go) => Ok(output_to_json(go)?.into()),
         }
     }
 
@@ -290,21 +524,31 @@ impl CommandRunner for Suggest {
     }
 }
 
+fn output_to_json(output: GrammarOutput) -> Result<serde_json::Value, Error> {
+    serde_json::to_value(output).map_err(|e| {
+        Error::msg(format!("Failed to serialize divvun::suggest output: {}", e))
+            .at_path("/output")
+    })
+}
+
+#[allow(clippy::unwrap_used)]
 static DELETE_REL: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^DELETE[0-9]*$"#).unwrap());
 
+#[allow(clippy::unwrap_used)]
 static LEFT_RIGHT_DELETE_REL: Lazy<Regex> =
     Lazy::new(|| Regex::new(r#"^(LEFT|RIGHT|DELETE[0-9]*)$"#).unwrap());
 
 // Relation names that fill numbered message-template placeholders ($2, $3, ...).
 // $1 is always the error cohort's own form.
+#[allow(clippy::unwrap_used)]
 static MSG_TEMPLATE_REL: Lazy<Regex> = Lazy::new(|| Regex::new(r#"^\$[0-9]+$"#).unwrap());
 
 #[derive(Debug, Default, Clone)]
 struct Reading {
     suggest: bool,
-    ana: String,                 // for generating suggestions from this reading
-    errtypes: HashSet<String>,   // the error tag(s) (without leading ampersand)
-    coerrtypes: HashSet<String>, // the COERROR error tag(s) (without leading ampersand)
+    ana: String,                  // for generating suggestions from this reading
+    errtypes: IndexSet<String>,   // the error tag(s) (without leading ampersand), in stream order
+    coerrtypes: IndexSet<String>, // the COERROR error tag(s) (without leading ampersand)
     sforms: Vec<String>,
     rels: HashMap<String, u32>, // rels[relname] = target.id
     id: u32,                    // id is 0 if unset, otherwise the relation id of this word
@@ -323,8 +567,8 @@ struct Cohort {
     pos: usize, // position in text
     id: u32,    // CG relation id
     readings: Vec<Reading>,
-    errtypes: HashSet<String>, // the error tag(s) of all readings (without leading ampersand)
-    coerrtypes: HashSet<String>, // the COERROR error tag(s) of all readings (without leading ampersand)
+    errtypes: IndexSet<String>, // the error tag(s) of all readings (without leading ampersand), in stream order
+    coerrtypes: IndexSet<String>, // the COERROR error tag(s) of all readings (without leading ampersand)
     added: AddedStatus,
     raw_pre_blank: String, // blank before cohort, in CG stream format (initial colon, brackets, escaped newlines)
     errs: Vec<GrammarErr>,
@@ -360,7 +604,7 @@ fn proc_subreading(reading: &cg3::Reading, generate_all_readings: bool) -> Readi
     let mut delete_self = false; // may be changed by DELETE tag
 
     for tag in reading.tags.iter() {
-        tracing::debug!("Processing tag: {}", tag);
+        tracing::debug!("Processing tag: {}", redact(tag));
         if *tag == "&LINK" || *tag == "&COERROR" || *tag == "COERROR" {
             // &LINK and COERROR kept for backward-compatibility
             r.coerror = true;
@@ -467,7 +711,7 @@ fn proc_subreading(reading: &cg3::Reading, generate_all_readings: bool) -> Readi
 
     let tagsplus = gentags.join("+");
     r.ana = format!("{}+{}", reading.base_form, tagsplus);
-    tracing::debug!("Built analysis: {}", r.ana);
+    tracing::debug!("Built analysis: {}", redact(&r.ana));
     tracing::debug!("r.suggest = {}, r.suggestwf = {}", r.suggest, r.suggestwf);
     if r.suggestwf {
         r.sforms.push(r.wf.clone());
@@ -476,9 +720,11 @@ fn proc_subreading(reading: &cg3::Reading, generate_all_readings: bool) -> Readi
 }
 
 fn proc_reading(
-    generator: &Mutex<AnyTransducer>,
+    generator: &Lookup,
     cohort: &cg3::Cohort,
     generate_all_readings: bool,
+    generate: bool,
+    suggests: impl Fn(&str) -> bool,
 ) -> Reading {
     let mut subs = Vec::new();
     for reading in &cohort.readings {
@@ -518,17 +764,22 @@ fn proc_reading(
 
     // HashMap naturally deduplicates by key, so no explicit dedupe needed
 
+    // Forms are only wasted on errors whose policies offer no suggestions
+    let generate = generate
+        && (r.errtypes.is_empty() && r.coerrtypes.is_empty()
+            || r.errtypes.iter().chain(&r.coerrtypes).any(|x| suggests(x)));
+
     // Generate suggestions from each analysis group that carries suggest=true.
     // Grouping + compound assembly (#31) is shared with the CG output via
     // `group_readings` / `generate_group`.
     for group in group_readings(cohort) {
-        if !group.iter().any(|&i| subs[i].suggest) {
+        if !generate || !group.iter().any(|&i| subs[i].suggest) {
             continue;
         }
         let (ana, paths) = generate_group(generator, cohort, &subs, &group);
         tracing::debug!(
             "Generating suggestions for analysis {}: {} path(s)",
-            ana,
+            redact(&ana),
             paths.len()
         );
         for path in paths {
@@ -546,13 +797,12 @@ fn proc_reading(
 /// Group a cohort's flat, depth-tagged readings into analyses: a depth-1
 /// reading starts a new analysis, deeper readings are its compound parts.
 /// Returns indices into `cohort.readings` (parallel to the `subs` vector).
-fn group_readings(cohort: &cg3::Cohort) -> Vec<Vec<usize>> {
+pub(super) fn group_readings(cohort: &cg3::Cohort) -> Vec<Vec<usize>> {
     let mut groups: Vec<Vec<usize>> = Vec::new();
     for (i, reading) in cohort.readings.iter().enumerate() {
-        if reading.depth <= 1 || groups.is_empty() {
-            groups.push(vec![i]);
-        } else {
-            groups.last_mut().unwrap().push(i);
+        match groups.last_mut() {
+            Some(group) if reading.depth > 1 => group.push(i),
+            _ => groups.push(vec![i]),
         }
     }
     groups
@@ -562,7 +812,7 @@ fn group_readings(cohort: &cg3::Cohort) -> Vec<Vec<usize>> {
 /// first, head last, joined with '#' — and generate its surface forms. Returns
 /// the analysis string and the generated forms (#31).
 fn generate_group(
-    generator: &Mutex<AnyTransducer>,
+    generator: &Lookup,
     cohort: &cg3::Cohort,
     subs: &[Reading],
     group: &[usize],
@@ -576,10 +826,10 @@ fn generate_group(
         .join("#");
 
     // If the analysis contains "?" (unknown), fall back to the base form only.
-    let mut paths = crate::modules::hfst::lookup_tags(generator, &ana, false);
+    let mut paths = crate::modules::hfst::generate(generator, &ana);
     if paths.is_empty() && ana.contains("+?") {
         if let Some(pos) = ana.find('+') {
-            paths = crate::modules::hfst::lookup_tags(generator, &ana[..pos], false);
+            paths = crate::modules::hfst::generate(generator, &ana[..pos]);
         }
     }
     (ana, paths)
@@ -595,13 +845,87 @@ pub struct GrammarErr {
     pub title: String,
     pub description: String,
     pub suggestions: Vec<String>,
+    /// From the error type's policy in `policies.json`, if it sets one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub severity: Option<String>,
+    /// Documentation of the error type, from the `.url` of its Fluent message
+    /// or the `url` of its errors.json entry.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    /// The error's policy allows correcting it automatically and it has
+    /// exactly one suggestion.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub autofix: bool,
+    /// Cohorts related to the error cohort, only with the `relations` config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub relations: Option<Vec<ErrorRelation>>,
+    /// Line and column of the error, only with `positions: "linecol"`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub position: Option<LineCol>,
+    /// The underline or the suggestions were cut short by
+    /// `max_underline_cohorts` or `max_replacements`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+}
+
+/// 1-based line and column of an error's start and end. Columns count
+/// grapheme clusters, so they match what an editor shows; the end is
+/// exclusive, like `end`.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct LineCol {
+    pub start_line: usize,
+    pub start_column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
+}
+
+impl LineCol {
+    /// Position of the byte range `start..end` of `text`.
+    pub(super) fn of(text: &str, start: usize, end: usize) -> Self {
+        let (start_line, start_column) = line_col(text, start);
+        let (end_line, end_column) = line_col(text, end);
+        LineCol {
+            start_line,
+            start_column,
+            end_line,
+            end_column,
+        }
+    }
+}
+
+fn line_col(text: &str, offset: usize) -> (usize, usize) {
+    use unicode_segmentation::UnicodeSegmentation as _;
+
+    let before = &text[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let line = before[..line_start].matches('\n').count() + 1;
+    let column = before[line_start..].graphemes(true).count() + 1;
+    (line, column)
+}
+
+/// A relation from the error cohort to another cohort in the sentence.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ErrorRelation {
+    /// Relation name as written in the CG rule, e.g. "LEFT" or "$2".
+    pub name: String,
+    pub form: String,
+    pub start: usize,
+    pub end: usize,
+    /// Whether the target carries the error tag as a COERROR.
+    pub coerror: bool,
 }
 
 impl GrammarErr {
     /// Create GrammarErr with UTF-16 positions
-    fn into_utf16(mut self, text: &str) -> Self {
+    pub(super) fn into_utf16(mut self, text: &str) -> Self {
         self.start = byte_to_utf16_offset(text, self.start);
         self.end = byte_to_utf16_offset(text, self.end);
+        for rel in self.relations.iter_mut().flatten() {
+            rel.start = byte_to_utf16_offset(text, rel.start);
+            rel.end = byte_to_utf16_offset(text, rel.end);
+        }
         self
     }
 }
@@ -614,15 +938,132 @@ struct Sentence {
     // runstate: RunState,
     raw_final_blank: String, // blank after last cohort, in CG stream format (initial colon, brackets, escaped newlines)
     errs: Vec<GrammarErr>,
+    timed_out_at: Option<usize>, // index of the first cohort processed after the time budget ran out
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum FlushOn {
     Nul,
     NulAndDelimiters,
+    NulAndParagraphs,
+}
+
+impl FlushOn {
+    /// Parse a `flush_mode` argument or config value.
+    fn parse(mode: &str) -> Result<FlushOn, Error> {
+        match mode {
+            "input" => Ok(FlushOn::Nul),
+            "sentence" => Ok(FlushOn::NulAndDelimiters),
+            "paragraph" => Ok(FlushOn::NulAndParagraphs),
+            other => Err(Error::msg(format!(
+                "unknown flush_mode '{}', expected \"input\", \"sentence\" or \"paragraph\"",
+                other
+            ))),
+        }
+    }
+}
+
+/// How `suggest` splits its input into the units errors are found in.
+#[derive(Debug, Clone)]
+struct Segmentation {
+    flush_on: FlushOn,
+    delimiters: HashSet<String>,
+    hard_limit: usize,
+}
+
+impl Default for Segmentation {
+    fn default() -> Self {
+        Segmentation {
+            flush_on: FlushOn::Nul,
+            delimiters: default_delimiters(),
+            hard_limit: 1000,
+        }
+    }
+}
+
+impl Segmentation {
+    /// These settings with the runtime config's overrides applied.
+    fn with_config(&self, config: &SuggestConfig) -> Result<Segmentation, Error> {
+        let mut segmentation = self.clone();
+        if let Some(mode) = &config.flush_mode {
+            segmentation.flush_on =
+                FlushOn::parse(mode).map_err(|e| e.at_path("/config/flush_mode"))?;
+        }
+        if let Some(delimiters) = &config.delimiters {
+            segmentation.delimiters = delimiters.iter().cloned().collect();
+        }
+        if let Some(limit) = config.hard_limit {
+            if limit == 0 {
+                return Err(
+                    Error::msg("hard_limit must be at least 1").at_path("/config/hard_limit")
+                );
+            }
+            segmentation.hard_limit = limit;
+        }
+        Ok(segmentation)
+    }
+}
+
+/// Bounds on building the suggestions of one error. Each cohort of an
+/// underline multiplies the partial replacements by its number of suggested
+/// forms, so a wide underline over cohorts with many of them would otherwise
+/// grow without limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReplacementLimits {
+    max_replacements: usize,
+    max_cohorts: usize,
+}
+
+impl Default for ReplacementLimits {
+    fn default() -> Self {
+        ReplacementLimits {
+            max_replacements: 256,
+            max_cohorts: 64,
+        }
+    }
+}
+
+impl ReplacementLimits {
+    /// These limits with the runtime config's overrides applied.
+    fn with_config(self, config: &SuggestConfig) -> Result<ReplacementLimits, Error> {
+        let mut limits = self;
+        if let Some(max) = config.max_replacements {
+            if max == 0 {
+                return Err(Error::msg("max_replacements must be at least 1")
+                    .at_path("/config/max_replacements"));
+            }
+            limits.max_replacements = max;
+        }
+        if let Some(max) = config.max_underline_cohorts {
+            if max == 0 {
+                return Err(Error::msg("max_underline_cohorts must be at least 1")
+                    .at_path("/config/max_underline_cohorts"));
+            }
+            limits.max_cohorts = max;
+        }
+        Ok(limits)
+    }
+
+    /// `left..=right` narrowed to at most `max_cohorts` cohorts around `i_c`,
+    /// and whether that cut anything off.
+    fn clamp(&self, left: usize, right: usize, i_c: usize) -> ((usize, usize), bool) {
+        if right - left < self.max_cohorts {
+            return ((left, right), false);
+        }
+        let before = (self.max_cohorts - 1) / 2;
+        let new_left = left.max(i_c.saturating_sub(before));
+        let new_right = right.min(new_left + self.max_cohorts - 1);
+        // Give the room not needed on the right back to the left
+        let new_left = left.max((new_right + 1).saturating_sub(self.max_cohorts));
+        ((new_left, new_right), true)
+    }
 }
 
 // Default value for Suggest.delimiters:
+fn out_of_time(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
 fn default_delimiters() -> HashSet<String> {
     crate::modules::cg3_util::default_sentence_breakers()
 }
@@ -654,6 +1095,38 @@ where
     }
 }
 
+/// The relation targets of the readings of `c` carrying `cg3_tag`, in text
+/// order. Targets that can't be found in the sentence are left out.
+fn error_relations(cg3_tag: &str, c: &Cohort, sentence: &Sentence) -> Vec<ErrorRelation> {
+    let mut relations: Vec<ErrorRelation> = Vec::new();
+    for r in c.readings.iter().filter(|r| r.errtypes.contains(cg3_tag)) {
+        for (rel_name, target_id) in &r.rels {
+            let Some(trg) = sentence
+                .ids_cohorts
+                .get(target_id)
+                .and_then(|&i_t| sentence.cohorts.get(i_t))
+            else {
+                continue;
+            };
+            if relations
+                .iter()
+                .any(|x| &x.name == rel_name && x.start == trg.pos)
+            {
+                continue;
+            }
+            relations.push(ErrorRelation {
+                name: rel_name.clone(),
+                form: trg.form.clone(),
+                start: trg.pos,
+                end: trg.pos + trg.form.len(),
+                coerror: trg.coerrtypes.contains(cg3_tag),
+            });
+        }
+    }
+    relations.sort_by(|a, b| (a.start, &a.name).cmp(&(b.start, &b.name)));
+    relations
+}
+
 /**
  * Calculate the left/right bounds of the error underline, as indices into sentence.
  */
@@ -736,7 +1209,7 @@ fn readings_with_errtype(trg: &Cohort, err_id: &str, applies_deletion: bool) ->
 fn do_delete(
     trg: &Cohort,
     err_id: &str,
-    src_errtypes: &HashSet<String>,
+    src_errtypes: &IndexSet<String>,
     deletions: &HashSet<u32>,
 ) -> bool {
     if !deletions.contains(&trg.id) {
@@ -755,8 +1228,9 @@ fn do_delete(
         }
     }
     // But what if source and target have no matching errtypes at all?
-    let trg_errtypes_w_co: HashSet<String> = trg.errtypes.union(&trg.coerrtypes).cloned().collect();
-    let errtypes_isect: HashSet<_> = trg_errtypes_w_co
+    let trg_errtypes_w_co: IndexSet<String> =
+        trg.errtypes.union(&trg.coerrtypes).cloned().collect();
+    let errtypes_isect: IndexSet<_> = trg_errtypes_w_co
         .intersection(src_errtypes)
         .cloned()
         .collect();
@@ -769,72 +1243,8 @@ fn do_delete(
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Copy)]
-enum Casing {
-    Lower,
-    Title,
-    Upper,
-    Mixed,
-}
-
-fn get_casing(input: &str) -> Casing {
-    if input.is_empty() {
-        return Casing::Mixed;
-    }
-
-    let mut seen_upper = false;
-    let mut seen_lower = false;
-    let mut fst_upper = false;
-    let mut non_fst_upper = false;
-
-    for c in input.chars() {
-        if c.is_uppercase() {
-            if seen_lower || seen_upper {
-                non_fst_upper = true;
-            } else {
-                fst_upper = true;
-            }
-            seen_upper = true;
-        }
-        if c.is_lowercase() {
-            seen_lower = true;
-        }
-    }
-
-    if !seen_upper && !seen_lower {
-        Casing::Mixed // No letters found, preserve original casing
-    } else if !seen_upper {
-        Casing::Lower
-    } else if !seen_lower {
-        Casing::Upper
-    } else if fst_upper && !non_fst_upper {
-        Casing::Title
-    } else {
-        Casing::Mixed
-    }
-}
-
-fn with_casing(fixedcase: bool, input_casing: Casing, input: &str) -> String {
-    if fixedcase {
-        return input.to_string();
-    }
-    match input_casing {
-        Casing::Title => {
-            let mut chars: Vec<char> = input.chars().collect();
-            if let Some(first_alpha_pos) = chars.iter().position(|c| c.is_alphabetic()) {
-                chars[first_alpha_pos] = chars[first_alpha_pos]
-                    .to_uppercase()
-                    .next()
-                    .unwrap_or(chars[first_alpha_pos]);
-            }
-            chars.into_iter().collect()
-        }
-        Casing::Upper => input.to_uppercase(),
-        Casing::Lower => input.to_lowercase(),
-        Casing::Mixed => input.to_string(),
-    }
-}
-
+/// The bounds and replacements of the underline `i_left..=i_right` of the
+/// error `err_id` of `src`, and whether `limits` cut either short.
 fn build_squiggle_replacement(
     r: &Reading,
     err_id: &str,
@@ -845,7 +1255,16 @@ fn build_squiggle_replacement(
     orig_end: usize,
     i_left: usize,
     i_right: usize,
-) -> Option<((usize, usize), Vec<String>)> {
+    limits: &ReplacementLimits,
+) -> Option<((usize, usize), Vec<String>, bool)> {
+    let ((i_left, i_right), mut truncated) = limits.clamp(i_left, i_right, i_c);
+    if truncated {
+        tracing::warn!(
+            "Underline of {} spans more than {} cohorts, narrowed",
+            err_id,
+            limits.max_cohorts
+        );
+    }
     let mut beg = orig_beg;
     let mut end = orig_end;
     let mut deletions = HashSet::new();
@@ -870,25 +1289,29 @@ fn build_squiggle_replacement(
     for i in i_left..=i_right {
         let trg = &sentence.cohorts[i];
         let casing = get_casing(&trg.form);
-        tracing::debug!("Form: '{}' detected casing: {:?}", trg.form, casing);
+        tracing::debug!(
+            "Form: '{}' detected casing: {:?}",
+            redact(&trg.form),
+            casing
+        );
 
         tracing::trace!("i=\t{}", i);
-        tracing::trace!("trg.form=\t'{}'", trg.form);
+        tracing::trace!("trg.form=\t'{}'", redact(&trg.form));
         tracing::trace!("trg.id=\t{}", trg.id);
-        tracing::trace!("trg.raw_pre_blank=\t'{}'", trg.raw_pre_blank);
+        tracing::trace!("trg.raw_pre_blank=\t'{}'", redact(&trg.raw_pre_blank));
 
         let mut rep_this_trg = vec![];
         let del = do_delete(trg, err_id, &src.errtypes, &deletions);
         if del {
             rep_this_trg.push(String::new());
-            tracing::trace!("\t\tdelete=\t{}", trg.form);
+            tracing::trace!("\t\tdelete=\t{}", redact(&trg.form));
         }
         let mut added_before_blank = false;
         let applies_deletion = trg.id == src.id && src_applies_deletion;
         let mut trg_beg = trg.pos;
         let mut trg_end = trg.pos + trg.form.len();
         for tr in readings_with_errtype(trg, err_id, applies_deletion) {
-            tracing::trace!("tr.line=\t{}", tr.line);
+            tracing::trace!("tr.line=\t{}", redact(&tr.line));
 
             if tr.added == AddedStatus::AddedBeforeBlank {
                 if i == 0 {
@@ -904,17 +1327,17 @@ fn build_squiggle_replacement(
                 trg_end = trg_beg;
             }
 
-            tracing::trace!("r.wf='{}'", tr.wf);
+            tracing::trace!("r.wf='{}'", redact(&tr.wf));
             tracing::trace!("r.coerror={}", tr.coerror);
             tracing::trace!("r.suggestwf={}", tr.suggestwf);
-            tracing::trace!("r.suggest={}\t{}", tr.suggest, tr.line);
+            tracing::trace!("r.suggest={}\t{}", tr.suggest, redact(&tr.line));
 
             if !del {
                 tracing::debug!("tr.sforms has {} suggestions", tr.sforms.len());
                 for sf in &tr.sforms {
                     tracing::debug!(
                         "Original suggestion: '{}', casing: {:?}, fixedcase: {}",
-                        sf,
+                        redact(sf),
                         casing,
                         tr.fixedcase
                     );
@@ -924,10 +1347,10 @@ fn build_squiggle_replacement(
                     // them back to match the input, or the case-only fix is lost (#44).
                     let form_with_casing =
                         with_casing(tr.fixedcase || tr.suggestwf, casing.clone(), sf);
-                    tracing::debug!("After casing: '{}'", form_with_casing);
+                    tracing::debug!("After casing: '{}'", redact(&form_with_casing));
                     rep_this_trg.push(form_with_casing.clone());
 
-                    tracing::trace!("\t\tsform=\t'{}'", sf);
+                    tracing::trace!("\t\tsform=\t'{}'", redact(sf));
                 }
             }
         }
@@ -947,8 +1370,8 @@ fn build_squiggle_replacement(
                     format!("{}{}{}", rep, pre_blank, trg.form.trim_end_matches('"'));
                 tracing::debug!(
                     "Trg form: '{}', Would append: '{}'",
-                    trg.form.trim_end_matches('"'),
-                    would_append
+                    redact(trg.form.trim_end_matches('"')),
+                    redact(&would_append)
                 );
 
                 let already_contains = rep.contains(&trg.form.trim_end_matches('"'));
@@ -973,6 +1396,17 @@ fn build_squiggle_replacement(
                 }
             }
         }
+        if reps_next.len() > limits.max_replacements {
+            if !truncated {
+                tracing::warn!(
+                    "Replacements of {} exceed {}, truncated",
+                    err_id,
+                    limits.max_replacements
+                );
+            }
+            reps_next.truncate(limits.max_replacements);
+            truncated = true;
+        }
         reps = reps_next;
         prev_added_before_blank = if added_before_blank {
             trg.raw_pre_blank.clone()
@@ -981,7 +1415,7 @@ fn build_squiggle_replacement(
         };
     }
 
-    tracing::debug!("Reps: {:?}", reps);
+    tracing::debug!("Reps: {}", redact(&format!("{:?}", reps)));
     for rep in &mut reps {
         *rep = rep.split_whitespace().collect::<Vec<&str>>().join(" ");
         rep.truncate(rep.trim_end().len());
@@ -990,12 +1424,12 @@ fn build_squiggle_replacement(
         rep.drain(..trim_amount);
     }
     for sf in &reps {
-        tracing::debug!("reps sf=\t'{}'\t{},{}", sf, beg, end);
+        tracing::debug!("reps sf=\t'{}'\t{},{}", redact(sf), beg, end);
     }
-    Some(((beg, end), reps))
+    Some(((beg, end), reps, truncated))
 }
 
-fn clean_blank(raw: &str) -> String {
+pub(super) fn clean_blank(raw: &str) -> String {
     let mut escaped = false;
     let mut bol = true; // at beginning of line
     let mut text = String::new();
@@ -1024,11 +1458,11 @@ fn clean_blank(raw: &str) -> String {
 
 fn demote_error_to_coerror(
     source: &Cohort,
-    target_errtypes: &mut HashSet<String>,
-    target_coerrtypes: &mut HashSet<String>,
+    target_errtypes: &mut IndexSet<String>,
+    target_coerrtypes: &mut IndexSet<String>,
 ) {
     for errtype in &source.errtypes {
-        if target_errtypes.remove(errtype) {
+        if target_errtypes.shift_remove(errtype) {
             target_coerrtypes.insert(errtype.clone());
         }
     }
@@ -1038,8 +1472,9 @@ fn expand_errs(errs: &mut Vec<GrammarErr>, text: &str) {
     if errs.len() < 2 {
         return;
     }
-    // First expand "backwards" towards errors with lower beg's:
-    errs.sort_unstable_by_key(|e| e.start);
+    // First expand "backwards" towards errors with lower beg's. The sorts are
+    // stable so errors on the same span keep their order.
+    errs.sort_by_key(|e| e.start);
     for i in 1..errs.len() {
         let (left, right) = errs.split_at_mut(i);
         let e = &mut right[0];
@@ -1056,7 +1491,7 @@ fn expand_errs(errs: &mut Vec<GrammarErr>, text: &str) {
         }
     }
     // Then expand "forwards" towards errors with higher end's:
-    errs.sort_unstable_by_key(|e| e.end);
+    errs.sort_by_key(|e| e.end);
     for i in (0..errs.len() - 1).rev() {
         let (left, right) = errs.split_at_mut(i + 1);
         let e = &mut left[i];
@@ -1101,21 +1536,85 @@ struct Suggester<'a> {
     pub locales: Vec<String>, // requested locales in priority order
     pub fluent_loader: &'a FluentLoader,
 
-    generator: Arc<Mutex<AnyTransducer>>,
+    generator: Arc<Lookup>,
     error_mappings: Arc<IndexMap<String, Vec<Id>>>,
+    policies: Arc<IndexMap<String, ErrorPolicy>>,
+    urls: Arc<IndexMap<String, String>>,
     ignores: IdSet,
     includes: IdSet,
+    flush_on: FlushOn, // when run_sentence returns, besides the end of input
     delimiters: HashSet<String>, // run_sentence(NulAndDelimiters) will return after seeing a cohort with one of these forms
-    hard_limit: usize, // run_sentence(NulAndDelimiters) will always flush after seeing this many cohorts
+    hard_limit: usize, // run_sentence will always flush after seeing this many cohorts, unless flushing on Nul
     generate_all_readings: bool,
+    relations: bool, // describe each error's relation targets in the output
+    suppressed: Option<SuppressedCounts>, // errors left out, when reporting them
+    warnings: bool,  // report invisible and lookalike characters in the text
+    limits: ReplacementLimits, // bounds on building each error's replacements
+    nocheck: Vec<NocheckRange>, // regions of the input not to report errors in
+    line_col: bool,  // add line/column positions to each error
+    postprocess: Option<Arc<Postprocess>>, // applied to every suggestion
+    time_budget: Option<Duration>, // for each sentence's suggestions
 }
 
 #[rt_struct(module = "divvun")]
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GrammarOutput {
+    /// The text that was checked, as rebuilt from the analysis: the
+    /// sentences joined by the blanks between them. Error offsets index into
+    /// this rather than the input, so clients can check that a range still
+    /// covers `form` before applying a suggestion.
     pub text: String,
     pub errors: Vec<GrammarErr>,
     pub encoding: String,
+    /// Set when the time budget ran out; `errors` only covers the text up to
+    /// that point.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub timed_out: bool,
+    /// Errors left out of `errors`, only with the `report_suppressed` config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suppressed: Option<Vec<SuppressedErr>>,
+    /// Invisible and lookalike characters in `text`, only with the
+    /// `report_warnings` config.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warnings: Option<Vec<TextWarning>>,
+}
+
+/// How many errors of one kind were left out of the output, and why:
+/// `"ignored"` by the `ignore` config or `"not_included"` by an include
+/// filter.
+#[rt_struct(module = "divvun")]
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SuppressedErr {
+    pub err: String,
+    pub count: usize,
+    pub reason: String,
+}
+
+/// Suppressed errors of one run by error ID and reason, in order of first
+/// occurrence.
+#[derive(Debug, Default)]
+struct SuppressedCounts(std::cell::RefCell<IndexMap<(String, &'static str), usize>>);
+
+impl SuppressedCounts {
+    fn add(&self, err_id: &str, reason: &'static str) {
+        *self
+            .0
+            .borrow_mut()
+            .entry((err_id.to_string(), reason))
+            .or_default() += 1;
+    }
+
+    fn summary(&self) -> Vec<SuppressedErr> {
+        self.0
+            .borrow()
+            .iter()
+            .map(|((err, reason), count)| SuppressedErr {
+                err: err.clone(),
+                count: *count,
+                reason: reason.to_string(),
+            })
+            .collect()
+    }
 }
 
 /// What `suggest`'s `forward()` produces, depending on the `format` config.
@@ -1126,7 +1625,7 @@ enum SuggestOutput {
 
 impl<'a> Suggester<'a> {
     pub fn new(
-        generator: Arc<Mutex<AnyTransducer>>,
+        generator: Arc<Lookup>,
         locales: Vec<String>,
         generate_all_readings: bool,
         fluent_loader: &'a FluentLoader,
@@ -1138,15 +1637,100 @@ impl<'a> Suggester<'a> {
             locales,
             generator,
             error_mappings,
+            policies: Default::default(),
+            urls: Default::default(),
+            flush_on: FlushOn::Nul,
             delimiters: default_delimiters(),
             generate_all_readings,
             hard_limit: 1000,
             ignores: ignores.unwrap_or_default(),
             includes: includes.unwrap_or_default(),
             fluent_loader,
+            time_budget: None,
+            relations: false,
+            suppressed: None,
+            warnings: false,
+            limits: ReplacementLimits::default(),
+            nocheck: Vec::new(),
+            line_col: false,
+            postprocess: None,
         }
     }
 
+    /// Bound the time spent generating suggestions for each sentence.
+    fn with_time_budget(mut self, budget: Option<Duration>) -> Self {
+        self.time_budget = budget;
+        self
+    }
+
+    fn with_relations(mut self, relations: bool) -> Self {
+        self.relations = relations;
+        self
+    }
+
+    fn with_report_suppressed(mut self, report: bool) -> Self {
+        self.suppressed = report.then(SuppressedCounts::default);
+        self
+    }
+
+    fn with_report_warnings(mut self, warnings: bool) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    fn with_replacement_limits(mut self, limits: ReplacementLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Leave out errors in `nocheck`, with offsets in the output's encoding.
+    fn with_nocheck(mut self, nocheck: Vec<NocheckRange>) -> Self {
+        self.nocheck = nocheck;
+        self
+    }
+
+    fn with_line_col(mut self, line_col: bool) -> Self {
+        self.line_col = line_col;
+        self
+    }
+
+    fn with_policies(mut self, policies: Arc<IndexMap<String, ErrorPolicy>>) -> Self {
+        self.policies = policies;
+        self
+    }
+
+    fn with_urls(mut self, urls: Arc<IndexMap<String, String>>) -> Self {
+        self.urls = urls;
+        self
+    }
+
+    fn with_postprocess(mut self, postprocess: Option<Arc<Postprocess>>) -> Self {
+        self.postprocess = postprocess;
+        self
+    }
+
+    /// `suggestions` as they should be shown, after the bundle's
+    /// post-processing.
+    fn postprocess(&self, suggestions: &mut [String]) {
+        if let Some(postprocess) = &self.postprocess {
+            for suggestion in suggestions {
+                *suggestion = postprocess.apply(suggestion);
+            }
+        }
+    }
+
+    fn with_segmentation(mut self, segmentation: Segmentation) -> Self {
+        self.flush_on = segmentation.flush_on;
+        self.delimiters = segmentation.delimiters;
+        self.hard_limit = segmentation.hard_limit;
+        self
+    }
+
+    /// When a sentence started now runs out of time.
+    fn deadline(&self) -> Option<Instant> {
+        self.time_budget.map(|budget| Instant::now() + budget)
+    }
+
     fn find_error_id_for_tag(&self, tag: &str) -> Option<&str> {
         for (error_id, ids) in self.error_mappings.iter() {
             for id in ids {
@@ -1158,31 +1742,95 @@ impl<'a> Suggester<'a> {
         None
     }
 
+    /// Whether errors tagged `tag` get suggestions, as `policies.json` has it.
+    fn suggests(&self, tag: &str) -> bool {
+        let err_id = self.find_error_id_for_tag(tag).unwrap_or(tag);
+        self.policies
+            .get(err_id)
+            .is_none_or(|x| x.generate_suggestions && x.max_rep != Some(0))
+    }
+
     fn run(&self, text: &str, encoding: Option<&str>) -> GrammarOutput {
-        tracing::debug!("run with input: {:?}", text);
-        let sentence = self.run_sentence(text, FlushOn::Nul);
+        tracing::debug!("run with input: {:?}", redact(text));
+        let input = cg3::Output::new(text.trim());
+        let mut blocks = input.iter().peekable();
+
+        // Sentences are checked one by one; their texts are joined with the
+        // blanks between them and their errors moved to match.
+        let mut full_text = String::new();
+        let mut errs = Vec::new();
+        let mut timed_out = false;
+        let mut blank = String::new();
+        while blocks.peek().is_some() {
+            let sentence = self.run_sentence(&mut blocks);
+            tracing::debug!(
+                "Sentence: cohorts={}, text={:?}, errs={}",
+                sentence.cohorts.len(),
+                redact(&sentence.text),
+                sentence.errs.len()
+            );
 
-        tracing::debug!(
-            "Final sentence: cohorts={}, text={:?}, errs={}",
-            sentence.cohorts.len(),
-            sentence.text,
-            sentence.errs.len()
-        );
+            let Some(first) = sentence.cohorts.first() else {
+                blank.push_str(&sentence.raw_final_blank);
+                continue;
+            };
+            if !full_text.is_empty() {
+                full_text.push_str(&clean_blank(&blank));
+                full_text.push_str(&clean_blank(&first.raw_pre_blank));
+            }
+            let offset = full_text.len();
+            full_text.push_str(&sentence.text);
+            errs.extend(sentence.errs.into_iter().map(|mut err| {
+                err.start += offset;
+                err.end += offset;
+                for rel in err.relations.iter_mut().flatten() {
+                    rel.start += offset;
+                    rel.end += offset;
+                }
+                err
+            }));
+            timed_out |= sentence.timed_out_at.is_some();
+            blank = sentence.raw_final_blank;
+        }
+
+        if self.line_col {
+            for err in &mut errs {
+                err.position = Some(LineCol::of(&full_text, err.start, err.end));
+            }
+        }
 
+        let mut warnings = self.warnings.then(|| text_warnings(&full_text));
         let output_errs: Vec<GrammarErr> = if encoding == Some("utf-16") {
-            sentence
-                .errs
-                .into_iter()
-                .map(|err| err.into_utf16(&sentence.text))
+            for warning in warnings.iter_mut().flatten() {
+                warning.start = byte_to_utf16_offset(&full_text, warning.start);
+                warning.end = byte_to_utf16_offset(&full_text, warning.end);
+            }
+            errs.into_iter()
+                .map(|err| err.into_utf16(&full_text))
                 .collect()
         } else {
-            sentence.errs
+            errs
         };
+        let output_errs = output_errs
+            .into_iter()
+            .filter(|err| {
+                let nocheck = self.nocheck.iter().any(|x| x.covers(err.start, err.end));
+                if nocheck {
+                    if let Some(suppressed) = &self.suppressed {
+                        suppressed.add(&err.error_id, "nocheck");
+                    }
+                }
+                !nocheck
+            })
+            .collect();
 
         GrammarOutput {
-            text: sentence.text,
+            text: full_text,
             errors: output_errs,
             encoding: encoding.unwrap_or("utf-8").to_string(),
+            timed_out,
+            suppressed: self.suppressed.as_ref().map(SuppressedCounts::summary),
+            warnings,
         }
     }
 
@@ -1191,15 +1839,19 @@ impl<'a> Suggester<'a> {
     /// through cg3-rs (so readings and blanks are preserved verbatim); the
     /// suggestions are generated with the same `proc_reading` path as the JSON
     /// output, so the dynamic-compound handling from #31 applies here too.
-    fn run_cg(&self, text: &str) -> String {
+    fn run_cg(&self, text: &str) -> Result<String, Error> {
         use std::fmt::Write as _;
+        let write_err =
+            |e: std::fmt::Error| Error::msg(format!("Failed to write CG output: {}", e));
         let mut out = String::new();
+        let mut timed_out = false;
+        let mut deadline = self.deadline();
         let input = cg3::Output::new(text.trim());
         for block in input.iter() {
             let Ok(block) = block else { continue };
             match &block {
                 cg3::Block::Cohort(cohort) => {
-                    let _ = writeln!(out, "\"<{}>\"", cohort.word_form);
+                    writeln!(out, "\"<{}>\"", cohort.word_form).map_err(write_err)?;
                     let subs: Vec<Reading> = cohort
                         .readings
                         .iter()
@@ -1208,25 +1860,37 @@ impl<'a> Suggester<'a> {
                     for group in group_readings(cohort) {
                         // The reading (and any compound sub-readings), verbatim.
                         for &i in &group {
-                            let _ = writeln!(out, "{}", cohort.readings[i]);
+                            writeln!(out, "{}", cohort.readings[i]).map_err(write_err)?;
                         }
                         // After a SUGGEST analysis, append "<ana>\t<form,form,...>",
                         // exactly like divvun-suggest's run_cg.
-                        if group.iter().any(|&i| subs[i].suggest) {
+                        if !timed_out && out_of_time(deadline) {
+                            tracing::warn!(
+                                "divvun::suggest time budget exceeded at {:?}; skipping remaining suggestions",
+                                redact(&cohort.word_form)
+                            );
+                            timed_out = true;
+                        }
+                        if !timed_out && group.iter().any(|&i| subs[i].suggest) {
                             let (ana, mut forms) =
                                 generate_group(&self.generator, cohort, &subs, &group);
+                            self.postprocess(&mut forms);
                             forms.dedup();
-                            let _ = writeln!(out, "{}\t{}", ana, forms.join(","));
+                            writeln!(out, "{}\t{}", ana, forms.join(",")).map_err(write_err)?;
                         }
                     }
+                    if crate::modules::cg3_util::is_sentence_boundary(cohort, &self.delimiters) {
+                        deadline = self.deadline();
+                        timed_out = false;
+                    }
                 }
                 // Blanks / superblanks / text, verbatim.
                 _ => {
-                    let _ = write!(out, "{}", block);
+                    write!(out, "{}", block).map_err(write_err)?;
                 }
             }
         }
-        out
+        Ok(out)
     }
 
     fn cohort_errs(
@@ -1238,7 +1902,7 @@ impl<'a> Suggester<'a> {
         sentence: &Sentence,
         text: &str,
     ) -> Option<GrammarErr> {
-        tracing::debug!("COHORT ERRS: {text:?}");
+        tracing::debug!("COHORT ERRS: {:?}", redact(text));
         if c.is_empty()
             || matches!(
                 c.added,
@@ -1246,9 +1910,18 @@ impl<'a> Suggester<'a> {
             )
         {
             return None;
-        } else if self.ignores.matches(err_id) {
-            return None;
+        }
+        let reason = if self.ignores.matches(err_id) {
+            Some("ignored")
         } else if !self.includes.is_empty() && !self.includes.matches(err_id) {
+            Some("not_included")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            if let Some(suppressed) = &self.suppressed {
+                suppressed.add(err_id, reason);
+            }
             return None;
         }
 
@@ -1304,6 +1977,7 @@ impl<'a> Suggester<'a> {
         let mut start = c.pos;
         let mut end = c.pos + c.form.len();
         let mut suggestions = Vec::new();
+        let mut truncated = false;
         for r in &c.readings {
             if !r.errtypes.contains(cg3_tag) {
                 continue; // Only process readings with the CG3 error tag
@@ -1311,26 +1985,53 @@ impl<'a> Suggester<'a> {
             // If there are LEFT/RIGHT added relations, add suggestions with those concatenated to our form
             // TODO: What about our current suggestions of the same error tag? Currently just using wordform
             let squiggle = squiggle_bounds(&r.rels, sentence, i_c, c);
-            if let Some((bounds, sforms)) = build_squiggle_replacement(
-                r, cg3_tag, i_c, c, sentence, start, end, squiggle.0, squiggle.1,
+            if let Some((bounds, sforms, cut)) = build_squiggle_replacement(
+                r,
+                cg3_tag,
+                i_c,
+                c,
+                sentence,
+                start,
+                end,
+                squiggle.0,
+                squiggle.1,
+                &self.limits,
             ) {
                 start = bounds.0;
                 end = bounds.1;
                 suggestions.extend(sforms);
+                truncated |= cut;
             }
         }
 
+        self.postprocess(&mut suggestions);
         // Avoid unchanging replacements:
         let form = &text[start..end];
         suggestions.retain(|r| r != form);
         // No duplicates:
         suggestions.dedup();
+        let policy = self.policies.get(err_id);
+        if let Some(policy) = policy {
+            if !policy.generate_suggestions {
+                suggestions.clear();
+            } else if let Some(max_rep) = policy.max_rep {
+                suggestions.truncate(max_rep);
+            }
+        }
         // Suggestion placeholders: €1, €2, ... -> 1st, 2nd, ... suggestion.
         for (i, suggestion) in suggestions.iter().enumerate() {
             let placeholder = format!("€{}", i + 1);
             msg.0 = msg.0.replace(&placeholder, suggestion);
             msg.1 = msg.1.replace(&placeholder, suggestion);
         }
+        let relations = self
+            .relations
+            .then(|| error_relations(cg3_tag, c, sentence));
+        let autofix = policy.is_some_and(|x| x.autofix) && suggestions.len() == 1;
+        let url = self
+            .fluent_loader
+            .get_attribute_localized(&locale_refs, &ftl_key, "url", Some(&args))
+            .or_else(|| self.urls.get(err_id).cloned());
         Some(GrammarErr {
             form: form.to_string(),
             start,
@@ -1339,11 +2040,17 @@ impl<'a> Suggester<'a> {
             title: msg.0,
             description: msg.1,
             suggestions,
+            severity: policy.and_then(|x| x.severity.clone()),
+            url,
+            autofix,
+            relations,
+            position: None,
+            truncated,
         })
     }
 
     fn mk_errs(&self, sentence: &mut Sentence) {
-        tracing::debug!("mk_errs {:?}", sentence.text);
+        tracing::debug!("mk_errs {:?}", redact(&sentence.text));
         let text = &sentence.text;
 
         // Preprocessing, demote target &error to co&error:
@@ -1393,7 +2100,12 @@ impl<'a> Suggester<'a> {
         let mut errs = vec![];
         let s = sentence.clone();
         for (i_c, c) in sentence.cohorts.iter_mut().enumerate() {
-            let mut c_errtypes = HashSet::new();
+            if s.timed_out_at.is_some_and(|cutoff| i_c >= cutoff) {
+                break;
+            }
+            // In order of first occurrence, so errors come out the same way
+            // on every run
+            let mut c_errtypes = IndexSet::new();
             for r in &c.readings {
                 if r.coerror {
                     // Needed for backwards-compatibility with `COERROR &errtag` readings
@@ -1423,28 +2135,32 @@ impl<'a> Suggester<'a> {
         expand_errs(&mut sentence.errs, &text);
     }
 
-    fn run_sentence(&self, reader: &str, flush_on: FlushOn) -> Sentence {
-        let input = cg3::Output::new(reader.trim());
+    /// Read blocks up to the end of the next sentence, as `flush_on` decides
+    /// where sentences end, and find its errors.
+    fn run_sentence<'t>(
+        &self,
+        blocks: &mut impl Iterator<Item = Result<cg3::Block<'t>, cg3::ParseError>>,
+    ) -> Sentence {
+        let flush_on = self.flush_on;
+        let deadline = self.deadline();
         let mut sentence = Sentence::default();
         let mut pos = 0;
         let mut raw_blank = String::new(); // Accumulated blank for next cohort
         let mut current_cohort: Option<Cohort> = None; // Current cohort being built (delayed save pattern)
         let mut reading_lines = String::new(); // For multi-line readings
 
-        tracing::debug!("run_sentence with reader length: {}", reader.len());
-
-        for block in input.iter() {
+        for block in blocks {
             let block = match block {
                 Ok(b) => b,
                 Err(e) => {
-                    tracing::warn!("Error parsing CG3 output: {}", e);
+                    tracing::warn!("Error parsing CG3 output: {}", redact(&e.to_string()));
                     continue;
                 }
             };
 
             match block {
                 cg3::Block::Cohort(cg_cohort) => {
-                    tracing::debug!("Processing cohort: {:?}", cg_cohort.word_form);
+                    tracing::debug!("Processing cohort: {:?}", redact(cg_cohort.word_form));
 
                     // Save the previous cohort if we have one (delayed save pattern)
                     if let Some(mut cohort) = current_cohort.take() {
@@ -1473,14 +2189,25 @@ impl<'a> Suggester<'a> {
                     // The accumulated blank belongs *before* the cohort we're about to
                     // build (raw_pre_blank), not after the one we just saved.
                     let pre_blank = std::mem::take(&mut raw_blank);
-                    current_cohort = Some(self.process_cohort(&cg_cohort, pos, pre_blank));
+                    if sentence.timed_out_at.is_none() && out_of_time(deadline) {
+                        tracing::warn!(
+                            "divvun::suggest time budget exceeded after {} cohorts; skipping the rest",
+                            sentence.cohorts.len()
+                        );
+                        sentence.timed_out_at = Some(sentence.cohorts.len());
+                    }
+                    let generate = sentence.timed_out_at.is_none();
+                    current_cohort =
+                        Some(self.process_cohort(&cg_cohort, pos, pre_blank, generate));
 
                     // Check for flushing conditions
-                    if flush_on == FlushOn::NulAndDelimiters {
-                        if crate::modules::cg3_util::is_sentence_boundary(
-                            &cg_cohort,
-                            &self.delimiters,
-                        ) {
+                    if flush_on != FlushOn::Nul {
+                        if flush_on == FlushOn::NulAndDelimiters
+                            && crate::modules::cg3_util::is_sentence_boundary(
+                                &cg_cohort,
+                                &self.delimiters,
+                            )
+                        {
                             break;
                         }
                         if sentence.cohorts.len() >= self.hard_limit {
@@ -1502,8 +2229,14 @@ impl<'a> Suggester<'a> {
                     // pos += clean.len();
                 }
                 cg3::Block::Escaped(escaped) => {
-                    tracing::debug!("Accumulating escaped block: {:?}", escaped);
+                    tracing::debug!("Accumulating escaped block: {:?}", redact(escaped));
                     raw_blank.push_str(&escaped);
+                    if flush_on == FlushOn::NulAndParagraphs
+                        && current_cohort.is_some()
+                        && clean_blank(&raw_blank).contains("\n\n")
+                    {
+                        break;
+                    }
                 }
             }
         }
@@ -1538,7 +2271,13 @@ impl<'a> Suggester<'a> {
         sentence
     }
 
-    fn process_cohort(&self, cg_cohort: &cg3::Cohort, pos: usize, raw_pre_blank: String) -> Cohort {
+    fn process_cohort(
+        &self,
+        cg_cohort: &cg3::Cohort,
+        pos: usize,
+        raw_pre_blank: String,
+        generate: bool,
+    ) -> Cohort {
         let mut cohort = Cohort {
             form: cg_cohort.word_form.to_string(),
             pos,
@@ -1547,7 +2286,13 @@ impl<'a> Suggester<'a> {
         };
 
         // Process the cohort as a whole to get a single reading
-        let reading = proc_reading(&self.generator, cg_cohort, self.generate_all_readings);
+        let reading = proc_reading(
+            &self.generator,
+            cg_cohort,
+            self.generate_all_readings,
+            generate,
+            |tag| self.suggests(tag),
+        );
 
         // Accumulate error types from the reading
         cohort.errtypes.extend(reading.errtypes.iter().cloned());
@@ -1566,3 +2311,573 @@ impl<'a> Suggester<'a> {
         cohort
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn reading(depth: usize) -> cg3::Reading<'static> {
+        cg3::Reading {
+            raw_line: "\t\"x\" N",
+            base_form: "x",
+            tags: vec!["N"],
+            depth,
+        }
+    }
+
+    #[test]
+    fn group_readings_starts_group_for_leading_subreading() {
+        // A stream that opens with a compound part must not panic.
+        let cohort = cg3::Cohort {
+            word_form: "x",
+            readings: vec![reading(2), reading(1), reading(2), reading(3)],
+        };
+        assert_eq!(group_readings(&cohort), vec![vec![0], vec![1, 2, 3]]);
+    }
+
+    #[test]
+    fn error_tags_keep_stream_order() {
+        let stream =
+            "\"<leat>\"\n\t\"leat\" V &msyn-z &msyn-a co&lex\n\t\"leat\" V &msyn-m &msyn-a\n";
+        let output = cg3::Output::new(stream);
+        let Some(Ok(cg3::Block::Cohort(cohort))) = output.iter().next() else {
+            panic!("no cohort");
+        };
+
+        let mut errtypes = IndexSet::new();
+        for reading in &cohort.readings {
+            errtypes.extend(proc_subreading(reading, false).errtypes);
+        }
+        assert_eq!(
+            errtypes.into_iter().collect::<Vec<_>>(),
+            ["msyn-z", "msyn-a", "msyn-m"]
+        );
+    }
+
+    #[test]
+    fn overlapping_errors_expand_in_stream_order() {
+        let text = "Mun leat boahtán";
+        let err = |error_id: &str, start: usize, end: usize| GrammarErr {
+            form: text[start..end].to_string(),
+            start,
+            end,
+            error_id: error_id.to_string(),
+            title: String::new(),
+            description: String::new(),
+            suggestions: vec![text[start..end].to_uppercase()],
+            severity: None,
+            url: None,
+            autofix: false,
+            relations: None,
+            position: None,
+            truncated: false,
+        };
+        let mut errs = vec![err("typo", 4, 8), err("agr", 4, 8), err("wo", 0, 8)];
+
+        expand_errs(&mut errs, text);
+        let errs = errs
+            .iter()
+            .map(|x| {
+                (
+                    x.error_id.as_str(),
+                    x.start,
+                    x.end,
+                    x.suggestions[0].as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            errs,
+            [
+                ("wo", 0, 8, "MUN LEAT"),
+                ("typo", 0, 8, "Mun LEAT"),
+                ("agr", 0, 8, "Mun LEAT"),
+            ]
+        );
+    }
+
+    #[test]
+    fn output_to_json_keeps_non_ascii_generated_forms() {
+        let text = "𝒜 gáhttet";
+        let err = GrammarErr {
+            form: "gáhttet".to_string(),
+            start: text.find('g').unwrap(),
+            end: text.len(),
+            error_id: "typo".to_string(),
+            title: "Čállinmeattáhus".to_string(),
+            description: String::new(),
+            suggestions: vec!["gáhttit".to_string(), "𝒜".to_string()],
+            severity: None,
+            url: None,
+            autofix: false,
+            relations: None,
+            position: None,
+            truncated: false,
+        }
+        .into_utf16(text);
+        let output = GrammarOutput {
+            text: text.to_string(),
+            errors: vec![err],
+            encoding: "utf-16".to_string(),
+            timed_out: false,
+            suppressed: None,
+            warnings: None,
+        };
+
+        let value = output_to_json(output).unwrap();
+        assert_eq!(value["errors"][0]["start"], 3);
+        assert_eq!(value["errors"][0]["suggestions"][1], "𝒜");
+    }
+
+    #[test]
+    fn output_to_json_flags_timeouts_only_when_set() {
+        let output = |timed_out| GrammarOutput {
+            text: "Mun leat".to_string(),
+            errors: vec![],
+            encoding: "utf-8".to_string(),
+            timed_out,
+            suppressed: None,
+            warnings: None,
+        };
+
+        assert!(
+            output_to_json(output(false))
+                .unwrap()
+                .get("timed_out")
+                .is_none()
+        );
+        assert_eq!(output_to_json(output(true)).unwrap()["timed_out"], true);
+    }
+
+    #[test]
+    fn suppressed_errors_are_counted_by_id_and_reason() {
+        let counts = SuppressedCounts::default();
+        counts.add("typo", "ignored");
+        counts.add("msyn-agr", "not_included");
+        counts.add("typo", "ignored");
+
+        let summary = counts.summary();
+        assert_eq!(
+            summary,
+            [
+                SuppressedErr {
+                    err: "typo".to_string(),
+                    count: 2,
+                    reason: "ignored".to_string(),
+                },
+                SuppressedErr {
+                    err: "msyn-agr".to_string(),
+                    count: 1,
+                    reason: "not_included".to_string(),
+                },
+            ]
+        );
+        let output = GrammarOutput {
+            text: String::new(),
+            errors: vec![],
+            encoding: "utf-8".to_string(),
+            timed_out: false,
+            suppressed: Some(summary),
+            warnings: None,
+        };
+        assert_eq!(
+            output_to_json(output).unwrap()["suppressed"][0],
+            serde_json::json!({ "err": "typo", "count": 2, "reason": "ignored" })
+        );
+    }
+
+    #[test]
+    fn error_relations_lists_targets_and_coerrors() {
+        let cohort = |form: &str, pos, id, coerr: &[&str]| Cohort {
+            form: form.to_string(),
+            pos,
+            id,
+            coerrtypes: coerr.iter().map(|x| x.to_string()).collect(),
+            ..Default::default()
+        };
+        let mut sentence = Sentence {
+            text: "Mun leat dál".to_string(),
+            ..Default::default()
+        };
+        sentence.cohorts = vec![
+            cohort("Mun", 0, 1, &["agr"]),
+            cohort("leat", 4, 2, &[]),
+            cohort("dál", 9, 3, &[]),
+        ];
+        sentence.ids_cohorts = HashMap::from([(1, 0), (2, 1), (3, 2)]);
+
+        let mut err_cohort = sentence.cohorts[1].clone();
+        err_cohort.readings = vec![Reading {
+            errtypes: IndexSet::from(["agr".to_string()]),
+            rels: HashMap::from([("LEFT".to_string(), 1), ("$2".to_string(), 3)]),
+            ..Default::default()
+        }];
+
+        let relations = error_relations("agr", &err_cohort, &sentence);
+        assert_eq!(relations.len(), 2);
+        assert_eq!(relations[0].name, "LEFT");
+        assert_eq!((relations[0].start, relations[0].end), (0, 3));
+        assert!(relations[0].coerror);
+        assert_eq!(relations[1].form, "dál");
+        assert!(!relations[1].coerror);
+
+        // Other error tags on the cohort don't contribute.
+        assert!(error_relations("typo", &err_cohort, &sentence).is_empty());
+    }
+
+    #[test]
+    fn error_policies_default_to_suggesting() {
+        let policies: IndexMap<String, ErrorPolicy> = serde_json::from_str(
+            r#"{"msyn-style": {"generate_suggestions": false, "severity": "hint"}, "typo": {"max_rep": 3, "autofix": true}}"#,
+        )
+        .unwrap();
+        assert!(!policies["msyn-style"].generate_suggestions);
+        assert_eq!(policies["msyn-style"].severity.as_deref(), Some("hint"));
+        assert!(policies["typo"].generate_suggestions);
+        assert_eq!(policies["typo"].max_rep, Some(3));
+        assert!(policies["typo"].autofix && !policies["msyn-style"].autofix);
+
+        assert!(serde_json::from_str::<ErrorPolicy>(r#"{"max_reps": 3}"#).is_err());
+    }
+
+    #[test]
+    fn line_col_counts_lines_and_graphemes() {
+        let text = "Mun leat.\nDát le\u{301}a boahtán.";
+        let start = text.find("boahtán").unwrap();
+        let end = start + "boahtán".len();
+        assert_eq!(
+            LineCol::of(text, start, end),
+            LineCol {
+                start_line: 2,
+                start_column: 9,
+                end_line: 2,
+                end_column: 16,
+            }
+        );
+        assert_eq!(line_col(text, 0), (1, 1));
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct ReplayCase {
+        #[serde(default)]
+        flush_mode: Option<String>,
+        #[serde(default)]
+        generate: IndexMap<String, Vec<String>>,
+        sentences: Vec<ReplaySentence>,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct ReplaySentence {
+        text: String,
+        errors: Vec<ReplayErr>,
+    }
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct ReplayErr {
+        form: String,
+        start: usize,
+        end: usize,
+        error_id: String,
+        suggestions: Vec<String>,
+    }
+
+    /// A suggester without messages or error mappings, around `generator`.
+    fn suggester(generator: Arc<Lookup>) -> Suggester<'static> {
+        let fluent_loader = FluentLoader::from_sources(std::iter::empty(), "en").unwrap();
+        Suggester::new(
+            generator,
+            vec![],
+            false,
+            // Leaked so that the suggester may outlive this call
+            Box::leak(Box::new(fluent_loader)),
+            Default::default(),
+            None,
+            None,
+        )
+    }
+
+    /// Runs the CG streams in `tests/fixtures/suggest` through `run_sentence`
+    /// with a canned generator; see the README there.
+    #[test]
+    fn replays_cg_fixtures() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/suggest");
+        let mut streams = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|x| x.unwrap().path())
+            .filter(|x| x.extension().is_some_and(|ext| ext == "cg"))
+            .collect::<Vec<_>>();
+        streams.sort();
+        assert!(!streams.is_empty());
+
+        for path in streams {
+            let stream = std::fs::read_to_string(&path).unwrap();
+            let case: ReplayCase = serde_json::from_str(
+                &std::fs::read_to_string(path.with_extension("json")).unwrap(),
+            )
+            .unwrap();
+
+            let mut suggester = suggester(Arc::new(Lookup::canned(case.generate)));
+            if let Some(mode) = &case.flush_mode {
+                suggester = suggester.with_segmentation(Segmentation {
+                    flush_on: FlushOn::parse(mode).unwrap(),
+                    ..Default::default()
+                });
+            }
+
+            let input = cg3::Output::new(stream.trim());
+            let mut blocks = input.iter().peekable();
+            let mut sentences = Vec::new();
+            while blocks.peek().is_some() {
+                let sentence = suggester.run_sentence(&mut blocks);
+                if sentence.cohorts.is_empty() {
+                    continue;
+                }
+                sentences.push(ReplaySentence {
+                    text: sentence.text,
+                    errors: sentence
+                        .errs
+                        .into_iter()
+                        .map(|x| ReplayErr {
+                            form: x.form,
+                            start: x.start,
+                            end: x.end,
+                            error_id: x.error_id,
+                            suggestions: x.suggestions,
+                        })
+                        .collect(),
+                });
+            }
+            assert_eq!(sentences, case.sentences, "{}", path.display());
+        }
+    }
+
+    #[test]
+    fn wide_underlines_and_many_replacements_are_capped() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom ID:1\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST ID:2 R:LEFT:1\n: \n\"<boahtán>\"\n\t\"boahtit\" V IV PrfPrc ID:3\n:\\n";
+        let errors = |limits: ReplacementLimits| {
+            let generator = Lookup::canned([(
+                "leat+V+IV+Ind+Prs+Sg1".to_string(),
+                vec!["lean".to_string(), "ledjen".to_string()],
+            )]);
+            let suggester = suggester(Arc::new(generator)).with_replacement_limits(limits);
+            let input = cg3::Output::new(stream);
+            let mut blocks = input.iter().peekable();
+            suggester
+                .run_sentence(&mut blocks)
+                .errs
+                .into_iter()
+                .map(|x| (x.form, x.suggestions, x.truncated))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            errors(ReplacementLimits::default()),
+            [(
+                "Mun leat".to_string(),
+                vec!["Mun lean".to_string(), "Mun ledjen".to_string()],
+                false
+            )]
+        );
+        assert_eq!(
+            errors(ReplacementLimits {
+                max_replacements: 1,
+                ..Default::default()
+            }),
+            [("Mun leat".to_string(), vec!["Mun lean".to_string()], true)]
+        );
+        assert_eq!(
+            errors(ReplacementLimits {
+                max_cohorts: 1,
+                ..Default::default()
+            }),
+            [(
+                "leat".to_string(),
+                vec!["lean".to_string(), "ledjen".to_string()],
+                true
+            )]
+        );
+    }
+
+    #[test]
+    fn errors_without_suggestions_skip_the_generator() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom ID:1\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST ID:2\n:\\n";
+        let run = |policies: &str| {
+            let generator = Arc::new(Lookup::canned([(
+                "leat+V+IV+Ind+Prs+Sg1".to_string(),
+                vec!["lean".to_string()],
+            )]));
+            let suggester = suggester(generator.clone())
+                .with_policies(Arc::new(serde_json::from_str(policies).unwrap()));
+            let input = cg3::Output::new(stream);
+            let mut blocks = input.iter().peekable();
+            let errs = suggester.run_sentence(&mut blocks).errs;
+            let stats = generator.stats();
+            (errs[0].suggestions.clone(), stats.hits + stats.misses)
+        };
+
+        assert_eq!(run("{}"), (vec!["lean".to_string()], 1));
+        assert_eq!(
+            run(r#"{"msyn-agr": {"generate_suggestions": false}}"#),
+            (vec![], 0)
+        );
+    }
+
+    #[test]
+    fn errors_in_nocheck_regions_are_left_out() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST\n: \n\"<𝒜>\"\n\t\"𝒜\" N Sg Nom\n: \n\"<boahtan>\"\n\t\"boahtit\" V IV PrfPrc &typo\n";
+        let run = |nocheck: Vec<NocheckRange>, encoding: Option<&str>| {
+            let output = suggester(Arc::new(Lookup::canned([])))
+                .with_report_suppressed(true)
+                .with_nocheck(nocheck)
+                .run(stream, encoding);
+            let ids = output
+                .errors
+                .iter()
+                .map(|x| x.error_id.clone())
+                .collect::<Vec<_>>();
+            (ids, output.suppressed.unwrap())
+        };
+
+        let (ids, suppressed) = run(vec![], None);
+        assert_eq!(ids, ["msyn-agr", "typo"]);
+        assert!(suppressed.is_empty());
+
+        // "leat" is at 4..8; "boahtan" at 14..21 in bytes, 12..19 in UTF-16
+        let (ids, suppressed) = run(vec![NocheckRange { start: 0, end: 5 }], None);
+        assert_eq!(ids, ["typo"]);
+        assert_eq!(
+            suppressed,
+            [SuppressedErr {
+                err: "msyn-agr".to_string(),
+                count: 1,
+                reason: "nocheck".to_string(),
+            }]
+        );
+        let (ids, _) = run(vec![NocheckRange { start: 12, end: 13 }], Some("utf-16"));
+        assert_eq!(ids, ["msyn-agr"]);
+        let (ids, _) = run(vec![NocheckRange { start: 8, end: 14 }], None);
+        assert_eq!(ids, ["msyn-agr", "typo"]);
+    }
+
+    #[test]
+    fn suggestions_are_postprocessed() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST\n";
+        let suggestions = |rules: &str| {
+            let generator = Lookup::canned([(
+                "leat+V+IV+Ind+Prs+Sg1".to_string(),
+                vec!["lean".to_string(), "leat".to_string()],
+            )]);
+            let postprocess = Postprocess::from_json(rules.as_bytes()).unwrap();
+            let output = suggester(Arc::new(generator))
+                .with_postprocess(Some(Arc::new(postprocess)))
+                .run(stream, None);
+            output.errors[0].suggestions.clone()
+        };
+
+        assert_eq!(
+            suggestions(r#"[{ "pattern": "^lea", "replacement": "léa" }]"#),
+            ["léan", "léat"]
+        );
+        // A suggestion the post-processing turns into the error's own form
+        // is dropped like any other unchanging one
+        assert_eq!(
+            suggestions(r#"[{ "pattern": "n$", "replacement": "t" }]"#),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn each_sentence_gets_its_own_time_budget() {
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST\n\"<.>\"\n\t\".\" CLB\n: \n\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST\n";
+        let suggester = suggester(Arc::new(Lookup::canned([])))
+            .with_segmentation(Segmentation {
+                flush_on: FlushOn::NulAndDelimiters,
+                ..Default::default()
+            })
+            .with_time_budget(Some(Duration::from_millis(20)));
+
+        // Only the first "leat" is slow to arrive
+        let mut slow = true;
+        let input = cg3::Output::new(stream);
+        let mut blocks = input.iter().inspect(|block| {
+            let leat = matches!(block, Ok(cg3::Block::Cohort(x)) if x.word_form == "leat");
+            if leat && std::mem::take(&mut slow) {
+                std::thread::sleep(Duration::from_millis(50));
+            }
+        });
+
+        let first = suggester.run_sentence(&mut blocks);
+        assert_eq!(first.timed_out_at, Some(1));
+        assert!(first.errs.is_empty());
+        let second = suggester.run_sentence(&mut blocks);
+        assert_eq!(second.timed_out_at, None);
+        assert_eq!(
+            second
+                .errs
+                .iter()
+                .map(|x| x.error_id.as_str())
+                .collect::<Vec<_>>(),
+            ["msyn-agr"]
+        );
+    }
+
+    #[test]
+    fn segmentation_config_overrides_args() {
+        let args = Segmentation {
+            flush_on: FlushOn::NulAndDelimiters,
+            delimiters: ["।".to_string()].into_iter().collect(),
+            hard_limit: 200,
+        };
+
+        let same = args.with_config(&SuggestConfig::default()).unwrap();
+        assert_eq!(same.flush_on, FlushOn::NulAndDelimiters);
+        assert!(same.delimiters.contains("।") && !same.delimiters.contains("."));
+        assert_eq!(same.hard_limit, 200);
+
+        let config: SuggestConfig = serde_json::from_str(
+            r#"{"flush_mode": "paragraph", "delimiters": [".", ";"], "hard_limit": 50}"#,
+        )
+        .unwrap();
+        let tuned = args.with_config(&config).unwrap();
+        assert_eq!(tuned.flush_on, FlushOn::NulAndParagraphs);
+        assert_eq!(tuned.delimiters.len(), 2);
+        assert_eq!(tuned.hard_limit, 50);
+
+        let bad: SuggestConfig = serde_json::from_str(r#"{"flush_mode": "line"}"#).unwrap();
+        assert!(args.with_config(&bad).is_err());
+        let zero: SuggestConfig = serde_json::from_str(r#"{"hard_limit": 0}"#).unwrap();
+        assert!(args.with_config(&zero).is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_utf8_is_an_error_or_replaced() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        // What a generator gives for a form that isn't valid UTF-8
+        let form = String::from_utf8_lossy(b"lea\xffn").into_owned();
+        let suggest = Suggest::canned(
+            context,
+            [("leat+V+IV+Ind+Prs+Sg1".to_string(), vec![form.clone()])],
+        )
+        .unwrap();
+        let forward = |input: PipelineValue, config: serde_json::Value| {
+            suggest.clone().forward(input, Arc::new(config))
+        };
+
+        let bytes = PipelineValue::Bytes(b"\"<le\xffat>\"\n".to_vec());
+        assert!(forward(bytes, serde_json::json!({})).await.is_err());
+
+        let stream = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n: \n\"<leat>\"\n\t\"leat\" V IV Ind Prs Sg1 &msyn-agr SUGGEST\n";
+        let input = PipelineValue::String(stream.to_string());
+        let output = forward(input.clone(), serde_json::json!({})).await.unwrap();
+        let Some(PipelineValue::Json(json)) = output.0.first() else {
+            panic!("expected JSON, got {:?}", output.0);
+        };
+        assert_eq!(json["errors"][0]["suggestions"][0], form.as_str());
+
+        let cg = serde_json::json!({ "format": "cg" });
+        let output = forward(input, cg).await.unwrap();
+        assert!(output.0[0].to_string().contains(&form));
+    }
+}
@@ -1,7 +1,17 @@
-use std::{borrow::Cow, collections::HashMap, sync::Arc, thread::JoinHandle};
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    num::NonZeroUsize,
+    sync::{
+        Arc, PoisonError,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+    thread::JoinHandle,
+};
 
 use async_trait::async_trait;
-use divvun_runtime_macros::rt_command;
+use divvun_runtime_macros::{rt_command, rt_struct};
+use serde::{Deserialize, Serialize};
 use tokio::sync::{
     Mutex,
     mpsc::{self, Receiver, Sender},
@@ -18,17 +28,172 @@ use hfst::pmatch_tokenize::{
 };
 use hfst::transducer::IStream;
 
-use crate::ast;
+use crate::{ast, util::privacy::redact};
 
 use super::{CommandRunner, Context, PipelineValue, PipelineValues, SharedPipelineValueFut};
 
-/// Load an optimized-lookup transducer for morphological lookup, wrapped in a
-/// `Mutex` for interior mutability — the native `lookup_fd_*` methods take
-/// `&mut self`, but callers hold the transducer behind a shared `&self`.
+/// Limits and caching for the lookups a command makes in one transducer,
+/// given as the command's optional `lookup` argument.
+#[rt_struct(module = "hfst")]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LookupConfig {
+    /// Keep at most this many results per lookup. Unlimited by default.
+    #[serde(default)]
+    pub max_results: Option<usize>,
+    /// Drop results weighing more than this. No cutoff by default.
+    #[serde(default)]
+    pub max_weight: Option<f64>,
+    /// Remember the results of this many distinct lookups; 0 disables the
+    /// cache. Defaults to 1024.
+    #[serde(default = "default_cache_size")]
+    pub cache_size: usize,
+    /// Generate from at most this many characters of an analysis, cutting
+    /// longer ones (say, one with a runaway lemma) short. Only generation
+    /// is cut; analysis and other lookups see the whole input. Defaults to
+    /// 256.
+    #[serde(default = "default_max_input_chars")]
+    pub max_input_chars: usize,
+}
+
+fn default_cache_size() -> usize {
+    1024
+}
+
+fn default_max_input_chars() -> usize {
+    256
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        LookupConfig {
+            max_results: None,
+            max_weight: None,
+            cache_size: default_cache_size(),
+            max_input_chars: default_max_input_chars(),
+        }
+    }
+}
+
+impl LookupConfig {
+    /// Read the optional `lookup` argument of a command.
+    pub(crate) fn from_kwargs(
+        kwargs: &HashMap<String, ast::Arg>,
+    ) -> Result<LookupConfig, crate::modules::Error> {
+        let Some(value) = kwargs.get("lookup").and_then(|x| x.value.as_ref()) else {
+            return Ok(LookupConfig::default());
+        };
+        let json = value.try_as_json().map_err(|e| {
+            crate::modules::Error::msg(format!("lookup arg is not valid JSON: {}", e))
+                .at("pipeline.json", "/args/lookup")
+        })?;
+        serde_json::from_value(json).map_err(|e| {
+            crate::modules::Error::msg(format!("lookup arg is not valid LookupConfig: {}", e))
+                .at("pipeline.json", "/args/lookup")
+        })
+    }
+}
+
+/// What a [`Lookup`] looks up inputs in.
+enum Source {
+    Transducer(std::sync::Mutex<AnyTransducer>),
+    /// Nothing but the cache, see [`Lookup::canned`].
+    #[cfg(test)]
+    Canned,
+}
+
+/// Cache counters of a [`Lookup`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct LookupStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub entries: usize,
+}
+
+/// An optimized-lookup transducer for morphological lookup, with the limits
+/// and result cache of a [`LookupConfig`].
+///
+/// The transducer is wrapped in a `Mutex` for interior mutability — the native
+/// `lookup_fd_*` methods take `&mut self`, but callers hold the transducer
+/// behind a shared `&self`.
+pub(crate) struct Lookup {
+    label: String,
+    source: Source,
+    config: LookupConfig,
+    cache: Option<std::sync::Mutex<lru::LruCache<(String, bool), Vec<String>>>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    /// Set once an over-long input has been warned about.
+    truncated: AtomicBool,
+}
+
+impl Lookup {
+    fn new(label: String, transducer: AnyTransducer, config: LookupConfig) -> Lookup {
+        let cache = NonZeroUsize::new(config.cache_size)
+            .map(|size| std::sync::Mutex::new(lru::LruCache::new(size)));
+        Lookup {
+            label,
+            source: Source::Transducer(std::sync::Mutex::new(transducer)),
+            config,
+            cache,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    /// A lookup answering from `results` only, by input, for testing
+    /// commands without a transducer. Anything else has no results.
+    #[cfg(test)]
+    pub(crate) fn canned(results: impl IntoIterator<Item = (String, Vec<String>)>) -> Lookup {
+        let mut cache = lru::LruCache::unbounded();
+        for (input, outputs) in results {
+            cache.put((input, false), outputs);
+        }
+        Lookup {
+            label: "canned".to_string(),
+            source: Source::Canned,
+            config: LookupConfig::default(),
+            cache: Some(std::sync::Mutex::new(cache)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            truncated: AtomicBool::new(false),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> LookupStats {
+        LookupStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self
+                .cache
+                .as_ref()
+                .map(|cache| cache.lock().unwrap_or_else(PoisonError::into_inner).len())
+                .unwrap_or(0),
+        }
+    }
+}
+
+impl Drop for Lookup {
+    fn drop(&mut self) {
+        let stats = self.stats();
+        if stats.hits + stats.misses > 0 {
+            tracing::debug!(
+                "lookup cache for {}: {} hits, {} misses, {} entries",
+                self.label,
+                stats.hits,
+                stats.misses,
+                stats.entries
+            );
+        }
+    }
+}
+
+/// Load an optimized-lookup transducer for morphological lookup.
 pub(crate) async fn load_lookup(
     context: &Context,
     path: impl AsRef<Path>,
-) -> Result<std::sync::Mutex<AnyTransducer>, crate::modules::Error> {
+    config: &LookupConfig,
+) -> Result<Lookup, crate::modules::Error> {
     let label = path.as_ref().display().to_string();
     let mapped = context.memory_map_file(path).await?;
     let bytes = mapped.as_slice().map_err(|e| {
@@ -49,22 +214,81 @@ pub(crate) async fn load_lookup(
             )));
         }
     }
-    Ok(std::sync::Mutex::new(transducer))
+    Ok(Lookup::new(label, transducer, config.clone()))
 }
 
 /// Flag-diacritic-aware lookup. Returns one output string per result path,
 /// keeping only the non-diacritic symbols (`is_diacritic == false`) or only the
 /// flag-diacritic symbols (`is_diacritic == true`). Mirrors the old FFI
-/// wrapper's `lookup_fd(input, -1, 10.0)` + `FdOperation::is_diacritic` filter.
-pub(crate) fn lookup_tags(
-    transducer: &std::sync::Mutex<AnyTransducer>,
-    input: &str,
-    is_diacritic: bool,
-) -> Vec<String> {
-    let mut guard = transducer.lock().unwrap();
+/// wrapper's `lookup_fd(input, -1, 10.0)` + `FdOperation::is_diacritic` filter,
+/// with the result limits of the lookup's [`LookupConfig`]. Results are cached
+/// per input.
+pub(crate) fn lookup_tags(lookup: &Lookup, input: &str, is_diacritic: bool) -> Vec<String> {
+    let key = (input.to_string(), is_diacritic);
+    if let Some(cache) = &lookup.cache {
+        if let Some(tags) = cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+        {
+            lookup.hits.fetch_add(1, Ordering::Relaxed);
+            return tags.clone();
+        }
+    }
+    lookup.misses.fetch_add(1, Ordering::Relaxed);
+
+    let tags = lookup_uncached(lookup, input, is_diacritic);
+    if let Some(cache) = &lookup.cache {
+        cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .put(key, tags.clone());
+    }
+    tags
+}
+
+/// Surface forms of the analysis `input` in a generator, cut to the lookup's
+/// `max_input_chars` first. Only the first cut input of a lookup is warned
+/// about, so a document full of them doesn't flood the log.
+pub(crate) fn generate(lookup: &Lookup, input: &str) -> Vec<String> {
+    let cut = truncate_input(input, lookup.config.max_input_chars);
+    if cut.len() < input.len() && !lookup.truncated.swap(true, Ordering::Relaxed) {
+        tracing::warn!(
+            "generator input of {} chars exceeds max_input_chars ({}) for {}, truncated: {}",
+            input.chars().count(),
+            lookup.config.max_input_chars,
+            lookup.label,
+            redact(input)
+        );
+    }
+    lookup_tags(lookup, cut, false)
+}
+
+/// `input` cut to at most `max` characters.
+fn truncate_input(input: &str, max: usize) -> &str {
+    match input.char_indices().nth(max) {
+        Some((end, _)) => &input[..end],
+        None => input,
+    }
+}
+
+fn lookup_uncached(lookup: &Lookup, input: &str, is_diacritic: bool) -> Vec<String> {
+    let config = &lookup.config;
+    // With a weight cutoff the limit applies to the results that pass it, so
+    // the search itself can't stop early.
+    let limit = match (config.max_results, config.max_weight) {
+        (Some(n), None) => n as _,
+        _ => -1,
+    };
+    let transducer = match &lookup.source {
+        Source::Transducer(transducer) => transducer,
+        #[cfg(test)]
+        Source::Canned => return Vec::new(),
+    };
+    let mut guard = transducer.lock().unwrap_or_else(PoisonError::into_inner);
     let paths = match &mut *guard {
-        AnyTransducer::OlW(t) => t.lookup_fd_string(input, -1, 10.0),
-        AnyTransducer::OlU(t) => t.lookup_fd_string(input, -1, 10.0),
+        AnyTransducer::OlW(t) => t.lookup_fd_string(input, limit, 10.0),
+        AnyTransducer::OlU(t) => t.lookup_fd_string(input, limit, 10.0),
         _ => return Vec::new(),
     };
     let Ok(paths) = paths else {
@@ -72,6 +296,12 @@ pub(crate) fn lookup_tags(
     };
     paths
         .into_iter()
+        .filter(|path| {
+            config
+                .max_weight
+                .is_none_or(|max| f64::from(path.first) <= max)
+        })
+        .take(config.max_results.unwrap_or(usize::MAX))
         .map(|path| {
             path.second
                 .iter()
@@ -160,7 +390,8 @@ pub struct Tokenize {
     input = [String],
     output = "String",
     kind = "cg3",
-    args = [model_path = "Path"]
+    args = [model_path = "Path"],
+    resources = [memory = "64M"]
 )]
 impl Tokenize {
     pub async fn new(
@@ -194,7 +425,9 @@ impl Tokenize {
                 };
 
                 let output = run_tokenizer(&mut container, &settings, &input);
-                output_tx.blocking_send(Some(output)).unwrap();
+                if output_tx.blocking_send(Some(output)).is_err() {
+                    break;
+                }
             }
         });
 
@@ -243,13 +476,12 @@ impl Tokenize {
         use ssml_parser::ParserEvent;
         use ssml_parser::elements::ParsedElement;
 
-        let events: Vec<ParserEvent> = tokio::task::spawn_blocking(move || {
+        let events: Vec<ParserEvent> = crate::util::priority::spawn_blocking(move || {
             ssml_parser::parse_ssml(&input)
                 .map(|s| s.event_iter().collect::<Vec<_>>())
                 .map_err(|e| crate::modules::Error::msg(e.to_string()))
         })
-        .await
-        .unwrap()?;
+        .await??;
 
         let mut output_rx = self.output_rx.lock().await;
         let mut fragments: Vec<String> = Vec::new();
@@ -259,7 +491,7 @@ impl Tokenize {
         for event in events {
             match event {
                 ParserEvent::Text(s) => {
-                    let frame = stack.last().unwrap().clone();
+                    let frame = stack.last().cloned().unwrap_or_default();
                     if frame.suppress {
                         continue;
                     }
@@ -284,7 +516,7 @@ impl Tokenize {
                     pending_break_ms = pending_break_ms.saturating_add(break_ms(&attrs));
                 }
                 ParserEvent::Open(elem) => {
-                    let parent = stack.last().unwrap().clone();
+                    let parent = stack.last().cloned().unwrap_or_default();
                     let mut frame = parent.clone();
 
                     match &elem {
@@ -429,11 +661,16 @@ impl CommandRunner for Tokenize {
             return self.forward_ssml(input).await;
         }
 
+        // Nothing to tokenize; whitespace passes through as is
+        if input.trim().is_empty() {
+            return Ok(input.into());
+        }
+
+        let mut output_rx = self.output_rx.lock().await;
         self.input_tx
             .send(Some(input))
             .await
             .expect("input tx send");
-        let mut output_rx = self.output_rx.lock().await;
         let output = output_rx.recv().await.expect("output rx recv");
 
         Ok(output.unwrap_or_else(|| "".to_string()).into())
@@ -622,6 +859,32 @@ fn inject_break_after_tag(fragment: &str, ms: u32) -> String {
     out
 }
 
+#[cfg(test)]
+mod lookup_tests {
+    use super::*;
+
+    #[test]
+    fn truncates_long_inputs_on_a_char_boundary() {
+        assert_eq!(truncate_input("girji+N+Sg", 256), "girji+N+Sg");
+        assert_eq!(truncate_input("", 0), "");
+        assert_eq!(truncate_input("áššiN", 4), "ášši");
+        let config: LookupConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config.max_input_chars, 256);
+    }
+
+    #[test]
+    fn only_generation_is_truncated() {
+        let lemma = "girji".repeat(100);
+        let lookup = Lookup::canned([
+            (lemma.clone(), vec!["analysed".to_string()]),
+            (lemma[..256].to_string(), vec!["generated".to_string()]),
+        ]);
+        assert_eq!(lookup_tags(&lookup, &lemma, false), ["analysed"]);
+        assert_eq!(generate(&lookup, &lemma), ["generated"]);
+        assert!(lookup.truncated.load(Ordering::Relaxed));
+    }
+}
+
 #[cfg(all(test, feature = "mod-ssml"))]
 mod tests {
     use super::*;
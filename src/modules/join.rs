@@ -0,0 +1,160 @@
+//! Fan-in for commands with several inputs.
+//!
+//! A command whose `input` lists several refs gets one
+//! [`PipelineValue::Multiple`] per document, holding what each input gave
+//! for it. The inputs run at their own pace, so [`join`] buffers each one's
+//! finished documents until all of them have finished the same document.
+
+use std::collections::VecDeque;
+
+use futures_util::{StreamExt, stream};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+
+use super::{Error, PipelineEvent, PipelineValue, PipelineValueRx, PipelineValueTx};
+
+/// What one input gave for one document.
+#[derive(Debug, Default)]
+struct Part {
+    values: Vec<PipelineValue>,
+    cancelled: bool,
+}
+
+impl Part {
+    /// The input's value in the joined value: its only value, or
+    /// `Multiple` of them when it gave none or several.
+    fn into_value(mut self) -> PipelineValue {
+        if self.values.len() == 1 {
+            self.values.remove(0)
+        } else {
+            PipelineValue::Multiple(self.values)
+        }
+    }
+}
+
+/// Join `inputs` into one stream on `output` for `command`: a `Value` of
+/// `Multiple` and a `Finish` per document, or a `Cancel` when any input
+/// cancelled it. The first `Error` is passed on and ends the join; `Close`
+/// is passed on once every input has closed.
+pub(crate) fn join(
+    command: &str,
+    inputs: Vec<PipelineValueRx>,
+    output: PipelineValueTx,
+) -> JoinHandle<Result<(), Error>> {
+    let command = command.to_string();
+    let count = inputs.len();
+    let mut events = stream::select_all(inputs.into_iter().enumerate().map(|(i, rx)| {
+        stream::unfold(rx, move |mut rx| async move {
+            let event = rx.recv().await;
+            Some(((i, event), rx))
+        })
+        .boxed()
+    }));
+
+    tokio::spawn(async move {
+        let mut current = (0..count).map(|_| Part::default()).collect::<Vec<_>>();
+        let mut finished = (0..count).map(|_| VecDeque::new()).collect::<Vec<_>>();
+        let mut closed = vec![false; count];
+
+        while let Some((i, event)) = events.next().await {
+            let event = match event {
+                Ok(event) => event,
+                Err(RecvError::Closed) => return Ok(()),
+                Err(e) => return Err(crate::util::channel::recv_error(&command, &output, e)),
+            };
+            match event {
+                PipelineEvent::Value(value) => current[i].values.push(value),
+                PipelineEvent::Finish => finished[i].push_back(std::mem::take(&mut current[i])),
+                PipelineEvent::Cancel => {
+                    let mut part = std::mem::take(&mut current[i]);
+                    part.cancelled = true;
+                    finished[i].push_back(part);
+                }
+                PipelineEvent::Error(e) => {
+                    output
+                        .send(PipelineEvent::Error(e.clone()))
+                        .map_err(Error::wrap)?;
+                    return Err(e);
+                }
+                PipelineEvent::Close => {
+                    closed[i] = true;
+                    if closed.iter().all(|x| *x) {
+                        output.send(PipelineEvent::Close).map_err(Error::wrap)?;
+                        return Ok(());
+                    }
+                }
+            }
+
+            while finished.iter().all(|x| !x.is_empty()) {
+                let parts = finished
+                    .iter_mut()
+                    .filter_map(|x| x.pop_front())
+                    .collect::<Vec<_>>();
+                if parts.iter().any(|x| x.cancelled) {
+                    output.send(PipelineEvent::Cancel).map_err(Error::wrap)?;
+                    continue;
+                }
+                let value =
+                    PipelineValue::Multiple(parts.into_iter().map(Part::into_value).collect());
+                output
+                    .send(PipelineEvent::Value(value))
+                    .map_err(Error::wrap)?;
+                output.send(PipelineEvent::Finish).map_err(Error::wrap)?;
+            }
+        }
+        Ok(())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::sync::broadcast;
+
+    use super::*;
+
+    fn text(x: &str) -> PipelineEvent {
+        PipelineEvent::Value(PipelineValue::String(x.into()))
+    }
+
+    fn texts(value: &PipelineValue) -> Vec<String> {
+        match value {
+            PipelineValue::Multiple(x) => x.iter().map(|x| x.to_string()).collect(),
+            other => panic!("expected multiple values, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_each_document_once_every_input_finished_it() {
+        let (a_tx, a_rx) = broadcast::channel(16);
+        let (b_tx, b_rx) = broadcast::channel(16);
+        let (out_tx, mut out_rx) = broadcast::channel(16);
+        let task = join("test", vec![a_rx, b_rx], out_tx);
+
+        // `a` is a document ahead of `b`
+        for event in [text("lean"), PipelineEvent::Finish, text("boahtan")] {
+            a_tx.send(event).unwrap();
+        }
+        for event in [text("V"), text("Ind"), PipelineEvent::Finish] {
+            b_tx.send(event).unwrap();
+        }
+        match out_rx.recv().await.unwrap() {
+            PipelineEvent::Value(value) => assert_eq!(texts(&value), ["lean", "V\nInd"]),
+            other => panic!("expected a value, got {:?}", other),
+        }
+        assert!(matches!(
+            out_rx.recv().await.unwrap(),
+            PipelineEvent::Finish
+        ));
+
+        a_tx.send(PipelineEvent::Finish).unwrap();
+        b_tx.send(PipelineEvent::Cancel).unwrap();
+        assert!(matches!(
+            out_rx.recv().await.unwrap(),
+            PipelineEvent::Cancel
+        ));
+
+        a_tx.send(PipelineEvent::Close).unwrap();
+        b_tx.send(PipelineEvent::Close).unwrap();
+        assert!(matches!(out_rx.recv().await.unwrap(), PipelineEvent::Close));
+        task.await.unwrap().unwrap();
+    }
+}
@@ -83,12 +83,12 @@ impl CommandRunner for Jq {
             })
             .collect();
 
-        let results = results?;
+        let mut results = results?;
 
         // Return results based on count
         match results.len() {
             0 => Ok(PipelineValue::Json(serde_json::Value::Null).into()),
-            1 => Ok(PipelineValue::Json(results.into_iter().next().unwrap()).into()),
+            1 => Ok(PipelineValue::Json(results.remove(0)).into()),
             _ => Ok(PipelineValue::Json(serde_json::Value::Array(results)).into()),
         }
     }
@@ -13,17 +13,24 @@ use std::{
 use once_cell::sync::Lazy;
 
 use async_trait::async_trait;
-use box_format::{BoxFileReader, BoxPath, Compression};
-use mmap_io::{MemoryMappedFile, segment::Segment};
+use box_format::{BoxFileReader, BoxPath};
+use mmap_io::segment::Segment;
 use tokio::{
-    io::AsyncReadExt,
     sync::broadcast::{Receiver, Sender},
     task::JoinHandle,
 };
 
 use crate::{
     ast::{self, Command, PipelineBundle, PipelineDefinition},
-    util::SharedBox,
+    util::{
+        SharedBox,
+        asset_cache::AssetCache,
+        channel::Channels,
+        download::Downloads,
+        integrity::{CHECKSUMS_FILE, Checksums, Integrity, VerifyMode},
+        priority::{self, Priority},
+        shutdown::Tasks,
+    },
 };
 
 // Simple glob matching for patterns like "errors-*.ftl"
@@ -40,6 +47,9 @@ fn glob_match(pattern: &str, text: &str) -> bool {
 
 pub mod debug;
 pub mod example;
+pub(crate) mod join;
+pub(crate) mod protocol;
+mod resolver;
 pub mod runtime;
 pub mod spell;
 
@@ -64,14 +74,31 @@ pub mod speech;
 #[cfg(feature = "mod-ssml")]
 pub mod ssml;
 
+pub use resolver::AssetResolver;
+
 pub type PipelineValueFut = Pin<Box<dyn Future<Output = Result<PipelineValue, Error>> + Send>>;
 pub type SharedPipelineValueFut =
     SharedBox<dyn Future<Output = Result<PipelineValue, Error>> + Send>;
 
+/// Events flowing along each edge of a pipeline.
+///
+/// The protocol, per edge:
+///
+/// - An input is one or more `Value`s followed by exactly one `Finish`. A
+///   command emits zero or more `Value`s for it and then exactly one `Finish`,
+///   after all of that input's values. Inputs may be pipelined, but outputs
+///   keep input order.
+/// - `Cancel` abandons the current input. It is passed downstream and stands
+///   in for that input's `Finish`.
+/// - `Error` and `Close` are terminal: nothing may follow them.
+///
+/// Debug and test builds check every edge with
+/// [`protocol::ProtocolValidator`](crate::modules::protocol).
 #[derive(Debug, Clone)]
 pub enum PipelineEvent {
     Value(PipelineValue),
     Error(Error),
+    /// Ends the current input; all of its values have been sent.
     Finish,
     /// "Stop the work you're doing for this forward() call." Discard any in-flight
     /// emission, forward downstream, then wait for the next value. The pipeline
@@ -91,6 +118,99 @@ pub struct AudioBuffer {
     pub channels: u16,
     /// Optional word ranges expressed as sample indices into `samples`.
     pub word_timings: Vec<AudioWordTiming>,
+    /// The pieces the text was synthesized in, when it was too long for the
+    /// voice model at once. Empty when it was synthesized whole. WAV output
+    /// carries them as labelled cue points, see [`AudioChunk::read_wav`].
+    pub chunks: Vec<AudioChunk>,
+}
+
+/// Text synthesized on its own and its half-open sample range in an
+/// [`AudioBuffer`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AudioChunk {
+    pub text: String,
+    pub start_sample: usize,
+    pub end_sample: usize,
+}
+
+impl AudioChunk {
+    /// The chunks of WAV bytes from [`AudioBuffer::to_wav_bytes`]: its cue
+    /// points with their `labl` text and `ltxt` length. Empty when the audio
+    /// was synthesized whole or the bytes aren't WAV.
+    pub fn read_wav(wav: &[u8]) -> Vec<AudioChunk> {
+        if !wav.starts_with(b"RIFF") || wav.get(8..12) != Some(&b"WAVE"[..]) {
+            return Vec::new();
+        }
+
+        let mut channels = 1;
+        let mut cues = Vec::new();
+        let mut labels = HashMap::new();
+        let mut lengths = HashMap::new();
+        for (id, body) in riff_chunks(&wav[12..]) {
+            match id {
+                b"fmt " => {
+                    channels = body
+                        .get(2..4)
+                        .map_or(1, |x| u16::from_le_bytes([x[0], x[1]]).max(1) as usize)
+                }
+                b"cue " => {
+                    let count = le_u32(body, 0).unwrap_or(0) as usize;
+                    for i in 0..count {
+                        let point = 4 + i * 24;
+                        if let (Some(id), Some(position)) =
+                            (le_u32(body, point), le_u32(body, point + 4))
+                        {
+                            cues.push((id, position as usize));
+                        }
+                    }
+                }
+                b"LIST" if body.starts_with(b"adtl") => {
+                    for (id, body) in riff_chunks(&body[4..]) {
+                        let Some(cue) = le_u32(body, 0) else {
+                            continue;
+                        };
+                        match id {
+                            b"labl" => {
+                                let text = body[4..].split(|x| *x == 0).next().unwrap_or_default();
+                                labels.insert(cue, String::from_utf8_lossy(text).into_owned());
+                            }
+                            b"ltxt" => {
+                                lengths.insert(cue, le_u32(body, 4).unwrap_or(0) as usize);
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        cues.into_iter()
+            .map(|(id, position)| AudioChunk {
+                text: labels.remove(&id).unwrap_or_default(),
+                start_sample: position * channels,
+                end_sample: (position + lengths.get(&id).copied().unwrap_or(0)) * channels,
+            })
+            .collect()
+    }
+}
+
+/// The IDs and bodies of the RIFF chunks in `bytes`.
+fn riff_chunks(bytes: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    let mut at = 0;
+    while let (Some(id), Some(size)) = (bytes.get(at..at + 4), le_u32(bytes, at + 4)) {
+        let start = at + 8;
+        let end = start.saturating_add(size as usize).min(bytes.len());
+        chunks.push((id, &bytes[start..end]));
+        at = end + (size as usize & 1);
+    }
+    chunks
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let x = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes([x[0], x[1], x[2], x[3]]))
 }
 
 /// A word and its half-open sample range in an [`AudioBuffer`].
@@ -140,10 +260,14 @@ impl AudioBuffer {
             .sample_rate
             .checked_mul(block_align as u32)
             .ok_or_else(|| IoError::new(ErrorKind::InvalidInput, "invalid WAV byte rate"))?;
-        let file_size = 36_u32.checked_add(data_size).ok_or_else(|| {
-            IoError::new(ErrorKind::InvalidInput, "audio data exceeds WAV limits")
-        })?;
-        let mut output = Vec::with_capacity(44 + data_size as usize);
+        let markers = self.chunk_markers()?;
+        let file_size = 36_u32
+            .checked_add(data_size)
+            .and_then(|size| size.checked_add(u32::try_from(markers.len()).ok()?))
+            .ok_or_else(|| {
+                IoError::new(ErrorKind::InvalidInput, "audio data exceeds WAV limits")
+            })?;
+        let mut output = Vec::with_capacity(44 + data_size as usize + markers.len());
 
         output.write_all(b"RIFF")?;
         output.write_all(&file_size.to_le_bytes())?;
@@ -161,20 +285,109 @@ impl AudioBuffer {
         for sample in &self.samples {
             output.write_all(&sample.to_le_bytes())?;
         }
+        output.write_all(&markers)?;
 
         Ok(output)
     }
+
+    /// A `cue ` chunk with a cue point at the start of each of `chunks` and a
+    /// `LIST` chunk labelling each with its text and length, which audio
+    /// editors show as regions. Empty without chunks.
+    fn chunk_markers(&self) -> std::io::Result<Vec<u8>> {
+        use std::io::{Error as IoError, ErrorKind};
+
+        if self.chunks.is_empty() {
+            return Ok(Vec::new());
+        }
+        let too_long = || IoError::new(ErrorKind::InvalidInput, "audio chunks exceed WAV limits");
+        let frame = |sample: usize| u32::try_from(sample / self.channels as usize);
+        let count = u32::try_from(self.chunks.len()).map_err(|_| too_long())?;
+
+        let mut cue = Vec::new();
+        cue.extend(count.to_le_bytes());
+        let mut labels = b"adtl".to_vec();
+        for (id, chunk) in (1_u32..).zip(&self.chunks) {
+            let start = frame(chunk.start_sample).map_err(|_| too_long())?;
+            let end = frame(chunk.end_sample).map_err(|_| too_long())?;
+            cue.extend(id.to_le_bytes());
+            cue.extend(start.to_le_bytes());
+            cue.extend(b"data");
+            cue.extend(0_u32.to_le_bytes());
+            cue.extend(0_u32.to_le_bytes());
+            cue.extend(start.to_le_bytes());
+
+            let mut label = id.to_le_bytes().to_vec();
+            label.extend(chunk.text.as_bytes());
+            label.push(0);
+            push_riff_chunk(&mut labels, b"labl", &label)?;
+
+            let mut region = id.to_le_bytes().to_vec();
+            region.extend(end.saturating_sub(start).to_le_bytes());
+            region.extend(b"rgn ");
+            // Country, language, dialect and code page
+            region.extend([0; 8]);
+            push_riff_chunk(&mut labels, b"ltxt", &region)?;
+        }
+
+        let mut markers = Vec::new();
+        push_riff_chunk(&mut markers, b"cue ", &cue)?;
+        push_riff_chunk(&mut markers, b"LIST", &labels)?;
+        Ok(markers)
+    }
+}
+
+/// Append a RIFF chunk, padded to an even length.
+fn push_riff_chunk(output: &mut Vec<u8>, id: &[u8; 4], body: &[u8]) -> std::io::Result<()> {
+    let size = u32::try_from(body.len()).map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "audio chunks exceed WAV limits",
+        )
+    })?;
+    output.extend(id);
+    output.extend(size.to_le_bytes());
+    output.extend(body);
+    if body.len() % 2 == 1 {
+        output.push(0);
+    }
+    Ok(())
 }
 
 /// A single value flowing through a pipeline. Multiplicity is expressed via
 /// `PipelineValues` at the return-type level (see `CommandRunner::forward`),
 /// not via dedicated array variants.
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub enum PipelineValue {
     String(String),
     Bytes(Vec<u8>),
     Json(serde_json::Value),
     Audio(AudioBuffer),
+    /// The input of a command with several inputs: what each of them gave
+    /// for one document, in the order the command lists them.
+    Multiple(Vec<PipelineValue>),
+}
+
+/// Text and JSON are summarized in privacy mode (see
+/// [`crate::util::privacy`]), so logging a value can't leak user input.
+impl std::fmt::Debug for PipelineValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use crate::util::privacy;
+
+        match self {
+            PipelineValue::String(x) => f.debug_tuple("String").field(&privacy::redact(x)).finish(),
+            PipelineValue::Bytes(x) if privacy::is_enabled() => {
+                write!(f, "Bytes(<redacted: {} bytes>)", x.len())
+            }
+            PipelineValue::Bytes(x) => f.debug_tuple("Bytes").field(x).finish(),
+            PipelineValue::Json(x) if privacy::is_enabled() => f
+                .debug_tuple("Json")
+                .field(&privacy::redact(&x.to_string()))
+                .finish(),
+            PipelineValue::Json(x) => f.debug_tuple("Json").field(x).finish(),
+            PipelineValue::Audio(x) => f.debug_tuple("Audio").field(x).finish(),
+            PipelineValue::Multiple(x) => f.debug_tuple("Multiple").field(x).finish(),
+        }
+    }
 }
 
 /// Ordered sequence of values produced by a single `forward()` call. A length-1
@@ -210,9 +423,7 @@ impl Display for PipelineValue {
             match self {
                 PipelineValue::String(x) => write!(f, "{}", x),
                 PipelineValue::Bytes(x) => write!(f, "<<{} bytes>>", x.len()),
-                PipelineValue::Json(x) => {
-                    write!(f, "{}", serde_json::to_string_pretty(&x).unwrap())
-                }
+                PipelineValue::Json(x) => write!(f, "{:#}", x),
                 PipelineValue::Audio(x) => write!(
                     f,
                     "<<{} audio samples, {} Hz, {} channel(s)>>",
@@ -220,12 +431,21 @@ impl Display for PipelineValue {
                     x.sample_rate,
                     x.channels
                 ),
+                PipelineValue::Multiple(x) => {
+                    for (i, value) in x.iter().enumerate() {
+                        if i > 0 {
+                            writeln!(f)?;
+                        }
+                        write!(f, "{:#}", value)?;
+                    }
+                    Ok(())
+                }
             }
         } else {
             match self {
                 PipelineValue::String(x) => write!(f, "{}", x),
                 PipelineValue::Bytes(x) => write!(f, "<<{} bytes>>", x.len()),
-                PipelineValue::Json(x) => write!(f, "{}", serde_json::to_string(&x).unwrap()),
+                PipelineValue::Json(x) => write!(f, "{}", x),
                 PipelineValue::Audio(x) => write!(
                     f,
                     "<<{} audio samples, {} Hz, {} channel(s)>>",
@@ -233,6 +453,15 @@ impl Display for PipelineValue {
                     x.sample_rate,
                     x.channels
                 ),
+                PipelineValue::Multiple(x) => {
+                    for (i, value) in x.iter().enumerate() {
+                        if i > 0 {
+                            writeln!(f)?;
+                        }
+                        write!(f, "{}", value)?;
+                    }
+                    Ok(())
+                }
             }
         }
     }
@@ -267,14 +496,32 @@ pub enum ErrorKind {
 }
 
 /// A diagnostic error with location info
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct Error {
     kind: ErrorKind,
     location: ErrorLocation,
+    input: bool, // raised on a command's input, so the message may quote it
+}
+
+impl Error {
+    /// Whether the message is summarized, see [`Error::about_input`].
+    fn hidden(&self) -> bool {
+        self.input && crate::util::privacy::is_enabled()
+    }
+
+    fn message(&self) -> String {
+        match &self.kind {
+            ErrorKind::Msg(s) => s.clone(),
+            ErrorKind::Wrapped(e) => e.to_string(),
+        }
+    }
 }
 
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.hidden() {
+            return write!(f, "{}", crate::util::privacy::redact(&self.message()));
+        }
         match &self.kind {
             ErrorKind::Msg(s) => write!(f, "{}", s),
             ErrorKind::Wrapped(e) => write!(f, "{}", e),
@@ -282,10 +529,24 @@ impl std::fmt::Display for Error {
     }
 }
 
+impl std::fmt::Debug for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut x = f.debug_struct("Error");
+        if self.hidden() {
+            x.field("kind", &crate::util::privacy::redact(&self.message()));
+        } else {
+            x.field("kind", &self.kind);
+        }
+        x.field("location", &self.location).finish()
+    }
+}
+
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match &self.kind {
             ErrorKind::Msg(_) => None,
+            // The causes are as likely to quote the input
+            ErrorKind::Wrapped(_) if self.hidden() => None,
             ErrorKind::Wrapped(e) => e.source(),
         }
     }
@@ -312,6 +573,7 @@ impl Error {
         Error {
             kind: ErrorKind::Msg(msg.into()),
             location: ErrorLocation::default(),
+            input: false,
         }
     }
 
@@ -334,16 +596,62 @@ impl Error {
         self
     }
 
+    /// Place the error under the command `key` of pipeline.json: argument
+    /// paths like `/args/model_path` become `/commands/<key>/args/model_path`,
+    /// and errors without a location point at the command itself.
+    pub fn in_command(mut self, key: &str) -> Self {
+        if self.location.file.is_empty() && self.location.path.is_empty() {
+            self.location.file = "pipeline.json".to_string();
+            self.location.path = format!("/commands/{}", key);
+        } else if self.location.file == "pipeline.json" && self.location.path.starts_with("/args") {
+            self.location.path = format!("/commands/{}{}", key, self.location.path);
+        }
+        self
+    }
+
+    pub fn location(&self) -> &ErrorLocation {
+        &self.location
+    }
+
+    /// Mark the error as raised while a command handled its input. Modules
+    /// quote the input in their errors freely, so in privacy mode (see
+    /// [`crate::util::privacy`]) such an error formats as a summary of its
+    /// message. Done for every error of `forward` by the code running it.
+    pub fn about_input(mut self) -> Self {
+        self.input = true;
+        self
+    }
+
     /// Wrap an error
     pub fn wrap<E: std::error::Error + Send + Sync + 'static>(err: E) -> Self {
         Error {
             kind: ErrorKind::Wrapped(Arc::new(err)),
             location: ErrorLocation::default(),
+            input: false,
+        }
+    }
+
+    /// The wrapped error, if it is a `T`.
+    pub(crate) fn downcast_ref<T: std::error::Error + 'static>(&self) -> Option<&T> {
+        match &self.kind {
+            ErrorKind::Msg(_) => None,
+            ErrorKind::Wrapped(e) => e.downcast_ref(),
         }
     }
 }
 
 impl PipelineValue {
+    /// Name of the value's type, as in an entry's `value_type`.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            PipelineValue::String(_) => "string",
+            PipelineValue::Bytes(_) => "bytes",
+            PipelineValue::Json(_) => "json",
+            PipelineValue::Audio(_) => "audio",
+            PipelineValue::Multiple(_) => "multiple",
+        }
+    }
+
     pub fn try_into_string(self) -> Result<String, Error> {
         match self {
             PipelineValue::String(x) => Ok(x),
@@ -371,6 +679,14 @@ impl PipelineValue {
             _ => Err(Error::msg("Could not convert input to audio")),
         }
     }
+
+    /// The values of a command's inputs, for a command with several.
+    pub fn try_into_multiple(self) -> Result<Vec<PipelineValue>, Error> {
+        match self {
+            PipelineValue::Multiple(x) => Ok(x),
+            _ => Err(Error::msg("Could not convert input to multiple values")),
+        }
+    }
 }
 
 impl From<String> for PipelineValue {
@@ -444,64 +760,70 @@ impl From<Vec<PipelineValue>> for PipelineValues {
 pub enum DataRef {
     BoxFile(Box<BoxFileReader>),
     Path(PathBuf),
+    /// A bundle served over HTTP, with assets downloaded on first use.
+    #[cfg(feature = "remote")]
+    Remote(Box<crate::util::remote::RemoteAssets>),
 }
 
 pub struct Context {
-    pub(crate) data: DataRef,
+    pub(crate) data: Box<dyn AssetResolver>,
     pub dev: bool,
     pub base_path: Option<PathBuf>,
+    /// Assets replaced by files on disk, keyed by their path inside the bundle
+    /// (e.g. `errors.json`). Consulted before the normal asset lookup, so a
+    /// modified FTL or CG file can be tested against a released .drb.
+    pub asset_overrides: HashMap<String, PathBuf>,
+    pub(crate) integrity: Integrity,
+    /// Values derived from assets, shared by every command using this context.
+    pub(crate) cache: AssetCache,
+    /// Assets downloaded on first use, from the bundle's `remote_assets`.
+    pub(crate) downloads: Downloads,
+    /// Pipelines running on this context's commands, for shutting down.
+    pub(crate) tasks: Tasks,
+    /// Capacity of the channels between the commands of its pipelines.
+    pub(crate) channels: Arc<Channels>,
 }
 
 impl Context {
-    pub async fn load_pipeline_bundle(&self) -> Result<PipelineBundle, Error> {
-        let bundle: PipelineBundle = match &self.data {
-            DataRef::BoxFile(bf) => {
-                let record = bf
-                    .find(
-                        &BoxPath::new("pipeline.json")
-                            .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?,
-                    )
-                    .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?
-                    .as_file()
-                    .unwrap();
-                let json: serde_json::Value = if record.compression == Compression::Stored {
-                    let m = bf
-                        .memory_map(&record)
-                        .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?;
-                    serde_json::from_slice(
-                        m.as_slice()
-                            .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?,
-                    )
-                    .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?
-                } else {
-                    let mut buf = Vec::with_capacity(record.decompressed_length as _);
-                    let mut reader = bf
-                        .read_bytes(record)
-                        .await
-                        .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?;
-                    reader
-                        .read_to_end(&mut buf)
-                        .await
-                        .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?;
-                    serde_json::from_slice(&buf)
-                        .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?
-                };
-                PipelineBundle::from_json(json)
-                    .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?
-            }
-            DataRef::Path(p) => {
-                let p = p.join("pipeline.json");
-                let contents = tokio::fs::read(&p)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(p.display().to_string()))?;
-                let json: serde_json::Value = serde_json::from_slice(&contents)
-                    .map_err(|e| Error::wrap(e).at_file(p.display().to_string()))?;
-                PipelineBundle::from_json(json)
-                    .map_err(|e| Error::wrap(e).at_file(p.display().to_string()))?
-            }
-        };
+    /// Context for running commands outside a pipeline. Assets come from a
+    /// .drb bundle, or from a project directory's `assets/` (`@` paths are
+    /// relative to the directory itself).
+    pub async fn standalone(path: &Path) -> Result<Self, Error> {
+        if path.extension().map(|x| x.as_encoded_bytes()) == Some(b"drb") {
+            let box_file = BoxFileReader::open(path)
+                .await
+                .map_err(|e| Error::wrap(e).at_file(path.display().to_string()))?;
+            return Ok(Context::from_resolver(DataRef::BoxFile(Box::new(box_file))));
+        }
+
+        Ok(Context {
+            dev: true,
+            base_path: Some(path.to_path_buf()),
+            ..Context::from_resolver(DataRef::Path(path.to_path_buf()))
+        })
+    }
+
+    /// Context reading its assets from `resolver`, e.g. an in-memory set of
+    /// files in a unit test. Not a dev context, so `@` paths are refused.
+    pub fn from_resolver(resolver: impl AssetResolver + 'static) -> Self {
+        Context {
+            data: Box::new(resolver),
+            dev: false,
+            base_path: None,
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
+        }
+    }
 
-        Ok(bundle)
+    pub async fn load_pipeline_bundle(&self) -> Result<PipelineBundle, Error> {
+        let contents = self.data.load_pipeline().await?;
+        let json: serde_json::Value = serde_json::from_slice(&contents)
+            .map_err(|e| Error::wrap(e).at_file("pipeline.json"))?;
+        PipelineBundle::from_json(json).map_err(|e| Error::wrap(e).at_file("pipeline.json"))
     }
 
     pub async fn load_pipeline_definition(&self) -> Result<PipelineDefinition, Error> {
@@ -556,28 +878,136 @@ impl Context {
         Ok(pipeline)
     }
 
-    fn resolve_path(&self, path: &str) -> Result<PathBuf, Error> {
-        if path.starts_with('@') {
-            // @ prefix - only allowed in dev mode
-            if !self.dev {
-                return Err(
-                    Error::msg("@ prefix paths are only allowed in dev pipelines").at_file(path),
-                );
+    /// Whether `path` is read from the local filesystem even when the context
+    /// is backed by a bundle (dev `@` paths, overridden and remote assets).
+    fn is_on_disk(&self, path: &str) -> bool {
+        path.starts_with('@')
+            || self.asset_overrides.contains_key(path)
+            || self.downloads.contains(path)
+    }
+
+    /// Download `path` if it is a remote asset or the context is a remote
+    /// bundle, and it isn't cached yet. The async loaders do this themselves;
+    /// call it before [`Context::load_fst`], which can't.
+    pub(crate) async fn prefetch(&self, path: &str) -> Result<(), Error> {
+        self.prefetch_remote(path).await?;
+        if self.is_on_disk(path) {
+            return Ok(());
+        }
+        self.data.prefetch(path).await
+    }
+
+    /// Download `path` if it is a remote asset that isn't overridden.
+    async fn prefetch_remote(&self, path: &str) -> Result<(), Error> {
+        if self.asset_overrides.contains_key(path) {
+            return Ok(());
+        }
+        self.downloads.fetch(path).await
+    }
+
+    /// Read the checksum manifest of a bundle, if it has one, and set up
+    /// verification. With [`VerifyMode::Eager`] every listed asset is checked
+    /// immediately.
+    pub(crate) async fn init_integrity(&mut self, mode: VerifyMode) -> Result<(), Error> {
+        if self.data.box_file().is_none() || mode == VerifyMode::Off {
+            self.integrity = Integrity::new(mode, None);
+            return Ok(());
+        }
+
+        let checksums = match self.load_file_optional(CHECKSUMS_FILE).await? {
+            Some(buf) => Some(
+                serde_json::from_slice::<Checksums>(&buf)
+                    .map_err(|e| Error::wrap(e).at_file(CHECKSUMS_FILE))?,
+            ),
+            None => {
+                tracing::debug!("Bundle has no {}, skipping verification", CHECKSUMS_FILE);
+                None
             }
-            // Drop the @ and resolve relative to pipeline.ts location
-            let relative_path = &path[1..];
-            let base = self
-                .base_path
-                .as_ref()
-                .ok_or_else(|| Error::msg("base_path not set for dev context"))?;
-            Ok(base.join(relative_path))
-        } else {
-            // Regular path - loads from assets/
-            match &self.data {
-                DataRef::BoxFile(_) => Ok(PathBuf::from(path)),
-                DataRef::Path(p) => Ok(p.join("assets").join(path)),
+        };
+        self.integrity = Integrity::new(mode, checksums);
+
+        if mode == VerifyMode::Eager {
+            self.verify_assets().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Check every asset listed in the bundle's checksum manifest.
+    pub async fn verify_assets(&self) -> Result<(), Error> {
+        let Some(checksums) = &self.integrity.checksums else {
+            return Ok(());
+        };
+        for path in checksums.assets.keys() {
+            if self.is_on_disk(path) {
+                continue;
             }
+            self.memory_map_file(path).await?;
         }
+        Ok(())
+    }
+
+    /// Check an asset read from the bundle against its checksum. Only .drb
+    /// bundles carry a manifest.
+    fn check_asset(&self, path: &Path, bytes: &[u8]) -> Result<(), Error> {
+        if self.data.box_file().is_none() {
+            return Ok(());
+        }
+        self.integrity.check(&asset_key(path)?, bytes)
+    }
+
+    fn verify_mapped(&self, bf: &BoxFileReader, resolved: &Path) -> Result<(), Error> {
+        let key = asset_key(resolved)?;
+        if !self.integrity.needs_check(&key) {
+            return Ok(());
+        }
+        let path_display = resolved.display().to_string();
+        let bpath = BoxPath::new(resolved).map_err(|e| Error::wrap(e).at_file(&path_display))?;
+        let node = bf
+            .find(&bpath)
+            .map_err(|e| Error::wrap(e).at_file(&path_display))?;
+        let node = node
+            .as_file()
+            .ok_or_else(|| Error::msg("Not a file").at_file(&path_display))?;
+        let segment = bf
+            .memory_map(node)
+            .map_err(|e| Error::wrap(e).at_file(&path_display))?;
+        let bytes = segment
+            .as_slice()
+            .map_err(|e| Error::wrap(e).at_file(&path_display))?;
+        self.integrity.check(&key, bytes)
+    }
+
+    /// Where `path` is on the local filesystem, if it bypasses the resolver:
+    /// an overridden asset, a downloaded remote asset or a dev `@` path.
+    fn disk_path(&self, path: &str) -> Result<Option<PathBuf>, Error> {
+        if let Some(over) = self.asset_overrides.get(path) {
+            tracing::debug!("Asset override: {} -> {}", path, over.display());
+            return Ok(Some(over.clone()));
+        }
+        if let Some(cached) = self.downloads.path(path)? {
+            return Ok(Some(cached));
+        }
+
+        let Some(relative_path) = path.strip_prefix('@') else {
+            return Ok(None);
+        };
+        // @ prefix - only allowed in dev mode
+        if !self.dev {
+            return Err(
+                Error::msg("@ prefix paths are only allowed in dev pipelines").at_file(path),
+            );
+        }
+        // Drop the @ and resolve relative to pipeline.ts location
+        let base = self
+            .base_path
+            .as_ref()
+            .ok_or_else(|| Error::msg("base_path not set for dev context"))?;
+        Ok(Some(base.join(relative_path)))
+    }
+
+    fn path_str(path: &Path) -> Result<&str, Error> {
+        path.to_str().ok_or_else(|| Error::msg("Invalid path"))
     }
 
     /// Load a divvun-fst model from either the ordinary assets directory or
@@ -588,193 +1018,118 @@ impl Context {
         T: divvun_fst::transducer::TransducerLoader<std::fs::File>
             + divvun_fst::transducer::TransducerLoader<divvun_fst::vfs::boxf::File>,
     {
-        let path_str = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::msg("Invalid path"))?;
-        let resolved = self.resolve_path(path_str)?;
+        let path_str = Self::path_str(path.as_ref())?;
 
-        match &self.data {
-            DataRef::BoxFile(bf) if !path_str.starts_with('@') => {
+        if let Some(bf) = self.data.box_file() {
+            if !self.is_on_disk(path_str) {
+                let resolved = Path::new(path_str);
+                self.verify_mapped(bf, resolved)?;
                 let reader = box_format::sync::BoxReader::open(bf.path())
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
+                    .map_err(|e| Error::wrap(e).at_file(path_str))?;
                 let fs = divvun_fst::vfs::boxf::Filesystem::new(&reader);
-                T::from_path(&fs, &resolved)
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))
+                return T::from_path(&fs, resolved).map_err(|e| Error::wrap(e).at_file(path_str));
             }
-            _ => T::from_path(&divvun_fst::vfs::Fs, &resolved)
-                .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string())),
         }
+
+        let resolved = match self.disk_path(path_str)? {
+            Some(resolved) => resolved,
+            None => self.data.extract(path_str)?,
+        };
+        T::from_path(&divvun_fst::vfs::Fs, &resolved)
+            .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))
     }
 
     pub async fn load_file(&self, path: impl AsRef<Path>) -> Result<Vec<u8>, Error> {
-        let path_str = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::msg("Invalid path"))?;
-        let resolved = self.resolve_path(path_str)?;
-
-        match &self.data {
-            DataRef::BoxFile(bf) => {
-                tracing::debug!("Loading file from box file: {}", resolved.display());
-                let record = bf
-                    .find(
-                        &BoxPath::new(&resolved)
-                            .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?,
-                    )
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?
-                    .as_file()
-                    .unwrap();
-                let mut reader = bf
-                    .read_bytes(record)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
-                let mut buf = Vec::new();
-                reader
-                    .read_to_end(&mut buf)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
-                Ok(buf)
-            }
-            DataRef::Path(_) => {
-                tracing::debug!("Loading file from path: {}", resolved.display());
-                tokio::fs::read(&resolved)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))
-            }
+        let path_str = Self::path_str(path.as_ref())?;
+        self.prefetch_remote(path_str).await?;
+        if let Some(resolved) = self.disk_path(path_str)? {
+            tracing::debug!("Loading file from path: {}", resolved.display());
+            return tokio::fs::read(&resolved)
+                .await
+                .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()));
         }
+
+        let buf = self
+            .data
+            .load_file(path_str)
+            .await?
+            .ok_or_else(|| Error::msg("No such asset").at_file(path_str))?;
+        self.check_asset(Path::new(path_str), &buf)?;
+        Ok(buf)
     }
 
     pub async fn load_file_optional(
         &self,
         path: impl AsRef<Path>,
     ) -> Result<Option<Vec<u8>>, Error> {
-        let path_str = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::msg("Invalid path"))?;
-        let resolved = self.resolve_path(path_str)?;
-
-        match &self.data {
-            DataRef::BoxFile(bf) if !path_str.starts_with('@') => {
-                let bpath = BoxPath::new(&resolved)
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
-                let Some(index) = bf.metadata().index(&bpath) else {
-                    return Ok(None);
-                };
-                let record = bf
-                    .metadata()
-                    .record(index)
-                    .and_then(|record| record.as_file())
-                    .ok_or_else(|| {
-                        Error::msg("Not a file").at_file(resolved.display().to_string())
-                    })?;
-                let mut reader = bf
-                    .read_bytes(record)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
-                let mut buf = Vec::new();
-                reader
-                    .read_to_end(&mut buf)
-                    .await
-                    .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?;
-                Ok(Some(buf))
-            }
-            _ => match tokio::fs::read(&resolved).await {
+        let path_str = Self::path_str(path.as_ref())?;
+        self.prefetch_remote(path_str).await?;
+        if let Some(resolved) = self.disk_path(path_str)? {
+            return match tokio::fs::read(&resolved).await {
                 Ok(contents) => Ok(Some(contents)),
                 Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
                 Err(e) => Err(Error::wrap(e).at_file(resolved.display().to_string())),
-            },
+            };
         }
+
+        let buf = self.data.load_file(path_str).await?;
+        if let Some(buf) = &buf {
+            self.check_asset(Path::new(path_str), buf)?;
+        }
+        Ok(buf)
     }
 
     pub async fn load_files_glob(&self, pattern: &str) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
-        match &self.data {
-            DataRef::BoxFile(bf) => {
-                // For box files, we need to iterate through entries and match the pattern
-                let mut files = Vec::new();
-                for entry in bf.metadata().iter() {
-                    let path_str = entry.path.to_string();
-                    if glob_match(pattern, &path_str) {
-                        if let Some(file_record) = entry.record.as_file() {
-                            let mut reader = bf
-                                .read_bytes(file_record)
-                                .await
-                                .map_err(|e| Error::wrap(e).at_file(&path_str))?;
-                            let mut buf = Vec::new();
-                            reader
-                                .read_to_end(&mut buf)
-                                .await
-                                .map_err(|e| Error::wrap(e).at_file(&path_str))?;
-                            files.push((PathBuf::from(path_str), buf));
-                        }
-                    }
-                }
-                Ok(files)
-            }
-            DataRef::Path(p) => {
-                // For regular paths, use filesystem globbing
-                let assets_dir = p.join("assets");
-                let full_pattern = assets_dir.join(pattern);
-                let mut files = Vec::new();
-
-                for entry in glob::glob(full_pattern.to_str().unwrap())
-                    .map_err(|e| Error::wrap(e).at_file(pattern))?
-                {
-                    let path = entry.map_err(Error::wrap)?;
-                    if path.is_file() {
-                        let contents = tokio::fs::read(&path)
-                            .await
-                            .map_err(|e| Error::wrap(e).at_file(path.display().to_string()))?;
-                        files.push((path, contents));
-                    }
-                }
-                Ok(files)
+        let mut files = self.data.glob(pattern).await?;
+        for (path, contents) in &files {
+            self.check_asset(path, contents)?;
+        }
+
+        // Overridden assets replace their bundled counterpart (matched by file
+        // name) or are added if the bundle doesn't have them.
+        for (key, over) in self.asset_overrides.iter() {
+            if !glob_match(pattern, key) {
+                continue;
             }
+            let contents = tokio::fs::read(over)
+                .await
+                .map_err(|e| Error::wrap(e).at_file(over.display().to_string()))?;
+            let name = Path::new(key).file_name();
+            files.retain(|(path, _)| path.file_name() != name);
+            files.push((PathBuf::from(key), contents));
         }
+
+        Ok(files)
     }
 
     pub async fn memory_map_file(&self, path: impl AsRef<Path>) -> Result<Segment, Error> {
-        let path_str = path
-            .as_ref()
-            .to_str()
-            .ok_or_else(|| Error::msg("Invalid path"))?;
-        let resolved = self.resolve_path(path_str)?;
-        let path_display = resolved.display().to_string();
-        match &self.data {
-            DataRef::BoxFile(bf) if !path_str.starts_with('@') => {
-                tracing::debug!("Memory mapping file from box: {}", resolved.display());
-                let bpath =
-                    BoxPath::new(&resolved).map_err(|e| Error::wrap(e).at_file(&path_display))?;
-                let node = bf
-                    .find(&bpath)
-                    .map_err(|e| Error::wrap(e).at_file(&path_display))?;
-                let node = node
-                    .as_file()
-                    .ok_or_else(|| Error::msg("Not a file").at_file(&path_display))?;
-
-                bf.memory_map(node)
-                    .map_err(|e| Error::wrap(e).at_file(&path_display))
-            }
-            _ => {
-                tracing::debug!("Memory mapping file: {}", resolved.display());
-                let full_path_clone = resolved.clone();
-                tokio::task::spawn_blocking(move || {
-                    let mmap =
-                        Arc::new(MemoryMappedFile::open_ro(&full_path_clone).map_err(|e| {
-                            Error::wrap(e).at_file(full_path_clone.display().to_string())
-                        })?);
-                    let len = mmap.len();
-                    Segment::new(mmap, 0, len)
-                        .map_err(|e| Error::wrap(e).at_file(full_path_clone.display().to_string()))
-                })
-                .await
-                .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))?
+        let path_str = Self::path_str(path.as_ref())?;
+        self.prefetch_remote(path_str).await?;
+        if let Some(resolved) = self.disk_path(path_str)? {
+            return resolver::map_file(resolved).await;
+        }
+
+        let segment = self.data.mmap(path_str).await?;
+        if self.data.box_file().is_some() {
+            let key = asset_key(Path::new(path_str))?;
+            if self.integrity.needs_check(&key) {
+                let bytes = segment
+                    .as_slice()
+                    .map_err(|e| Error::wrap(e).at_file(path_str))?;
+                self.integrity.check(&key, bytes)?;
             }
         }
+        Ok(segment)
     }
 }
 
+/// The key an asset is listed under in the bundle's checksum manifest.
+fn asset_key(resolved: &Path) -> Result<String, Error> {
+    BoxPath::new(resolved)
+        .map(|p| p.to_string())
+        .map_err(|e| Error::wrap(e).at_file(resolved.display().to_string()))
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Module {
     pub name: &'static str,
@@ -809,6 +1164,12 @@ pub struct CommandDef {
     pub input: &'static [Ty],
     pub args: &'static [Arg],
     pub assets: &'static [AssetDep],
+    /// Environment variables (name, value) the command's native library or
+    /// subprocess needs, passed to its `new` as [`declared_env`].
+    pub env: &'static [(&'static str, &'static str)],
+    /// Approximate resources the command needs once created, for
+    /// [preflight checks](crate::preflight).
+    pub resources: Resources,
     pub init: InitFn,
     pub returns: Ty,
     pub kind: Option<&'static str>,
@@ -818,6 +1179,30 @@ pub struct CommandDef {
     pub config_shape: Option<&'static facet::Shape>,
 }
 
+tokio::task_local! {
+    static DECLARED_ENV: &'static [(&'static str, &'static str)];
+}
+
+/// Environment variables (name, value) the command being created declared
+/// with `env`, for its `new` to hand to the library or subprocess that needs
+/// them. Empty outside `new`. The process environment is never changed.
+pub fn declared_env() -> &'static [(&'static str, &'static str)] {
+    DECLARED_ENV.try_with(|x| *x).unwrap_or_default()
+}
+
+impl CommandDef {
+    /// Create the command, with its `env` as the [`declared_env`] of `new`.
+    pub async fn create(
+        &self,
+        context: Arc<Context>,
+        kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        DECLARED_ENV
+            .scope(self.env, (self.init)(context, kwargs))
+            .await
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Arg {
     pub name: &'static str,
@@ -840,6 +1225,31 @@ pub struct StructField {
     pub optional: bool,
 }
 
+/// What a command needs from the host, from the `resources` of its
+/// `rt_command`. Sizes are rough, for the command's usual models.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Resources {
+    /// Bytes of memory, its models included.
+    pub memory: u64,
+    /// Bytes of disk for assets downloaded or extracted before use.
+    pub disk: u64,
+    pub gpu: Gpu,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Gpu {
+    #[default]
+    None,
+    /// Runs faster with a GPU.
+    Optional,
+    /// Can't run without a GPU.
+    Required,
+}
+
+/// An asset a command reads without it being named by one of its args, such
+/// as the error messages of `divvun::suggest`. Literal paths may hold a `*`,
+/// as for [`Context::load_files_glob`].
 #[derive(Debug, Clone)]
 pub enum AssetDep {
     Required(&'static str),      // required("file.json")
@@ -848,6 +1258,21 @@ pub enum AssetDep {
     OptionalRegex(&'static str), // optional(r"pattern")
 }
 
+impl AssetDep {
+    /// Whether the asset at `path` (relative to the assets directory) is
+    /// this dependency.
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            AssetDep::Required(pattern) | AssetDep::Optional(pattern) => glob_match(pattern, path),
+            AssetDep::RequiredRegex(pattern) | AssetDep::OptionalRegex(pattern) => {
+                regex::Regex::new(&format!("^(?:{pattern})$"))
+                    .map(|re| re.is_match(path))
+                    .unwrap_or(false)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Ty {
     Path,
@@ -964,6 +1389,46 @@ pub fn get_structs() -> impl Iterator<Item = &'static StructDef> {
     inventory::iter::<&StructDef>().copied()
 }
 
+/// Look up a command by module and name, e.g. `("divvun", "suggest")`.
+pub fn find_command(module: &str, command: &str) -> Option<&'static CommandDef> {
+    crate::ts::MODULES.get(module)?.get(command)
+}
+
+/// Construct a single command outside any pipeline and run one input through
+/// it, for testing a stage with hand-written input.
+pub async fn run_single(
+    context: Arc<Context>,
+    module: &str,
+    command: &str,
+    args: HashMap<String, ast::Arg>,
+    input: PipelineValue,
+    config: serde_json::Value,
+) -> Result<PipelineValues, Error> {
+    let def = find_command(module, command)
+        .ok_or_else(|| Error::msg(format!("Unknown command {}::{}", module, command)))?;
+
+    let missing: Vec<&str> = def
+        .args
+        .iter()
+        .filter(|arg| !arg.optional && !args.contains_key(arg.name))
+        .map(|arg| arg.name)
+        .collect();
+    if !missing.is_empty() {
+        return Err(Error::msg(format!(
+            "{}::{} is missing required argument(s): {}",
+            module,
+            command,
+            missing.join(", ")
+        )));
+    }
+
+    let runner = def.create(context, args).await?;
+    runner
+        .forward(input, Arc::new(config))
+        .await
+        .map_err(Error::about_input)
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TapOutput {
     #[default]
@@ -975,6 +1440,33 @@ pub type TapFn = dyn Fn(&str, &Command, &PipelineEvent) -> Pin<Box<dyn Future<Ou
     + Send
     + Sync;
 
+/// A command's runtime config, read again for every input so it can be
+/// replaced while the pipeline runs (see
+/// [`PipelineHandle::update_config`](crate::ast::PipelineHandle::update_config)).
+#[derive(Clone, Default)]
+pub struct LiveConfig(Arc<std::sync::RwLock<Arc<serde_json::Value>>>);
+
+impl LiveConfig {
+    pub fn new(config: serde_json::Value) -> Self {
+        LiveConfig(Arc::new(std::sync::RwLock::new(Arc::new(config))))
+    }
+
+    /// The config to run the next input with.
+    pub fn get(&self) -> Arc<serde_json::Value> {
+        self.0
+            .read()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn set(&self, config: serde_json::Value) {
+        *self
+            .0
+            .write()
+            .unwrap_or_else(std::sync::PoisonError::into_inner) = Arc::new(config);
+    }
+}
+
 #[derive(Clone)]
 pub struct Tap {
     pub key: Arc<str>,
@@ -982,6 +1474,12 @@ pub struct Tap {
     pub tap: Arc<TapFn>,
 }
 
+impl Tap {
+    pub fn header(&self) -> ast::StepHeader {
+        self.command.header(&self.key)
+    }
+}
+
 #[async_trait]
 pub trait CommandRunner: Any
 where
@@ -1000,7 +1498,7 @@ where
         mut input_rx: PipelineValueRx,
         output: PipelineValueTx,
         tap: Option<Tap>,
-        config: Arc<serde_json::Value>,
+        config: LiveConfig,
     ) -> JoinHandle<Result<(), Error>>
     where
         Self: Send + Sync + 'static,
@@ -1009,13 +1507,31 @@ where
         let name = self.name().to_string();
         tokio::spawn(async move {
             tracing::debug!("{name}: forward_stream task started");
+            // Priority picks the task's scheduling once; later config
+            // updates apply to `forward` only.
+            let priority = match Priority::from_config(&config.get()) {
+                Ok(priority) => priority,
+                Err(e) => {
+                    output
+                        .send(PipelineEvent::Error(e.clone()))
+                        .map_err(Error::wrap)?;
+                    return Err(e);
+                }
+            };
             loop {
-                let event = input_rx.recv().await.map_err(Error::wrap)?;
+                let event = input_rx
+                    .recv()
+                    .await
+                    .map_err(|e| crate::util::channel::recv_error(&name, &output, e))?;
                 let this = this.clone();
                 match event {
                     PipelineEvent::Value(input) => {
                         tracing::debug!("{name}: received input, forwarding");
-                        let outputs = match this.forward(input, config.clone()).await {
+                        let forward = this.forward(input, config.get());
+                        let outputs = match priority::scope(priority, forward)
+                            .await
+                            .map_err(Error::about_input)
+                        {
                             Ok(outputs) => {
                                 tracing::debug!(
                                     "{name}: forward produced {} value(s)",
@@ -1087,6 +1603,46 @@ where
 mod context_tests {
     use super::*;
 
+    /// Outputs the config it runs each input with.
+    struct ShowConfig;
+
+    #[async_trait]
+    impl CommandRunner for ShowConfig {
+        async fn forward(
+            self: Arc<Self>,
+            _input: PipelineValue,
+            config: Arc<serde_json::Value>,
+        ) -> Result<PipelineValues, Error> {
+            Ok(config.to_string().into())
+        }
+
+        fn name(&self) -> &'static str {
+            "test::show_config"
+        }
+    }
+
+    #[tokio::test]
+    async fn config_updates_apply_to_later_inputs() {
+        let (in_tx, in_rx) = tokio::sync::broadcast::channel(16);
+        let (out_tx, mut out_rx) = tokio::sync::broadcast::channel(16);
+        let config = LiveConfig::new(serde_json::json!({"locales": ["se"]}));
+        let _task = Arc::new(ShowConfig).forward_stream(in_rx, out_tx, None, config.clone());
+
+        let mut next = async |input: &str| {
+            in_tx
+                .send(PipelineEvent::Value(PipelineValue::String(input.into())))
+                .unwrap();
+            match out_rx.recv().await.unwrap() {
+                PipelineEvent::Value(PipelineValue::String(x)) => x,
+                other => panic!("unexpected {other:?}"),
+            }
+        };
+
+        assert_eq!(next("a").await, r#"{"locales":["se"]}"#);
+        config.set(serde_json::json!({"locales": ["en"]}));
+        assert_eq!(next("b").await, r#"{"locales":["en"]}"#);
+    }
+
     #[test]
     fn audio_buffer_serializes_as_float_wav() {
         let audio = AudioBuffer {
@@ -1098,6 +1654,7 @@ mod context_tests {
                 start_sample: 0,
                 end_sample: 2,
             }],
+            chunks: Vec::new(),
         };
         let wav = audio.to_wav_bytes().unwrap();
         let mut reader = hound::WavReader::new(std::io::Cursor::new(wav)).unwrap();
@@ -1115,6 +1672,40 @@ mod context_tests {
         );
     }
 
+    #[test]
+    fn wav_carries_audio_chunks() {
+        let chunks = vec![
+            AudioChunk {
+                text: "Mun boađán.".into(),
+                start_sample: 0,
+                end_sample: 3,
+            },
+            AudioChunk {
+                text: "Don vuolggát".into(),
+                start_sample: 4,
+                end_sample: 6,
+            },
+        ];
+        let audio = AudioBuffer {
+            samples: vec![0.0; 6],
+            sample_rate: 22_050,
+            channels: 1,
+            word_timings: Vec::new(),
+            chunks: chunks.clone(),
+        };
+        let wav = audio.to_wav_bytes().unwrap();
+
+        assert_eq!(AudioChunk::read_wav(&wav), chunks);
+        let mut reader = hound::WavReader::new(std::io::Cursor::new(&wav)).unwrap();
+        assert_eq!(reader.samples::<f32>().count(), 6);
+
+        let whole = AudioBuffer {
+            chunks: Vec::new(),
+            ..audio
+        };
+        assert!(AudioChunk::read_wav(&whole.to_wav_bytes().unwrap()).is_empty());
+    }
+
     #[tokio::test]
     async fn memory_map_file_resolves_asset_and_dev_paths() {
         let temp = tempfile::tempdir().unwrap();
@@ -1124,9 +1715,15 @@ mod context_tests {
         std::fs::write(temp.path().join("dev-model.bin"), b"dev model").unwrap();
 
         let context = Context {
-            data: DataRef::Path(temp.path().to_path_buf()),
+            data: Box::new(DataRef::Path(temp.path().to_path_buf())),
             dev: true,
             base_path: Some(temp.path().to_path_buf()),
+            asset_overrides: HashMap::new(),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
         };
 
         let asset = context.memory_map_file("model.bin").await.unwrap();
@@ -1148,4 +1745,278 @@ mod context_tests {
             None
         );
     }
+
+    #[tokio::test]
+    async fn asset_overrides_take_precedence_over_assets() {
+        let temp = tempfile::tempdir().unwrap();
+        let assets = temp.path().join("assets");
+        std::fs::create_dir(&assets).unwrap();
+        std::fs::write(assets.join("errors.json"), b"bundled").unwrap();
+        std::fs::write(assets.join("errors-en.ftl"), b"bundled ftl").unwrap();
+        let local = temp.path().join("local");
+        std::fs::create_dir(&local).unwrap();
+        std::fs::write(local.join("errors.json"), b"local").unwrap();
+        std::fs::write(local.join("errors-en.ftl"), b"local ftl").unwrap();
+
+        let context = Context {
+            data: Box::new(DataRef::Path(temp.path().to_path_buf())),
+            dev: false,
+            base_path: None,
+            asset_overrides: HashMap::from([
+                ("errors.json".to_string(), local.join("errors.json")),
+                ("errors-en.ftl".to_string(), local.join("errors-en.ftl")),
+            ]),
+            integrity: Default::default(),
+            cache: Default::default(),
+            downloads: Default::default(),
+            tasks: Default::default(),
+            channels: Default::default(),
+        };
+
+        assert_eq!(context.load_file("errors.json").await.unwrap(), b"local");
+        let ftl = context.load_files_glob("errors-*.ftl").await.unwrap();
+        assert_eq!(ftl.len(), 1);
+        assert_eq!(ftl[0].1, b"local ftl");
+    }
+
+    #[tokio::test]
+    async fn context_reads_through_its_resolver() {
+        let context = Context::from_resolver(resolver::MemoryAssets::new([
+            ("errors-en.ftl", "en"),
+            ("errors-se.ftl", "se"),
+            ("model.bin", "model"),
+        ]));
+
+        assert_eq!(context.load_file("model.bin").await.unwrap(), b"model");
+        assert!(context.load_file("missing.bin").await.is_err());
+        assert_eq!(
+            context.load_file_optional("missing.bin").await.unwrap(),
+            None
+        );
+
+        let ftl = context.load_files_glob("errors-*.ftl").await.unwrap();
+        assert_eq!(
+            ftl,
+            vec![
+                (PathBuf::from("errors-en.ftl"), b"en".to_vec()),
+                (PathBuf::from("errors-se.ftl"), b"se".to_vec()),
+            ]
+        );
+
+        let model = context.memory_map_file("model.bin").await.unwrap();
+        assert_eq!(&*model.as_slice().unwrap(), b"model");
+
+        let err = context.load_file("@model.bin").await.err().unwrap();
+        assert!(err.to_string().contains("only allowed in dev pipelines"));
+
+        // Closing removes what mapping extracted to disk
+        let extracted = context.data.extract("model.bin").unwrap();
+        assert!(extracted.exists());
+        let closed = crate::util::shutdown::finish(
+            crate::util::shutdown::close_context(&context),
+            std::time::Duration::ZERO,
+        )
+        .await;
+        assert_eq!(closed.removed, 1);
+        assert!(!extracted.exists());
+    }
+
+    #[tokio::test]
+    async fn remote_assets_are_read_from_the_download_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let hash = blake3::hash(b"voice").to_hex().to_string();
+        std::fs::create_dir(temp.path().join(&hash)).unwrap();
+        std::fs::write(temp.path().join(&hash).join("voice.onnx"), b"voice").unwrap();
+        let asset = ast::RemoteAsset {
+            url: "https://example.org/voice.onnx".to_string(),
+            hash,
+            size: Some(5),
+        };
+
+        let mut context =
+            Context::from_resolver(resolver::MemoryAssets::new([("voice.onnx", "bundled")]));
+        context.downloads = Downloads::new(
+            [("voice.onnx".to_string(), asset)].into_iter().collect(),
+            Some(temp.path().to_path_buf()),
+            None,
+        );
+
+        let voice = context.memory_map_file("voice.onnx").await.unwrap();
+        assert_eq!(&*voice.as_slice().unwrap(), b"voice");
+        assert_eq!(context.load_file("voice.onnx").await.unwrap(), b"voice");
+    }
+
+    #[tokio::test]
+    async fn run_single_runs_one_command_outside_a_pipeline() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+        let output = run_single(
+            context.clone(),
+            "example",
+            "reverse",
+            HashMap::new(),
+            PipelineValue::String("abc".into()),
+            serde_json::Value::Null,
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.0.len(), 1);
+        let value = output.into_iter().next().unwrap();
+        assert_eq!(value.try_into_string().unwrap(), "cba");
+
+        let err = run_single(
+            context,
+            "example",
+            "nope",
+            HashMap::new(),
+            PipelineValue::String("abc".into()),
+            serde_json::Value::Null,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("Unknown command example::nope"));
+    }
+
+    #[tokio::test]
+    async fn declared_env_is_passed_to_new_only() {
+        let def = CommandDef {
+            name: "env",
+            module: "test",
+            input: &[],
+            args: &[],
+            assets: &[],
+            env: &[("OMP_NUM_THREADS", "1")],
+            resources: Resources::default(),
+            // Reports what it was given as its error
+            init: |_, _| Box::pin(async { Err(Error::msg(format!("{:?}", declared_env()))) }),
+            returns: Ty::String,
+            kind: None,
+            schema: None,
+            config: None,
+            shape: None,
+            config_shape: None,
+        };
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+        let err = def.create(context, HashMap::new()).await.err().unwrap();
+        assert!(err.to_string().contains(r#"[("OMP_NUM_THREADS", "1")]"#));
+        assert!(declared_env().is_empty());
+    }
+}
+
+/// Degenerate input for every command that runs without a model, and for
+/// `divvun::suggest` with a canned one: each must finish promptly, and empty
+/// or blank input must give blank output.
+#[cfg(test)]
+mod edge_case_tests {
+    use super::*;
+
+    fn arg(value: &str) -> ast::Arg {
+        ast::Arg::new("string", value)
+    }
+
+    fn commands() -> Vec<(&'static str, &'static str, HashMap<String, ast::Arg>)> {
+        #[allow(unused_mut)]
+        let mut commands = vec![
+            ("example", "reverse", HashMap::new()),
+            ("example", "upper", HashMap::new()),
+        ];
+        #[cfg(feature = "mod-cg3")]
+        commands.extend([
+            ("cg3", "mwesplit", HashMap::new()),
+            (
+                "cg3",
+                "sentences",
+                HashMap::from([("mode".to_string(), arg("surface"))]),
+            ),
+            ("cg3", "to_json", HashMap::new()),
+            (
+                "cg3",
+                "streamcmd",
+                HashMap::from([("key".to_string(), arg("SETVAR"))]),
+            ),
+        ]);
+        #[cfg(feature = "mod-divvun")]
+        commands.push((
+            "divvun",
+            "case",
+            HashMap::from([("operation".to_string(), arg("detect"))]),
+        ));
+        #[cfg(feature = "mod-ssml")]
+        commands.push(("ssml", "strip", HashMap::new()));
+        commands
+    }
+
+    /// A fresh runner of each command, by name.
+    async fn runners(
+        context: &Arc<Context>,
+    ) -> Vec<(String, Arc<dyn CommandRunner + Send + Sync>)> {
+        let mut runners = Vec::new();
+        for (module, command, args) in commands() {
+            let def = find_command(module, command).unwrap();
+            let runner = def.create(context.clone(), args).await.unwrap();
+            runners.push((format!("{module}::{command}"), runner));
+        }
+        #[cfg(feature = "mod-divvun")]
+        runners.push((
+            "divvun::suggest".to_string(),
+            divvun::Suggest::canned(context.clone(), []).unwrap(),
+        ));
+        runners
+    }
+
+    async fn run(
+        (name, runner): (String, Arc<dyn CommandRunner + Send + Sync>),
+        input: &str,
+    ) -> PipelineValues {
+        let run = runner.forward(
+            PipelineValue::String(input.to_string()),
+            Arc::new(serde_json::Value::Null),
+        );
+        tokio::time::timeout(std::time::Duration::from_secs(10), run)
+            .await
+            .unwrap_or_else(|_| panic!("{name} hangs on {} bytes", input.len()))
+            .unwrap_or_else(|e| panic!("{name} fails on {} bytes: {e}", input.len()))
+    }
+
+    #[tokio::test]
+    async fn blank_input_gives_blank_output() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+        for input in ["", " \n\t \n"] {
+            for runner in runners(&context).await {
+                let name = runner.0.clone();
+                for value in run(runner, input).await {
+                    let blank = match &value {
+                        PipelineValue::String(x) => x.trim().is_empty(),
+                        // A grammar checker's report without errors
+                        PipelineValue::Json(x) => x
+                            .as_array()
+                            .or_else(|| x.get("errors").and_then(|x| x.as_array()))
+                            .is_some_and(|x| x.is_empty()),
+                        _ => false,
+                    };
+                    assert!(blank, "{} turns {:?} into {:?}", name, input, value);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn huge_tokens_finish() {
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let token = "girji".repeat(20_000);
+        let stream = format!("\"<{token}>\"\n\t\"{token}\" N Sg Nom SUGGEST <W:0.0>\n:\n");
+
+        for input in [&token, &stream] {
+            for runner in runners(&context).await {
+                run(runner, input).await;
+            }
+        }
+    }
 }
@@ -0,0 +1,179 @@
+//! Checks that commands follow the [`PipelineEvent`] protocol.
+//!
+//! In debug and test builds every edge of a pipeline goes through a relay
+//! task holding a [`ProtocolValidator`]. It watches what a command receives
+//! and what it emits, and turns the first violation into an error event
+//! instead of letting outputs drift onto the wrong `forward()` call.
+
+use tokio::{
+    sync::broadcast::{self, error::TryRecvError},
+    task::JoinHandle,
+};
+
+use super::{Error, PipelineEvent, PipelineValueRx, PipelineValueTx};
+
+#[derive(Debug, Default)]
+pub(crate) struct ProtocolValidator {
+    /// Values received since the last input Finish or Cancel.
+    open_in: bool,
+    finished_in: u64,
+    finished_out: u64,
+    /// Set once Close or Error has been emitted.
+    terminated: Option<&'static str>,
+}
+
+impl ProtocolValidator {
+    pub(crate) fn input(&mut self, event: &PipelineEvent) {
+        match event {
+            PipelineEvent::Value(_) => self.open_in = true,
+            PipelineEvent::Finish => {
+                self.open_in = false;
+                self.finished_in += 1;
+            }
+            PipelineEvent::Cancel => self.open_in = false,
+            PipelineEvent::Error(_) | PipelineEvent::Close => {}
+        }
+    }
+
+    pub(crate) fn output(&mut self, event: &PipelineEvent) -> Result<(), String> {
+        if let Some(terminal) = self.terminated {
+            return Err(format!("emitted {} after {}", event_name(event), terminal));
+        }
+
+        match event {
+            PipelineEvent::Value(_) => {
+                if !self.open_in && self.finished_out >= self.finished_in {
+                    return Err("emitted a Value with no input outstanding".to_string());
+                }
+            }
+            PipelineEvent::Finish => {
+                if self.finished_out >= self.finished_in {
+                    return Err(format!(
+                        "emitted Finish #{} but only received {}",
+                        self.finished_out + 1,
+                        self.finished_in
+                    ));
+                }
+                self.finished_out += 1;
+            }
+            PipelineEvent::Cancel => {}
+            PipelineEvent::Error(_) => self.terminated = Some("Error"),
+            PipelineEvent::Close => self.terminated = Some("Close"),
+        }
+        Ok(())
+    }
+}
+
+fn event_name(event: &PipelineEvent) -> &'static str {
+    match event {
+        PipelineEvent::Value(_) => "Value",
+        PipelineEvent::Error(_) => "Error",
+        PipelineEvent::Finish => "Finish",
+        PipelineEvent::Cancel => "Cancel",
+        PipelineEvent::Close => "Close",
+    }
+}
+
+/// Put a validator between `command` and `output`. Returns the sender the
+/// command should emit into, and the relay task.
+pub(crate) fn validate(
+    command: &str,
+    mut input: PipelineValueRx,
+    output: PipelineValueTx,
+    capacity: usize,
+) -> (PipelineValueTx, JoinHandle<Result<(), Error>>) {
+    let (tx, mut rx) = broadcast::channel(capacity);
+    let command = command.to_string();
+
+    let handle = tokio::spawn(async move {
+        let mut validator = ProtocolValidator::default();
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    let err = crate::util::channel::report_lag(&command, skipped);
+                    let _ = output.send(PipelineEvent::Error(err.clone()));
+                    return Err(err);
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            };
+
+            // Everything the command has acted on was sent to it before this
+            // output, so it's already buffered here.
+            loop {
+                match input.try_recv() {
+                    Ok(event) => validator.input(&event),
+                    Err(TryRecvError::Lagged(_)) => validator = ProtocolValidator::default(),
+                    Err(TryRecvError::Empty | TryRecvError::Closed) => break,
+                }
+            }
+
+            if let Err(violation) = validator.output(&event) {
+                let err = Error::msg(format!(
+                    "{} broke the pipeline event protocol: {}",
+                    command, violation
+                ));
+                tracing::error!("{}", err);
+                let _ = output.send(PipelineEvent::Error(err.clone()));
+                return Err(err);
+            }
+
+            let done = matches!(event, PipelineEvent::Close | PipelineEvent::Error(_));
+            output.send(event).map_err(Error::wrap)?;
+            if done {
+                return Ok(());
+            }
+        }
+    });
+
+    (tx, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modules::PipelineValue;
+
+    fn value() -> PipelineEvent {
+        PipelineEvent::Value(PipelineValue::String("x".into()))
+    }
+
+    #[test]
+    fn accepts_one_finish_per_input() {
+        let mut v = ProtocolValidator::default();
+        v.input(&value());
+        v.output(&value()).unwrap();
+        v.input(&PipelineEvent::Finish);
+        // Pipelined second input before the first finished on the output side.
+        v.input(&value());
+        v.output(&value()).unwrap();
+        v.output(&PipelineEvent::Finish).unwrap();
+        v.output(&value()).unwrap();
+        v.input(&PipelineEvent::Finish);
+        v.output(&PipelineEvent::Finish).unwrap();
+        v.output(&PipelineEvent::Close).unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicate_finish() {
+        let mut v = ProtocolValidator::default();
+        v.input(&value());
+        v.output(&value()).unwrap();
+        v.input(&PipelineEvent::Finish);
+        v.output(&PipelineEvent::Finish).unwrap();
+        assert!(v.output(&PipelineEvent::Finish).is_err());
+    }
+
+    #[test]
+    fn rejects_values_after_finish_or_close() {
+        let mut v = ProtocolValidator::default();
+        v.input(&value());
+        v.input(&PipelineEvent::Finish);
+        v.output(&PipelineEvent::Finish).unwrap();
+        assert!(v.output(&value()).is_err());
+
+        let mut v = ProtocolValidator::default();
+        v.output(&PipelineEvent::Close).unwrap();
+        assert!(v.output(&PipelineEvent::Finish).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+//! Where a [`Context`](super::Context) reads its assets from: a .drb bundle, a
+//! project directory, a remote bundle, or anything else implementing
+//! [`AssetResolver`].
+//!
+//! Resolvers only see asset paths, as written in pipeline arguments. Dev `@`
+//! paths, asset overrides and checksum verification are handled by the
+//! context before and after asking its resolver.
+
+use std::path::{Path, PathBuf};
+#[cfg(test)]
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use box_format::{BoxFileReader, BoxPath};
+use mmap_io::{MemoryMappedFile, segment::Segment};
+use tokio::io::AsyncReadExt;
+
+use super::{DataRef, Error, glob_match};
+
+/// A source of assets for a [`Context`](super::Context).
+#[async_trait]
+pub trait AssetResolver: Send + Sync {
+    /// Contents of the asset at `path`, or `None` if there is no such asset.
+    async fn load_file(&self, path: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Paths and contents of the assets matching `pattern`, which may hold
+    /// one `*`.
+    async fn glob(&self, pattern: &str) -> Result<Vec<(PathBuf, Vec<u8>)>, Error>;
+
+    /// The asset at `path`, mapped into memory.
+    async fn mmap(&self, path: &str) -> Result<Segment, Error>;
+
+    /// A file on disk holding the asset at `path`, for libraries that open
+    /// files by name. Called from blocking code, so remote assets must have
+    /// been fetched with [`prefetch`](Self::prefetch).
+    fn extract(&self, path: &str) -> Result<PathBuf, Error>;
+
+    /// Make sure the asset at `path` can be read without waiting, e.g. by
+    /// downloading it.
+    async fn prefetch(&self, _path: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// The bundle's `pipeline.json`.
+    async fn load_pipeline(&self) -> Result<Vec<u8>, Error> {
+        self.load_file("pipeline.json")
+            .await?
+            .ok_or_else(|| Error::msg("No pipeline.json").at_file("pipeline.json"))
+    }
+
+    /// The .drb the assets are in, if they are, so models can be read from
+    /// it in place and checked against its checksum manifest.
+    fn box_file(&self) -> Option<&BoxFileReader> {
+        None
+    }
+
+    /// Remove the files the resolver left on disk, such as downloads that
+    /// were cut short, returning how many. See [`shutdown`](crate::shutdown).
+    fn remove_temporary(&self) -> usize {
+        0
+    }
+}
+
+#[async_trait]
+impl AssetResolver for DataRef {
+    async fn load_file(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            DataRef::BoxFile(bf) => {
+                tracing::debug!("Loading file from box file: {}", path);
+                let bpath = BoxPath::new(path).map_err(|e| Error::wrap(e).at_file(path))?;
+                let Some(index) = bf.metadata().index(&bpath) else {
+                    return Ok(None);
+                };
+                let record = bf
+                    .metadata()
+                    .record(index)
+                    .and_then(|record| record.as_file())
+                    .ok_or_else(|| Error::msg("Not a file").at_file(path))?;
+                let mut reader = bf
+                    .read_bytes(record)
+                    .await
+                    .map_err(|e| Error::wrap(e).at_file(path))?;
+                let mut buf = Vec::new();
+                reader
+                    .read_to_end(&mut buf)
+                    .await
+                    .map_err(|e| Error::wrap(e).at_file(path))?;
+                Ok(Some(buf))
+            }
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => {
+                remote.fetch(path).await?;
+                read_optional(&remote.cache_dir().join(path)).await
+            }
+            DataRef::Path(p) => read_optional(&p.join("assets").join(path)).await,
+        }
+    }
+
+    async fn glob(&self, pattern: &str) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        match self {
+            DataRef::BoxFile(bf) => {
+                // For box files, we need to iterate through entries and match the pattern
+                let mut files = Vec::new();
+                for entry in bf.metadata().iter() {
+                    let path_str = entry.path.to_string();
+                    if glob_match(pattern, &path_str) {
+                        if let Some(file_record) = entry.record.as_file() {
+                            let mut reader = bf
+                                .read_bytes(file_record)
+                                .await
+                                .map_err(|e| Error::wrap(e).at_file(&path_str))?;
+                            let mut buf = Vec::new();
+                            reader
+                                .read_to_end(&mut buf)
+                                .await
+                                .map_err(|e| Error::wrap(e).at_file(&path_str))?;
+                            files.push((PathBuf::from(path_str), buf));
+                        }
+                    }
+                }
+                Ok(files)
+            }
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => {
+                // The manifest lists every asset, so match against that
+                // rather than whatever happens to be cached.
+                let mut files = Vec::new();
+                for path_str in remote.assets().filter(|x| glob_match(pattern, x)) {
+                    remote.fetch(path_str).await?;
+                    let path = remote.cache_dir().join(path_str);
+                    let contents = tokio::fs::read(&path)
+                        .await
+                        .map_err(|e| Error::wrap(e).at_file(path_str))?;
+                    files.push((PathBuf::from(path_str), contents));
+                }
+                Ok(files)
+            }
+            DataRef::Path(p) => {
+                // For regular paths, use filesystem globbing
+                let assets_dir = p.join("assets");
+                let full_pattern = assets_dir.join(pattern);
+                let mut files = Vec::new();
+
+                for entry in glob::glob(&full_pattern.to_string_lossy())
+                    .map_err(|e| Error::wrap(e).at_file(pattern))?
+                {
+                    let path = entry.map_err(Error::wrap)?;
+                    if path.is_file() {
+                        let contents = tokio::fs::read(&path)
+                            .await
+                            .map_err(|e| Error::wrap(e).at_file(path.display().to_string()))?;
+                        files.push((path, contents));
+                    }
+                }
+                Ok(files)
+            }
+        }
+    }
+
+    async fn mmap(&self, path: &str) -> Result<Segment, Error> {
+        match self {
+            DataRef::BoxFile(bf) => {
+                tracing::debug!("Memory mapping file from box: {}", path);
+                let bpath = BoxPath::new(path).map_err(|e| Error::wrap(e).at_file(path))?;
+                let node = bf.find(&bpath).map_err(|e| Error::wrap(e).at_file(path))?;
+                let node = node
+                    .as_file()
+                    .ok_or_else(|| Error::msg("Not a file").at_file(path))?;
+                bf.memory_map(node)
+                    .map_err(|e| Error::wrap(e).at_file(path))
+            }
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => {
+                remote.fetch(path).await?;
+                map_file(remote.cache_dir().join(path)).await
+            }
+            DataRef::Path(p) => map_file(p.join("assets").join(path)).await,
+        }
+    }
+
+    fn extract(&self, path: &str) -> Result<PathBuf, Error> {
+        match self {
+            DataRef::BoxFile(_) => Err(Error::msg(
+                "Assets of a .drb bundle are read in place, not extracted",
+            )
+            .at_file(path)),
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => Ok(remote.cache_dir().join(path)),
+            DataRef::Path(p) => Ok(p.join("assets").join(path)),
+        }
+    }
+
+    #[cfg_attr(not(feature = "remote"), allow(unused_variables))]
+    async fn prefetch(&self, path: &str) -> Result<(), Error> {
+        #[cfg(feature = "remote")]
+        if let DataRef::Remote(remote) = self {
+            remote.fetch(path).await?;
+        }
+        Ok(())
+    }
+
+    async fn load_pipeline(&self) -> Result<Vec<u8>, Error> {
+        let p = match self {
+            DataRef::BoxFile(_) => {
+                return self.load_file("pipeline.json").await?.ok_or_else(|| {
+                    Error::msg("Bundle has no pipeline.json").at_file("pipeline.json")
+                });
+            }
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => remote.cache_dir().join("pipeline.json"),
+            DataRef::Path(p) => p.join("pipeline.json"),
+        };
+        tokio::fs::read(&p)
+            .await
+            .map_err(|e| Error::wrap(e).at_file(p.display().to_string()))
+    }
+
+    fn box_file(&self) -> Option<&BoxFileReader> {
+        match self {
+            DataRef::BoxFile(bf) => Some(bf),
+            _ => None,
+        }
+    }
+
+    fn remove_temporary(&self) -> usize {
+        match self {
+            #[cfg(feature = "remote")]
+            DataRef::Remote(remote) => remote.remove_partial(),
+            _ => 0,
+        }
+    }
+}
+
+async fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, Error> {
+    tracing::debug!("Loading file from path: {}", path.display());
+    match tokio::fs::read(path).await {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::wrap(e).at_file(path.display().to_string())),
+    }
+}
+
+/// Map a file on disk into memory.
+pub(crate) async fn map_file(path: PathBuf) -> Result<Segment, Error> {
+    tracing::debug!("Memory mapping file: {}", path.display());
+    let display = path.display().to_string();
+    tokio::task::spawn_blocking(move || {
+        let mmap = std::sync::Arc::new(
+            MemoryMappedFile::open_ro(&path)
+                .map_err(|e| Error::wrap(e).at_file(path.display().to_string()))?,
+        );
+        let len = mmap.len();
+        Segment::new(mmap, 0, len).map_err(|e| Error::wrap(e).at_file(path.display().to_string()))
+    })
+    .await
+    .map_err(|e| Error::wrap(e).at_file(display))?
+}
+
+/// Assets held in memory, for testing commands without a bundle on disk.
+/// Files are written to a temporary directory when they have to be mapped
+/// or extracted.
+#[cfg(test)]
+pub(crate) struct MemoryAssets {
+    files: HashMap<String, Vec<u8>>,
+    dir: tempfile::TempDir,
+    extracted: Mutex<Vec<String>>,
+}
+
+#[cfg(test)]
+impl MemoryAssets {
+    pub(crate) fn new(
+        files: impl IntoIterator<Item = (impl Into<String>, impl Into<Vec<u8>>)>,
+    ) -> Self {
+        MemoryAssets {
+            files: files
+                .into_iter()
+                .map(|(path, contents)| (path.into(), contents.into()))
+                .collect(),
+            dir: tempfile::tempdir().unwrap(),
+            extracted: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[async_trait]
+impl AssetResolver for MemoryAssets {
+    async fn load_file(&self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.files.get(path).cloned())
+    }
+
+    async fn glob(&self, pattern: &str) -> Result<Vec<(PathBuf, Vec<u8>)>, Error> {
+        let mut files = self
+            .files
+            .iter()
+            .filter(|(path, _)| glob_match(pattern, path))
+            .map(|(path, contents)| (PathBuf::from(path), contents.clone()))
+            .collect::<Vec<_>>();
+        files.sort();
+        Ok(files)
+    }
+
+    async fn mmap(&self, path: &str) -> Result<Segment, Error> {
+        map_file(self.extract(path)?).await
+    }
+
+    fn extract(&self, path: &str) -> Result<PathBuf, Error> {
+        let contents = self
+            .files
+            .get(path)
+            .ok_or_else(|| Error::msg("No such asset").at_file(path))?;
+        let target = self.dir.path().join(path);
+        let mut extracted = self.extracted.lock().unwrap();
+        if !extracted.iter().any(|x| x == path) {
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| Error::wrap(e).at_file(path))?;
+            }
+            std::fs::write(&target, contents).map_err(|e| Error::wrap(e).at_file(path))?;
+            extracted.push(path.to_string());
+        }
+        Ok(target)
+    }
+
+    fn remove_temporary(&self) -> usize {
+        let extracted = std::mem::take(&mut *self.extracted.lock().unwrap());
+        extracted
+            .iter()
+            .filter(|path| std::fs::remove_file(self.dir.path().join(path)).is_ok())
+            .count()
+    }
+}
@@ -1,11 +1,14 @@
 use std::{collections::HashMap, sync::Arc};
 
 use async_trait::async_trait;
-use divvun_runtime_macros::rt_command;
+use divvun_runtime_macros::{rt_command, rt_struct};
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 use crate::{ast, bundle::Bundle};
 
-use super::{CommandRunner, Context, PipelineValue, PipelineValues};
+use super::{CommandRunner, Context, Error, PipelineValue, PipelineValues};
 
 /// Forward input through a pipeline bundle
 #[derive(facet::Facet)]
@@ -64,3 +67,281 @@ impl CommandRunner for Forward {
         "runtime::forward"
     }
 }
+
+/// Declarative reshaping for `runtime::select_json`.
+///
+/// Paths are dot separated field names (`errors.title`) and step through
+/// arrays transparently. `keep` and `limit` paths refer to the input's
+/// original field names. Renames are applied last, one after another in the
+/// order given, so each sees the names the ones before it gave.
+#[rt_struct(module = "runtime")]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SelectJsonSpec {
+    /// JSON pointer to the part of the input to keep, e.g. `/errors`.
+    #[serde(default)]
+    pub select: Option<String>,
+    /// Fields to keep; everything else is dropped.
+    #[serde(default)]
+    pub keep: Option<Vec<String>>,
+    /// Maximum number of items to keep in arrays, by path.
+    #[serde(default)]
+    pub limit: Option<HashMap<String, usize>>,
+    /// New names for fields, by path, applied in order.
+    #[serde(default)]
+    pub rename: Option<IndexMap<String, String>>,
+}
+
+/// Filter and reshape JSON output
+#[derive(facet::Facet)]
+pub struct SelectJson {
+    #[facet(opaque)]
+    spec: SelectJsonSpec,
+}
+
+#[rt_command(
+    module = "runtime",
+    name = "select_json",
+    input = [Json],
+    output = "Json",
+    args = [spec = "SelectJsonSpec"]
+)]
+impl SelectJson {
+    pub async fn new(
+        _context: Arc<Context>,
+        mut kwargs: HashMap<String, ast::Arg>,
+    ) -> Result<Arc<dyn CommandRunner + Send + Sync>, Error> {
+        let spec = kwargs
+            .remove("spec")
+            .and_then(|x| x.value)
+            .ok_or_else(|| Error::msg("spec missing").at("pipeline.json", "/args/spec"))?
+            .try_as_json()
+            .map_err(|e| {
+                Error::msg(format!("spec arg is not valid JSON: {}", e))
+                    .at("pipeline.json", "/args/spec")
+            })?;
+        let spec: SelectJsonSpec = serde_json::from_value(spec)
+            .map_err(|e| Error::wrap(e).at("pipeline.json", "/args/spec"))?;
+
+        if let Some(pointer) = &spec.select {
+            if !pointer.is_empty() && !pointer.starts_with('/') {
+                return Err(Error::msg(format!(
+                    "select must be a JSON pointer starting with '/', got '{}'",
+                    pointer
+                ))
+                .at("pipeline.json", "/args/spec/select"));
+            }
+        }
+
+        Ok(Arc::new(Self { spec }) as _)
+    }
+}
+
+#[async_trait]
+impl CommandRunner for SelectJson {
+    async fn forward(
+        self: Arc<Self>,
+        input: PipelineValue,
+        _config: Arc<serde_json::Value>,
+    ) -> Result<PipelineValues, Error> {
+        let input = input.try_into_json()?;
+        Ok(PipelineValue::Json(select_json(input, &self.spec)).into())
+    }
+
+    fn name(&self) -> &'static str {
+        "runtime::select_json"
+    }
+}
+
+fn split_path(path: &str) -> Vec<&str> {
+    path.split('.').filter(|x| !x.is_empty()).collect()
+}
+
+fn select_json(input: Value, spec: &SelectJsonSpec) -> Value {
+    let mut value = match &spec.select {
+        Some(pointer) => input.pointer(pointer).cloned().unwrap_or(Value::Null),
+        None => input,
+    };
+
+    if let Some(keep) = &spec.keep {
+        let paths: Vec<Vec<&str>> = keep.iter().map(|x| split_path(x)).collect();
+        value = keep_paths(value, &paths);
+    }
+
+    for (path, max) in spec.limit.iter().flatten() {
+        let path = split_path(path);
+        if path.is_empty() {
+            truncate(&mut value, *max);
+            continue;
+        }
+        with_field(&mut value, &path, &mut |map, key| {
+            if let Some(field) = map.get_mut(key) {
+                truncate(field, *max);
+            }
+        });
+    }
+
+    for (path, name) in spec.rename.iter().flatten() {
+        with_field(&mut value, &split_path(path), &mut |map, key| {
+            if let Some(field) = map.remove(key) {
+                map.insert(name.clone(), field);
+            }
+        });
+    }
+
+    value
+}
+
+fn truncate(value: &mut Value, max: usize) {
+    if let Value::Array(items) = value {
+        items.truncate(max);
+    }
+}
+
+fn keep_paths(value: Value, paths: &[Vec<&str>]) -> Value {
+    match value {
+        Value::Array(items) => Value::Array(
+            items
+                .into_iter()
+                .map(|item| keep_paths(item, paths))
+                .collect(),
+        ),
+        Value::Object(map) => {
+            let mut out = Map::new();
+            for (key, field) in map {
+                let matching: Vec<&Vec<&str>> =
+                    paths.iter().filter(|p| p.first() == Some(&&*key)).collect();
+                if matching.is_empty() {
+                    continue;
+                }
+                if matching.iter().any(|p| p.len() == 1) {
+                    out.insert(key, field);
+                } else {
+                    let rest: Vec<Vec<&str>> = matching.iter().map(|p| p[1..].to_vec()).collect();
+                    out.insert(key, keep_paths(field, &rest));
+                }
+            }
+            Value::Object(out)
+        }
+        other => other,
+    }
+}
+
+/// Call `f` with the object holding the last segment of `path`, for every
+/// object the path reaches through nested arrays.
+fn with_field(value: &mut Value, path: &[&str], f: &mut impl FnMut(&mut Map<String, Value>, &str)) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    match value {
+        Value::Array(items) => {
+            for item in items {
+                with_field(item, path, f);
+            }
+        }
+        Value::Object(map) if rest.is_empty() => f(map, first),
+        Value::Object(map) => {
+            if let Some(field) = map.get_mut(*first) {
+                with_field(field, rest, f);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn output() -> Value {
+        json!({
+            "text": "Mun leat boahtán",
+            "encoding": "utf-8",
+            "errors": [
+                {
+                    "form": "leat",
+                    "start": 4,
+                    "end": 8,
+                    "error_id": "msyn-agr",
+                    "suggestions": ["lean", "leat", "ledjen"]
+                },
+                {
+                    "form": "boahtán",
+                    "start": 9,
+                    "end": 16,
+                    "error_id": "typo",
+                    "suggestions": []
+                }
+            ]
+        })
+    }
+
+    #[test]
+    fn keep_limit_and_rename() {
+        let spec = SelectJsonSpec {
+            keep: Some(vec![
+                "text".into(),
+                "errors.error_id".into(),
+                "errors.suggestions".into(),
+            ]),
+            limit: Some(HashMap::from([("errors.suggestions".into(), 1)])),
+            rename: Some(IndexMap::from([("errors.error_id".into(), "id".into())])),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            select_json(output(), &spec),
+            json!({
+                "text": "Mun leat boahtán",
+                "errors": [
+                    { "id": "msyn-agr", "suggestions": ["lean"] },
+                    { "id": "typo", "suggestions": [] }
+                ]
+            })
+        );
+    }
+
+    #[test]
+    fn select_then_limit_top_level() {
+        let spec = SelectJsonSpec {
+            select: Some("/errors".into()),
+            keep: Some(vec!["form".into()]),
+            limit: Some(HashMap::from([("".into(), 1)])),
+            ..Default::default()
+        };
+
+        assert_eq!(select_json(output(), &spec), json!([{ "form": "leat" }]));
+    }
+
+    #[test]
+    fn renames_apply_in_order() {
+        let spec = SelectJsonSpec {
+            keep: Some(vec!["text".into(), "errors.error_id".into()]),
+            rename: Some(IndexMap::from([
+                ("errors.error_id".into(), "id".into()),
+                ("errors".into(), "issues".into()),
+                ("issues.id".into(), "type".into()),
+                ("text".into(), "body".into()),
+                ("body".into(), "content".into()),
+            ])),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            select_json(output(), &spec),
+            json!({
+                "content": "Mun leat boahtán",
+                "issues": [{ "type": "msyn-agr" }, { "type": "typo" }]
+            })
+        );
+    }
+
+    #[test]
+    fn missing_pointer_gives_null() {
+        let spec = SelectJsonSpec {
+            select: Some("/nope".into()),
+            ..Default::default()
+        };
+        assert_eq!(select_json(output(), &spec), Value::Null);
+    }
+}
@@ -1,24 +1,26 @@
-use std::{collections::HashMap, sync::Arc, sync::Mutex};
+use std::{collections::HashMap, sync::Arc, sync::Mutex, sync::PoisonError};
 
 use async_trait::async_trait;
 use divvun_runtime_macros::{rt_command, rt_struct};
 use divvun_speech::{Options, SAMPLE_RATE, Synthesizer};
-use hfst::hfst_transducer::AnyTransducer;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 
-use crate::{ast, modules::Error};
+use crate::{ast, modules::Error, util::privacy::redact};
 
-use super::{AudioBuffer, AudioWordTiming, CommandRunner, Context, PipelineValue, PipelineValues};
+use super::{
+    AudioBuffer, AudioChunk, AudioWordTiming, CommandRunner, Context, PipelineValue, PipelineValues,
+};
 use crate::modules::cg3::{self, Cohort, Reading};
+use crate::modules::hfst::{Lookup, LookupConfig};
 
 /// Phonetic transcription using HFST
 #[derive(facet::Facet)]
 struct Phon {
     #[facet(opaque)]
-    model: Mutex<AnyTransducer>,
+    model: Lookup,
     #[facet(opaque)]
-    tag_models: IndexMap<String, Mutex<AnyTransducer>>,
+    tag_models: IndexMap<String, Lookup>,
 }
 
 #[rt_command(
@@ -26,7 +28,7 @@ struct Phon {
     name = "phon",
     input = [String],
     output = "String",
-    args = [model = "Path", tag_models = "MapPath"]
+    args = [model = "Path", tag_models = "MapPath", lookup? = "LookupConfig"]
 )]
 impl Phon {
     pub async fn new(
@@ -47,12 +49,13 @@ impl Phon {
                 Error::msg("Missing tag_models").at("pipeline.json", "/args/tag_models")
             })?;
 
-        let model = crate::modules::hfst::load_lookup(&context, &model_path).await?;
+        let lookup = LookupConfig::from_kwargs(&kwargs)?;
+        let model = crate::modules::hfst::load_lookup(&context, &model_path, &lookup).await?;
         let mut tag_models = IndexMap::new();
         for (k, v) in tag_model_paths.iter() {
             tag_models.insert(
                 k.clone(),
-                crate::modules::hfst::load_lookup(&context, v).await?,
+                crate::modules::hfst::load_lookup(&context, v, &lookup).await?,
             );
         }
 
@@ -101,8 +104,8 @@ impl Phon {
                 .filter(|tag| !tag.ends_with("\"phon"))
                 .map(|tag| tag.to_string())
                 .collect::<Vec<String>>();
-            tracing::debug!("New output: {:?}", new_output);
-            new_output.push(format!("\"{}\"phon", expansions.first().unwrap()));
+            tracing::debug!("New output: {}", redact(&format!("{:?}", new_output)));
+            new_output.push(format!("\"{}\"phon", expansions[0]));
             return Some(format!(
                 "\t\"{}\" {}",
                 reading.base_form,
@@ -168,11 +171,11 @@ impl CommandRunner for Phon {
 #[derive(facet::Facet)]
 struct Normalize {
     #[facet(opaque)]
-    normalizers: IndexMap<String, Mutex<AnyTransducer>>,
+    normalizers: IndexMap<String, Lookup>,
     #[facet(opaque)]
-    generator: Mutex<AnyTransducer>,
+    generator: Lookup,
     #[facet(opaque)]
-    analyzer: Mutex<AnyTransducer>,
+    analyzer: Lookup,
 }
 
 #[derive(Debug, Clone)]
@@ -208,6 +211,8 @@ impl NormalizedReading {
 #[derive(Debug, Clone)]
 struct NormalizedCohort {
     readings: Vec<NormalizedReading>,
+    /// See [`NormalizedToken::source`].
+    source: String,
 }
 
 impl NormalizedCohort {
@@ -220,12 +225,50 @@ impl NormalizedCohort {
     }
 }
 
+/// A cohort of the input to `speech::normalize` and what it is read out as.
+#[rt_struct(module = "speech")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizedToken {
+    pub form: String,
+    /// The phonological form it is read out as, or `form` when nothing was
+    /// normalized.
+    pub normalized: String,
+    /// The tag whose normalizer expanded it (e.g. `Sem/Plc`), or
+    /// `regenerated` for a compound regenerated from its parts. None when
+    /// nothing was normalized.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Lemma and tags of the reading it is read out from.
+    pub lemma: String,
+    pub tags: Vec<String>,
+}
+
+#[rt_struct(module = "speech")]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NormalizeOutput {
+    /// The CG stream `speech::normalize` gives for the same input.
+    pub cg: String,
+    pub tokens: Vec<NormalizedToken>,
+}
+
+/// Configuration for the normalize command's forward() function
+#[rt_struct(module = "speech")]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NormalizeConfig {
+    /// Output format: "cg" (default, the CG stream with phonological forms)
+    /// or "json" (a `NormalizeOutput` with each cohort's normalization).
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
 #[rt_command(
     module = "speech",
     name = "normalize",
     input = [String],
     output = "String",
-    args = [normalizers = "MapPath", generator = "Path", analyzer = "Path"]
+    args = [normalizers = "MapPath", generator = "Path", analyzer = "Path", lookup? = "LookupConfig"],
+    config = "NormalizeConfig",
+    resources = [memory = "64M"]
 )]
 impl Normalize {
     pub async fn new(
@@ -257,16 +300,22 @@ impl Normalize {
                 Error::msg("Missing analyzer path").at("pipeline.json", "/args/analyzer")
             })?;
 
+        let lookup = LookupConfig::from_kwargs(&kwargs)?;
+
         tracing::debug!("Loading normalizers");
         let mut normalizers = IndexMap::new();
         for (k, path) in normalizer_path_map {
             tracing::debug!("adding HFST transducer for tag {}", k);
-            normalizers.insert(k, crate::modules::hfst::load_lookup(&context, &path).await?);
+            normalizers.insert(
+                k,
+                crate::modules::hfst::load_lookup(&context, &path, &lookup).await?,
+            );
         }
         tracing::debug!("Loading generator: {}", generator_path);
-        let generator = crate::modules::hfst::load_lookup(&context, &generator_path).await?;
+        let generator =
+            crate::modules::hfst::load_lookup(&context, &generator_path, &lookup).await?;
         tracing::debug!("Loading analyzer: {}", analyzer_path);
-        let analyzer = crate::modules::hfst::load_lookup(&context, &analyzer_path).await?;
+        let analyzer = crate::modules::hfst::load_lookup(&context, &analyzer_path, &lookup).await?;
 
         Ok(Arc::new(Self {
             normalizers,
@@ -275,7 +324,8 @@ impl Normalize {
         }))
     }
 
-    fn needs_expansion(&self, reading: &Reading) -> Option<&Mutex<AnyTransducer>> {
+    /// The normalizer of the first normalizer tag `reading` has, with the tag.
+    fn needs_expansion(&self, reading: &Reading) -> Option<(&str, &Lookup)> {
         if self.normalizers.is_empty() {
             return None;
         }
@@ -283,7 +333,7 @@ impl Normalize {
         self.normalizers.iter().find_map(|(tag, normalizer)| {
             if reading.tags.contains(&&**tag) {
                 tracing::debug!("Expanding because of {}", tag);
-                return Some(normalizer);
+                return Some((tag.as_str(), normalizer));
             }
             None
         })
@@ -400,16 +450,19 @@ impl Normalize {
         tracing::debug!("2.b regenerating lookup: {}", regen);
 
         // Try regeneration with normalized form first
-        let regenerations = crate::modules::hfst::lookup_tags(&self.generator, &regen, false);
+        let regenerations = crate::modules::hfst::generate(&self.generator, &regen);
         // Also try with base form as fallback
         let regenerations_base_form =
-            crate::modules::hfst::lookup_tags(&self.generator, &regen_base_form, false);
+            crate::modules::hfst::generate(&self.generator, &regen_base_form);
 
         let mut regenerated = false;
         let mut last_phon = None;
 
-        tracing::debug!("regenerated: {:?}", regenerations);
-        tracing::debug!("regenerated_base_form: {:?}", regenerations_base_form);
+        tracing::debug!("regenerated: {}", redact(&format!("{:?}", regenerations)));
+        tracing::debug!(
+            "regenerated_base_form: {}",
+            redact(&format!("{:?}", regenerations_base_form))
+        );
 
         // Process regenerations from normalized form
         for phon in regenerations.iter().chain(regenerations_base_form.iter()) {
@@ -519,7 +572,7 @@ impl Normalize {
 
     fn process_expansion(
         &self,
-        normalizer: &Mutex<AnyTransducer>,
+        normalizer: &Lookup,
         surface_form: &str,
         reading: &Reading,
     ) -> Option<NormalizedReading> {
@@ -531,7 +584,7 @@ impl Normalize {
 
         let expansions = crate::modules::hfst::lookup_tags(normalizer, surface_form, false);
 
-        tracing::debug!("Expansions: {:?}", expansions);
+        tracing::debug!("Expansions: {}", redact(&format!("{:?}", expansions)));
 
         let mut all_expansions = expansions;
 
@@ -590,7 +643,7 @@ impl Normalize {
         None
     }
 
-    fn process_cohort(&self, cohort: &Cohort) -> Option<String> {
+    fn process_cohort(&self, cohort: &Cohort) -> Option<NormalizedCohort> {
         tracing::debug!("Processing whole cohort");
 
         // Group readings by their hierarchical structure
@@ -638,7 +691,7 @@ impl Normalize {
         &self,
         cohort: &Cohort,
         hierarchy: &mut Vec<ReadingNode>,
-    ) -> Option<String> {
+    ) -> Option<NormalizedCohort> {
         // Find root readings (depth == 1)
         let root_indices: Vec<usize> = hierarchy
             .iter()
@@ -650,7 +703,7 @@ impl Normalize {
         // Process each root reading with its subreadings
         for &root_idx in &root_indices {
             if let Some(result) = self.process_reading_node(cohort, hierarchy, root_idx) {
-                return Some(result.to_cg3_format());
+                return Some(result);
             }
         }
 
@@ -710,11 +763,13 @@ impl Normalize {
         // Check if this reading needs expansion due to normalizer tags
         let normalizer = self.needs_expansion(reading);
         let mut result = None;
+        let mut source = "regenerated";
 
-        if let Some(normalizer) = normalizer {
+        if let Some((tag, normalizer)) = normalizer {
             // Process with normalizer expansion
             let surface_form = self.extract_surface_form(cohort, reading);
             result = self.process_expansion(normalizer, &surface_form, reading);
+            source = tag;
         } else if !node.subreadings.is_empty() {
             // Process main reading when subreadings exist (expandmain logic)
             let surface_form = reading.base_form.trim_matches('"');
@@ -742,6 +797,7 @@ impl Normalize {
 
             return Some(NormalizedCohort {
                 readings: all_readings,
+                source: source.to_string(),
             });
         }
 
@@ -778,7 +834,7 @@ impl Normalize {
         let normalizer = self.needs_expansion(reading);
         let surface_form = self.extract_surface_form(cohort, reading);
 
-        let normalized_form = if let Some(normalizer) = normalizer {
+        let normalized_form = if let Some((_, normalizer)) = normalizer {
             // Try to get normalized form from expansion
             if let Some(result) = self.process_expansion(normalizer, &surface_form, reading) {
                 result.phonological_form
@@ -794,9 +850,11 @@ impl Normalize {
         Some(prefix)
     }
 
-    fn process_cg3(&self, text: &str) -> String {
+    /// The normalized CG stream of `text`, and each of its cohorts as a token.
+    fn normalize(&self, text: &str) -> NormalizeOutput {
         let output = cg3::Output::new(text);
         let mut result = String::new();
+        let mut tokens = Vec::new();
         let mut everything_has_failed = true;
 
         // Process each block
@@ -808,12 +866,33 @@ impl Normalize {
                         result.push_str("\"<");
                         result.push_str(&cohort.word_form);
                         result.push_str(">\"\n");
-                        result.push_str(&normalized);
+                        result.push_str(&normalized.to_cg3_format());
                         result.push('\n');
+                        let main = &normalized.readings[0];
+                        tokens.push(NormalizedToken {
+                            form: cohort.word_form.to_string(),
+                            normalized: main.phonological_form.clone(),
+                            source: Some(normalized.source.clone()),
+                            lemma: main.base_form.clone(),
+                            tags: main.tags.clone(),
+                        });
                     } else {
                         // If no normalization was applied, output the original cohort
                         result.push_str(&cohort.to_string());
                         result.push('\n');
+                        let reading = cohort.readings.first();
+                        tokens.push(NormalizedToken {
+                            form: cohort.word_form.to_string(),
+                            normalized: cohort.word_form.to_string(),
+                            source: None,
+                            lemma: reading
+                                .map(|x| x.base_form.trim_matches('"'))
+                                .unwrap_or(cohort.word_form)
+                                .to_string(),
+                            tags: reading
+                                .map(|x| x.tags.iter().map(|tag| tag.to_string()).collect())
+                                .unwrap_or_default(),
+                        });
                     }
                 }
                 cg3::Block::Text(text) => {
@@ -832,7 +911,7 @@ impl Normalize {
             tracing::debug!("no usable results, printing source");
         }
 
-        result
+        NormalizeOutput { cg: result, tokens }
     }
 
     fn combine_prefix_with_main(
@@ -861,13 +940,31 @@ impl CommandRunner for Normalize {
     async fn forward(
         self: Arc<Self>,
         input: PipelineValue,
-        _config: Arc<serde_json::Value>,
+        config: Arc<serde_json::Value>,
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
+        let config: NormalizeConfig = serde_json::from_value((*config).clone()).unwrap_or_default();
 
         // Parse the input using cg3::Output
-        let output = self.process_cg3(&input);
-        Ok(output.into())
+        let output = self.normalize(&input);
+        match config.format.as_deref() {
+            None | Some("cg") => Ok(output.cg.into()),
+            Some("json") => {
+                let json = serde_json::to_value(output).map_err(|e| {
+                    Error::msg(format!(
+                        "Failed to serialize speech::normalize output: {}",
+                        e
+                    ))
+                    .at_path("/output")
+                })?;
+                Ok(PipelineValue::Json(json).into())
+            }
+            Some(other) => Err(Error::msg(format!(
+                "unknown format '{}', expected \"cg\" or \"json\"",
+                other
+            ))
+            .at_path("/config/format")),
+        }
     }
 
     fn name(&self) -> &'static str {
@@ -902,6 +999,10 @@ struct Tts {
     speech: Mutex<Synthesizer>,
     #[facet(opaque)]
     config: Option<TtsConfig>,
+    #[facet(opaque)]
+    limits: ChunkLimits,
+    /// Milliseconds of silence between chunks.
+    chunk_pause: u32,
 }
 
 #[rt_command(
@@ -910,7 +1011,8 @@ struct Tts {
     input = [String],
     output = "Bytes",
     kind = "audio",
-    args = [voice_model = "Path", vocoder_model = "Path", speaker = "Int", language = "Int", config = "TtsConfig"]
+    args = [voice_model = "Path", vocoder_model = "Path", speaker = "Int", language = "Int", config = "TtsConfig", max_chars? = "Int", max_phonemes? = "Int", chunk_pause? = "Int"],
+    resources = [memory = "600M", gpu = "optional"]
 )]
 impl Tts {
     pub async fn new(
@@ -957,6 +1059,20 @@ impl Tts {
         //         .at("pipeline.json", "/args/config")
         // })?;
 
+        let int_arg = |name: &str| {
+            kwargs
+                .get(name)
+                .and_then(|x| x.value.as_ref())
+                .and_then(|x| x.try_as_int())
+        };
+        let limits = ChunkLimits {
+            max_chars: int_arg("max_chars").map(|x| x as usize),
+            max_phonemes: int_arg("max_phonemes").map(|x| x as usize),
+        };
+        let chunk_pause = int_arg("chunk_pause")
+            .map(|x| x as u32)
+            .unwrap_or(DEFAULT_CHUNK_PAUSE_MS);
+
         let voice_model = context.memory_map_file(voice_model).await?;
         let vocoder_model = context.memory_map_file(vocoder_model).await?;
 
@@ -967,6 +1083,8 @@ impl Tts {
             speech: Mutex::new(speech),
             language,
             config: None,
+            limits,
+            chunk_pause,
         }))
     }
 }
@@ -1017,6 +1135,158 @@ fn silence_samples(ms: u32) -> Vec<f32> {
     vec![0.0_f32; n]
 }
 
+/// Silence between chunks of a sentence split for the voice model, about a
+/// comma's pause.
+const DEFAULT_CHUNK_PAUSE_MS: u32 = 150;
+
+/// Milliseconds two chunks overlap and fade into each other when there is
+/// no pause between them, so the join doesn't click.
+const CHUNK_CROSSFADE_MS: u32 = 10;
+
+/// How much text the voice model is given at once. Models have a maximum
+/// input length and fail or cut the sentence short beyond it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ChunkLimits {
+    max_chars: Option<usize>,
+    /// Letters, which the voice models read as one symbol each.
+    max_phonemes: Option<usize>,
+}
+
+impl ChunkLimits {
+    fn is_unlimited(&self) -> bool {
+        self.max_chars.is_none() && self.max_phonemes.is_none()
+    }
+
+    fn fits(&self, text: &str) -> bool {
+        self.max_chars.is_none_or(|max| text.chars().count() <= max)
+            && self
+                .max_phonemes
+                .is_none_or(|max| text.chars().filter(|c| c.is_alphabetic()).count() <= max)
+    }
+}
+
+/// Where `text` may be split, from the best place to the worst: after the
+/// end of a sentence, after clause punctuation, and between words.
+const CHUNK_BOUNDARIES: [&[char]; 3] = [&['.', '!', '?', '…'], &[',', ';', ':', '–', '—'], &[]];
+
+/// Split `text` into chunks within `limits`, as few as possible and at the
+/// best boundaries that do. A word too long on its own is split between
+/// letters as a last resort.
+fn chunk_text(text: &str, limits: ChunkLimits) -> Vec<String> {
+    let mut chunks = Vec::new();
+    pack_chunks(text.trim(), limits, 0, &mut chunks);
+    chunks
+}
+
+fn pack_chunks(text: &str, limits: ChunkLimits, level: usize, chunks: &mut Vec<String>) {
+    if limits.fits(text) {
+        if !text.is_empty() {
+            chunks.push(text.to_string());
+        }
+        return;
+    }
+    let Some(delimiters) = CHUNK_BOUNDARIES.get(level) else {
+        let mut current = String::new();
+        for c in text.chars() {
+            current.push(c);
+            if !limits.fits(&current) && current.chars().count() > 1 {
+                current.pop();
+                chunks.push(std::mem::replace(&mut current, c.to_string()));
+            }
+        }
+        chunks.push(current);
+        return;
+    };
+
+    let mut current = String::new();
+    for piece in split_after(text, delimiters) {
+        let joined = if current.is_empty() {
+            piece.to_string()
+        } else {
+            format!("{} {}", current, piece)
+        };
+        if limits.fits(&joined) {
+            current = joined;
+            continue;
+        }
+        if !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        if limits.fits(piece) {
+            current = piece.to_string();
+        } else {
+            pack_chunks(piece, limits, level + 1, chunks);
+        }
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+}
+
+/// `text` split after each of `delimiters` followed by whitespace, or at
+/// whitespace when there are none.
+fn split_after<'a>(text: &'a str, delimiters: &[char]) -> Vec<&'a str> {
+    if delimiters.is_empty() {
+        return text.split_whitespace().collect();
+    }
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let at_boundary = chars.peek().is_some_and(|(_, next)| next.is_whitespace());
+        if delimiters.contains(&c) && at_boundary {
+            let end = i + c.len_utf8();
+            pieces.push(text[start..end].trim());
+            start = end;
+        }
+    }
+    pieces.push(text[start..].trim());
+    pieces.retain(|x| !x.is_empty());
+    pieces
+}
+
+/// Join the audio of chunks into one, with `pause_ms` of silence between
+/// them or, without a pause, a short cross-fade. Word timings are moved to
+/// where their chunk ends up.
+fn stitch_chunks(
+    chunks: Vec<(String, Vec<f32>, Vec<AudioWordTiming>)>,
+    pause_ms: u32,
+) -> (Vec<f32>, Vec<AudioWordTiming>, Vec<AudioChunk>) {
+    let mut samples: Vec<f32> = Vec::new();
+    let mut word_timings = Vec::new();
+    let mut placed = Vec::new();
+    let overlap = silence_samples(CHUNK_CROSSFADE_MS).len();
+
+    for (i, (text, chunk, timings)) in chunks.into_iter().enumerate() {
+        let start = if i == 0 {
+            0
+        } else if pause_ms > 0 {
+            samples.extend(silence_samples(pause_ms));
+            samples.len()
+        } else {
+            samples.len() - overlap.min(samples.len()).min(chunk.len())
+        };
+        let faded = samples.len() - start;
+        for (j, sample) in chunk[..faded].iter().enumerate() {
+            let t = (j + 1) as f32 / (faded + 1) as f32;
+            samples[start + j] = samples[start + j] * (1.0 - t) + sample * t;
+        }
+        samples.extend_from_slice(&chunk[faded..]);
+
+        word_timings.extend(timings.into_iter().map(|x| AudioWordTiming {
+            start_sample: x.start_sample + start,
+            end_sample: x.end_sample + start,
+            ..x
+        }));
+        placed.push(AudioChunk {
+            text,
+            start_sample: start,
+            end_sample: start + chunk.len(),
+        });
+    }
+    (samples, word_timings, placed)
+}
+
 async fn speak_sentence(
     this: Arc<Tts>,
     sentence: String,
@@ -1025,13 +1295,13 @@ async fn speak_sentence(
     pace: f32,
     include_word_timings: bool,
 ) -> Result<(Vec<f32>, Vec<AudioWordTiming>), crate::modules::Error> {
-    let output = tokio::task::spawn_blocking(move || {
+    let output = crate::util::priority::spawn_blocking(move || {
         let options = Options {
             pace,
             speaker_id,
             language_id,
         };
-        let mut speech = this.speech.lock().unwrap();
+        let mut speech = this.speech.lock().unwrap_or_else(PoisonError::into_inner);
         if include_word_timings {
             let (samples, timings) = speech
                 .synthesize_with_word_timings(&sentence, &options)
@@ -1052,8 +1322,7 @@ async fn speak_sentence(
             Ok((samples, Vec::new()))
         }
     })
-    .await
-    .map_err(Error::wrap)??;
+    .await??;
 
     Ok(output)
 }
@@ -1086,29 +1355,72 @@ impl CommandRunner for Tts {
             .get("word_timings")
             .and_then(|x| x.as_bool())
             .unwrap_or(false);
+        let limits = ChunkLimits {
+            max_chars: config
+                .get("max_chars")
+                .and_then(|x| x.as_u64())
+                .map(|x| x as usize)
+                .or(self.limits.max_chars),
+            max_phonemes: config
+                .get("max_phonemes")
+                .and_then(|x| x.as_u64())
+                .map(|x| x as usize)
+                .or(self.limits.max_phonemes),
+        };
+        let chunk_pause = config
+            .get("chunk_pause")
+            .and_then(|x| x.as_u64())
+            .map(|x| x as u32)
+            .unwrap_or(self.chunk_pause);
 
         match input {
             PipelineValue::String(sentence) => {
-                let (samples, word_timings) = if let Some(ms) = parse_break_sentinel(&sentence) {
-                    (silence_samples(ms), Vec::new())
-                } else {
-                    let (opts, text) = parse_opts_prefix(&sentence);
-                    let effective_pace = opts.pace.unwrap_or(pace);
-                    speak_sentence(
-                        self.clone(),
-                        text.to_string(),
-                        speaker,
-                        language,
-                        effective_pace,
-                        include_word_timings,
-                    )
-                    .await?
-                };
+                let (samples, word_timings, chunks) =
+                    if let Some(ms) = parse_break_sentinel(&sentence) {
+                        (silence_samples(ms), Vec::new(), Vec::new())
+                    } else if parse_opts_prefix(&sentence).1.trim().is_empty() {
+                        // Nothing to say: empty audio rather than a call to the model
+                        (Vec::new(), Vec::new(), Vec::new())
+                    } else {
+                        let (opts, text) = parse_opts_prefix(&sentence);
+                        let effective_pace = opts.pace.unwrap_or(pace);
+                        let pieces = if limits.is_unlimited() {
+                            vec![text.to_string()]
+                        } else {
+                            chunk_text(text, limits)
+                        };
+                        if pieces.len() > 1 {
+                            tracing::debug!(
+                                "Splitting {} characters into {} chunks for speech::tts",
+                                text.chars().count(),
+                                pieces.len()
+                            );
+                        }
+
+                        let mut spoken = Vec::with_capacity(pieces.len());
+                        for piece in pieces {
+                            let (samples, timings) = speak_sentence(
+                                self.clone(),
+                                piece.clone(),
+                                speaker,
+                                language,
+                                effective_pace,
+                                include_word_timings,
+                            )
+                            .await?;
+                            spoken.push((piece, samples, timings));
+                        }
+                        match <[_; 1]>::try_from(spoken) {
+                            Ok([(_, samples, timings)]) => (samples, timings, Vec::new()),
+                            Err(spoken) => stitch_chunks(spoken, chunk_pause),
+                        }
+                    };
                 let audio = AudioBuffer {
                     samples,
                     sample_rate: SAMPLE_RATE,
                     channels: 1,
                     word_timings,
+                    chunks,
                 };
                 if raw_audio {
                     Ok(audio.into())
@@ -1174,6 +1486,65 @@ mod tts_tests {
         assert_eq!(text, "hi");
     }
 
+    #[test]
+    fn long_text_is_chunked_at_the_best_boundaries() {
+        let limits = ChunkLimits {
+            max_chars: Some(30),
+            max_phonemes: None,
+        };
+        assert_eq!(
+            chunk_text("Mun lean boahtán. Don leat maid boahtán ruoktot.", limits),
+            ["Mun lean boahtán.", "Don leat maid boahtán ruoktot."]
+        );
+        assert_eq!(
+            chunk_text(
+                "Go mun bohten ruoktot, de don ledje juo vuolgán eret.",
+                limits
+            ),
+            ["Go mun bohten ruoktot,", "de don ledje juo vuolgán eret."]
+        );
+        assert_eq!(
+            chunk_text("Mun lean boahtán ruoktot ja don leat vuolgán.", limits),
+            ["Mun lean boahtán ruoktot ja", "don leat vuolgán."]
+        );
+        assert_eq!(chunk_text("Buorre beaivi.", limits), ["Buorre beaivi."]);
+
+        // Punctuation and spaces aren't phonemes
+        let limits = ChunkLimits {
+            max_chars: None,
+            max_phonemes: Some(4),
+        };
+        assert_eq!(chunk_text("a, b, c, d, e", limits), ["a, b, c, d,", "e"]);
+        assert_eq!(chunk_text("guhkesbádni", limits), ["guhk", "esbá", "dni"]);
+    }
+
+    #[test]
+    fn chunks_are_stitched_with_pauses_or_cross_fades() {
+        let timing = |start_sample, end_sample| AudioWordTiming {
+            word: "x".into(),
+            start_sample,
+            end_sample,
+        };
+        let chunks = vec![
+            ("a".to_string(), vec![1.0; 10], vec![timing(0, 10)]),
+            ("b".to_string(), vec![1.0; 10], vec![timing(2, 8)]),
+        ];
+        let pause = silence_samples(100).len();
+        let (samples, timings, placed) = stitch_chunks(chunks.clone(), 100);
+        assert_eq!(samples.len(), 20 + pause);
+        assert_eq!(timings[1], timing(12 + pause, 18 + pause));
+        assert_eq!(
+            (placed[1].start_sample, placed[1].end_sample),
+            (10 + pause, 20 + pause)
+        );
+
+        // Chunks shorter than the cross-fade overlap completely
+        let (samples, _, placed) = stitch_chunks(chunks, 0);
+        assert_eq!(samples.len(), 10);
+        assert_eq!(placed[1].start_sample, 0);
+        assert!(samples.iter().all(|x| (x - 1.0).abs() < 1e-6));
+    }
+
     #[test]
     fn opts_prefix_malformed_passes_through() {
         // Missing closing \x1F → treat as plain text.
@@ -1182,3 +1553,46 @@ mod tts_tests {
         assert_eq!(text, "\x1FOPTS:pace=1.0 no closer");
     }
 }
+
+#[cfg(test)]
+mod normalize_tests {
+    use super::*;
+
+    fn canned(results: &[(&str, &str)]) -> Lookup {
+        Lookup::canned(
+            results
+                .iter()
+                .map(|(input, output)| (input.to_string(), vec![output.to_string()])),
+        )
+    }
+
+    #[test]
+    fn tokens_say_what_was_normalized() {
+        let normalize = Normalize {
+            normalizers: IndexMap::from([("ABBR".to_string(), canned(&[("nr.", "nummar")]))]),
+            generator: canned(&[("nummar+N+Sg+Nom", "nummar")]),
+            analyzer: canned(&[("nummar", "nummar+N+Sg+Nom")]),
+        };
+        let stream = "\"<nr.>\"\n\t\"nr.\" N ABBR Sg Nom\n\"<lea>\"\n\t\"leat\" V Ind Prs Sg3\n";
+        let output = normalize.normalize(stream);
+
+        assert_eq!(
+            output.tokens[0],
+            NormalizedToken {
+                form: "nr.".to_string(),
+                normalized: "nummar".to_string(),
+                source: Some("ABBR".to_string()),
+                lemma: "nummar".to_string(),
+                tags: vec!["N".to_string(), "Sg".to_string(), "Nom".to_string()],
+            }
+        );
+        assert_eq!(output.tokens[1].normalized, "lea");
+        assert_eq!(output.tokens[1].source, None);
+        assert_eq!(output.tokens[1].lemma, "leat");
+        assert!(
+            output
+                .cg
+                .contains("\t\"nummar\" N Sg Nom \"nummar\"phon \"nr.\"oldlemma\n")
+        );
+    }
+}
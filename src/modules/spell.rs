@@ -1,8 +1,13 @@
 use std::{collections::HashMap, sync::Arc, thread::JoinHandle};
 
 use async_trait::async_trait;
-use divvun_fst::{speller::Speller, transducer::thfst::MmapThfstTransducer};
+use divvun_fst::{
+    speller::Speller,
+    transducer::{Transducer, hfst::HfstTransducer, thfst::MmapThfstTransducer},
+    types::{FlagDiacriticOperation, FlagDiacriticOperator, SymbolNumber, TransitionTableIndex},
+};
 use divvun_runtime_macros::rt_command;
+use indexmap::IndexSet;
 use tokio::sync::{
     Mutex,
     mpsc::{self, Receiver, Sender},
@@ -30,7 +35,8 @@ struct Suggest {
     name = "suggest",
     input = [String],
     output = "Json",
-    args = [lexicon_path = "Path", mutator_path = "Path"]
+    args = [lexicon_path = "Path", mutator_path = "Path"],
+    resources = [memory = "128M"]
 )]
 impl Suggest {
     pub async fn new(
@@ -57,15 +63,12 @@ impl Suggest {
         let (input_tx, mut input_rx) = mpsc::channel(1);
         let (output_tx, output_rx) = mpsc::channel(1);
 
-        let model_context = context.clone();
+        context.prefetch(&lexicon_path).await?;
+        context.prefetch(&mutator_path).await?;
+        let lexicon = context.load_fst::<MmapThfstTransducer>(&lexicon_path)?;
+        let mutator = context.load_fst::<MmapThfstTransducer>(&mutator_path)?;
         let thread =
             std::thread::spawn(move || {
-                let lexicon = model_context
-                    .load_fst::<MmapThfstTransducer>(&lexicon_path)
-                    .unwrap();
-                let mutator = model_context
-                    .load_fst::<MmapThfstTransducer>(&mutator_path)
-                    .unwrap();
                 let speller = divvun_fst::speller::HfstSpeller::new(mutator, lexicon);
 
                 loop {
@@ -74,13 +77,17 @@ impl Suggest {
                     };
 
                     let results = input.word_bound_indices().map(|(pos, word)| {
-                    let results = speller.clone().suggest(&word);
-                    serde_json::json!({ "index": pos, "word": word, "suggestions": results })
+                    let speller = speller.clone();
+                    let correct = speller.clone().is_correct(&word);
+                    let results = speller.suggest(&word);
+                    serde_json::json!({ "index": pos, "word": word, "suggestions": results, "correct": correct })
                 }).collect::<Vec<_>>();
 
-                    let results = serde_json::to_string(&results).unwrap();
+                    let results = serde_json::Value::Array(results).to_string();
 
-                    output_tx.blocking_send(Some(results)).unwrap();
+                    if output_tx.blocking_send(Some(results)).is_err() {
+                        break;
+                    }
                 }
             });
 
@@ -102,11 +109,11 @@ impl CommandRunner for Suggest {
     ) -> Result<PipelineValues, crate::modules::Error> {
         let input = input.try_into_string()?;
 
+        let mut output_rx = self.output_rx.lock().await;
         self.input_tx
             .send(Some(input))
             .await
             .expect("input tx send");
-        let mut output_rx = self.output_rx.lock().await;
         let value = output_rx.recv().await.expect("output rx recv");
 
         Ok(value.unwrap_or_default().into())
@@ -116,3 +123,413 @@ impl CommandRunner for Suggest {
         "spell::suggest"
     }
 }
+
+/// Limits of a [`wordlist`] traversal. Lexicons accept infinitely many forms
+/// (compounds, derivations), so every traversal is bounded.
+#[derive(Debug, Clone)]
+pub struct WordlistOptions {
+    /// Forms to list at most.
+    pub max_forms: usize,
+    /// Longest form, in symbols. Epsilons and flag diacritics don't count.
+    pub max_length: usize,
+    /// Epsilons and flag diacritics to follow in a row at most, which ends
+    /// loops of them.
+    pub max_epsilons: usize,
+    /// Transitions to follow at most, counting the ones that lead nowhere.
+    pub max_steps: usize,
+    /// Only list forms matching this.
+    pub pattern: Option<regex::Regex>,
+}
+
+impl Default for WordlistOptions {
+    fn default() -> Self {
+        WordlistOptions {
+            max_forms: 10_000,
+            max_length: 32,
+            max_epsilons: 64,
+            max_steps: 10_000_000,
+            pattern: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Wordlist {
+    /// Accepted surface forms, without duplicates, in the order found.
+    pub forms: Vec<String>,
+    /// Whether the traversal ran out of forms before hitting `max_forms` or
+    /// `max_steps`. Forms longer than `max_length` are never listed.
+    pub complete: bool,
+}
+
+/// Commands whose `args` name a speller acceptor, with the arg naming it.
+const ACCEPTOR_ARGS: &[(&str, &str, &str)] = &[
+    ("divvun", "cgspell", "acc_model_path"),
+    ("spell", "suggest", "lexicon_path"),
+];
+
+/// Asset paths of the speller acceptors a pipeline's commands load, in
+/// pipeline order.
+pub fn acceptor_paths(definition: &ast::PipelineDefinition) -> Vec<String> {
+    let mut paths = Vec::new();
+    for command in definition.commands.values() {
+        let Some((_, _, arg)) = ACCEPTOR_ARGS
+            .iter()
+            .find(|(m, c, _)| command.module == *m && command.command == *c)
+        else {
+            continue;
+        };
+        let path = command
+            .args
+            .get(*arg)
+            .and_then(|x| x.value.as_ref())
+            .and_then(|x| x.try_as_string());
+        if let Some(path) = path {
+            if !paths.contains(&path) {
+                paths.push(path);
+            }
+        }
+    }
+    paths
+}
+
+/// Enumerate the surface forms the acceptor at `path` (an asset, `.hfst` or
+/// `.thfst`) accepts, depth first, within `options`' limits. Flag diacritics
+/// are honoured, so forms they rule out aren't listed.
+pub fn wordlist(
+    context: &Context,
+    path: &str,
+    options: &WordlistOptions,
+) -> Result<Wordlist, Error> {
+    if path.ends_with(".thfst") {
+        let fst = context.load_fst::<MmapThfstTransducer>(path)?;
+        Ok(enumerate(&Acceptor::<std::fs::File, _>::new(&fst), options))
+    } else {
+        let fst = context.load_fst::<HfstTransducer>(path)?;
+        Ok(enumerate(&Acceptor::<std::fs::File, _>::new(&fst), options))
+    }
+}
+
+/// The states and transitions [`enumerate`] walks.
+trait Graph {
+    /// Flag diacritic features, each of which a path keeps a value for.
+    fn flag_count(&self) -> usize;
+    fn is_final(&self, state: TransitionTableIndex) -> bool;
+    /// Transitions out of `state` that lead somewhere, in the order to follow
+    /// them.
+    fn edges(&self, state: TransitionTableIndex) -> Vec<Edge<'_>>;
+}
+
+struct Edge<'a> {
+    /// The symbol added to the form, `None` for epsilons and flags.
+    symbol: Option<&'a str>,
+    flag: Option<&'a FlagDiacriticOperation>,
+    target: TransitionTableIndex,
+}
+
+/// A transducer as a [`Graph`] of its input side.
+struct Acceptor<'a, F, T> {
+    fst: &'a T,
+    /// Symbols that add to a form: not flags or special symbols.
+    symbols: Vec<SymbolNumber>,
+    _file: std::marker::PhantomData<F>,
+}
+
+impl<'a, F, T> Acceptor<'a, F, T>
+where
+    F: divvun_fst::vfs::File,
+    T: Transducer<F>,
+{
+    fn new(fst: &'a T) -> Self {
+        let alphabet = fst.alphabet();
+        let keys = alphabet.key_table();
+        let symbols = (1..alphabet.initial_symbol_count().0)
+            .map(SymbolNumber)
+            .filter(|x| {
+                let key = &keys[x.0 as usize];
+                !alphabet.is_flag(*x) && !(key.starts_with("@_") && key.ends_with("_@"))
+            })
+            .collect();
+        Acceptor {
+            fst,
+            symbols,
+            _file: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<F, T> Graph for Acceptor<'_, F, T>
+where
+    F: divvun_fst::vfs::File,
+    T: Transducer<F>,
+{
+    fn flag_count(&self) -> usize {
+        self.fst.alphabet().state_size().0 as usize
+    }
+
+    fn is_final(&self, state: TransitionTableIndex) -> bool {
+        self.fst.is_final(state)
+    }
+
+    fn edges(&self, state: TransitionTableIndex) -> Vec<Edge<'_>> {
+        let alphabet = self.fst.alphabet();
+        let keys = alphabet.key_table();
+        let mut edges = Vec::new();
+
+        if self.fst.has_epsilons_or_flags(state.incr()) {
+            if let Some(mut next) = self.fst.next(state, SymbolNumber(0)) {
+                while let Some(transition) = self.fst.take_epsilons_and_flags(next) {
+                    next = next.incr();
+                    let Some(target) = transition.target() else {
+                        continue;
+                    };
+                    let flag = transition
+                        .symbol()
+                        .filter(|x| alphabet.is_flag(*x))
+                        .and_then(|x| alphabet.operations().get(&x));
+                    edges.push(Edge {
+                        symbol: None,
+                        flag,
+                        target,
+                    });
+                }
+            }
+        }
+
+        for &symbol in &self.symbols {
+            if !self.fst.has_transitions(state.incr(), Some(symbol)) {
+                continue;
+            }
+            let Some(mut next) = self.fst.next(state, symbol) else {
+                continue;
+            };
+            let key: &str = &keys[symbol.0 as usize];
+            while let Some(transition) = self.fst.take_non_epsilons(next, symbol) {
+                next = next.incr();
+                if let Some(target) = transition.target() {
+                    edges.push(Edge {
+                        symbol: Some(key),
+                        flag: None,
+                        target,
+                    });
+                }
+            }
+        }
+        edges
+    }
+}
+
+struct Partial {
+    state: TransitionTableIndex,
+    form: String,
+    /// Symbols in `form`.
+    length: usize,
+    /// Epsilons and flags followed since the last symbol.
+    epsilons: usize,
+    flags: Vec<i16>,
+}
+
+fn enumerate<G: Graph>(graph: &G, options: &WordlistOptions) -> Wordlist {
+    let mut forms = IndexSet::new();
+    let mut steps = 0;
+    let mut stack = vec![Partial {
+        state: TransitionTableIndex(0),
+        form: String::new(),
+        length: 0,
+        epsilons: 0,
+        flags: vec![0; graph.flag_count()],
+    }];
+
+    while let Some(path) = stack.pop() {
+        if forms.len() >= options.max_forms || steps >= options.max_steps {
+            return Wordlist {
+                forms: forms.into_iter().collect(),
+                complete: false,
+            };
+        }
+
+        if graph.is_final(path.state)
+            && !path.form.is_empty()
+            && options
+                .pattern
+                .as_ref()
+                .is_none_or(|x| x.is_match(&path.form))
+        {
+            forms.insert(path.form.clone());
+        }
+
+        let mut next_paths = Vec::new();
+        for edge in graph.edges(path.state) {
+            steps += 1;
+            match edge.symbol {
+                None => {
+                    // Epsilons and flags don't lengthen the form, so a loop
+                    // of them is cut off here instead.
+                    if path.epsilons >= options.max_epsilons {
+                        continue;
+                    }
+                    let mut flags = path.flags.clone();
+                    if edge.flag.is_none_or(|op| apply_flag(op, &mut flags)) {
+                        next_paths.push(Partial {
+                            state: edge.target,
+                            form: path.form.clone(),
+                            length: path.length,
+                            epsilons: path.epsilons + 1,
+                            flags,
+                        });
+                    }
+                }
+                Some(symbol) => {
+                    if path.length >= options.max_length {
+                        continue;
+                    }
+                    next_paths.push(Partial {
+                        state: edge.target,
+                        form: format!("{}{}", path.form, symbol),
+                        length: path.length + 1,
+                        epsilons: 0,
+                        flags: path.flags.clone(),
+                    });
+                }
+            }
+        }
+
+        // Reversed so the first transitions are followed first.
+        stack.extend(next_paths.into_iter().rev());
+    }
+
+    Wordlist {
+        forms: forms.into_iter().collect(),
+        complete: true,
+    }
+}
+
+/// Apply a flag diacritic to `flags`, returning whether the path may go on.
+fn apply_flag(op: &FlagDiacriticOperation, flags: &mut [i16]) -> bool {
+    let feature = op.feature.0 as usize;
+    let value = op.value.0;
+    match op.operation {
+        FlagDiacriticOperator::PositiveSet => {
+            flags[feature] = value;
+            true
+        }
+        FlagDiacriticOperator::NegativeSet => {
+            flags[feature] = -value;
+            true
+        }
+        FlagDiacriticOperator::Require if value == 0 => flags[feature] != 0,
+        FlagDiacriticOperator::Require => flags[feature] == value,
+        FlagDiacriticOperator::Disallow if value == 0 => flags[feature] == 0,
+        FlagDiacriticOperator::Disallow => flags[feature] != value,
+        FlagDiacriticOperator::Clear => {
+            flags[feature] = 0;
+            true
+        }
+        FlagDiacriticOperator::Unification => {
+            let current = flags[feature];
+            if current == 0 || current == value || (current < 0 && -current != value) {
+                flags[feature] = value;
+                true
+            } else {
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn acceptors_are_found_by_command_arg() {
+        let definition: ast::PipelineDefinition = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "string" },
+            "output": { "ref": "grammar" },
+            "commands": {
+                "spell": {
+                    "module": "divvun",
+                    "command": "cgspell",
+                    "args": {
+                        "acc_model_path": { "type": "path", "value": "acceptor.default.hfst" },
+                        "err_model_path": { "type": "path", "value": "errmodel.default.hfst" }
+                    },
+                    "input": { "ref": "#/entry" },
+                    "returns": "string"
+                },
+                "suggest": {
+                    "module": "spell",
+                    "command": "suggest",
+                    "args": {
+                        "lexicon_path": { "type": "path", "value": "acceptor.default.hfst" },
+                        "mutator_path": { "type": "path", "value": "errmodel.default.hfst" }
+                    },
+                    "input": { "ref": "spell" },
+                    "returns": "json"
+                },
+                "grammar": {
+                    "module": "cg3",
+                    "command": "vislcg3",
+                    "args": { "model_path": { "type": "path", "value": "grammar.bin" } },
+                    "input": { "ref": "suggest" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+
+        assert_eq!(acceptor_paths(&definition), ["acceptor.default.hfst"]);
+    }
+
+    /// States by number: whether it's final, and its transitions.
+    struct Toy(Vec<(bool, Vec<(Option<&'static str>, u32)>)>);
+
+    impl Graph for Toy {
+        fn flag_count(&self) -> usize {
+            0
+        }
+
+        fn is_final(&self, state: TransitionTableIndex) -> bool {
+            self.0[state.0 as usize].0
+        }
+
+        fn edges(&self, state: TransitionTableIndex) -> Vec<Edge<'_>> {
+            self.0[state.0 as usize]
+                .1
+                .iter()
+                .map(|&(symbol, target)| Edge {
+                    symbol,
+                    flag: None,
+                    target: TransitionTableIndex(target as _),
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn epsilons_dont_count_towards_the_length() {
+        // "ab" behind a run of epsilons, then an epsilon loop
+        let toy = Toy(vec![
+            (false, vec![(None, 1)]),
+            (false, vec![(None, 2)]),
+            (false, vec![(Some("a"), 3)]),
+            (false, vec![(None, 4)]),
+            (false, vec![(Some("b"), 5)]),
+            (true, vec![(None, 6), (Some("c"), 7)]),
+            (false, vec![(None, 5)]),
+            (true, vec![]),
+        ]);
+        let options = WordlistOptions {
+            max_length: 2,
+            ..Default::default()
+        };
+
+        let list = enumerate(&toy, &options);
+        assert_eq!(list.forms, ["ab"]);
+        assert!(list.complete);
+
+        let list = enumerate(&toy, &WordlistOptions::default());
+        assert_eq!(list.forms, ["ab", "abc"]);
+        assert!(list.complete);
+    }
+}
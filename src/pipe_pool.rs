@@ -0,0 +1,328 @@
+//! Warm pipelines of one bundle, reused across requests.
+//!
+//! Creating a [`PipelineHandle`] spawns a task per command, which is cheap next
+//! to loading the models but not free, and long-running servers see the same
+//! bundle over and over. A [`PipePool`] keeps finished handles around for the
+//! next request, caps how many run at once and drops handles nobody has used
+//! for a while. It also counts and times the requests it runs, for
+//! [metrics](crate::util::metrics).
+
+use std::{
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::StreamExt;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::{
+    ast::PipelineHandle,
+    bundle::{Bundle, Error},
+    modules::PipelineValue,
+    util::metrics::{Histogram, HistogramSnapshot, StageClock, StageTimings},
+};
+
+#[derive(Debug, Clone)]
+pub struct PipePoolOptions {
+    /// Maximum number of pipelines running at the same time.
+    pub max_concurrent: usize,
+    /// Maximum number of idle pipelines kept for reuse.
+    pub max_idle: usize,
+    /// Idle pipelines older than this are dropped by [`PipePool::evict_idle`].
+    pub idle_timeout: Duration,
+}
+
+impl Default for PipePoolOptions {
+    fn default() -> Self {
+        let max_concurrent = std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(4);
+        PipePoolOptions {
+            max_concurrent,
+            max_idle: max_concurrent,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// Counters of a [`PipePool`], e.g. for a status endpoint.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct PoolStats {
+    pub idle: usize,
+    pub in_use: usize,
+    pub max_concurrent: usize,
+    /// Requests waiting for a free slot.
+    pub waiting: usize,
+    pub created: u64,
+    pub reused: u64,
+    pub evicted: u64,
+    /// Requests run with [`PipePool::forward`], and how many of them failed.
+    pub requests: u64,
+    pub errors: u64,
+}
+
+/// ```
+/// # use std::sync::Arc;
+/// # use divvun_runtime::{bundle::Bundle, modules::PipelineValue, pipe_pool::{PipePool, PipePoolOptions}};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/toy");
+/// let pool = PipePool::new(Arc::new(Bundle::from_path(path).await?), PipePoolOptions::default());
+/// for text in ["giella", "sátni"] {
+///     let output = pool.forward(PipelineValue::String(text.into())).await?;
+///     println!("{:?}", output);
+/// }
+/// assert_eq!(pool.stats().reused, 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct PipePool {
+    bundle: Arc<Bundle>,
+    idle: Mutex<Vec<Idle>>,
+    permits: Arc<Semaphore>,
+    options: PipePoolOptions,
+    waiting: AtomicU64,
+    created: AtomicU64,
+    reused: AtomicU64,
+    evicted: AtomicU64,
+    requests: AtomicU64,
+    errors: AtomicU64,
+    latency: Histogram,
+    stages: Arc<StageTimings>,
+}
+
+/// A pipeline waiting in a [`PipePool`].
+struct Idle {
+    handle: PipelineHandle,
+    clock: StageClock,
+    /// Runtime config the pipeline last ran with.
+    config: serde_json::Value,
+    since: Instant,
+}
+
+impl PipePool {
+    pub fn new(bundle: Arc<Bundle>, options: PipePoolOptions) -> Arc<Self> {
+        Arc::new(PipePool {
+            bundle,
+            idle: Mutex::new(Vec::new()),
+            permits: Arc::new(Semaphore::new(options.max_concurrent.max(1))),
+            options,
+            waiting: AtomicU64::new(0),
+            created: AtomicU64::new(0),
+            reused: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+            requests: AtomicU64::new(0),
+            errors: AtomicU64::new(0),
+            latency: Histogram::default(),
+            stages: Arc::new(StageTimings::default()),
+        })
+    }
+
+    pub fn bundle(&self) -> &Arc<Bundle> {
+        &self.bundle
+    }
+
+    /// Wait for a free slot and take an idle pipeline, or create one. The
+    /// pipeline goes back to the pool when the returned guard is dropped,
+    /// if it was last used with [`PooledPipe::forward`] and that succeeded;
+    /// otherwise it may still be running a document and is dropped.
+    ///
+    /// Pooled pipelines are created with an empty runtime config.
+    pub async fn acquire(self: &Arc<Self>) -> Result<PooledPipe, Error> {
+        self.waiting.fetch_add(1, Ordering::Relaxed);
+        let permit = self.permits.clone().acquire_owned().await;
+        self.waiting.fetch_sub(1, Ordering::Relaxed);
+        let permit = permit.map_err(|e| Error::Command(crate::modules::Error::wrap(e)))?;
+
+        let idle = self
+            .idle
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .pop();
+        let (handle, clock, config) = match idle {
+            Some(idle) => {
+                self.reused.fetch_add(1, Ordering::Relaxed);
+                (idle.handle, idle.clock, idle.config)
+            }
+            None => {
+                self.created.fetch_add(1, Ordering::Relaxed);
+                let clock = StageClock::default();
+                let tap = self.stages.tap(clock.clone());
+                let config = serde_json::json!({});
+                let handle = self.bundle.create_with_tap(config.clone(), tap).await?;
+                (handle, clock, config)
+            }
+        };
+
+        Ok(PooledPipe {
+            handle: Some(handle),
+            drained: false,
+            config,
+            clock,
+            pool: self.clone(),
+            _permit: permit,
+        })
+    }
+
+    /// Run `input` through a pooled pipeline and collect its output. A
+    /// pipeline that fails is dropped rather than reused.
+    pub async fn forward(
+        self: &Arc<Self>,
+        input: PipelineValue,
+    ) -> Result<Vec<PipelineValue>, Error> {
+        self.forward_with_config(input, serde_json::json!({})).await
+    }
+
+    /// As [`forward`](Self::forward), with runtime config as for
+    /// [`Bundle::create`] for this input only. A command's `priority` stays
+    /// what the pipeline was created with.
+    pub async fn forward_with_config(
+        self: &Arc<Self>,
+        input: PipelineValue,
+        config: serde_json::Value,
+    ) -> Result<Vec<PipelineValue>, Error> {
+        let start = Instant::now();
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        let output = self.run(input, config).await;
+        self.latency.observe(start.elapsed());
+        if output.is_err() {
+            self.errors.fetch_add(1, Ordering::Relaxed);
+        }
+        output
+    }
+
+    async fn run(
+        self: &Arc<Self>,
+        input: PipelineValue,
+        config: serde_json::Value,
+    ) -> Result<Vec<PipelineValue>, Error> {
+        let mut pipe = self.acquire().await?;
+        pipe.clock.start();
+        pipe.forward_with_config(input, config).await
+    }
+
+    /// Drop pipelines idle for longer than the idle timeout. Returns how many
+    /// were dropped.
+    pub fn evict_idle(&self) -> usize {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        let before = idle.len();
+        let timeout = self.options.idle_timeout;
+        idle.retain(|x| x.since.elapsed() < timeout);
+        let evicted = before - idle.len();
+        self.evicted.fetch_add(evicted as u64, Ordering::Relaxed);
+        evicted
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        let max_concurrent = self.options.max_concurrent.max(1);
+        PoolStats {
+            idle: self
+                .idle
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len(),
+            in_use: max_concurrent - self.permits.available_permits(),
+            max_concurrent,
+            waiting: self.waiting.load(Ordering::Relaxed) as usize,
+            created: self.created.load(Ordering::Relaxed),
+            reused: self.reused.load(Ordering::Relaxed),
+            evicted: self.evicted.load(Ordering::Relaxed),
+            requests: self.requests.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Time taken by the requests run with [`forward`](Self::forward),
+    /// waiting for a free slot included.
+    pub fn latency(&self) -> HistogramSnapshot {
+        self.latency.snapshot()
+    }
+
+    /// Time taken by each step of the pipelines, by step key. See
+    /// [`StageTimings`].
+    pub fn stage_latency(&self) -> Vec<(String, HistogramSnapshot)> {
+        self.stages.snapshot()
+    }
+
+    fn release(&self, handle: PipelineHandle, clock: StageClock, config: serde_json::Value) {
+        let mut idle = self.idle.lock().unwrap_or_else(PoisonError::into_inner);
+        if idle.len() < self.options.max_idle {
+            idle.push(Idle {
+                handle,
+                clock,
+                config,
+                since: Instant::now(),
+            });
+        } else {
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A pipeline borrowed from a [`PipePool`].
+pub struct PooledPipe {
+    handle: Option<PipelineHandle>,
+    /// Whether every document sent through the handle has run to its end,
+    /// so the next borrower won't get its output.
+    drained: bool,
+    /// Runtime config the pipeline runs with.
+    config: serde_json::Value,
+    clock: StageClock,
+    pool: Arc<PipePool>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledPipe {
+    /// The pipeline itself. A pipeline used this way isn't pooled again, as
+    /// its documents may not have run to their end.
+    pub fn handle(&mut self) -> &mut PipelineHandle {
+        self.drained = false;
+        self.handle.as_mut().expect("pipeline taken")
+    }
+
+    /// Run `input` through the pipeline and collect its output. The pipeline
+    /// is pooled again only if the whole output was read without an error.
+    pub async fn forward(&mut self, input: PipelineValue) -> Result<Vec<PipelineValue>, Error> {
+        self.forward_with_config(input, serde_json::json!({})).await
+    }
+
+    /// As [`forward`](Self::forward), with runtime config as for
+    /// [`PipePool::forward_with_config`].
+    pub async fn forward_with_config(
+        &mut self,
+        input: PipelineValue,
+        config: serde_json::Value,
+    ) -> Result<Vec<PipelineValue>, Error> {
+        if config != self.config {
+            if let Some(handle) = &self.handle {
+                handle.update_config(config.clone());
+            }
+            self.config = config;
+        }
+        let mut output = Vec::new();
+        let mut stream = self.handle().forward(input).await;
+        while let Some(value) = stream.next().await {
+            output.push(value?);
+        }
+        self.drained = true;
+        Ok(output)
+    }
+
+    /// Drop the pipeline instead of returning it to the pool, e.g. after an
+    /// error left it in an unknown state.
+    pub fn discard(mut self) {
+        self.handle = None;
+    }
+}
+
+impl Drop for PooledPipe {
+    fn drop(&mut self) {
+        if let Some(handle) = self.handle.take().filter(|_| self.drained) {
+            let config = std::mem::take(&mut self.config);
+            self.pool.release(handle, self.clock.clone(), config);
+        }
+    }
+}
@@ -0,0 +1,319 @@
+//! Whether the host has the memory, disk and GPU a pipeline needs, from the
+//! `resources` its commands declare in `rt_command`.
+//!
+//! Bundles are checked when they are loaded, before any command starts, so a
+//! device short on memory gets a warning saying what is missing (or an error,
+//! with [`BundleOptions::require_resources`](crate::bundle::BundleOptions))
+//! instead of being killed halfway through loading the models.
+
+use std::fmt;
+
+use crate::{
+    ast::PipelineDefinition,
+    modules::{Gpu, Resources},
+};
+
+/// What the host offers. Amounts that aren't known aren't checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Host {
+    /// Bytes of memory available to this process.
+    pub memory: Option<u64>,
+    /// Bytes of free disk where assets are downloaded or extracted.
+    pub disk: Option<u64>,
+    pub gpu: Option<bool>,
+}
+
+impl Host {
+    /// What can be found out about this machine: available memory and GPU
+    /// devices on Linux and Android. Free disk space isn't detected; apps
+    /// that download bundles can fill it in from their platform's API.
+    pub fn detect() -> Host {
+        Host {
+            memory: available_memory(),
+            disk: None,
+            gpu: gpu_present(),
+        }
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn available_memory() -> Option<u64> {
+    parse_meminfo(&std::fs::read_to_string("/proc/meminfo").ok()?)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn available_memory() -> Option<u64> {
+    None
+}
+
+/// `MemAvailable` of `/proc/meminfo`, in bytes.
+#[cfg_attr(not(any(target_os = "linux", target_os = "android")), allow(dead_code))]
+fn parse_meminfo(meminfo: &str) -> Option<u64> {
+    let line = meminfo.lines().find(|x| x.starts_with("MemAvailable:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn gpu_present() -> Option<bool> {
+    let devices = ["/dev/nvidiactl", "/dev/kfd", "/dev/dri/renderD128"];
+    Some(devices.iter().any(|x| std::path::Path::new(x).exists()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn gpu_present() -> Option<bool> {
+    None
+}
+
+/// What a pipeline needs and what the host lacks of it. See
+/// [`Bundle::preflight`](crate::bundle::Bundle::preflight).
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Preflight {
+    /// Memory and disk summed over the pipeline's commands, and the most any
+    /// of them needs a GPU.
+    pub needs: Resources,
+    /// What each command declares, by step key, for those that declare
+    /// anything.
+    pub commands: Vec<(String, Resources)>,
+    pub host: Host,
+    pub shortfalls: Vec<Shortfall>,
+}
+
+impl Preflight {
+    pub fn is_ok(&self) -> bool {
+        self.shortfalls.is_empty()
+    }
+}
+
+/// Something a pipeline needs that the host doesn't have.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Shortfall {
+    /// Not enough memory; `largest` are the steps needing the most.
+    Memory {
+        needed: u64,
+        available: u64,
+        largest: Vec<String>,
+    },
+    Disk {
+        needed: u64,
+        available: u64,
+    },
+    /// No GPU for the steps that can't run without one.
+    Gpu {
+        steps: Vec<String>,
+    },
+}
+
+impl fmt::Display for Shortfall {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shortfall::Memory {
+                needed,
+                available,
+                largest,
+            } => write!(
+                f,
+                "about {} of memory, mostly for {} ({} available)",
+                size(*needed),
+                largest.join(" and "),
+                size(*available)
+            ),
+            Shortfall::Disk { needed, available } => write!(
+                f,
+                "about {} of disk for its assets ({} free)",
+                size(*needed),
+                size(*available)
+            ),
+            Shortfall::Gpu { steps } => write!(f, "a GPU, for {}", steps.join(", ")),
+        }
+    }
+}
+
+/// A pipeline the host doesn't have the resources for, when they are
+/// [required](crate::bundle::BundleOptions::require_resources).
+#[derive(Debug, Clone)]
+pub struct Unavailable {
+    pub shortfalls: Vec<Shortfall>,
+}
+
+impl fmt::Display for Unavailable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "This device lacks what the pipeline needs. It needs:")?;
+        for shortfall in &self.shortfalls {
+            write!(f, "\n  - {}", shortfall)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Unavailable {}
+
+impl miette::Diagnostic for Unavailable {
+    fn code<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        Some(Box::new("divvun_runtime::insufficient_resources"))
+    }
+
+    fn help<'a>(&'a self) -> Option<Box<dyn fmt::Display + 'a>> {
+        let help = self
+            .shortfalls
+            .iter()
+            .map(|x| match x {
+                Shortfall::Memory { .. } => {
+                    "close other apps or load a smaller pipeline, e.g. one without speech"
+                }
+                Shortfall::Disk { .. } => "free up disk space",
+                Shortfall::Gpu { .. } => "run it on a machine with a GPU",
+            })
+            .collect::<Vec<_>>()
+            .join("; ");
+        let mut chars = help.chars();
+        let first = chars.next()?;
+        Some(Box::new(format!(
+            "{}{}",
+            first.to_uppercase(),
+            chars.as_str()
+        )))
+    }
+}
+
+/// Sum what the commands of `defn` need and compare it with `host`.
+pub fn check(defn: &PipelineDefinition, host: &Host) -> Preflight {
+    let commands = defn.commands.iter().filter_map(|(key, command)| {
+        let def = crate::modules::find_command(&command.module, &command.command)?;
+        Some((key.clone(), def.resources))
+    });
+    compare(commands, host)
+}
+
+fn compare(commands: impl Iterator<Item = (String, Resources)>, host: &Host) -> Preflight {
+    let commands = commands
+        .filter(|(_, x)| *x != Resources::default())
+        .collect::<Vec<_>>();
+    let mut needs = Resources::default();
+    for (_, resources) in &commands {
+        needs.memory += resources.memory;
+        needs.disk += resources.disk;
+        needs.gpu = needs.gpu.max(resources.gpu);
+    }
+
+    let mut shortfalls = Vec::new();
+    if let Some(available) = host.memory.filter(|&x| x < needs.memory) {
+        let mut by_memory = commands.iter().collect::<Vec<_>>();
+        by_memory.sort_by(|a, b| b.1.memory.cmp(&a.1.memory));
+        shortfalls.push(Shortfall::Memory {
+            needed: needs.memory,
+            available,
+            largest: by_memory
+                .iter()
+                .take(2)
+                .map(|(key, _)| key.clone())
+                .collect(),
+        });
+    }
+    if let Some(available) = host.disk.filter(|&x| x < needs.disk) {
+        shortfalls.push(Shortfall::Disk {
+            needed: needs.disk,
+            available,
+        });
+    }
+    if host.gpu == Some(false) && needs.gpu == Gpu::Required {
+        shortfalls.push(Shortfall::Gpu {
+            steps: commands
+                .iter()
+                .filter(|(_, x)| x.gpu == Gpu::Required)
+                .map(|(key, _)| key.clone())
+                .collect(),
+        });
+    }
+
+    Preflight {
+        needs,
+        commands,
+        host: host.clone(),
+        shortfalls,
+    }
+}
+
+/// `bytes` for people: `1.5 GB`, `300 MB`, `12 KB`.
+fn size(bytes: u64) -> String {
+    const MB: u64 = 1 << 20;
+    const GB: u64 = 1 << 30;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes as f64 / GB as f64)
+    } else if bytes >= MB {
+        format!("{} MB", bytes / MB)
+    } else {
+        format!("{} KB", bytes.div_ceil(1024))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(memory_mb: u64, gpu: Gpu) -> Resources {
+        Resources {
+            memory: memory_mb << 20,
+            disk: 0,
+            gpu,
+        }
+    }
+
+    #[test]
+    fn sums_memory_and_names_the_largest_steps() {
+        let commands = [
+            ("tokenize".to_string(), resources(64, Gpu::None)),
+            ("tts".to_string(), resources(600, Gpu::Optional)),
+            ("cg".to_string(), resources(100, Gpu::None)),
+            ("forward".to_string(), Resources::default()),
+        ];
+        let host = Host {
+            memory: Some(512 << 20),
+            disk: None,
+            gpu: Some(false),
+        };
+        let preflight = compare(commands.into_iter(), &host);
+
+        assert_eq!(preflight.needs.memory, 764 << 20);
+        assert_eq!(preflight.needs.gpu, Gpu::Optional);
+        assert_eq!(preflight.commands.len(), 3);
+        assert_eq!(
+            preflight.shortfalls,
+            [Shortfall::Memory {
+                needed: 764 << 20,
+                available: 512 << 20,
+                largest: vec!["tts".to_string(), "cg".to_string()],
+            }]
+        );
+        assert_eq!(
+            preflight.shortfalls[0].to_string(),
+            "about 764 MB of memory, mostly for tts and cg (512 MB available)"
+        );
+    }
+
+    #[test]
+    fn unknown_amounts_pass_and_required_gpus_are_checked() {
+        let commands = [("tts".to_string(), resources(600, Gpu::Required))];
+        assert!(compare(commands.clone().into_iter(), &Host::default()).is_ok());
+
+        let host = Host {
+            gpu: Some(false),
+            ..Default::default()
+        };
+        let preflight = compare(commands.into_iter(), &host);
+        assert_eq!(
+            preflight.shortfalls,
+            [Shortfall::Gpu {
+                steps: vec!["tts".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    fn reads_available_memory() {
+        let meminfo = "MemTotal:       16314672 kB\nMemFree:         1034032 kB\nMemAvailable:    8157336 kB\n";
+        assert_eq!(parse_meminfo(meminfo), Some(8157336 * 1024));
+        assert_eq!(parse_meminfo("MemTotal: 1 kB\n"), None);
+    }
+}
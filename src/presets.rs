@@ -0,0 +1,232 @@
+//! Standard pipelines as [`PipelineDefinition`]s, for embedders that have a
+//! language's models on disk but no `.drb` bundle (tests, other GiellaLT
+//! tooling). Each preset follows the chain the GiellaLT language repositories
+//! build, so callers get the current best practice instead of copying it.
+//!
+//! Asset paths are resolved by the [`Context`](crate::modules::Context) the
+//! pipeline runs in, like the paths in a TypeScript pipeline.
+//!
+//! ```no_run
+//! # use divvun_runtime::presets::{self, GrammarCheckerAssets};
+//! let defn = presets::grammar_checker(&GrammarCheckerAssets {
+//!     mwe_disambiguator: None,
+//!     ..Default::default()
+//! })?;
+//! # Ok::<_, divvun_runtime::ast::Error>(())
+//! ```
+
+use std::path::PathBuf;
+
+use indexmap::IndexMap;
+
+#[cfg(any(feature = "mod-divvun", feature = "mod-speech"))]
+use crate::ast::{Arg, Error, PipelineBuilder, PipelineDefinition};
+
+/// Models of a grammar checker. The defaults are the file names GiellaLT
+/// language repositories use; optional stages are skipped when `None`.
+#[derive(Debug, Clone)]
+pub struct GrammarCheckerAssets {
+    pub tokenizer: PathBuf,
+    pub whitespace_analyzer: Option<PathBuf>,
+    pub disambiguator: PathBuf,
+    pub mwe_disambiguator: Option<PathBuf>,
+    pub spell_error_model: PathBuf,
+    pub spell_acceptor: PathBuf,
+    pub post_spell: Option<PathBuf>,
+    pub grammar: PathBuf,
+    pub generator: PathBuf,
+}
+
+impl Default for GrammarCheckerAssets {
+    fn default() -> Self {
+        Self {
+            tokenizer: "tokeniser-gramcheck-gt-desc.pmhfst".into(),
+            whitespace_analyzer: Some("analyser-gt-whitespace.hfst".into()),
+            disambiguator: "valency.bin".into(),
+            mwe_disambiguator: Some("mwe-dis.bin".into()),
+            spell_error_model: "errmodel.default.hfst".into(),
+            spell_acceptor: "acceptor.default.hfst".into(),
+            post_spell: Some("valency-postspell.bin".into()),
+            grammar: "grammarchecker.bin".into(),
+            generator: "generator-gramcheck-gt-norm.hfstol".into(),
+        }
+    }
+}
+
+/// Text in, `divvun::suggest` JSON out: tokenize, disambiguate, spell check,
+/// run the grammar rules and generate suggestions.
+#[cfg(feature = "mod-divvun")]
+pub fn grammar_checker(assets: &GrammarCheckerAssets) -> Result<PipelineDefinition, Error> {
+    let mut b = PipelineBuilder::new().cmd(
+        "tokenize",
+        "hfst",
+        "tokenize",
+        [("model_path", Arg::path(&assets.tokenizer))],
+    );
+    if let Some(path) = &assets.whitespace_analyzer {
+        b = b.cmd(
+            "blanktag",
+            "divvun",
+            "blanktag",
+            [("model_path", Arg::path(path))],
+        );
+    }
+    b = b.cmd(
+        "disambiguate",
+        "cg3",
+        "vislcg3",
+        [("model_path", Arg::path(&assets.disambiguator))],
+    );
+    if let Some(path) = &assets.mwe_disambiguator {
+        b = b
+            .cmd("mwesplit", "cg3", "mwesplit", Vec::<(String, Arg)>::new())
+            .cmd(
+                "mwe-dis",
+                "cg3",
+                "vislcg3",
+                [("model_path", Arg::path(path))],
+            );
+    }
+    b = b.cmd(
+        "cgspell",
+        "divvun",
+        "cgspell",
+        [
+            ("err_model_path", Arg::path(&assets.spell_error_model)),
+            ("acc_model_path", Arg::path(&assets.spell_acceptor)),
+        ],
+    );
+    if let Some(path) = &assets.post_spell {
+        b = b.cmd(
+            "post-spell",
+            "cg3",
+            "vislcg3",
+            [("model_path", Arg::path(path))],
+        );
+    }
+    b.cmd(
+        "grammar",
+        "cg3",
+        "vislcg3",
+        [("model_path", Arg::path(&assets.grammar))],
+    )
+    .cmd(
+        "suggest",
+        "divvun",
+        "suggest",
+        [("model_path", Arg::path(&assets.generator))],
+    )
+    .build()
+}
+
+/// Models and voice of a text-to-speech pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct TtsAssets {
+    pub tokenizer: PathBuf,
+    pub disambiguator: PathBuf,
+    /// Normalizer per tag, e.g. `"Sem/Plc"`.
+    pub normalizers: IndexMap<String, PathBuf>,
+    pub normalizer_generator: PathBuf,
+    pub normalizer_analyzer: PathBuf,
+    pub phon: PathBuf,
+    /// Phonology model per tag, e.g. `"Prop"`.
+    pub phon_tag_models: IndexMap<String, PathBuf>,
+    pub voice_model: PathBuf,
+    pub vocoder_model: PathBuf,
+    pub speaker: isize,
+    pub language: isize,
+}
+
+/// Text in, WAV audio out: tokenize, disambiguate, normalize numbers and
+/// abbreviations, add phonological forms and synthesize each sentence.
+#[cfg(feature = "mod-speech")]
+pub fn tts(assets: &TtsAssets) -> Result<PipelineDefinition, Error> {
+    PipelineBuilder::new()
+        .cmd(
+            "tokenize",
+            "hfst",
+            "tokenize",
+            [("model_path", Arg::path(&assets.tokenizer))],
+        )
+        .cmd(
+            "disambiguate",
+            "cg3",
+            "vislcg3",
+            [("model_path", Arg::path(&assets.disambiguator))],
+        )
+        .cmd(
+            "normalize",
+            "speech",
+            "normalize",
+            [
+                ("normalizers", Arg::map_path(assets.normalizers.clone())),
+                ("generator", Arg::path(&assets.normalizer_generator)),
+                ("analyzer", Arg::path(&assets.normalizer_analyzer)),
+            ],
+        )
+        .cmd(
+            "phon",
+            "speech",
+            "phon",
+            [
+                ("model", Arg::path(&assets.phon)),
+                ("tag_models", Arg::map_path(assets.phon_tag_models.clone())),
+            ],
+        )
+        .cmd(
+            "sentences",
+            "cg3",
+            "sentences",
+            [("mode", Arg::string("phonological"))],
+        )
+        .cmd(
+            "tts",
+            "speech",
+            "tts",
+            [
+                ("voice_model", Arg::path(&assets.voice_model)),
+                ("vocoder_model", Arg::path(&assets.vocoder_model)),
+                ("speaker", Arg::int(assets.speaker)),
+                ("language", Arg::int(assets.language)),
+                (
+                    "config",
+                    Arg::json(serde_json::json!({ "voices": {} }))
+                        .map_err(|e| Error::Command(crate::modules::Error::wrap(e)))?,
+                ),
+            ],
+        )
+        .build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(feature = "mod-divvun")]
+    #[test]
+    fn grammar_checker_skips_missing_stages() {
+        let full = grammar_checker(&GrammarCheckerAssets::default()).unwrap();
+        assert_eq!(full.commands.len(), 9);
+        assert_eq!(full.output.r#ref, "suggest");
+
+        let minimal = grammar_checker(&GrammarCheckerAssets {
+            whitespace_analyzer: None,
+            mwe_disambiguator: None,
+            post_spell: None,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(
+            minimal.commands.keys().collect::<Vec<_>>(),
+            ["tokenize", "disambiguate", "cgspell", "grammar", "suggest"]
+        );
+    }
+
+    #[cfg(feature = "mod-speech")]
+    #[test]
+    fn tts_feeds_sentences_to_the_synthesizer() {
+        let defn = tts(&TtsAssets::default()).unwrap();
+        assert_eq!(defn.output.r#ref, "tts");
+        assert_eq!(defn.commands["tts"].returns, "bytes");
+    }
+}
@@ -0,0 +1,605 @@
+//! Grammar checking of documents an editor keeps changing.
+//!
+//! A [`CheckSession`] keeps one pipeline warm and checks documents a sentence
+//! at a time, caching each sentence's errors by the hash of its text. When a
+//! document is checked again after an edit, only the sentences that changed go
+//! through the pipeline; the rest come from the cache, moved to where the
+//! sentence is now. Sentences end where `divvun::suggest` ends them with
+//! `flush_mode: "sentence"`: at its `delimiters`, from the config when given. Words the user added to their dictionary, errors they
+//! chose to ignore and errors in regions of a document they turned checking
+//! off for are filtered out of every result, cached or not.
+//!
+//! The pipeline has to end in `divvun::suggest` with JSON output. Rules can't
+//! see across sentence boundaries in a session, and errors carry offsets only
+//! (`positions: "linecol"` is not supported).
+
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use futures_util::StreamExt;
+use lru::LruCache;
+
+use crate::{
+    ast::PipelineHandle,
+    bundle::{Bundle, Error},
+    modules::{
+        self, PipelineValue, cg3_util,
+        divvun::{GrammarErr, GrammarOutput, NocheckRange},
+    },
+};
+
+/// Sentences whose results are kept when no capacity is given.
+pub const DEFAULT_CACHE_CAPACITY: usize = 10_000;
+
+/// Counters of a [`CheckSession`]'s sentence cache.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct SessionStats {
+    pub documents: usize,
+    pub cached_sentences: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Errors found in a document, with offsets into the whole document.
+#[derive(Debug, Clone, Default)]
+pub struct DocumentCheck {
+    pub errors: Vec<GrammarErr>,
+    /// Sentences that went through the pipeline, rather than the cache.
+    pub checked: usize,
+    /// The time budget ran out on one of the sentences checked.
+    pub timed_out: bool,
+}
+
+struct Document {
+    text: String,
+    /// Byte ranges not to report errors in, moved along by edits.
+    nocheck: Vec<Range<usize>>,
+}
+
+pub struct CheckSession {
+    handle: PipelineHandle,
+    utf16: bool,
+    /// Sentence delimiters of the suggest config.
+    delimiters: HashSet<String>,
+    documents: HashMap<String, Document>,
+    cache: SentenceCache,
+    dictionary: HashSet<String>,
+    ignored_ids: HashSet<String>,
+    ignored: HashSet<(String, String)>,
+    hits: u64,
+    misses: u64,
+}
+
+impl CheckSession {
+    /// Start a session on `bundle`'s pipeline, run with `config` like
+    /// [`Bundle::create`].
+    pub async fn new(bundle: &Bundle, config: serde_json::Value) -> Result<CheckSession, Error> {
+        let utf16 = reports_utf16(&config);
+        let delimiters = sentence_delimiters(&config);
+        let handle = bundle.create(config).await?;
+
+        Ok(CheckSession {
+            handle,
+            utf16,
+            delimiters,
+            documents: HashMap::new(),
+            cache: SentenceCache::new(DEFAULT_CACHE_CAPACITY),
+            dictionary: HashSet::new(),
+            ignored_ids: HashSet::new(),
+            ignored: HashSet::new(),
+            hits: 0,
+            misses: 0,
+        })
+    }
+
+    /// Keep the results of at most `capacity` sentences.
+    pub fn with_cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache.set_capacity(capacity);
+        self
+    }
+
+    /// Start tracking a document, or replace the text of one already open.
+    pub fn open(&mut self, id: impl Into<String>, text: impl Into<String>) {
+        self.documents.insert(
+            id.into(),
+            Document {
+                text: text.into(),
+                nocheck: Vec::new(),
+            },
+        );
+    }
+
+    /// Report no errors in `ranges` (UTF-8 byte offsets) of document `id`,
+    /// e.g. code blocks or a selection the user turned checking off for.
+    /// Replaces the ranges set before. Edits move them along with the text
+    /// around them, and text typed inside one is not checked either.
+    pub fn set_nocheck(&mut self, id: &str, ranges: Vec<Range<usize>>) -> Result<(), Error> {
+        let document = self.document_mut(id)?;
+        if let Some(range) = ranges.iter().find(|x| {
+            x.start > x.end
+                || x.end > document.text.len()
+                || !document.text.is_char_boundary(x.start)
+                || !document.text.is_char_boundary(x.end)
+        }) {
+            return Err(Error::Command(modules::Error::msg(format!(
+                "nocheck range {}..{} is not within document '{}'",
+                range.start, range.end, id
+            ))));
+        }
+        document.nocheck = ranges;
+        Ok(())
+    }
+
+    /// Replace `range` (UTF-8 byte offsets) of document `id` with `text`.
+    /// Sentences outside the edit keep their cached results.
+    pub fn edit(&mut self, id: &str, range: Range<usize>, text: &str) -> Result<(), Error> {
+        let document = self.document_mut(id)?;
+        if range.start > range.end
+            || range.end > document.text.len()
+            || !document.text.is_char_boundary(range.start)
+            || !document.text.is_char_boundary(range.end)
+        {
+            return Err(Error::Command(modules::Error::msg(format!(
+                "edit range {}..{} is not within document '{}'",
+                range.start, range.end, id
+            ))));
+        }
+        document.nocheck = document
+            .nocheck
+            .iter()
+            .filter_map(|x| moved_by_edit(x, &range, text.len()))
+            .collect();
+        document.text.replace_range(range, text);
+        Ok(())
+    }
+
+    pub fn close(&mut self, id: &str) {
+        self.documents.remove(id);
+    }
+
+    pub fn text(&self, id: &str) -> Option<&str> {
+        self.documents.get(id).map(|x| x.text.as_str())
+    }
+
+    /// Forget every cached result, e.g. after the bundle's rules changed.
+    pub fn invalidate_all(&mut self) {
+        self.cache.clear();
+    }
+
+    /// Check with `config` from now on, e.g. other locales or ignored error
+    /// types, without reloading the pipeline. Cached results are dropped, as
+    /// they were found with the old config.
+    pub fn update_config(&mut self, config: serde_json::Value) {
+        self.utf16 = reports_utf16(&config);
+        self.delimiters = sentence_delimiters(&config);
+        self.handle.update_config(config);
+        self.cache.clear();
+    }
+
+    /// Forget the cached results of document `id`'s sentences, so the next
+    /// check runs all of them through the pipeline again.
+    pub fn invalidate(&mut self, id: &str) -> Result<(), Error> {
+        let text = self.document(id)?.text.clone();
+        for range in split_sentences_at(&text, &self.delimiters) {
+            self.cache.remove(&blake3::hash(text[range].as_bytes()));
+        }
+        Ok(())
+    }
+
+    /// Never report errors on `word`.
+    pub fn add_word(&mut self, word: impl Into<String>) {
+        self.dictionary.insert(word.into());
+    }
+
+    pub fn remove_word(&mut self, word: &str) {
+        self.dictionary.remove(word);
+    }
+
+    /// Stop reporting errors of type `error_id`.
+    pub fn ignore_error_id(&mut self, error_id: impl Into<String>) {
+        self.ignored_ids.insert(error_id.into());
+    }
+
+    /// Stop reporting `error`: errors of its type on the same form.
+    pub fn ignore(&mut self, error: &GrammarErr) {
+        self.ignored
+            .insert((error.error_id.clone(), error.form.clone()));
+    }
+
+    /// Report everything that was ignored again.
+    pub fn clear_ignored(&mut self) {
+        self.ignored_ids.clear();
+        self.ignored.clear();
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            documents: self.documents.len(),
+            cached_sentences: self.cache.len(),
+            hits: self.hits,
+            misses: self.misses,
+        }
+    }
+
+    /// Check document `id`, running only sentences not in the cache.
+    pub async fn check(&mut self, id: &str) -> Result<DocumentCheck, Error> {
+        let document = self.document(id)?;
+        let text = document.text.clone();
+        let nocheck = document
+            .nocheck
+            .iter()
+            .map(|x| NocheckRange {
+                start: self.offset(&text, x.start),
+                end: self.offset(&text, x.end),
+            })
+            .collect::<Vec<_>>();
+        let mut result = DocumentCheck::default();
+
+        for range in split_sentences_at(&text, &self.delimiters) {
+            let sentence = &text[range.clone()];
+            let hash = blake3::hash(sentence.as_bytes());
+            let errors = match self.cache.get(&hash) {
+                Some(errors) => {
+                    self.hits += 1;
+                    errors
+                }
+                None => {
+                    self.misses += 1;
+                    result.checked += 1;
+                    let output = self.run(sentence).await?;
+                    let errors = Arc::new(output.errors);
+                    // A timed-out sentence has partial errors; check it again
+                    // next time instead of caching them.
+                    if output.timed_out {
+                        result.timed_out = true;
+                    } else {
+                        self.cache.insert(hash, errors.clone());
+                    }
+                    errors
+                }
+            };
+
+            let shift = self.offset(&text, range.start);
+            result.errors.extend(
+                errors
+                    .iter()
+                    .filter(|x| self.is_reported(x))
+                    .map(|x| shifted(x, shift))
+                    .filter(|x| !nocheck.iter().any(|n| n.covers(x.start, x.end))),
+            );
+        }
+
+        Ok(result)
+    }
+
+    async fn run(&mut self, sentence: &str) -> Result<GrammarOutput, Error> {
+        let mut output = None;
+        let mut stream = self
+            .handle
+            .forward(PipelineValue::String(sentence.to_string()))
+            .await;
+        while let Some(value) = stream.next().await {
+            let json = match value? {
+                PipelineValue::Json(json) => json,
+                PipelineValue::String(text) => serde_json::from_str(&text).map_err(not_suggest)?,
+                _ => return Err(not_suggest("not JSON")),
+            };
+            let value: GrammarOutput = serde_json::from_value(json).map_err(not_suggest)?;
+            match &mut output {
+                None => output = Some(value),
+                Some(output) => {
+                    output.errors.extend(value.errors);
+                    output.timed_out |= value.timed_out;
+                }
+            }
+        }
+        output.ok_or_else(|| not_suggest("no output"))
+    }
+
+    fn is_reported(&self, error: &GrammarErr) -> bool {
+        !self.dictionary.contains(&error.form)
+            && !self.ignored_ids.contains(&error.error_id)
+            && !self
+                .ignored
+                .contains(&(error.error_id.clone(), error.form.clone()))
+    }
+
+    /// Byte offset `at` of `text` in the units errors are reported in.
+    fn offset(&self, text: &str, at: usize) -> usize {
+        if self.utf16 {
+            text[..at].encode_utf16().count()
+        } else {
+            at
+        }
+    }
+
+    fn document(&self, id: &str) -> Result<&Document, Error> {
+        self.documents.get(id).ok_or_else(|| unknown_document(id))
+    }
+
+    fn document_mut(&mut self, id: &str) -> Result<&mut Document, Error> {
+        self.documents
+            .get_mut(id)
+            .ok_or_else(|| unknown_document(id))
+    }
+}
+
+/// Errors of each sentence by the hash of its text, offsets relative to the
+/// sentence. The least recently used are evicted when full.
+struct SentenceCache {
+    entries: LruCache<blake3::Hash, Arc<Vec<GrammarErr>>>,
+    capacity: usize,
+}
+
+impl SentenceCache {
+    fn new(capacity: usize) -> Self {
+        SentenceCache {
+            entries: LruCache::unbounded(),
+            capacity,
+        }
+    }
+
+    fn get(&mut self, hash: &blake3::Hash) -> Option<Arc<Vec<GrammarErr>>> {
+        self.entries.get(hash).cloned()
+    }
+
+    fn insert(&mut self, hash: blake3::Hash, errors: Arc<Vec<GrammarErr>>) {
+        self.entries.put(hash, errors);
+        self.evict();
+    }
+
+    fn remove(&mut self, hash: &blake3::Hash) {
+        self.entries.pop(hash);
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.evict();
+    }
+
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity {
+            self.entries.pop_lru();
+        }
+    }
+}
+
+fn unknown_document(id: &str) -> Error {
+    Error::Command(modules::Error::msg(format!("no open document '{}'", id)))
+}
+
+fn not_suggest(e: impl std::fmt::Display) -> Error {
+    Error::Command(modules::Error::msg(format!(
+        "session pipeline output is not divvun::suggest JSON: {}",
+        e
+    )))
+}
+
+/// `error` with its offsets moved `by` units later.
+fn shifted(error: &GrammarErr, by: usize) -> GrammarErr {
+    let mut error = error.clone();
+    error.start += by;
+    error.end += by;
+    for relation in error.relations.iter_mut().flatten() {
+        relation.start += by;
+        relation.end += by;
+    }
+    error.position = None;
+    error
+}
+
+/// Where `range` ends up after replacing `edit` with `len` bytes of text, or
+/// `None` if nothing is left of it. Text replacing part of the range joins it;
+/// text inserted at either end stays outside.
+fn moved_by_edit(range: &Range<usize>, edit: &Range<usize>, len: usize) -> Option<Range<usize>> {
+    let after = |at: usize| at - edit.end + edit.start + len;
+    let start = if range.start < edit.start {
+        range.start
+    } else if range.start >= edit.end {
+        after(range.start)
+    } else {
+        edit.start
+    };
+    let end = if range.end <= edit.start {
+        range.end
+    } else if range.end >= edit.end {
+        after(range.end)
+    } else {
+        edit.start + len
+    };
+    (start < end).then_some(start..end)
+}
+
+/// Whether suggest reports offsets in UTF-16 code units with `config`, the
+/// unit cached errors are then shifted in.
+fn reports_utf16(config: &serde_json::Value) -> bool {
+    config
+        .as_object()
+        .into_iter()
+        .flat_map(|x| x.values())
+        .any(|x| x.get("encoding").and_then(|x| x.as_str()) == Some("utf-16"))
+}
+
+/// The sentence delimiters suggest uses with `config`: its `delimiters`, or
+/// the default `.`, `?` and `!`.
+fn sentence_delimiters(config: &serde_json::Value) -> HashSet<String> {
+    config
+        .as_object()
+        .into_iter()
+        .flat_map(|x| x.values())
+        .find_map(|x| {
+            let delimiters = x.get("delimiters")?.as_array()?;
+            Some(
+                delimiters
+                    .iter()
+                    .filter_map(|x| x.as_str())
+                    .map(String::from)
+                    .collect(),
+            )
+        })
+        .unwrap_or_else(cg3_util::default_sentence_breakers)
+}
+
+/// Byte ranges of the sentences in `text`, split at suggest's default
+/// delimiters. See [`split_sentences_at`].
+pub fn split_sentences(text: &str) -> Vec<Range<usize>> {
+    split_sentences_at(text, &cg3_util::default_sentence_breakers())
+}
+
+/// Byte ranges of the sentences in `text`, without surrounding whitespace. A
+/// sentence ends at a line break, or after one of `delimiters` (and any
+/// closing quotes or brackets) followed by whitespace and something other
+/// than a lowercase letter, so most abbreviations don't end one.
+pub fn split_sentences_at(text: &str, delimiters: &HashSet<String>) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = None;
+    let mut end = 0;
+    let mut after_stop = false;
+
+    for (i, c) in text.char_indices() {
+        if c.is_whitespace() {
+            let ends = c == '\n'
+                || (after_stop
+                    && !text[i..]
+                        .trim_start()
+                        .chars()
+                        .next()
+                        .is_some_and(char::is_lowercase));
+            if ends {
+                if let Some(start) = start.take() {
+                    sentences.push(start..end);
+                }
+                after_stop = false;
+            }
+            continue;
+        }
+
+        start.get_or_insert(i);
+        end = i + c.len_utf8();
+        after_stop = match c {
+            '"' | '\'' | '»' | '”' | '’' | ')' | ']' => after_stop,
+            _ => delimiters.contains(&text[i..end]),
+        };
+    }
+    if let Some(start) = start {
+        sentences.push(start..end);
+    }
+
+    sentences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(form: &str, start: usize) -> GrammarErr {
+        serde_json::from_value(serde_json::json!({
+            "form": form,
+            "start": start,
+            "end": start + form.len(),
+            "error_id": "typo",
+            "title": "",
+            "description": "",
+            "suggestions": [],
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn splits_sentences_and_lines() {
+        let text = "Mun boahtán. Don \"bohtet?\" Son\nlea  dáppe…\n\nLoahppa";
+        let sentences = split_sentences(text)
+            .into_iter()
+            .map(|x| &text[x])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sentences,
+            [
+                "Mun boahtán.",
+                "Don \"bohtet?\"",
+                "Son",
+                "lea  dáppe…",
+                "Loahppa"
+            ]
+        );
+        assert!(split_sentences(" \n ").is_empty());
+        assert_eq!(
+            split_sentences("Dat lea d.d. buorre. 2. oassi"),
+            [0..20, 21..29]
+        );
+    }
+
+    #[test]
+    fn splits_sentences_at_configured_delimiters() {
+        let config = serde_json::json!({ "suggest": { "delimiters": ["…", "."] } });
+        let delimiters = sentence_delimiters(&config);
+        let text = "Mun boahtán… Don bohtet? Son lea dáppe. Loahppa";
+        let sentences = split_sentences_at(text, &delimiters)
+            .into_iter()
+            .map(|x| &text[x])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sentences,
+            ["Mun boahtán…", "Don bohtet? Son lea dáppe.", "Loahppa"]
+        );
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let hash = |x: &str| blake3::hash(x.as_bytes());
+        let mut cache = SentenceCache::new(2);
+        cache.insert(hash("a"), Arc::new(vec![]));
+        cache.insert(hash("b"), Arc::new(vec![]));
+        // A hit keeps "a" over "b"
+        assert!(cache.get(&hash("a")).is_some());
+        cache.insert(hash("c"), Arc::new(vec![]));
+        assert!(cache.get(&hash("b")).is_none());
+        assert!(cache.get(&hash("a")).is_some());
+        assert!(cache.get(&hash("c")).is_some());
+
+        cache.set_capacity(1);
+        assert_eq!(cache.len(), 1);
+        assert!(cache.get(&hash("c")).is_some());
+    }
+
+    #[test]
+    fn nocheck_ranges_follow_edits() {
+        // "Mun `koda` lea" with the code at 4..10
+        let nocheck = 4..10;
+        // Typing before it moves it, after it leaves it
+        assert_eq!(moved_by_edit(&nocheck, &0..0, 3), Some(7..13));
+        assert_eq!(moved_by_edit(&nocheck, &10..10, 2), Some(4..10));
+        assert_eq!(moved_by_edit(&nocheck, &4..4, 2), Some(6..12));
+        // Typing inside it widens it, deleting across an end cuts it
+        assert_eq!(moved_by_edit(&nocheck, &6..6, 5), Some(4..15));
+        assert_eq!(moved_by_edit(&nocheck, &2..6, 0), Some(2..6));
+        assert_eq!(moved_by_edit(&nocheck, &8..12, 1), Some(4..9));
+        assert_eq!(moved_by_edit(&nocheck, &0..14, 0), None);
+    }
+
+    #[test]
+    fn shifts_errors_into_the_document() {
+        let mut err = error("boahtan", 4);
+        err.relations = Some(vec![
+            serde_json::from_value(serde_json::json!({
+                "name": "LEFT", "form": "Mun", "start": 0, "end": 3, "coerror": false,
+            }))
+            .unwrap(),
+        ]);
+        let err = shifted(&err, 20);
+        assert_eq!((err.start, err.end), (24, 31));
+        let relation = &err.relations.unwrap()[0];
+        assert_eq!((relation.start, relation.end), (20, 23));
+    }
+}
@@ -27,20 +27,28 @@ pub fn generate<P: AsRef<Path>>(output_path: P) -> std::io::Result<()> {
     let output_path = output_path.as_ref();
     std::fs::create_dir_all(output_path)?;
 
-    // Generate main index.ts file
-    std::fs::write(output_path.join("mod.ts"), INDEX_TS)?;
+    for (file_name, contents) in generate_files()? {
+        std::fs::write(output_path.join(file_name), contents)?;
+    }
 
-    for module in crate::modules::get_modules().iter() {
-        let ts_fn = output_path.join(module.name).with_extension("ts");
+    Ok(())
+}
 
-        std::fs::write(
-            &ts_fn,
+/// Render the TypeScript bindings without touching the filesystem, as
+/// `(file name, contents)` pairs in the order `generate` writes them.
+pub fn generate_files() -> std::io::Result<Vec<(String, String)>> {
+    // Main index.ts file first
+    let mut files = vec![("mod.ts".to_string(), INDEX_TS.to_string())];
+
+    for module in crate::modules::get_modules().iter() {
+        files.push((
+            format!("{}.ts", module.name),
             generate_ts(&module)
                 .map_err(|_| std::io::Error::new(std::io::ErrorKind::Other, "format failed"))?,
-        )?;
+        ));
     }
 
-    Ok(())
+    Ok(files)
 }
 
 fn generate_ts(module: &Module) -> Result<String, std::fmt::Error> {
@@ -144,6 +152,13 @@ fn generate_ts(module: &Module) -> Result<String, std::fmt::Error> {
                     arg.ty.as_ts_type()
                 )?;
             }
+            if let Some(config) = command.config {
+                writeln!(
+                    &mut s,
+                    "    /** Runtime config used when the caller doesn't override it. */"
+                )?;
+                writeln!(&mut s, "    defaults?: Partial<{}>;", config)?;
+            }
             writeln!(&mut s, "}}\n")?;
         }
 
@@ -264,6 +279,9 @@ fn generate_ts(module: &Module) -> Result<String, std::fmt::Error> {
         if let Some(kind) = command.kind {
             writeln!(&mut s, "        kind: \"{}\",", kind)?;
         }
+        if command.config.is_some() && !command.args.is_empty() {
+            writeln!(&mut s, "        defaults: options.defaults,")?;
+        }
         if !command.args.is_empty() {
             writeln!(&mut s, "        args: {{")?;
             for arg in command.args {
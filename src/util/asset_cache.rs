@@ -0,0 +1,91 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Values built from assets (parsed resources, loaders), keyed by type and a
+/// caller-chosen key such as the asset's hash. Lives on the [`Context`], so
+/// every command in the pipes of one bundle shares it.
+///
+/// [`Context`]: crate::modules::Context
+#[derive(Default)]
+pub(crate) struct AssetCache {
+    entries: Mutex<HashMap<(TypeId, String), Arc<dyn Any + Send + Sync>>>,
+}
+
+impl AssetCache {
+    pub(crate) fn get<T: Any + Send + Sync>(&self, key: &str) -> Option<Arc<T>> {
+        let entries = self.entries.lock().unwrap();
+        entries
+            .get(&(TypeId::of::<T>(), key.to_string()))
+            .cloned()
+            .and_then(|x| x.downcast::<T>().ok())
+    }
+
+    pub(crate) fn insert<T: Any + Send + Sync>(&self, key: &str, value: T) -> Arc<T> {
+        let value = Arc::new(value);
+        self.entries
+            .lock()
+            .unwrap()
+            .insert((TypeId::of::<T>(), key.to_string()), value.clone());
+        value
+    }
+
+    /// Return the cached value for `key`, or build and cache it. `init` runs
+    /// without the lock held, so two callers racing on a cold key may both
+    /// build it; the last one wins.
+    pub(crate) fn get_or_try_insert<T, E>(
+        &self,
+        key: &str,
+        init: impl FnOnce() -> Result<T, E>,
+    ) -> Result<Arc<T>, E>
+    where
+        T: Any + Send + Sync,
+    {
+        if let Some(value) = self.get(key) {
+            return Ok(value);
+        }
+        Ok(self.insert(key, init()?))
+    }
+
+    /// Drop every entry. Commands holding a value keep it alive until they
+    /// are dropped themselves.
+    pub(crate) fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_are_keyed_by_type_and_key() {
+        let cache = AssetCache::default();
+        let mut calls = 0;
+        let a = cache
+            .get_or_try_insert::<_, ()>("a", || {
+                calls += 1;
+                Ok(1u32)
+            })
+            .unwrap();
+        let again = cache
+            .get_or_try_insert::<_, ()>("a", || {
+                calls += 1;
+                Ok(2u32)
+            })
+            .unwrap();
+        assert_eq!(calls, 1);
+        assert!(Arc::ptr_eq(&a, &again));
+
+        // Same key, different type.
+        assert!(cache.get::<String>("a").is_none());
+        assert!(
+            cache
+                .get_or_try_insert("b", || Err::<u32, _>("bad"))
+                .is_err()
+        );
+        assert!(cache.get::<u32>("b").is_none());
+    }
+}
@@ -0,0 +1,414 @@
+//! Breakpoints for taps, shared by the REPL and the playground.
+//!
+//! A breakpoint names a step and optionally a condition on the values that
+//! step emits, so a tap can stop only when the interesting cohort flows
+//! through rather than on every input:
+//!
+//! ```text
+//! suggest
+//! suggest contains "girjii"
+//! mwesplit matches "\"girji\" N .* Ill"
+//! suggest json /errors/0/error_id == "msyn-agr" and not contains "eai"
+//! ```
+//!
+//! A condition is made of these predicates, combined with `and`, `or`, `not`
+//! and parentheses (`and` binds tighter than `or`):
+//!
+//! - `contains "text"`: the value's text contains `text`
+//! - `matches "regex"`: the value's text matches the regular expression
+//! - `json /pointer == value`, `!= value`: the JSON at the pointer is (not)
+//!   `value`, which is any JSON literal
+//! - `json /pointer contains "text"`: the JSON at the pointer is a string
+//!   containing `text`, or an array with that string in it
+//!
+//! The text of a JSON value is its compact serialization, and JSON
+//! predicates on a text value parse it first. Binary and audio values only
+//! hit a breakpoint without a condition.
+
+use std::{fmt, str::FromStr};
+
+use regex::Regex;
+
+use crate::modules::{Error, PipelineEvent, PipelineValue};
+
+#[derive(Debug, Clone)]
+pub struct Breakpoint {
+    source: String,
+    step: String,
+    condition: Option<Condition>,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Contains(String),
+    Matches(Regex),
+    Json {
+        pointer: String,
+        op: JsonOp,
+        value: serde_json::Value,
+    },
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JsonOp {
+    Eq,
+    Ne,
+    Contains,
+}
+
+impl Breakpoint {
+    /// Parse `<step> [condition]`, as described in the module docs.
+    pub fn parse(source: &str) -> Result<Breakpoint, Error> {
+        let source = source.trim();
+        let mut tokens = tokenize(source)?.into_iter().peekable();
+        let Some(Token::Word(step)) = tokens.next() else {
+            return Err(Error::msg("breakpoint needs a step name"));
+        };
+
+        let condition = if tokens.peek().is_some() {
+            let mut parser = Parser { tokens };
+            let condition = parser.or()?;
+            if let Some(token) = parser.tokens.next() {
+                return Err(Error::msg(format!(
+                    "unexpected {} in breakpoint condition",
+                    token
+                )));
+            }
+            Some(condition)
+        } else {
+            None
+        };
+
+        Ok(Breakpoint {
+            source: source.to_string(),
+            step,
+            condition,
+        })
+    }
+
+    pub fn step(&self) -> &str {
+        &self.step
+    }
+
+    pub fn has_condition(&self) -> bool {
+        self.condition.is_some()
+    }
+
+    /// Whether a tap on step `key` seeing `event` should stop.
+    pub fn hits(&self, key: &str, event: &PipelineEvent) -> bool {
+        if key != self.step {
+            return false;
+        }
+        let PipelineEvent::Value(value) = event else {
+            return false;
+        };
+        match &self.condition {
+            None => true,
+            Some(condition) => condition.eval(&Subject::new(value)),
+        }
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Breakpoint::parse(s)
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+/// A value as the predicates see it, with the text and JSON views worked
+/// out once.
+struct Subject {
+    text: Option<String>,
+    json: Option<serde_json::Value>,
+}
+
+impl Subject {
+    fn new(value: &PipelineValue) -> Subject {
+        match value {
+            PipelineValue::String(text) => Subject {
+                json: serde_json::from_str(text).ok(),
+                text: Some(text.clone()),
+            },
+            PipelineValue::Json(json) => Subject {
+                text: Some(json.to_string()),
+                json: Some(json.clone()),
+            },
+            PipelineValue::Multiple(_) => Subject {
+                text: Some(value.to_string()),
+                json: None,
+            },
+            PipelineValue::Bytes(_) | PipelineValue::Audio(_) => Subject {
+                text: None,
+                json: None,
+            },
+        }
+    }
+}
+
+impl Condition {
+    fn eval(&self, subject: &Subject) -> bool {
+        match self {
+            Condition::Contains(needle) => subject
+                .text
+                .as_deref()
+                .is_some_and(|x| x.contains(needle.as_str())),
+            Condition::Matches(re) => subject.text.as_deref().is_some_and(|x| re.is_match(x)),
+            Condition::Json { pointer, op, value } => {
+                let found = subject.json.as_ref().and_then(|x| x.pointer(pointer));
+                match op {
+                    JsonOp::Eq => found == Some(value),
+                    JsonOp::Ne => found != Some(value),
+                    JsonOp::Contains => match (found, value) {
+                        (Some(serde_json::Value::String(x)), serde_json::Value::String(y)) => {
+                            x.contains(y.as_str())
+                        }
+                        (Some(serde_json::Value::Array(xs)), _) => xs.contains(value),
+                        _ => false,
+                    },
+                }
+            }
+            Condition::Not(x) => !x.eval(subject),
+            Condition::And(a, b) => a.eval(subject) && b.eval(subject),
+            Condition::Or(a, b) => a.eval(subject) || b.eval(subject),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(x) => write!(f, "'{}'", x),
+            Token::Quoted(x) => write!(f, "{:?}", x),
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some(c @ ('"' | '\\')) => text.push(c),
+                            // Left alone, so regexes keep their \b, \d and so on
+                            Some(c) => {
+                                text.push('\\');
+                                text.push(c);
+                            }
+                            None => return Err(Error::msg("unterminated string in breakpoint")),
+                        },
+                        Some(c) => text.push(c),
+                        None => return Err(Error::msg("unterminated string in breakpoint")),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser<I: Iterator<Item = Token>> {
+    tokens: std::iter::Peekable<I>,
+}
+
+impl<I: Iterator<Item = Token>> Parser<I> {
+    fn or(&mut self) -> Result<Condition, Error> {
+        let mut condition = self.and()?;
+        while self.keyword("or") {
+            condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+        }
+        Ok(condition)
+    }
+
+    fn and(&mut self) -> Result<Condition, Error> {
+        let mut condition = self.unary()?;
+        while self.keyword("and") {
+            condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+        }
+        Ok(condition)
+    }
+
+    fn unary(&mut self) -> Result<Condition, Error> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.unary()?)));
+        }
+        if self.tokens.peek() == Some(&Token::Open) {
+            self.tokens.next();
+            let condition = self.or()?;
+            return match self.tokens.next() {
+                Some(Token::Close) => Ok(condition),
+                _ => Err(Error::msg("missing ')' in breakpoint condition")),
+            };
+        }
+        self.predicate()
+    }
+
+    fn predicate(&mut self) -> Result<Condition, Error> {
+        match self.tokens.next() {
+            Some(Token::Word(x)) if x == "contains" => Ok(Condition::Contains(self.string()?)),
+            Some(Token::Word(x)) if x == "matches" => {
+                let pattern = self.string()?;
+                Regex::new(&pattern)
+                    .map(Condition::Matches)
+                    .map_err(|e| Error::msg(format!("invalid regex in breakpoint: {}", e)))
+            }
+            Some(Token::Word(x)) if x == "json" => {
+                let pointer = match self.tokens.next() {
+                    Some(Token::Word(x)) if x.is_empty() || x.starts_with('/') => x,
+                    _ => return Err(Error::msg("'json' needs a JSON pointer like /errors/0")),
+                };
+                let op = match self.tokens.next() {
+                    Some(Token::Word(x)) if x == "==" => JsonOp::Eq,
+                    Some(Token::Word(x)) if x == "!=" => JsonOp::Ne,
+                    Some(Token::Word(x)) if x == "contains" => JsonOp::Contains,
+                    _ => {
+                        return Err(Error::msg(format!(
+                            "'json {}' needs ==, != or contains",
+                            pointer
+                        )));
+                    }
+                };
+                let value = match self.tokens.next() {
+                    Some(Token::Quoted(x)) => serde_json::Value::String(x),
+                    Some(Token::Word(x)) => serde_json::from_str(&x).map_err(|_| {
+                        Error::msg(format!("'{}' is not a JSON value; quote strings", x))
+                    })?,
+                    _ => return Err(Error::msg(format!("'json {}' needs a value", pointer))),
+                };
+                Ok(Condition::Json { pointer, op, value })
+            }
+            Some(token) => Err(Error::msg(format!(
+                "expected contains, matches or json in breakpoint, found {}",
+                token
+            ))),
+            None => Err(Error::msg("breakpoint condition ends too early")),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, Error> {
+        match self.tokens.next() {
+            Some(Token::Quoted(x)) => Ok(x),
+            _ => Err(Error::msg("expected a quoted string in breakpoint")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(self.tokens.peek(), Some(Token::Word(x)) if x == keyword);
+        if found {
+            self.tokens.next();
+        }
+        found
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(x: &str) -> PipelineEvent {
+        PipelineEvent::Value(PipelineValue::String(x.to_string()))
+    }
+
+    #[test]
+    fn breaks_on_matching_values_of_the_step() {
+        let bp = Breakpoint::parse(r#"mwe contains "girjii" and not matches "\bSg\b""#).unwrap();
+        assert_eq!(bp.step(), "mwe");
+        assert!(bp.hits("mwe", &text("\"<girjii>\"\n\t\"girji\" N Pl Nom")));
+        assert!(!bp.hits("mwe", &text("\"<girjii>\"\n\t\"girji\" N Sg Ill")));
+        assert!(!bp.hits("mwe", &text("\"<girji>\"")));
+        assert!(!bp.hits("cg", &text("\"<girjii>\"")));
+        assert!(!bp.hits("mwe", &PipelineEvent::Finish));
+
+        let bp = Breakpoint::parse("tok").unwrap();
+        assert!(!bp.has_condition());
+        assert!(bp.hits("tok", &text("")));
+    }
+
+    #[test]
+    fn compares_json() {
+        let event = PipelineEvent::Value(PipelineValue::Json(serde_json::json!({
+            "errors": [{ "error_id": "typo", "start": 4, "suggestions": ["girjii"] }],
+        })));
+        let hits = |x: &str| Breakpoint::parse(x).unwrap().hits("suggest", &event);
+
+        assert!(hits(r#"suggest json /errors/0/error_id == "typo""#));
+        assert!(hits("suggest json /errors/0/start == 4"));
+        assert!(hits(
+            r#"suggest json /errors/0/suggestions contains "girjii""#
+        ));
+        assert!(hits(r#"suggest json /errors/1 != null or contains "typo""#));
+        assert!(!hits(
+            r#"suggest (json /errors/0/start != 4 or contains "x") and contains "typo""#
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_conditions() {
+        for source in [
+            "",
+            r#"suggest contains girjii"#,
+            r#"suggest contains "girjii"#,
+            r#"suggest matches "(""#,
+            r#"suggest json errors == 1"#,
+            r#"suggest json /errors == typo"#,
+            r#"suggest (contains "a""#,
+            r#"suggest contains "a" "b""#,
+        ] {
+            assert!(Breakpoint::parse(source).is_err(), "{}", source);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Sizing and lag reporting for the broadcast channels linking commands.
+//!
+//! A receiver that falls more than the channel capacity behind loses the
+//! oldest events. That used to surface as a generic receive error (or not at
+//! all), so lag is now reported with the command it happened in, and the
+//! capacity for the bundle's new pipelines can optionally grow when it
+//! happens.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::modules::{Error, PipelineEvent, PipelineValueTx};
+
+pub const DEFAULT_CAPACITY: usize = 16;
+
+/// Upper bound for automatic growth.
+pub const MAX_AUTO_CAPACITY: usize = 4096;
+
+/// The channel capacity of a bundle's pipelines, set with
+/// [`BundleOptions`](crate::bundle::BundleOptions). Shared by every pipeline
+/// of the bundle, so when it grows, pipelines created afterwards get the
+/// larger channels.
+#[derive(Debug)]
+pub struct Channels {
+    capacity: AtomicUsize,
+    /// Double the capacity (up to [`MAX_AUTO_CAPACITY`]) whenever a command
+    /// falls behind.
+    auto_resize: bool,
+}
+
+impl Default for Channels {
+    fn default() -> Self {
+        Channels::new(DEFAULT_CAPACITY, false)
+    }
+}
+
+impl Channels {
+    pub fn new(capacity: usize, auto_resize: bool) -> Self {
+        Channels {
+            capacity: AtomicUsize::new(capacity.max(1)),
+            auto_resize,
+        }
+    }
+
+    /// Capacity used for channels created by new pipeline streams.
+    pub fn capacity(&self) -> usize {
+        self.capacity.load(Ordering::Relaxed)
+    }
+
+    /// Log `err` and, if it is a command falling behind, say what the
+    /// capacity was and grow it when auto-resizing. Other errors are
+    /// returned as they are.
+    pub(crate) fn explain(&self, err: Error) -> Error {
+        let Some(lagged) = err.downcast_ref::<Lagged>() else {
+            return err;
+        };
+        let current = self.capacity();
+        let mut msg = format!("{} (channel capacity {})", lagged, current);
+
+        if self.auto_resize && current < MAX_AUTO_CAPACITY {
+            let grown = grow(current, lagged.skipped);
+            // Another lagging command may have grown it already; keep the larger.
+            self.capacity.fetch_max(grown, Ordering::Relaxed);
+            msg.push_str(&format!(
+                "; capacity raised to {} for new pipelines",
+                self.capacity()
+            ));
+        } else {
+            msg.push_str("; use --channel-capacity to raise it");
+        }
+
+        tracing::error!("{}", msg);
+        Error::msg(msg)
+    }
+}
+
+/// A command fell further behind its input than the channel holds.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error("{command} fell behind its input and {skipped} event(s) were dropped")]
+pub(crate) struct Lagged {
+    command: String,
+    skipped: u64,
+}
+
+/// The error for `command` lagging `skipped` events behind its input, to pass
+/// down the pipeline. The pipeline's [`Channels`] explain it at the output.
+pub(crate) fn report_lag(command: &str, skipped: u64) -> Error {
+    Error::wrap(Lagged {
+        command: command.to_string(),
+        skipped,
+    })
+}
+
+/// Map a receive error in `command`'s stream task. Lag is reported and passed
+/// downstream as an error event so the consumer sees why output went missing.
+pub(crate) fn recv_error(command: &str, output: &PipelineValueTx, err: RecvError) -> Error {
+    match err {
+        RecvError::Lagged(skipped) => {
+            let err = report_lag(command, skipped);
+            let _ = output.send(PipelineEvent::Error(err.clone()));
+            err
+        }
+        RecvError::Closed => Error::wrap(err),
+    }
+}
+
+fn grow(current: usize, skipped: u64) -> usize {
+    let needed = current.saturating_add(usize::try_from(skipped).unwrap_or(usize::MAX));
+    needed
+        .min(MAX_AUTO_CAPACITY)
+        .next_power_of_two()
+        .max(current.saturating_mul(2))
+        .min(MAX_AUTO_CAPACITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grow_at_least_doubles_and_covers_the_gap() {
+        assert_eq!(grow(16, 1), 32);
+        assert_eq!(grow(16, 40), 64);
+        assert_eq!(grow(2048, 10_000), MAX_AUTO_CAPACITY);
+    }
+
+    #[test]
+    fn report_lag_names_the_command() {
+        let err = Channels::default().explain(report_lag("divvun::suggest", 3));
+        let msg = err.to_string();
+        assert!(msg.contains("divvun::suggest"));
+        assert!(msg.contains("3 event(s) were dropped"));
+        assert!(msg.contains("channel capacity 16"));
+    }
+
+    #[test]
+    fn lag_grows_only_its_own_bundles_channels() {
+        let growing = Channels::new(16, true);
+        let fixed = Channels::new(16, false);
+        growing.explain(report_lag("divvun::suggest", 3));
+        fixed.explain(report_lag("divvun::suggest", 3));
+        assert_eq!(growing.capacity(), 32);
+        assert_eq!(fixed.capacity(), 16);
+
+        let other = Error::msg("not a lag");
+        assert_eq!(growing.explain(other).to_string(), "not a lag");
+        assert_eq!(growing.capacity(), 32);
+    }
+}
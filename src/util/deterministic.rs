@@ -0,0 +1,105 @@
+//! Deterministic mode, for golden tests and bug reports that have to be
+//! reproducible byte-for-byte across machines.
+//!
+//! When enabled, outputs that would otherwise follow `HashMap` iteration
+//! order are sorted and rayon work runs on a single thread. Nothing in the
+//! runtime samples randomly today, `speech::tts` included; anything that does
+//! must take its seed from here when this mode is on. Running every stage on
+//! one thread is up to the embedder (the CLI uses a current-thread tokio
+//! runtime). [`Bundle::self_test`](crate::bundle::Bundle::self_test) runs each
+//! case twice in this mode and fails the ones whose output changed.
+
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Placeholder written in place of the temporary directory by
+/// [`normalize_paths`].
+pub const TEMP_DIR_PLACEHOLDER: &str = "$TMPDIR";
+
+/// Turn deterministic mode on for the rest of the process. Call it before
+/// loading bundles, so the rayon pool is still unconfigured.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    if rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build_global()
+        .is_err()
+    {
+        tracing::warn!("rayon thread pool already started; parallel work may still vary in timing");
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sort the keys of every object in `value`, so JSON built from hash maps
+/// prints the same way every time.
+pub fn canonicalize_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            let mut entries = std::mem::take(map).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (key, mut value) in entries {
+                canonicalize_json(&mut value);
+                map.insert(key, value);
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(canonicalize_json),
+        _ => {}
+    }
+}
+
+/// Replace the system temporary directory in `text` with
+/// [`TEMP_DIR_PLACEHOLDER`].
+pub fn normalize_paths(text: &str) -> String {
+    normalize_temp_dir(text, &std::env::temp_dir())
+}
+
+fn normalize_temp_dir(text: &str, temp_dir: &Path) -> String {
+    let mut out = text.to_string();
+    // macOS hands out /var/... paths that canonicalize to /private/var/...;
+    // replace the longer spelling first.
+    let mut spellings = vec![temp_dir.to_path_buf()];
+    if let Ok(canonical) = temp_dir.canonicalize() {
+        spellings.push(canonical);
+    }
+    spellings.sort_by_key(|x| std::cmp::Reverse(x.as_os_str().len()));
+
+    for spelling in spellings {
+        let spelling = spelling.to_string_lossy();
+        let spelling = spelling.trim_end_matches(std::path::MAIN_SEPARATOR);
+        if !spelling.is_empty() {
+            out = out.replace(spelling, TEMP_DIR_PLACEHOLDER);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_json_sorts_nested_keys() {
+        let mut value = serde_json::json!({ "b": 1, "a": { "z": [ { "y": 1, "x": 2 } ], "c": 3 } });
+        canonicalize_json(&mut value);
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"a":{"c":3,"z":[{"x":2,"y":1}]},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn temp_paths_are_replaced() {
+        let temp = Path::new("/tmp/build");
+        assert_eq!(
+            normalize_temp_dir("failed to open /tmp/build/.tmpX1/model.hfstol", temp),
+            "failed to open $TMPDIR/.tmpX1/model.hfstol"
+        );
+    }
+}
@@ -0,0 +1,336 @@
+//! Assets a bundle downloads the first time they are loaded instead of
+//! carrying them, such as neural speech models too large to ship in every
+//! .drb of a language.
+//!
+//! A bundle lists them in the `remote_assets` of its `pipeline.json` with a
+//! URL and BLAKE3 hash, and pipeline args name them by path like any other
+//! asset. Downloads are checked against the hash and kept in a cache shared
+//! by all bundles, under the hash, so bundles with the same model share one
+//! copy and a changed model is never mistaken for the old one.
+//!
+//! [`Fetcher`] does the downloading, for these and for the assets of bundles
+//! opened over HTTP.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use indexmap::IndexMap;
+use pathos::AppDirs;
+use tokio::sync::Mutex;
+
+use crate::{ast::RemoteAsset, modules::Error};
+
+/// How far a download has come, passed to a [`DownloadCallback`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize)]
+pub struct DownloadProgress {
+    /// The asset's path in the bundle, e.g. `voice.onnx`.
+    pub path: String,
+    pub url: String,
+    /// Bytes received so far.
+    pub downloaded: u64,
+    /// Size of the download, if the server or the bundle says.
+    pub total: Option<u64>,
+    /// The download is complete and matched its hash.
+    pub done: bool,
+}
+
+/// Called as remote assets download, e.g. to show progress while a bundle
+/// loads for the first time.
+#[derive(Clone)]
+pub struct DownloadCallback(Arc<dyn Fn(&DownloadProgress) + Send + Sync>);
+
+impl DownloadCallback {
+    pub fn new(f: impl Fn(&DownloadProgress) + Send + Sync + 'static) -> Self {
+        DownloadCallback(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for DownloadCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DownloadCallback")
+    }
+}
+
+/// Where remote assets are cached unless
+/// [`BundleOptions::download_dir`](crate::bundle::BundleOptions) says
+/// otherwise: `assets` in the user's cache directory for Divvun Runtime.
+pub fn default_download_dir() -> Option<PathBuf> {
+    let dirs = pathos::user::AppDirs::new("Divvun Runtime").ok()?;
+    Some(dirs.cache_dir().join("assets"))
+}
+
+/// Where `target` is written until its download is complete and checked, so
+/// an interrupted or corrupt download never looks cached.
+pub(crate) fn partial_path(target: &Path) -> PathBuf {
+    let mut partial = target.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Bytes received between progress reports.
+#[cfg(feature = "remote")]
+const REPORT_EVERY: u64 = 1 << 20;
+
+/// A file to download and the BLAKE3 hash it must have.
+pub(crate) struct Source<'a> {
+    /// The file's path in the bundle, for progress and errors.
+    pub path: &'a str,
+    pub url: &'a str,
+    pub hash: &'a str,
+    /// Size of the file, if known before the server says.
+    pub size: Option<u64>,
+}
+
+/// Downloads files into a cache, streaming each to a partial file and
+/// checking its hash before it takes the file's place.
+#[derive(Default)]
+pub(crate) struct Fetcher {
+    #[cfg_attr(not(feature = "remote"), allow(dead_code))]
+    callback: Option<DownloadCallback>,
+    /// A lock per target, held while it downloads, so concurrent loads of
+    /// one file fetch it once while other files download alongside.
+    locks: std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>,
+    #[cfg(feature = "remote")]
+    client: reqwest::Client,
+}
+
+impl Fetcher {
+    pub(crate) fn new(callback: Option<DownloadCallback>) -> Self {
+        Fetcher {
+            callback,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    pub(crate) fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Download `source` to `target` unless it is already there.
+    pub(crate) async fn fetch(&self, source: &Source<'_>, target: &Path) -> Result<(), Error> {
+        let lock = self
+            .locks
+            .lock()
+            .unwrap()
+            .entry(target.to_path_buf())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+        if target.exists() {
+            return Ok(());
+        }
+        self.download(source, target).await
+    }
+
+    #[cfg(feature = "remote")]
+    fn report(&self, progress: &DownloadProgress) {
+        if let Some(callback) = &self.callback {
+            (callback.0)(progress);
+        }
+    }
+
+    #[cfg(feature = "remote")]
+    async fn download(&self, source: &Source<'_>, target: &Path) -> Result<(), Error> {
+        use tokio::io::AsyncWriteExt;
+
+        tracing::info!("Downloading {} from {}", source.path, source.url);
+        let mut response = self
+            .client
+            .get(source.url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| Error::wrap(e).at_file(source.url))?;
+        let mut progress = DownloadProgress {
+            path: source.path.to_string(),
+            url: source.url.to_string(),
+            downloaded: 0,
+            total: response.content_length().or(source.size),
+            done: false,
+        };
+        self.report(&progress);
+
+        let display = target.display().to_string();
+        if let Some(parent) = target.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| Error::wrap(e).at_file(&display))?;
+        }
+        let partial = partial_path(target);
+        let mut file = tokio::fs::File::create(&partial)
+            .await
+            .map_err(|e| Error::wrap(e).at_file(&display))?;
+
+        let mut hasher = blake3::Hasher::new();
+        let mut reported = 0;
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(|e| Error::wrap(e).at_file(source.url))?
+        {
+            hasher.update(&chunk);
+            file.write_all(&chunk)
+                .await
+                .map_err(|e| Error::wrap(e).at_file(&display))?;
+            progress.downloaded += chunk.len() as u64;
+            if progress.downloaded - reported >= REPORT_EVERY {
+                reported = progress.downloaded;
+                self.report(&progress);
+            }
+        }
+        file.flush()
+            .await
+            .map_err(|e| Error::wrap(e).at_file(&display))?;
+        drop(file);
+
+        let actual = hasher.finalize().to_hex().to_string();
+        if !actual.eq_ignore_ascii_case(source.hash) {
+            let _ = tokio::fs::remove_file(&partial).await;
+            return Err(Error::msg(format!(
+                "download of {} failed checksum (expected {}, got {})",
+                source.url, source.hash, actual
+            ))
+            .at_file(source.path));
+        }
+        tokio::fs::rename(&partial, target)
+            .await
+            .map_err(|e| Error::wrap(e).at_file(&display))?;
+
+        progress.done = true;
+        self.report(&progress);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "remote"))]
+    async fn download(&self, source: &Source<'_>, target: &Path) -> Result<(), Error> {
+        Err(Error::msg(format!(
+            "{} is downloaded from {}, but this build of divvun-runtime can't download; build it with the `remote` feature or put the file at {}",
+            source.path,
+            source.url,
+            target.display()
+        ))
+        .at_file(source.path))
+    }
+}
+
+/// The remote assets of a loaded bundle.
+#[derive(Default)]
+pub(crate) struct Downloads {
+    assets: IndexMap<String, RemoteAsset>,
+    dir: Option<PathBuf>,
+    fetcher: Fetcher,
+}
+
+impl Downloads {
+    pub(crate) fn new(
+        assets: IndexMap<String, RemoteAsset>,
+        dir: Option<PathBuf>,
+        callback: Option<DownloadCallback>,
+    ) -> Self {
+        Downloads {
+            assets,
+            dir: dir.or_else(default_download_dir),
+            fetcher: Fetcher::new(callback),
+        }
+    }
+
+    pub(crate) fn contains(&self, path: &str) -> bool {
+        self.assets.contains_key(path)
+    }
+
+    /// Where `path` is cached, if it is a remote asset. The file is only
+    /// there once [`fetch`](Self::fetch) has been called.
+    pub(crate) fn path(&self, path: &str) -> Result<Option<PathBuf>, Error> {
+        let Some(asset) = self.assets.get(path) else {
+            return Ok(None);
+        };
+        if asset.hash.is_empty() || !asset.hash.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(
+                Error::msg(format!("Remote asset has an invalid hash {:?}", asset.hash))
+                    .at_file(path),
+            );
+        }
+        let dir = self
+            .dir
+            .as_ref()
+            .ok_or_else(|| Error::msg("No directory to download remote assets to").at_file(path))?;
+        let name = Path::new(path)
+            .file_name()
+            .ok_or_else(|| Error::msg("Invalid remote asset path").at_file(path))?;
+        Ok(Some(dir.join(asset.hash.to_ascii_lowercase()).join(name)))
+    }
+
+    /// Download `path` unless it is cached or isn't a remote asset.
+    pub(crate) async fn fetch(&self, path: &str) -> Result<(), Error> {
+        let Some(target) = self.path(path)? else {
+            return Ok(());
+        };
+        let asset = &self.assets[path];
+        let source = Source {
+            path,
+            url: &asset.url,
+            hash: &asset.hash,
+            size: asset.size,
+        };
+        self.fetcher.fetch(&source, &target).await
+    }
+
+    /// Remove assets whose download was cut short, returning how many.
+    pub(crate) fn remove_partial(&self) -> usize {
+        self.assets
+            .keys()
+            .filter_map(|path| self.path(path).ok().flatten())
+            .filter(|target| std::fs::remove_file(partial_path(target)).is_ok())
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn downloads(dir: &Path, hash: &str) -> Downloads {
+        let asset = RemoteAsset {
+            url: "https://example.org/models/voice.onnx".to_string(),
+            hash: hash.to_string(),
+            size: None,
+        };
+        Downloads::new(
+            [("voices/voice.onnx".to_string(), asset)]
+                .into_iter()
+                .collect(),
+            Some(dir.to_path_buf()),
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn cached_assets_are_found_by_hash() {
+        let temp = tempfile::tempdir().unwrap();
+        let hash = blake3::hash(b"model").to_hex().to_string();
+        let cached = temp.path().join(&hash).join("voice.onnx");
+        std::fs::create_dir_all(cached.parent().unwrap()).unwrap();
+        std::fs::write(&cached, b"model").unwrap();
+
+        let downloads = downloads(temp.path(), &hash.to_ascii_uppercase());
+        assert!(downloads.contains("voices/voice.onnx"));
+        assert_eq!(downloads.path("voices/voice.onnx").unwrap(), Some(cached));
+        assert_eq!(downloads.path("errors.json").unwrap(), None);
+        // Already cached, so nothing is downloaded
+        downloads.fetch("voices/voice.onnx").await.unwrap();
+        downloads.fetch("errors.json").await.unwrap();
+
+        // A download cut short, e.g. by shutting down
+        std::fs::write(partial_path(&cached), b"mo").unwrap();
+        assert_eq!(downloads.remove_partial(), 1);
+        assert!(!partial_path(&cached).exists() && cached.exists());
+
+        let escaping = downloads(temp.path(), "../../etc");
+        assert!(escaping.path("voices/voice.onnx").is_err());
+    }
+}
@@ -7,22 +7,37 @@ use fluent::FluentResource;
 use fluent_bundle::{FluentArgs, concurrent::FluentBundle};
 use unic_langid::LanguageIdentifier;
 
+use super::integrity::Checksums;
 use crate::modules::{Context, Error};
 
 #[derive(Clone)]
 pub struct FluentLoader {
-    bundles: HashMap<String, Arc<FluentBundle<FluentResource>>>,
+    bundles: HashMap<String, Arc<FluentBundle<Arc<FluentResource>>>>,
     default_locale: String,
 }
 
 impl FluentLoader {
+    /// Load the FTL files matching `pattern`. The loader is cached on the
+    /// context, so every command asking for the same files shares one set of
+    /// bundles, and parsed resources are cached by content hash.
     pub async fn new(
         context: Arc<Context>,
         pattern: &str,
         default_locale: &str,
     ) -> Result<Self, Error> {
-        let mut bundles = HashMap::new();
+        let key = format!("{}\0{}", pattern, default_locale);
+        if let Some(loader) = context.cache.get::<FluentLoader>(&key) {
+            tracing::debug!("Reusing Fluent bundles for {}", pattern);
+            return Ok((*loader).clone());
+        }
+
+        let loader = Self::load(&context, pattern, default_locale).await?;
+        Ok((*context.cache.insert(&key, loader)).clone())
+    }
+
+    async fn load(context: &Context, pattern: &str, default_locale: &str) -> Result<Self, Error> {
         let files = context.load_files_glob(pattern).await?;
+        let mut resources = Vec::new();
 
         for (path, contents) in files {
             let filename = path
@@ -32,75 +47,74 @@ impl FluentLoader {
 
             // Extract language code from filename like "errors-en.ftl" -> "en"
             if let Some(lang_code) = extract_language_code(filename) {
-                let content = String::from_utf8(contents)
-                    .map_err(|e| Error::msg(format!("Failed to read file {}: {}", filename, e)))?;
-
-                // Parse the Fluent resource. On parse errors we keep the
-                // partially-parsed resource so a single bad message doesn't drop
-                // the whole language file.
-                let resource = match FluentResource::try_new(content) {
-                    Ok(resource) => resource,
-                    Err((resource, errors)) => {
-                        tracing::warn!(
-                            "Fluent resource {} has {} parse error(s); the messages that parsed were still loaded.",
+                let hash = Checksums::hash(&contents);
+                let resource = context
+                    .cache
+                    .get_or_try_insert(&hash, || parse_resource(filename, contents))?;
+                resources.push((filename.to_string(), lang_code, resource));
+            }
+        }
+
+        if resources.is_empty() {
+            tracing::warn!("No valid Fluent resources loaded from pattern: {}", pattern);
+        }
+
+        Self::from_resources(resources, default_locale)
+    }
+
+    /// Build a loader from FTL files held in memory, e.g. compiled into a
+    /// binary, as `(filename, contents)` pairs. The language comes from the
+    /// filename as for files in a bundle (`cli-se.ftl` is `se`).
+    pub fn from_sources<'a>(
+        sources: impl IntoIterator<Item = (&'a str, &'a str)>,
+        default_locale: &str,
+    ) -> Result<Self, Error> {
+        let mut resources = Vec::new();
+        for (filename, contents) in sources {
+            let lang_code = extract_language_code(filename)
+                .ok_or_else(|| Error::msg(format!("No language code in file name {}", filename)))?;
+            let resource = parse_resource(filename, contents.as_bytes().to_vec())?;
+            resources.push((filename.to_string(), lang_code, Arc::new(resource)));
+        }
+        Self::from_resources(resources, default_locale)
+    }
+
+    fn from_resources(
+        resources: Vec<(String, String, Arc<FluentResource>)>,
+        default_locale: &str,
+    ) -> Result<Self, Error> {
+        let mut bundles = HashMap::new();
+
+        for (filename, lang_code, resource) in resources {
+            let lang_id: LanguageIdentifier = lang_code.parse().map_err(|e| {
+                Error::msg(format!("Invalid language identifier {}: {}", lang_code, e))
+            })?;
+
+            let mut bundle = FluentBundle::new_concurrent(vec![lang_id]);
+            // Don't wrap interpolated values in Unicode bidi isolates (U+2068/U+2069).
+            bundle.set_use_isolating(false);
+            match bundle.add_resource(resource) {
+                Ok(_) => {
+                    tracing::debug!("Successfully loaded Fluent resource: {}", filename);
+                }
+                Err(errors) => {
+                    // Check if errors are only "Overriding" errors (which are non-fatal)
+                    let non_fatal = errors
+                        .iter()
+                        .all(|e| matches!(e, fluent_bundle::FluentError::Overriding { .. }));
+                    if non_fatal {
+                        tracing::debug!(
+                            "Fluent resource {} has overriding messages (normal for localization): {:?}",
                             filename,
-                            errors.len()
+                            errors
                         );
-                        // Report each error with file:line:column and a snippet of
-                        // the offending line, instead of a raw byte offset (#32).
-                        let source = resource.source();
-                        for error in &errors {
-                            let (line, col) = line_col(source, error.pos.start);
-                            let snippet =
-                                source.lines().nth(line.saturating_sub(1)).unwrap_or("");
-                            tracing::warn!(
-                                "  {}:{}:{}: {}\n    {}\n    {}^",
-                                filename,
-                                line,
-                                col,
-                                error.kind,
-                                snippet,
-                                " ".repeat(col.saturating_sub(1))
-                            );
-                        }
-                        resource
-                    }
-                };
-
-                let lang_id: LanguageIdentifier = lang_code.parse().map_err(|e| {
-                    Error::msg(format!("Invalid language identifier {}: {}", lang_code, e))
-                })?;
-
-                let mut bundle = FluentBundle::new_concurrent(vec![lang_id]);
-                // Don't wrap interpolated values in Unicode bidi isolates (U+2068/U+2069).
-                bundle.set_use_isolating(false);
-                match bundle.add_resource(resource) {
-                    Ok(_) => {
-                        tracing::debug!("Successfully loaded Fluent resource: {}", filename);
-                    }
-                    Err(errors) => {
-                        // Check if errors are only "Overriding" errors (which are non-fatal)
-                        let non_fatal = errors
-                            .iter()
-                            .all(|e| matches!(e, fluent_bundle::FluentError::Overriding { .. }));
-                        if non_fatal {
-                            tracing::debug!(
-                                "Fluent resource {} has overriding messages (normal for localization): {:?}",
-                                filename,
-                                errors
-                            );
-                        } else {
-                            tracing::warn!("Fluent resource {} has errors: {:?}", filename, errors);
-                        }
+                    } else {
+                        tracing::warn!("Fluent resource {} has errors: {:?}", filename, errors);
                     }
                 }
-                // Add the bundle regardless of overriding errors
-                bundles.insert(lang_code, Arc::new(bundle));
             }
-        }
-
-        if bundles.is_empty() {
-            tracing::warn!("No valid Fluent resources loaded from pattern: {}", pattern);
+            // Add the bundle regardless of overriding errors
+            bundles.insert(lang_code, Arc::new(bundle));
         }
 
         Ok(Self {
@@ -161,6 +175,47 @@ impl FluentLoader {
         None
     }
 
+    /// Format the `attribute` of a message, e.g. `url`, from the first of
+    /// `locales` (then the default locale, then any loaded bundle) whose
+    /// message has it. A language can leave out an attribute that is the same
+    /// for all of them.
+    pub fn get_attribute_localized(
+        &self,
+        locales: &[&str],
+        message_id: &str,
+        attribute: &str,
+        args: Option<&FluentArgs>,
+    ) -> Option<String> {
+        let mut seen = HashSet::new();
+        let candidates = locales
+            .iter()
+            .copied()
+            .chain(std::iter::once(self.default_locale.as_str()))
+            .chain(self.bundles.keys().map(String::as_str));
+
+        for locale in candidates {
+            if !seen.insert(locale) {
+                continue;
+            }
+            let Some(bundle) = self.bundles.get(locale) else {
+                continue;
+            };
+            let Some(attr) = bundle
+                .get_message(message_id)
+                .and_then(|x| x.attributes().find(|attr| attr.id() == attribute))
+            else {
+                continue;
+            };
+            return Some(
+                bundle
+                    .format_pattern(attr.value(), args, &mut vec![])
+                    .into_owned(),
+            );
+        }
+
+        None
+    }
+
     /// Backwards-compatible single-locale lookup. Delegates to
     /// [`Self::get_message_localized`], so it now also falls back across locales
     /// at the message level rather than erroring when the chosen bundle lacks the
@@ -188,6 +243,41 @@ impl FluentLoader {
     }
 }
 
+/// Parse an FTL file. On parse errors we keep the partially-parsed resource so
+/// a single bad message doesn't drop the whole language file.
+fn parse_resource(filename: &str, contents: Vec<u8>) -> Result<FluentResource, Error> {
+    let content = String::from_utf8(contents)
+        .map_err(|e| Error::msg(format!("Failed to read file {}: {}", filename, e)))?;
+
+    Ok(match FluentResource::try_new(content) {
+        Ok(resource) => resource,
+        Err((resource, errors)) => {
+            tracing::warn!(
+                "Fluent resource {} has {} parse error(s); the messages that parsed were still loaded.",
+                filename,
+                errors.len()
+            );
+            // Report each error with file:line:column and a snippet of
+            // the offending line, instead of a raw byte offset (#32).
+            let source = resource.source();
+            for error in &errors {
+                let (line, col) = line_col(source, error.pos.start);
+                let snippet = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+                tracing::warn!(
+                    "  {}:{}:{}: {}\n    {}\n    {}^",
+                    filename,
+                    line,
+                    col,
+                    error.kind,
+                    snippet,
+                    " ".repeat(col.saturating_sub(1))
+                );
+            }
+            resource
+        }
+    })
+}
+
 fn extract_language_code(filename: &str) -> Option<String> {
     // Extract language code from filename like "errors-en.ftl" -> "en"
     if let Some(stem) = filename.strip_suffix(".ftl") {
@@ -250,6 +340,41 @@ mod tests {
         assert_eq!(line_col(utf8, utf8.find("y =").unwrap()), (2, 3));
     }
 
+    #[test]
+    fn attributes_fall_back_to_other_locales() {
+        let loader = FluentLoader::from_sources(
+            [
+                (
+                    "errors-en.ftl",
+                    "typo = Spelling error\n    .url = https://example.org/en/typo\nagr = Agreement\n",
+                ),
+                (
+                    "errors-se.ftl",
+                    "typo = Čállinmeattáhus\nagr = Kongrueansa\n    .url = https://example.org/se/agr\n",
+                ),
+            ],
+            "en",
+        )
+        .unwrap();
+
+        assert_eq!(
+            loader
+                .get_attribute_localized(&["se"], "typo", "url", None)
+                .as_deref(),
+            Some("https://example.org/en/typo")
+        );
+        assert_eq!(
+            loader
+                .get_attribute_localized(&["se"], "agr", "url", None)
+                .as_deref(),
+            Some("https://example.org/se/agr")
+        );
+        assert_eq!(
+            loader.get_attribute_localized(&["se"], "typo", "desc", None),
+            None
+        );
+    }
+
     #[test]
     fn test_find_first_available_locale() {
         use std::collections::HashMap;
@@ -303,4 +428,55 @@ mod tests {
         let preferred = vec![];
         assert_eq!(loader.find_first_available_locale(&preferred), None);
     }
+
+    #[tokio::test]
+    async fn loaders_are_shared_through_the_context() {
+        let temp = tempfile::tempdir().unwrap();
+        let assets = temp.path().join("assets");
+        std::fs::create_dir(&assets).unwrap();
+        std::fs::write(assets.join("errors-en.ftl"), "typo = Typo\n").unwrap();
+        std::fs::write(assets.join("errors-se.ftl"), "typo = Čállinmeattáhus\n").unwrap();
+
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let a = FluentLoader::new(context.clone(), "errors-*.ftl", "en")
+            .await
+            .unwrap();
+        let b = FluentLoader::new(context.clone(), "errors-*.ftl", "en")
+            .await
+            .unwrap();
+        assert!(Arc::ptr_eq(&a.bundles["se"], &b.bundles["se"]));
+
+        // A different default locale gets its own loader.
+        let c = FluentLoader::new(context, "errors-*.ftl", "se")
+            .await
+            .unwrap();
+        assert!(!Arc::ptr_eq(&a.bundles["se"], &c.bundles["se"]));
+        assert_eq!(
+            c.get_message(None, "typo", None).unwrap().0,
+            "Čállinmeattáhus"
+        );
+    }
+
+    #[test]
+    fn loads_sources_held_in_memory() {
+        let loader = FluentLoader::from_sources(
+            [
+                ("cli-en.ftl", "hello = Hello, { $name }\nbye = Bye\n"),
+                ("cli-se.ftl", "hello = Bures, { $name }\n"),
+            ],
+            "en",
+        )
+        .unwrap();
+        let mut args = FluentArgs::new();
+        args.set("name", "Ánde");
+        let message = |locale, id| {
+            loader
+                .get_message_localized(&[locale], id, Some(&args))
+                .unwrap()
+                .0
+        };
+        assert_eq!(message("se", "hello"), "Bures, Ánde");
+        assert_eq!(message("se", "bye"), "Bye");
+        assert!(FluentLoader::from_sources([("cli.ftl", "")], "en").is_err());
+    }
 }
@@ -0,0 +1,159 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    str::FromStr,
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::modules::Error;
+
+/// Name of the checksum manifest stored at the root of a .drb bundle.
+pub const CHECKSUMS_FILE: &str = "checksums.json";
+
+/// When bundle assets are checked against the checksum manifest.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    /// Verify every asset when the bundle is loaded.
+    Eager,
+    /// Verify each asset the first time a command reads it.
+    #[default]
+    Lazy,
+    /// Don't verify.
+    Off,
+}
+
+impl FromStr for VerifyMode {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eager" => Ok(VerifyMode::Eager),
+            "lazy" => Ok(VerifyMode::Lazy),
+            "off" => Ok(VerifyMode::Off),
+            _ => Err(Error::msg(format!(
+                "Invalid verify mode '{}' (expected eager, lazy or off)",
+                s
+            ))),
+        }
+    }
+}
+
+/// Per-asset BLAKE3 digests, keyed by the asset's path inside the bundle.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checksums {
+    pub algorithm: String,
+    pub assets: BTreeMap<String, String>,
+}
+
+impl Default for Checksums {
+    fn default() -> Self {
+        Checksums {
+            algorithm: "blake3".to_string(),
+            assets: BTreeMap::new(),
+        }
+    }
+}
+
+impl Checksums {
+    pub fn hash(bytes: &[u8]) -> String {
+        blake3::hash(bytes).to_hex().to_string()
+    }
+
+    /// Hash everything `reader` yields and record it under `path`.
+    pub fn insert_reader(
+        &mut self,
+        path: impl Into<String>,
+        reader: impl std::io::Read,
+    ) -> std::io::Result<()> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(reader)?;
+        self.assets
+            .insert(path.into(), hasher.finalize().to_hex().to_string());
+        Ok(())
+    }
+
+    pub fn verify(&self, path: &str, bytes: &[u8]) -> Result<(), Error> {
+        let Some(expected) = self.assets.get(path) else {
+            // Assets added after bundling (or by older bundlers) aren't listed.
+            return Ok(());
+        };
+        let actual = Self::hash(bytes);
+        if &actual != expected {
+            return Err(Error::msg(format!(
+                "asset {} failed checksum (expected {}, got {}); the bundle may be corrupted",
+                path, expected, actual
+            ))
+            .at_file(path));
+        }
+        Ok(())
+    }
+}
+
+/// Verification state for a loaded bundle.
+#[derive(Debug, Default)]
+pub(crate) struct Integrity {
+    pub(crate) mode: VerifyMode,
+    pub(crate) checksums: Option<Checksums>,
+    verified: Mutex<HashSet<String>>,
+}
+
+impl Integrity {
+    pub(crate) fn new(mode: VerifyMode, checksums: Option<Checksums>) -> Self {
+        Integrity {
+            mode,
+            checksums,
+            verified: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Whether `path` still has to be hashed before it can be trusted.
+    pub(crate) fn needs_check(&self, path: &str) -> bool {
+        if self.mode == VerifyMode::Off {
+            return false;
+        }
+        let Some(checksums) = &self.checksums else {
+            return false;
+        };
+        checksums.assets.contains_key(path) && !self.verified.lock().unwrap().contains(path)
+    }
+
+    pub(crate) fn check(&self, path: &str, bytes: &[u8]) -> Result<(), Error> {
+        if !self.needs_check(path) {
+            return Ok(());
+        }
+        if let Some(checksums) = &self.checksums {
+            checksums.verify(path, bytes)?;
+        }
+        self.verified.lock().unwrap().insert(path.to_string());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verify_detects_corruption() {
+        let mut checksums = Checksums::default();
+        checksums
+            .insert_reader("model.hfstol", &b"transducer"[..])
+            .unwrap();
+
+        assert!(checksums.verify("model.hfstol", b"transducer").is_ok());
+        let err = checksums.verify("model.hfstol", b"transduc3r").unwrap_err();
+        assert!(err.to_string().contains("model.hfstol failed checksum"));
+        // Unlisted assets are not an error.
+        assert!(checksums.verify("other.bin", b"anything").is_ok());
+    }
+
+    #[test]
+    fn off_mode_skips_verification() {
+        let mut checksums = Checksums::default();
+        checksums.insert_reader("a", &b"a"[..]).unwrap();
+        let integrity = Integrity::new(VerifyMode::Off, Some(checksums));
+        assert!(integrity.check("a", b"corrupt").is_ok());
+    }
+}
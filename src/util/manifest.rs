@@ -0,0 +1,214 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::integrity::Checksums;
+
+/// Name of the build manifest stored at the root of a .drb bundle.
+pub const BUILD_MANIFEST_FILE: &str = "build-manifest.json";
+
+/// Provenance of a bundle: what it was built from, and with what.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildManifest {
+    pub pipeline: PipelineSource,
+    pub assets: BTreeMap<String, AssetRecord>,
+    pub runtime: RuntimeBuild,
+    /// Seconds since the Unix epoch; `SOURCE_DATE_EPOCH` if set at build time.
+    pub built_at: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineSource {
+    pub path: String,
+    /// BLAKE3 digest of the pipeline source file.
+    pub hash: String,
+    /// BLAKE3 digest of the `pipeline.json` built from it, for checking it
+    /// when the bundle is served over HTTP. Missing from older manifests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub json_hash: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssetRecord {
+    pub hash: String,
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RuntimeBuild {
+    pub version: String,
+    pub git_describe: String,
+    pub target: String,
+}
+
+impl RuntimeBuild {
+    /// The runtime doing the build.
+    pub fn current() -> Self {
+        RuntimeBuild {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_describe: crate::VERSION_INFO.git_describe.to_string(),
+            target: crate::VERSION_INFO.cargo_target_triple.to_string(),
+        }
+    }
+}
+
+impl BuildManifest {
+    pub fn new(
+        pipeline_path: impl Into<String>,
+        pipeline_source: &[u8],
+        checksums: &Checksums,
+        sizes: &BTreeMap<String, u64>,
+        built_at: u64,
+    ) -> Self {
+        let assets = checksums
+            .assets
+            .iter()
+            .map(|(path, hash)| {
+                (
+                    path.clone(),
+                    AssetRecord {
+                        hash: hash.clone(),
+                        size: sizes.get(path).copied().unwrap_or_default(),
+                    },
+                )
+            })
+            .collect();
+
+        BuildManifest {
+            pipeline: PipelineSource {
+                path: pipeline_path.into(),
+                hash: Checksums::hash(pipeline_source),
+                json_hash: None,
+            },
+            assets,
+            runtime: RuntimeBuild::current(),
+            built_at,
+        }
+    }
+
+    pub fn total_asset_size(&self) -> u64 {
+        self.assets.values().map(|x| x.size).sum()
+    }
+
+    /// What changed from `self` to the manifest of a later build.
+    pub fn diff(&self, later: &BuildManifest) -> Vec<ManifestChange> {
+        let mut changes = Vec::new();
+        if self.runtime.version != later.runtime.version {
+            changes.push(ManifestChange::Runtime {
+                from: self.runtime.version.clone(),
+                to: later.runtime.version.clone(),
+            });
+        }
+        if self.pipeline.hash != later.pipeline.hash {
+            changes.push(ManifestChange::Pipeline {
+                path: later.pipeline.path.clone(),
+            });
+        }
+        for (path, asset) in &self.assets {
+            match later.assets.get(path) {
+                None => changes.push(ManifestChange::AssetRemoved(path.clone())),
+                Some(later) if later.hash != asset.hash => {
+                    changes.push(ManifestChange::AssetChanged {
+                        path: path.clone(),
+                        from_size: asset.size,
+                        to_size: later.size,
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        for path in later.assets.keys() {
+            if !self.assets.contains_key(path) {
+                changes.push(ManifestChange::AssetAdded(path.clone()));
+            }
+        }
+        changes
+    }
+}
+
+/// One difference between two [`BuildManifest`]s.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifestChange {
+    Runtime {
+        from: String,
+        to: String,
+    },
+    Pipeline {
+        path: String,
+    },
+    AssetAdded(String),
+    AssetRemoved(String),
+    AssetChanged {
+        path: String,
+        from_size: u64,
+        to_size: u64,
+    },
+}
+
+impl std::fmt::Display for ManifestChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ManifestChange::Runtime { from, to } => write!(f, "runtime {} -> {}", from, to),
+            ManifestChange::Pipeline { path } => write!(f, "changed {}", path),
+            ManifestChange::AssetAdded(path) => write!(f, "added {}", path),
+            ManifestChange::AssetRemoved(path) => write!(f, "removed {}", path),
+            ManifestChange::AssetChanged {
+                path,
+                from_size,
+                to_size,
+            } => write!(f, "changed {} ({} -> {} bytes)", path, from_size, to_size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manifest_records_asset_hashes_and_sizes() {
+        let mut checksums = Checksums::default();
+        checksums.insert_reader("errors.json", &b"{}"[..]).unwrap();
+        let sizes = BTreeMap::from([("errors.json".to_string(), 2)]);
+
+        let manifest =
+            BuildManifest::new("pipeline.ts", b"export default 1", &checksums, &sizes, 7);
+
+        assert_eq!(manifest.pipeline.hash, Checksums::hash(b"export default 1"));
+        assert_eq!(manifest.assets["errors.json"].hash, Checksums::hash(b"{}"));
+        assert_eq!(manifest.total_asset_size(), 2);
+
+        let json = serde_json::to_value(&manifest).unwrap();
+        let back: BuildManifest = serde_json::from_value(json).unwrap();
+        assert_eq!(back.built_at, 7);
+    }
+
+    #[test]
+    fn diff_lists_changed_assets() {
+        let build = |assets: &[(&str, &[u8])]| {
+            let mut checksums = Checksums::default();
+            let mut sizes = BTreeMap::new();
+            for (path, contents) in assets {
+                checksums.insert_reader(*path, *contents).unwrap();
+                sizes.insert(path.to_string(), contents.len() as u64);
+            }
+            BuildManifest::new("pipeline.ts", b"export default 1", &checksums, &sizes, 0)
+        };
+        let old = build(&[("errors.json", b"{}"), ("grammar.bin", b"a")]);
+        let new = build(&[("grammar.bin", b"ab"), ("errors-se.ftl", b"x")]);
+
+        assert_eq!(
+            old.diff(&new),
+            [
+                ManifestChange::AssetRemoved("errors.json".to_string()),
+                ManifestChange::AssetChanged {
+                    path: "grammar.bin".to_string(),
+                    from_size: 1,
+                    to_size: 2,
+                },
+                ManifestChange::AssetAdded("errors-se.ftl".to_string()),
+            ]
+        );
+        assert!(old.diff(&old).is_empty());
+    }
+}
@@ -0,0 +1,211 @@
+//! Latency histograms for long-running services, e.g. the `/metrics`
+//! endpoint of `divvun-runtime serve`.
+//!
+//! A [`PipePool`](crate::pipe_pool::PipePool) times the requests it runs and,
+//! through a [`StageTimings`] tap on its pipelines, each step of them. A
+//! step's time runs from the last output of the step it reads from (or the
+//! start of the request) to each of its own outputs, so it includes time
+//! spent waiting for a free task.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use futures_util::FutureExt;
+use indexmap::IndexMap;
+
+use crate::{
+    ast::{Command, InputValue},
+    modules::{PipelineEvent, TapFn, TapOutput},
+};
+
+/// Upper bounds of the histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 12] = [
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+/// Counts of durations by [`LATENCY_BUCKETS`], safe to update from any task.
+#[derive(Debug)]
+pub struct Histogram {
+    /// One count per bucket, and one for longer durations.
+    counts: [AtomicU64; LATENCY_BUCKETS.len() + 1],
+    sum_micros: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            counts: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+}
+
+impl Histogram {
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&le| seconds <= le)
+            .unwrap_or(LATENCY_BUCKETS.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        let mut total = 0;
+        let buckets = LATENCY_BUCKETS
+            .iter()
+            .zip(&self.counts)
+            .map(|(&le, count)| {
+                total += count.load(Ordering::Relaxed);
+                (le, total)
+            })
+            .collect();
+        total += self.counts[LATENCY_BUCKETS.len()].load(Ordering::Relaxed);
+        HistogramSnapshot {
+            buckets,
+            count: total,
+            sum: self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0,
+        }
+    }
+}
+
+/// A [`Histogram`] at one point in time, with cumulative bucket counts as
+/// Prometheus has them.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct HistogramSnapshot {
+    /// Upper bound in seconds and the number of durations up to it.
+    pub buckets: Vec<(f64, u64)>,
+    pub count: u64,
+    /// Total of all durations, in seconds.
+    pub sum: f64,
+}
+
+/// Time of the last output of each step of one pipeline, for
+/// [`StageTimings`]. [`start`](Self::start) it when a request goes in.
+#[derive(Debug, Clone, Default)]
+pub struct StageClock(Arc<Mutex<HashMap<String, Instant>>>);
+
+impl StageClock {
+    pub fn start(&self) {
+        let mut times = self.0.lock().unwrap();
+        times.clear();
+        times.insert(crate::ast::ENTRY.to_string(), Instant::now());
+    }
+}
+
+/// Time spent in each step of the pipelines of one bundle, by step key.
+#[derive(Debug, Default)]
+pub struct StageTimings {
+    stages: Mutex<IndexMap<String, Arc<Histogram>>>,
+}
+
+impl StageTimings {
+    /// A tap timing the outputs of the pipeline `clock` belongs to.
+    pub fn tap(self: &Arc<Self>, clock: StageClock) -> Arc<TapFn> {
+        let timings = self.clone();
+        Arc::new(move |key: &str, command: &Command, event: &PipelineEvent| {
+            if matches!(event, PipelineEvent::Value(_)) {
+                timings.record(&clock, key, command);
+            }
+            async { TapOutput::Continue }.boxed()
+        })
+    }
+
+    fn record(&self, clock: &StageClock, key: &str, command: &Command) {
+        let now = Instant::now();
+        let mut times = clock.0.lock().unwrap();
+        let since = match &command.input {
+            InputValue::Single(x) => times.get(&x.r#ref).copied(),
+            InputValue::Multiple(x) => x.iter().filter_map(|x| times.get(&x.r#ref)).max().copied(),
+        };
+        times.insert(key.to_string(), now);
+        drop(times);
+
+        // Without a started clock there's nothing to measure from
+        let Some(since) = since else {
+            return;
+        };
+        let histogram = self
+            .stages
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        histogram.observe(now.saturating_duration_since(since));
+    }
+
+    /// Histograms of the steps that have had output, in the order they first
+    /// did.
+    pub fn snapshot(&self) -> Vec<(String, HistogramSnapshot)> {
+        self.stages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, histogram)| (key.clone(), histogram.snapshot()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(input: &str) -> Command {
+        serde_json::from_value(serde_json::json!({
+            "module": "example",
+            "command": "upper",
+            "input": { "ref": input },
+            "returns": "string"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(30));
+        histogram.observe(Duration::from_secs(20));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 3);
+        assert_eq!(snapshot.buckets[0], (0.001, 1));
+        assert_eq!(snapshot.buckets[5], (0.05, 2));
+        assert_eq!(snapshot.buckets.last(), Some(&(10.0, 2)));
+        assert!((snapshot.sum - 20.0305).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn stages_are_timed_from_their_input() {
+        let timings = Arc::new(StageTimings::default());
+        let clock = StageClock::default();
+        let tap = timings.tap(clock.clone());
+        let value = PipelineEvent::Value("x".to_string().into());
+
+        // Not started: nothing to measure the first step from
+        tap("upper", &command("#/entry"), &value).await;
+        assert!(timings.snapshot().is_empty());
+
+        clock.start();
+        tap("upper", &command("#/entry"), &value).await;
+        tap("reverse", &command("upper"), &value).await;
+        tap("upper", &command("#/entry"), &PipelineEvent::Finish).await;
+
+        let stages = timings.snapshot();
+        let keys = stages
+            .iter()
+            .map(|(key, _)| key.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["upper", "reverse"]);
+        assert!(stages.iter().all(|(_, x)| x.count == 1));
+    }
+}
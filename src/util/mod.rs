@@ -1,5 +1,26 @@
+#[allow(clippy::unwrap_used)]
+pub(crate) mod asset_cache;
+pub mod breakpoint;
+pub mod channel;
+pub mod deterministic;
+#[allow(clippy::unwrap_used)]
+pub mod download;
 pub mod fluent_loader;
+#[allow(clippy::unwrap_used)]
+pub mod integrity;
+pub mod manifest;
+#[allow(clippy::unwrap_used)]
+pub mod metrics;
+pub mod priority;
+pub mod privacy;
+#[allow(clippy::unwrap_used)]
+pub mod recorder;
+#[cfg(feature = "remote")]
+pub(crate) mod remote;
+#[allow(clippy::unwrap_used)]
 pub(crate) mod shared_box;
+#[allow(clippy::unwrap_used)]
+pub mod shutdown;
 
 pub(crate) use shared_box::SharedBox;
 
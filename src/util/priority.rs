@@ -0,0 +1,170 @@
+//! Priority hints for the CPU-heavy work of commands, so that e.g. speech
+//! synthesis for a live listener doesn't wait behind a batch of documents
+//! being grammar checked in the same process.
+//!
+//! A command's priority is the `priority` key of its runtime config,
+//! `"realtime"` or `"batch"`, so a bundle can set it in a command's defaults
+//! and an embedder per pipeline it creates. Tokio has no task priorities, so
+//! the hint decides where the command's [`spawn_blocking`] work runs:
+//!
+//! - `realtime` work has a few threads of its own that batch work never
+//!   occupies.
+//! - `batch` work shares one thread per remaining core, so a large batch
+//!   queues up instead of competing with realtime work for every core.
+//! - Work without a hint goes to tokio's blocking pool, as does all work in
+//!   [deterministic mode](super::deterministic), so it runs on the threads
+//!   the embedder allows.
+//!
+//! Commands that keep a worker thread of their own, like `cg3::vislcg3` and
+//! `hfst::tokenize`, run there whatever their priority.
+
+use std::panic::AssertUnwindSafe;
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use super::shutdown::BlockingGuard;
+use crate::modules::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    Realtime,
+    Batch,
+}
+
+tokio::task_local! {
+    static CURRENT: Option<Priority>;
+}
+
+type Pool = Result<rayon::ThreadPool, rayon::ThreadPoolBuildError>;
+
+static REALTIME_POOL: Lazy<Pool> = Lazy::new(|| pool("realtime", realtime_threads()));
+
+static BATCH_POOL: Lazy<Pool> =
+    Lazy::new(|| pool("batch", cores().saturating_sub(realtime_threads()).max(1)));
+
+fn pool(name: &'static str, threads: usize) -> Pool {
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .thread_name(move |i| format!("drt-{}-{}", name, i))
+        .build()
+}
+
+fn cores() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(4)
+}
+
+/// A quarter of the cores, at least one.
+fn realtime_threads() -> usize {
+    (cores() / 4).max(1)
+}
+
+impl Priority {
+    /// The `priority` in a command's runtime config, if it has one.
+    pub fn from_config(config: &serde_json::Value) -> Result<Option<Priority>, Error> {
+        match config.get("priority") {
+            None | Some(serde_json::Value::Null) => Ok(None),
+            Some(value) => serde_json::from_value(value.clone())
+                .map(Some)
+                .map_err(|_| {
+                    Error::msg(format!(
+                        "unknown priority {}, expected \"realtime\" or \"batch\"",
+                        value
+                    ))
+                    .at_path("/config/priority")
+                }),
+        }
+    }
+
+    /// Priority of the command whose `forward` is running, if it has one.
+    pub fn current() -> Option<Priority> {
+        CURRENT.try_with(|x| *x).ok().flatten()
+    }
+}
+
+/// Run `f` with `priority` as the [current](Priority::current) priority.
+pub async fn scope<F: Future>(priority: Option<Priority>, f: F) -> F::Output {
+    CURRENT.scope(priority, f).await
+}
+
+/// Run blocking `f` on the threads for the current command's priority. A
+/// panic in `f` comes back as an error.
+pub async fn spawn_blocking<F, R>(f: F) -> Result<R, Error>
+where
+    F: FnOnce() -> R + Send + 'static,
+    R: Send + 'static,
+{
+    // Counted until `f` returns, for shutdown to wait for
+    let guard = BlockingGuard::new();
+    let f = move || {
+        let _guard = guard;
+        f()
+    };
+    let priority = Priority::current().filter(|_| !super::deterministic::is_enabled());
+    let (name, pool) = match priority {
+        Some(Priority::Realtime) => ("realtime", &*REALTIME_POOL),
+        Some(Priority::Batch) => ("batch", &*BATCH_POOL),
+        None => return tokio::task::spawn_blocking(f).await.map_err(Error::wrap),
+    };
+    let pool = pool
+        .as_ref()
+        .map_err(|e| Error::msg(format!("failed to start {} thread pool: {}", name, e)))?;
+
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    pool.spawn(move || {
+        let _ = tx.send(std::panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+    match rx.await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(_)) => Err(Error::msg("blocking work panicked")),
+        Err(e) => Err(Error::wrap(e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_priority_from_config() {
+        let config = |x| serde_json::json!({ "priority": x });
+        assert_eq!(
+            Priority::from_config(&config("realtime")).unwrap(),
+            Some(Priority::Realtime)
+        );
+        assert_eq!(Priority::from_config(&serde_json::json!({})).unwrap(), None);
+        assert_eq!(
+            Priority::from_config(&serde_json::json!("x")).unwrap(),
+            None
+        );
+        let err = Priority::from_config(&config("urgent")).unwrap_err();
+        assert_eq!(err.location().path, "/config/priority");
+    }
+
+    #[tokio::test]
+    async fn runs_on_the_pool_of_the_priority() {
+        let thread_name = || {
+            std::thread::current()
+                .name()
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let name = scope(Some(Priority::Realtime), spawn_blocking(thread_name)).await;
+        assert!(name.unwrap().starts_with("drt-realtime-"));
+        let name = scope(Some(Priority::Batch), spawn_blocking(thread_name)).await;
+        assert!(name.unwrap().starts_with("drt-batch-"));
+        let name = spawn_blocking(thread_name).await.unwrap();
+        assert!(!name.starts_with("drt-"));
+
+        let panicked = scope(
+            Some(Priority::Batch),
+            spawn_blocking(|| -> u8 { panic!("oops") }),
+        )
+        .await;
+        assert!(panicked.is_err());
+    }
+}
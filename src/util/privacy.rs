@@ -0,0 +1,106 @@
+//! Privacy mode, so text users check never ends up in logs.
+//!
+//! When enabled, user text is written to tracing output, error messages and
+//! crash dumps only as its length and a short hash, which is enough to tell
+//! whether two reports are about the same input. It is on by default in
+//! release builds; embedders and developers turn it off with [`set_enabled`]
+//! (the CLI's `--log-input`) when debugging locally.
+//!
+//! Errors are covered centrally: every error a command's `forward` returns is
+//! marked with [`Error::about_input`](crate::modules::Error::about_input) by
+//! the code running it, and formats as a summary in privacy mode, wherever it
+//! is logged or reported. Values are covered by the `Debug` impl of
+//! [`PipelineValue`](crate::modules::PipelineValue). Logs that format text
+//! themselves go through [`redact`]; as a net for those that don't, whoever
+//! installs the tracing subscriber filters events with [`allows`], which
+//! leaves out the modules' debug and trace output in privacy mode.
+
+use std::{
+    fmt,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(!cfg!(debug_assertions));
+
+/// Turn privacy mode on or off for the rest of the process.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Whether a tracing event may be logged. In privacy mode the debug and trace
+/// events of the modules are left out, as they log the text being worked on
+/// more often than not. For a `filter_fn` of the subscriber, as the CLI does.
+pub fn allows(metadata: &tracing::Metadata<'_>) -> bool {
+    !(is_enabled()
+        && *metadata.level() > tracing::Level::INFO
+        && metadata.target().starts_with("divvun_runtime::modules"))
+}
+
+/// `text`, formatted as-is or as a summary depending on privacy mode.
+pub fn redact(text: &str) -> Redacted<'_> {
+    Redacted::new(text, is_enabled())
+}
+
+/// User text that formats as `<redacted: N chars, H>` when hidden, where `H`
+/// is the first 8 hex digits of its BLAKE3 hash. `Display` and `Debug`
+/// otherwise behave like those of `str`.
+pub struct Redacted<'a> {
+    text: &'a str,
+    hidden: bool,
+}
+
+impl<'a> Redacted<'a> {
+    /// `text`, hidden or not whatever the privacy mode.
+    pub fn new(text: &'a str, hidden: bool) -> Self {
+        Redacted { text, hidden }
+    }
+
+    fn summary(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "<redacted: {} chars, {}>",
+            self.text.chars().count(),
+            &blake3::hash(self.text.as_bytes()).to_hex()[..8]
+        )
+    }
+}
+
+impl fmt::Display for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hidden {
+            self.summary(f)
+        } else {
+            fmt::Display::fmt(self.text, f)
+        }
+    }
+}
+
+impl fmt::Debug for Redacted<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.hidden {
+            self.summary(f)
+        } else {
+            fmt::Debug::fmt(self.text, f)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_only_when_hidden() {
+        let text = |hidden| Redacted::new("sámi", hidden);
+        let hidden = format!("{} {:?}", text(true), text(true));
+        let shown = format!("{} {:?}", text(false), text(false));
+
+        assert!(!hidden.contains("sámi"));
+        assert!(hidden.starts_with("<redacted: 4 chars, "));
+        assert_eq!(shown, "sámi \"sámi\"");
+    }
+}
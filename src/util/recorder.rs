@@ -0,0 +1,270 @@
+//! Recording of what a pipeline's commands emit, for debugging tools: the
+//! REPL's `:save`, the playground's steps, and anything else that wants to
+//! look at a run after it's done.
+//!
+//! A [`RunRecorder`] is opt-in. Its [`tap`](RunRecorder::tap) goes where any
+//! other tap would, e.g. `bundle.create_with_tap(config, recorder.tap(None))`,
+//! and records every event until [`finish`](RunRecorder::finish) moves the
+//! run to its history. Memory is bounded: a run keeps its first
+//! `max_events` events and counts the rest, and the history keeps the last
+//! `max_runs` runs.
+
+use std::collections::VecDeque;
+use std::fmt::Write as _;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures_util::FutureExt;
+
+use crate::ast::Command;
+use crate::modules::{PipelineEvent, PipelineValue, TapFn, TapOutput};
+
+/// An event a command emitted during a run.
+#[derive(Debug, Clone)]
+pub struct RecordedEvent {
+    /// Key of the command in the pipeline.
+    pub key: String,
+    pub command: Command,
+    pub event: PipelineEvent,
+    /// Time since the run started.
+    pub at: Duration,
+}
+
+/// The events of one run, in the order the commands emitted them.
+#[derive(Debug, Clone)]
+pub struct PipelineRun {
+    pub input: String,
+    pub started: SystemTime,
+    pub events: Vec<RecordedEvent>,
+    /// Events left out after `max_events`.
+    pub dropped: usize,
+    start: Instant,
+}
+
+impl PipelineRun {
+    fn new(input: String) -> Self {
+        PipelineRun {
+            input,
+            started: SystemTime::now(),
+            events: Vec::new(),
+            dropped: 0,
+            start: Instant::now(),
+        }
+    }
+
+    /// The events of the command with `key`.
+    pub fn events_for<'a>(&'a self, key: &'a str) -> impl Iterator<Item = &'a RecordedEvent> {
+        self.events.iter().filter(move |x| x.key == key)
+    }
+
+    /// The events from `from` up to `to` after the run started.
+    pub fn events_between(
+        &self,
+        from: Duration,
+        to: Duration,
+    ) -> impl Iterator<Item = &RecordedEvent> {
+        self.events
+            .iter()
+            .filter(move |x| x.at >= from && x.at < to)
+    }
+
+    /// A report with the input and every event, for attaching to bug reports.
+    pub fn to_markdown(&self) -> String {
+        let mut markdown = String::new();
+        let _ = writeln!(markdown, "# Pipeline Debug Report\n");
+        let _ = writeln!(markdown, "## PipelineValue\n```\n{}\n```\n", self.input);
+        let _ = writeln!(markdown, "## Pipeline Execution\n");
+        for event in &self.events {
+            let _ = writeln!(
+                markdown,
+                "<details>\n<summary><code>[{}]</code> <code>{}</code></summary>\n",
+                event.key, event.command
+            );
+            let _ = writeln!(markdown, "```\n{:#}\n```\n</details>\n", event.event);
+        }
+        if self.dropped > 0 {
+            let _ = writeln!(markdown, "_{} more events not recorded._", self.dropped);
+        }
+        markdown
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let started = self
+            .started
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        serde_json::json!({
+            "input": self.input,
+            "started_ms": started.as_millis() as u64,
+            "events": self.events.iter().map(event_json).collect::<Vec<_>>(),
+            "dropped": self.dropped,
+        })
+    }
+}
+
+fn event_json(event: &RecordedEvent) -> serde_json::Value {
+    let (kind, value) = match &event.event {
+        PipelineEvent::Value(PipelineValue::String(x)) => ("value", serde_json::json!(x)),
+        PipelineEvent::Value(PipelineValue::Json(x)) => ("value", x.clone()),
+        PipelineEvent::Value(x) => ("value", serde_json::json!(format!("{:#}", x))),
+        PipelineEvent::Error(e) => ("error", serde_json::json!(e.to_string())),
+        PipelineEvent::Finish => ("finish", serde_json::Value::Null),
+        PipelineEvent::Cancel => ("cancel", serde_json::Value::Null),
+        PipelineEvent::Close => ("close", serde_json::Value::Null),
+    };
+    serde_json::json!({
+        "key": event.key,
+        "module": event.command.module,
+        "command": event.command.command,
+        "at_ms": event.at.as_secs_f64() * 1000.0,
+        "event": kind,
+        "value": value,
+    })
+}
+
+/// Records pipeline runs through a tap. See the [module docs](self).
+#[derive(Debug)]
+pub struct RunRecorder {
+    max_runs: usize,
+    max_events: usize,
+    current: Mutex<Option<PipelineRun>>,
+    history: Mutex<VecDeque<PipelineRun>>,
+}
+
+impl Default for RunRecorder {
+    fn default() -> Self {
+        RunRecorder::new(10, 10_000)
+    }
+}
+
+impl RunRecorder {
+    /// Keep the last `max_runs` runs, each with at most `max_events` events.
+    pub fn new(max_runs: usize, max_events: usize) -> Self {
+        RunRecorder {
+            max_runs: max_runs.max(1),
+            max_events,
+            current: Mutex::new(None),
+            history: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Start recording a run of `input`, finishing the one before if it
+    /// wasn't.
+    pub fn start(&self, input: impl Into<String>) {
+        self.finish();
+        *self.current.lock().unwrap() = Some(PipelineRun::new(input.into()));
+    }
+
+    /// Record an event, in a run of its own if none was started. Returns the
+    /// event's index in the run.
+    pub fn record(&self, key: &str, command: &Command, event: &PipelineEvent) -> usize {
+        let mut current = self.current.lock().unwrap();
+        let run = current.get_or_insert_with(|| PipelineRun::new(String::new()));
+        let index = run.events.len() + run.dropped;
+        if run.events.len() < self.max_events {
+            run.events.push(RecordedEvent {
+                key: key.to_string(),
+                command: command.clone(),
+                event: event.clone(),
+                at: run.start.elapsed(),
+            });
+        } else {
+            run.dropped += 1;
+        }
+        index
+    }
+
+    /// End the current run and add it to the history. Runs without events
+    /// are dropped.
+    pub fn finish(&self) -> Option<PipelineRun> {
+        let run = self.current.lock().unwrap().take()?;
+        if run.events.is_empty() && run.dropped == 0 {
+            return None;
+        }
+        let mut history = self.history.lock().unwrap();
+        history.push_back(run.clone());
+        while history.len() > self.max_runs {
+            history.pop_front();
+        }
+        Some(run)
+    }
+
+    /// The last finished run.
+    pub fn last_run(&self) -> Option<PipelineRun> {
+        self.history.lock().unwrap().back().cloned()
+    }
+
+    /// Finished runs, oldest first.
+    pub fn runs(&self) -> Vec<PipelineRun> {
+        self.history.lock().unwrap().iter().cloned().collect()
+    }
+
+    /// A tap recording every event before passing it on to `inner`.
+    pub fn tap(self: &Arc<Self>, inner: Option<Arc<TapFn>>) -> Arc<TapFn> {
+        let recorder = self.clone();
+        Arc::new(move |key: &str, command: &Command, event: &PipelineEvent| {
+            recorder.record(key, command, event);
+            match &inner {
+                Some(inner) => inner(key, command, event),
+                None => async { TapOutput::Continue }.boxed(),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(module: &str) -> Command {
+        serde_json::from_value(serde_json::json!({
+            "module": module,
+            "command": "x",
+            "input": { "ref": "#/entry" },
+            "returns": "string"
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn records_runs_within_bounds() {
+        let recorder = Arc::new(RunRecorder::new(2, 3));
+        let tap = recorder.tap(None);
+        let (a, b) = (command("a"), command("b"));
+
+        recorder.start("Mun");
+        tap("a", &a, &PipelineEvent::Value("Mun".to_string().into())).await;
+        tap("b", &b, &PipelineEvent::Value("MUN".to_string().into())).await;
+        tap("a", &a, &PipelineEvent::Finish).await;
+        assert_eq!(recorder.record("b", &b, &PipelineEvent::Finish), 3);
+
+        let run = recorder.finish().unwrap();
+        assert_eq!(run.events.len(), 3);
+        assert_eq!(run.dropped, 1);
+        assert_eq!(run.events_for("a").count(), 2);
+        assert_eq!(
+            run.events_between(Duration::ZERO, Duration::from_secs(60))
+                .count(),
+            3
+        );
+        let json = run.to_json();
+        assert_eq!(json["events"][1]["value"], "MUN");
+        assert_eq!(json["events"][2]["event"], "finish");
+        assert!(run.to_markdown().contains("<code>[b]</code>"));
+
+        // Only the last runs are kept
+        for input in ["x", "y"] {
+            recorder.start(input);
+            recorder.record("a", &a, &PipelineEvent::Finish);
+        }
+        recorder.start("empty");
+        assert!(recorder.finish().is_none());
+        let inputs = recorder
+            .runs()
+            .into_iter()
+            .map(|x| x.input)
+            .collect::<Vec<_>>();
+        assert_eq!(inputs, ["x", "y"]);
+        assert_eq!(recorder.last_run().unwrap().input, "y");
+    }
+}
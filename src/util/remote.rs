@@ -0,0 +1,281 @@
+//! Bundles served over HTTP.
+//!
+//! A remote bundle is the unpacked contents of a .drb on a web server:
+//! `pipeline.json` and `build-manifest.json` at the base URL, assets next to
+//! them at their bundle paths. The manifest and pipeline are fetched when the
+//! bundle is opened, the pipeline checked against the manifest's hash of it;
+//! each asset is downloaded the first time a command loads it, streamed to a
+//! partial file and checked against the manifest's hash, and kept in a cache
+//! directory named after the manifest's hash, so a rebuilt bundle never
+//! reuses stale files and an unchanged one is never downloaded twice.
+
+use std::path::{Component, Path, PathBuf};
+
+use super::{
+    download::{DownloadCallback, Fetcher, Source, partial_path},
+    integrity::Checksums,
+    manifest::BuildManifest,
+};
+use crate::modules::Error;
+
+pub(crate) struct RemoteAssets {
+    base_url: String,
+    cache_dir: PathBuf,
+    manifest: BuildManifest,
+    fetcher: Fetcher,
+}
+
+impl RemoteAssets {
+    /// Fetch the manifest and pipeline of the bundle at `base_url`, caching
+    /// them under `cache_root`. `callback` is told how asset downloads are
+    /// coming along.
+    pub(crate) async fn open(
+        base_url: &str,
+        cache_root: &Path,
+        callback: Option<DownloadCallback>,
+    ) -> Result<Self, Error> {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        let fetcher = Fetcher::new(callback);
+        let client = fetcher.client();
+
+        let manifest_bytes = get(client, &base_url, super::manifest::BUILD_MANIFEST_FILE).await?;
+        let manifest: BuildManifest = serde_json::from_slice(&manifest_bytes)
+            .map_err(|e| Error::wrap(e).at_file(super::manifest::BUILD_MANIFEST_FILE))?;
+
+        let cache_dir = cache_root.join(Checksums::hash(&manifest_bytes));
+        tokio::fs::create_dir_all(&cache_dir)
+            .await
+            .map_err(|e| Error::wrap(e).at_file(cache_dir.display().to_string()))?;
+
+        let remote = RemoteAssets {
+            base_url,
+            cache_dir,
+            manifest,
+            fetcher,
+        };
+
+        let hash = remote.manifest.pipeline.json_hash.as_deref().ok_or_else(|| {
+            Error::msg(
+                "The build manifest has no hash of pipeline.json to check it against; rebuild the bundle with a newer divvun-runtime",
+            )
+            .at_file(super::manifest::BUILD_MANIFEST_FILE)
+        })?;
+        let url = remote.url("pipeline.json");
+        let source = Source {
+            path: "pipeline.json",
+            url: &url,
+            hash,
+            size: None,
+        };
+        remote
+            .fetcher
+            .fetch(&source, &remote.cache_dir.join("pipeline.json"))
+            .await?;
+
+        Ok(remote)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path)
+    }
+
+    pub(crate) fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Asset paths listed in the bundle's manifest.
+    pub(crate) fn assets(&self) -> impl Iterator<Item = &str> {
+        self.manifest.assets.keys().map(String::as_str)
+    }
+
+    /// Remove assets whose download was cut short, returning how many.
+    pub(crate) fn remove_partial(&self) -> usize {
+        self.assets()
+            .chain(["pipeline.json"])
+            .filter(|path| std::fs::remove_file(partial_path(&self.cache_dir.join(path))).is_ok())
+            .count()
+    }
+
+    /// Make sure `path` is in the cache. Paths the manifest doesn't list are
+    /// left alone; reading them fails like any missing file.
+    pub(crate) async fn fetch(&self, path: &str) -> Result<(), Error> {
+        let Some(record) = self.manifest.assets.get(path) else {
+            return Ok(());
+        };
+        if !Path::new(path)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(Error::msg("Remote asset path escapes the bundle").at_file(path));
+        }
+        let url = self.url(path);
+        let source = Source {
+            path,
+            url: &url,
+            hash: &record.hash,
+            size: Some(record.size),
+        };
+        self.fetcher
+            .fetch(&source, &self.cache_dir.join(path))
+            .await
+    }
+}
+
+async fn get(client: &reqwest::Client, base_url: &str, path: &str) -> Result<Vec<u8>, Error> {
+    let url = format!("{}/{}", base_url, path);
+    let response = client
+        .get(&url)
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| Error::wrap(e).at_file(&url))?;
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| Error::wrap(e).at_file(&url))?;
+    Ok(bytes.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::{BTreeMap, HashMap},
+        sync::{Arc, Mutex},
+    };
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    use super::*;
+    use crate::util::manifest::BUILD_MANIFEST_FILE;
+
+    type Requests = Arc<Mutex<Vec<String>>>;
+
+    /// The files of a bundle served over HTTP, with a build manifest
+    /// listing `assets`.
+    fn bundle(pipeline: &[u8], assets: &[(&str, &[u8])]) -> HashMap<String, Vec<u8>> {
+        let mut checksums = Checksums::default();
+        let mut sizes = BTreeMap::new();
+        let mut files = HashMap::new();
+        for (path, contents) in assets {
+            checksums.insert_reader(*path, *contents).unwrap();
+            sizes.insert(path.to_string(), contents.len() as u64);
+            files.insert(path.to_string(), contents.to_vec());
+        }
+        let mut manifest =
+            BuildManifest::new("pipeline.ts", b"export default 1", &checksums, &sizes, 0);
+        manifest.pipeline.json_hash = Some(Checksums::hash(pipeline));
+        files.insert("pipeline.json".to_string(), pipeline.to_vec());
+        files.insert(
+            BUILD_MANIFEST_FILE.to_string(),
+            serde_json::to_vec(&manifest).unwrap(),
+        );
+        files
+    }
+
+    /// Serve `files` on a local port, recording the path of every request.
+    async fn serve(files: HashMap<String, Vec<u8>>) -> (String, Requests) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/se/", listener.local_addr().unwrap());
+        let files = Arc::new(files);
+        let requests = Requests::default();
+        let log = requests.clone();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                let files = files.clone();
+                let log = log.clone();
+                tokio::spawn(async move {
+                    let mut head = Vec::new();
+                    let mut buf = [0; 1024];
+                    while !head.windows(4).any(|x| x == b"\r\n\r\n") {
+                        let n = stream.read(&mut buf).await.unwrap();
+                        if n == 0 {
+                            return;
+                        }
+                        head.extend_from_slice(&buf[..n]);
+                    }
+                    let head = String::from_utf8_lossy(&head);
+                    let path = head.split(' ').nth(1).unwrap_or_default();
+                    let path = path.strip_prefix("/se/").unwrap_or(path).to_string();
+                    let response = match files.get(&path) {
+                        Some(body) => {
+                            let mut response = format!(
+                                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                                body.len()
+                            )
+                            .into_bytes();
+                            response.extend_from_slice(body);
+                            response
+                        }
+                        None => b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_vec(),
+                    };
+                    log.lock().unwrap().push(path);
+                    stream.write_all(&response).await.unwrap();
+                });
+            }
+        });
+        (url, requests)
+    }
+
+    fn count(requests: &Requests, path: &str) -> usize {
+        requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|x| *x == path)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn assets_are_downloaded_once_and_checked() {
+        let mut files = bundle(
+            b"{}",
+            &[("voices/model.bin", b"weights"), ("errors.json", b"{}")],
+        );
+        // Changed on the server after the bundle was built
+        files.insert("errors.json".to_string(), b"[]".to_vec());
+        let (url, requests) = serve(files).await;
+        let temp = tempfile::tempdir().unwrap();
+
+        let remote = RemoteAssets::open(&url, temp.path(), None).await.unwrap();
+        let (a, b) = tokio::join!(
+            remote.fetch("voices/model.bin"),
+            remote.fetch("voices/model.bin")
+        );
+        a.unwrap();
+        b.unwrap();
+        let model = remote.cache_dir().join("voices/model.bin");
+        assert_eq!(std::fs::read(&model).unwrap(), b"weights");
+        assert_eq!(count(&requests, "voices/model.bin"), 1);
+
+        let error = remote.fetch("errors.json").await.unwrap_err();
+        assert!(error.to_string().contains("failed checksum"), "{}", error);
+        let errors = remote.cache_dir().join("errors.json");
+        assert!(!errors.exists() && !partial_path(&errors).exists());
+
+        // Not in the manifest, so not downloaded
+        remote.fetch("other.bin").await.unwrap();
+        assert_eq!(count(&requests, "other.bin"), 0);
+
+        // Opening it again uses the cache
+        let again = RemoteAssets::open(&url, temp.path(), None).await.unwrap();
+        again.fetch("voices/model.bin").await.unwrap();
+        assert_eq!(count(&requests, "pipeline.json"), 1);
+        assert_eq!(count(&requests, "voices/model.bin"), 1);
+    }
+
+    #[tokio::test]
+    async fn pipeline_is_checked_against_the_manifest() {
+        let mut files = bundle(b"{}", &[]);
+        files.insert("pipeline.json".to_string(), b"{\"tampered\": 1}".to_vec());
+        let (url, _) = serve(files).await;
+        let temp = tempfile::tempdir().unwrap();
+
+        let error = RemoteAssets::open(&url, temp.path(), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("failed checksum"), "{}", error);
+    }
+}
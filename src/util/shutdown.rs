@@ -0,0 +1,284 @@
+//! Unloading the runtime from a host application, such as an Office plugin
+//! or a Tauri app closing a document window.
+//!
+//! Dropping a [`Bundle`](crate::bundle::Bundle) frees its commands once no
+//! pipeline uses them, but work already started carries on: pipeline tasks on
+//! the host's tokio runtime, blocking work on the priority pools, downloads
+//! half written to disk. [`shutdown`] and
+//! [`Bundle::close`](crate::bundle::Bundle::close) stop all that so the host
+//! can unload without leaking threads or temporary files.
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, PoisonError, Weak},
+    time::Duration,
+};
+
+use tokio::{
+    sync::Notify,
+    task::{self, AbortHandle},
+};
+
+use crate::modules::Context;
+
+/// What a shutdown did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct Shutdown {
+    /// Running pipelines that were cancelled.
+    pub cancelled: usize,
+    /// Partial downloads and other temporary files removed.
+    pub removed: usize,
+    /// Blocking work still running when the timeout ran out. It can't be
+    /// interrupted, and its output is dropped when it finishes.
+    pub unfinished: usize,
+}
+
+/// A pipeline created from a context, until its tasks end.
+struct Running {
+    tasks: Vec<AbortHandle>,
+    /// Tells the pipeline's open documents it was stopped.
+    stop: Box<dyn Fn() + Send + Sync>,
+}
+
+/// The running pipelines of a [`Context`].
+#[derive(Default)]
+pub(crate) struct Tasks {
+    running: Mutex<Vec<Running>>,
+}
+
+impl Tasks {
+    /// Keep `tasks` to be aborted on shutdown, calling `stop` after.
+    pub(crate) fn track(&self, tasks: Vec<AbortHandle>, stop: impl Fn() + Send + Sync + 'static) {
+        let mut running = self.running.lock().unwrap();
+        running.retain(|x| !x.tasks.iter().all(AbortHandle::is_finished));
+        running.push(Running {
+            tasks,
+            stop: Box::new(stop),
+        });
+    }
+
+    /// Abort every running pipeline, returning how many were still running
+    /// and the tasks of all of them.
+    fn cancel(&self) -> (usize, HashSet<task::Id>) {
+        let running = std::mem::take(&mut *self.running.lock().unwrap());
+        let mut cancelled = 0;
+        let mut tasks = HashSet::new();
+        for pipeline in running {
+            tasks.extend(pipeline.tasks.iter().map(AbortHandle::id));
+            if pipeline.tasks.iter().all(AbortHandle::is_finished) {
+                continue;
+            }
+            for task in &pipeline.tasks {
+                task.abort();
+            }
+            (pipeline.stop)();
+            cancelled += 1;
+        }
+        (cancelled, tasks)
+    }
+}
+
+/// Contexts with pipelines, for [`shutdown`].
+static CONTEXTS: Mutex<Vec<Weak<Context>>> = Mutex::new(Vec::new());
+
+/// Make `context` known to [`shutdown`].
+pub(crate) fn register(context: &Arc<Context>) {
+    let mut contexts = CONTEXTS.lock().unwrap();
+    contexts.retain(|x| x.strong_count() > 0);
+    if !contexts.iter().any(|x| x.as_ptr() == Arc::as_ptr(context)) {
+        contexts.push(Arc::downgrade(context));
+    }
+}
+
+/// Blocking work started with [`spawn_blocking`](super::priority::spawn_blocking)
+/// and not yet finished, by the task that started it: a pipeline's command
+/// or none, for work started outside a task.
+static BLOCKING: Mutex<Vec<Option<task::Id>>> = Mutex::new(Vec::new());
+static BLOCKING_DONE: Notify = Notify::const_new();
+
+/// Keeps blocking work in [`BLOCKING`] while alive.
+pub(crate) struct BlockingGuard(Option<task::Id>);
+
+impl BlockingGuard {
+    pub(crate) fn new() -> Self {
+        let task = task::try_id();
+        BLOCKING
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(task);
+        BlockingGuard(task)
+    }
+}
+
+impl Drop for BlockingGuard {
+    fn drop(&mut self) {
+        let mut blocking = BLOCKING.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(i) = blocking.iter().position(|x| *x == self.0) {
+            blocking.swap_remove(i);
+        }
+        drop(blocking);
+        BLOCKING_DONE.notify_waiters();
+    }
+}
+
+/// How much blocking work started by tasks that `of` accepts is running.
+fn blocking(of: &impl Fn(Option<task::Id>) -> bool) -> usize {
+    let blocking = BLOCKING.lock().unwrap_or_else(PoisonError::into_inner);
+    blocking.iter().filter(|x| of(**x)).count()
+}
+
+/// Wait up to `timeout` for the blocking work started by tasks that `of`
+/// accepts to finish, returning how much hasn't.
+async fn drain_blocking(timeout: Duration, of: impl Fn(Option<task::Id>) -> bool) -> usize {
+    let drained = tokio::time::timeout(timeout, async {
+        loop {
+            let done = BLOCKING_DONE.notified();
+            if blocking(&of) == 0 {
+                return;
+            }
+            done.await;
+        }
+    })
+    .await;
+    match drained {
+        Ok(()) => 0,
+        Err(_) => blocking(&of),
+    }
+}
+
+/// What closing a context did, and the tasks of its pipelines, whose
+/// blocking work [`finish`] waits for.
+pub(crate) struct Closed {
+    shutdown: Shutdown,
+    tasks: HashSet<task::Id>,
+}
+
+/// Stop what a context has running and free what it holds: abort its
+/// pipelines, whose open documents end with an error, empty its asset cache
+/// and remove its temporary files.
+pub(crate) fn close_context(context: &Context) -> Closed {
+    let (cancelled, tasks) = context.tasks.cancel();
+    context.cache.clear();
+    let removed = context.data.remove_temporary() + context.downloads.remove_partial();
+    Closed {
+        shutdown: Shutdown {
+            cancelled,
+            removed,
+            unfinished: 0,
+        },
+        tasks,
+    }
+}
+
+/// Stop everything the runtime has running, for a host about to unload it.
+///
+/// Every pipeline still running is cancelled, and documents still waiting for
+/// output get an error. Caches of derived asset data are emptied and
+/// downloads cut short are removed from disk. Blocking work already started,
+/// such as a sentence being synthesized, can't be interrupted; this waits up
+/// to `timeout` for it to finish.
+///
+/// Bundles stay usable: a pipeline created after a shutdown runs as usual.
+/// Call this from the tokio runtime the pipelines ran on.
+pub async fn shutdown(timeout: Duration) -> Shutdown {
+    let contexts = std::mem::take(&mut *CONTEXTS.lock().unwrap());
+    let mut shutdown = Shutdown::default();
+    for context in contexts.iter().filter_map(Weak::upgrade) {
+        let closed = close_context(&context).shutdown;
+        shutdown.cancelled += closed.cancelled;
+        shutdown.removed += closed.removed;
+        register(&context);
+    }
+    shutdown.unfinished = drain_blocking(timeout, |_| true).await;
+    tracing::debug!("shutdown: {:?}", shutdown);
+    shutdown
+}
+
+/// [`drain_blocking`] for [`Bundle::close`](crate::bundle::Bundle::close):
+/// only the work of the closed context's pipelines is waited for.
+pub(crate) async fn finish(closed: Closed, timeout: Duration) -> Shutdown {
+    let Closed {
+        mut shutdown,
+        tasks,
+    } = closed;
+    shutdown.unfinished =
+        drain_blocking(timeout, |task| task.is_some_and(|x| tasks.contains(&x))).await;
+    shutdown
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn cancels_running_pipelines_and_waits_for_blocking_work() {
+        let tasks = Tasks::default();
+        let stopped = Arc::new(AtomicUsize::new(0));
+        let running = tokio::spawn(std::future::pending::<()>());
+        let counter = stopped.clone();
+        tasks.track(vec![running.abort_handle()], move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        // Nothing left running
+        tasks.track(vec![], || unreachable!());
+
+        let (cancelled, ids) = tasks.cancel();
+        assert_eq!(cancelled, 1);
+        assert!(ids.contains(&running.id()));
+        assert_eq!(stopped.load(Ordering::SeqCst), 1);
+        assert!(running.await.unwrap_err().is_cancelled());
+        assert_eq!(tasks.cancel().0, 0);
+
+        // Only the blocking work of the given tasks is waited for
+        let (guard, id) = tokio::spawn(async { (BlockingGuard::new(), task::id()) })
+            .await
+            .unwrap();
+        let _elsewhere = BlockingGuard::new();
+        let of = move |task: Option<task::Id>| task == Some(id);
+        assert_eq!(drain_blocking(Duration::from_millis(10), of).await, 1);
+        let waiting = tokio::spawn(drain_blocking(Duration::from_secs(5), of));
+        drop(guard);
+        assert_eq!(waiting.await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn documents_of_a_closed_context_end_with_an_error() {
+        use futures_util::StreamExt;
+
+        use crate::{ast::Pipe, modules::PipelineValue};
+
+        let defn = serde_json::from_value(serde_json::json!({
+            "entry": { "value_type": "string" },
+            "output": { "ref": "trickle" },
+            "commands": {
+                "trickle": {
+                    "module": "debug",
+                    "command": "trickle",
+                    "args": {
+                        "count": { "type": "int", "value": 100 },
+                        "delay_ms": { "type": "int", "value": 1000 }
+                    },
+                    "input": { "ref": "#/entry" },
+                    "returns": "string"
+                }
+            }
+        }))
+        .unwrap();
+        let temp = tempfile::tempdir().unwrap();
+        let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+        let pipe = Pipe::new(context.clone(), Arc::new(defn)).await.unwrap();
+        let mut handle = pipe
+            .create_stream(Arc::new(serde_json::json!({})), None)
+            .await
+            .unwrap();
+        let mut stream = handle.forward(PipelineValue::String("a".into())).await;
+
+        let closed = close_context(&context);
+        assert_eq!(closed.shutdown.cancelled, 1);
+        let err = stream.next().await.unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "the runtime was shut down");
+        assert!(stream.next().await.is_none());
+    }
+}
@@ -0,0 +1,301 @@
+//! Reloading a bundle directory when its files change, so editing a pipeline
+//! or its messages doesn't need a restart of the playground or REPL.
+//!
+//! A [`BundleWatch`] checks the bundle's `pipeline.json`, everything under
+//! `assets/` and the asset overrides a few times a second. When any of them
+//! changes, the bundle is loaded again and [`BundleWatch::current`] returns
+//! the new one from then on; pipelines created from the old one run on
+//! until they are dropped. Each reload, or failure to reload, is sent to
+//! [`subscribers`](BundleWatch::subscribe).
+//!
+//! [`Snapshot`] is the change detection on its own, for watching other files
+//! such as a pipeline's tests.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use tokio::{
+    sync::{broadcast, watch},
+    task::AbortHandle,
+};
+
+use crate::bundle::{Bundle, BundleOptions, Error};
+
+/// How often the files are checked for changes.
+pub(crate) const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a [`BundleWatch`] did after files changed.
+#[derive(Debug, Clone)]
+pub enum Reload {
+    /// The bundle was loaded again.
+    Reloaded { changed: Vec<PathBuf> },
+    /// Loading failed, e.g. on a half-written `pipeline.json`. The previous
+    /// bundle stays in use.
+    Failed {
+        changed: Vec<PathBuf>,
+        error: String,
+    },
+}
+
+/// A bundle loaded from a directory, reloaded when its files change. See
+/// [`Bundle::watch`] and the [module docs](self).
+pub struct BundleWatch {
+    current: watch::Receiver<Arc<Bundle>>,
+    events: broadcast::Sender<Reload>,
+    task: AbortHandle,
+}
+
+impl BundleWatch {
+    pub(crate) async fn start(
+        path: PathBuf,
+        options: BundleOptions,
+        interval: Duration,
+    ) -> Result<BundleWatch, Error> {
+        let bundle = Bundle::from_path_with_options(&path, options.clone()).await?;
+        Ok(Self::spawn(bundle, path, options, interval))
+    }
+
+    /// Watch `bundle`, already loaded from the directory `contents_path`
+    /// with `options`, e.g. to only start watching once it loaded without
+    /// loading it twice.
+    pub fn from_bundle(
+        bundle: Bundle,
+        contents_path: impl AsRef<Path>,
+        options: BundleOptions,
+    ) -> BundleWatch {
+        let path = contents_path.as_ref().to_path_buf();
+        Self::spawn(bundle, path, options, POLL_INTERVAL)
+    }
+
+    fn spawn(
+        bundle: Bundle,
+        path: PathBuf,
+        options: BundleOptions,
+        interval: Duration,
+    ) -> BundleWatch {
+        let base = if path.is_dir() {
+            path.clone()
+        } else {
+            // Only "" has none, which is as relative as a bare file name
+            path.parent().unwrap_or(Path::new("")).to_path_buf()
+        };
+        let mut roots = vec![base.join("pipeline.json"), base.join("assets")];
+        roots.extend(options.asset_overrides.values().cloned());
+
+        let (sender, current) = watch::channel(Arc::new(bundle));
+        let (events, _) = broadcast::channel(16);
+        let task = tokio::spawn(poll(path, options, roots, interval, sender, events.clone()));
+
+        BundleWatch {
+            current,
+            events,
+            task: task.abort_handle(),
+        }
+    }
+
+    /// The bundle as of the last successful load.
+    pub fn current(&self) -> Arc<Bundle> {
+        self.current.borrow().clone()
+    }
+
+    /// Get a [`Reload`] each time files change from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Reload> {
+        self.events.subscribe()
+    }
+}
+
+impl Drop for BundleWatch {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+async fn poll(
+    path: PathBuf,
+    options: BundleOptions,
+    roots: Vec<PathBuf>,
+    interval: Duration,
+    current: watch::Sender<Arc<Bundle>>,
+    events: broadcast::Sender<Reload>,
+) {
+    let mut snapshot = Snapshot::new(roots.clone()).await;
+    loop {
+        tokio::time::sleep(interval).await;
+        let next = Snapshot::new(roots.clone()).await;
+        let changed = snapshot.changed(&next);
+        snapshot = next;
+        if changed.is_empty() {
+            continue;
+        }
+
+        tracing::debug!(
+            "Reloading {} after changes to {:?}",
+            path.display(),
+            changed
+        );
+        let reload = match Bundle::from_path_with_options(&path, options.clone()).await {
+            Ok(bundle) => {
+                current.send_replace(Arc::new(bundle));
+                Reload::Reloaded { changed }
+            }
+            Err(e) => {
+                tracing::warn!("Reloading {} failed: {}", path.display(), e);
+                Reload::Failed {
+                    changed,
+                    error: e.to_string(),
+                }
+            }
+        };
+        // Nobody listening is fine
+        let _ = events.send(reload);
+    }
+}
+
+/// Modification time and size of every file under some paths, to compare
+/// with a later snapshot of the same paths.
+///
+/// ```no_run
+/// # use std::{path::PathBuf, time::Duration};
+/// # use divvun_runtime::watch::Snapshot;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let roots = vec![PathBuf::from("tests"), PathBuf::from("pipeline.ts")];
+/// let before = Snapshot::new(roots.clone()).await;
+/// tokio::time::sleep(Duration::from_secs(1)).await;
+/// for path in before.changed(&Snapshot::new(roots).await) {
+///     println!("{} changed", path.display());
+/// }
+/// # }
+/// ```
+#[derive(Debug, Default)]
+pub struct Snapshot(BTreeMap<PathBuf, (SystemTime, u64)>);
+
+impl Snapshot {
+    /// Walk `roots`, files or directories, on a blocking thread. Paths that
+    /// don't exist are left out.
+    pub async fn new(roots: Vec<PathBuf>) -> Snapshot {
+        tokio::task::spawn_blocking(move || {
+            let mut files = BTreeMap::new();
+            for root in &roots {
+                add_files(root, &mut files);
+            }
+            Snapshot(files)
+        })
+        .await
+        // Only when the runtime is shutting down
+        .unwrap_or_default()
+    }
+
+    /// Files added, removed or modified since `self`.
+    pub fn changed(&self, next: &Snapshot) -> Vec<PathBuf> {
+        let mut changed = next
+            .0
+            .iter()
+            .filter(|(path, stamp)| self.0.get(*path) != Some(stamp))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        changed.extend(self.0.keys().filter(|x| !next.0.contains_key(*x)).cloned());
+        changed
+    }
+}
+
+fn add_files(path: &Path, files: &mut BTreeMap<PathBuf, (SystemTime, u64)>) {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        let Ok(entries) = std::fs::read_dir(path) else {
+            return;
+        };
+        for entry in entries.filter_map(|e| e.ok()) {
+            add_files(&entry.path(), files);
+        }
+    } else {
+        let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+        files.insert(path.to_path_buf(), (modified, metadata.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+
+    use super::*;
+    use crate::modules::PipelineValue;
+
+    fn write_pipeline(dir: &Path, command: &str) {
+        let pipeline = serde_json::json!({
+            "version": 1,
+            "default": "main",
+            "pipelines": {
+                "main": {
+                    "entry": { "value_type": "string" },
+                    "output": { "ref": "step" },
+                    "commands": {
+                        "step": {
+                            "module": "example",
+                            "command": command,
+                            "input": { "ref": "#/entry" },
+                            "returns": "string"
+                        }
+                    }
+                }
+            }
+        });
+        write_atomically(dir, &pipeline.to_string());
+    }
+
+    /// Replace `pipeline.json` in one go, so the watcher never reads it half
+    /// written.
+    fn write_atomically(dir: &Path, contents: &str) {
+        std::fs::write(dir.join("pipeline.json.tmp"), contents).unwrap();
+        std::fs::rename(dir.join("pipeline.json.tmp"), dir.join("pipeline.json")).unwrap();
+    }
+
+    async fn next(events: &mut broadcast::Receiver<Reload>) -> Reload {
+        tokio::time::timeout(Duration::from_secs(5), events.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+
+    async fn run(bundle: &Bundle, text: &str) -> String {
+        let mut handle = bundle.create(serde_json::json!({})).await.unwrap();
+        let mut stream = handle.forward(PipelineValue::String(text.into())).await;
+        let output = stream.next().await.unwrap().unwrap();
+        output.try_into_string().unwrap()
+    }
+
+    #[tokio::test]
+    async fn reloads_when_the_pipeline_changes() {
+        let temp = tempfile::tempdir().unwrap();
+        write_pipeline(temp.path(), "upper");
+        let watch = BundleWatch::start(
+            temp.path().to_path_buf(),
+            BundleOptions::default(),
+            Duration::from_millis(10),
+        )
+        .await
+        .unwrap();
+        let mut events = watch.subscribe();
+        assert_eq!(run(&watch.current(), "giella").await, "GIELLA");
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        write_pipeline(temp.path(), "reverse");
+        match next(&mut events).await {
+            Reload::Reloaded { changed } => {
+                assert_eq!(changed, [temp.path().join("pipeline.json")])
+            }
+            other => panic!("expected a reload, got {:?}", other),
+        }
+        assert_eq!(run(&watch.current(), "giella").await, "alleig");
+
+        write_atomically(temp.path(), "{");
+        assert!(matches!(next(&mut events).await, Reload::Failed { .. }));
+        assert_eq!(run(&watch.current(), "giella").await, "alleig");
+    }
+}
@@ -0,0 +1,37 @@
+//! Privacy mode is process-wide, so it is tested in a binary of its own.
+
+use std::{collections::HashMap, sync::Arc};
+
+use divvun_runtime::{
+    modules::{Context, PipelineValue, run_single},
+    util::privacy,
+};
+
+async fn analyses_error(context: &Arc<Context>) -> String {
+    let err = run_single(
+        context.clone(),
+        "divvun",
+        "analyses",
+        HashMap::new(),
+        PipelineValue::String("\t\"sámegiella\" N Sg Nom\n".to_string()),
+        serde_json::Value::Null,
+    )
+    .await
+    .err()
+    .unwrap();
+    format!("{} {:?}", err, err)
+}
+
+#[tokio::test]
+async fn errors_on_input_are_redacted() {
+    let temp = tempfile::tempdir().unwrap();
+    let context = Arc::new(Context::standalone(temp.path()).await.unwrap());
+
+    privacy::set_enabled(false);
+    assert!(analyses_error(&context).await.contains("sámegiella"));
+
+    privacy::set_enabled(true);
+    let err = analyses_error(&context).await;
+    assert!(!err.contains("sámegiella"), "{}", err);
+    assert!(err.starts_with("<redacted: "), "{}", err);
+}
@@ -0,0 +1,175 @@
+//! The public API against the toy bundle in `tests/fixtures/toy`, which is
+//! small enough to live in the repository.
+
+use std::{path::PathBuf, sync::Arc};
+
+use divvun_runtime::{
+    bundle::Bundle,
+    bundle_set::{BundleSet, BundleSetOptions},
+    modules::PipelineValue,
+    pipe_pool::{PipePool, PipePoolOptions},
+};
+use futures_util::StreamExt;
+
+fn toy() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/toy")
+}
+
+fn strings(values: Vec<PipelineValue>) -> Vec<String> {
+    values
+        .into_iter()
+        .map(|x| x.try_into_string().unwrap())
+        .collect()
+}
+
+async fn run(bundle: &Bundle, input: &str) -> Vec<String> {
+    let mut handle = bundle.create(serde_json::json!({})).await.unwrap();
+    let mut stream = handle
+        .forward(PipelineValue::String(input.to_string()))
+        .await;
+    let mut output = Vec::new();
+    while let Some(value) = stream.next().await {
+        output.push(value.unwrap());
+    }
+    strings(output)
+}
+
+#[tokio::test]
+async fn runs_the_default_pipeline() {
+    let bundle = Bundle::from_path(toy()).await.unwrap();
+    assert_eq!(bundle.list_pipelines(), ["shout", "disambiguate"]);
+    assert_eq!(bundle.definition().output.r#ref, "reverse");
+
+    assert_eq!(run(&bundle, "Mun borran.").await, [".NARROB NUM"]);
+    // A handle runs any number of inputs
+    let mut handle = bundle.create(serde_json::json!({})).await.unwrap();
+    for input in ["a", "bc"] {
+        let mut stream = handle
+            .forward(PipelineValue::String(input.to_string()))
+            .await;
+        let output = stream.next().await.unwrap().unwrap();
+        let expected = input.to_uppercase().chars().rev().collect::<String>();
+        assert_eq!(output.try_into_string().unwrap(), expected);
+    }
+}
+
+#[cfg(feature = "mod-cg3")]
+#[tokio::test]
+async fn runs_a_named_pipeline() {
+    use divvun_runtime::modules::cg3::{Block, Output};
+
+    let bundle = Bundle::from_path_named(toy(), "disambiguate")
+        .await
+        .unwrap();
+    let input = "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom\n\"<borran>\"\n\t\"borrat\" V Ind Prs Sg1\n\t\"borran\" N Sg Nom\n\"<.>\"\n\t\".\" CLB\n";
+    let output = run(&bundle, input).await.concat();
+
+    let output = Output::new(output.as_str());
+    let readings = output
+        .iter()
+        .filter_map(Result::ok)
+        .filter_map(|block| match block {
+            Block::Cohort(cohort) if cohort.word_form == "borran" => Some(cohort.readings),
+            _ => None,
+        })
+        .next()
+        .unwrap();
+    let lemmas = readings.iter().map(|x| x.base_form).collect::<Vec<_>>();
+    assert_eq!(lemmas, ["borrat"]);
+}
+
+#[cfg(feature = "mod-cg3")]
+#[tokio::test]
+async fn self_test_passes() {
+    let bundle = Bundle::from_path(toy()).await.unwrap();
+    let report = bundle.self_test().await.unwrap();
+    assert_eq!(report.cases.len(), 2);
+    assert!(
+        report.passed(),
+        "{:?}",
+        report.failures().collect::<Vec<_>>()
+    );
+}
+
+#[tokio::test]
+async fn bundle_set_loads_on_first_use() {
+    let mut set = BundleSet::new(BundleSetOptions::default());
+    set.insert("se", toy());
+    assert!(set.loaded_languages().await.is_empty());
+
+    let output = set.check("se-NO", "giella").await.unwrap();
+    assert_eq!(strings(output), ["ALLEIG"]);
+    assert_eq!(set.loaded_languages().await, ["se"]);
+
+    // Checks arriving together share one load
+    set.insert("sma", toy());
+    let (a, b) = tokio::join!(set.get("sma"), set.get("sma"));
+    assert!(Arc::ptr_eq(&a.unwrap(), &b.unwrap()));
+    let output = set.check("se", "giella").await.unwrap();
+    assert_eq!(strings(output), ["ALLEIG"]);
+}
+
+#[tokio::test]
+async fn pipe_pool_reuses_pipelines() {
+    let bundle = Arc::new(Bundle::from_path(toy()).await.unwrap());
+    let pool = PipePool::new(bundle, PipePoolOptions::default());
+    for _ in 0..3 {
+        let output = pool
+            .forward(PipelineValue::String("giella".to_string()))
+            .await
+            .unwrap();
+        assert_eq!(strings(output), ["ALLEIG"]);
+    }
+    let stats = pool.stats();
+    assert_eq!((stats.created, stats.reused), (1, 2));
+}
+
+#[tokio::test]
+async fn pipe_pool_drops_pipelines_left_mid_document() {
+    let bundle = Arc::new(Bundle::from_path(toy()).await.unwrap());
+    let pool = PipePool::new(bundle, PipePoolOptions::default());
+
+    let mut pipe = pool.acquire().await.unwrap();
+    let stream = pipe
+        .handle()
+        .forward(PipelineValue::String("giella".to_string()))
+        .await;
+    drop(stream);
+    drop(pipe);
+    assert_eq!(pool.stats().idle, 0);
+
+    let mut pipe = pool.acquire().await.unwrap();
+    let output = pipe
+        .forward(PipelineValue::String("giella".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(strings(output), ["ALLEIG"]);
+    drop(pipe);
+    assert_eq!(pool.stats().idle, 1);
+}
+
+#[tokio::test]
+async fn capabilities_list_pipelines_and_encodings() {
+    let bundle = Bundle::from_path(toy()).await.unwrap();
+    let capabilities = bundle.capabilities().await.unwrap();
+    assert_eq!(capabilities.default_pipeline, "shout");
+    let pipelines = capabilities
+        .pipelines
+        .iter()
+        .map(|x| x.name.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(pipelines, ["shout", "disambiguate"]);
+
+    let shout = &capabilities.pipelines[0];
+    assert_eq!(shout.input, "string");
+    assert_eq!(shout.output.as_ref().unwrap().returns, "string");
+    assert!(shout.missing.is_empty());
+    assert_eq!(
+        capabilities.pipelines[1].missing.is_empty(),
+        cfg!(feature = "mod-cg3")
+    );
+
+    assert_eq!(capabilities.encodings, ["utf-8", "utf-16"]);
+    assert!(capabilities.locales.is_empty());
+    assert!(capabilities.voices.is_empty());
+}